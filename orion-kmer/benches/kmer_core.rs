@@ -0,0 +1,98 @@
+//! Micro-benchmarks of the k-mer encoding core and the `count` pipeline on a fixed synthetic
+//! genome, printed as one JSON object per line so runs can be diffed across commits.
+//!
+//! Run with `cargo bench --bench kmer_core`.
+
+use clap::Parser;
+use orion_kmer::{
+    cli::CountArgs,
+    commands::{
+        bench::{synthetic_genome, write_synthetic_fasta},
+        count::run_count,
+    },
+    kmer::{canonical_u64, reverse_complement_u64, seq_to_u64},
+};
+use serde_json::json;
+use std::{hint::black_box, time::Instant};
+
+const ITERATIONS: u64 = 20_000_000;
+const GENOME_LENGTH: usize = 2_000_000;
+const GENOME_SEED: u64 = 42;
+
+// Runs `kmer_fn` over a stream of pseudo-random k-mers and returns nanoseconds per call.
+fn time_per_call(k: u8, kmer_fn: fn(u64, u8) -> u64) -> f64 {
+    let k_mask = if k == 32 {
+        u64::MAX
+    } else {
+        (1u64 << (2 * k)) - 1
+    };
+    let mut kmer_val: u64 = 0x9E37_79B9_7F4A_7C15;
+    let mut checksum: u64 = 0;
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        kmer_val = kmer_val
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        checksum ^= kmer_fn(black_box(kmer_val & k_mask), black_box(k));
+    }
+    black_box(checksum);
+    start.elapsed().as_nanos() as f64 / ITERATIONS as f64
+}
+
+// Encodes every window of `genome` and returns nanoseconds per window.
+fn time_seq_to_u64(genome: &[u8], k: u8) -> f64 {
+    let mut checksum: u64 = 0;
+    let start = Instant::now();
+    for window in genome.windows(k as usize) {
+        checksum ^= seq_to_u64(black_box(window), k).unwrap_or(0);
+    }
+    black_box(checksum);
+    start.elapsed().as_nanos() as f64 / (genome.len() - k as usize + 1) as f64
+}
+
+fn main() {
+    let genome = synthetic_genome(GENOME_LENGTH, GENOME_SEED);
+    for k in [11u8, 21, 31] {
+        println!(
+            "{}",
+            json!({
+                "k": k,
+                "seq_to_u64_ns": time_seq_to_u64(&genome, k),
+                "canonical_u64_ns": time_per_call(k, canonical_u64),
+                "reverse_complement_u64_ns": time_per_call(k, reverse_complement_u64),
+            })
+        );
+    }
+
+    // The full count pipeline: parsing, k-mer extraction, counting and writing the table
+    let work_dir = std::env::temp_dir().join(format!("orion-kmer-bench-core-{}", std::process::id()));
+    std::fs::create_dir_all(&work_dir).expect("create benchmark directory");
+    let genome_path = work_dir.join("synthetic.fa");
+    write_synthetic_fasta(&genome_path, &genome).expect("write benchmark genome");
+    for k in [21u8, 31] {
+        let output_path = work_dir.join(format!("counts_k{}.tsv", k));
+        let args = CountArgs::try_parse_from([
+            "count".to_string(),
+            "-k".to_string(),
+            k.to_string(),
+            "-i".to_string(),
+            genome_path.to_string_lossy().into_owned(),
+            "-o".to_string(),
+            output_path.to_string_lossy().into_owned(),
+        ])
+        .expect("parse count arguments");
+        let start = Instant::now();
+        run_count(args).expect("run count");
+        let seconds = start.elapsed().as_secs_f64();
+        println!(
+            "{}",
+            json!({
+                "k": k,
+                "count_genome_length": GENOME_LENGTH,
+                "count_seconds": seconds,
+                "count_bases_per_second": GENOME_LENGTH as f64 / seconds,
+            })
+        );
+    }
+    std::fs::remove_dir_all(&work_dir).expect("remove benchmark directory");
+}
@@ -0,0 +1,53 @@
+//! Compares `reverse_complement_u64` with the previous per-base loop across k.
+//!
+//! Run with `cargo bench --bench reverse_complement`.
+
+use orion_kmer::kmer::reverse_complement_u64;
+use std::{hint::black_box, time::Instant};
+
+const ITERATIONS: u64 = 20_000_000;
+
+// The original implementation: one shift/XOR/OR round per base.
+fn reverse_complement_per_base(kmer_val: u64, k: u8) -> u64 {
+    let mut rc_val: u64 = 0;
+    for i in 0..k {
+        let complemented_base = ((kmer_val >> (2 * i)) & 0b11) ^ 0b11;
+        rc_val |= complemented_base << (2 * (k - 1 - i));
+    }
+    rc_val
+}
+
+// Runs `rc` over a stream of pseudo-random k-mers and returns nanoseconds per call.
+fn time_per_call(k: u8, rc: fn(u64, u8) -> u64) -> f64 {
+    let k_mask = if k == 32 {
+        u64::MAX
+    } else {
+        (1u64 << (2 * k)) - 1
+    };
+    let mut kmer_val: u64 = 0x9E37_79B9_7F4A_7C15;
+    let mut checksum: u64 = 0;
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        kmer_val = kmer_val
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        checksum ^= rc(black_box(kmer_val & k_mask), black_box(k));
+    }
+    black_box(checksum);
+    start.elapsed().as_nanos() as f64 / ITERATIONS as f64
+}
+
+fn main() {
+    println!("k\tper_base_ns\tswar_ns\tspeedup");
+    for k in [11u8, 15, 21, 25, 31, 32] {
+        let per_base_ns = time_per_call(k, reverse_complement_per_base);
+        let swar_ns = time_per_call(k, reverse_complement_u64);
+        println!(
+            "{}\t{:.2}\t{:.2}\t{:.1}x",
+            k,
+            per_base_ns,
+            swar_ns,
+            per_base_ns / swar_ns
+        );
+    }
+}
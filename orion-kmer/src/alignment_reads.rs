@@ -0,0 +1,191 @@
+//! Reads of BAM and CRAM files (typically unaligned), for the commands that read FASTA/FASTQ.
+//!
+//! A BAM/CRAM input is decoded on a background thread into FASTQ text, or FASTA when its first
+//! read has no base qualities, so every record loop parses it like any other sequence file.
+//! Secondary and supplementary alignments are skipped, as they repeat a read given by its
+//! primary record, and reads aligned to the reverse strand are reverse-complemented back to the
+//! orientation they were sequenced in.
+
+use anyhow::{Context, Result};
+use log::{debug, info};
+use noodles::{bam, cram, fasta, sam::alignment::RecordBuf};
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::{Path, PathBuf},
+    sync::{mpsc, OnceLock},
+    thread,
+};
+
+use crate::{
+    kmer::reverse_complement_seq,
+    utils::{get_decompressed_input_reader, ReadAheadReader},
+};
+
+/// Alignment file formats read as sequence input, chosen by extension (`.bam`, `.cram`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AlignmentFormat {
+    Bam,
+    Cram,
+}
+
+// Reference FASTA for decoding CRAM input, set by the global `--reference`.
+static CRAM_REFERENCE: OnceLock<PathBuf> = OnceLock::new();
+
+/// Sets the reference FASTA that CRAM inputs are decoded against. Only the first call has an effect.
+pub fn init_cram_reference(path: &Path) {
+    if CRAM_REFERENCE.set(path.to_path_buf()).is_err() {
+        debug!("CRAM reference already initialized; ignoring {:?}", path);
+    }
+}
+
+// Decoded text handed to the parser per chunk, and chunks queued ahead of it.
+const CHUNK_SIZE: usize = 1 << 20;
+const CHUNKS_AHEAD: usize = 4;
+
+// Samtools writes 0xFF for every base of a read without qualities; noodles may also leave them empty.
+fn has_qualities(quality_scores: &[u8]) -> bool {
+    !quality_scores.is_empty() && quality_scores[0] != 0xff
+}
+
+// Writes reads as FASTQ or FASTA text and sends it to the parser in chunks.
+struct ReadEncoder {
+    sender: mpsc::SyncSender<io::Result<Vec<u8>>>,
+    chunk: Vec<u8>,
+    // FASTQ or FASTA, fixed by the first read written
+    fastq: Option<bool>,
+    skipped_alignments: usize,
+}
+
+impl ReadEncoder {
+    // Adds one record; returns false once the parser has stopped reading.
+    fn push(&mut self, record: &RecordBuf) -> bool {
+        let flags = record.flags();
+        if flags.is_secondary() || flags.is_supplementary() {
+            self.skipped_alignments += 1;
+            return true;
+        }
+        let mut seq = record.sequence().as_ref().to_vec();
+        let mut quals = record.quality_scores().as_ref().to_vec();
+        if flags.is_reverse_complemented() {
+            seq = reverse_complement_seq(&seq);
+            quals.reverse();
+        }
+        let fastq = *self.fastq.get_or_insert_with(|| has_qualities(&quals));
+        self.chunk.push(if fastq { b'@' } else { b'>' });
+        if let Some(name) = record.name() {
+            self.chunk.extend_from_slice(name.as_ref());
+        }
+        self.chunk.push(b'\n');
+        self.chunk.extend_from_slice(&seq);
+        self.chunk.push(b'\n');
+        if fastq {
+            self.chunk.extend_from_slice(b"+\n");
+            if has_qualities(&quals) {
+                self.chunk.extend(quals.iter().map(|&qual| qual.saturating_add(33)));
+            } else {
+                // A read without qualities among FASTQ reads gets the lowest quality
+                self.chunk.extend(std::iter::repeat_n(b'!', seq.len()));
+            }
+            self.chunk.push(b'\n');
+        }
+        if self.chunk.len() >= CHUNK_SIZE {
+            return self.sender.send(Ok(std::mem::take(&mut self.chunk))).is_ok();
+        }
+        true
+    }
+
+    fn finish(self, result: io::Result<()>, path: &Path) {
+        if self.skipped_alignments > 0 {
+            debug!("Skipped {} secondary or supplementary alignments in {:?}", self.skipped_alignments, path);
+        }
+        if !self.chunk.is_empty() && self.sender.send(Ok(self.chunk)).is_err() {
+            return;
+        }
+        if let Err(e) = result {
+            let _ = self.sender.send(Err(e));
+        }
+    }
+}
+
+fn encode_bam_reads(file: File, encoder: &mut ReadEncoder) -> io::Result<()> {
+    let mut reader = bam::io::Reader::new(file);
+    let header = reader.read_header()?;
+    let mut record = RecordBuf::default();
+    while reader.read_record_buf(&header, &mut record)? > 0 {
+        if !encoder.push(&record) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn encode_cram_reads(file: File, repository: fasta::Repository, encoder: &mut ReadEncoder) -> io::Result<()> {
+    let mut reader = cram::io::reader::Builder::default()
+        .set_reference_sequence_repository(repository)
+        .build_from_reader(file);
+    let header = reader.read_header()?;
+    for record in reader.records(&header) {
+        let record = record.map_err(|e| {
+            let hint = if CRAM_REFERENCE.get().is_none() {
+                "; aligned CRAM is decoded against its reference, given with --reference"
+            } else {
+                ""
+            };
+            io::Error::new(e.kind(), format!("Failed to decode CRAM record: {}{}", e, hint))
+        })?;
+        if !encoder.push(&record) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+// Loads the --reference FASTA into memory for CRAM decoding; without it, only reads stored
+// without reference compression (e.g. unaligned CRAM) can be decoded.
+fn cram_reference_repository() -> Result<fasta::Repository> {
+    let Some(reference_path) = CRAM_REFERENCE.get() else {
+        return Ok(fasta::Repository::default());
+    };
+    info!("Loading CRAM reference {:?}", reference_path);
+    let reader = get_decompressed_input_reader(reference_path)
+        .with_context(|| format!("Failed to open CRAM reference: {:?}", reference_path))?;
+    let records = fasta::io::Reader::new(reader)
+        .records()
+        .collect::<io::Result<Vec<_>>>()
+        .with_context(|| format!("Failed to read CRAM reference FASTA: {:?}", reference_path))?;
+    Ok(fasta::Repository::new(records))
+}
+
+/// Opens the reads of a BAM or CRAM file as FASTQ (or FASTA) text, decoded on a background
+/// thread. Decoding errors surface as read errors of the returned reader.
+pub fn alignment_reads_reader(path: &Path, format: AlignmentFormat) -> Result<Box<dyn BufRead + Send>> {
+    let file = File::open(path).with_context(|| format!("Failed to open input file: {:?}", path))?;
+    let repository = match format {
+        AlignmentFormat::Bam => {
+            info!("Reading reads of BAM file: {:?}", path);
+            None
+        }
+        AlignmentFormat::Cram => {
+            info!("Reading reads of CRAM file: {:?}", path);
+            Some(cram_reference_repository()?)
+        }
+    };
+    let (sender, receiver) = mpsc::sync_channel(CHUNKS_AHEAD);
+    let path = path.to_path_buf();
+    thread::spawn(move || {
+        let mut encoder = ReadEncoder {
+            sender,
+            chunk: Vec::with_capacity(CHUNK_SIZE),
+            fastq: None,
+            skipped_alignments: 0,
+        };
+        let result = match repository {
+            None => encode_bam_reads(file, &mut encoder),
+            Some(repository) => encode_cram_reads(file, repository, &mut encoder),
+        }
+        .map_err(|e| io::Error::new(e.kind(), format!("{:?}: {}", path, e)));
+        encoder.finish(result, &path);
+    });
+    Ok(Box::new(BufReader::new(ReadAheadReader::from_chunks(receiver))))
+}
@@ -0,0 +1,134 @@
+//! Writer for BGZF (blocked gzip), the gzip variant required by tabix and samtools.
+//!
+//! A BGZF file is a series of independent gzip members, each holding at most 64 KiB of
+//! uncompressed data and recording its compressed size in a `BC` extra field, followed by an
+//! empty end-of-file block. Any gzip reader that handles multiple members (such as
+//! `flate2::read::MultiGzDecoder`) can read it.
+
+use flate2::{write::DeflateEncoder, Compression, Crc};
+use std::io::{self, Write};
+
+/// Uncompressed bytes per block; small enough that a block stays below 64 KiB even when
+/// its data does not compress (the value htslib uses).
+const BLOCK_DATA_SIZE: usize = 0xff00;
+
+// Gzip member header with FEXTRA set and a `BC` subfield; the last two bytes (BSIZE) are filled in per block.
+const BLOCK_HEADER: [u8; 18] = [
+    0x1f, 0x8b, 0x08, 0x04, 0, 0, 0, 0, 0, 0xff, 0x06, 0x00, b'B', b'C', 0x02, 0x00, 0, 0,
+];
+// Header plus CRC32 and ISIZE trailer
+const BLOCK_OVERHEAD: usize = BLOCK_HEADER.len() + 8;
+
+/// The empty block that marks the end of a BGZF file.
+pub const BGZF_EOF_BLOCK: [u8; 28] = [
+    0x1f, 0x8b, 0x08, 0x04, 0, 0, 0, 0, 0, 0xff, 0x06, 0x00, b'B', b'C', 0x02, 0x00, 0x1b, 0x00,
+    0x03, 0x00, 0, 0, 0, 0, 0, 0, 0, 0,
+];
+
+/// Compresses written data into BGZF blocks. Call `finish` to write the last block and the
+/// end-of-file marker.
+pub struct BgzfWriter<W: Write> {
+    inner: W,
+    buffer: Vec<u8>,
+    compression: Compression,
+}
+
+impl<W: Write> BgzfWriter<W> {
+    pub fn new(inner: W) -> Self {
+        BgzfWriter {
+            inner,
+            buffer: Vec::with_capacity(BLOCK_DATA_SIZE),
+            compression: Compression::default(),
+        }
+    }
+
+    // Compresses the buffered data into one block.
+    fn write_block(&mut self) -> io::Result<()> {
+        let mut encoder = DeflateEncoder::new(Vec::new(), self.compression);
+        encoder.write_all(&self.buffer)?;
+        let compressed = encoder.finish()?;
+        let block_size = u16::try_from(compressed.len() + BLOCK_OVERHEAD - 1).map_err(|_| {
+            io::Error::new(io::ErrorKind::InvalidData, "BGZF block exceeds 64 KiB")
+        })?;
+        let mut crc = Crc::new();
+        crc.update(&self.buffer);
+
+        let mut header = BLOCK_HEADER;
+        header[16..].copy_from_slice(&block_size.to_le_bytes());
+        self.inner.write_all(&header)?;
+        self.inner.write_all(&compressed)?;
+        self.inner.write_all(&crc.sum().to_le_bytes())?;
+        self.inner.write_all(&(self.buffer.len() as u32).to_le_bytes())?;
+        self.buffer.clear();
+        Ok(())
+    }
+
+    /// Writes any buffered data as a final block, then the end-of-file marker, and returns the
+    /// inner writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        if !self.buffer.is_empty() {
+            self.write_block()?;
+        }
+        self.inner.write_all(&BGZF_EOF_BLOCK)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for BgzfWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = buf.len().min(BLOCK_DATA_SIZE - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..len]);
+        if self.buffer.len() == BLOCK_DATA_SIZE {
+            self.write_block()?;
+        }
+        Ok(len)
+    }
+
+    // Blocks are only cut when full, so flushing passes on what was already compressed.
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::MultiGzDecoder;
+    use std::io::Read;
+
+    #[test]
+    fn test_bgzf_round_trip_and_block_layout() {
+        // Enough data for several blocks, with an incompressible-ish tail
+        let mut data: Vec<u8> = b"ACGT\t1\n".repeat(30_000);
+        data.extend((0..70_000u32).map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8));
+
+        let mut writer = BgzfWriter::new(Vec::new());
+        writer.write_all(&data).unwrap();
+        let bgzf = writer.finish().unwrap();
+        assert!(bgzf.ends_with(&BGZF_EOF_BLOCK));
+
+        // Walk the blocks using their BSIZE fields
+        let mut offset = 0;
+        let mut num_blocks = 0;
+        while offset < bgzf.len() {
+            assert_eq!(&bgzf[offset..offset + 4], &[0x1f, 0x8b, 0x08, 0x04]);
+            assert_eq!(&bgzf[offset + 12..offset + 14], b"BC");
+            let block_size = u16::from_le_bytes([bgzf[offset + 16], bgzf[offset + 17]]) as usize + 1;
+            offset += block_size;
+            num_blocks += 1;
+        }
+        assert_eq!(offset, bgzf.len());
+        assert_eq!(num_blocks, data.len().div_ceil(BLOCK_DATA_SIZE) + 1);
+
+        let mut decoded = Vec::new();
+        MultiGzDecoder::new(&bgzf[..]).read_to_end(&mut decoded).unwrap();
+        assert_eq!(decoded, data);
+    }
+
+    #[test]
+    fn test_bgzf_empty_output_is_eof_block() {
+        let bgzf = BgzfWriter::new(Vec::new()).finish().unwrap();
+        assert_eq!(bgzf, BGZF_EOF_BLOCK);
+    }
+}
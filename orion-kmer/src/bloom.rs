@@ -0,0 +1,220 @@
+// Bloom filter of a database's unified k-mers, written by `dump --format bloom` and loaded by
+// `query --bloom-file`.
+//
+// Layout (all integers little-endian):
+//   [0..8)    magic `ORKBLM01`
+//   [8]       k
+//   [9]       canonical (0 or 1)
+//   [10]      hashed (0 or 1): whether the inserted keys are MurmurHash3 k-mer hashes
+//   [11..16)  reserved, zero
+//   [16..24)  hash seed of a hashed database, 0 otherwise
+//   [24..32)  number of bits m
+//   [32..40)  number of hash functions h
+//   [40..48)  number of inserted k-mers n
+//   [48..)    ceil(m / 64) u64 words; bit i is bit (i % 64) of word i / 64
+//
+// A key (the database's stored k-mer value, see `KmerDbV2::lookup_key`) sets bits
+// (a + j * b) mod m for j in 0..h, where a = splitmix64(key) and b = splitmix64(a) | 1.
+
+use anyhow::{Context, Result};
+use std::io::{Read, Write};
+
+use crate::{
+    db_types::{HashFunction, KmerDbV2, KmerHashing},
+    errors::OrionKmerError,
+    kmer::CanonicalPolicy,
+};
+
+pub const BLOOM_FILTER_MAGIC: &[u8; 8] = b"ORKBLM01";
+const HEADER_LEN: usize = 48;
+const MAX_HASH_FUNCTIONS: u64 = 32;
+
+// SplitMix64 finalizer, spelled out in the layout comment so other tools can probe the filter.
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// A Bloom filter over a database's stored k-mer keys, keeping the k, canonical form and
+/// hashing needed to turn read k-mers into those keys.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KmerBloomFilter {
+    pub k: u8,
+    pub canonical: bool,
+    pub hashing: Option<KmerHashing>,
+    pub num_bits: u64,
+    pub num_hashes: u64,
+    pub num_items: u64,
+    bits: Vec<u64>,
+}
+
+impl KmerBloomFilter {
+    /// Sizes a filter for `num_items` keys at the target false-positive rate, using the optimal
+    /// m = -n ln(p) / ln(2)^2 bits and h = (m / n) ln(2) hash functions.
+    pub fn with_capacity(num_items: usize, fp_rate: f64, k: u8, canonical: bool, hashing: Option<KmerHashing>) -> Result<Self> {
+        if !(fp_rate > 0.0 && fp_rate < 1.0) {
+            return Err(OrionKmerError::Generic(format!(
+                "--fp-rate must be between 0 and 1 (exclusive), got {}",
+                fp_rate
+            ))
+            .into());
+        }
+        let n = num_items.max(1) as f64;
+        let ln2 = std::f64::consts::LN_2;
+        let num_bits = ((-n * fp_rate.ln() / (ln2 * ln2)).ceil() as u64).max(64);
+        let num_hashes = ((num_bits as f64 / n * ln2).round() as u64).clamp(1, MAX_HASH_FUNCTIONS);
+        Ok(KmerBloomFilter {
+            k,
+            canonical,
+            hashing,
+            num_bits,
+            num_hashes,
+            num_items: 0,
+            bits: vec![0; num_bits.div_ceil(64) as usize],
+        })
+    }
+
+    /// Builds a filter of every k-mer in `kmer_db` (the union over its references).
+    pub fn from_kmer_db(kmer_db: &KmerDbV2, fp_rate: f64) -> Result<Self> {
+        if kmer_db.canonical_policy != CanonicalPolicy::Lexicographic {
+            return Err(OrionKmerError::Generic(format!(
+                "Bloom filters only hold lexicographically canonical k-mers, but the database uses the {:?} canonical policy",
+                kmer_db.canonical_policy
+            ))
+            .into());
+        }
+        let unified = kmer_db.get_all_kmers_unified();
+        let mut filter =
+            KmerBloomFilter::with_capacity(unified.len(), fp_rate, kmer_db.k, kmer_db.canonical, kmer_db.hashing)?;
+        for &key in &unified {
+            filter.insert(key);
+        }
+        Ok(filter)
+    }
+
+    fn bit_positions(&self, key: u64) -> impl Iterator<Item = u64> + '_ {
+        let a = splitmix64(key);
+        let b = splitmix64(a) | 1;
+        (0..self.num_hashes).map(move |j| a.wrapping_add(j.wrapping_mul(b)) % self.num_bits)
+    }
+
+    pub fn insert(&mut self, key: u64) {
+        let positions: Vec<u64> = self.bit_positions(key).collect();
+        for bit in positions {
+            self.bits[(bit / 64) as usize] |= 1 << (bit % 64);
+        }
+        self.num_items += 1;
+    }
+
+    /// Whether `key` may be in the filter: false means it was never inserted, true is wrong
+    /// with about the false-positive rate the filter was sized for.
+    pub fn contains(&self, key: u64) -> bool {
+        self.bit_positions(key)
+            .all(|bit| self.bits[(bit / 64) as usize] & (1 << (bit % 64)) != 0)
+    }
+
+    /// Expected false-positive rate for the inserted k-mers, (1 - e^(-h n / m))^h.
+    pub fn estimated_fp_rate(&self) -> f64 {
+        let fill = 1.0 - (-(self.num_hashes as f64) * self.num_items as f64 / self.num_bits as f64).exp();
+        fill.powf(self.num_hashes as f64)
+    }
+
+    /// A `KmerDbV2` without references carrying the filter's k-mer settings, for key lookups.
+    pub fn metadata(&self) -> KmerDbV2 {
+        let mut kmer_db = KmerDbV2::new(self.k);
+        kmer_db.hashing = self.hashing;
+        kmer_db.canonical = self.canonical;
+        kmer_db
+    }
+
+    pub fn write_to<W: Write + ?Sized>(&self, writer: &mut W) -> Result<()> {
+        let mut header = [0u8; HEADER_LEN];
+        header[0..8].copy_from_slice(BLOOM_FILTER_MAGIC);
+        header[8] = self.k;
+        header[9] = u8::from(self.canonical);
+        header[10] = u8::from(self.hashing.is_some());
+        header[16..24].copy_from_slice(&self.hashing.map_or(0, |hashing| hashing.seed).to_le_bytes());
+        header[24..32].copy_from_slice(&self.num_bits.to_le_bytes());
+        header[32..40].copy_from_slice(&self.num_hashes.to_le_bytes());
+        header[40..48].copy_from_slice(&self.num_items.to_le_bytes());
+        writer.write_all(&header).context("Failed to write Bloom filter header")?;
+        for word in &self.bits {
+            writer
+                .write_all(&word.to_le_bytes())
+                .context("Failed to write Bloom filter bits")?;
+        }
+        Ok(())
+    }
+
+    pub fn read_from<R: Read + ?Sized>(reader: &mut R) -> Result<Self> {
+        let mut header = [0u8; HEADER_LEN];
+        reader
+            .read_exact(&mut header)
+            .context("Failed to read Bloom filter header")?;
+        if &header[0..8] != BLOOM_FILTER_MAGIC {
+            return Err(OrionKmerError::Generic(
+                "Not a Bloom filter written by `dump --format bloom` (bad magic bytes)".to_string(),
+            )
+            .into());
+        }
+        let read_u64 = |offset: usize| u64::from_le_bytes(header[offset..offset + 8].try_into().unwrap());
+        let (num_bits, num_hashes) = (read_u64(24), read_u64(32));
+        if num_bits == 0 || num_hashes == 0 || num_hashes > MAX_HASH_FUNCTIONS {
+            return Err(OrionKmerError::Generic(format!(
+                "Corrupt Bloom filter header: {} bits, {} hash functions",
+                num_bits, num_hashes
+            ))
+            .into());
+        }
+        let hashing = (header[10] != 0).then(|| KmerHashing {
+            function: HashFunction::Murmur3X64,
+            seed: read_u64(16),
+        });
+        let mut bits = vec![0u64; num_bits.div_ceil(64) as usize];
+        let mut word = [0u8; 8];
+        for slot in &mut bits {
+            reader
+                .read_exact(&mut word)
+                .context("Bloom filter bit array is truncated")?;
+            *slot = u64::from_le_bytes(word);
+        }
+        Ok(KmerBloomFilter {
+            k: header[8],
+            canonical: header[9] != 0,
+            hashing,
+            num_bits,
+            num_hashes,
+            num_items: read_u64(40),
+            bits,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bloom_filter_has_no_false_negatives_and_round_trips() {
+        let mut filter = KmerBloomFilter::with_capacity(1000, 0.01, 21, true, None).unwrap();
+        for key in 0..1000u64 {
+            filter.insert(key * 7919);
+        }
+        assert!((0..1000u64).all(|key| filter.contains(key * 7919)));
+        let false_positives = (0..10_000u64).filter(|key| filter.contains(key * 7919 + 1)).count();
+        assert!(false_positives < 300, "{} false positives", false_positives);
+
+        let mut bytes = Vec::new();
+        filter.write_to(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), HEADER_LEN + filter.num_bits.div_ceil(64) as usize * 8);
+        assert_eq!(KmerBloomFilter::read_from(&mut bytes.as_slice()).unwrap(), filter);
+    }
+
+    #[test]
+    fn test_bloom_filter_rejects_bad_fp_rate() {
+        assert!(KmerBloomFilter::with_capacity(10, 0.0, 21, true, None).is_err());
+        assert!(KmerBloomFilter::with_capacity(10, 1.0, 21, true, None).is_err());
+    }
+}
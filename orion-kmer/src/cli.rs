@@ -1,4 +1,4 @@
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 use std::path::PathBuf;
 
 #[derive(Parser, Debug)]
@@ -17,173 +17,1830 @@ pub struct Cli {
     )]
     pub threads: usize,
 
+    #[clap(
+        long,
+        global = true,
+        help = "Fail instead of warning when --threads exceeds the number of logical cores"
+    )]
+    pub strict_threads: bool,
+
     #[clap(short, long, global = true, action = clap::ArgAction::Count, help = "Verbosity level (e.g., -v, -vv)")]
     pub verbose: u8,
+
+    #[clap(
+        long,
+        global = true,
+        help = "Write newline-delimited JSON progress events to this file ('-' for stderr) instead of drawing progress bars"
+    )]
+    pub progress_json: Option<PathBuf>,
+
+    #[clap(
+        long,
+        global = true,
+        help = "Sample the process's resident memory (RSS) every second while each task runs and write the samples to this TSV file (unix_time_ms, task, elapsed_ms, rss_bytes), to plot memory growth or find what ran out of memory"
+    )]
+    pub profile_memory: Option<PathBuf>,
+
+    #[clap(
+        long,
+        global = true,
+        help = "Decimal digits for floating-point output fields (classify TSV defaults to 4; compare and search JSON to full precision)"
+    )]
+    pub precision: Option<usize>,
+
+    #[clap(
+        long,
+        global = true,
+        value_enum,
+        help = "Parse uncompressed input as this format instead of detecting FASTA/FASTQ from the first byte (count, build, query and classify)"
+    )]
+    pub assume_format: Option<SequenceFormat>,
+
+    #[clap(
+        long,
+        global = true,
+        help = "Print how much wall time count, build and classify spent in each phase (parsing, k-mer extraction, set operations, serialization) once they succeed. Ignored with --log-format json"
+    )]
+    pub profile: bool,
+
+    #[clap(
+        long,
+        global = true,
+        help = "Check that every input file can be opened and print the resolved plan (inputs, outputs, k) as JSON instead of running the command"
+    )]
+    pub dry_run: bool,
+
+    #[clap(
+        long,
+        global = true,
+        help = "Read U/u in sequences and k-mer arguments as T, for RNA input"
+    )]
+    pub rna: bool,
+
+    #[clap(
+        long,
+        global = true,
+        default_value_t = 1,
+        help = "Decompress .xz and .zst inputs on a background thread, up to this many 1 MiB chunks ahead of parsing, when above 1. The xz and zstd decoders are single-threaded, so this overlaps decompression with k-mer processing rather than splitting it."
+    )]
+    pub decompress_threads: usize,
+
+    #[clap(
+        long,
+        global = true,
+        default_value_t = 1,
+        help = "Compress .gz output on this many threads, in independent 1 MiB gzip members that any gzip reader (including gunzip) concatenates. 1 writes a single-member gzip stream."
+    )]
+    pub compress_threads: usize,
+
+    #[clap(
+        long,
+        global = true,
+        default_value_t = 100_000,
+        help = "Log progress (at -vv) every this many records read by count, build and classify"
+    )]
+    pub progress_interval: usize,
+
+    #[clap(
+        long,
+        global = true,
+        value_enum,
+        default_value_t = LogFormat::Text,
+        help = "Format of log lines on stderr: human-readable text, or one JSON object per line (timestamp, level, target, message) for log aggregators"
+    )]
+    pub log_format: LogFormat,
+
+    #[clap(
+        long,
+        global = true,
+        help = "Stop reading a .gz input cleanly at the end of its last valid gzip member when it is followed by bytes that are not another member (e.g. padding from a broken download), with a warning giving the byte offset, instead of failing"
+    )]
+    pub lenient_gzip: bool,
+
+    #[clap(
+        long,
+        global = true,
+        alias = "stop-after",
+        help = "Stop reading input after this many records in total, across all input files, for quick checks of count, build, query and classify on huge inputs. Other commands always read their whole inputs"
+    )]
+    pub max_records: Option<u64>,
+
+    #[clap(
+        long,
+        global = true,
+        help = "Reference FASTA that .cram inputs are decoded against, needed for aligned (reference-compressed) CRAM. Supports .gz, .xz, .zst, .lz4 compression."
+    )]
+    pub reference: Option<PathBuf>,
+
+    #[clap(
+        long,
+        global = true,
+        help = "K-mers to remove from every database as it is loaded, from a k-mer database (built without --hashed) or a text list with one k-mer per line. Its k must match the databases'. Supports .gz, .xz, .zst, .lz4 compression."
+    )]
+    pub blocklist: Option<PathBuf>,
+}
+
+/// Log line formats for `--log-format`.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Commands {
+    /// Count k-mers in FASTA/FASTQ files
+    Count(CountArgs),
+    /// Build a unique k-mer database from genome assemblies
+    Build(BuildArgs),
+    /// Compare two k-mer databases
+    Compare(CompareArgs),
+    /// Report the references and k-mers that changed between two versions of a database
+    Diff(DiffArgs),
+    /// Query short reads against a k-mer database
+    Query(QueryArgs),
+    /// Classify sequences against k-mer databases and report coverage statistics
+    Classify(ClassifyArgs),
+    /// Export the contents of a k-mer database for external analysis
+    Dump(DumpArgs),
+    /// Extract regions of target genomes made of k-mers absent from background databases
+    Markers(MarkersArgs),
+    /// List the de Bruijn graph neighbors of a k-mer that are present in a database
+    Neighbors(NeighborsArgs),
+    /// Measure how much of a sample (MinHash sketch or sequences) is contained in a k-mer database
+    Search(SearchArgs),
+    /// Check that a FASTA/FASTQ file parses and summarize its contents
+    Validate(ValidateArgs),
+    /// Sum k-mer count tables (e.g. from a sharded count run) without recounting
+    Sum(SumArgs),
+    /// Rewrite a k-mer database in another on-disk representation without reprocessing sequences
+    Convert(ConvertArgs),
+    /// Write each reference of a k-mer database to its own single-reference database file
+    Split(SplitArgs),
+    /// Print a database's k-mer settings, size and, for databases written by `build`, how it was built
+    Info(InfoArgs),
+    /// Write the reverse complement of every record of a FASTA/FASTQ file
+    Revcomp(RevcompArgs),
+    /// Estimate the average nucleotide identity (ANI) of two genomes from their shared k-mers
+    Ani(AniArgs),
+    /// Decompress a file to stdout, or transcode it to the compression of the -o extension
+    Cat(CatArgs),
+    /// Time build and count end to end on a generated genome and print the timings as JSON
+    #[clap(hide = true)]
+    Bench(BenchArgs),
+    /// Check that every compression codec and the database format work in this environment
+    Doctor(DoctorArgs),
+}
+
+/// Sequence file formats that `--assume-format` can force.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SequenceFormat {
+    Fasta,
+    Fastq,
+}
+
+#[derive(Parser, Debug)]
+pub struct CountArgs {
+    #[clap(short, long, required_unless_present = "match_db", help = "The length of the k-mer")]
+    pub kmer_size: Option<u8>,
+
+    #[clap(
+        long,
+        help = "Count at the k of this database (read from its header) instead of -k, so the counts match a later classify/query against it. Fails if -k is also given and differs."
+    )]
+    pub match_db: Option<PathBuf>,
+
+    #[clap(short, long, required = true, num_args = 1.., help = "One or more input FASTA/FASTQ files. Supports .gz, .xz, .zst, .lz4 compression.")]
+    pub input_files: Vec<PathBuf>,
+
+    #[clap(
+        short,
+        long,
+        required_unless_present = "estimate_only",
+        help = "Output file for k-mer counts (kmer<TAB>count). Supports .gz, .xz, .zst, .lz4 compression based on extension."
+    )]
+    pub output_file: Option<PathBuf>,
+
+    #[clap(
+        short = 'm',
+        long,
+        default_value_t = 1,
+        help = "Minimum count to report a k-mer"
+    )]
+    pub min_count: usize,
+
+    #[clap(
+        long,
+        help = "Only report k-mers with a count of at least this value (combined with --min-count)"
+    )]
+    pub at_least: Option<usize>,
+
+    #[clap(
+        long,
+        help = "Only report k-mers with a count of at most this value (e.g. 1 for singletons)"
+    )]
+    pub at_most: Option<usize>,
+
+    #[clap(
+        long,
+        conflicts_with_all = ["at_least", "at_most"],
+        help = "Only report k-mers with exactly this count (e.g. 1 for singletons, which are likely errors)"
+    )]
+    pub exactly: Option<usize>,
+
+    #[clap(
+        long,
+        help = "Expand IUPAC ambiguity codes into every concrete ACGT k-mer instead of skipping those windows"
+    )]
+    pub expand_iupac: bool,
+
+    #[clap(
+        long,
+        help = "Process records even when most of their characters are not A/C/G/T/U/N; by default such input is rejected as protein or non-DNA"
+    )]
+    pub allow_non_dna: bool,
+
+    #[clap(
+        long,
+        default_value_t = 2,
+        help = "With --expand-iupac, skip windows containing more than this many ambiguous positions"
+    )]
+    pub max_ambiguous_bases: usize,
+
+    #[clap(
+        long,
+        help = "Report how many k-mer windows were skipped because they contained N or other non-ACGT characters"
+    )]
+    pub report_invalid: bool,
+
+    #[clap(
+        long = "include-seq",
+        help = "Only use records with this sequence ID (header up to the first whitespace). Repeatable."
+    )]
+    pub include_seq: Vec<String>,
+
+    #[clap(
+        long = "exclude-seq",
+        help = "Skip records with this sequence ID. Repeatable; takes precedence over --include-seq and --seq-regex."
+    )]
+    pub exclude_seq: Vec<String>,
+
+    #[clap(
+        long,
+        help = "Only use records whose sequence ID matches this regular expression anywhere in the ID, unless anchored with ^ and $ (in addition to any --include-seq names)"
+    )]
+    pub seq_regex: Option<String>,
+
+    #[clap(
+        long,
+        help = "Only use this fraction (0-1) of records, chosen deterministically from a hash of each record ID"
+    )]
+    pub subsample: Option<f64>,
+
+    #[clap(
+        long,
+        default_value_t = 0,
+        requires = "subsample",
+        help = "Seed for --subsample; the same seed selects the same records across runs"
+    )]
+    pub subsample_seed: u64,
+
+    #[clap(
+        long,
+        help = "Skip k-mers found in this file (e.g. adapters): a k-mer database (.db) or a text list with one k-mer per line. Its k must match -k."
+    )]
+    pub mask_kmers: Option<PathBuf>,
+
+    #[clap(
+        long,
+        help = "Weight each FASTQ k-mer occurrence by the lowest base quality in its window instead of counting it as 1, writing kmer<TAB>weighted_count (FASTA records weigh 1)"
+    )]
+    pub qual_weight: bool,
+
+    #[clap(
+        long,
+        default_value_t = 20,
+        requires = "qual_weight",
+        help = "With --qual-weight, windows whose lowest Phred quality is at least this weigh 1.0; lower qualities weigh proportionally less"
+    )]
+    pub full_weight_qual: u8,
+
+    #[clap(
+        long,
+        conflicts_with = "qual_weight",
+        help = "Count each k-mer at most once per record, so a k-mer's count is the number of records containing it (document frequency)"
+    )]
+    pub per_record_unique: bool,
+
+    #[clap(
+        long,
+        conflicts_with = "qual_weight",
+        help = "Count each input file separately and write a matrix: kmer<TAB>one count column per input file (0 when absent). -m applies to the row sum."
+    )]
+    pub matrix: bool,
+
+    #[clap(
+        long,
+        value_delimiter = ',',
+        conflicts_with_all = ["qual_weight", "matrix"],
+        help = "Comma-separated, increasing read-length boundaries (e.g. 100,1000) splitting reads into length bins (0-99, 100-999, 1000+), counted separately and written as kmer<TAB>bin<TAB>count. -m applies to each bin's count."
+    )]
+    pub length_bins: Vec<usize>,
+
+    #[clap(
+        long,
+        help = "Also write a per-file QC report: filename<TAB>unique_kmers<TAB>total_kmers for each input file, counting the k-mers that file contributed before -m filtering. Supports .gz, .xz, .zst, .lz4 compression based on extension."
+    )]
+    pub per_file_report: Option<PathBuf>,
+
+    #[clap(
+        long,
+        help = "Count into a plain hash map without atomics (independent of --threads), for reproducibility checks against the default concurrent counting"
+    )]
+    pub single_thread: bool,
+
+    #[clap(
+        long,
+        value_enum,
+        default_value_t = CountType::U32,
+        help = "Integer type of each k-mer's count in the count map. Narrower types shrink each map entry (16, 12 or 10 bytes for u64, u32 or u16); counts saturate at the type's maximum instead of overflowing, with a warning when any does"
+    )]
+    pub count_type: CountType,
+
+    #[clap(
+        long,
+        help = "Add each k-mer's reverse complement as a second column (kmer<TAB>revcomp<TAB>count), for manual inspection. `sum` and `build --from-counts` do not read this layout."
+    )]
+    pub with_revcomp: bool,
+
+    #[clap(
+        long,
+        conflicts_with_all = ["qual_weight", "per_record_unique", "matrix", "length_bins"],
+        help = "Add a strand column (kmer<TAB>count<TAB>strand): + when the k-mer was read in canonical orientation (its forward encoding is the smaller) in at least half of its occurrences, - otherwise"
+    )]
+    pub emit_orientation: bool,
+
+    #[clap(
+        long,
+        help = "Report how many k-mer occurrences were read in canonical orientation (their forward encoding is the smaller) versus reverse-complement orientation, and the forward fraction; a fraction far from 0.5 points to strand-specific or artifact-laden data"
+    )]
+    pub canonical_balance: bool,
+
+    #[clap(
+        long,
+        value_name = "BASE",
+        help = "Trim a trailing run of this base (A, C, G or T), e.g. G for the poly-G tails of two-color Illumina chemistry, off each record before k-mer extraction when the run is at least --polyx-min-run long"
+    )]
+    pub trim_polyx: Option<char>,
+
+    #[clap(
+        long,
+        default_value_t = 10,
+        requires = "trim_polyx",
+        help = "Shortest trailing homopolymer run that --trim-polyx trims"
+    )]
+    pub polyx_min_run: usize,
+
+    #[clap(
+        long,
+        help = "Fail with an error instead of writing empty output when no k-mers were counted, e.g. from empty or header-only inputs"
+    )]
+    pub fail_on_empty: bool,
+
+    #[clap(
+        long,
+        value_enum,
+        default_value = "value",
+        conflicts_with_all = ["matrix", "length_bins"],
+        help = "Order of the output rows: value sorts by k-mer; count by descending count (weight with --qual-weight), ties broken by k-mer, so the order is total and reproducible"
+    )]
+    pub sort: CountSortOrder,
+
+    #[clap(
+        long,
+        conflicts_with_all = ["matrix", "length_bins"],
+        help = "Only estimate the number of distinct k-mers with a HyperLogLog sketch, in the same pass over the input but without storing counts, and print it with the approximate memory counting them would take; no counts are written"
+    )]
+    pub estimate_only: bool,
+
+    #[clap(
+        long,
+        value_name = "N",
+        conflicts_with_all = ["estimate_only", "matrix", "length_bins", "qual_weight", "per_record_unique", "emit_orientation"],
+        help = "Write only about the N most frequent k-mers with estimated counts, found with a Count-Min Sketch and a heavy-hitters list in constant memory (16 MiB plus the N candidates) however large the input, instead of counting every k-mer exactly. Estimates never undercount; --min-count/--at-least/--at-most/--exactly filter on them"
+    )]
+    pub approx_top: Option<usize>,
+}
+
+#[derive(Parser, Debug)]
+pub struct BuildArgs {
+    #[clap(short, long, required_unless_present = "k_list", conflicts_with = "k_list", help = "The length of the k-mer")]
+    pub kmer_size: Option<u8>,
+
+    #[clap(
+        long,
+        value_delimiter = ',',
+        num_args = 1..,
+        conflicts_with_all = ["from_counts", "append", "checkpoint", "uniqueness_report", "overlap_matrix", "sorted_index"],
+        help = "Build one table per k-mer size in this comma-separated list (e.g. 21,27,31) from the same inputs and write them all to a single multi-k database; `query` and `classify` pick a table with --kmer-size"
+    )]
+    pub k_list: Vec<u8>,
+
+    #[clap(short = 'g', long = "genomes", required_unless_present = "from_counts", num_args = 1.., help = "One or more input genome assembly files (FASTA). Supports .gz, .xz, .zst, .lz4 compression.")]
+    pub genome_files: Vec<PathBuf>,
+
+    #[clap(
+        long,
+        conflicts_with_all = ["genome_files", "no_canonical", "per_record"],
+        help = "Build a single reference from a `count` k-mer table (TSV, or a binary table from `classify --dump-input-kmers`) instead of sequence files, skipping a second pass over the reads"
+    )]
+    pub from_counts: Option<PathBuf>,
+
+    #[clap(
+        long,
+        default_value_t = 1,
+        requires = "from_counts",
+        help = "With --from-counts, only keep k-mers counted at least this many times"
+    )]
+    pub min_count: usize,
+
+    #[clap(
+        long,
+        requires = "from_counts",
+        help = "With --from-counts, the reference name (default: the count table's file name without its last extension)"
+    )]
+    pub ref_name: Option<String>,
+
+    #[clap(
+        short,
+        long,
+        required = true,
+        help = "Output path for the binary k-mer database. Supports .gz, .xz, .zst, .lz4 compression based on extension."
+    )]
+    pub output_file: PathBuf,
+
+    #[clap(
+        long,
+        help = "Expand IUPAC ambiguity codes into every concrete ACGT k-mer instead of skipping those windows"
+    )]
+    pub expand_iupac: bool,
+
+    #[clap(
+        long,
+        help = "Process records even when most of their characters are not A/C/G/T/U/N; by default such input is rejected as protein or non-DNA"
+    )]
+    pub allow_non_dna: bool,
+
+    #[clap(
+        long,
+        default_value_t = 2,
+        help = "With --expand-iupac, skip windows containing more than this many ambiguous positions"
+    )]
+    pub max_ambiguous_bases: usize,
+
+    #[clap(
+        long,
+        help = "Report how many k-mer windows were skipped because they contained N or other non-ACGT characters"
+    )]
+    pub report_invalid: bool,
+
+    #[clap(
+        long = "include-seq",
+        help = "Only use records with this sequence ID (header up to the first whitespace). Repeatable."
+    )]
+    pub include_seq: Vec<String>,
+
+    #[clap(
+        long = "exclude-seq",
+        help = "Skip records with this sequence ID. Repeatable; takes precedence over --include-seq and --seq-regex."
+    )]
+    pub exclude_seq: Vec<String>,
+
+    #[clap(
+        long,
+        help = "Only use records whose sequence ID matches this regular expression anywhere in the ID, unless anchored with ^ and $ (in addition to any --include-seq names)"
+    )]
+    pub seq_regex: Option<String>,
+
+    #[clap(
+        long,
+        help = "Only use this fraction (0-1) of records, chosen deterministically from a hash of each record ID"
+    )]
+    pub subsample: Option<f64>,
+
+    #[clap(
+        long,
+        default_value_t = 0,
+        requires = "subsample",
+        help = "Seed for --subsample; the same seed selects the same records across runs"
+    )]
+    pub subsample_seed: u64,
+
+    #[clap(
+        long,
+        help = "Keep at most this many k-mers per reference, sampled by a seeded hash; the sampling rate is stored so classify can report it"
+    )]
+    pub max_kmers_per_reference: Option<usize>,
+
+    #[clap(
+        long,
+        default_value_t = 0,
+        requires = "max_kmers_per_reference",
+        help = "Seed for --max-kmers-per-reference; the same seed keeps the same k-mers across runs"
+    )]
+    pub sampling_seed: u64,
+
+    #[clap(
+        long,
+        help = "Store 64-bit MurmurHash3 hashes of canonical k-mers instead of their 2-bit encodings (sourmash/Mash compatible)"
+    )]
+    pub hashed: bool,
+
+    #[clap(
+        long,
+        default_value_t = 42,
+        requires = "hashed",
+        help = "Seed for --hashed k-mer hashing"
+    )]
+    pub hash_seed: u64,
+
+    #[clap(
+        long,
+        help = "Store each k-mer as read instead of its canonical form (the smaller of the k-mer and its reverse complement), e.g. for strand-specific data. Recorded in the database; `compare` rejects comparing it with a canonical database."
+    )]
+    pub no_canonical: bool,
+
+    #[clap(
+        long,
+        value_enum,
+        default_value_t = CanonicalPolicyKind::Lexicographic,
+        conflicts_with = "no_canonical",
+        help = "How the stored strand of each k-mer is chosen: the lexicographically smaller one, or the one with the smaller minimizer (smallest --minimizer-length subword), so k-mers sharing a minimizer are stored on the same strand. Recorded in the database; query, classify and the other commands canonicalize their k-mers the same way, and reject mixing databases of different policies."
+    )]
+    pub canonical_policy: CanonicalPolicyKind,
+
+    #[clap(
+        long,
+        default_value_t = 7,
+        help = "Subword length m of --canonical-policy minimizer, between 1 and k"
+    )]
+    pub minimizer_length: u8,
+
+    #[clap(
+        long,
+        help = "Existing database to extend: its references are kept and the -g files are added as new references. -k must match the database."
+    )]
+    pub append: Option<PathBuf>,
+
+    #[clap(
+        long,
+        value_enum,
+        help = "What to do when a new reference has the same name as one already in the database [default: error with --append or --resume, otherwise replace, so a later input replaces an earlier one of the same name]"
+    )]
+    pub on_conflict: Option<OnConflict>,
+
+    #[clap(
+        long,
+        value_enum,
+        default_value_t = RefNameStyle::Filename,
+        help = "How to name each input file's reference, e.g. `path` to keep same-named files from different directories apart"
+    )]
+    pub ref_name_style: RefNameStyle,
+
+    #[clap(
+        long,
+        help = "Replace every character of reference names that is not an ASCII letter or digit with '_', adding _2, _3, ... to names that then collide"
+    )]
+    pub sanitize_names: bool,
+
+    #[clap(
+        long,
+        value_enum,
+        value_name = "MODE",
+        help = "Trim whitespace from reference names (`trim`), or trim and lowercase them (`lowercase`), before storing them. Names that then coincide are merged according to --on-conflict. Recorded in the database, so --only-reference/--exclude-reference names are normalized the same way"
+    )]
+    pub normalize_ref_names: Option<RefNameNormalization>,
+
+    #[clap(
+        long,
+        default_value_t = crate::commands::build::DEFAULT_BATCH_SIZE,
+        help = "Records per batch handed from the parsing thread to the k-mer extraction threads"
+    )]
+    pub batch_size: usize,
+
+    #[clap(
+        long,
+        default_value_t = 1,
+        help = "Only store k-mers occurring at least this many times in their input file, e.g. to drop k-mers from sequencing errors (counting uses more memory than presence)"
+    )]
+    pub min_multiplicity: usize,
+
+    #[clap(
+        long,
+        conflicts_with = "min_multiplicity",
+        help = "Make every record its own reference, named by its sequence ID, and store where each k-mer lies in it (needed by `classify --coverage-track`)"
+    )]
+    pub per_record: bool,
+
+    #[clap(
+        long,
+        help = "Optional: Write a TSV of how many of each reference's k-mers occur in no other reference (reference, total_kmers, unique_kmers, unique_fraction). Supports .gz, .xz, .zst, .lz4 compression based on extension."
+    )]
+    pub uniqueness_report: Option<PathBuf>,
+
+    #[clap(
+        long,
+        alias = "report-shared-kmers",
+        help = "Optional: Write a TSV matrix of how many k-mers each pair of references shares (the diagonal is each reference's k-mer count), to spot redundant references. Supports .gz, .xz, .zst, .lz4 compression based on extension."
+    )]
+    pub overlap_matrix: Option<PathBuf>,
+
+    #[clap(
+        long,
+        help = "Save the partial database to this path after each completed reference, so an interrupted build can be resumed"
+    )]
+    pub checkpoint: Option<PathBuf>,
+
+    #[clap(
+        long,
+        requires = "checkpoint",
+        help = "Continue from --checkpoint if it exists, skipping references it already contains"
+    )]
+    pub resume: bool,
+
+    #[clap(
+        long,
+        help = "Also write the database as an uncompressed sorted k-mer index to this path, for `query --mmap` and `classify --mmap`"
+    )]
+    pub sorted_index: Option<PathBuf>,
+
+    #[clap(
+        long,
+        help = "Fail with an error instead of writing the database when it holds no k-mers at all, e.g. built from empty or header-only FASTA files"
+    )]
+    pub fail_on_empty: bool,
+}
+
+/// Integer types of the counts `count` holds per k-mer (see `count --count-type`).
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CountType {
+    U16,
+    U32,
+    U64,
+}
+
+/// Output orderings of `count --sort`.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CountSortOrder {
+    /// By k-mer
+    Value,
+    /// By descending count, ties broken by k-mer
+    #[value(alias = "count-then-value")]
+    Count,
+}
+
+/// Canonicalization policies of `build --canonical-policy` (see `kmer::CanonicalPolicy`).
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CanonicalPolicyKind {
+    /// The lexicographically smaller of the k-mer and its reverse complement
+    Lexicographic,
+    /// The strand with the smaller minimizer, ties broken lexicographically
+    Minimizer,
+}
+
+/// Policy for a reference whose name is already present in the database being built.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OnConflict {
+    /// Abort with an error
+    Error,
+    /// Keep the existing reference and ignore the new one
+    Skip,
+    /// Overwrite the existing reference with the new one
+    Replace,
+}
+
+/// How `build --normalize-ref-names` rewrites reference names.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RefNameNormalization {
+    /// Remove leading and trailing whitespace
+    Trim,
+    /// Remove leading and trailing whitespace and lowercase
+    Lowercase,
+}
+
+/// How `build` derives a reference name from an input file.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RefNameStyle {
+    /// The file name, e.g. `genome.fa.gz`
+    Filename,
+    /// The path as given on the command line
+    Path,
+    /// The file name without its compression and sequence extensions, e.g. `genome`
+    Stem,
+    /// The header (ID and description) of the file's first record
+    Header,
+}
+
+/// Formats of the `compare -o` output.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompareOutputFormat {
+    Json,
+    Tsv,
+}
+
+#[derive(Parser, Debug)]
+pub struct CompareArgs {
+    #[clap(
+        long,
+        required_unless_present = "stream_fasta",
+        help = "First k-mer database file. Supports .gz, .xz, .zst, .lz4 compression."
+    )]
+    pub db1: Option<PathBuf>,
+
+    #[clap(
+        long,
+        required_unless_present_any = ["against_fasta", "stream_fasta"],
+        help = "Second k-mer database file. Supports .gz, .xz, .zst, .lz4 compression."
+    )]
+    pub db2: Option<PathBuf>,
+
+    #[clap(
+        long,
+        conflicts_with = "db2",
+        help = "Compare --db1 against the k-mers of this FASTA/FASTQ file (with --db1's k and hashing) instead of a second database. Supports .gz, .xz, .zst, .lz4 compression."
+    )]
+    pub against_fasta: Option<PathBuf>,
+
+    #[clap(
+        long,
+        num_args = 2,
+        value_names = ["A", "B"],
+        conflicts_with_all = ["db1", "db2", "against_fasta", "tsv"],
+        requires = "kmer_size",
+        help = "Estimate the Jaccard index of two FASTA/FASTQ files from bottom-N MinHash sketches built while streaming them, without building k-mer sets or databases. Supports .gz, .xz, .zst, .lz4 compression."
+    )]
+    pub stream_fasta: Vec<PathBuf>,
+
+    #[clap(short, long, requires = "stream_fasta", help = "K-mer size for --stream-fasta")]
+    pub kmer_size: Option<u8>,
+
+    #[clap(
+        long,
+        default_value_t = 1000,
+        requires = "stream_fasta",
+        help = "Number of smallest k-mer hashes kept per file with --stream-fasta; larger sketches give more precise estimates"
+    )]
+    pub sketch_size: usize,
+
+    #[clap(
+        long,
+        default_value_t = 42,
+        requires = "stream_fasta",
+        help = "Seed for the --stream-fasta k-mer hashing (42 matches sourmash)"
+    )]
+    pub hash_seed: u64,
+
+    #[clap(
+        short,
+        long,
+        required_unless_present_any = ["tsv", "sqlite", "intersection_db"],
+        help = "Output file for comparison stats (JSON, or TSV with --output-format tsv). Supports .gz, .xz, .zst, .lz4 compression based on extension."
+    )]
+    pub output_file: Option<PathBuf>,
+
+    #[clap(
+        long,
+        value_enum,
+        default_value_t = CompareOutputFormat::Json,
+        requires = "output_file",
+        conflicts_with = "stream_fasta",
+        help = "Format of the -o output: pretty JSON, or a TSV header and one row with the --tsv columns"
+    )]
+    pub output_format: CompareOutputFormat,
+
+    #[clap(
+        long,
+        help = "Also (or, without -o, only) write the statistics as one TSV row: db1, db2, k, intersection, union, jaccard, containment1, containment2"
+    )]
+    pub tsv: Option<PathBuf>,
+
+    #[clap(
+        long,
+        requires = "tsv",
+        help = "Append the --tsv row to an existing file instead of overwriting it; the header is only written when the file is new or empty"
+    )]
+    pub append: bool,
+
+    #[clap(
+        long,
+        conflicts_with = "stream_fasta",
+        help = "Also (or, without -o, only) append the statistics as a row of the `comparisons` table of this SQLite database, creating the database and its tables if absent"
+    )]
+    pub sqlite: Option<PathBuf>,
+
+    #[clap(
+        long,
+        conflicts_with = "stream_fasta",
+        help = "Also (or, without -o, only) write the k-mers shared by both inputs as a database with a single reference named `intersection`, stored like the inputs (k, hashing, canonical form), for querying them later. Supports .gz, .xz, .zst, .lz4 compression based on extension."
+    )]
+    pub intersection_db: Option<PathBuf>,
+
+    // Databases that disagree on canonical storage are now always rejected; the flag is kept
+    // so existing scripts keep working.
+    #[clap(long, hide = true)]
+    pub strict: bool,
+
+    #[clap(
+        long,
+        help = "When one database is canonical and the other is not, canonicalize the non-canonical one's k-mers before comparing instead of failing. The result is approximate."
+    )]
+    pub coerce_canonical: bool,
+
+    #[clap(
+        long,
+        help = "For hashed databases, estimate how many shared k-mers are hash collisions between distinct k-mers (from the hash width and set sizes), report it in the JSON output and warn when the collision probability exceeds --collision-threshold"
+    )]
+    pub collision_check: bool,
+
+    #[clap(
+        long,
+        default_value_t = 1e-6,
+        requires = "collision_check",
+        help = "Probability of at least one spurious shared k-mer above which --collision-check warns"
+    )]
+    pub collision_threshold: f64,
+
+    #[clap(
+        long = "only-reference",
+        help = "Only use this database reference, dropping all others before computing. Repeatable."
+    )]
+    pub only_reference: Vec<String>,
+
+    #[clap(
+        long = "exclude-reference",
+        help = "Ignore this database reference (e.g. a spike-in) before computing. Repeatable."
+    )]
+    pub exclude_reference: Vec<String>,
+}
+
+#[derive(Parser, Debug)]
+pub struct DiffArgs {
+    #[clap(long, required = true, help = "Previous version of the k-mer database. Supports .gz, .xz, .zst, .lz4 compression.")]
+    pub old: PathBuf,
+
+    #[clap(long, required = true, help = "New version of the k-mer database. Supports .gz, .xz, .zst, .lz4 compression.")]
+    pub new: PathBuf,
+
+    #[clap(
+        short,
+        long,
+        required = true,
+        help = "Output file for the diff report (JSON format). Supports .gz, .xz, .zst, .lz4 compression based on extension."
+    )]
+    pub output_file: PathBuf,
+}
+
+#[derive(Parser, Debug)]
+pub struct QueryArgs {
+    #[clap(
+        short = 'd',
+        long = "database",
+        required_unless_present = "bloom_files",
+        num_args = 1..,
+        help = "One or more k-mer databases to query against (see --combine). Supports .gz, .xz, .zst, .lz4 compression."
+    )]
+    pub database_files: Vec<PathBuf>,
+
+    #[clap(
+        long = "bloom-file",
+        num_args = 1..,
+        conflicts_with_all = ["database_files", "mmap", "disk_index", "derived_k", "annotate_references", "mask", "only_reference", "exclude_reference"],
+        help = "Query against one or more Bloom filters written by `dump --format bloom` instead of databases, without loading the k-mers. Membership tests have the filter's false-positive rate, so reads may gain spurious hits but never lose real ones. Supports .gz, .xz, .zst, .lz4 compression."
+    )]
+    pub bloom_files: Vec<PathBuf>,
+
+    #[clap(
+        short = 'r',
+        long = "reads",
+        required = true,
+        help = "Short-read file (FASTQ). Supports .gz, .xz, .zst, .lz4 compression."
+    )]
+    pub reads_file: PathBuf,
+
+    #[clap(
+        short,
+        long,
+        required = true,
+        help = "Output file for the IDs of matching reads. Supports .gz, .xz, .zst, .lz4 compression based on extension."
+    )]
+    pub output_file: PathBuf,
+
+    #[clap(
+        short = 'c',
+        long = "min-hits",
+        default_value_t = 1,
+        help = "Minimum number of k-mer hits to report a read"
+    )]
+    pub min_hits: usize,
+
+    #[clap(
+        long,
+        help = "Stop counting a read's hits once it has this many (at least --min-hits), skipping its remaining windows. Bounds the work on repetitive reads without changing which reads are reported; strand counts, --annotate-references counts and --bed intervals then cover only the windows looked up"
+    )]
+    pub max_hits_cap: Option<usize>,
+
+    #[clap(
+        long,
+        value_enum,
+        default_value_t = DatabaseCombine::Any,
+        help = "With several databases, report reads meeting --min-hits against any or against all of them"
+    )]
+    pub combine: DatabaseCombine,
+
+    #[clap(
+        long,
+        help = "Append a column listing the databases (comma-separated paths) against which each reported read met --min-hits"
+    )]
+    pub annotate_databases: bool,
+
+    #[clap(
+        long,
+        conflicts_with_all = ["mmap", "disk_index", "derived_k", "mismatches"],
+        help = "Append a column listing the references (name:hits, comma-separated, most hits first) whose k-mers each reported read matched, looked up through a k-mer to references index"
+    )]
+    pub annotate_references: bool,
+
+    #[clap(
+        long,
+        conflicts_with_all = ["bloom_files", "mmap", "disk_index", "derived_k", "mismatches"],
+        help = "Bin the matching reads by reference: write each one, as read, to <DIR>/<reference>.fastq (.fasta for FASTA reads) for the reference it has the most k-mer hits against, looked up through a k-mer to references index. Ties go to the earlier database, then to the reference whose name sorts first."
+    )]
+    pub bin_output_dir: Option<PathBuf>,
+
+    #[clap(
+        long,
+        requires = "bin_output_dir",
+        help = "With --bin-output-dir, also write the queried reads that did not match (e.g. fewer than --min-hits hits) to <DIR>/unbinned.fastq"
+    )]
+    pub keep_unbinned: bool,
+
+    #[clap(
+        long,
+        value_name = "L",
+        conflicts_with_all = ["bloom_files", "mmap", "disk_index", "derived_k", "mismatches", "max_hits_cap", "strand", "canonical_both", "annotate_databases", "annotate_references"],
+        help = "Chimera-aware mode: split each reported read into consecutive segments of L bases (at least k; the last may be shorter) and write one row per segment instead of one per read: read_id, segment (start-end in read coordinates), best_reference (the reference with the most k-mer hits among the k-mers lying within the segment, or - for none) and potential_chimera (true when the read's segments have different best references)"
+    )]
+    pub segment_length: Option<usize>,
+
+    #[clap(
+        long,
+        conflicts_with_all = ["mmap", "disk_index", "derived_k"],
+        help = "Remove the k-mers of this database (e.g. repetitive or ubiquitous k-mers) from the query databases before querying, so they never count as hits. Its k, hashing and canonical form must match. Supports .gz, .xz, .zst, .lz4 compression."
+    )]
+    pub mask: Option<PathBuf>,
+
+    #[clap(
+        long,
+        conflicts_with = "error_on_duplicate_ids",
+        help = "Report each matching read ID once, keeping the first read with that ID, instead of one line per matching read"
+    )]
+    pub dedup_ids: bool,
+
+    #[clap(
+        long,
+        help = "Fail if two matching reads have the same ID"
+    )]
+    pub error_on_duplicate_ids: bool,
+
+    #[clap(
+        long,
+        help = "Optional: Output BED file (read_id<TAB>start<TAB>end) of read intervals covered by database k-mers. Supports .gz, .xz, .zst, .lz4 compression based on extension."
+    )]
+    pub bed: Option<PathBuf>,
+
+    #[clap(
+        long,
+        help = "Report per-read forward and reverse-complement hit counts (read_id<TAB>forward_hits<TAB>reverse_hits) instead of bare read IDs. Against a canonical database these count windows read in canonical orientation versus its reverse; against a --no-canonical database, windows found as read versus found only as their reverse complement"
+    )]
+    pub strand: bool,
+
+    #[clap(
+        long,
+        conflicts_with = "strand",
+        help = "Like --strand, but count a hit on every strand that matches, so a window found both as read and as its reverse complement in a --no-canonical database counts on both (for stranded RNA-seq). Against a canonical database this differs from --strand only for palindromic k-mers (see --palindromes)"
+    )]
+    pub canonical_both: bool,
+
+    #[clap(
+        long,
+        value_enum,
+        default_value_t = PalindromeCounting::Both,
+        requires = "canonical_both",
+        help = "With --canonical-both, how to count hits of palindromic k-mers, which match on both strands"
+    )]
+    pub palindromes: PalindromeCounting,
+
+    #[clap(
+        long,
+        help = "Fail instead of warning when the database's k-mer encoding cannot be proven compatible with the canonical k-mers used for lookup"
+    )]
+    pub strict: bool,
+
+    #[clap(
+        long,
+        default_value_t = 0,
+        help = "Skip reads shorter than this many bases"
+    )]
+    pub min_read_length: usize,
+
+    #[clap(
+        long,
+        help = "Skip FASTQ reads whose mean Phred quality is below this value (no effect on FASTA input)"
+    )]
+    pub min_mean_qual: Option<f64>,
+
+    #[clap(
+        long,
+        help = "Skip reads whose sequence complexity (normalized trinucleotide Shannon entropy, 0-1) is below this value, e.g. homopolymers and short tandem repeats"
+    )]
+    pub min_complexity: Option<f64>,
+
+    #[clap(
+        long,
+        default_value_t = 0,
+        help = "Trim this many bases (e.g. a fixed-length adapter) off the 5' end of each read before k-mer extraction"
+    )]
+    pub trim_front: usize,
+
+    #[clap(
+        long,
+        default_value_t = 0,
+        help = "Trim this many bases off the 3' end of each read before k-mer extraction. Reads shorter than --trim-front plus --trim-back are skipped."
+    )]
+    pub trim_back: usize,
+
+    #[clap(
+        long,
+        value_name = "BASE",
+        help = "Trim a trailing run of this base (A, C, G or T), e.g. G for the poly-G tails of two-color Illumina chemistry, off each record before k-mer extraction when the run is at least --polyx-min-run long"
+    )]
+    pub trim_polyx: Option<char>,
+
+    #[clap(
+        long,
+        default_value_t = 10,
+        requires = "trim_polyx",
+        help = "Shortest trailing homopolymer run that --trim-polyx trims"
+    )]
+    pub polyx_min_run: usize,
+
+    #[clap(
+        long,
+        default_value_t = 0,
+        help = "Also count a window as a hit if a k-mer one base substitution away is in the database (0 or 1), to tolerate sequencing errors. Multiplies lookups for missing windows by 3k, so only practical for small k."
+    )]
+    pub mismatches: u8,
+
+    #[clap(
+        short,
+        long,
+        help = "K-mer size the databases must have; required to choose a table of a multi-k database (see `build --k-list`)"
+    )]
+    pub kmer_size: Option<u8>,
+
+    #[clap(
+        long,
+        help = "Query at this smaller k: every k-length subword of the database k-mers is loaded into an in-memory index, and reads are looked up at this k for higher sensitivity. The index holds up to (database k - this k + 1) entries per database k-mer, so expect several times the database's memory."
+    )]
+    pub derived_k: Option<u8>,
+
+    #[clap(
+        long,
+        help = "Treat each --database as a sorted k-mer index (see `build --sorted-index`) and memory-map it instead of loading it into RAM"
+    )]
+    pub mmap: bool,
+
+    #[clap(
+        long,
+        conflicts_with = "mmap",
+        help = "Look read k-mers up by binary search in a memory-mapped sorted file instead of an in-memory set, trading CPU for much lower RAM. Each --database is loaded once and its sorted unique k-mers written to a temporary file; sorted k-mer indexes (see `build --sorted-index`) are mapped directly."
+    )]
+    pub disk_index: bool,
+
+    #[clap(
+        long = "only-reference",
+        conflicts_with = "mmap",
+        help = "Only use this database reference, dropping all others before computing. Repeatable."
+    )]
+    pub only_reference: Vec<String>,
+
+    #[clap(
+        long = "exclude-reference",
+        conflicts_with = "mmap",
+        help = "Ignore this database reference (e.g. a spike-in) before computing. Repeatable."
+    )]
+    pub exclude_reference: Vec<String>,
+}
+
+#[derive(Parser, Debug)]
+pub struct ClassifyArgs {
+    #[clap(
+        short,
+        long = "input-file",
+        required = true,
+        num_args = 1..,
+        help = "One or more input genome (FASTA) or reads (FASTQ) files, e.g. all the FASTQ files of one sample; their k-mers are pooled into a single profile. Supports .gz, .xz, .zst, .lz4 compression."
+    )]
+    pub input_files: Vec<PathBuf>,
+
+    #[clap(
+        short = 'd',
+        long = "databases",
+        required = true,
+        num_args = 1..,
+        help = "One or more k-mer database files (.db). Supports .gz, .xz, .zst, .lz4 compression."
+    )]
+    pub database_files: Vec<PathBuf>,
+
+    #[clap(
+        short,
+        long,
+        required_unless_present = "output_dir",
+        conflicts_with = "output_dir",
+        help = "Output file for classification results (JSON format). Supports .gz, .xz, .zst, .lz4 compression based on extension."
+    )]
+    pub output_file: Option<PathBuf>,
+
+    #[clap(
+        long,
+        conflicts_with = "output_tsv",
+        help = "Write the JSON results and the summary TSV to <input_stem>.classify.json and <input_stem>.classify.tsv in this directory (created if absent) instead of -o and --output-tsv, e.g. when classifying many samples in a loop. With several -i files the stem is that of the first"
+    )]
+    pub output_dir: Option<PathBuf>,
+
+    #[clap(
+        short,
+        long,
+        help = "Optional: K-mer size to validate against databases, and the table to use from multi-k databases (see `build --k-list`). If not provided, uses k from the first database."
+    )]
+    pub kmer_size: Option<u8>,
+
+    #[clap(
+        long,
+        conflicts_with_all = ["kmer_size", "dump_input_kmers", "groups", "krona", "coverage_track", "depth_track", "extract_reads", "per_read_normalize", "per_input_record"],
+        help = "Accept databases of different k instead of failing: the input's k-mers are counted at every database k in the same pass, and each database is classified against the profile of its own k. total_unique_kmers_in_input and input_reads_shorter_than_k are those of the first database's k; total_unique_kmers_in_input_by_k reports every k"
+    )]
+    pub allow_mixed_k: bool,
+
+    #[clap(
+        long,
+        default_value_t = 1,
+        help = "Minimum frequency for a k-mer in the input to be considered for depth calculation"
+    )]
+    pub min_kmer_frequency: usize,
+
+    #[clap(
+        long,
+        default_value_t = 0.0,
+        help = "Minimum reference breadth of coverage to include a reference in the output"
+    )]
+    pub min_coverage: f64,
+
+    #[clap(
+        long,
+        value_enum,
+        default_value_t = BreadthDenominator::ReferenceKmers,
+        help = "What breadth of coverage divides the matched k-mers by: the reference's unique k-mers, or the k-mer windows of its sequence length (length - k + 1 per record), as stored by build. References without stored lengths (e.g. built before lengths were stored, from a count table, or read from a sorted index) fall back to their unique k-mers"
+    )]
+    pub breadth_denominator: BreadthDenominator,
+
+    #[clap(
+        long,
+        default_value_t = 0,
+        help = "Minimum number of distinct input k-mers hitting a reference to include it in the output (applied together with --min-coverage)"
+    )]
+    pub min_reference_hits: usize,
+
+    #[clap(
+        long,
+        help = "Optional: Output file path for a summary table of the classification results, delimited by --delimiter. Supports .gz, .xz, .zst, .lz4 compression based on extension."
+    )]
+    pub output_tsv: Option<PathBuf>,
+
+    #[clap(
+        long,
+        help = "Optional: Output file path for a table of every reference, ignoring --min-coverage and --min-reference-hits, with an added PassedFilter column, delimited by --delimiter. Supports .gz, .xz, .zst, .lz4 compression based on extension."
+    )]
+    pub full_tsv: Option<PathBuf>,
+
+    #[clap(
+        long,
+        help = "Optional: Append the results to this SQLite database, creating it and its tables if absent: one `classifications` row for the run and one `references` row per reported reference (with --full-tsv, filtered-out references too, with passed_filter = 0)"
+    )]
+    pub sqlite: Option<PathBuf>,
+
+    #[clap(
+        long,
+        value_enum,
+        default_value_t = TableDelimiter::Tab,
+        help = "Column delimiter of --output-tsv and --full-tsv (comma writes CSV)"
+    )]
+    pub delimiter: TableDelimiter,
+
+    #[clap(
+        long,
+        help = "Also report depth and breadth of coverage with each k-mer weighted by 1 / (number of references in the database containing it), so k-mers shared across many references count less"
+    )]
+    pub weight_by_uniqueness: bool,
+
+    #[clap(
+        long,
+        help = "Also report each reference's coverage_uniformity, 1 minus the Gini coefficient of its coverage: of the per-base depth along references with stored k-mer positions (build --per-record), else of the input depths of its matched k-mers. 1 is perfectly even; values near 0 mean the depth piles up on a few bases or k-mers"
+    )]
+    pub coverage_uniformity: bool,
+
+    #[clap(
+        long,
+        conflicts_with = "weight_by_uniqueness",
+        help = "Treat the databases as sorted k-mer indexes (see `build --sorted-index`) and memory-map them instead of loading them into RAM"
+    )]
+    pub mmap: bool,
+
+    #[clap(
+        long = "only-reference",
+        conflicts_with = "mmap",
+        help = "Only use this database reference, dropping all others before computing. Repeatable."
+    )]
+    pub only_reference: Vec<String>,
+
+    #[clap(
+        long = "exclude-reference",
+        conflicts_with = "mmap",
+        help = "Ignore this database reference (e.g. a spike-in) before computing. Repeatable."
+    )]
+    pub exclude_reference: Vec<String>,
+
+    #[clap(
+        long,
+        help = "Optional: Write the input k-mer counts remaining after --min-kmer-frequency as a binary count table. Supports .gz, .xz, .zst, .lz4 compression based on extension."
+    )]
+    pub dump_input_kmers: Option<PathBuf>,
+
+    #[clap(
+        long,
+        help = "Count the input's records in a quick first pass so the progress bar shows a percentage and ETA. Skipped for inputs that cannot be read twice, such as pipes."
+    )]
+    pub prescan: bool,
+
+    #[clap(
+        long,
+        help = "Fail instead of warning when the database's k-mer encoding cannot be proven compatible with the canonical k-mers used for lookup"
+    )]
+    pub strict: bool,
+
+    #[clap(
+        long,
+        default_value_t = 0,
+        help = "Skip reads shorter than this many bases"
+    )]
+    pub min_read_length: usize,
+
+    #[clap(
+        long,
+        help = "Skip FASTQ reads whose mean Phred quality is below this value (no effect on FASTA input)"
+    )]
+    pub min_mean_qual: Option<f64>,
+
+    #[clap(
+        long,
+        default_value_t = 0,
+        help = "Trim this many bases (e.g. a fixed-length adapter) off the 5' end of each read before k-mer extraction"
+    )]
+    pub trim_front: usize,
+
+    #[clap(
+        long,
+        default_value_t = 0,
+        help = "Trim this many bases off the 3' end of each read before k-mer extraction. Reads shorter than --trim-front plus --trim-back are skipped."
+    )]
+    pub trim_back: usize,
+
+    #[clap(
+        long,
+        value_name = "BASE",
+        help = "Trim a trailing run of this base (A, C, G or T), e.g. G for the poly-G tails of two-color Illumina chemistry, off each record before k-mer extraction when the run is at least --polyx-min-run long"
+    )]
+    pub trim_polyx: Option<char>,
+
+    #[clap(
+        long,
+        default_value_t = 10,
+        requires = "trim_polyx",
+        help = "Shortest trailing homopolymer run that --trim-polyx trims"
+    )]
+    pub polyx_min_run: usize,
+
+    #[clap(
+        long,
+        help = "Process reads even when most of their characters are not A/C/G/T/U/N; by default such input is rejected as protein or non-DNA"
+    )]
+    pub allow_non_dna: bool,
+
+    #[clap(
+        long,
+        help = "Report in the JSON output how many k-mer windows were skipped because they contained N or other non-ACGT characters"
+    )]
+    pub report_invalid: bool,
+
+    #[clap(
+        long,
+        help = "Optional: TSV mapping reference names to groups (e.g. genus), one `reference<TAB>group` line per reference. Adds a `groups` array to the JSON output rolling up coverage per group."
+    )]
+    pub groups: Option<PathBuf>,
+
+    #[clap(
+        long,
+        help = "Optional: Write a Krona text input (count<TAB>level1<TAB>level2...) of each reported reference's matched input k-mers, under its --groups group (split into levels at ';'). Supports .gz, .xz, .zst, .lz4 compression based on extension."
+    )]
+    pub krona: Option<PathBuf>,
+
+    #[clap(
+        long,
+        value_name = "REF:PATH",
+        conflicts_with = "mmap",
+        help = "Write a bedGraph of how many distinct input k-mers cover each base of reference REF to PATH. REF must have been built with `build --per-record`. Repeatable."
+    )]
+    pub coverage_track: Vec<String>,
+
+    #[clap(
+        long,
+        value_name = "REF:PATH",
+        conflicts_with = "mmap",
+        help = "Write a bedGraph of the k-mer depth along reference REF to PATH: at each base, the input count (after --min-kmer-frequency) of the reference k-mer starting there, an alignment-free coverage profile. REF must have been built with `build --per-record`. Repeatable."
+    )]
+    pub depth_track: Vec<String>,
+
+    #[clap(
+        long,
+        value_name = "REF:PATH",
+        conflicts_with = "mmap",
+        help = "Write every input read with at least one k-mer in reference REF to PATH, unchanged (FASTQ reads keep their qualities), e.g. for targeted reassembly. Supports .gz, .xz, .zst, .lz4 compression based on extension. Repeatable."
+    )]
+    pub extract_reads: Vec<String>,
+
+    #[clap(
+        long,
+        help = "Also report mean_per_read_coverage per reference: the fraction of each read's k-mers found in the reference, averaged over reads, so long reads do not outweigh short ones as they do in the pooled counts. Meant for long-read input."
+    )]
+    pub per_read_normalize: bool,
+
+    #[clap(
+        long,
+        help = "Also classify each input record on its own, e.g. each contig of an assembly, and add a `records` array to the JSON output with every record's per-database results. Keeps the k-mer counts of every record, so it is meant for assemblies rather than read sets."
+    )]
+    pub per_input_record: bool,
 }
 
-#[derive(Subcommand, Debug)]
-pub enum Commands {
-    /// Count k-mers in FASTA/FASTQ files
-    Count(CountArgs),
-    /// Build a unique k-mer database from genome assemblies
-    Build(BuildArgs),
-    /// Compare two k-mer databases
-    Compare(CompareArgs),
-    /// Query short reads against a k-mer database
-    Query(QueryArgs),
-    /// Classify sequences against k-mer databases and report coverage statistics
-    Classify(ClassifyArgs),
+pub fn parse_cli() -> Cli {
+    Cli::parse()
 }
 
 #[derive(Parser, Debug)]
-pub struct CountArgs {
-    #[clap(short, long, required = true, help = "The length of the k-mer")]
-    pub kmer_size: u8,
-
-    #[clap(short, long, required = true, num_args = 1.., help = "One or more input FASTA/FASTQ files. Supports .gz, .xz, .zst compression.")]
-    pub input_files: Vec<PathBuf>,
+pub struct DumpArgs {
+    #[clap(
+        short = 'd',
+        long = "database",
+        required = true,
+        help = "K-mer database to export. Supports .gz, .xz, .zst, .lz4 compression."
+    )]
+    pub database_file: PathBuf,
 
     #[clap(
         short,
         long,
         required = true,
-        help = "Output file for k-mer counts (kmer<TAB>count). Supports .gz, .xz, .zst compression based on extension."
+        help = "Output file for the exported database. Supports .gz, .xz, .zst, .lz4 compression based on extension."
     )]
     pub output_file: PathBuf,
 
     #[clap(
-        short = 'm',
         long,
-        default_value_t = 1,
-        help = "Minimum count to report a k-mer"
+        value_enum,
+        default_value_t = DumpFormat::Tsv,
+        help = "Output format"
     )]
-    pub min_count: usize,
+    pub format: DumpFormat,
+
+    #[clap(
+        long,
+        default_value_t = 0.01,
+        help = "Target false-positive rate of the filter written by --format bloom, between 0 and 1"
+    )]
+    pub fp_rate: f64,
+
+    #[clap(
+        long,
+        conflicts_with = "num",
+        help = "Scaled MinHash for --format sourmash: keep the hashes at most 2^64 / N, about one in N k-mers [default: 1000, unless --num is given]"
+    )]
+    pub scaled: Option<u64>,
+
+    #[clap(
+        long,
+        help = "Bottom-N MinHash for --format sourmash: keep the N smallest hashes instead of a --scaled sketch"
+    )]
+    pub num: Option<usize>,
+
+    #[clap(
+        long,
+        default_value_t = 42,
+        help = "MurmurHash3 seed of the --format sourmash sketch; sourmash's default is 42. A --hashed database must have been hashed with this seed"
+    )]
+    pub seed: u64,
+}
+
+/// Which databases a read must meet `query --min-hits` against to be reported.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DatabaseCombine {
+    /// At least one database
+    Any,
+    /// Every database
+    All,
+}
+
+/// What `classify` divides a reference's matched k-mers by for its breadth of coverage.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BreadthDenominator {
+    /// The reference's unique k-mers
+    ReferenceKmers,
+    /// The k-mer windows of the reference's sequence records, from their stored lengths
+    ReferenceLength,
+}
+
+/// Column delimiter of the `classify` summary tables.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TableDelimiter {
+    /// Tab-separated values
+    Tab,
+    /// Comma-separated values
+    Comma,
+}
+
+/// How `query --canonical-both` tallies hits of k-mers equal to their own reverse complement.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PalindromeCounting {
+    /// Count the hit on both strands
+    Both,
+    /// Count the hit on neither strand
+    Neither,
 }
 
 #[derive(Parser, Debug)]
-pub struct BuildArgs {
-    #[clap(short, long, required = true, help = "The length of the k-mer")]
-    pub kmer_size: u8,
+pub struct MarkersArgs {
+    #[clap(
+        long = "target",
+        required = true,
+        num_args = 1..,
+        help = "Target genome files (FASTA) to extract markers from; positions are reported within their records. Supports .gz, .xz, .zst, .lz4 compression."
+    )]
+    pub target_files: Vec<PathBuf>,
 
-    #[clap(short = 'g', long = "genomes", required = true, num_args = 1.., help = "One or more input genome assembly files (FASTA). Supports .gz, .xz, .zst compression.")]
-    pub genome_files: Vec<PathBuf>,
+    #[clap(
+        long = "background",
+        required = true,
+        num_args = 1..,
+        help = "Background k-mer database(s); target k-mers found in any of them are not specific. Repeatable."
+    )]
+    pub background_files: Vec<PathBuf>,
 
     #[clap(
         short,
         long,
         required = true,
-        help = "Output path for the binary k-mer database. Supports .gz, .xz, .zst compression based on extension."
+        help = "Output TSV of marker regions (reference, sequence_id, start, end, sequence). Supports .gz, .xz, .zst, .lz4 compression based on extension."
     )]
     pub output_file: PathBuf,
+
+    #[clap(
+        long,
+        default_value_t = 1,
+        help = "Only report regions made of at least this many consecutive target-specific k-mers"
+    )]
+    pub min_run: usize,
 }
 
 #[derive(Parser, Debug)]
-pub struct CompareArgs {
-    #[clap(long, required = true, help = "First k-mer database file. Supports .gz, .xz, .zst compression.")]
-    pub db1: PathBuf,
-
-    #[clap(long, required = true, help = "Second k-mer database file. Supports .gz, .xz, .zst compression.")]
-    pub db2: PathBuf,
+pub struct NeighborsArgs {
+    #[clap(
+        short = 'd',
+        long = "database",
+        required = true,
+        help = "K-mer database to look neighbors up in. Supports .gz, .xz, .zst, .lz4 compression."
+    )]
+    pub database_file: PathBuf,
 
     #[clap(
-        short,
         long,
         required = true,
-        help = "Output file for comparison stats (JSON format). Supports .gz, .xz, .zst compression based on extension."
+        help = "K-mer (A/C/G/T, same length as the database's k) whose neighbors to list"
     )]
-    pub output_file: PathBuf,
+    pub kmer: String,
 }
 
 #[derive(Parser, Debug)]
-pub struct QueryArgs {
+pub struct SearchArgs {
+    #[clap(
+        long = "sketch",
+        required_unless_present = "query_file",
+        help = "sourmash signature (JSON) with a DNA MinHash sketch of the sample; the sketch whose k matches the database is used. Supports .gz, .xz, .zst, .lz4 compression."
+    )]
+    pub sketch_file: Option<PathBuf>,
+
+    #[clap(
+        short = 'q',
+        long = "query",
+        conflicts_with = "sketch_file",
+        help = "FASTA/FASTQ file whose k-mers are counted on the fly for exact per-reference containment in both directions, instead of a sketch. Supports .gz, .xz, .zst, .lz4 compression."
+    )]
+    pub query_file: Option<PathBuf>,
+
     #[clap(
         short = 'd',
         long = "database",
         required = true,
-        help = "K-mer database to query against. Supports .gz, .xz, .zst compression."
+        help = "K-mer database to screen the sketch or query against. Supports .gz, .xz, .zst, .lz4 compression."
     )]
     pub database_file: PathBuf,
 
     #[clap(
-        short = 'r',
-        long = "reads",
+        short,
+        long,
         required = true,
-        help = "Short-read file (FASTQ). Supports .gz, .xz, .zst compression."
+        help = "Output file for the containment estimate or per-reference containment (JSON format). Supports .gz, .xz, .zst, .lz4 compression based on extension."
     )]
-    pub reads_file: PathBuf,
+    pub output_file: PathBuf,
+
+    #[clap(
+        long,
+        help = "Fail instead of warning when the database's k-mers are not stored in canonical form, as sketch hashes and query k-mers are"
+    )]
+    pub strict: bool,
+}
+
+#[derive(Parser, Debug)]
+pub struct ValidateArgs {
+    #[clap(
+        short,
+        long,
+        required = true,
+        help = "FASTA/FASTQ file to check. Supports .gz, .xz, .zst, .lz4 compression."
+    )]
+    pub input_file: PathBuf,
+}
+
+#[derive(Parser, Debug)]
+pub struct SumArgs {
+    #[clap(
+        short,
+        long,
+        required = true,
+        num_args = 1..,
+        help = "Count tables to sum: `count` TSV output or binary count tables (detected from the content). Supports .gz, .xz, .zst, .lz4 compression."
+    )]
+    pub input_files: Vec<PathBuf>,
 
     #[clap(
         short,
         long,
         required = true,
-        help = "Output file for the IDs of matching reads. Supports .gz, .xz, .zst compression based on extension."
+        help = "Output file for the summed counts. Supports .gz, .xz, .zst, .lz4 compression based on extension."
     )]
     pub output_file: PathBuf,
 
     #[clap(
-        short = 'c',
-        long = "min-hits",
+        short = 'm',
+        long,
         default_value_t = 1,
-        help = "Minimum number of k-mer hits to report a read"
+        help = "Minimum summed count to report a k-mer"
     )]
-    pub min_hits: usize,
+    pub min_count: usize,
+
+    #[clap(
+        long,
+        help = "Write a binary count table (bincode, with k in the header) instead of TSV"
+    )]
+    pub binary: bool,
+}
+
+/// Export formats for `dump`.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DumpFormat {
+    /// One row per (reference, k-mer): reference, kmer_u64, kmer_seq
+    Tsv,
+    /// The rows of `tsv` as a Snappy-compressed Parquet file, with a string, uint64 and
+    /// (nullable) string column
+    Parquet,
+    /// Little-endian binary: a header (magic, k, record count), then one (u64 k-mer, u32 number
+    /// of references containing it) record per distinct k-mer, sorted by k-mer
+    U64,
+    /// Bloom filter of the database's unified k-mers, sized for --fp-rate, for `query --bloom-file`
+    Bloom,
+    /// sourmash signature (JSON) with a MinHash sketch of the database's unified k-mers, hashed
+    /// as sourmash does (MurmurHash3 x64 of canonical k-mers), sized by --scaled or --num
+    Sourmash,
 }
 
 #[derive(Parser, Debug)]
-pub struct ClassifyArgs {
+pub struct ConvertArgs {
     #[clap(
         short,
         long,
         required = true,
-        help = "Input genome (FASTA) or reads (FASTQ) file. Supports .gz, .xz, .zst compression."
+        help = "Database to convert: a `build` database or a sorted k-mer index. Supports .gz, .xz, .zst, .lz4 compression."
     )]
     pub input_file: PathBuf,
 
+    #[clap(
+        short,
+        long,
+        required = true,
+        help = "Output path for the converted database. Supports .gz, .xz, .zst, .lz4 compression based on extension (except --to sorted, which must stay uncompressed to be memory-mapped)."
+    )]
+    pub output_file: PathBuf,
+
+    #[clap(long, value_enum, help = "Representation to write")]
+    pub to: DbRepresentation,
+
+    #[clap(
+        long,
+        default_value_t = 42,
+        help = "Seed for --to hashed k-mer hashing"
+    )]
+    pub hash_seed: u64,
+}
+
+#[derive(Parser, Debug)]
+pub struct SplitArgs {
     #[clap(
         short = 'd',
-        long = "databases",
+        long = "database",
         required = true,
-        num_args = 1..,
-        help = "One or more k-mer database files (.db). Supports .gz, .xz, .zst compression."
+        help = "K-mer database to split: a `build` database or a sorted k-mer index. Supports .gz, .xz, .zst, .lz4 compression."
     )]
-    pub database_files: Vec<PathBuf>,
+    pub database_file: PathBuf,
 
     #[clap(
-        short,
         long,
         required = true,
-        help = "Output file for classification results (JSON format). Supports .gz, .xz, .zst compression based on extension."
+        help = "Directory for the per-reference databases, created if missing. Each is named after its reference, with characters other than letters and digits replaced by _, e.g. genome_fa.db."
     )]
-    pub output_file: PathBuf,
+    pub output_dir: PathBuf,
+
+    #[clap(long, value_enum, help = "Compress each per-reference database, adding the matching extension (e.g. .db.gz)")]
+    pub compress: Option<OutputCompression>,
+}
+
+#[derive(Parser, Debug)]
+pub struct InfoArgs {
+    #[clap(
+        short = 'd',
+        long = "database",
+        required = true,
+        help = "K-mer database to describe: a `build` database (single or multi-k) or a sorted k-mer index. Supports .gz, .xz, .zst, .lz4 compression."
+    )]
+    pub database_file: PathBuf,
+
+    #[clap(
+        long,
+        help = "Only print the database's format, format version, magic bytes, k and reference (or multi-k table) count, read from its first bytes without loading it, to check quickly whether it can be loaded"
+    )]
+    pub format_version: bool,
+}
+
+#[derive(Parser, Debug)]
+pub struct RevcompArgs {
+    #[clap(
+        short,
+        long,
+        default_value = "-",
+        help = "FASTA/FASTQ file to reverse-complement, or - for stdin. Supports .gz, .xz, .zst, .lz4 compression (detected from the content for stdin)."
+    )]
+    pub input_file: PathBuf,
+
+    #[clap(
+        short,
+        long,
+        help = "Output file, in the input's format; stdout if not given. Supports .gz, .xz, .zst, .lz4 compression based on extension."
+    )]
+    pub output_file: Option<PathBuf>,
+}
+
+#[derive(Parser, Debug)]
+pub struct AniArgs {
+    #[clap(
+        long,
+        required_unless_present = "fasta1",
+        conflicts_with = "fasta1",
+        help = "First genome as a k-mer database; its k-mers are pooled over all references. Supports .gz, .xz, .zst, .lz4 compression."
+    )]
+    pub db1: Option<PathBuf>,
+
+    #[clap(
+        long,
+        required_unless_present = "fasta2",
+        conflicts_with = "fasta2",
+        help = "Second genome as a k-mer database. Supports .gz, .xz, .zst, .lz4 compression."
+    )]
+    pub db2: Option<PathBuf>,
+
+    #[clap(
+        long,
+        help = "First genome as a FASTA/FASTQ file, whose k-mers are extracted with -k (or the k and settings of --db2). Supports .gz, .xz, .zst, .lz4 compression."
+    )]
+    pub fasta1: Option<PathBuf>,
+
+    #[clap(
+        long,
+        help = "Second genome as a FASTA/FASTQ file, whose k-mers are extracted with -k (or the k and settings of --db1). Supports .gz, .xz, .zst, .lz4 compression."
+    )]
+    pub fasta2: Option<PathBuf>,
 
     #[clap(
         short,
         long,
-        help = "Optional: K-mer size to validate against databases. If not provided, uses k from the first database."
+        help = "K-mer size for --fasta1/--fasta2; required when neither genome is a database, and must match the database's k otherwise"
     )]
     pub kmer_size: Option<u8>,
 
     #[clap(
+        short,
         long,
-        default_value_t = 1,
-        help = "Minimum frequency for a k-mer in the input to be considered for depth calculation"
+        help = "Output file for the ANI estimates (JSON format); stdout if not given. Supports .gz, .xz, .zst, .lz4 compression based on extension."
     )]
-    pub min_kmer_frequency: usize,
+    pub output_file: Option<PathBuf>,
+}
+
+/// Compression formats for files a command names itself, such as `split` outputs.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputCompression {
+    Gz,
+    Xz,
+    Zst,
+}
+
+#[derive(Parser, Debug)]
+pub struct CatArgs {
+    #[clap(
+        help = "File to decompress, by extension: .gz, .bgz, .bz2, .xz, .zst, or uncompressed. The reads of .bam and .cram files are written as FASTQ (or FASTA)."
+    )]
+    pub input_file: PathBuf,
 
     #[clap(
+        short,
         long,
-        default_value_t = 0.0,
-        help = "Minimum reference breadth of coverage to include a reference in the output"
+        help = "Output file, compressed according to its extension (.gz, .bgz, .bz2, .xz, .zst); uncompressed to stdout if not given."
     )]
-    pub min_coverage: f64,
+    pub output_file: Option<PathBuf>,
+}
+
+#[derive(Parser, Debug)]
+pub struct BenchArgs {
+    #[clap(
+        long,
+        default_value_t = 1_000_000,
+        help = "Length in bases of the generated genome"
+    )]
+    pub genome_length: usize,
+
+    #[clap(short, long, default_value_t = 21, help = "The length of the k-mer")]
+    pub kmer_size: u8,
 
     #[clap(
         long,
-        help = "Optional: Output file path for a TSV summary of the classification results. Supports .gz, .xz, .zst compression based on extension."
+        default_value_t = 42,
+        help = "Seed of the generated genome, so runs time the same input"
     )]
-    pub output_tsv: Option<PathBuf>,
+    pub seed: u64,
+
+    #[clap(
+        short,
+        long,
+        help = "Write the timings JSON to this file instead of stdout"
+    )]
+    pub output_file: Option<PathBuf>,
 }
 
-pub fn parse_cli() -> Cli {
-    Cli::parse()
+// `doctor` has no options of its own; the global --threads is reported as resolved.
+#[derive(Parser, Debug)]
+pub struct DoctorArgs {}
+
+/// On-disk database representations that `convert` can write.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DbRepresentation {
+    /// A `build` database storing 2-bit k-mer encodings
+    Hashset,
+    /// A sorted k-mer index for `query --mmap` and `classify --mmap` (see `build --sorted-index`)
+    Sorted,
+    /// A `build` database storing MurmurHash3 hashes of the k-mers (see `build --hashed`)
+    Hashed,
 }
@@ -0,0 +1,169 @@
+use anyhow::{Context, Result};
+use log::info;
+use serde::Serialize;
+use std::{io::Write, path::Path};
+
+use crate::{
+    cli::AniArgs,
+    commands::{build::build_kmer_db_from_file, compare::jaccard_stats},
+    db_types::KmerDbV2,
+    errors::OrionKmerError,
+    utils::{check_canonical_policy, finalize_writer, get_output_writer, load_kmer_db_v2, round_float},
+};
+
+#[derive(Serialize, Debug)]
+struct AniOutput {
+    genome1_path: String,
+    genome2_path: String,
+    kmer_size: u8,
+    genome1_unique_kmers: usize,
+    genome2_unique_kmers: usize,
+    shared_kmers: usize,
+    // Share of each genome's k-mers found in the other
+    containment_1_in_2: f64,
+    containment_2_in_1: f64,
+    ani_1_in_2: f64,
+    ani_2_in_1: f64,
+    ani_average: f64,
+}
+
+/// Converts the containment of one genome's k-mers in another into an ANI estimate with
+/// `ANI = 1 + ln(containment) / k`: a k-mer survives only if all k of its bases are identical,
+/// so containment is about ANI^k. Clamped to 0 when nothing is shared or the estimate is negative.
+pub fn ani_from_containment(containment: f64, k: u8) -> f64 {
+    if containment <= 0.0 {
+        return 0.0;
+    }
+    (1.0 + containment.ln() / k as f64).max(0.0)
+}
+
+// One genome: its path and k-mers, from --dbN or --fastaN.
+struct Genome<'a> {
+    path: &'a Path,
+    kmer_db: KmerDbV2,
+}
+
+fn check_user_kmer_size(args: &AniArgs, genome: &Genome) -> Result<()> {
+    if let Some(user_k) = args.kmer_size
+        && user_k != genome.kmer_db.k
+    {
+        return Err(OrionKmerError::KmerSizeMismatchValidation(user_k, genome.kmer_db.k, genome.path.to_path_buf()).into());
+    }
+    Ok(())
+}
+
+// Loads the genome given as a database, if it is one.
+fn load_genome_db(db_path: Option<&Path>) -> Result<Option<Genome<'_>>> {
+    db_path
+        .map(|path| Ok(Genome { path, kmer_db: load_kmer_db_v2(path)? }))
+        .transpose()
+}
+
+// Extracts the k-mers of a genome given as a FASTA file, stored with the k and settings of `settings`.
+fn fasta_genome<'a>(fasta_path: Option<&'a Path>, settings: &KmerDbV2) -> Result<Genome<'a>> {
+    let path = fasta_path
+        .ok_or_else(|| OrionKmerError::Generic("Each genome needs a database or a FASTA file".to_string()))?;
+    info!("Extracting k-mers of {:?} with k={}", path, settings.k);
+    let kmer_db = build_kmer_db_from_file(
+        &path.to_path_buf(),
+        settings.k,
+        settings.hashing,
+        settings.canonical,
+        settings.canonical_policy,
+    )?;
+    Ok(Genome { path, kmer_db })
+}
+
+pub fn run_ani(args: AniArgs) -> Result<()> {
+    info!("Starting ani command with args: {:?}", args);
+
+    let db1 = load_genome_db(args.db1.as_deref())?;
+    let db2 = load_genome_db(args.db2.as_deref())?;
+
+    // FASTA genomes are extracted like the database they are compared with, or with -k
+    let settings = match db1.as_ref().or(db2.as_ref()) {
+        Some(genome) => {
+            let mut settings = KmerDbV2::new(genome.kmer_db.k);
+            settings.hashing = genome.kmer_db.hashing;
+            settings.canonical = genome.kmer_db.canonical;
+            settings.canonical_policy = genome.kmer_db.canonical_policy;
+            settings
+        }
+        None => {
+            let k = args.kmer_size.ok_or_else(|| {
+                OrionKmerError::Generic("-k is required when neither genome is a database (--db1/--db2)".to_string())
+            })?;
+            if k == 0 || k > 32 {
+                return Err(OrionKmerError::InvalidKmerSize(k).into());
+            }
+            KmerDbV2::new(k)
+        }
+    };
+    let genome1 = match db1 {
+        Some(genome) => genome,
+        None => fasta_genome(args.fasta1.as_deref(), &settings)?,
+    };
+    let genome2 = match db2 {
+        Some(genome) => genome,
+        None => fasta_genome(args.fasta2.as_deref(), &settings)?,
+    };
+    check_user_kmer_size(&args, &genome1)?;
+    check_user_kmer_size(&args, &genome2)?;
+
+    // Two databases must store their k-mers the same way for shared k-mers to be found
+    if genome1.kmer_db.k != genome2.kmer_db.k {
+        return Err(OrionKmerError::KmerSizeMismatch(genome1.kmer_db.k, genome2.kmer_db.k).into());
+    }
+    if genome1.kmer_db.hashing != genome2.kmer_db.hashing {
+        return Err(OrionKmerError::HashingMismatch(
+            genome1.kmer_db.hashing,
+            genome2.kmer_db.hashing,
+            genome2.path.to_path_buf(),
+        )
+        .into());
+    }
+    if genome1.kmer_db.canonical != genome2.kmer_db.canonical {
+        return Err(OrionKmerError::CanonicalMismatch(genome1.path.to_path_buf(), genome2.path.to_path_buf()).into());
+    }
+    check_canonical_policy(&genome1.kmer_db, &genome2.kmer_db, genome2.path)?;
+
+    let stats = jaccard_stats(
+        &genome1.kmer_db.get_all_kmers_unified(),
+        &genome2.kmer_db.get_all_kmers_unified(),
+    );
+    let k = settings.k;
+    let ani_1_in_2 = ani_from_containment(stats.containment_a_in_b, k);
+    let ani_2_in_1 = ani_from_containment(stats.containment_b_in_a, k);
+    let output_data = AniOutput {
+        genome1_path: genome1.path.to_string_lossy().into_owned(),
+        genome2_path: genome2.path.to_string_lossy().into_owned(),
+        kmer_size: k,
+        genome1_unique_kmers: stats.a_size,
+        genome2_unique_kmers: stats.b_size,
+        shared_kmers: stats.intersection_size,
+        containment_1_in_2: round_float(stats.containment_a_in_b),
+        containment_2_in_1: round_float(stats.containment_b_in_a),
+        ani_1_in_2: round_float(ani_1_in_2),
+        ani_2_in_1: round_float(ani_2_in_1),
+        ani_average: round_float((ani_1_in_2 + ani_2_in_1) / 2.0),
+    };
+    info!("ANI results: {:?}", output_data);
+
+    match &args.output_file {
+        Some(output_path) => {
+            let mut writer = get_output_writer(output_path)
+                .with_context(|| format!("Failed to create output JSON file: {:?}", output_path))?;
+            serde_json::to_writer_pretty(&mut writer, &output_data)
+                .with_context(|| format!("Failed to write ANI JSON to {:?}", output_path))?;
+            writeln!(writer)?;
+            finalize_writer(writer).context("Failed to finish ANI JSON writer")?;
+            info!("Successfully wrote ANI estimates to {:?}", output_path);
+        }
+        None => {
+            let mut stdout = std::io::stdout().lock();
+            serde_json::to_writer_pretty(&mut stdout, &output_data).context("Failed to write ANI JSON to stdout")?;
+            writeln!(stdout)?;
+        }
+    }
+    Ok(())
+}
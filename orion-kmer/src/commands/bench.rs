@@ -0,0 +1,127 @@
+use anyhow::{Context, Result};
+use clap::Parser;
+use log::info;
+use serde::Serialize;
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use crate::{
+    cli::{BenchArgs, BuildArgs, CountArgs},
+    commands::{build::run_build, count::run_count},
+    errors::OrionKmerError,
+};
+
+// Timings of one `bench` run. Throughputs are in bases of the generated genome per second.
+#[derive(Serialize, Debug)]
+struct BenchReport {
+    genome_length: usize,
+    kmer_size: u8,
+    seed: u64,
+    build_seconds: f64,
+    build_bases_per_second: f64,
+    count_seconds: f64,
+    count_bases_per_second: f64,
+}
+
+/// Generates `length` pseudo-random bases (A/C/G/T) from `seed`. The same seed always gives
+/// the same sequence, so benchmark runs on different machines or commits time the same input.
+pub fn synthetic_genome(length: usize, seed: u64) -> Vec<u8> {
+    let mut state = seed ^ 0x9E37_79B9_7F4A_7C15;
+    (0..length)
+        .map(|_| {
+            // 64-bit LCG (Knuth's MMIX constants); the high bits are the best distributed
+            state = state
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1_442_695_040_888_963_407);
+            b"ACGT"[(state >> 62) as usize]
+        })
+        .collect()
+}
+
+/// Writes `genome` as a single-record FASTA file.
+pub fn write_synthetic_fasta(path: &Path, genome: &[u8]) -> Result<()> {
+    let mut file = fs::File::create(path)
+        .with_context(|| format!("Failed to create benchmark input: {:?}", path))?;
+    file.write_all(b">synthetic\n")?;
+    for line in genome.chunks(80) {
+        file.write_all(line)?;
+        file.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+// Runs `command` and returns its wall-clock time in seconds.
+fn time_command(command: impl FnOnce() -> Result<()>) -> Result<f64> {
+    let start = Instant::now();
+    command()?;
+    Ok(start.elapsed().as_secs_f64())
+}
+
+fn run_timings(args: &BenchArgs, work_dir: &Path) -> Result<BenchReport> {
+    let genome_path = work_dir.join("synthetic.fa");
+    write_synthetic_fasta(&genome_path, &synthetic_genome(args.genome_length, args.seed))?;
+    let k = args.kmer_size.to_string();
+    let genome_arg = genome_path.to_string_lossy().into_owned();
+
+    let db_path = work_dir.join("synthetic.db").to_string_lossy().into_owned();
+    let build_args = BuildArgs::try_parse_from(["build", "-k", &k, "-g", &genome_arg, "-o", &db_path])?;
+    let build_seconds = time_command(|| run_build(build_args))?;
+
+    let counts_path = work_dir.join("synthetic.counts").to_string_lossy().into_owned();
+    let count_args = CountArgs::try_parse_from(["count", "-k", &k, "-i", &genome_arg, "-o", &counts_path])?;
+    let count_seconds = time_command(|| run_count(count_args))?;
+
+    let bases = args.genome_length as f64;
+    Ok(BenchReport {
+        genome_length: args.genome_length,
+        kmer_size: args.kmer_size,
+        seed: args.seed,
+        build_seconds,
+        build_bases_per_second: bases / build_seconds,
+        count_seconds,
+        count_bases_per_second: bases / count_seconds,
+    })
+}
+
+pub fn run_bench(args: BenchArgs) -> Result<()> {
+    info!("Starting bench command with args: {:?}", args);
+    if args.kmer_size == 0 || args.kmer_size > 32 {
+        return Err(OrionKmerError::InvalidKmerSize(args.kmer_size).into());
+    }
+    if args.genome_length < args.kmer_size as usize {
+        return Err(OrionKmerError::Generic(format!(
+            "--genome-length must be at least the k-mer size {}, got {}",
+            args.kmer_size, args.genome_length
+        ))
+        .into());
+    }
+
+    // The generated genome, database and counts live in a scratch directory removed afterwards
+    let work_dir: PathBuf = std::env::temp_dir().join(format!("orion-kmer-bench-{}", std::process::id()));
+    fs::create_dir_all(&work_dir)
+        .with_context(|| format!("Failed to create benchmark directory: {:?}", work_dir))?;
+    let report = run_timings(&args, &work_dir);
+    fs::remove_dir_all(&work_dir)
+        .with_context(|| format!("Failed to remove benchmark directory: {:?}", work_dir))?;
+    let report = report?;
+    info!("Benchmark timings: {:?}", report);
+
+    match &args.output_file {
+        Some(output_path) => {
+            let output_file = fs::File::create(output_path)
+                .with_context(|| format!("Failed to create output JSON file: {:?}", output_path))?;
+            serde_json::to_writer_pretty(output_file, &report)
+                .with_context(|| format!("Failed to write benchmark JSON to {:?}", output_path))?;
+        }
+        None => {
+            let mut stdout = std::io::stdout().lock();
+            serde_json::to_writer_pretty(&mut stdout, &report).context("Failed to write benchmark JSON")?;
+            writeln!(stdout)?;
+        }
+    }
+    Ok(())
+}
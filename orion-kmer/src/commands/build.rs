@@ -1,31 +1,121 @@
 use anyhow::{Context, Result};
-use dashmap::DashSet; // Using DashSet for concurrent k-mer collection per file
-use log::{debug, info};
-use needletail::{parse_fastx_reader, Sequence}; // Corrected import order & added parse_fastx_reader
+use dashmap::{DashMap, DashSet}; // Using DashSet for concurrent k-mer collection per file
+use log::{debug, info, warn};
+use needletail::Sequence;
+use rayon::prelude::*;
 use std::{
-    collections::HashSet, // Keep HashSet for final storage in KmerDbV2
+    collections::{BTreeSet, HashMap, HashSet}, // Keep HashSet for final storage in KmerDbV2
     // fs::File, // No longer directly used for output
     // io::{BufWriter, Write}, // No longer directly used for output
-    path::PathBuf, // For getting filename
+    fs,
+    io::{Read, Write},
+    path::{Path, PathBuf}, // For getting filename
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
 use crate::{
-    cli::BuildArgs,
-    db_types::KmerDbV2, // Import the new database structure
+    cli::{BuildArgs, CanonicalPolicyKind, OnConflict, RefNameNormalization, RefNameStyle},
+    commands::sum::read_count_table,
+    db_types::{DbMetadata, HashFunction, KmerDbMultiK, KmerDbV2, KmerHashing, ReferenceNameNormalization, ReferencePositions, SequenceLengths}, // Import the new database structure
     errors::OrionKmerError,
-    kmer::{canonical_u64, seq_to_u64},
-    utils::{get_buffered_file_reader, get_output_writer, track_progress_and_resources}, // Import the wrapper function and I/O helpers
+    kmer::{ambiguous_base_count, canonical_u64_with_policy, expand_iupac_window, rolling_kmers, CanonicalPolicy},
+    kmer_index::write_sorted_kmer_index,
+    utils::{
+        for_each_tar_entry, finalize_writer, get_output_writer, get_sequence_file_reader, is_progress_record, is_tar_archive,
+        check_dna_record, load_kmer_db_v2, open_fastx_reader, report_base_counts, report_short_records, restart_record_limit, sanitize_reference_name, take_input_record, track_progress_and_resources,
+        write_kmer_db, write_multi_k_db, BaseCounts, PhaseTimer, SequenceFilter,
+    }, // Import the wrapper function and I/O helpers
 };
 // use indicatif::ProgressBar; // Required for the closure signature - actually not needed
 
-// This function processes sequences for a single file and populates a DashSet for that file.
+/// Records per batch sent from the parsing thread to the k-mer extraction workers (`--batch-size`).
+pub const DEFAULT_BATCH_SIZE: usize = 1000;
+// Batches queued between the parser and the workers; bounds memory when parsing outpaces extraction.
+const PIPELINE_BATCHES_IN_FLIGHT: usize = 4;
+
+// File extensions (after stripping any compression suffix) treated as sequence files inside archives.
+const SEQUENCE_FILE_EXTENSIONS: &[&str] = &["fa", "fasta", "fna", "ffn", "fas", "fq", "fastq"];
+
+// Returns true if an archive entry name looks like a FASTA/FASTQ file (optionally compressed).
+// Hidden entries such as macOS `._` resource forks are ignored.
+fn is_sequence_file_name(name: &str) -> bool {
+    let base_name = name.rsplit('/').next().unwrap_or(name).to_lowercase();
+    if base_name.starts_with('.') {
+        return false;
+    }
+    let stripped = [".gz", ".xz", ".zst", ".zstd", ".bz2", ".lz4"]
+        .iter()
+        .find_map(|ext| base_name.strip_suffix(ext))
+        .unwrap_or(&base_name);
+    stripped
+        .rsplit_once('.')
+        .is_some_and(|(_, ext)| SEQUENCE_FILE_EXTENSIONS.contains(&ext))
+}
+
+// Strips a compression suffix and then the sequence extension, e.g. `dir/genome.fa.gz` -> `dir/genome`.
+pub(crate) fn strip_sequence_extensions(name: &str) -> &str {
+    let uncompressed = [".gz", ".xz", ".zst", ".zstd", ".bz2", ".lz4"]
+        .iter()
+        .find_map(|ext| name.strip_suffix(ext))
+        .unwrap_or(name);
+    let file_name_start = uncompressed.rfind('/').map_or(0, |slash| slash + 1);
+    match uncompressed[file_name_start..].rfind('.') {
+        Some(dot) if dot > 0 => &uncompressed[..file_name_start + dot],
+        _ => uncompressed,
+    }
+}
+
+// Names the reference of an input file according to `style`.
+fn reference_name_for_file(file_path: &Path, style: RefNameStyle) -> Result<String> {
+    let file_name = file_path.file_name().map_or_else(
+        || file_path.to_string_lossy().into_owned(),
+        |os_str| os_str.to_string_lossy().into_owned(),
+    );
+    Ok(match style {
+        RefNameStyle::Filename => file_name,
+        RefNameStyle::Path => file_path.to_string_lossy().into_owned(),
+        RefNameStyle::Stem => strip_sequence_extensions(&file_name).to_string(),
+        RefNameStyle::Header => first_record_header(file_path)?.unwrap_or_else(|| {
+            warn!("{:?} has no records to take a header from; naming it '{}'", file_path, file_name);
+            file_name
+        }),
+    })
+}
+
+// Returns the header (ID and description) of the first record in `file_path`, if any.
+fn first_record_header(file_path: &Path) -> Result<Option<String>> {
+    let input_buf_reader = get_sequence_file_reader(file_path)?;
+    let mut reader = match open_fastx_reader(input_buf_reader, file_path) {
+        Ok(reader) => reader,
+        // Empty files have no header; other parse errors surface when the file is processed
+        Err(_) => return Ok(None),
+    };
+    match reader.next() {
+        Some(record) => {
+            let record = record
+                .with_context(|| format!("Error reading first record from {:?}", file_path))?;
+            Ok(Some(String::from_utf8_lossy(record.id()).trim().to_string()))
+        }
+        None => Ok(None),
+    }
+}
+
+// This function processes sequences for a single file and populates `file_kmer_set` for that file.
 // It now accepts a ProgressBar to update progress within the file processing.
 fn process_sequences_for_file(
     file_path: &PathBuf,
     k: u8,
-    file_kmer_set: &DashSet<u64>,
+    extraction: KmerExtraction,
+    sequence_filter: &SequenceFilter,
+    file_kmer_set: &FileKmers,
+    batch_size: usize,
     // pb: &ProgressBar, // Optional: if we want fine-grained progress per file
-) -> Result<()> {
+) -> Result<SourceSummary> {
     let path_str = file_path.to_string_lossy();
     info!("Processing genome file: {}", path_str);
 
@@ -34,86 +124,1033 @@ fn process_sequences_for_file(
     // let total_records = ...;
     // pb.set_length(total_records); // If using a per-file progress bar
 
-    // Use get_buffered_file_reader, needletail will handle decompression
-    let input_buf_reader = get_buffered_file_reader(file_path)
-        .with_context(|| format!("Failed to get buffered file reader for file: {}", path_str))?;
+    // needletail decompresses the input unless --decompress-threads moves it to a background thread
+    let input_buf_reader = get_sequence_file_reader(file_path)?;
 
-    // Pass the BufRead to parse_fastx_reader instead of a path to parse_fastx_file
-    let mut reader = parse_fastx_reader(input_buf_reader)
-        .with_context(|| format!("Failed to parse FASTA/Q content from: {}", path_str))?;
+    process_sequences_from_reader(
+        input_buf_reader,
+        &path_str,
+        k,
+        extraction,
+        sequence_filter,
+        file_kmer_set,
+        batch_size,
+    )
+}
 
-    let mut record_count = 0;
-    while let Some(record) = reader.next() {
-        let record = record.with_context(|| format!("Error reading record from {}", path_str))?;
-        let norm_seq = record.normalize(false); // Ensure uppercase, no N conversion yet
-
-        if norm_seq.len() >= k as usize {
-            for window in norm_seq.windows(k as usize) {
-                // seq_to_u64 handles 'N' by returning None
-                if let Some(kmer_val) = seq_to_u64(window, k) {
-                    let canonical_kmer = canonical_u64(kmer_val, k);
-                    file_kmer_set.insert(canonical_kmer);
-                }
+// How k-mers are taken from each sequence.
+#[derive(Clone, Copy)]
+struct KmerExtraction {
+    // None skips windows with ambiguous bases; Some(n) expands windows with at most n IUPAC codes
+    iupac_max_ambiguous: Option<usize>,
+    // Store the canonical form of each k-mer, or the k-mer as read with --no-canonical
+    canonical: bool,
+    // Which strand the canonical form is (--canonical-policy)
+    canonical_policy: CanonicalPolicy,
+    // Skip the protein/non-DNA input check (--allow-non-dna)
+    allow_non_dna: bool,
+    // Count records against --max-records, which limits only build's own inputs
+    record_limit: bool,
+}
+
+// What reading one sequence source found besides its k-mers.
+struct SourceSummary {
+    first_header: Option<String>,
+    // Windows skipped because they contained 'N' or other non-ACGT characters
+    invalid_windows: usize,
+    // Records passing the sequence filter, and those among them shorter than k
+    records: usize,
+    short_records: usize,
+    // Bases of those records, and how many were N/ambiguous
+    bases: BaseCounts,
+    // The lengths of those records, stored with a whole-file reference
+    lengths: SequenceLengths,
+    // Time the parsing thread spent decompressing and parsing records
+    parse_time: Duration,
+}
+
+// The k-mers collected from one reference: a presence set, or occurrence counts
+// when --min-multiplicity drops k-mers seen fewer than `min_multiplicity` times.
+enum FileKmers {
+    Presence(DashSet<u64>),
+    Counts {
+        counts: DashMap<u64, AtomicUsize>,
+        min_multiplicity: usize,
+    },
+}
+
+impl FileKmers {
+    fn new(min_multiplicity: usize) -> Self {
+        if min_multiplicity > 1 {
+            FileKmers::Counts {
+                counts: DashMap::new(),
+                min_multiplicity,
             }
+        } else {
+            FileKmers::Presence(DashSet::new())
         }
-        record_count += 1;
-        if record_count % 100_000 == 0 {
-            // Keep debug logging for detailed progress
-            debug!(
-                "Processed {} records from {}. Current unique k-mers for this file: {}",
-                record_count,
-                path_str,
-                file_kmer_set.len()
-            );
+    }
+
+    fn insert(&self, canonical_kmer: u64) {
+        match self {
+            FileKmers::Presence(kmer_set) => {
+                kmer_set.insert(canonical_kmer);
+            }
+            FileKmers::Counts { counts, .. } => {
+                counts
+                    .entry(canonical_kmer)
+                    .or_insert_with(|| AtomicUsize::new(0))
+                    .fetch_add(1, Ordering::Relaxed);
+            }
         }
-        // pb.inc(1); // Increment per-file progress bar if used
     }
+
+    // Distinct k-mers seen so far, before any --min-multiplicity threshold
+    fn len(&self) -> usize {
+        match self {
+            FileKmers::Presence(kmer_set) => kmer_set.len(),
+            FileKmers::Counts { counts, .. } => counts.len(),
+        }
+    }
+
+    // The k-mers to store for the reference
+    fn into_kmers(self) -> Box<dyn Iterator<Item = u64>> {
+        match self {
+            FileKmers::Presence(kmer_set) => Box::new(kmer_set.into_iter()),
+            FileKmers::Counts {
+                counts,
+                min_multiplicity,
+            } => Box::new(
+                counts
+                    .into_iter()
+                    .filter(move |(_, count)| count.load(Ordering::Relaxed) >= min_multiplicity)
+                    .map(|(kmer, _)| kmer),
+            ),
+        }
+    }
+}
+
+// Calls `visit` with the start and stored form (canonical unless `extraction` says otherwise) of
+// every k-mer of one normalized sequence, returning the number of windows skipped as invalid.
+fn for_each_sequence_kmer(
+    norm_seq: &[u8],
+    k: u8,
+    extraction: KmerExtraction,
+    mut visit: impl FnMut(usize, u64),
+) -> usize {
+    let stored_kmer = |kmer_val: u64| {
+        if extraction.canonical {
+            canonical_u64_with_policy(kmer_val, k, extraction.canonical_policy)
+        } else {
+            kmer_val
+        }
+    };
+    let mut invalid_windows = 0;
+    // rolling_kmers yields None for windows with 'N' or other non-ACGT characters
+    for (pos, kmer) in rolling_kmers(norm_seq, k) {
+        if let Some(kmer_val) = kmer {
+            visit(pos, stored_kmer(kmer_val));
+            continue;
+        }
+        if let Some(max_ambiguous) = extraction.iupac_max_ambiguous {
+            let window = &norm_seq[pos..pos + k as usize];
+            if ambiguous_base_count(window) <= max_ambiguous {
+                for kmer_val in expand_iupac_window(window, k) {
+                    visit(pos, stored_kmer(kmer_val));
+                }
+                continue;
+            }
+        }
+        invalid_windows += 1;
+    }
+    invalid_windows
+}
+
+// Inserts the k-mers of one normalized sequence into `file_kmer_set`, returning the number of
+// windows skipped as invalid.
+fn insert_sequence_kmers(
+    norm_seq: &[u8],
+    k: u8,
+    extraction: KmerExtraction,
+    file_kmer_set: &FileKmers,
+) -> usize {
+    for_each_sequence_kmer(norm_seq, k, extraction, |_, kmer| file_kmer_set.insert(kmer))
+}
+
+// Extracts k-mers from FASTA/Q content read from `input_reader` into `file_kmer_set`, returning
+// a SourceSummary of what was read: the first header, the invalid windows skipped, the record,
+// base and length counts, and the parsing time. `source_name` is only used for logging and
+// error context.
+//
+// The calling thread decompresses and parses records and sends them in batches of `batch_size`
+// over a bounded channel to a consumer thread, which extracts each batch's k-mers on the rayon
+// pool. Parsing (often the bottleneck for xz input) thus overlaps with k-mer extraction.
+fn process_sequences_from_reader<R: Read + Send>(
+    input_reader: R,
+    source_name: &str,
+    k: u8,
+    extraction: KmerExtraction,
+    sequence_filter: &SequenceFilter,
+    file_kmer_set: &FileKmers,
+    batch_size: usize,
+) -> Result<SourceSummary> {
+    // Pass the reader to open_fastx_reader instead of a path to parse_fastx_file
+    let mut reader = open_fastx_reader(input_reader, Path::new(source_name))
+        .with_context(|| format!("Failed to parse FASTA/Q content from: {}", source_name))?;
+
+    let mut record_count = 0;
+    let mut short_records = 0;
+    let mut bases = BaseCounts::default();
+    let mut lengths = SequenceLengths::default();
+    let summary = thread::scope(|scope| -> Result<SourceSummary> {
+        let (batch_sender, batch_receiver) = mpsc::sync_channel::<Vec<Vec<u8>>>(PIPELINE_BATCHES_IN_FLIGHT);
+        let consumer = scope.spawn(move || {
+            batch_receiver
+                .into_iter()
+                .map(|batch| {
+                    batch
+                        .par_iter()
+                        .map(|norm_seq| insert_sequence_kmers(norm_seq, k, extraction, file_kmer_set))
+                        .sum::<usize>()
+                })
+                .sum::<usize>()
+        });
+        let send_batch = |batch: Vec<Vec<u8>>| {
+            batch_sender.send(batch).map_err(|_| {
+                OrionKmerError::Generic(format!("K-mer extraction stopped while reading {}", source_name))
+            })
+        };
+
+        let mut first_header = None;
+        let mut parse_time = Duration::ZERO;
+        let mut batch: Vec<Vec<u8>> = Vec::with_capacity(batch_size);
+        loop {
+            let parse_start = Instant::now();
+            let Some(record) = reader.next() else {
+                break;
+            };
+            if extraction.record_limit && !take_input_record() {
+                break;
+            }
+            parse_time += parse_start.elapsed();
+            let record = record.with_context(|| format!("Error reading record from {}", source_name))?;
+            if first_header.is_none() {
+                first_header = Some(String::from_utf8_lossy(record.id()).trim().to_string());
+            }
+            if !sequence_filter.matches(record.id()) {
+                continue;
+            }
+            record_count += 1;
+            if !extraction.allow_non_dna {
+                check_dna_record(record.raw_seq(), record.id(), source_name)?;
+            }
+            // Ensure uppercase; IUPAC codes are kept only when they are going to be expanded
+            let norm_seq = record.normalize(extraction.iupac_max_ambiguous.is_some());
+            bases.add_sequence(&norm_seq);
+            lengths.add(norm_seq.len() as u64);
+            if norm_seq.len() < k as usize {
+                short_records += 1;
+                continue;
+            }
+            batch.push(norm_seq.into_owned());
+            if batch.len() == batch_size {
+                send_batch(std::mem::replace(&mut batch, Vec::with_capacity(batch_size)))?;
+            }
+
+            if is_progress_record(record_count) {
+                // Keep debug logging for detailed progress
+                debug!(
+                    "Processed {} records from {}. Current unique k-mers for this file: {}",
+                    record_count,
+                    source_name,
+                    file_kmer_set.len()
+                );
+            }
+            // pb.inc(1); // Increment per-file progress bar if used
+        }
+        if !batch.is_empty() {
+            send_batch(batch)?;
+        }
+        // Dropping the sender ends the consumer, which is then joined for its tally
+        drop(batch_sender);
+        let invalid_windows = consumer
+            .join()
+            .map_err(|_| OrionKmerError::Generic(format!("K-mer extraction failed for {}", source_name)))?;
+        Ok(SourceSummary {
+            first_header,
+            invalid_windows,
+            records: record_count,
+            short_records,
+            bases,
+            lengths,
+            parse_time,
+        })
+    })?;
     info!(
         "Finished processing {} records from {}. Found {} unique k-mers for this file.",
         record_count,
-        path_str,
+        source_name,
         file_kmer_set.len()
     );
+    Ok(summary)
+}
+
+// Adds every record read from `input_reader` as its own reference, named by its sequence ID,
+// and stores where each of its k-mers starts (--per-record). Records are read one at a time so
+// positions stay in sequence order; records shorter than k are skipped.
+fn add_per_record_references<R: Read + Send>(
+    kmer_db_v2: &mut KmerDbV2,
+    input_reader: R,
+    source_name: &str,
+    extraction: KmerExtraction,
+    sequence_filter: &SequenceFilter,
+    reference_names: &mut NewReferenceNames,
+    sampling: Option<ReferenceSampling>,
+) -> Result<SourceSummary> {
+    let k = kmer_db_v2.k;
+    let mut reader = open_fastx_reader(input_reader, Path::new(source_name))
+        .with_context(|| format!("Failed to parse FASTA/Q content from: {}", source_name))?;
+    let mut summary = SourceSummary {
+        first_header: None,
+        invalid_windows: 0,
+        records: 0,
+        short_records: 0,
+        bases: BaseCounts::default(),
+        lengths: SequenceLengths::default(),
+        parse_time: Duration::ZERO,
+    };
+    loop {
+        let parse_start = Instant::now();
+        let Some(record) = reader.next() else {
+            break;
+        };
+        if extraction.record_limit && !take_input_record() {
+            break;
+        }
+        summary.parse_time += parse_start.elapsed();
+        let record = record.with_context(|| format!("Error reading record from {}", source_name))?;
+        if summary.first_header.is_none() {
+            summary.first_header = Some(String::from_utf8_lossy(record.id()).trim().to_string());
+        }
+        if !sequence_filter.matches(record.id()) {
+            continue;
+        }
+        summary.records += 1;
+        if !extraction.allow_non_dna {
+            check_dna_record(record.raw_seq(), record.id(), source_name)?;
+        }
+        let norm_seq = record.normalize(extraction.iupac_max_ambiguous.is_some());
+        summary.bases.add_sequence(&norm_seq);
+        if norm_seq.len() < k as usize {
+            summary.short_records += 1;
+            continue;
+        }
+        let header = String::from_utf8_lossy(record.id());
+        let record_name = header.split_whitespace().next().unwrap_or("").to_string();
+        let Some(reference_name) = reference_names.admit(kmer_db_v2, record_name)? else {
+            continue;
+        };
+        let mut kmer_positions: Vec<(u64, u64)> = Vec::new();
+        summary.invalid_windows += for_each_sequence_kmer(&norm_seq, k, extraction, |pos, kmer| {
+            kmer_positions.push((kmer_db_v2.lookup_key(kmer), pos as u64));
+        });
+        kmer_positions.sort_unstable();
+        kmer_positions.dedup();
+        let kmer_set: HashSet<u64> = kmer_positions.iter().map(|&(key, _)| key).collect();
+        info!(
+            "Adding {} unique k-mers from record '{}' in {} to the database.",
+            kmer_set.len(),
+            reference_name,
+            source_name
+        );
+        kmer_db_v2.add_reference(reference_name.clone(), kmer_set);
+        kmer_db_v2.positions.insert(
+            reference_name.clone(),
+            ReferencePositions {
+                length: norm_seq.len() as u64,
+                kmer_positions,
+            },
+        );
+        let mut lengths = SequenceLengths::default();
+        lengths.add(norm_seq.len() as u64);
+        kmer_db_v2.reference_lengths.insert(reference_name.clone(), lengths);
+        sample_reference(kmer_db_v2, &reference_name, sampling);
+    }
+    Ok(summary)
+}
+
+// Records the --profile phases of reading one sequence source that started at `started`. Parsing
+// overlaps with k-mer extraction, so extraction is the remaining time not hidden behind parsing.
+fn record_source_phases(timer: &PhaseTimer, started: Instant, summary: &SourceSummary) {
+    timer.add("parse", summary.parse_time);
+    timer.add("k-mer extraction", started.elapsed().saturating_sub(summary.parse_time));
+}
+
+// Logs the --report-invalid tally for one sequence source.
+fn report_invalid_windows(source_name: &str, invalid_windows: usize) {
+    warn!(
+        "Skipped {} k-mer windows containing N or other non-ACGT characters in {}",
+        invalid_windows, source_name
+    );
+}
+
+/// Builds an in-memory database with a single reference, named after the file, holding the
+/// k-mers of `file_path`, stored with `hashing` and in canonical form (or not, and with
+/// `canonical_policy`) like a database built with the same settings.
+pub(crate) fn build_kmer_db_from_file(
+    file_path: &PathBuf,
+    k: u8,
+    hashing: Option<KmerHashing>,
+    canonical: bool,
+    canonical_policy: CanonicalPolicy,
+) -> Result<KmerDbV2> {
+    let mut kmer_db_v2 = KmerDbV2::new(k);
+    kmer_db_v2.hashing = hashing;
+    kmer_db_v2.canonical = canonical;
+    kmer_db_v2.canonical_policy = canonical_policy;
+    let file_kmer_set = FileKmers::new(1);
+    let summary = process_sequences_for_file(
+        file_path,
+        k,
+        KmerExtraction {
+            iupac_max_ambiguous: None,
+            canonical,
+            canonical_policy,
+            allow_non_dna: true,
+            record_limit: false,
+        },
+        &SequenceFilter::default(),
+        &file_kmer_set,
+        DEFAULT_BATCH_SIZE,
+    )?;
+    let reference_name = file_path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| file_path.to_string_lossy().into_owned());
+    add_reference_from_set(&mut kmer_db_v2, reference_name, file_kmer_set, summary.lengths, None);
+    Ok(kmer_db_v2)
+}
+
+// Moves the k-mers collected for one reference into the database, with the lengths of the
+// records they came from.
+fn add_reference_from_set(
+    kmer_db_v2: &mut KmerDbV2,
+    reference_name: String,
+    kmer_set: FileKmers,
+    lengths: SequenceLengths,
+    sampling: Option<ReferenceSampling>,
+) {
+    let final_kmers: HashSet<u64> = kmer_set
+        .into_kmers()
+        .map(|kmer| kmer_db_v2.lookup_key(kmer))
+        .collect();
+    info!(
+        "Adding {} unique k-mers from reference '{}' to the database.",
+        final_kmers.len(),
+        reference_name
+    );
+    kmer_db_v2.add_reference(reference_name.clone(), final_kmers);
+    kmer_db_v2.reference_lengths.insert(reference_name.clone(), lengths);
+    sample_reference(kmer_db_v2, &reference_name, sampling);
+}
+
+/// --max-kmers-per-reference: the most k-mers a reference keeps, and the sampling seed.
+#[derive(Clone, Copy, Debug)]
+struct ReferenceSampling {
+    max_kmers: usize,
+    seed: u64,
+}
+
+impl ReferenceSampling {
+    fn from_args(args: &BuildArgs) -> Option<Self> {
+        args.max_kmers_per_reference.map(|max_kmers| ReferenceSampling {
+            max_kmers,
+            seed: args.sampling_seed,
+        })
+    }
+}
+
+// Caps a reference just added to the database at --max-kmers-per-reference k-mers.
+fn sample_reference(kmer_db_v2: &mut KmerDbV2, reference_name: &str, sampling: Option<ReferenceSampling>) {
+    let Some(sampling) = sampling else {
+        return;
+    };
+    if kmer_db_v2.sample_reference(reference_name, sampling.max_kmers, sampling.seed) {
+        info!(
+            "Sampled {} of the k-mers of reference '{}' (rate {:.4})",
+            sampling.max_kmers, reference_name, kmer_db_v2.sampling_rates[reference_name]
+        );
+    }
+}
+
+// Applies the --on-conflict policy to a reference about to be added. Returns false if the
+// reference should be skipped because its name is already taken.
+fn should_add_reference(
+    kmer_db_v2: &KmerDbV2,
+    reference_name: &str,
+    on_conflict: OnConflict,
+) -> Result<bool> {
+    if !kmer_db_v2.references.contains_key(reference_name) {
+        return Ok(true);
+    }
+    match on_conflict {
+        OnConflict::Error => {
+            Err(OrionKmerError::DuplicateReference(reference_name.to_string()).into())
+        }
+        OnConflict::Skip => {
+            info!(
+                "Skipping reference '{}': a reference with this name is already in the database.",
+                reference_name
+            );
+            Ok(false)
+        }
+        OnConflict::Replace => {
+            info!("Replacing existing reference '{}'.", reference_name);
+            Ok(true)
+        }
+    }
+}
+
+// The --on-conflict policy, defaulting to an error only when extending an existing database
+// (--append or --resume), whose references a clash would overwrite. Plain builds keep letting a
+// later input replace an earlier one of the same name.
+fn on_conflict_policy(args: &BuildArgs) -> OnConflict {
+    args.on_conflict.unwrap_or(if args.append.is_some() || args.resume {
+        OnConflict::Error
+    } else {
+        OnConflict::Replace
+    })
+}
+
+// Decides the name each new reference is stored under and whether it is added at all:
+// references completed in a resumed checkpoint are skipped and taken names follow --on-conflict.
+// With --normalize-ref-names, names are normalized before anything else. With --sanitize-names,
+// names are then made safe identifiers; distinct names that sanitize to the same identifier get
+// `_2`, `_3`, ... suffixes in input order, while a name seen again maps to the same identifier,
+// so --on-conflict still applies to it.
+struct NewReferenceNames<'a> {
+    on_conflict: OnConflict,
+    checkpointed_references: &'a HashSet<String>,
+    normalization: Option<ReferenceNameNormalization>,
+    sanitize: bool,
+    sanitized_names: HashMap<String, String>, // Name as derived -> name it is stored under
+    assigned_names: HashSet<String>,
+}
+
+impl<'a> NewReferenceNames<'a> {
+    fn new(args: &BuildArgs, checkpointed_references: &'a HashSet<String>) -> Self {
+        NewReferenceNames {
+            on_conflict: on_conflict_policy(args),
+            checkpointed_references,
+            normalization: name_normalization(args),
+            sanitize: args.sanitize_names,
+            sanitized_names: HashMap::new(),
+            assigned_names: HashSet::new(),
+        }
+    }
+
+    fn stored_name(&mut self, reference_name: String) -> String {
+        let reference_name = match self.normalization {
+            Some(normalization) => normalization.apply(&reference_name),
+            None => reference_name,
+        };
+        if !self.sanitize {
+            return reference_name;
+        }
+        if let Some(stored_name) = self.sanitized_names.get(&reference_name) {
+            return stored_name.clone();
+        }
+        let base_name = sanitize_reference_name(&reference_name);
+        let mut stored_name = base_name.clone();
+        let mut suffix = 2;
+        while self.assigned_names.contains(&stored_name) {
+            stored_name = format!("{}_{}", base_name, suffix);
+            suffix += 1;
+        }
+        if stored_name != reference_name {
+            debug!("Storing reference '{}' as '{}'", reference_name, stored_name);
+        }
+        self.assigned_names.insert(stored_name.clone());
+        self.sanitized_names.insert(reference_name, stored_name.clone());
+        stored_name
+    }
+
+    // Returns the name to add the reference under, or None if it should be skipped.
+    fn admit(&mut self, kmer_db_v2: &KmerDbV2, reference_name: String) -> Result<Option<String>> {
+        let reference_name = self.stored_name(reference_name);
+        if self.checkpointed_references.contains(&reference_name) {
+            info!("Skipping '{}': already in checkpoint", reference_name);
+            return Ok(None);
+        }
+        if !should_add_reference(kmer_db_v2, &reference_name, self.on_conflict)? {
+            return Ok(None);
+        }
+        Ok(Some(reference_name))
+    }
+}
+
+// Name of a --from-counts reference: the table's file name without compression and last
+// extension, e.g. `sample` for `sample.tsv.gz`.
+fn count_table_reference_name(counts_path: &Path) -> String {
+    let file_name = counts_path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| counts_path.to_string_lossy().into_owned());
+    let uncompressed = [".gz", ".xz", ".zst", ".zstd"]
+        .iter()
+        .find_map(|ext| file_name.strip_suffix(ext))
+        .unwrap_or(&file_name);
+    Path::new(uncompressed)
+        .file_stem()
+        .map_or_else(|| uncompressed.to_string(), |stem| stem.to_string_lossy().into_owned())
+}
+
+// Adds the k-mers of a count table counted at least --min-count times as one reference
+// (--from-counts). Count tables hold canonical k-mers, so they are stored like `build` would.
+fn add_reference_from_counts(
+    kmer_db_v2: &mut KmerDbV2,
+    counts_path: &Path,
+    args: &BuildArgs,
+    reference_names: &mut NewReferenceNames,
+) -> Result<()> {
+    let (table_k, counts) = read_count_table(counts_path)?;
+    if let Some(table_k) = table_k
+        && table_k != kmer_db_v2.k
+    {
+        return Err(OrionKmerError::Generic(format!(
+            "Count table {:?} has k={}, but the database is being built with k={}",
+            counts_path, table_k, kmer_db_v2.k
+        ))
+        .into());
+    }
+    let reference_name = args
+        .ref_name
+        .clone()
+        .unwrap_or_else(|| count_table_reference_name(counts_path));
+    let Some(reference_name) = reference_names.admit(kmer_db_v2, reference_name)? else {
+        return Ok(());
+    };
+    let table_size = counts.len();
+    let kmers: HashSet<u64> = counts
+        .into_iter()
+        .filter(|&(_, count)| count >= args.min_count)
+        // Count tables hold lexicographically canonical k-mers; restore the database's policy
+        .map(|(kmer, _)| {
+            let kmer = if kmer_db_v2.canonical { kmer_db_v2.canonicalize(kmer) } else { kmer };
+            kmer_db_v2.lookup_key(kmer)
+        })
+        .collect();
+    info!(
+        "Adding {} of {} k-mers counted at least {} times in {:?} as reference '{}'",
+        kmers.len(),
+        table_size,
+        args.min_count,
+        counts_path,
+        reference_name
+    );
+    kmer_db_v2.add_reference(reference_name.clone(), kmers);
+    sample_reference(kmer_db_v2, &reference_name, ReferenceSampling::from_args(args));
     Ok(())
 }
 
-pub fn run_build(args: BuildArgs) -> Result<()> {
-    info!("Starting build command with args: {:?}", args);
+// Writes, for each reference, how many of its k-mers appear in no other reference.
+fn write_uniqueness_report(kmer_db_v2: &KmerDbV2, report_path: &Path) -> Result<()> {
+    let occurrences = kmer_db_v2.reference_occurrence_counts();
+
+    let mut writer = get_output_writer(report_path).with_context(|| {
+        format!(
+            "Failed to get output writer for uniqueness report: {:?}",
+            report_path
+        )
+    })?;
+    writeln!(writer, "reference\ttotal_kmers\tunique_kmers\tunique_fraction")?;
 
-    if args.kmer_size == 0 || args.kmer_size > 32 {
-        return Err(OrionKmerError::InvalidKmerSize(args.kmer_size).into());
+    for (reference_name, kmer_set) in &kmer_db_v2.references {
+        let unique_kmers = kmer_set
+            .iter()
+            .filter(|kmer| occurrences[*kmer] == 1)
+            .count();
+        let unique_fraction = if kmer_set.is_empty() {
+            0.0
+        } else {
+            unique_kmers as f64 / kmer_set.len() as f64
+        };
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t{:.4}",
+            reference_name,
+            kmer_set.len(),
+            unique_kmers,
+            unique_fraction
+        )?;
     }
-    let k = args.kmer_size;
+    finalize_writer(writer).context("Failed to finish uniqueness report writer")?;
+    Ok(())
+}
 
-    let mut kmer_db_v2 = KmerDbV2::new(k);
+// Writes the TSV matrix of how many k-mers each pair of references shares. The diagonal holds
+// each reference's own k-mer count. Pairs are intersected in parallel, each by iterating the
+// smaller set.
+fn write_overlap_matrix(kmer_db_v2: &KmerDbV2, matrix_path: &Path) -> Result<()> {
+    let references: Vec<(&String, &HashSet<u64>)> = kmer_db_v2.references.iter().collect();
+    let n = references.len();
+    let pairs: Vec<(usize, usize)> = (0..n).flat_map(|i| (i + 1..n).map(move |j| (i, j))).collect();
+    let shared: Vec<usize> = pairs
+        .par_iter()
+        .map(|&(i, j)| {
+            let (smaller, larger) = if references[i].1.len() <= references[j].1.len() {
+                (references[i].1, references[j].1)
+            } else {
+                (references[j].1, references[i].1)
+            };
+            smaller.iter().filter(|kmer| larger.contains(kmer)).count()
+        })
+        .collect();
+    let mut matrix = vec![vec![0usize; n]; n];
+    for (i, (_, kmer_set)) in references.iter().enumerate() {
+        matrix[i][i] = kmer_set.len();
+    }
+    for (&(i, j), &shared_kmers) in pairs.iter().zip(&shared) {
+        matrix[i][j] = shared_kmers;
+        matrix[j][i] = shared_kmers;
+    }
+
+    let mut writer = get_output_writer(matrix_path).with_context(|| {
+        format!("Failed to get output writer for overlap matrix: {:?}", matrix_path)
+    })?;
+    write!(writer, "reference")?;
+    for (name, _) in &references {
+        write!(writer, "\t{}", name)?;
+    }
+    writeln!(writer)?;
+    for ((name, _), row) in references.iter().zip(&matrix) {
+        write!(writer, "{}", name)?;
+        for shared_kmers in row {
+            write!(writer, "\t{}", shared_kmers)?;
+        }
+        writeln!(writer)?;
+    }
+    finalize_writer(writer).context("Failed to finish overlap matrix writer")?;
+    Ok(())
+}
+
+// Saves the partial database after a completed reference. The checkpoint is written next to
+// its final path and renamed into place, so an interruption never leaves a truncated file.
+fn save_checkpoint(kmer_db_v2: &KmerDbV2, checkpoint_path: Option<&Path>) -> Result<()> {
+    let Some(checkpoint_path) = checkpoint_path else {
+        return Ok(());
+    };
+    let file_name = checkpoint_path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    // Keep the extension so the temporary file is compressed the same way
+    let partial_path = checkpoint_path.with_file_name(format!(".partial-{}", file_name));
+    write_kmer_db(kmer_db_v2, &partial_path)?;
+    fs::rename(&partial_path, checkpoint_path)
+        .with_context(|| format!("Failed to move checkpoint into place at {:?}", checkpoint_path))?;
+    debug!(
+        "Checkpointed {} references to {:?}",
+        kmer_db_v2.num_references(),
+        checkpoint_path
+    );
+    Ok(())
+}
+
+// Builds the database of `args`' inputs at k-mer size `k`, without writing any output.
+fn build_kmer_db(args: &BuildArgs, k: u8, timer: &PhaseTimer) -> Result<KmerDbV2> {
+    // Each pass of --k-list reads the same first --max-records records
+    restart_record_limit();
+    let extraction = KmerExtraction {
+        iupac_max_ambiguous: args.expand_iupac.then_some(args.max_ambiguous_bases),
+        canonical: !args.no_canonical,
+        canonical_policy: canonical_policy(args, k)?,
+        allow_non_dna: args.allow_non_dna,
+        record_limit: true,
+    };
+    let sequence_filter =
+        SequenceFilter::new(&args.include_seq, &args.exclude_seq, args.seq_regex.as_deref())?
+            .with_subsample(args.subsample, args.subsample_seed)?;
+    if args.max_kmers_per_reference == Some(0) {
+        return Err(OrionKmerError::Generic("--max-kmers-per-reference must be at least 1".to_string()).into());
+    }
+    let sampling = ReferenceSampling::from_args(args);
+
+    let load_phase = timer.phase("load databases");
+    let mut kmer_db_v2 = match &args.append {
+        Some(existing_db_path) => {
+            let existing_db = load_kmer_db_v2(existing_db_path).with_context(|| {
+                format!("Failed to load database to append to: {:?}", existing_db_path)
+            })?;
+            if existing_db.k != k {
+                return Err(OrionKmerError::KmerSizeMismatchValidation(
+                    k,
+                    existing_db.k,
+                    existing_db_path.clone(),
+                )
+                .into());
+            }
+            if existing_db.canonical != extraction.canonical {
+                return Err(OrionKmerError::CanonicalMismatch(
+                    existing_db_path.clone(),
+                    args.output_file.clone(),
+                )
+                .into());
+            }
+            if existing_db.canonical_policy != extraction.canonical_policy {
+                return Err(OrionKmerError::CanonicalPolicyMismatch(
+                    existing_db.canonical_policy,
+                    extraction.canonical_policy,
+                    existing_db_path.clone(),
+                )
+                .into());
+            }
+            check_name_normalization(&existing_db, args, existing_db_path)?;
+            existing_db
+        }
+        None => {
+            let mut kmer_db_v2 = KmerDbV2::new(k);
+            kmer_db_v2.canonical = extraction.canonical;
+            kmer_db_v2.canonical_policy = extraction.canonical_policy;
+            kmer_db_v2.name_normalization = name_normalization(args);
+            kmer_db_v2
+        }
+    };
+    if args.hashed {
+        let hashing = Some(KmerHashing {
+            function: HashFunction::Murmur3X64,
+            seed: args.hash_seed,
+        });
+        // An appended database keeps its hashing; new references must be stored the same way
+        if let Some(existing_db_path) = &args.append
+            && kmer_db_v2.hashing != hashing
+        {
+            return Err(OrionKmerError::HashingMismatch(
+                kmer_db_v2.hashing,
+                hashing,
+                existing_db_path.clone(),
+            )
+            .into());
+        }
+        kmer_db_v2.hashing = hashing;
+        info!("Storing k-mers as MurmurHash3 hashes (seed {})", args.hash_seed);
+    }
+
+    // References already in the checkpoint were completed by an earlier run and are skipped
+    let mut checkpointed_references: HashSet<String> = HashSet::new();
+    if let Some(checkpoint_path) = &args.checkpoint
+        && args.resume
+    {
+        if checkpoint_path.exists() {
+            let checkpoint_db = load_kmer_db_v2(checkpoint_path).with_context(|| {
+                format!("Failed to load build checkpoint: {:?}", checkpoint_path)
+            })?;
+            if checkpoint_db.k != k {
+                return Err(OrionKmerError::KmerSizeMismatchValidation(
+                    k,
+                    checkpoint_db.k,
+                    checkpoint_path.clone(),
+                )
+                .into());
+            }
+            if checkpoint_db.hashing != kmer_db_v2.hashing {
+                return Err(OrionKmerError::HashingMismatch(
+                    kmer_db_v2.hashing,
+                    checkpoint_db.hashing,
+                    checkpoint_path.clone(),
+                )
+                .into());
+            }
+            if checkpoint_db.canonical != kmer_db_v2.canonical {
+                return Err(OrionKmerError::CanonicalMismatch(
+                    checkpoint_path.clone(),
+                    args.output_file.clone(),
+                )
+                .into());
+            }
+            if checkpoint_db.canonical_policy != kmer_db_v2.canonical_policy {
+                return Err(OrionKmerError::CanonicalPolicyMismatch(
+                    kmer_db_v2.canonical_policy,
+                    checkpoint_db.canonical_policy,
+                    checkpoint_path.clone(),
+                )
+                .into());
+            }
+            check_name_normalization(&checkpoint_db, args, checkpoint_path)?;
+            checkpointed_references = checkpoint_db.references.keys().cloned().collect();
+            info!(
+                "Resuming from checkpoint {:?} with {} completed references",
+                checkpoint_path,
+                checkpointed_references.len()
+            );
+            kmer_db_v2 = checkpoint_db;
+        } else {
+            info!(
+                "No checkpoint found at {:?}; starting from the beginning",
+                checkpoint_path
+            );
+        }
+    }
+    drop(load_phase);
+    let mut reference_names = NewReferenceNames::new(args, &checkpointed_references);
+    if let Some(counts_path) = &args.from_counts {
+        let _counts_phase = timer.phase("load count table");
+        add_reference_from_counts(&mut kmer_db_v2, counts_path, args, &mut reference_names)?;
+    }
     let num_files = args.genome_files.len() as u64;
+    // Bases over all genome files, logged once they have been read
+    let mut input_bases = BaseCounts::default();
 
     // Wrap the main file processing loop
     track_progress_and_resources("Building k-mer database", num_files, |pb_files| {
         for input_path in &args.genome_files {
-            // For each file, create a new DashSet to collect its k-mers.
-            let file_kmer_set: DashSet<u64> = DashSet::new();
+            if is_tar_archive(input_path) {
+                // Every sequence file inside the archive becomes its own reference,
+                // named by its path within the archive.
+                let path_str = input_path.to_string_lossy();
+                info!("Processing genome archive: {}", path_str);
+                for_each_tar_entry(input_path, |entry_name, entry_reader| {
+                    if !is_sequence_file_name(entry_name) {
+                        debug!("Skipping non-sequence archive entry '{}' in {}", entry_name, path_str);
+                        return Ok(());
+                    }
+                    if args.per_record {
+                        let source_name = format!("{}:{}", path_str, entry_name);
+                        let started = Instant::now();
+                        let summary = add_per_record_references(
+                            &mut kmer_db_v2,
+                            entry_reader,
+                            &source_name,
+                            extraction,
+                            &sequence_filter,
+                            &mut reference_names,
+                            sampling,
+                        )?;
+                        record_source_phases(timer, started, &summary);
+                        report_short_records(&source_name, summary.short_records, summary.records, k);
+                        report_base_counts(&source_name, &summary.bases);
+                        input_bases.add(&summary.bases);
+                        if args.report_invalid {
+                            report_invalid_windows(&source_name, summary.invalid_windows);
+                        }
+                        return timer.measure("checkpoint", || save_checkpoint(&kmer_db_v2, args.checkpoint.as_deref()));
+                    }
+                    // Header names are only known once the entry has been read
+                    let path_name = match args.ref_name_style {
+                        RefNameStyle::Filename | RefNameStyle::Path => Some(entry_name.to_string()),
+                        RefNameStyle::Stem => Some(strip_sequence_extensions(entry_name).to_string()),
+                        RefNameStyle::Header => None,
+                    };
+                    let path_name = match path_name {
+                        Some(path_name) => match reference_names.admit(&kmer_db_v2, path_name)? {
+                            Some(reference_name) => Some(reference_name),
+                            None => return Ok(()),
+                        },
+                        None => None,
+                    };
+                    let entry_kmer_set = FileKmers::new(args.min_multiplicity);
+                    let started = Instant::now();
+                    let summary = process_sequences_from_reader(
+                        entry_reader,
+                        &format!("{}:{}", path_str, entry_name),
+                        k,
+                        extraction,
+                        &sequence_filter,
+                        &entry_kmer_set,
+                        args.batch_size,
+                    )?;
+                    record_source_phases(timer, started, &summary);
+                    report_short_records(
+                        &format!("{}:{}", path_str, entry_name),
+                        summary.short_records,
+                        summary.records,
+                        k,
+                    );
+                    report_base_counts(&format!("{}:{}", path_str, entry_name), &summary.bases);
+                    input_bases.add(&summary.bases);
+                    if args.report_invalid {
+                        report_invalid_windows(&format!("{}:{}", path_str, entry_name), summary.invalid_windows);
+                    }
+                    let reference_name = match path_name {
+                        Some(reference_name) => reference_name,
+                        None => {
+                            let header_name = summary.first_header.unwrap_or_else(|| entry_name.to_string());
+                            match reference_names.admit(&kmer_db_v2, header_name)? {
+                                Some(reference_name) => reference_name,
+                                None => return Ok(()),
+                            }
+                        }
+                    };
+                    timer.measure("set operations", || {
+                        add_reference_from_set(&mut kmer_db_v2, reference_name, entry_kmer_set, summary.lengths, sampling)
+                    });
+                    timer.measure("checkpoint", || save_checkpoint(&kmer_db_v2, args.checkpoint.as_deref()))
+                })?;
+                pb_files.set_message(format!("Processed archive: {}", path_str));
+                pb_files.inc(1);
+                continue;
+            }
+
+            if args.per_record {
+                let input_buf_reader = get_sequence_file_reader(input_path)?;
+                let started = Instant::now();
+                let summary = add_per_record_references(
+                    &mut kmer_db_v2,
+                    input_buf_reader,
+                    &input_path.to_string_lossy(),
+                    extraction,
+                    &sequence_filter,
+                    &mut reference_names,
+                    sampling,
+                )?;
+                record_source_phases(timer, started, &summary);
+                report_short_records(&input_path.to_string_lossy(), summary.short_records, summary.records, k);
+                report_base_counts(&input_path.to_string_lossy(), &summary.bases);
+                input_bases.add(&summary.bases);
+                if args.report_invalid {
+                    report_invalid_windows(&input_path.to_string_lossy(), summary.invalid_windows);
+                }
+                timer.measure("checkpoint", || save_checkpoint(&kmer_db_v2, args.checkpoint.as_deref()))?;
+                pb_files.set_message(format!("Processed: {:?}", input_path));
+                pb_files.inc(1);
+                continue;
+            }
+
+            let file_name = reference_name_for_file(input_path, args.ref_name_style)?;
+            let Some(reference_name) = reference_names.admit(&kmer_db_v2, file_name)? else {
+                pb_files.inc(1);
+                continue;
+            };
+
+            // For each file, create a new set (or counts, with --min-multiplicity) to collect its k-mers.
+            let file_kmer_set = FileKmers::new(args.min_multiplicity);
 
             // Pass the main progress bar `pb_files` if process_sequences_for_file
             // is to update it directly (e.g. if it was for sequences, not files).
             // Here, we are processing file by file, so `pb_files.inc(1)` is done after each file.
-            process_sequences_for_file(input_path, k, &file_kmer_set)?;
+            let started = Instant::now();
+            let summary = process_sequences_for_file(
+                input_path,
+                k,
+                extraction,
+                &sequence_filter,
+                &file_kmer_set,
+                args.batch_size,
+            )?;
+            record_source_phases(timer, started, &summary);
+            report_short_records(&input_path.to_string_lossy(), summary.short_records, summary.records, k);
+            report_base_counts(&input_path.to_string_lossy(), &summary.bases);
+            input_bases.add(&summary.bases);
+            if args.report_invalid {
+                report_invalid_windows(&input_path.to_string_lossy(), summary.invalid_windows);
+            }
             // Consider adding a nested progress bar inside process_sequences_for_file
             // if individual file processing is very long and has measurable units (e.g. sequences).
 
-            let final_file_kmers: HashSet<u64> = file_kmer_set.into_iter().collect();
-
-            let reference_name = input_path.file_name().map_or_else(
-                || input_path.to_string_lossy().into_owned(),
-                |os_str| os_str.to_string_lossy().into_owned(),
-            );
-
-            info!(
-                "Adding {} unique k-mers from reference '{}' to the database.",
-                final_file_kmers.len(),
-                reference_name
-            );
-            kmer_db_v2.add_reference(reference_name.clone(), final_file_kmers); // Use clone if reference_name is used after
+            timer.measure("set operations", || {
+                add_reference_from_set(&mut kmer_db_v2, reference_name.clone(), file_kmer_set, summary.lengths, sampling) // Use clone if reference_name is used after
+            });
+            timer.measure("checkpoint", || save_checkpoint(&kmer_db_v2, args.checkpoint.as_deref()))?;
             pb_files.set_message(format!("Processed: {}", reference_name));
             pb_files.inc(1);
         }
@@ -121,40 +1158,161 @@ pub fn run_build(args: BuildArgs) -> Result<()> {
     })?;
 
     info!(
-        "Finished processing all input files. Database contains {} references and a total of {} unique canonical k-mers across all references.",
+        "Finished processing all input files. Database contains {} references and a total of {} unique k-mers across all references.",
         kmer_db_v2.num_references(),
         kmer_db_v2.total_unique_kmers()
     );
+    if !args.genome_files.is_empty() {
+        report_base_counts("all genome files", &input_bases);
+    }
+    if args.fail_on_empty && kmer_db_v2.total_unique_kmers() == 0 {
+        return Err(OrionKmerError::Generic(format!(
+            "--fail-on-empty: the k={} database holds no k-mers; the inputs may be empty, header-only or shorter than k",
+            k
+        ))
+        .into());
+    }
+    Ok(kmer_db_v2)
+}
 
-    debug!(
-        "Opening output database file for writing: {:?}",
+// Provenance stored with the built `kmer_db`. An appended database keeps the source files
+// recorded in the one it extends.
+fn build_metadata(args: &BuildArgs, k_values: Vec<u8>, kmer_db: &KmerDbV2) -> DbMetadata {
+    let mut source_files: Vec<String> = kmer_db
+        .metadata
+        .as_ref()
+        .map(|metadata| metadata.source_files.clone())
+        .unwrap_or_default();
+    source_files.extend(args.genome_files.iter().map(|path| path.display().to_string()));
+    source_files.extend(args.from_counts.iter().map(|path| path.display().to_string()));
+    DbMetadata {
+        orion_kmer_version: env!("CARGO_PKG_VERSION").to_string(),
+        built_at_unix_secs: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs()),
+        command_line: std::env::args().collect(),
+        k_values,
+        canonical: kmer_db.canonical,
+        hashed: kmer_db.hashing.is_some(),
+        source_files,
+    }
+}
+
+// The reference name normalization selected by --normalize-ref-names, as the database records it.
+fn name_normalization(args: &BuildArgs) -> Option<ReferenceNameNormalization> {
+    args.normalize_ref_names.map(|normalization| match normalization {
+        RefNameNormalization::Trim => ReferenceNameNormalization::Trim,
+        RefNameNormalization::Lowercase => ReferenceNameNormalization::Lowercase,
+    })
+}
+
+// Databases extended by --append or --resume must have had their names normalized the same way,
+// or the same reference could end up stored under two names.
+fn check_name_normalization(existing_db: &KmerDbV2, args: &BuildArgs, path: &Path) -> Result<()> {
+    let normalization = name_normalization(args);
+    if existing_db.name_normalization != normalization {
+        return Err(OrionKmerError::Generic(format!(
+            "{:?} was built with reference name normalization {:?}, but this build uses {:?}; --normalize-ref-names must match",
+            path, existing_db.name_normalization, normalization
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+// The canonical policy selected by --canonical-policy and --minimizer-length for k-mer size `k`.
+fn canonical_policy(args: &BuildArgs, k: u8) -> Result<CanonicalPolicy> {
+    match args.canonical_policy {
+        CanonicalPolicyKind::Lexicographic => Ok(CanonicalPolicy::Lexicographic),
+        CanonicalPolicyKind::Minimizer => {
+            let m = args.minimizer_length;
+            if m == 0 || m > k {
+                return Err(OrionKmerError::Generic(format!(
+                    "--minimizer-length must be between 1 and the k-mer size {}, got {}",
+                    k, m
+                ))
+                .into());
+            }
+            Ok(CanonicalPolicy::Minimizer { m })
+        }
+    }
+}
+
+fn validate_kmer_size(k: u8) -> Result<()> {
+    if k == 0 || k > 32 {
+        return Err(OrionKmerError::InvalidKmerSize(k).into());
+    }
+    Ok(())
+}
+
+// --k-list: builds one table per k from the same inputs and writes them to a single file.
+fn run_multi_k_build(args: &BuildArgs) -> Result<()> {
+    let k_values: BTreeSet<u8> = args.k_list.iter().copied().collect();
+    for &k in &k_values {
+        validate_kmer_size(k)?;
+    }
+    let timer = PhaseTimer::new("build");
+    let mut multi_k_db = KmerDbMultiK::default();
+    for &k in &k_values {
+        info!("Building the k={} table of the multi-k database", k);
+        multi_k_db.tables.insert(k, build_kmer_db(args, k, &timer)?);
+    }
+    let k_values = multi_k_db.k_values();
+    for table in multi_k_db.tables.values_mut() {
+        table.metadata = Some(build_metadata(args, k_values.clone(), table));
+    }
+    let serialize_phase = timer.phase("serialize");
+    write_multi_k_db(&multi_k_db, &args.output_file)?;
+    drop(serialize_phase);
+    timer.report();
+    info!(
+        "Successfully wrote multi-k database (k = {:?}) to {:?}",
+        multi_k_db.k_values(),
         args.output_file
     );
-    // Use get_output_writer to handle potential compression
-    let mut writer = get_output_writer(&args.output_file).with_context(|| {
-        format!(
-            "Failed to get output writer for database file: {:?}",
-            args.output_file
-        )
-    })?;
+    Ok(())
+}
 
-    bincode::serialize_into(&mut writer, &kmer_db_v2).with_context(|| {
-        format!(
-            "Failed to serialize k-mer database (KmerDbV2) to {:?}",
-            args.output_file
-        )
+pub fn run_build(args: BuildArgs) -> Result<()> {
+    info!("Starting build command with args: {:?}", args);
+
+    if args.batch_size == 0 {
+        return Err(OrionKmerError::Generic("--batch-size must be at least 1".to_string()).into());
+    }
+    if !args.k_list.is_empty() {
+        return run_multi_k_build(&args);
+    }
+    let k = args.kmer_size.ok_or_else(|| {
+        OrionKmerError::Generic("Either --kmer-size or --k-list must be given".to_string())
     })?;
+    validate_kmer_size(k)?;
+
+    let timer = PhaseTimer::new("build");
+    let mut kmer_db_v2 = build_kmer_db(&args, k, &timer)?;
+    kmer_db_v2.metadata = Some(build_metadata(&args, vec![k], &kmer_db_v2));
 
-    // The writer from get_output_writer is already buffered (e.g. BufWriter wrapping an encoder)
-    // and will flush on drop (especially ZstdEncoder with auto_finish, GzEncoder, XzEncoder).
-    // Explicit flush can still be called if desired, but might be redundant.
-    writer
-        .flush()
-        .context("Failed to flush output database writer")?;
+    let serialize_phase = timer.phase("serialize");
+    if let Some(report_path) = &args.uniqueness_report {
+        info!("Writing per-reference k-mer uniqueness report to: {:?}", report_path);
+        write_uniqueness_report(&kmer_db_v2, report_path)?;
+    }
+    if let Some(matrix_path) = &args.overlap_matrix {
+        info!("Writing pairwise shared k-mer matrix to: {:?}", matrix_path);
+        write_overlap_matrix(&kmer_db_v2, matrix_path)?;
+    }
+
+    write_kmer_db(&kmer_db_v2, &args.output_file)?;
     info!(
         "Successfully wrote k-mer database (KmerDbV2) to {:?}",
         args.output_file
     );
 
+    if let Some(index_path) = &args.sorted_index {
+        write_sorted_kmer_index(&kmer_db_v2, index_path)?;
+        info!("Wrote sorted k-mer index to {:?}", index_path);
+    }
+    drop(serialize_phase);
+    timer.report();
+
     Ok(())
 }
@@ -0,0 +1,33 @@
+use anyhow::{Context, Result};
+use log::info;
+use std::io::{self, Write};
+
+use crate::{
+    cli::CatArgs,
+    utils::{finalize_writer, get_decompressed_input_reader, get_output_writer},
+};
+
+pub fn run_cat(args: CatArgs) -> Result<()> {
+    info!("Starting cat command with args: {:?}", args);
+
+    let input_path = &args.input_file;
+    let mut reader = get_decompressed_input_reader(input_path)?;
+    let bytes = match &args.output_file {
+        Some(output_path) => {
+            let mut writer = get_output_writer(output_path)?;
+            let bytes = io::copy(&mut reader, &mut writer)
+                .with_context(|| format!("Failed to copy {:?} to {:?}", input_path, output_path))?;
+            finalize_writer(writer).context("Failed to finish cat writer")?;
+            bytes
+        }
+        None => {
+            let mut stdout = io::stdout().lock();
+            let bytes = io::copy(&mut reader, &mut stdout)
+                .with_context(|| format!("Failed to copy {:?} to stdout", input_path))?;
+            stdout.flush().context("Failed to flush stdout")?;
+            bytes
+        }
+    };
+    info!("Wrote {} uncompressed bytes of {:?}", bytes, input_path);
+    Ok(())
+}
@@ -1,69 +1,795 @@
 use anyhow::{Context, Result};
-use log::{debug, info}; // Removed warn
+use bincode::Options;
+use log::{debug, info, warn};
 use serde::Serialize;
 use std::{
-    collections::{HashMap, HashSet},
-    // path::PathBuf, // Removed PathBuf, as it's not directly used as a type here
+    collections::{BTreeMap, HashMap, HashSet},
+    fs,
+    io::{BufRead, Write},
+    path::{Path, PathBuf},
 };
 
 use crate::{
-    cli::ClassifyArgs,
-    db_types::KmerDbV2,
+    cli::{BreadthDenominator, ClassifyArgs, TableDelimiter},
+    commands::build::strip_sequence_extensions,
+    db_types::{KmerCountTable, KmerDbV2, ReferencePositions, MAX_INVERTED_INDEX_REFERENCES},
     errors::OrionKmerError,
-    kmer::{canonical_u64, seq_to_u64},
-    utils::{get_buffered_file_reader, get_output_writer, load_kmer_db_v2, track_progress_and_resources}, // Import the wrapper & I/O helpers
+    kmer::seq_to_u64,
+    kmer_index::{SortedKmerMmap, SortedKmers},
+    sqlite_output::{ClassificationRow, ReferenceRow, ResultsDb},
+    utils::{check_blocklist_applies, check_canonical_lookup, check_canonical_policy, check_dna_record, db_bincode_options, finalize_writer, format_float, get_decompressed_input_reader, get_output_writer, is_progress_record, load_kmer_db_v2_for_k, open_sequence_reader, OutputWriter, polyx_base, prescan_record_count, report_base_counts, report_short_records, take_input_record, track_progress_and_resources, trim_polyx, trim_read, BaseCounts, PhaseTimer, ReadFilter, ReferenceSelection}, // Import the wrapper & I/O helpers
 };
 use csv;
-use needletail::{parse_fastx_reader, Sequence}; // Changed to parse_fastx_reader
+use needletail::{parser::SequenceRecord, Sequence};
 // use indicatif::ProgressBar; // Not strictly needed for the closure signature if pb is not used inside
 
+// From this many references, an in-memory database is classified through an inverted index
+// (one pass over the input k-mers) rather than a pass over the input per reference.
+const INVERTED_INDEX_MIN_REFERENCES: usize = 8;
+
+// A reference's k-mer keys, either loaded into a set or memory-mapped with --mmap.
+enum ReferenceKmers<'a> {
+    InMemory(&'a HashSet<u64>),
+    Mapped(SortedKmers<'a>),
+}
+
+impl ReferenceKmers<'_> {
+    fn contains(&self, key: u64) -> bool {
+        match self {
+            ReferenceKmers::InMemory(kmer_set) => kmer_set.contains(&key),
+            ReferenceKmers::Mapped(kmers) => kmers.contains(key),
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            ReferenceKmers::InMemory(kmer_set) => kmer_set.len(),
+            ReferenceKmers::Mapped(kmers) => kmers.len(),
+        }
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = u64> + '_> {
+        match self {
+            ReferenceKmers::InMemory(kmer_set) => Box::new(kmer_set.iter().copied()),
+            ReferenceKmers::Mapped(kmers) => Box::new(kmers.iter()),
+        }
+    }
+}
+
 // --- Output Structures ---
 
+/// One reference's classification, as reported in the JSON and TSV outputs.
+#[derive(Serialize, Debug)]
+pub struct ReferenceClassificationResult {
+    pub reference_name: String,
+    pub total_kmers_in_reference: usize,
+    pub input_kmers_hitting_reference: usize,
+    pub sum_depth_of_matched_kmers_in_input: usize,
+    pub avg_depth_of_matched_kmers_in_input: f64, // (sum_depth / input_kmers_hitting_reference)
+    pub proportion_input_kmers_hitting_reference: f64, // (input_kmers_hitting_reference / total_unique_input_kmers)
+    pub reference_breadth_of_coverage: f64, // (input_kmers_hitting_reference / total_kmers_in_reference)
+    // With --weight-by-uniqueness: each k-mer weighs 1 / (number of references in the DB containing it)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub weighted_avg_depth_of_matched_kmers_in_input: Option<f64>, // (sum weight * depth / sum weight) over matched k-mers
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub weighted_reference_breadth_of_coverage: Option<f64>, // (matched weight / total weight of the reference's k-mers)
+    // Set for references built with --max-kmers-per-reference: breadth on the sample estimates the
+    // full reference's breadth, and input_kmers_hitting_reference / rate estimates its full hit count
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kmer_sampling_rate: Option<f64>,
+    // With --per-read-normalize: the fraction of each read's k-mers in the reference, averaged over reads
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mean_per_read_coverage: Option<f64>,
+    // With --coverage-uniformity: 1 - Gini coefficient of the per-base depth (positional
+    // databases) or of the matched k-mers' depths; 0 when nothing is matched
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub coverage_uniformity: Option<f64>,
+}
+
+/// The classification of an input against one database.
+#[derive(Serialize, Debug)]
+pub struct DatabaseClassificationResult {
+    pub database_path: String,
+    pub database_kmer_size: u8,
+    pub total_unique_kmers_in_db_across_references: usize,
+    pub overall_input_kmers_matched_in_db: usize,
+    pub overall_sum_depth_of_matched_kmers_in_input: usize,
+    pub overall_avg_depth_of_matched_kmers_in_input: f64,
+    pub proportion_input_kmers_in_db_overall: f64, // (overall_input_kmers_matched_in_db / total_unique_input_kmers)
+    pub proportion_db_kmers_covered_overall: f64, // (overall_input_kmers_matched_in_db / total_unique_kmers_in_db_across_references)
+    pub references: Vec<ReferenceClassificationResult>,
+    #[serde(skip)]
+    pub filtered_out_references: Vec<ReferenceClassificationResult>, // Failed min_coverage/min_reference_hits; only in --full-tsv
+}
+
+/// The k-mer profile of an input, filtered by minimum frequency, classified against any number
+/// of databases.
+///
+/// The profile is computed once, so the same input can be classified again as databases are
+/// built or extended, e.g. over rounds of reference curation, without reading it again.
+pub struct Classifier {
+    input_kmer_counts: HashMap<u64, usize>,
+    min_coverage: f64,
+    min_reference_hits: usize,
+    weight_by_uniqueness: bool,
+    breadth_denominator: BreadthDenominator,
+    coverage_uniformity: bool,
+}
+
+// What run_classify adds to a database's classification beyond `Classifier::classify_against`.
+#[derive(Default)]
+struct ReferenceContext<'a> {
+    // The --mmap index the database's references are read from
+    mapped_index: Option<&'a SortedKmerMmap>,
+    // --per-read-normalize coverages, with the index of the database among them
+    per_read_coverage: Option<(&'a PerReadCoverage<'a>, usize)>,
+    // --groups memberships and the group k-mers gathered so far
+    groups: Option<GroupContext<'a>>,
+    // The database's inverted index and k-mer occurrences, when already built for another
+    // input (--per-input-record classifies every record against the same database)
+    inverted_index: Option<&'a HashMap<u64, Vec<u16>>>,
+    kmer_occurrences: Option<&'a HashMap<u64, u32>>,
+}
+
+// The Gini coefficient of `values`: 0 when all are equal, approaching 1 when one holds the
+// whole sum. None when they sum to 0.
+fn gini_coefficient(mut values: Vec<u64>) -> Option<f64> {
+    let total: f64 = values.iter().map(|&value| value as f64).sum();
+    if total == 0.0 {
+        return None;
+    }
+    values.sort_unstable();
+    let n = values.len() as f64;
+    let rank_weighted: f64 = values
+        .iter()
+        .enumerate()
+        .map(|(i, &value)| (i + 1) as f64 * value as f64)
+        .sum();
+    Some(2.0 * rank_weighted / (n * total) - (n + 1.0) / n)
+}
+
+// Whether classifying against `num_references` in-memory references uses an inverted index.
+fn uses_inverted_index(mapped: bool, num_references: usize) -> bool {
+    !mapped && (INVERTED_INDEX_MIN_REFERENCES..=MAX_INVERTED_INDEX_REFERENCES).contains(&num_references)
+}
+
+struct GroupContext<'a> {
+    reference_groups: &'a HashMap<String, String>,
+    accumulators: &'a mut BTreeMap<String, GroupAccumulator>,
+}
+
+impl Classifier {
+    /// Creates a classifier for input k-mer counts (k-mer key to occurrences in the input), keyed
+    /// as the databases store their k-mers. Every reference is reported until a filter is set
+    /// with `with_min_coverage` or `with_min_reference_hits`.
+    pub fn new(input_kmer_counts: HashMap<u64, usize>) -> Self {
+        Classifier {
+            input_kmer_counts,
+            min_coverage: 0.0,
+            min_reference_hits: 0,
+            weight_by_uniqueness: false,
+            breadth_denominator: BreadthDenominator::ReferenceKmers,
+            coverage_uniformity: false,
+        }
+    }
+
+    /// Reports only references with at least this breadth of coverage (`--min-coverage`).
+    pub fn with_min_coverage(mut self, min_coverage: f64) -> Self {
+        self.min_coverage = min_coverage;
+        self
+    }
+
+    /// Reports only references hit by at least this many input k-mers (`--min-reference-hits`).
+    pub fn with_min_reference_hits(mut self, min_reference_hits: usize) -> Self {
+        self.min_reference_hits = min_reference_hits;
+        self
+    }
+
+    /// Also computes the uniqueness-weighted depth and breadth (`--weight-by-uniqueness`).
+    pub fn with_weight_by_uniqueness(mut self, weight_by_uniqueness: bool) -> Self {
+        self.weight_by_uniqueness = weight_by_uniqueness;
+        self
+    }
+
+    /// Sets what breadth of coverage is relative to (`--breadth-denominator`).
+    pub fn with_breadth_denominator(mut self, breadth_denominator: BreadthDenominator) -> Self {
+        self.breadth_denominator = breadth_denominator;
+        self
+    }
+
+    /// Also computes each reference's coverage uniformity (`--coverage-uniformity`).
+    pub fn with_coverage_uniformity(mut self, coverage_uniformity: bool) -> Self {
+        self.coverage_uniformity = coverage_uniformity;
+        self
+    }
+
+    pub fn input_kmer_counts(&self) -> &HashMap<u64, usize> {
+        &self.input_kmer_counts
+    }
+
+    /// Classifies the input against the references of an in-memory database. The result's
+    /// `database_path` is left empty for the caller to fill in.
+    pub fn classify_against(&self, kmer_db: &KmerDbV2) -> DatabaseClassificationResult {
+        self.classify_database(kmer_db, String::new(), ReferenceContext::default()).0
+    }
+
+    // Also returns the input k-mers found in any reference of the database.
+    fn classify_database(
+        &self,
+        kmer_db_v2: &KmerDbV2,
+        db_path_str: String,
+        mut context: ReferenceContext,
+    ) -> (DatabaseClassificationResult, HashSet<u64>) {
+        let total_unique_input_kmers = self.input_kmer_counts.len();
+        let mut overall_matched_kmers_in_db_set: HashSet<u64> = HashSet::new();
+        let overall_sum_depth_for_db: usize;
+        let mut per_reference_results: Vec<ReferenceClassificationResult> = Vec::new();
+        let mut filtered_out_references: Vec<ReferenceClassificationResult> = Vec::new();
+        // Number of references containing each k-mer, for --weight-by-uniqueness
+        let owned_occurrences = (self.weight_by_uniqueness && context.kmer_occurrences.is_none())
+            .then(|| kmer_db_v2.reference_occurrence_counts());
+        let kmer_occurrences = self
+            .weight_by_uniqueness
+            .then(|| context.kmer_occurrences.or(owned_occurrences.as_ref()))
+            .flatten();
+
+        let references: Vec<(&str, ReferenceKmers)> = match context.mapped_index {
+            Some(index) => index
+                .references()
+                .map(|(name, kmers)| (name, ReferenceKmers::Mapped(kmers)))
+                .collect(),
+            None => kmer_db_v2
+                .references
+                .iter()
+                .map(|(name, kmer_set)| (name.as_str(), ReferenceKmers::InMemory(kmer_set)))
+                .collect(),
+        };
+
+        // With many in-memory references, attribute each input k-mer to all its references
+        // in a single pass over the input instead of one pass per reference
+        let mut indexed_matches: Option<Vec<(HashSet<u64>, usize)>> = None;
+        if uses_inverted_index(context.mapped_index.is_some(), references.len()) {
+            debug!("Using an inverted index for the {} references of {}", references.len(), db_path_str);
+            let owned_index;
+            let inverted_index = match context.inverted_index {
+                Some(inverted_index) => inverted_index,
+                None => {
+                    owned_index = kmer_db_v2.build_inverted_index();
+                    &owned_index
+                }
+            };
+            let mut matches = vec![(HashSet::new(), 0); references.len()];
+            for (input_kmer, input_count) in &self.input_kmer_counts {
+                if let Some(reference_ids) = inverted_index.get(input_kmer) {
+                    overall_matched_kmers_in_db_set.insert(*input_kmer);
+                    for &reference_id in reference_ids {
+                        let (matched_kmers, sum_depth) = &mut matches[reference_id as usize];
+                        matched_kmers.insert(*input_kmer);
+                        *sum_depth += input_count;
+                    }
+                }
+            }
+            indexed_matches = Some(matches);
+        }
+
+        let mut references_without_lengths = 0;
+        for (reference_id, (ref_name, ref_kmers)) in references.into_iter().enumerate() {
+            debug!("Processing reference: {} from {}", ref_name, db_path_str);
+            let (matched_kmers_for_ref_set, sum_depth_for_ref) = match &mut indexed_matches {
+                Some(matches) => std::mem::take(&mut matches[reference_id]),
+                None => {
+                    let mut matched_kmers_for_ref_set: HashSet<u64> = HashSet::new();
+                    let mut sum_depth_for_ref: usize = 0;
+                    for (input_kmer, input_count) in &self.input_kmer_counts {
+                        if ref_kmers.contains(*input_kmer) {
+                            matched_kmers_for_ref_set.insert(*input_kmer);
+                            sum_depth_for_ref += input_count;
+                            overall_matched_kmers_in_db_set.insert(*input_kmer); // Add to overall set for the DB
+                        }
+                    }
+                    (matched_kmers_for_ref_set, sum_depth_for_ref)
+                }
+            };
+
+            // Groups cover all their members, whether or not the reference is reported
+            if let Some(groups) = &mut context.groups
+                && let Some(group_name) = groups.reference_groups.get(ref_name)
+            {
+                let group = groups.accumulators.entry(group_name.clone()).or_default();
+                group.num_references += 1;
+                group.kmers.extend(ref_kmers.iter());
+                group.matched_kmers.extend(&matched_kmers_for_ref_set);
+            }
+
+            let num_matched_for_ref = matched_kmers_for_ref_set.len();
+            let total_kmers_in_ref = ref_kmers.len();
+
+            // The k-mer windows of a sampled reference are scaled by its sampling rate, as only
+            // that fraction of its k-mers can be matched
+            let lengths = kmer_db_v2.reference_lengths.get(ref_name);
+            let breadth_denominator = match (self.breadth_denominator, lengths) {
+                (BreadthDenominator::ReferenceLength, Some(lengths)) => {
+                    let rate = kmer_db_v2.sampling_rates.get(ref_name).copied().unwrap_or(1.0);
+                    lengths.kmer_windows(kmer_db_v2.k) as f64 * rate
+                }
+                (BreadthDenominator::ReferenceLength, None) => {
+                    references_without_lengths += 1;
+                    total_kmers_in_ref as f64
+                }
+                (BreadthDenominator::ReferenceKmers, _) => total_kmers_in_ref as f64,
+            };
+            let reference_breadth_of_coverage = if breadth_denominator > 0.0 {
+                num_matched_for_ref as f64 / breadth_denominator
+            } else {
+                0.0
+            };
+
+            let mut weighted_avg_depth = None;
+            let mut weighted_breadth = None;
+            if let Some(occurrences) = kmer_occurrences {
+                let kmer_weight = |kmer: &u64| 1.0 / occurrences[kmer] as f64;
+                let total_weight: f64 =
+                    ref_kmers.iter().map(|kmer| kmer_weight(&kmer)).sum();
+                let matched_weight: f64 =
+                    matched_kmers_for_ref_set.iter().map(kmer_weight).sum();
+                let weighted_depth_sum: f64 = matched_kmers_for_ref_set
+                    .iter()
+                    .map(|kmer| kmer_weight(kmer) * self.input_kmer_counts[kmer] as f64)
+                    .sum();
+                weighted_avg_depth = Some(if matched_weight > 0.0 {
+                    weighted_depth_sum / matched_weight
+                } else {
+                    0.0
+                });
+                weighted_breadth = Some(if total_weight > 0.0 {
+                    matched_weight / total_weight
+                } else {
+                    0.0
+                });
+            }
+
+            // Spatial where the reference's k-mer positions are stored, else over k-mer depths
+            let coverage_uniformity = self.coverage_uniformity.then(|| {
+                let depths = match kmer_db_v2.positions.get(ref_name) {
+                    Some(positions) => positions.coverage(kmer_db_v2.k, |key| {
+                        self.input_kmer_counts.get(&key).copied().unwrap_or(0) as u64
+                    }),
+                    None => matched_kmers_for_ref_set
+                        .iter()
+                        .map(|kmer| self.input_kmer_counts[kmer] as u64)
+                        .collect(),
+                };
+                gini_coefficient(depths).map_or(0.0, |gini| 1.0 - gini)
+            });
+
+            let reference_result = ReferenceClassificationResult {
+                reference_name: ref_name.to_string(),
+                total_kmers_in_reference: total_kmers_in_ref,
+                input_kmers_hitting_reference: num_matched_for_ref,
+                sum_depth_of_matched_kmers_in_input: sum_depth_for_ref,
+                avg_depth_of_matched_kmers_in_input: if num_matched_for_ref > 0 {
+                    sum_depth_for_ref as f64 / num_matched_for_ref as f64
+                } else {
+                    0.0
+                },
+                proportion_input_kmers_hitting_reference:
+                    if total_unique_input_kmers > 0 {
+                        num_matched_for_ref as f64
+                            / total_unique_input_kmers as f64
+                    } else {
+                        0.0
+                    },
+                reference_breadth_of_coverage,
+                weighted_avg_depth_of_matched_kmers_in_input: weighted_avg_depth,
+                weighted_reference_breadth_of_coverage: weighted_breadth,
+                kmer_sampling_rate: kmer_db_v2.sampling_rates.get(ref_name).copied(),
+                mean_per_read_coverage: context
+                    .per_read_coverage
+                    .map(|(per_read_coverage, db_idx)| per_read_coverage.mean(db_idx, reference_id)),
+                coverage_uniformity,
+            };
+
+            // A reference is reported only if it passes both the breadth and the hit-count filters
+            if reference_breadth_of_coverage >= self.min_coverage
+                && num_matched_for_ref >= self.min_reference_hits
+            {
+                per_reference_results.push(reference_result);
+            } else {
+                filtered_out_references.push(reference_result);
+            }
+        }
+
+        if references_without_lengths > 0 {
+            warn!(
+                "{} references of {} have no stored sequence lengths; their breadth of coverage is relative to their unique k-mers",
+                references_without_lengths, db_path_str
+            );
+        }
+
+        // Calculate sum of depths for overall_matched_kmers_in_db_set
+        // This needs to iterate input_kmer_counts again, specifically for k-mers in overall_matched_kmers_in_db_set
+        overall_sum_depth_for_db = overall_matched_kmers_in_db_set
+            .iter()
+            .map(|kmer| self.input_kmer_counts.get(kmer).copied().unwrap_or(0))
+            .sum();
+
+        let num_overall_matched_kmers = overall_matched_kmers_in_db_set.len();
+        let total_kmers_in_db_union = match context.mapped_index {
+            Some(index) => index.unified_kmers().len(),
+            None => kmer_db_v2.total_unique_kmers(),
+        };
+
+        let db_result = DatabaseClassificationResult {
+            database_path: db_path_str,
+            database_kmer_size: kmer_db_v2.k,
+            total_unique_kmers_in_db_across_references: total_kmers_in_db_union,
+            overall_input_kmers_matched_in_db: num_overall_matched_kmers,
+            overall_sum_depth_of_matched_kmers_in_input: overall_sum_depth_for_db,
+            overall_avg_depth_of_matched_kmers_in_input: if num_overall_matched_kmers > 0 {
+                overall_sum_depth_for_db as f64 / num_overall_matched_kmers as f64
+            } else {
+                0.0
+            },
+            proportion_input_kmers_in_db_overall: if total_unique_input_kmers
+                > 0
+            {
+                num_overall_matched_kmers as f64
+                    / total_unique_input_kmers as f64
+            } else {
+                0.0
+            },
+            proportion_db_kmers_covered_overall: if total_kmers_in_db_union > 0 {
+                num_overall_matched_kmers as f64 / total_kmers_in_db_union as f64
+            } else {
+                0.0
+            },
+            references: per_reference_results,
+            filtered_out_references,
+        };
+        (db_result, overall_matched_kmers_in_db_set)
+    }
+}
+
+// Coverage of a --groups group: its member references' k-mers taken together.
 #[derive(Serialize, Debug)]
-struct ReferenceClassificationResult {
-    reference_name: String,
-    total_kmers_in_reference: usize,
-    input_kmers_hitting_reference: usize,
+struct GroupClassificationResult {
+    group_name: String,
+    num_references: usize, // Member references found in the databases
+    total_kmers_in_group: usize, // Union of the member references' k-mers
+    input_kmers_hitting_group: usize,
     sum_depth_of_matched_kmers_in_input: usize,
-    avg_depth_of_matched_kmers_in_input: f64, // (sum_depth / input_kmers_hitting_reference)
-    proportion_input_kmers_hitting_reference: f64, // (input_kmers_hitting_reference / total_unique_input_kmers)
-    reference_breadth_of_coverage: f64, // (input_kmers_hitting_reference / total_kmers_in_reference)
+    avg_depth_of_matched_kmers_in_input: f64, // (sum_depth / input_kmers_hitting_group)
+    group_breadth_of_coverage: f64, // (input_kmers_hitting_group / total_kmers_in_group)
 }
 
+// Classification of one input record on its own, for --per-input-record.
 #[derive(Serialize, Debug)]
-struct DatabaseClassificationResult {
-    database_path: String,
-    database_kmer_size: u8,
-    total_unique_kmers_in_db_across_references: usize,
-    overall_input_kmers_matched_in_db: usize,
-    overall_sum_depth_of_matched_kmers_in_input: usize,
-    overall_avg_depth_of_matched_kmers_in_input: f64,
-    proportion_input_kmers_in_db_overall: f64, // (overall_input_kmers_matched_in_db / total_unique_input_kmers)
-    proportion_db_kmers_covered_overall: f64, // (overall_input_kmers_matched_in_db / total_unique_kmers_in_db_across_references)
-    references: Vec<ReferenceClassificationResult>,
+struct RecordClassificationResult {
+    record_id: String,
+    total_unique_kmers_in_record: usize, // After --min-kmer-frequency, applied to the record's own counts
+    databases_analyzed: Vec<DatabaseClassificationResult>,
+}
+
+// K-mers gathered for one group while classifying its member references.
+#[derive(Default)]
+struct GroupAccumulator {
+    num_references: usize,
+    kmers: HashSet<u64>,
+    matched_kmers: HashSet<u64>,
 }
 
+// Fields of the classification JSON written ahead of `databases_analyzed` and `groups`,
+// which ClassificationJsonWriter streams one element at a time.
 #[derive(Serialize, Debug)]
 struct ClassificationOutput {
     input_file_path: String,
     total_unique_kmers_in_input: usize,
     min_kmer_frequency_filter: usize,
-    databases_analyzed: Vec<DatabaseClassificationResult>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    input_reads_filtered: Option<usize>, // Reads skipped by --min-read-length/--min-mean-qual, if set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    input_reads_too_short_to_trim: Option<usize>, // Reads shorter than --trim-front plus --trim-back, if set
+    input_reads_shorter_than_k: usize, // Reads with no k-mer, skipped
+    input_total_bases: u64, // Bases of the reads passing the read filters, before trimming
+    input_ambiguous_bases: u64, // Those of them that were N or another non-ACGT character
+    #[serde(skip_serializing_if = "Option::is_none")]
+    invalid_windows_skipped: Option<usize>, // Windows with non-ACGT characters, with --report-invalid
+    #[serde(skip_serializing_if = "Option::is_none")]
+    total_unique_kmers_in_input_by_k: Option<BTreeMap<u8, usize>>, // Every database k, with --allow-mixed-k
+}
+
+// Writes the classification JSON incrementally, so only one database's results are held at
+// a time. The output is byte-for-byte what `to_writer_pretty` gives for the whole document.
+struct ClassificationJsonWriter {
+    writer: OutputWriter,
+    path: PathBuf,
+    databases_written: usize,
+}
+
+// Pretty-prints `value` for nesting at `indent` levels (two spaces each) inside a
+// pretty-printed document. Strings are escaped, so every newline starts a new JSON line.
+fn to_nested_pretty_json<T: Serialize>(value: &T, indent: usize) -> serde_json::Result<String> {
+    let pretty = serde_json::to_string_pretty(value)?;
+    Ok(pretty.replace('\n', &format!("\n{}", "  ".repeat(indent))))
+}
+
+impl ClassificationJsonWriter {
+    fn create(path: &Path, header: &ClassificationOutput) -> Result<Self> {
+        let mut writer = get_output_writer(path)
+            .with_context(|| format!("Failed to get output writer for JSON file: {:?}", path))?;
+        let header_json = serde_json::to_string_pretty(header)?;
+        // Reopen the header object to append the streamed array
+        let header_fields = header_json.strip_suffix("\n}").unwrap_or(&header_json);
+        write!(writer, "{},\n  \"databases_analyzed\": [", header_fields)
+            .with_context(|| format!("Failed to write classification JSON to {:?}", path))?;
+        Ok(ClassificationJsonWriter {
+            writer,
+            path: path.to_path_buf(),
+            databases_written: 0,
+        })
+    }
+
+    fn write_database(&mut self, db_result: &DatabaseClassificationResult) -> Result<()> {
+        let separator = if self.databases_written == 0 { "" } else { "," };
+        let element = to_nested_pretty_json(db_result, 2)?;
+        write!(self.writer, "{}\n    {}", separator, element)
+            .with_context(|| format!("Failed to write classification JSON to {:?}", self.path))?;
+        self.databases_written += 1;
+        Ok(())
+    }
+
+    fn finish(
+        mut self,
+        groups: Option<&[GroupClassificationResult]>,
+        records: Option<&[RecordClassificationResult]>,
+    ) -> Result<()> {
+        let close_databases = if self.databases_written == 0 { "]" } else { "\n  ]" };
+        let groups_field = match groups {
+            Some(groups) => format!(",\n  \"groups\": {}", to_nested_pretty_json(&groups, 1)?),
+            None => String::new(),
+        };
+        let records_field = match records {
+            Some(records) => format!(",\n  \"records\": {}", to_nested_pretty_json(&records, 1)?),
+            None => String::new(),
+        };
+        write!(self.writer, "{}{}{}\n}}", close_databases, groups_field, records_field)
+            .with_context(|| format!("Failed to write classification JSON to {:?}", self.path))?;
+        finalize_writer(self.writer).context("Failed to finish JSON output writer")
+    }
+}
+
+// Reads the --groups TSV: `<reference name>\t<group>` per line. Blank lines and lines starting
+// with '#' are ignored.
+fn load_reference_groups(path: &Path) -> Result<HashMap<String, String>> {
+    let reader = get_decompressed_input_reader(path)
+        .with_context(|| format!("Failed to open reference groups file: {:?}", path))?;
+    let mut groups = HashMap::new();
+    for (line_index, line) in reader.lines().enumerate() {
+        let line = line.with_context(|| format!("Failed to read reference groups file: {:?}", path))?;
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (reference_name, group_name) = line
+            .split_once('\t')
+            .filter(|(reference_name, group_name)| !reference_name.is_empty() && !group_name.is_empty())
+            .ok_or_else(|| {
+                OrionKmerError::FileParsingError(format!(
+                    "{:?} line {}: expected `<reference>\\t<group>`, got '{}'",
+                    path,
+                    line_index + 1,
+                    line
+                ))
+            })?;
+        groups.insert(reference_name.to_string(), group_name.trim_end().to_string());
+    }
+    Ok(groups)
+}
+
+// Resolves the JSON and summary TSV paths: -o and --output-tsv, or files named after the input
+// in --output-dir (created by run_classify, not here, so --dry-run leaves no trace).
+pub(crate) fn classify_output_paths(args: &ClassifyArgs) -> Result<(PathBuf, Option<PathBuf>)> {
+    let Some(output_dir) = &args.output_dir else {
+        let json_path = args.output_file.clone().ok_or_else(|| {
+            OrionKmerError::Generic("Either -o or --output-dir must be given".to_string())
+        })?;
+        return Ok((json_path, args.output_tsv.clone()));
+    };
+    // clap requires at least one -i file
+    let first_input = &args.input_files[0];
+    let file_name = first_input.file_name().map_or_else(
+        || first_input.to_string_lossy().into_owned(),
+        |name| name.to_string_lossy().into_owned(),
+    );
+    let stem = strip_sequence_extensions(&file_name);
+    Ok((
+        output_dir.join(format!("{}.classify.json", stem)),
+        Some(output_dir.join(format!("{}.classify.tsv", stem))),
+    ))
 }
 
 // --- Main Logic ---
 
+// Splits a --coverage-track `<ref_name>:<path>` value.
+pub(crate) fn parse_coverage_track(spec: &str) -> Result<(String, PathBuf)> {
+    parse_reference_output("--coverage-track", spec)
+}
+
+// Splits a --depth-track `<ref_name>:<path>` value.
+pub(crate) fn parse_depth_track(spec: &str) -> Result<(String, PathBuf)> {
+    parse_reference_output("--depth-track", spec)
+}
+
+// Splits a --extract-reads `<ref_name>:<path>` value.
+pub(crate) fn parse_extract_reads(spec: &str) -> Result<(String, PathBuf)> {
+    parse_reference_output("--extract-reads", spec)
+}
+
+fn parse_reference_output(option: &str, spec: &str) -> Result<(String, PathBuf)> {
+    match spec.split_once(':') {
+        Some((ref_name, path)) if !ref_name.is_empty() && !path.is_empty() => {
+            Ok((ref_name.to_string(), PathBuf::from(path)))
+        }
+        _ => Err(OrionKmerError::Generic(format!(
+            "{} expects <ref_name>:<path>, got '{}'",
+            option, spec
+        ))
+        .into()),
+    }
+}
+
+// --per-read-normalize: for each database and reference, the fraction of each read's k-mer
+// windows found in the reference, summed over the reads with at least one valid window.
+struct PerReadCoverage<'a> {
+    references: Vec<Vec<ReferenceKmers<'a>>>,
+    fraction_sums: Vec<Vec<f64>>,
+    reads: usize,
+}
+
+impl<'a> PerReadCoverage<'a> {
+    fn new(databases: &'a [KmerDbV2], mapped_indexes: &'a [Option<SortedKmerMmap>]) -> Self {
+        // References in the order the classification loop enumerates them
+        let references: Vec<Vec<ReferenceKmers>> = databases
+            .iter()
+            .zip(mapped_indexes)
+            .map(|(kmer_db, mapped_index)| match mapped_index {
+                Some(index) => index.references().map(|(_, kmers)| ReferenceKmers::Mapped(kmers)).collect(),
+                None => kmer_db.references.values().map(ReferenceKmers::InMemory).collect(),
+            })
+            .collect();
+        let fraction_sums = references.iter().map(|db_refs| vec![0.0; db_refs.len()]).collect();
+        PerReadCoverage {
+            references,
+            fraction_sums,
+            reads: 0,
+        }
+    }
+
+    fn add_read(&mut self, read_keys: &[u64]) {
+        if read_keys.is_empty() {
+            return;
+        }
+        self.reads += 1;
+        for (db_refs, sums) in self.references.iter().zip(&mut self.fraction_sums) {
+            for (ref_kmers, sum) in db_refs.iter().zip(sums.iter_mut()) {
+                let hits = read_keys.iter().filter(|&&key| ref_kmers.contains(key)).count();
+                *sum += hits as f64 / read_keys.len() as f64;
+            }
+        }
+    }
+
+    fn mean(&self, db_idx: usize, reference_id: usize) -> f64 {
+        if self.reads == 0 {
+            0.0
+        } else {
+            self.fraction_sums[db_idx][reference_id] / self.reads as f64
+        }
+    }
+}
+
+// --extract-reads: writes each input record with at least one k-mer in `kmers`, the key set of
+// one reference, unchanged (FASTQ records keep their qualities).
+struct ReadExtractor<'a> {
+    ref_name: String,
+    path: PathBuf,
+    kmers: &'a HashSet<u64>,
+    writer: OutputWriter,
+    reads: usize,
+}
+
+impl<'a> ReadExtractor<'a> {
+    fn create(databases: &'a [KmerDbV2], ref_name: String, path: PathBuf) -> Result<Self> {
+        let kmers = databases
+            .iter()
+            .find_map(|kmer_db| kmer_db.references.get(&ref_name))
+            .ok_or_else(|| {
+                OrionKmerError::Generic(format!("Reference '{}' for --extract-reads is not in any database", ref_name))
+            })?;
+        let writer = get_output_writer(&path)
+            .with_context(|| format!("Failed to get output writer for extracted reads: {:?}", path))?;
+        Ok(ReadExtractor {
+            ref_name,
+            path,
+            kmers,
+            writer,
+            reads: 0,
+        })
+    }
+
+    fn write_record(&mut self, record: &SequenceRecord) -> Result<()> {
+        record
+            .write(&mut self.writer, None)
+            .with_context(|| format!("Failed to write extracted read to {:?}", self.path))?;
+        self.reads += 1;
+        Ok(())
+    }
+
+    fn finish(self) -> Result<()> {
+        info!(
+            "Wrote {} reads matching reference '{}' to {:?}",
+            self.reads, self.ref_name, self.path
+        );
+        finalize_writer(self.writer).context("Failed to finish extracted reads writer")?;
+        Ok(())
+    }
+}
+
+// Finds the k-mer positions stored for `ref_name` by a `build --per-record` database.
+fn find_reference_positions<'a>(
+    databases: &'a [KmerDbV2],
+    ref_name: &str,
+    option: &str,
+) -> Result<&'a ReferencePositions> {
+    if let Some(positions) = databases.iter().find_map(|kmer_db| kmer_db.positions.get(ref_name)) {
+        return Ok(positions);
+    }
+    let message = if databases.iter().any(|kmer_db| kmer_db.references.contains_key(ref_name)) {
+        format!(
+            "Reference '{}' has no stored k-mer positions; rebuild it with `build --per-record` to use {}",
+            ref_name, option
+        )
+    } else {
+        format!("Reference '{}' for {} is not in any database", ref_name, option)
+    };
+    Err(OrionKmerError::Generic(message).into())
+}
+
+// Writes per-base values of one reference as bedGraph, merging runs of equal value.
+fn write_bedgraph(path: &Path, ref_name: &str, values: &[u64]) -> Result<()> {
+    let mut writer = get_output_writer(path)
+        .with_context(|| format!("Failed to get output writer for bedGraph file: {:?}", path))?;
+    let mut start = 0;
+    while start < values.len() {
+        let value = values[start];
+        let end = start + values[start..].iter().take_while(|&&v| v == value).count();
+        writeln!(writer, "{}\t{}\t{}\t{}", ref_name, start, end, value)?;
+        start = end;
+    }
+    finalize_writer(writer).context("Failed to finish bedGraph writer")?;
+    Ok(())
+}
+
 pub fn run_classify(args: ClassifyArgs) -> Result<()> {
-    eprintln!(
-        "DEBUG: Entered run_classify. Input file: {:?}, Num DBs: {}, Output: {:?}",
-        args.input_file,
+    debug!(
+        "Entered run_classify. Input files: {:?}, Num DBs: {}, Output: {:?}",
+        args.input_files,
         args.database_files.len(),
         args.output_file
-    ); // Basic entry print
+    );
     info!("Starting classify command with args: {:?}", args);
+    let (json_path, tsv_path) = classify_output_paths(&args)?;
+    if let Some(output_dir) = &args.output_dir {
+        fs::create_dir_all(output_dir)
+            .with_context(|| format!("Failed to create output directory: {:?}", output_dir))?;
+    }
+    let timer = PhaseTimer::new("classify");
+    let load_phase = timer.phase("load databases");
 
     // --- 1. Load databases and determine/validate k ---
     let mut loaded_databases: Vec<KmerDbV2> = Vec::new();
+    // With --mmap, loaded_databases only holds metadata and the k-mers stay in these indexes
+    let mut mapped_indexes: Vec<Option<SortedKmerMmap>> = Vec::new();
+    let reference_selection = ReferenceSelection::new(&args.only_reference, &args.exclude_reference);
+    let mut found_references = HashSet::new();
     let mut final_k: Option<u8> = None;
 
     if let Some(user_k) = args.kmer_size {
@@ -76,11 +802,22 @@ pub fn run_classify(args: ClassifyArgs) -> Result<()> {
 
     for (_i, db_path) in args.database_files.iter().enumerate() {
         // Changed i to _i
-        let kmer_db = load_kmer_db_v2(db_path)
-            .with_context(|| format!("Failed to load database: {:?}", db_path))?;
+        let (kmer_db, mapped_index) = if args.mmap {
+            check_blocklist_applies("--mmap")?;
+            let index = SortedKmerMmap::open(db_path)
+                .with_context(|| format!("Failed to memory-map database: {:?}", db_path))?;
+            (index.metadata(), Some(index))
+        } else {
+            let mut kmer_db = load_kmer_db_v2_for_k(db_path, args.kmer_size)
+                .with_context(|| format!("Failed to load database: {:?}", db_path))?;
+            reference_selection.apply(&mut kmer_db, &mut found_references);
+            (kmer_db, None)
+        };
+        check_canonical_lookup(&kmer_db, db_path, args.strict)?;
 
         if let Some(current_k) = final_k {
-            if kmer_db.k != current_k {
+            // With --allow-mixed-k, databases of another k get a profile of their own
+            if kmer_db.k != current_k && !args.allow_mixed_k {
                 if args.kmer_size.is_some() {
                     // Mismatch against user-provided k
                     return Err(OrionKmerError::KmerSizeMismatchValidation(
@@ -111,8 +848,54 @@ pub fn run_classify(args: ClassifyArgs) -> Result<()> {
                 kmer_db.k, db_path
             );
         }
+        // All databases must store k-mers the same way (raw encodings or the same hashing, and the
+        // same canonical policy)
+        if let Some(first_db) = loaded_databases.first()
+            && first_db.hashing != kmer_db.hashing
+        {
+            return Err(OrionKmerError::HashingMismatch(
+                first_db.hashing,
+                kmer_db.hashing,
+                db_path.clone(),
+            )
+            .into());
+        }
+        if let Some(first_db) = loaded_databases.first() {
+            check_canonical_policy(first_db, &kmer_db, db_path)?;
+        }
         loaded_databases.push(kmer_db);
+        mapped_indexes.push(mapped_index);
+    }
+    reference_selection.check_found(&found_references)?;
+    let coverage_tracks: Vec<(String, PathBuf)> = args
+        .coverage_track
+        .iter()
+        .map(|spec| parse_coverage_track(spec))
+        .collect::<Result<_>>()?;
+    let depth_tracks: Vec<(String, PathBuf)> = args
+        .depth_track
+        .iter()
+        .map(|spec| parse_depth_track(spec))
+        .collect::<Result<_>>()?;
+    // Fail before reading the input if a track cannot be produced
+    for (ref_name, _) in &coverage_tracks {
+        find_reference_positions(&loaded_databases, ref_name, "--coverage-track")?;
     }
+    for (ref_name, _) in &depth_tracks {
+        find_reference_positions(&loaded_databases, ref_name, "--depth-track")?;
+    }
+    let mut read_extractors: Vec<ReadExtractor> = args
+        .extract_reads
+        .iter()
+        .map(|spec| {
+            let (ref_name, path) = parse_extract_reads(spec)?;
+            ReadExtractor::create(&loaded_databases, ref_name, path)
+        })
+        .collect::<Result<_>>()?;
+
+    let mut per_read_coverage = args
+        .per_read_normalize
+        .then(|| PerReadCoverage::new(&loaded_databases, &mapped_indexes));
 
     let k = match final_k {
         Some(k_val) => {
@@ -130,57 +913,162 @@ pub fn run_classify(args: ClassifyArgs) -> Result<()> {
         }
     };
     info!("Processing with effective k-mer size: {}", k);
+    // With --allow-mixed-k, the input k-mers of every other database k, counted with (and keyed
+    // like) the first database of that k
+    let mut mixed_k_profiles: Vec<(usize, HashMap<u64, usize>)> = Vec::new();
+    for (idx, kmer_db) in loaded_databases.iter().enumerate() {
+        if kmer_db.k != k && !mixed_k_profiles.iter().any(|&(first_idx, _)| loaded_databases[first_idx].k == kmer_db.k) {
+            mixed_k_profiles.push((idx, HashMap::new()));
+        }
+    }
+    if !mixed_k_profiles.is_empty() {
+        let other_ks: Vec<u8> = mixed_k_profiles.iter().map(|&(idx, _)| loaded_databases[idx].k).collect();
+        info!("--allow-mixed-k: also counting input k-mers at k = {:?}", other_ks);
+    }
 
-    // --- 2. Process input file: count k-mers ---
+    drop(load_phase);
+
+    // --- 2. Process input files: count k-mers, pooled over all of them ---
     let mut input_kmer_counts: HashMap<u64, usize> = HashMap::new();
-    let input_file_path_str = args.input_file.to_string_lossy().into_owned();
+    let input_file_path_str = args
+        .input_files
+        .iter()
+        .map(|path| path.to_string_lossy().into_owned())
+        .collect::<Vec<_>>()
+        .join(",");
+    let read_filter = ReadFilter {
+        min_length: args.min_read_length,
+        min_mean_qual: args.min_mean_qual,
+    };
+    let trim_polyx_base = args.trim_polyx.map(polyx_base).transpose()?;
+    let mut filtered_reads: usize = 0;
+    let mut invalid_windows: usize = 0;
+    let mut too_short_to_trim: usize = 0;
+    let mut short_records: usize = 0;
+    let mut processed_records: usize = 0;
+    let mut input_bases = BaseCounts::default();
+    // With --per-input-record, each record's ID and k-mer counts
+    let mut record_kmer_counts: Vec<(String, HashMap<u64, usize>)> = Vec::new();
 
+    // Without --prescan the record count is unknown and the bar is a spinner (length 0)
+    let total_records = if args.prescan {
+        let mut total_records = Some(0);
+        for input_file in &args.input_files {
+            let file_records = timer.measure("parse", || prescan_record_count(input_file))?;
+            match file_records {
+                Some(file_records) => info!("Prescan found {} records in {:?}", file_records, input_file),
+                None => info!(
+                    "Skipping --prescan for {:?}: it is not a regular file that can be read twice",
+                    input_file
+                ),
+            }
+            total_records = total_records.zip(file_records).map(|(total, file)| total + file);
+        }
+        total_records
+    } else {
+        None
+    };
     track_progress_and_resources(
-        &format!("Processing input file: {}", input_file_path_str),
-        0, // 0 for indeterminate progress bar (spinner style) as we don't know total records easily
+        &format!("Processing input files: {}", input_file_path_str),
+        total_records.unwrap_or(0),
         |pb_input| {
-            // Use get_buffered_file_reader, needletail will handle decompression
-            let input_buf_reader = get_buffered_file_reader(&args.input_file).with_context(|| {
-                format!(
-                    "Failed to get buffered file reader for file: {:?}",
-                    args.input_file
-                )
-            })?;
-            // Pass the BufRead to parse_fastx_reader
-            let mut reader = parse_fastx_reader(input_buf_reader).with_context(|| {
-                format!(
-                    "Failed to parse FASTA/Q content from: {:?}",
-                    args.input_file
-                )
-            })?;
+            for input_file in &args.input_files {
+                let mut file_bases = BaseCounts::default();
+                let mut reader = open_sequence_reader(input_file)?;
 
-            let mut processed_records = 0;
-            while let Some(record) = reader.next() {
-                let record = record.with_context(|| {
-                    format!(
-                        "Error reading record from input file: {:?}",
-                        args.input_file
-                    )
-                })?;
-                let norm_seq = record.normalize(false);
-
-                if norm_seq.len() >= k as usize {
-                    for window in norm_seq.windows(k as usize) {
-                        if let Some(kmer_val) = seq_to_u64(window, k) {
-                            let canonical_kmer = canonical_u64(kmer_val, k);
-                            *input_kmer_counts.entry(canonical_kmer).or_insert(0) += 1;
+                while let Some(record) = timer.measure("parse", || reader.next()) {
+                    if !take_input_record() {
+                        break;
+                    }
+                    let record = record.with_context(|| {
+                        format!(
+                            "Error reading record from input file: {:?}",
+                            input_file
+                        )
+                    })?;
+                    if total_records.is_some() {
+                        pb_input.inc(1);
+                    }
+                    if !read_filter.passes(&record.seq(), record.qual()) {
+                        filtered_reads += 1;
+                        continue;
+                    }
+                    if !args.allow_non_dna {
+                        check_dna_record(record.raw_seq(), record.id(), &format!("{:?}", input_file))?;
+                    }
+                    let norm_seq = timer.measure("k-mer extraction", || record.normalize(false));
+                    file_bases.add_sequence(&norm_seq);
+                    let Some(trimmed_seq) = trim_read(&norm_seq, args.trim_front, args.trim_back) else {
+                        too_short_to_trim += 1;
+                        continue;
+                    };
+                    let trimmed_seq = match trim_polyx_base {
+                        Some(base) => trim_polyx(trimmed_seq, base, args.polyx_min_run),
+                        None => trimmed_seq,
+                    };
+                    processed_records += 1;
+                    for (first_idx, counts) in &mut mixed_k_profiles {
+                        let kmer_db = &loaded_databases[*first_idx];
+                        timer.measure("k-mer extraction", || {
+                            for window in trimmed_seq.windows(kmer_db.k as usize) {
+                                if let Some(kmer_val) = seq_to_u64(window, kmer_db.k) {
+                                    *counts.entry(kmer_db.lookup_key(kmer_db.canonicalize(kmer_val))).or_insert(0) += 1;
+                                }
+                            }
+                        });
+                    }
+                    if trimmed_seq.len() < k as usize {
+                        short_records += 1;
+                        continue;
+                    }
+                    // Which --extract-reads references this record has a k-mer in
+                    let mut extract_record = vec![false; read_extractors.len()];
+                    // With --per-read-normalize, the keys of the read's valid windows
+                    let mut read_keys: Vec<u64> = Vec::new();
+                    let mut record_counts: HashMap<u64, usize> = HashMap::new();
+                    timer.measure("k-mer extraction", || {
+                        for window in trimmed_seq.windows(k as usize) {
+                            if let Some(kmer_val) = seq_to_u64(window, k) {
+                                let canonical_kmer = loaded_databases[0].canonicalize(kmer_val);
+                                let key = loaded_databases[0].lookup_key(canonical_kmer);
+                                *input_kmer_counts.entry(key).or_insert(0) += 1;
+                                for (matched, extractor) in extract_record.iter_mut().zip(&read_extractors) {
+                                    *matched |= extractor.kmers.contains(&key);
+                                }
+                                if per_read_coverage.is_some() {
+                                    read_keys.push(key);
+                                }
+                                if args.per_input_record {
+                                    *record_counts.entry(key).or_insert(0) += 1;
+                                }
+                            } else {
+                                invalid_windows += 1;
+                            }
                         }
+                    });
+                    if let Some(per_read_coverage) = &mut per_read_coverage {
+                        per_read_coverage.add_read(&read_keys);
+                    }
+                    if args.per_input_record {
+                        let record_id = String::from_utf8_lossy(record.id()).trim().to_string();
+                        record_counts.retain(|_, count| *count >= args.min_kmer_frequency);
+                        record_kmer_counts.push((record_id, record_counts));
+                    }
+                    for (extractor, matched) in read_extractors.iter_mut().zip(extract_record) {
+                        if matched {
+                            extractor.write_record(&record)?;
+                        }
+                    }
+                    if is_progress_record(processed_records) {
+                        // Update progress bar message periodically
+                        pb_input.set_message(format!("Processed {} records...", processed_records));
                     }
                 }
-                processed_records += 1;
-                if processed_records % 100_000 == 0 {
-                    // Update progress bar message periodically
-                    pb_input.set_message(format!("Processed {} records...", processed_records));
-                }
-                // We don't call pb_input.inc() here as length is 0 (spinner)
+                report_base_counts(&input_file.to_string_lossy(), &file_bases);
+                input_bases.add(&file_bases);
             }
             pb_input.set_message(format!(
-                "Processed {} total records from input file.",
+                "Processed {} total records from input files.",
                 processed_records
             ));
             Ok(())
@@ -191,9 +1079,31 @@ pub fn run_classify(args: ClassifyArgs) -> Result<()> {
         "Finished processing input file. Found {} unique k-mers with total occurrences before frequency filtering.",
         input_kmer_counts.len()
     );
+    report_short_records(&input_file_path_str, short_records, processed_records, k);
+    if args.input_files.len() > 1 {
+        report_base_counts("all input files", &input_bases);
+    }
+    if read_filter.is_active() {
+        info!(
+            "Skipped {} input reads failing --min-read-length/--min-mean-qual.",
+            filtered_reads
+        );
+    }
+    if args.trim_front > 0 || args.trim_back > 0 {
+        info!(
+            "Skipped {} input reads shorter than --trim-front plus --trim-back.",
+            too_short_to_trim
+        );
+    }
+    if args.report_invalid {
+        info!(
+            "Skipped {} input k-mer windows containing N or other non-ACGT characters.",
+            invalid_windows
+        );
+    }
 
     // Filter input_kmer_counts by min_kmer_frequency
-    let filtered_input_kmer_counts: HashMap<u64, usize> = input_kmer_counts
+    let mut filtered_input_kmer_counts: HashMap<u64, usize> = input_kmer_counts
         .into_iter()
         .filter(|&(_, count)| count >= args.min_kmer_frequency)
         .collect();
@@ -204,8 +1114,104 @@ pub fn run_classify(args: ClassifyArgs) -> Result<()> {
         args.min_kmer_frequency, total_unique_input_kmers_after_filter
     );
 
-    // --- 3. Perform classification ---
-    let mut db_results: Vec<DatabaseClassificationResult> = Vec::new();
+    if let Some(dump_path) = &args.dump_input_kmers {
+        info!("Writing filtered input k-mer counts to: {:?}", dump_path);
+        let count_table = KmerCountTable {
+            k,
+            counts: filtered_input_kmer_counts,
+        };
+        let mut dump_writer = get_output_writer(dump_path).with_context(|| {
+            format!(
+                "Failed to get output writer for input k-mer dump: {:?}",
+                dump_path
+            )
+        })?;
+        db_bincode_options().serialize_into(&mut dump_writer, &count_table).with_context(|| {
+            format!("Failed to serialize input k-mer counts to {:?}", dump_path)
+        })?;
+        finalize_writer(dump_writer).context("Failed to finish input k-mer dump writer")?;
+        filtered_input_kmer_counts = count_table.counts;
+    }
+    let new_classifier = |input_kmer_counts: HashMap<u64, usize>| {
+        Classifier::new(input_kmer_counts)
+            .with_min_coverage(args.min_coverage)
+            .with_min_reference_hits(args.min_reference_hits)
+            .with_weight_by_uniqueness(args.weight_by_uniqueness)
+            .with_breadth_denominator(args.breadth_denominator)
+            .with_coverage_uniformity(args.coverage_uniformity)
+    };
+    let classifier = new_classifier(filtered_input_kmer_counts);
+    let mixed_k_classifiers: Vec<(u8, Classifier)> = mixed_k_profiles
+        .into_iter()
+        .map(|(first_idx, mut counts)| {
+            counts.retain(|_, count| *count >= args.min_kmer_frequency);
+            (loaded_databases[first_idx].k, new_classifier(counts))
+        })
+        .collect();
+    let total_unique_kmers_in_input_by_k = (!mixed_k_classifiers.is_empty()).then(|| {
+        mixed_k_classifiers
+            .iter()
+            .map(|(mixed_k, mixed_k_classifier)| (*mixed_k, mixed_k_classifier.input_kmer_counts().len()))
+            .chain([(k, total_unique_input_kmers_after_filter)])
+            .collect::<BTreeMap<u8, usize>>()
+    });
+
+    let reference_groups = args
+        .groups
+        .as_deref()
+        .map(load_reference_groups)
+        .transpose()?;
+    let mut group_accumulators: BTreeMap<String, GroupAccumulator> = BTreeMap::new();
+
+    let classify_phase = timer.phase("set operations");
+
+    // --- 3. Perform classification, writing each database's results as they are computed ---
+    let header = ClassificationOutput {
+        input_file_path: input_file_path_str,
+        total_unique_kmers_in_input: total_unique_input_kmers_after_filter,
+        min_kmer_frequency_filter: args.min_kmer_frequency,
+        input_reads_filtered: read_filter.is_active().then_some(filtered_reads),
+        input_reads_too_short_to_trim: (args.trim_front > 0 || args.trim_back > 0).then_some(too_short_to_trim),
+        input_reads_shorter_than_k: short_records,
+        input_total_bases: input_bases.total_bases,
+        input_ambiguous_bases: input_bases.ambiguous_bases,
+        invalid_windows_skipped: args.report_invalid.then_some(invalid_windows),
+        total_unique_kmers_in_input_by_k,
+    };
+    info!("Writing classification results to: {:?}", json_path);
+    let mut json_writer = ClassificationJsonWriter::create(&json_path, &header)?;
+    let mut tsv_writers = Vec::new();
+    if let Some(tsv_path) = &tsv_path {
+        info!("Writing classification summary TSV to: {:?}", tsv_path);
+        tsv_writers.push(ReferenceTsvWriter::create(tsv_path, args.delimiter, false, args.weight_by_uniqueness, args.coverage_uniformity)?);
+    }
+    if let Some(full_tsv_path) = &args.full_tsv {
+        info!("Writing unfiltered per-reference TSV to: {:?}", full_tsv_path);
+        tsv_writers.push(ReferenceTsvWriter::create(full_tsv_path, args.delimiter, true, args.weight_by_uniqueness, args.coverage_uniformity)?);
+    }
+    let sqlite_writer = match &args.sqlite {
+        Some(sqlite_path) => {
+            info!("Writing classification results to SQLite database: {:?}", sqlite_path);
+            Some(ClassificationSqliteWriter::create(sqlite_path, &header, args.full_tsv.is_some())?)
+        }
+        None => None,
+    };
+    let mut krona_writer = match &args.krona {
+        Some(krona_path) => {
+            info!("Writing Krona input to: {:?}", krona_path);
+            Some(KronaWriter::create(krona_path, reference_groups.as_ref())?)
+        }
+        None => None,
+    };
+    // Input k-mers matched by any database, for the Krona Unclassified row
+    let mut kmers_matched_in_any_db: HashSet<u64> = HashSet::new();
+    let record_classifiers: Vec<(String, Classifier)> = record_kmer_counts
+        .into_iter()
+        .map(|(record_id, counts)| {
+            (record_id, new_classifier(counts))
+        })
+        .collect();
+    let mut record_results: Vec<Vec<DatabaseClassificationResult>> = (0..record_classifiers.len()).map(|_| Vec::new()).collect();
     let num_databases = loaded_databases.len() as u64;
 
     track_progress_and_resources(
@@ -217,137 +1223,223 @@ pub fn run_classify(args: ClassifyArgs) -> Result<()> {
                 info!("Classifying against database: {}", db_path_str);
                 pb_classify.set_message(format!("Classifying against: {}", db_path_str));
 
-                let mut overall_matched_kmers_in_db_set: HashSet<u64> = HashSet::new();
-                let overall_sum_depth_for_db: usize;
-                let mut per_reference_results: Vec<ReferenceClassificationResult> = Vec::new();
-
-                for (ref_name, ref_kmers_set) in &kmer_db_v2.references {
-                    debug!("Processing reference: {} from {}", ref_name, db_path_str);
-                    // ---- END DEBUG PRINT ----
-                    let mut matched_kmers_for_ref_set: HashSet<u64> = HashSet::new();
-                    let mut sum_depth_for_ref: usize = 0;
-
-                    for (input_kmer, input_count) in &filtered_input_kmer_counts {
-                        if ref_kmers_set.contains(input_kmer) {
-                            matched_kmers_for_ref_set.insert(*input_kmer);
-                            sum_depth_for_ref += input_count;
-                            overall_matched_kmers_in_db_set.insert(*input_kmer); // Add to overall set for the DB
-                        }
-                    }
-
-                    let num_matched_for_ref = matched_kmers_for_ref_set.len();
-                    let total_kmers_in_ref = ref_kmers_set.len();
-
-                    let reference_breadth_of_coverage = if total_kmers_in_ref > 0 {
-                        num_matched_for_ref as f64 / total_kmers_in_ref as f64
-                    } else {
-                        0.0
+                // Built once for the pooled input and every --per-input-record record
+                let shared_index = uses_inverted_index(mapped_indexes[idx].is_some(), kmer_db_v2.references.len())
+                    .then(|| kmer_db_v2.build_inverted_index());
+                let shared_occurrences = args
+                    .weight_by_uniqueness
+                    .then(|| kmer_db_v2.reference_occurrence_counts());
+                let reference_context = ReferenceContext {
+                    mapped_index: mapped_indexes[idx].as_ref(),
+                    inverted_index: shared_index.as_ref(),
+                    kmer_occurrences: shared_occurrences.as_ref(),
+                    per_read_coverage: per_read_coverage.as_ref().map(|per_read_coverage| (per_read_coverage, idx)),
+                    groups: reference_groups.as_ref().map(|reference_groups| GroupContext {
+                        reference_groups,
+                        accumulators: &mut group_accumulators,
+                    }),
+                };
+                let db_classifier = mixed_k_classifiers
+                    .iter()
+                    .find(|(mixed_k, _)| *mixed_k == kmer_db_v2.k)
+                    .map_or(&classifier, |(_, mixed_k_classifier)| mixed_k_classifier);
+                let (db_result, overall_matched_kmers_in_db_set) =
+                    db_classifier.classify_database(kmer_db_v2, db_path_str, reference_context);
+                json_writer.write_database(&db_result)?;
+                for tsv_writer in &mut tsv_writers {
+                    tsv_writer.write_database(&header.input_file_path, &db_result)?;
+                }
+                if let Some(sqlite_writer) = &sqlite_writer {
+                    sqlite_writer.write_database(&db_result)?;
+                }
+                if let Some(krona_writer) = &mut krona_writer {
+                    krona_writer.write_database(&db_result)?;
+                    kmers_matched_in_any_db.extend(&overall_matched_kmers_in_db_set);
+                }
+                for ((_, record_classifier), results) in record_classifiers.iter().zip(&mut record_results) {
+                    let record_context = ReferenceContext {
+                        mapped_index: mapped_indexes[idx].as_ref(),
+                        inverted_index: shared_index.as_ref(),
+                        kmer_occurrences: shared_occurrences.as_ref(),
+                        ..ReferenceContext::default()
                     };
-
-                    if reference_breadth_of_coverage >= args.min_coverage {
-                        per_reference_results.push(ReferenceClassificationResult {
-                            reference_name: ref_name.clone(),
-                            total_kmers_in_reference: total_kmers_in_ref,
-                            input_kmers_hitting_reference: num_matched_for_ref,
-                            sum_depth_of_matched_kmers_in_input: sum_depth_for_ref,
-                            avg_depth_of_matched_kmers_in_input: if num_matched_for_ref > 0 {
-                                sum_depth_for_ref as f64 / num_matched_for_ref as f64
-                            } else {
-                                0.0
-                            },
-                            proportion_input_kmers_hitting_reference:
-                                if total_unique_input_kmers_after_filter > 0 {
-                                    num_matched_for_ref as f64
-                                        / total_unique_input_kmers_after_filter as f64
-                                } else {
-                                    0.0
-                                },
-                            reference_breadth_of_coverage,
-                        });
-                    }
+                    let (record_result, _) =
+                        record_classifier.classify_database(kmer_db_v2, db_result.database_path.clone(), record_context);
+                    results.push(record_result);
                 }
+                pb_classify.inc(1); // Increment after processing each database
+            }
+            Ok(()) // Return Ok from the closure
+        },
+    )?;
 
-                // Calculate sum of depths for overall_matched_kmers_in_db_set
-                // This needs to iterate input_kmer_counts again, specifically for k-mers in overall_matched_kmers_in_db_set
-                overall_sum_depth_for_db = overall_matched_kmers_in_db_set
+    let groups: Option<Vec<GroupClassificationResult>> = reference_groups.is_some().then(|| {
+        group_accumulators
+            .into_iter()
+            .map(|(group_name, group)| {
+                let num_matched = group.matched_kmers.len();
+                let sum_depth: usize = group
+                    .matched_kmers
                     .iter()
-                    .map(|kmer| filtered_input_kmer_counts.get(kmer).copied().unwrap_or(0))
+                    .map(|kmer| classifier.input_kmer_counts()[kmer])
                     .sum();
-
-                let num_overall_matched_kmers = overall_matched_kmers_in_db_set.len();
-                // ---- END DEBUG PRINT ----
-                let total_kmers_in_db_union = kmer_db_v2.total_unique_kmers();
-
-                db_results.push(DatabaseClassificationResult {
-                    database_path: db_path_str,
-                    database_kmer_size: kmer_db_v2.k,
-                    total_unique_kmers_in_db_across_references: total_kmers_in_db_union,
-                    overall_input_kmers_matched_in_db: num_overall_matched_kmers,
-                    overall_sum_depth_of_matched_kmers_in_input: overall_sum_depth_for_db,
-                    overall_avg_depth_of_matched_kmers_in_input: if num_overall_matched_kmers > 0 {
-                        overall_sum_depth_for_db as f64 / num_overall_matched_kmers as f64
+                GroupClassificationResult {
+                    group_name,
+                    num_references: group.num_references,
+                    total_kmers_in_group: group.kmers.len(),
+                    input_kmers_hitting_group: num_matched,
+                    sum_depth_of_matched_kmers_in_input: sum_depth,
+                    avg_depth_of_matched_kmers_in_input: if num_matched > 0 {
+                        sum_depth as f64 / num_matched as f64
                     } else {
                         0.0
                     },
-                    proportion_input_kmers_in_db_overall: if total_unique_input_kmers_after_filter
-                        > 0
-                    {
-                        num_overall_matched_kmers as f64
-                            / total_unique_input_kmers_after_filter as f64
-                    } else {
+                    group_breadth_of_coverage: if group.kmers.is_empty() {
                         0.0
-                    },
-                    proportion_db_kmers_covered_overall: if total_kmers_in_db_union > 0 {
-                        num_overall_matched_kmers as f64 / total_kmers_in_db_union as f64
                     } else {
-                        0.0
+                        num_matched as f64 / group.kmers.len() as f64
                     },
-                    references: per_reference_results,
-                });
-                pb_classify.inc(1); // Increment after processing each database
-            }
-            Ok(()) // Return Ok from the closure
-        },
-    )?;
+                }
+            })
+            .collect()
+    });
 
-    // --- 4. Write output ---
-    let final_output = ClassificationOutput {
-        input_file_path: args.input_file.to_string_lossy().into_owned(),
-        total_unique_kmers_in_input: total_unique_input_kmers_after_filter,
-        min_kmer_frequency_filter: args.min_kmer_frequency,
-        databases_analyzed: db_results,
-    };
+    drop(classify_phase);
+    let serialize_phase = timer.phase("serialize");
 
-    info!("Writing classification results to: {:?}", args.output_file);
-    // Use get_output_writer for the main JSON output
-    let mut writer = get_output_writer(&args.output_file).with_context(|| {
-        format!(
-            "Failed to get output writer for JSON file: {:?}",
-            args.output_file
-        )
-    })?;
-    serde_json::to_writer_pretty(&mut writer, &final_output).with_context(|| {
-        format!(
-            "Failed to write classification JSON to {:?}",
-            args.output_file
-        )
-    })?;
-    writer.flush().context("Failed to flush JSON output writer")?;
+    // --- 4. Finish the JSON output with the group roll-up ---
+    let records: Option<Vec<RecordClassificationResult>> = args.per_input_record.then(|| {
+        record_classifiers
+            .into_iter()
+            .zip(record_results)
+            .map(|((record_id, record_classifier), databases_analyzed)| RecordClassificationResult {
+                record_id,
+                total_unique_kmers_in_record: record_classifier.input_kmer_counts().len(),
+                databases_analyzed,
+            })
+            .collect()
+    });
+    json_writer.finish(groups.as_deref(), records.as_deref())?;
+    info!("Classification results written to {:?}", json_path);
 
+    for extractor in read_extractors {
+        extractor.finish()?;
+    }
 
-    // --- 5. Optionally write TSV output ---
-    if let Some(tsv_path) = &args.output_tsv {
-        info!("Writing classification summary TSV to: {:?}", tsv_path);
+    // --- 5. Finish the TSV, SQLite and Krona outputs ---
+    for tsv_writer in tsv_writers {
+        tsv_writer.finish()?;
+    }
+    if let Some(sqlite_writer) = sqlite_writer {
+        sqlite_writer.finish()?;
+    }
+    if let Some(krona_writer) = krona_writer {
+        krona_writer.finish(total_unique_input_kmers_after_filter - kmers_matched_in_any_db.len())?;
+    }
+
+    // --- 6. Optionally write coverage tracks ---
+    for (ref_name, track_path) in &coverage_tracks {
+        let positions = find_reference_positions(&loaded_databases, ref_name, "--coverage-track")?;
+        // Each distinct input k-mer adds 1 to every base of every occurrence it matches
+        let coverage = positions.coverage(k, |key| classifier.input_kmer_counts().contains_key(&key) as u64);
+        info!("Writing coverage track for '{}' to: {:?}", ref_name, track_path);
+        write_bedgraph(track_path, ref_name, &coverage)?;
+    }
+    for (ref_name, track_path) in &depth_tracks {
+        let positions = find_reference_positions(&loaded_databases, ref_name, "--depth-track")?;
+        // Each reference k-mer occurrence gets the input count of its k-mer at its start
+        let depth = positions.start_depth(|key| classifier.input_kmer_counts().get(&key).copied().unwrap_or(0) as u64);
+        info!("Writing depth track for '{}' to: {:?}", ref_name, track_path);
+        write_bedgraph(track_path, ref_name, &depth)?;
+    }
+
+    drop(serialize_phase);
+    timer.report();
+    info!("Classification successfully completed.");
+    Ok(())
+}
+
+// Writes the --krona text input, a database at a time: one `count<TAB>level1<TAB>...` row per
+// reported reference with matched k-mers, counting its distinct matched input k-mers under the
+// levels of its --groups group (split at ';', or "Ungrouped"), and finally an Unclassified row
+// for the input k-mers that matched no database. Krona sums rows sharing a lineage, so k-mers
+// shared by several references count once for each of them.
+struct KronaWriter<'a> {
+    writer: OutputWriter,
+    path: PathBuf,
+    reference_groups: Option<&'a HashMap<String, String>>,
+}
+
+impl<'a> KronaWriter<'a> {
+    fn create(path: &Path, reference_groups: Option<&'a HashMap<String, String>>) -> Result<Self> {
+        let writer = get_output_writer(path)
+            .with_context(|| format!("Failed to get output writer for Krona file: {:?}", path))?;
+        Ok(KronaWriter {
+            writer,
+            path: path.to_path_buf(),
+            reference_groups,
+        })
+    }
+
+    fn write_database(&mut self, db_res: &DatabaseClassificationResult) -> Result<()> {
+        for ref_res in &db_res.references {
+            if ref_res.input_kmers_hitting_reference == 0 {
+                continue;
+            }
+            let mut lineage: Vec<&str> = Vec::new();
+            if let Some(groups) = self.reference_groups {
+                match groups.get(&ref_res.reference_name) {
+                    Some(group_name) => lineage.extend(group_name.split(';').map(str::trim)),
+                    None => lineage.push("Ungrouped"),
+                }
+            }
+            lineage.push(&ref_res.reference_name);
+            writeln!(self.writer, "{}\t{}", ref_res.input_kmers_hitting_reference, lineage.join("\t"))
+                .with_context(|| format!("Failed to write Krona row to {:?}", self.path))?;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self, unclassified_kmers: usize) -> Result<()> {
+        if unclassified_kmers > 0 {
+            writeln!(self.writer, "{}\tUnclassified", unclassified_kmers)
+                .with_context(|| format!("Failed to write Krona row to {:?}", self.path))?;
+        }
+        finalize_writer(self.writer).context("Failed to finish Krona output writer")
+    }
+}
+
+// Writes one TSV row per reported reference, a database at a time. With `include_filtered_out`,
+// references that failed the reporting filters are written as well and a PassedFilter column
+// is appended. `include_weighted` adds the --weight-by-uniqueness columns after the raw ones,
+// and `include_uniformity` the --coverage-uniformity column after those.
+struct ReferenceTsvWriter {
+    tsv_writer: csv::Writer<OutputWriter>,
+    include_filtered_out: bool,
+    include_weighted: bool,
+    include_uniformity: bool,
+}
+
+impl ReferenceTsvWriter {
+    fn create(
+        tsv_path: &Path,
+        delimiter: TableDelimiter,
+        include_filtered_out: bool,
+        include_weighted: bool,
+        include_uniformity: bool,
+    ) -> Result<Self> {
         // Use get_output_writer for the TSV output
-        let tsv_writer_boxed = get_output_writer(tsv_path).with_context(|| {
-            format!("Failed to get output writer for TSV file: {:?}", tsv_path)
-        })?;
+        let tsv_writer_boxed = get_output_writer(tsv_path)
+            .with_context(|| format!("Failed to get output writer for TSV file: {:?}", tsv_path))?;
         let mut tsv_writer = csv::WriterBuilder::new()
-            .delimiter(b'\t')
+            .delimiter(match delimiter {
+                TableDelimiter::Tab => b'\t',
+                TableDelimiter::Comma => b',',
+            })
             .from_writer(tsv_writer_boxed); // from_writer expects W: Write
 
         // Write header
-        tsv_writer.write_record(&[
+        let mut header = vec![
             "InputFile",
             "Database",
             "Reference",
@@ -357,29 +1449,129 @@ pub fn run_classify(args: ClassifyArgs) -> Result<()> {
             "AvgDepthMatchedKmers",
             "ProportionInputKmersHittingReference",
             "ReferenceBreadthOfCoverage",
-        ])?;
-
-        // Write data rows
-        for db_res in &final_output.databases_analyzed {
-            for ref_res in &db_res.references {
-                // Note: ref_res here are already filtered by min_coverage
-                tsv_writer.write_record(&[
-                    final_output.input_file_path.clone(),
-                    db_res.database_path.clone(),
-                    ref_res.reference_name.clone(),
-                    ref_res.total_kmers_in_reference.to_string(),
-                    ref_res.input_kmers_hitting_reference.to_string(),
-                    ref_res.sum_depth_of_matched_kmers_in_input.to_string(),
-                    format!("{:.4}", ref_res.avg_depth_of_matched_kmers_in_input),
-                    format!("{:.4}", ref_res.proportion_input_kmers_hitting_reference),
-                    format!("{:.4}", ref_res.reference_breadth_of_coverage),
-                ])?;
+        ];
+        if include_weighted {
+            header.push("WeightedAvgDepthMatchedKmers");
+            header.push("WeightedReferenceBreadthOfCoverage");
+        }
+        if include_uniformity {
+            header.push("CoverageUniformity");
+        }
+        if include_filtered_out {
+            header.push("PassedFilter");
+        }
+        tsv_writer.write_record(&header)?;
+        Ok(ReferenceTsvWriter {
+            tsv_writer,
+            include_filtered_out,
+            include_weighted,
+            include_uniformity,
+        })
+    }
+
+    fn write_database(&mut self, input_file_path: &str, db_res: &DatabaseClassificationResult) -> Result<()> {
+        let passed = db_res.references.iter().map(|ref_res| (ref_res, true));
+        let filtered_out = db_res
+            .filtered_out_references
+            .iter()
+            .filter(|_| self.include_filtered_out)
+            .map(|ref_res| (ref_res, false));
+
+        for (ref_res, passed_filter) in passed.chain(filtered_out) {
+            let mut row = vec![
+                input_file_path.to_string(),
+                db_res.database_path.clone(),
+                ref_res.reference_name.clone(),
+                ref_res.total_kmers_in_reference.to_string(),
+                ref_res.input_kmers_hitting_reference.to_string(),
+                ref_res.sum_depth_of_matched_kmers_in_input.to_string(),
+                format_float(ref_res.avg_depth_of_matched_kmers_in_input, 4),
+                format_float(ref_res.proportion_input_kmers_hitting_reference, 4),
+                format_float(ref_res.reference_breadth_of_coverage, 4),
+            ];
+            if self.include_weighted {
+                for weighted_value in [
+                    ref_res.weighted_avg_depth_of_matched_kmers_in_input,
+                    ref_res.weighted_reference_breadth_of_coverage,
+                ] {
+                    row.push(format_float(weighted_value.unwrap_or(0.0), 4));
+                }
+            }
+            if self.include_uniformity {
+                row.push(format_float(ref_res.coverage_uniformity.unwrap_or(0.0), 4));
             }
+            if self.include_filtered_out {
+                row.push(passed_filter.to_string());
+            }
+            self.tsv_writer.write_record(&row)?;
         }
-        tsv_writer.flush()?;
-        info!("TSV summary successfully written to {:?}", tsv_path);
+        Ok(())
     }
 
-    info!("Classification successfully completed.");
-    Ok(())
+    fn finish(self) -> Result<()> {
+        let tsv_writer_boxed = self
+            .tsv_writer
+            .into_inner()
+            .map_err(|e| e.into_error())
+            .context("Failed to flush TSV output writer")?;
+        finalize_writer(tsv_writer_boxed).context("Failed to finish TSV output writer")?;
+        Ok(())
+    }
+}
+
+// Appends one `classifications` row for the run and a `references` row per reported reference
+// to the --sqlite database. With `include_filtered_out` (--full-tsv), references that failed
+// the reporting filters are recorded too, with passed_filter = 0.
+struct ClassificationSqliteWriter {
+    results_db: ResultsDb,
+    classification_id: i64,
+    include_filtered_out: bool,
+}
+
+impl ClassificationSqliteWriter {
+    fn create(sqlite_path: &Path, header: &ClassificationOutput, include_filtered_out: bool) -> Result<Self> {
+        let results_db = ResultsDb::open(sqlite_path)?;
+        let classification_id = results_db.insert_classification(&ClassificationRow {
+            input_file_path: &header.input_file_path,
+            total_unique_kmers_in_input: header.total_unique_kmers_in_input,
+            min_kmer_frequency_filter: header.min_kmer_frequency_filter,
+            input_total_bases: header.input_total_bases,
+            input_ambiguous_bases: header.input_ambiguous_bases,
+        })?;
+        Ok(ClassificationSqliteWriter {
+            results_db,
+            classification_id,
+            include_filtered_out,
+        })
+    }
+
+    fn write_database(&self, db_res: &DatabaseClassificationResult) -> Result<()> {
+        let passed = db_res.references.iter().map(|ref_res| (ref_res, true));
+        let filtered_out = db_res
+            .filtered_out_references
+            .iter()
+            .filter(|_| self.include_filtered_out)
+            .map(|ref_res| (ref_res, false));
+        for (ref_res, passed_filter) in passed.chain(filtered_out) {
+            self.results_db.insert_reference(
+                self.classification_id,
+                &ReferenceRow {
+                    database_path: &db_res.database_path,
+                    reference_name: &ref_res.reference_name,
+                    total_kmers_in_reference: ref_res.total_kmers_in_reference,
+                    input_kmers_hitting_reference: ref_res.input_kmers_hitting_reference,
+                    sum_depth_of_matched_kmers_in_input: ref_res.sum_depth_of_matched_kmers_in_input,
+                    avg_depth_of_matched_kmers_in_input: ref_res.avg_depth_of_matched_kmers_in_input,
+                    proportion_input_kmers_hitting_reference: ref_res.proportion_input_kmers_hitting_reference,
+                    reference_breadth_of_coverage: ref_res.reference_breadth_of_coverage,
+                    passed_filter,
+                },
+            )?;
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<()> {
+        self.results_db.commit()
+    }
 }
@@ -1,14 +1,22 @@
 use anyhow::{Context, Result};
-use log::info;
+use needletail::Sequence;
+use log::{info, warn};
 use serde::Serialize;
-use std::fs::File; // Removed BufReader, PathBuf as load_kmer_db_v2 takes &Path
+use std::{
+    collections::{BTreeSet, HashSet},
+    fs::OpenOptions,
+    io::Write,
+    path::Path,
+}; // Removed BufReader, PathBuf as load_kmer_db_v2 takes &Path
 
 use crate::{
-    cli::CompareArgs,
-    // KmerDbV2 is loaded by load_kmer_db_v2, direct import not needed here
-    // db_types::KmerDbV2,
+    cli::{CompareArgs, CompareOutputFormat},
+    commands::build::build_kmer_db_from_file,
+    db_types::{ComparisonStats, KmerDbV2},
     errors::OrionKmerError,
-    utils::{load_kmer_db_v2, track_progress_and_resources}, // Import the wrapper
+    kmer::{canonical_u64, hash_kmer, rolling_kmers},
+    sqlite_output::{ComparisonRow, ResultsDb},
+    utils::{check_canonical_policy, finalize_writer, get_output_writer, load_kmer_db_v2, open_sequence_reader, round_float, track_progress_and_resources, write_kmer_db, ReferenceSelection}, // Import the wrapper
 };
 // use indicatif::ProgressBar; // Not needed here
 
@@ -21,77 +29,414 @@ struct ComparisonOutput {
     db2_total_unique_kmers_across_references: usize, // Name changed for clarity
     intersection_size: usize,
     union_size: usize,
+    // K-mers in only one of the databases, for Venn-style reporting
+    db1_only_kmers: usize,
+    db2_only_kmers: usize,
     jaccard_index: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    collision_check: Option<CollisionCheck>,
+}
+
+// --collision-check estimate of how much hashing inflates the intersection of two hashed databases.
+#[derive(Serialize, Debug)]
+struct CollisionCheck {
+    hash_bits: u32,
+    // Expected number of (db1, db2) pairs of distinct k-mers sharing a hash, |A| |B| / 2^bits
+    expected_false_shared_kmers: f64,
+    // Probability of at least one such pair, 1 - e^(-expected)
+    collision_probability: f64,
+}
+
+fn estimate_collisions(hash_bits: u32, a_size: usize, b_size: usize) -> CollisionCheck {
+    let expected = a_size as f64 * b_size as f64 / 2f64.powi(hash_bits as i32);
+    CollisionCheck {
+        hash_bits,
+        expected_false_shared_kmers: expected,
+        collision_probability: -(-expected).exp_m1(),
+    }
 }
 
 // Removed local load_kmer_db function, will use utils::load_kmer_db_v2
 
+// Share of `total` k-mers that are shared, or 0.0 for an empty set.
+fn fraction(shared: usize, total: usize) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        shared as f64 / total as f64
+    }
+}
+
+/// Computes the overlap between two in-memory k-mer sets: intersection, union, Jaccard index
+/// and the containment of each set in the other.
+pub fn jaccard_stats(a: &HashSet<u64>, b: &HashSet<u64>) -> ComparisonStats {
+    // Iterate over the smaller set
+    let (smaller, larger) = if a.len() <= b.len() { (a, b) } else { (b, a) };
+    let intersection_size = smaller.iter().filter(|kmer| larger.contains(kmer)).count();
+    stats_from_sizes(a.len(), b.len(), intersection_size)
+}
+
+/// Like `jaccard_stats`, also returning the shared k-mers themselves.
+pub fn jaccard_stats_with_intersection(a: &HashSet<u64>, b: &HashSet<u64>) -> (ComparisonStats, HashSet<u64>) {
+    let (smaller, larger) = if a.len() <= b.len() { (a, b) } else { (b, a) };
+    let intersection: HashSet<u64> = smaller.iter().filter(|kmer| larger.contains(kmer)).copied().collect();
+    (stats_from_sizes(a.len(), b.len(), intersection.len()), intersection)
+}
+
+fn stats_from_sizes(a_size: usize, b_size: usize, intersection_size: usize) -> ComparisonStats {
+    let union_size = a_size + b_size - intersection_size;
+    ComparisonStats {
+        a_size,
+        b_size,
+        intersection_size,
+        union_size,
+        jaccard_index: fraction(intersection_size, union_size),
+        containment_a_in_b: fraction(intersection_size, a_size),
+        containment_b_in_a: fraction(intersection_size, b_size),
+    }
+}
+
+const TSV_HEADER: &str = "db1\tdb2\tk\tintersection\tunion\tjaccard\tcontainment1\tcontainment2\n";
+
+fn comparison_tsv_row(output: &ComparisonOutput, stats: &ComparisonStats) -> String {
+    format!(
+        "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+        output.db1_path,
+        output.db2_path,
+        output.kmer_size,
+        output.intersection_size,
+        output.union_size,
+        output.jaccard_index,
+        round_float(stats.containment_a_in_b),
+        round_float(stats.containment_b_in_a)
+    )
+}
+
+// Writes the --tsv row for one comparison, appending to an existing file with --append so
+// that many compare jobs can build one table. The header is written only to a new or empty file.
+fn write_comparison_tsv(
+    tsv_path: &Path,
+    append: bool,
+    output: &ComparisonOutput,
+    stats: &ComparisonStats,
+) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(tsv_path)
+        .with_context(|| format!("Failed to open comparison TSV: {:?}", tsv_path))?;
+    let is_new = file
+        .metadata()
+        .with_context(|| format!("Failed to read metadata of comparison TSV: {:?}", tsv_path))?
+        .len()
+        == 0;
+    let mut text = if is_new { TSV_HEADER.to_string() } else { String::new() };
+    text.push_str(&comparison_tsv_row(output, stats));
+    // A single write keeps rows from concurrent jobs appending to the same file intact
+    file.write_all(text.as_bytes())
+        .with_context(|| format!("Failed to write comparison TSV: {:?}", tsv_path))?;
+    Ok(())
+}
+
+// --stream-fasta result: the Jaccard index estimated from two bottom-N MinHash sketches.
+#[derive(Serialize, Debug)]
+struct StreamComparisonOutput {
+    fasta1_path: String,
+    fasta2_path: String,
+    kmer_size: u8,
+    hash_seed: u64,
+    sketch_size: usize,
+    fasta1_sketch_hashes: usize, // Fewer than sketch_size when the file has fewer distinct k-mers
+    fasta2_sketch_hashes: usize,
+    shared_sketch_hashes: usize, // Hashes of the union's bottom-N sketch found in both sketches
+    estimated_jaccard_index: f64, // (shared_sketch_hashes / hashes in the union's bottom-N sketch)
+}
+
+// Streams the records of `path` and keeps the `sketch_size` smallest distinct hashes of its
+// canonical k-mers, so memory stays proportional to the sketch rather than the file.
+fn bottom_sketch(path: &Path, k: u8, seed: u64, sketch_size: usize) -> Result<BTreeSet<u64>> {
+    let mut reader = open_sequence_reader(path)?;
+    let mut sketch: BTreeSet<u64> = BTreeSet::new();
+    while let Some(record) = reader.next() {
+        let record = record.with_context(|| format!("Error reading record from {:?}", path))?;
+        let norm_seq = record.normalize(false);
+        for (_, kmer) in rolling_kmers(&norm_seq, k) {
+            let Some(kmer_val) = kmer else {
+                continue;
+            };
+            let hash = hash_kmer(canonical_u64(kmer_val, k), k, seed);
+            if sketch.len() < sketch_size {
+                sketch.insert(hash);
+            } else if sketch.last().is_some_and(|&largest| hash < largest) && sketch.insert(hash) {
+                sketch.pop_last();
+            }
+        }
+    }
+    Ok(sketch)
+}
+
+// Estimates the Jaccard index of two sketches the bottom-k way: the fraction of the smallest
+// `sketch_size` hashes of their union that are in both. Returns (shared hashes, estimate).
+fn sketch_jaccard(a: &BTreeSet<u64>, b: &BTreeSet<u64>, sketch_size: usize) -> (usize, f64) {
+    let union_sketch: Vec<u64> = a.union(b).copied().take(sketch_size).collect();
+    let shared = union_sketch
+        .iter()
+        .filter(|hash| a.contains(hash) && b.contains(hash))
+        .count();
+    (shared, fraction(shared, union_sketch.len()))
+}
+
+fn run_stream_compare(args: &CompareArgs) -> Result<()> {
+    let [fasta1_path, fasta2_path] = args.stream_fasta.as_slice() else {
+        return Err(OrionKmerError::Generic("--stream-fasta takes exactly two files".to_string()).into());
+    };
+    let k = args.kmer_size.ok_or_else(|| {
+        OrionKmerError::Generic("--kmer-size must be given with --stream-fasta".to_string())
+    })?;
+    if k == 0 || k > 32 {
+        return Err(OrionKmerError::InvalidKmerSize(k).into());
+    }
+    if args.sketch_size == 0 {
+        return Err(OrionKmerError::Generic("--sketch-size must be at least 1".to_string()).into());
+    }
+    let output_path = args.output_file.as_ref().ok_or_else(|| {
+        OrionKmerError::Generic("-o must be given with --stream-fasta".to_string())
+    })?;
+
+    let sketches = track_progress_and_resources("Sketching FASTA files", 2, |pb| {
+        let mut sketches = Vec::new();
+        for fasta_path in [fasta1_path, fasta2_path] {
+            pb.set_message(format!("Sketching: {}", fasta_path.to_string_lossy()));
+            sketches.push(bottom_sketch(fasta_path, k, args.hash_seed, args.sketch_size)?);
+            pb.inc(1);
+        }
+        Ok(sketches)
+    })?;
+    let (shared_sketch_hashes, jaccard_estimate) = sketch_jaccard(&sketches[0], &sketches[1], args.sketch_size);
+    let output_data = StreamComparisonOutput {
+        fasta1_path: fasta1_path.to_string_lossy().into_owned(),
+        fasta2_path: fasta2_path.to_string_lossy().into_owned(),
+        kmer_size: k,
+        hash_seed: args.hash_seed,
+        sketch_size: args.sketch_size,
+        fasta1_sketch_hashes: sketches[0].len(),
+        fasta2_sketch_hashes: sketches[1].len(),
+        shared_sketch_hashes,
+        estimated_jaccard_index: round_float(jaccard_estimate),
+    };
+    info!("Streaming comparison results: {:?}", output_data);
+
+    write_comparison_json(output_path, &output_data)?;
+    info!("Successfully wrote comparison statistics to {:?}", output_path);
+    Ok(())
+}
+
+// Writes the comparison JSON through get_output_writer, so `-o out.json.gz` etc. is compressed.
+fn write_comparison_json<T: Serialize>(output_path: &Path, output_data: &T) -> Result<()> {
+    let mut writer = get_output_writer(output_path)
+        .with_context(|| format!("Failed to create output JSON file: {:?}", output_path))?;
+    serde_json::to_writer_pretty(&mut writer, output_data)
+        .with_context(|| format!("Failed to write comparison JSON to {:?}", output_path))?;
+    finalize_writer(writer).context("Failed to finish comparison JSON writer")
+}
+
 pub fn run_compare(args: CompareArgs) -> Result<()> {
     info!("Starting compare command with args: {:?}", args);
+    if !args.stream_fasta.is_empty() {
+        return run_stream_compare(&args);
+    }
 
+    let Some(db1_path) = &args.db1 else {
+        return Err(OrionKmerError::Generic("Either --db1 or --stream-fasta must be given".to_string()).into());
+    };
     // Load KmerDbV2 instances
     // These already have their own info logging. We could wrap them too if they are very slow.
-    let db1_v2 = load_kmer_db_v2(&args.db1)?;
-    let db2_v2 = load_kmer_db_v2(&args.db2)?;
+    let mut db1_v2 = load_kmer_db_v2(db1_path)?;
+    // With --against-fasta the second k-mer set is built on the fly with db1's settings
+    let (db2_path, mut db2_v2) = match (&args.db2, &args.against_fasta) {
+        (Some(db2_path), _) => (db2_path.clone(), load_kmer_db_v2(db2_path)?),
+        (None, Some(fasta_path)) => {
+            info!("Building k-mers of {:?} with k={}", fasta_path, db1_v2.k);
+            let fasta_db = build_kmer_db_from_file(fasta_path, db1_v2.k, db1_v2.hashing, db1_v2.canonical, db1_v2.canonical_policy)?;
+            (fasta_path.clone(), fasta_db)
+        }
+        (None, None) => {
+            return Err(OrionKmerError::Generic(
+                "Either --db2 or --against-fasta must be given".to_string(),
+            )
+            .into());
+        }
+    };
+    let reference_selection = ReferenceSelection::new(&args.only_reference, &args.exclude_reference);
+    let mut found_references = HashSet::new();
+    reference_selection.apply(&mut db1_v2, &mut found_references);
+    if args.db2.is_some() {
+        reference_selection.apply(&mut db2_v2, &mut found_references);
+    }
+    reference_selection.check_found(&found_references)?;
 
     if db1_v2.k != db2_v2.k {
         return Err(OrionKmerError::KmerSizeMismatch(db1_v2.k, db2_v2.k).into());
     }
+    if db1_v2.hashing != db2_v2.hashing {
+        return Err(
+            OrionKmerError::HashingMismatch(db1_v2.hashing, db2_v2.hashing, db2_path.clone()).into(),
+        );
+    }
+    // Both databases must agree on canonical storage for their k-mers to be comparable:
+    // otherwise most shared k-mers are stored in different orientations and the Jaccard index is meaningless
+    let coerce_canonical = db1_v2.canonical != db2_v2.canonical;
+    if coerce_canonical {
+        if !args.coerce_canonical {
+            return Err(OrionKmerError::CanonicalMismatch(db1_path.clone(), db2_path.clone()).into());
+        }
+        if db1_v2.hashing.is_some() {
+            return Err(OrionKmerError::Generic(
+                "--coerce-canonical cannot canonicalize hashed k-mers".to_string(),
+            )
+            .into());
+        }
+        let non_canonical_path = if db1_v2.canonical { &db2_path } else { db1_path };
+        warn!(
+            "--coerce-canonical: canonicalizing the k-mers of non-canonical database {:?} before comparing. \
+             Its k-mers and their reverse complements collapse into one, so its size and the Jaccard index are approximate.",
+            non_canonical_path
+        );
+    } else if db1_v2.canonical {
+        check_canonical_policy(&db1_v2, &db2_v2, &db2_path)?;
+    }
     let kmer_size = db1_v2.k;
 
-    let output_data = track_progress_and_resources(
+    let (stats, intersection) = track_progress_and_resources(
         &format!(
             "Comparing databases: {} and {}",
-            args.db1.to_string_lossy(),
-            args.db2.to_string_lossy()
+            db1_path.to_string_lossy(),
+            db2_path.to_string_lossy()
         ),
         1, // Single task for the comparison logic
         |pb| {
             // Get the unified set of k-mers for each database
-            let db1_all_kmers = db1_v2.get_all_kmers_unified();
-            let db2_all_kmers = db2_v2.get_all_kmers_unified();
+            let mut db1_all_kmers = db1_v2.get_all_kmers_unified();
+            let mut db2_all_kmers = db2_v2.get_all_kmers_unified();
+            if coerce_canonical {
+                // Canonicalize with the canonical database's policy so the orientations agree
+                let (canonical_db, non_canonical_kmers) = if db1_v2.canonical {
+                    (&db1_v2, &mut db2_all_kmers)
+                } else {
+                    (&db2_v2, &mut db1_all_kmers)
+                };
+                *non_canonical_kmers = non_canonical_kmers
+                    .iter()
+                    .map(|&kmer| canonical_db.canonicalize(kmer))
+                    .collect();
+            }
             pb.inc(0); // Indicate activity, actual inc(1) at the end.
 
-            let db1_unique_kmers_count = db1_all_kmers.len();
-            let db2_unique_kmers_count = db2_all_kmers.len();
-
-            let intersection_size = db1_all_kmers.intersection(&db2_all_kmers).count();
-
-            let union_size = db1_unique_kmers_count + db2_unique_kmers_count - intersection_size;
-
-            let jaccard_index = if union_size == 0 {
-                0.0
+            // The shared k-mers are only kept when --intersection-db writes them
+            let (stats, intersection) = if args.intersection_db.is_some() {
+                let (stats, intersection) = jaccard_stats_with_intersection(&db1_all_kmers, &db2_all_kmers);
+                (stats, Some(intersection))
             } else {
-                intersection_size as f64 / union_size as f64
+                (jaccard_stats(&db1_all_kmers, &db2_all_kmers), None)
             };
 
             pb.inc(1); // Complete the progress bar for this single task.
 
-            Ok(ComparisonOutput {
-                db1_path: args.db1.to_string_lossy().into_owned(),
-                db2_path: args.db2.to_string_lossy().into_owned(),
-                kmer_size,
-                db1_total_unique_kmers_across_references: db1_unique_kmers_count,
-                db2_total_unique_kmers_across_references: db2_unique_kmers_count,
-                intersection_size,
-                union_size,
-                jaccard_index,
-            })
+            Ok((stats, intersection))
         },
     )?;
+    let collision_check = match db1_v2.hashing {
+        Some(hashing) if args.collision_check => {
+            let check = estimate_collisions(hashing.function.bits(), stats.a_size, stats.b_size);
+            if check.collision_probability > args.collision_threshold {
+                warn!(
+                    "--collision-check: with {}-bit hashes, an expected {:.3e} of the {} shared k-mers are collisions between distinct k-mers \
+                     (probability of any {:.3e}, above --collision-threshold {:e}); the intersection and Jaccard index may be inflated.",
+                    check.hash_bits,
+                    check.expected_false_shared_kmers,
+                    stats.intersection_size,
+                    check.collision_probability,
+                    args.collision_threshold
+                );
+            }
+            Some(check)
+        }
+        None if args.collision_check => {
+            info!("--collision-check: the databases store exact k-mer encodings, which cannot collide");
+            None
+        }
+        _ => None,
+    };
+    let output_data = ComparisonOutput {
+        db1_path: db1_path.to_string_lossy().into_owned(),
+        db2_path: db2_path.to_string_lossy().into_owned(),
+        kmer_size,
+        db1_total_unique_kmers_across_references: stats.a_size,
+        db2_total_unique_kmers_across_references: stats.b_size,
+        intersection_size: stats.intersection_size,
+        union_size: stats.union_size,
+        db1_only_kmers: stats.a_size - stats.intersection_size,
+        db2_only_kmers: stats.b_size - stats.intersection_size,
+        jaccard_index: round_float(stats.jaccard_index),
+        collision_check,
+    };
 
     info!("Comparison results: {:?}", output_data);
 
-    let output_file = File::create(&args.output_file)
-        .with_context(|| format!("Failed to create output JSON file: {:?}", args.output_file))?;
+    if let Some(output_path) = &args.output_file {
+        match args.output_format {
+            CompareOutputFormat::Json => write_comparison_json(output_path, &output_data)?,
+            CompareOutputFormat::Tsv => {
+                let mut writer = get_output_writer(output_path)
+                    .with_context(|| format!("Failed to create output TSV file: {:?}", output_path))?;
+                writer
+                    .write_all(format!("{}{}", TSV_HEADER, comparison_tsv_row(&output_data, &stats)).as_bytes())
+                    .with_context(|| format!("Failed to write comparison TSV to {:?}", output_path))?;
+                finalize_writer(writer).context("Failed to finish comparison TSV writer")?;
+            }
+        }
+        info!("Successfully wrote comparison statistics to {:?}", output_path);
+    }
+
+    if let Some(tsv_path) = &args.tsv {
+        write_comparison_tsv(tsv_path, args.append, &output_data, &stats)?;
+        info!("Successfully wrote comparison row to {:?}", tsv_path);
+    }
 
-    serde_json::to_writer_pretty(output_file, &output_data)
-        .with_context(|| format!("Failed to write comparison JSON to {:?}", args.output_file))?;
+    if let Some(sqlite_path) = &args.sqlite {
+        let results_db = ResultsDb::open(sqlite_path)?;
+        results_db.insert_comparison(&ComparisonRow {
+            db1_path: &output_data.db1_path,
+            db2_path: &output_data.db2_path,
+            kmer_size,
+            db1_unique_kmers: stats.a_size,
+            db2_unique_kmers: stats.b_size,
+            intersection_size: stats.intersection_size,
+            union_size: stats.union_size,
+            jaccard_index: output_data.jaccard_index,
+            containment_1_in_2: round_float(stats.containment_a_in_b),
+            containment_2_in_1: round_float(stats.containment_b_in_a),
+        })?;
+        results_db.commit()?;
+        info!("Successfully wrote comparison row to SQLite database {:?}", sqlite_path);
+    }
 
-    info!(
-        "Successfully wrote comparison statistics to {:?}",
-        args.output_file
-    );
+    if let (Some(intersection_path), Some(intersection)) = (&args.intersection_db, intersection) {
+        // With --coerce-canonical the shared k-mers are in the canonical database's form
+        let template = if db1_v2.canonical || !db2_v2.canonical { &db1_v2 } else { &db2_v2 };
+        let mut intersection_db = KmerDbV2::new(kmer_size);
+        intersection_db.hashing = template.hashing;
+        intersection_db.canonical = template.canonical;
+        intersection_db.canonical_policy = template.canonical_policy;
+        intersection_db.add_reference("intersection".to_string(), intersection);
+        write_kmer_db(&intersection_db, intersection_path)?;
+        info!(
+            "Successfully wrote the {} shared k-mers to {:?}",
+            stats.intersection_size, intersection_path
+        );
+    }
 
     Ok(())
 }
@@ -0,0 +1,81 @@
+use anyhow::{Context, Result};
+use log::{info, warn};
+use std::collections::HashSet;
+
+use crate::{
+    cli::{ConvertArgs, DbRepresentation},
+    db_types::{HashFunction, KmerDbV2, KmerHashing},
+    errors::OrionKmerError,
+    kmer_index::write_sorted_kmer_index,
+    utils::{load_kmer_db_v2, write_kmer_db},
+};
+
+// Replaces each reference's k-mer encodings with their hashes.
+fn hash_references(kmer_db: &mut KmerDbV2, hashing: KmerHashing) {
+    let k = kmer_db.k;
+    for kmer_set in kmer_db.references.values_mut() {
+        let hashed: HashSet<u64> = kmer_set.iter().map(|&kmer| hashing.hash(kmer, k)).collect();
+        *kmer_set = hashed;
+    }
+    kmer_db.hashing = Some(hashing);
+}
+
+pub fn run_convert(args: ConvertArgs) -> Result<()> {
+    info!("Starting convert command with args: {:?}", args);
+
+    // load_kmer_db_v2 reads both `build` databases and sorted k-mer indexes
+    let mut kmer_db = load_kmer_db_v2(&args.input_file)
+        .with_context(|| format!("Failed to load database to convert: {:?}", args.input_file))?;
+
+    match args.to {
+        DbRepresentation::Hashset => {
+            // Hashes cannot be turned back into k-mers
+            if kmer_db.hashing.is_some() {
+                return Err(OrionKmerError::Generic(format!(
+                    "{:?} stores hashed k-mers, which cannot be converted back to k-mer encodings; rebuild the database from its sequences instead",
+                    args.input_file
+                ))
+                .into());
+            }
+            write_kmer_db(&kmer_db, &args.output_file)?;
+        }
+        DbRepresentation::Hashed => {
+            let hashing = KmerHashing {
+                function: HashFunction::Murmur3X64,
+                seed: args.hash_seed,
+            };
+            match kmer_db.hashing {
+                None => {
+                    warn!(
+                        "Converting {:?} to hashed k-mers is one-way: {:?} cannot be converted back to k-mer encodings, so keep the original if you need them (e.g. for `dump` or `neighbors`)",
+                        args.input_file, args.output_file
+                    );
+                    hash_references(&mut kmer_db, hashing);
+                }
+                // Already hashed the same way; only the file is rewritten
+                Some(existing) if existing == hashing => {}
+                Some(existing) => {
+                    return Err(OrionKmerError::HashingMismatch(
+                        Some(existing),
+                        Some(hashing),
+                        args.input_file.clone(),
+                    )
+                    .into());
+                }
+            }
+            write_kmer_db(&kmer_db, &args.output_file)?;
+        }
+        DbRepresentation::Sorted => {
+            write_sorted_kmer_index(&kmer_db, &args.output_file)?;
+        }
+    }
+
+    info!(
+        "Wrote {:?} ({} references, {} unique k-mers) as {:?}",
+        args.output_file,
+        kmer_db.num_references(),
+        kmer_db.total_unique_kmers(),
+        args.to
+    );
+    Ok(())
+}
@@ -1,77 +1,833 @@
 use anyhow::{Context, Result};
 use dashmap::DashMap;
 // use flate2::read::MultiGzDecoder; // Removed
-use log::{debug, info};
-use needletail::{parse_fastx_reader, Sequence}; // Changed parse_fastx_file to parse_fastx_reader
+use log::{debug, info, warn};
+use needletail::Sequence;
 // use rayon::prelude::*;
 use std::{
+    collections::{BTreeMap, HashMap, HashSet},
     // fs::File, // No longer directly used
     // io::{BufWriter, Write}, // No longer directly used
-    // path::Path, // Removed
-    io::Write, // Only Write is needed for writeln! macro with Box<dyn Write>
-    sync::atomic::{AtomicUsize, Ordering},
+    io::{BufRead, Write}, // Write is needed for writeln! macro with Box<dyn Write>
+    path::Path,
+    sync::atomic::{AtomicU16, AtomicU32, AtomicU64, Ordering},
 };
 
 use crate::{
-    cli::CountArgs,
+    cli::{CountArgs, CountSortOrder, CountType},
     errors::OrionKmerError,
-    kmer::{canonical_u64, seq_to_u64, u64_to_seq},
-    utils::{get_decompressed_input_reader, get_output_writer, track_progress_and_resources}, // Import I/O helpers
+    kmer::{ambiguous_base_count, canonical_u64, canonical_with_orientation, expand_iupac_window, reverse_complement_u64, rolling_kmers, seq_to_u64, u64_to_seq, CanonicalPolicy, Strand},
+    utils::{cardinality::HyperLogLog, check_dna_record, cms::{CountMinSketch, HeavyHitters}, finalize_writer, get_decompressed_input_reader, get_output_writer, is_progress_record, load_kmer_db_v2, open_sequence_reader, polyx_base, read_kmer_db_k_values, report_base_counts, report_short_records, take_input_record, track_progress_and_resources, trim_polyx, BaseCounts, PhaseTimer, SequenceFilter}, // Import I/O helpers
 };
 // use indicatif::ProgressBar; // Not needed if progress is per file
 
-fn process_sequence_chunk(seq_chunk: &[u8], k: u8, kmer_counts: &DashMap<u64, AtomicUsize>) {
+// A k-mer's count cell for --count-type, keyed by the k-mer split into words of the count's
+// width: a (u64, u16) entry would be padded to 16 bytes like a (u64, u64) one, while a
+// ([u16; 4], u16) entry takes 10.
+trait CountCell: Send + Sync + 'static {
+    type Key: Copy + Eq + std::hash::Hash + Send + Sync + 'static;
+    const MAX: usize;
+
+    fn zero() -> Self;
+    fn key(kmer_val: u64) -> Self::Key;
+    fn kmer(key: Self::Key) -> u64;
+    // Adds one occurrence; false when the count is already at its maximum
+    fn increment(&self) -> bool;
+    fn increment_mut(&mut self) -> bool;
+    fn into_count(self) -> usize;
+}
+
+macro_rules! impl_count_cell {
+    ($atomic:ty, $int:ty, $words:literal) => {
+        impl CountCell for $atomic {
+            type Key = [$int; $words];
+            const MAX: usize = <$int>::MAX as usize;
+
+            fn zero() -> Self {
+                <$atomic>::new(0)
+            }
+
+            fn key(kmer_val: u64) -> Self::Key {
+                std::array::from_fn(|word| (kmer_val >> (word as u32 * <$int>::BITS)) as $int)
+            }
+
+            fn kmer(key: Self::Key) -> u64 {
+                key.iter().enumerate().fold(0, |kmer_val, (word, &bits)| {
+                    kmer_val | (bits as u64) << (word as u32 * <$int>::BITS)
+                })
+            }
+
+            fn increment(&self) -> bool {
+                self.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |count| count.checked_add(1))
+                    .is_ok()
+            }
+
+            fn increment_mut(&mut self) -> bool {
+                let count = self.get_mut();
+                match count.checked_add(1) {
+                    Some(incremented) => *count = incremented,
+                    None => return false,
+                }
+                true
+            }
+
+            fn into_count(self) -> usize {
+                self.into_inner() as usize
+            }
+        }
+    };
+}
+
+impl_count_cell!(AtomicU16, u16, 4);
+impl_count_cell!(AtomicU32, u32, 2);
+impl_count_cell!(AtomicU64, u64, 1);
+
+// Occurrence counts per canonical k-mer: a concurrent map by default, or a plain HashMap without
+// atomics with --single-thread.
+enum CountMap<C: CountCell> {
+    Concurrent(DashMap<C::Key, C>),
+    Plain(HashMap<C::Key, C>),
+}
+
+impl<C: CountCell> CountMap<C> {
+    fn new(single_thread: bool) -> Self {
+        if single_thread {
+            CountMap::Plain(HashMap::new())
+        } else {
+            CountMap::Concurrent(DashMap::new())
+        }
+    }
+
+    fn add(&mut self, kmer_val: u64) -> bool {
+        match self {
+            CountMap::Concurrent(counts) => counts.entry(C::key(kmer_val)).or_insert_with(C::zero).increment(),
+            CountMap::Plain(counts) => counts.entry(C::key(kmer_val)).or_insert_with(C::zero).increment_mut(),
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            CountMap::Concurrent(counts) => counts.len(),
+            CountMap::Plain(counts) => counts.len(),
+        }
+    }
+
+    fn into_counts(self) -> Box<dyn Iterator<Item = (u64, usize)>> {
+        match self {
+            CountMap::Concurrent(counts) => {
+                Box::new(counts.into_iter().map(|(key, count)| (C::kmer(key), count.into_count())))
+            }
+            CountMap::Plain(counts) => Box::new(counts.into_iter().map(|(key, count)| (C::kmer(key), count.into_count()))),
+        }
+    }
+}
+
+// Bytes of one count map slot for a --count-type.
+fn count_slot_bytes(count_type: CountType) -> u64 {
+    fn slot_bytes<C: CountCell>() -> u64 {
+        std::mem::size_of::<(C::Key, C)>() as u64
+    }
+    match count_type {
+        CountType::U16 => slot_bytes::<AtomicU16>(),
+        CountType::U32 => slot_bytes::<AtomicU32>(),
+        CountType::U64 => slot_bytes::<AtomicU64>(),
+    }
+}
+
+// The counts of one count map in the integer type of --count-type.
+enum TypedCountMap {
+    U16(CountMap<AtomicU16>),
+    U32(CountMap<AtomicU32>),
+    U64(CountMap<AtomicU64>),
+}
+
+struct KmerCounts {
+    counts: TypedCountMap,
+    // Occurrences left uncounted because their k-mer's count was at the --count-type maximum
+    saturated: usize,
+}
+
+impl KmerCounts {
+    fn new(single_thread: bool, count_type: CountType) -> Self {
+        let counts = match count_type {
+            CountType::U16 => TypedCountMap::U16(CountMap::new(single_thread)),
+            CountType::U32 => TypedCountMap::U32(CountMap::new(single_thread)),
+            CountType::U64 => TypedCountMap::U64(CountMap::new(single_thread)),
+        };
+        KmerCounts { counts, saturated: 0 }
+    }
+
+    fn add(&mut self, kmer_val: u64) {
+        let counted = match &mut self.counts {
+            TypedCountMap::U16(counts) => counts.add(kmer_val),
+            TypedCountMap::U32(counts) => counts.add(kmer_val),
+            TypedCountMap::U64(counts) => counts.add(kmer_val),
+        };
+        if !counted {
+            self.saturated += 1;
+        }
+    }
+
+    fn len(&self) -> usize {
+        match &self.counts {
+            TypedCountMap::U16(counts) => counts.len(),
+            TypedCountMap::U32(counts) => counts.len(),
+            TypedCountMap::U64(counts) => counts.len(),
+        }
+    }
+
+    fn into_counts(self) -> Box<dyn Iterator<Item = (u64, usize)>> {
+        let (counts, max_count) = match self.counts {
+            TypedCountMap::U16(counts) => (counts.into_counts(), AtomicU16::MAX),
+            TypedCountMap::U32(counts) => (counts.into_counts(), AtomicU32::MAX),
+            TypedCountMap::U64(counts) => (counts.into_counts(), AtomicU64::MAX),
+        };
+        if self.saturated > 0 {
+            warn!(
+                "{} k-mer occurrences were not counted: their k-mers' counts saturated at the --count-type maximum of {}",
+                self.saturated, max_count
+            );
+        }
+        counts
+    }
+}
+
+// The k-mers one input file contributed to the counts, for --per-file-report.
+#[derive(Default)]
+struct FileKmerStats {
+    distinct: HashSet<u64>,
+    total: usize,
+    bases: BaseCounts,
+}
+
+impl FileKmerStats {
+    fn add(&mut self, kmer_val: u64) {
+        self.distinct.insert(kmer_val);
+        self.total += 1;
+    }
+}
+
+// Writes the --per-file-report table, one row per input file in input order.
+fn write_per_file_report(report_path: &Path, rows: &[(String, FileKmerStats)]) -> Result<()> {
+    let mut writer = get_output_writer(report_path)
+        .with_context(|| format!("Failed to get output writer for per-file report: {:?}", report_path))?;
+    writeln!(writer, "filename\tunique_kmers\ttotal_kmers\ttotal_bases\tambiguous_bases")?;
+    for (filename, stats) in rows {
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t{}\t{}",
+            filename,
+            stats.distinct.len(),
+            stats.total,
+            stats.bases.total_bases,
+            stats.bases.ambiguous_bases
+        )
+        .context("Failed to write per-file report row")?;
+    }
+    finalize_writer(writer).context("Failed to finish per-file report writer")?;
+    info!("Wrote per-file k-mer report for {} input files to {:?}", rows.len(), report_path);
+    Ok(())
+}
+
+// Loads the canonical k-mers to skip while counting, from a k-mer database (`.db`, optionally
+// compressed) or a text list with one k-mer per line (extra columns, as in count output, are ignored).
+fn load_mask_kmers(mask_path: &Path, k: u8) -> Result<HashSet<u64>> {
+    let file_name = mask_path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let stripped_name = [".gz", ".xz", ".zst", ".zstd"]
+        .iter()
+        .find_map(|ext| file_name.strip_suffix(ext))
+        .unwrap_or(&file_name);
+
+    if stripped_name.ends_with(".db") {
+        let mask_db = load_kmer_db_v2(mask_path)?;
+        if mask_db.k != k {
+            return Err(OrionKmerError::KmerSizeMismatchValidation(
+                k,
+                mask_db.k,
+                mask_path.to_path_buf(),
+            )
+            .into());
+        }
+        if mask_db.hashing.is_some() {
+            return Err(OrionKmerError::Generic(format!(
+                "Mask database {:?} stores hashed k-mers and cannot be used with count",
+                mask_path
+            ))
+            .into());
+        }
+        if mask_db.canonical_policy != CanonicalPolicy::Lexicographic {
+            return Err(OrionKmerError::CanonicalPolicyMismatch(
+                CanonicalPolicy::Lexicographic,
+                mask_db.canonical_policy,
+                mask_path.to_path_buf(),
+            )
+            .into());
+        }
+        return Ok(mask_db.get_all_kmers_unified());
+    }
+
+    let reader = get_decompressed_input_reader(mask_path)
+        .with_context(|| format!("Failed to get input reader for mask file: {:?}", mask_path))?;
+    let mut mask_kmers = HashSet::new();
+    for (line_idx, line) in reader.lines().enumerate() {
+        let line = line.with_context(|| format!("Failed to read mask file: {:?}", mask_path))?;
+        let Some(kmer_str) = line.split_whitespace().next() else {
+            continue; // Skip blank lines
+        };
+        let kmer_bytes = kmer_str.to_ascii_uppercase().into_bytes();
+        if kmer_bytes.len() != k as usize {
+            return Err(OrionKmerError::Generic(format!(
+                "K-mer '{}' on line {} of mask file {:?} does not have length k={}",
+                kmer_str,
+                line_idx + 1,
+                mask_path,
+                k
+            ))
+            .into());
+        }
+        let kmer_val = seq_to_u64(&kmer_bytes, k).ok_or_else(|| {
+            OrionKmerError::Generic(format!(
+                "Invalid k-mer '{}' on line {} of mask file {:?}",
+                kmer_str,
+                line_idx + 1,
+                mask_path
+            ))
+        })?;
+        mask_kmers.insert(canonical_u64(kmer_val, k));
+    }
+    Ok(mask_kmers)
+}
+
+// The k-mer column of an output row, followed by its reverse complement with --with-revcomp.
+fn kmer_columns(args: &CountArgs, kmer_val: u64, k: u8) -> Result<String> {
+    let mut columns = String::from_utf8(u64_to_seq(kmer_val, k))
+        .context("Failed to convert k-mer bytes to string (should not happen)")?;
+    if args.with_revcomp {
+        columns.push('\t');
+        columns.push_str(
+            &String::from_utf8(u64_to_seq(reverse_complement_u64(kmer_val, k), k))
+                .context("Failed to convert k-mer bytes to string (should not happen)")?,
+        );
+    }
+    Ok(columns)
+}
+
+// Whether a k-mer's (possibly weighted) count passes --min-count and the --at-least/--at-most/--exactly bounds.
+fn count_in_bounds(args: &CountArgs, count: f64) -> bool {
+    if let Some(exactly) = args.exactly {
+        return count == exactly as f64 && count >= args.min_count as f64;
+    }
+    count >= args.min_count.max(args.at_least.unwrap_or(0)) as f64
+        && args.at_most.is_none_or(|at_most| count <= at_most as f64)
+}
+
+// Weight of a k-mer occurrence from the lowest base quality (Phred+33) in its window: 1.0 at or
+// above `full_weight_qual`, falling linearly to 0.0 at Q0 below it.
+fn quality_weight(window_qual: &[u8], full_weight_qual: u8) -> f64 {
+    let min_qual = window_qual
+        .iter()
+        .map(|q| q.saturating_sub(33))
+        .min()
+        .unwrap_or(0);
+    if min_qual >= full_weight_qual {
+        1.0
+    } else {
+        min_qual as f64 / full_weight_qual as f64
+    }
+}
+
+// Calls `add` with each unmasked canonical k-mer of `seq_chunk`, the start of its window and
+// whether the window was read in canonical orientation.
+// `iupac_max_ambiguous` is None when ambiguous windows should be skipped (the default),
+// or the maximum number of ambiguous positions a window may have to be expanded.
+// Returns the number of windows skipped because they contained non-ACGT characters.
+fn process_sequence_chunk(
+    seq_chunk: &[u8],
+    k: u8,
+    iupac_max_ambiguous: Option<usize>,
+    mask_kmers: Option<&HashSet<u64>>,
+    mut add: impl FnMut(u64, usize, bool),
+) -> usize {
     if seq_chunk.len() < k as usize {
-        return;
+        return 0;
     }
 
-    for window in seq_chunk.windows(k as usize) {
-        if let Some(kmer_val) = seq_to_u64(window, k) {
-            let canonical_kmer = canonical_u64(kmer_val, k);
-            kmer_counts
-                .entry(canonical_kmer)
-                .or_insert_with(|| AtomicUsize::new(0))
-                .fetch_add(1, Ordering::Relaxed);
+    let mut add_unmasked = |kmer_val: u64, pos: usize| {
+        let (canonical_kmer, strand) = canonical_with_orientation(kmer_val, k);
+        if !mask_kmers.is_some_and(|mask| mask.contains(&canonical_kmer)) {
+            add(canonical_kmer, pos, strand == Strand::Forward);
+        }
+    };
+    let mut invalid_windows = 0;
+    for (pos, kmer) in rolling_kmers(seq_chunk, k) {
+        if let Some(kmer_val) = kmer {
+            add_unmasked(kmer_val, pos);
+            continue;
+        }
+        if let Some(max_ambiguous) = iupac_max_ambiguous {
+            let window = &seq_chunk[pos..pos + k as usize];
+            if ambiguous_base_count(window) <= max_ambiguous {
+                for kmer_val in expand_iupac_window(window, k) {
+                    add_unmasked(kmer_val, pos);
+                }
+                continue;
+            }
         }
-        // else: sequence contained 'N' or other non-ACGT char, skip this k-mer
+        // Sequence contained 'N' or other non-ACGT char, skip this k-mer
+        invalid_windows += 1;
+    }
+    invalid_windows
+}
+
+// --canonical-balance tally of k-mer occurrences by the orientation they were read in.
+// Palindromic k-mers are their own reverse complement, so they are tallied apart.
+#[derive(Default)]
+struct CanonicalBalance {
+    forward: u64,
+    reverse: u64,
+    palindromic: u64,
+}
+
+impl CanonicalBalance {
+    fn add(&mut self, canonical_kmer: u64, k: u8, forward: bool) {
+        if reverse_complement_u64(canonical_kmer, k) == canonical_kmer {
+            self.palindromic += 1;
+        } else if forward {
+            self.forward += 1;
+        } else {
+            self.reverse += 1;
+        }
+    }
+
+    // Share of the non-palindromic occurrences read in canonical orientation; 0.5 when strands are balanced.
+    fn forward_fraction(&self) -> f64 {
+        let oriented = self.forward + self.reverse;
+        if oriented == 0 {
+            0.0
+        } else {
+            self.forward as f64 / oriented as f64
+        }
+    }
+
+    fn report(&self) {
+        warn!(
+            "Canonical balance: {} k-mer occurrences read in canonical orientation, {} in reverse-complement orientation \
+             (forward fraction {:.4}); {} palindromic occurrences not counted",
+            self.forward,
+            self.reverse,
+            self.forward_fraction(),
+            self.palindromic
+        );
+    }
+}
+
+// Writes the --matrix output: one row per k-mer whose counts summed over all input files reach
+// `--min-count`, with a column per input file, sorted by k-mer like the regular output.
+fn write_count_matrix(
+    args: &CountArgs,
+    k: u8,
+    per_file_counts: Vec<KmerCounts>,
+    output_path: &Path,
+) -> Result<()> {
+    let num_files = per_file_counts.len();
+    let mut rows: BTreeMap<u64, Vec<usize>> = BTreeMap::new();
+    for (file_idx, file_counts) in per_file_counts.into_iter().enumerate() {
+        for (kmer_val, count) in file_counts.into_counts() {
+            rows.entry(kmer_val).or_insert_with(|| vec![0; num_files])[file_idx] = count;
+        }
+    }
+
+    let mut writer = get_output_writer(output_path).with_context(|| {
+        format!(
+            "Failed to get output writer for counts file: {:?}",
+            output_path
+        )
+    })?;
+    // Columns are named after the input files
+    write!(writer, "{}", if args.with_revcomp { "kmer\trevcomp" } else { "kmer" })?;
+    for input_path in &args.input_files {
+        let column_name = input_path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| input_path.to_string_lossy().into_owned());
+        write!(writer, "\t{}", column_name)?;
+    }
+    writeln!(writer)?;
+
+    let mut rows_written: usize = 0;
+    for (kmer_val, counts) in rows {
+        if !count_in_bounds(args, counts.iter().sum::<usize>() as f64) {
+            continue;
+        }
+        write!(writer, "{}", kmer_columns(args, kmer_val, k)?)?;
+        for count in counts {
+            write!(writer, "\t{}", count)?;
+        }
+        writeln!(writer).context("Failed to write k-mer count row to output file")?;
+        rows_written += 1;
+    }
+
+    finalize_writer(writer).context("Failed to finish output writer")?;
+    info!(
+        "Successfully wrote {} k-mer rows for {} input files to {:?}",
+        rows_written, num_files, output_path
+    );
+    Ok(())
+}
+
+// Label of length bin `bin_idx` for the --length-bins boundaries, e.g. `100-999` or `1000+`.
+fn length_bin_label(boundaries: &[usize], bin_idx: usize) -> String {
+    let lower = if bin_idx == 0 { 0 } else { boundaries[bin_idx - 1] };
+    match boundaries.get(bin_idx) {
+        Some(upper) => format!("{}-{}", lower, upper - 1),
+        None => format!("{}+", lower),
+    }
+}
+
+// Writes the --length-bins output: kmer<TAB>bin<TAB>count for each bin a k-mer occurs in with
+// at least `--min-count` occurrences, sorted by k-mer and then by bin.
+fn write_length_binned_counts(
+    args: &CountArgs,
+    k: u8,
+    per_bin_counts: Vec<KmerCounts>,
+    output_path: &Path,
+) -> Result<()> {
+    let mut rows: Vec<(u64, usize, usize)> = Vec::new();
+    for (bin_idx, bin_counts) in per_bin_counts.into_iter().enumerate() {
+        rows.extend(
+            bin_counts
+                .into_counts()
+                .filter(|&(_, count)| count_in_bounds(args, count as f64))
+                .map(|(kmer_val, count)| (kmer_val, bin_idx, count)),
+        );
+    }
+    rows.sort_unstable();
+
+    let mut writer = get_output_writer(output_path).with_context(|| {
+        format!(
+            "Failed to get output writer for counts file: {:?}",
+            output_path
+        )
+    })?;
+    let labels: Vec<String> = (0..=args.length_bins.len())
+        .map(|bin_idx| length_bin_label(&args.length_bins, bin_idx))
+        .collect();
+    for &(kmer_val, bin_idx, count) in &rows {
+        let kmer_str = kmer_columns(args, kmer_val, k)?;
+        writeln!(writer, "{}\t{}\t{}", kmer_str, labels[bin_idx], count)
+            .context("Failed to write k-mer count row to output file")?;
+    }
+
+    finalize_writer(writer).context("Failed to finish output writer")?;
+    info!(
+        "Successfully wrote {} k-mer rows for {} length bins to {:?}",
+        rows.len(),
+        labels.len(),
+        output_path
+    );
+    Ok(())
+}
+
+// Approximate size of a count map holding `entries` k-mers: hashbrown tables (which DashMap
+// shards are made of too) keep at most 7/8 of a power-of-two number of buckets full, with one
+// control byte per bucket beside each (k-mer, count) slot.
+fn count_map_memory_bytes(entries: u64, count_type: CountType) -> u64 {
+    let buckets = (entries.saturating_mul(8) / 7 + 1).next_power_of_two().max(4);
+    buckets * (count_slot_bytes(count_type) + 1)
+}
+
+// Prints the --estimate-only report: the estimated distinct k-mers and what counting them would take.
+fn write_count_estimate(distinct_kmers: &HyperLogLog, count_type: CountType) -> Result<()> {
+    let estimate = distinct_kmers.estimate().round() as u64;
+    let table_bytes = count_map_memory_bytes(estimate, count_type);
+    // The map reaches its final size by doubling, briefly holding the half-size table alongside
+    let memory_bytes = table_bytes + table_bytes / 2;
+    info!(
+        "Estimated {} distinct k-mers (±{:.1}%); counting them takes about {:.1} MiB",
+        estimate,
+        100.0 * distinct_kmers.relative_error(),
+        memory_bytes as f64 / (1u64 << 20) as f64
+    );
+    let mut stdout = std::io::stdout().lock();
+    writeln!(stdout, "estimated_distinct_kmers\t{}", estimate)?;
+    writeln!(stdout, "estimate_relative_error\t{:.4}", distinct_kmers.relative_error())?;
+    writeln!(stdout, "estimated_count_memory_bytes\t{}", memory_bytes)?;
+    Ok(())
+}
+
+// Writes the --approx-top k-mers and their estimated counts, most frequent first.
+fn write_approx_top(args: &CountArgs, k: u8, heavy_hitters: HeavyHitters, output_path: &Path) -> Result<()> {
+    info!(
+        "Count-Min Sketch of {:.1} MiB kept {} candidate k-mers",
+        heavy_hitters.sketch().memory_bytes() as f64 / (1u64 << 20) as f64,
+        heavy_hitters.len()
+    );
+    let mut writer = get_output_writer(output_path)
+        .with_context(|| format!("Failed to get output writer for counts file: {:?}", output_path))?;
+    let top: Vec<(u64, u64)> = heavy_hitters
+        .into_top()
+        .into_iter()
+        .filter(|&(_, estimate)| count_in_bounds(args, estimate as f64))
+        .collect();
+    info!("Writing {} approximately most frequent k-mers to output file...", top.len());
+    for (kmer_val, estimate) in top {
+        let kmer_str = kmer_columns(args, kmer_val, k)?;
+        writeln!(writer, "{}\t{}", kmer_str, estimate).context("Failed to write k-mer count to output file")?;
+    }
+    finalize_writer(writer).context("Failed to finish output writer")?;
+    info!("Successfully wrote approximate top k-mer counts to {:?}", output_path);
+    Ok(())
+}
+
+// The k-mer size to count at: -k, or the k of the --match-db database, which -k must agree with.
+fn resolve_kmer_size(args: &CountArgs) -> Result<u8> {
+    let Some(db_path) = &args.match_db else {
+        // clap requires -k without --match-db
+        return args
+            .kmer_size
+            .ok_or_else(|| OrionKmerError::Generic("-k/--kmer-size is required".to_string()).into());
+    };
+    let k_values = read_kmer_db_k_values(db_path)
+        .with_context(|| format!("Failed to read the k-mer size of --match-db {:?}", db_path))?;
+    match (args.kmer_size, k_values.as_slice()) {
+        (Some(user_k), _) if k_values.contains(&user_k) => Ok(user_k),
+        (Some(user_k), &[db_k]) => Err(OrionKmerError::KmerSizeMismatchValidation(user_k, db_k, db_path.clone()).into()),
+        (None, &[db_k]) => {
+            info!("Counting at k={} from --match-db {:?}", db_k, db_path);
+            Ok(db_k)
+        }
+        (_, _) => Err(OrionKmerError::Generic(format!(
+            "--match-db {:?} is a multi-k database (k = {:?}); choose one of its k values with -k",
+            db_path, k_values
+        ))
+        .into()),
     }
 }
 
 pub fn run_count(args: CountArgs) -> Result<()> {
     info!("Starting count command with args: {:?}", args);
 
-    if args.kmer_size == 0 || args.kmer_size > 32 {
-        return Err(OrionKmerError::InvalidKmerSize(args.kmer_size).into());
+    let k = resolve_kmer_size(&args)?;
+    if k == 0 || k > 32 {
+        return Err(OrionKmerError::InvalidKmerSize(k).into());
+    }
+
+    if args.length_bins.first() == Some(&0)
+        || args.length_bins.windows(2).any(|pair| pair[0] >= pair[1])
+    {
+        return Err(OrionKmerError::Generic(format!(
+            "--length-bins boundaries must be positive and strictly increasing, got {:?}",
+            args.length_bins
+        ))
+        .into());
+    }
+
+    if args.approx_top == Some(0) {
+        return Err(OrionKmerError::Generic("--approx-top must be at least 1".to_string()).into());
+    }
+
+    let timer = PhaseTimer::new("count");
+    count_kmers(args, k, &timer)?;
+    timer.report();
+    Ok(())
+}
+
+// Counts the k-mers of the inputs and writes the requested output, timing its phases in `timer`.
+fn count_kmers(args: CountArgs, k: u8, timer: &PhaseTimer) -> Result<()> {
+    let iupac_max_ambiguous = args.expand_iupac.then_some(args.max_ambiguous_bases);
+    let trim_polyx_base = args.trim_polyx.map(polyx_base).transpose()?;
+    let sequence_filter =
+        SequenceFilter::new(&args.include_seq, &args.exclude_seq, args.seq_regex.as_deref())?
+            .with_subsample(args.subsample, args.subsample_seed)?;
+    let mask_kmers = timer.measure("load mask", || {
+        args.mask_kmers
+            .as_deref()
+            .map(|mask_path| load_mask_kmers(mask_path, k))
+            .transpose()
+    })?;
+    if let Some(mask) = &mask_kmers {
+        info!("Masking {} k-mers during counting", mask.len());
     }
-    let k = args.kmer_size;
 
-    let kmer_counts: DashMap<u64, AtomicUsize> = DashMap::new();
+    let mut kmer_counts = KmerCounts::new(args.single_thread, args.count_type);
+    // With --qual-weight, k-mers accumulate fractional weights here instead of kmer_counts
+    let weighted_counts: DashMap<u64, f64> = DashMap::new();
+    // With --matrix, each input file is counted into its own map
+    let mut per_file_counts: Vec<KmerCounts> = if args.matrix {
+        args.input_files
+            .iter()
+            .map(|_| KmerCounts::new(args.single_thread, args.count_type))
+            .collect()
+    } else {
+        Vec::new()
+    };
+    // With --length-bins, each read-length bin is counted into its own map
+    let mut per_bin_counts: Vec<KmerCounts> = if args.length_bins.is_empty() {
+        Vec::new()
+    } else {
+        (0..=args.length_bins.len())
+            .map(|_| KmerCounts::new(args.single_thread, args.count_type))
+            .collect()
+    };
     let num_files = args.input_files.len() as u64;
+    let mut per_file_report: Vec<(String, FileKmerStats)> = Vec::new();
+    // Bases over all input files, logged once they have been read
+    let mut input_bases = BaseCounts::default();
+    // With --emit-orientation, how often each k-mer was read in its canonical orientation
+    let mut forward_counts: HashMap<u64, usize> = HashMap::new();
+    let mut canonical_balance = CanonicalBalance::default();
+    // With --estimate-only, a sketch of the k-mers in place of their counts
+    let mut distinct_kmers = HyperLogLog::default();
+    // With --approx-top, a sketch of the k-mer counts and the most frequent k-mers in their place
+    let mut heavy_hitters = args.approx_top.map(|top| HeavyHitters::new(top, CountMinSketch::default()));
 
     track_progress_and_resources("Counting k-mers from input files", num_files, |pb_files| {
-        for input_path in &args.input_files {
+        for (file_idx, input_path) in args.input_files.iter().enumerate() {
+            let file_counts = match per_file_counts.get_mut(file_idx) {
+                Some(file_counts) => file_counts,
+                None => &mut kmer_counts,
+            };
             let path_str = input_path.to_string_lossy();
             info!("Processing file: {}", path_str);
             // Update progress bar message for the current file
             pb_files.set_message(format!("Processing: {}", path_str));
 
-            // Use get_decompressed_input_reader to handle potential compression
-            let input_buf_reader = get_decompressed_input_reader(input_path)
-                .with_context(|| format!("Failed to get input reader for file: {}", path_str))?;
-
-            // Pass the BufRead to parse_fastx_reader
-            let mut reader = parse_fastx_reader(input_buf_reader)
-                .with_context(|| format!("Failed to parse FASTA/Q content from: {}", path_str))?;
+            let mut reader = open_sequence_reader(input_path)?;
 
             info!("Processing records from {}...", path_str);
             let mut record_count = 0;
-            while let Some(record) = reader.next() {
+            let mut short_records = 0;
+            let mut invalid_windows = 0;
+            let mut file_bases = BaseCounts::default();
+            // With --per-record-unique, the distinct k-mers of the current record
+            let mut record_kmers: HashSet<u64> = HashSet::new();
+            let mut file_stats = FileKmerStats::default();
+            let mut note_kmer = |kmer: u64| {
+                if args.per_file_report.is_some() {
+                    file_stats.add(kmer);
+                }
+            };
+            while let Some(record) = timer.measure("parse", || reader.next()) {
+                if !take_input_record() {
+                    break;
+                }
                 let record =
                     record.with_context(|| format!("Error reading record from {}", path_str))?;
-                let norm_seq = record.normalize(false);
-                process_sequence_chunk(&norm_seq, k, &kmer_counts);
+                if !sequence_filter.matches(record.id()) {
+                    continue;
+                }
                 record_count += 1;
-                if record_count % 100_000 == 0 {
+                if !args.allow_non_dna {
+                    check_dna_record(record.raw_seq(), record.id(), &path_str)?;
+                }
+                // Keep IUPAC codes intact when they are going to be expanded
+                let norm_seq = timer.measure("k-mer extraction", || record.normalize(args.expand_iupac));
+                file_bases.add_sequence(&norm_seq);
+                if norm_seq.len() < k as usize {
+                    short_records += 1;
+                    continue;
+                }
+                let record_counts = if per_bin_counts.is_empty() {
+                    &mut *file_counts
+                } else {
+                    let bin_idx = args
+                        .length_bins
+                        .partition_point(|&boundary| boundary <= record.sequence().len());
+                    &mut per_bin_counts[bin_idx]
+                };
+                invalid_windows += timer.measure("k-mer extraction", || {
+                    let norm_seq = match trim_polyx_base {
+                        Some(base) => trim_polyx(&norm_seq, base, args.polyx_min_run),
+                        None => &norm_seq,
+                    };
+                    if args.estimate_only {
+                        process_sequence_chunk(
+                            norm_seq,
+                            k,
+                            iupac_max_ambiguous,
+                            mask_kmers.as_ref(),
+                            |kmer, _, forward| {
+                                if args.canonical_balance {
+                                    canonical_balance.add(kmer, k, forward);
+                                }
+                                distinct_kmers.add(kmer);
+                            },
+                        )
+                    } else if let Some(heavy_hitters) = &mut heavy_hitters {
+                        process_sequence_chunk(
+                            norm_seq,
+                            k,
+                            iupac_max_ambiguous,
+                            mask_kmers.as_ref(),
+                            |kmer, _, forward| {
+                                heavy_hitters.add(kmer);
+                                note_kmer(kmer);
+                                if args.canonical_balance {
+                                    canonical_balance.add(kmer, k, forward);
+                                }
+                            },
+                        )
+                    } else if args.qual_weight {
+                        // Normalization keeps the sequence length, so qualities stay aligned
+                        let qual = record.qual();
+                        process_sequence_chunk(
+                            norm_seq,
+                            k,
+                            iupac_max_ambiguous,
+                            mask_kmers.as_ref(),
+                            |kmer, pos, forward| {
+                                if args.canonical_balance {
+                                    canonical_balance.add(kmer, k, forward);
+                                }
+                                let weight = qual.map_or(1.0, |qual| {
+                                    quality_weight(&qual[pos..pos + k as usize], args.full_weight_qual)
+                                });
+                                *weighted_counts.entry(kmer).or_insert(0.0) += weight;
+                                note_kmer(kmer);
+                            },
+                        )
+                    } else if args.per_record_unique {
+                        record_kmers.clear();
+                        let record_invalid_windows = process_sequence_chunk(
+                            norm_seq,
+                            k,
+                            iupac_max_ambiguous,
+                            mask_kmers.as_ref(),
+                            |kmer, _, forward| {
+                                if args.canonical_balance {
+                                    canonical_balance.add(kmer, k, forward);
+                                }
+                                record_kmers.insert(kmer);
+                            },
+                        );
+                        for &kmer in &record_kmers {
+                            record_counts.add(kmer);
+                            note_kmer(kmer);
+                        }
+                        record_invalid_windows
+                    } else {
+                        process_sequence_chunk(
+                            norm_seq,
+                            k,
+                            iupac_max_ambiguous,
+                            mask_kmers.as_ref(),
+                            |kmer, _, forward| {
+                                record_counts.add(kmer);
+                                note_kmer(kmer);
+                                if args.emit_orientation && forward {
+                                    *forward_counts.entry(kmer).or_insert(0) += 1;
+                                }
+                                if args.canonical_balance {
+                                    canonical_balance.add(kmer, k, forward);
+                                }
+                            },
+                        )
+                    }
+                });
+                if is_progress_record(record_count) {
                     debug!("Processed {} records from {}", record_count, path_str);
                 }
                 // If we wanted a per-file progress bar based on records, we'd need total records first.
@@ -81,8 +837,21 @@ pub fn run_count(args: CountArgs) -> Result<()> {
                 "Finished processing {} records from {}. Unique k-mers so far: {}",
                 record_count,
                 path_str,
-                kmer_counts.len()
+                file_counts.len() + weighted_counts.len()
             );
+            report_short_records(&path_str, short_records, record_count, k);
+            report_base_counts(&path_str, &file_bases);
+            input_bases.add(&file_bases);
+            if args.report_invalid {
+                warn!(
+                    "Skipped {} k-mer windows containing N or other non-ACGT characters in {}",
+                    invalid_windows, path_str
+                );
+            }
+            if args.per_file_report.is_some() {
+                file_stats.bases = file_bases;
+                per_file_report.push((path_str.into_owned(), file_stats));
+            }
             pb_files.inc(1); // Increment file progress bar
         }
         Ok(())
@@ -90,33 +859,93 @@ pub fn run_count(args: CountArgs) -> Result<()> {
 
     info!(
         "Finished processing all input files. Found {} unique canonical k-mers.",
-        kmer_counts.len()
+        kmer_counts.len() + weighted_counts.len()
     );
+    report_base_counts("all input files", &input_bases);
+    if args.canonical_balance {
+        canonical_balance.report();
+    }
+    if args.estimate_only {
+        return write_count_estimate(&distinct_kmers, args.count_type);
+    }
+    let output_path = args
+        .output_file
+        .as_deref()
+        .ok_or_else(|| OrionKmerError::Generic("-o is required unless --estimate-only is given".to_string()))?;
+
+    if args.fail_on_empty {
+        let counted = kmer_counts.len()
+            + weighted_counts.len()
+            + heavy_hitters.as_ref().map_or(0, HeavyHitters::len)
+            + per_file_counts.iter().chain(&per_bin_counts).map(KmerCounts::len).sum::<usize>();
+        if counted == 0 {
+            return Err(OrionKmerError::Generic(format!(
+                "--fail-on-empty: no k-mers (k={}) were counted from {:?}; the inputs may be empty, header-only or shorter than k",
+                k, args.input_files
+            ))
+            .into());
+        }
+    }
+
+    let _write_phase = timer.phase("sort and write");
+    if let Some(report_path) = &args.per_file_report {
+        write_per_file_report(report_path, &per_file_report)?;
+    }
+    if args.matrix {
+        return write_count_matrix(&args, k, per_file_counts, output_path);
+    }
+    if !per_bin_counts.is_empty() {
+        return write_length_binned_counts(&args, k, per_bin_counts, output_path);
+    }
+    if let Some(heavy_hitters) = heavy_hitters {
+        return write_approx_top(&args, k, heavy_hitters, output_path);
+    }
 
     // Outputting results
-    debug!("Opening output file: {:?}", args.output_file);
+    debug!("Opening output file: {:?}", output_path);
     // Use get_output_writer to handle potential compression
-    let mut writer = get_output_writer(&args.output_file).with_context(|| {
+    let mut writer = get_output_writer(output_path).with_context(|| {
         format!(
             "Failed to get output writer for counts file: {:?}",
-            args.output_file
+            output_path
         )
     })?;
 
+    if args.qual_weight {
+        let mut weighted_vec: Vec<(u64, f64)> = weighted_counts
+            .into_iter()
+            .filter(|&(_, weight)| count_in_bounds(&args, weight))
+            .collect();
+        weighted_vec.sort_by_key(|item| item.0);
+        if args.sort == CountSortOrder::Count {
+            // Stable, so equal weights stay in k-mer order
+            weighted_vec.sort_by(|a, b| b.1.total_cmp(&a.1));
+        }
+        info!(
+            "Writing {} k-mers (weighted count >= {}) to output file...",
+            weighted_vec.len(),
+            args.min_count
+        );
+        for (kmer_val, weight) in weighted_vec {
+            let kmer_str = kmer_columns(&args, kmer_val, k)?;
+            writeln!(writer, "{}\t{:.4}", kmer_str, weight)
+                .context("Failed to write weighted k-mer count to output file")?;
+        }
+        finalize_writer(writer).context("Failed to finish output writer")?;
+        info!("Successfully wrote weighted k-mer counts to {:?}", output_path);
+        return Ok(());
+    }
+
     let mut kmer_vec: Vec<(u64, usize)> = kmer_counts
-        .into_iter()
-        .filter_map(|(kmer_val, count_atomic)| {
-            let count = count_atomic.into_inner();
-            if count >= args.min_count {
-                Some((kmer_val, count))
-            } else {
-                None
-            }
-        })
+        .into_counts()
+        .filter(|&(_, count)| count_in_bounds(&args, count as f64))
         .collect();
 
     // Sort for consistent output (optional, but good for testing)
-    kmer_vec.sort_by_key(|item| item.0);
+    match args.sort {
+        CountSortOrder::Value => kmer_vec.sort_unstable_by_key(|item| item.0),
+        CountSortOrder::Count => kmer_vec.sort_unstable_by_key(|&(kmer, count)| (std::cmp::Reverse(count), kmer)),
+    }
 
     info!(
         "Writing {} k-mers (count >= {}) to output file...",
@@ -125,17 +954,22 @@ pub fn run_count(args: CountArgs) -> Result<()> {
     );
 
     for (kmer_val, count) in kmer_vec {
-        let kmer_seq_bytes = u64_to_seq(kmer_val, k);
         // This allocation to String can be slow for many k-mers.
         // Consider writing bytes directly if performance becomes an issue.
-        let kmer_str = String::from_utf8(kmer_seq_bytes)
-            .context("Failed to convert k-mer bytes to string (should not happen)")?;
-        writeln!(writer, "{}\t{}", kmer_str, count)
-            .context("Failed to write k-mer count to output file")?;
+        let kmer_str = kmer_columns(&args, kmer_val, k)?;
+        if args.emit_orientation {
+            // Majority orientation of the k-mer's occurrences; ties (e.g. palindromes) count as +
+            let forward = forward_counts.get(&kmer_val).copied().unwrap_or(0);
+            let strand = if 2 * forward >= count { '+' } else { '-' };
+            writeln!(writer, "{}\t{}\t{}", kmer_str, count, strand)
+        } else {
+            writeln!(writer, "{}\t{}", kmer_str, count)
+        }
+        .context("Failed to write k-mer count to output file")?;
     }
 
-    writer.flush().context("Failed to flush output writer")?;
-    info!("Successfully wrote k-mer counts to {:?}", args.output_file);
+    finalize_writer(writer).context("Failed to finish output writer")?;
+    info!("Successfully wrote k-mer counts to {:?}", output_path);
 
     Ok(())
 }
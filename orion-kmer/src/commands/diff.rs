@@ -0,0 +1,105 @@
+use anyhow::{Context, Result};
+use log::info;
+use serde::Serialize;
+
+use crate::{
+    cli::DiffArgs,
+    errors::OrionKmerError,
+    utils::{check_canonical_policy, finalize_writer, get_output_writer, load_kmer_db_v2},
+};
+
+#[derive(Serialize, Debug)]
+struct ReferenceChange {
+    reference_name: String,
+    old_kmers: usize,
+    new_kmers: usize,
+    gained_kmers: usize, // In the new version only
+    lost_kmers: usize,   // In the old version only
+}
+
+#[derive(Serialize, Debug)]
+struct DiffOutput {
+    old_path: String,
+    new_path: String,
+    kmer_size: u8,
+    added_references: Vec<String>,
+    removed_references: Vec<String>,
+    common_references: Vec<ReferenceChange>, // Sorted by name, including unchanged references
+}
+
+pub fn run_diff(args: DiffArgs) -> Result<()> {
+    info!("Starting diff command with args: {:?}", args);
+
+    let old_db = load_kmer_db_v2(&args.old)?;
+    let new_db = load_kmer_db_v2(&args.new)?;
+    if old_db.k != new_db.k {
+        return Err(OrionKmerError::KmerSizeMismatch(old_db.k, new_db.k).into());
+    }
+    // Hashed and unhashed k-mers (or different seeds) cannot be compared value by value
+    if old_db.hashing != new_db.hashing {
+        return Err(
+            OrionKmerError::HashingMismatch(old_db.hashing, new_db.hashing, args.new.clone()).into(),
+        );
+    }
+    check_canonical_policy(&old_db, &new_db, &args.new)?;
+
+    let mut added_references: Vec<String> = new_db
+        .references
+        .keys()
+        .filter(|name| !old_db.references.contains_key(*name))
+        .cloned()
+        .collect();
+    added_references.sort();
+    let mut removed_references: Vec<String> = old_db
+        .references
+        .keys()
+        .filter(|name| !new_db.references.contains_key(*name))
+        .cloned()
+        .collect();
+    removed_references.sort();
+
+    let mut common_references: Vec<ReferenceChange> = old_db
+        .references
+        .iter()
+        .filter_map(|(name, old_kmers)| {
+            let new_kmers = new_db.references.get(name)?;
+            Some(ReferenceChange {
+                reference_name: name.clone(),
+                old_kmers: old_kmers.len(),
+                new_kmers: new_kmers.len(),
+                gained_kmers: new_kmers.difference(old_kmers).count(),
+                lost_kmers: old_kmers.difference(new_kmers).count(),
+            })
+        })
+        .collect();
+    common_references.sort_by(|a, b| a.reference_name.cmp(&b.reference_name));
+
+    let output_data = DiffOutput {
+        old_path: args.old.to_string_lossy().into_owned(),
+        new_path: args.new.to_string_lossy().into_owned(),
+        kmer_size: old_db.k,
+        added_references,
+        removed_references,
+        common_references,
+    };
+    info!(
+        "{} references added, {} removed, {} changed",
+        output_data.added_references.len(),
+        output_data.removed_references.len(),
+        output_data
+            .common_references
+            .iter()
+            .filter(|change| change.gained_kmers > 0 || change.lost_kmers > 0)
+            .count()
+    );
+
+    let mut writer = get_output_writer(&args.output_file).with_context(|| {
+        format!("Failed to get output writer for diff JSON: {:?}", args.output_file)
+    })?;
+    serde_json::to_writer_pretty(&mut writer, &output_data)
+        .with_context(|| format!("Failed to write diff JSON to {:?}", args.output_file))?;
+    finalize_writer(writer).context("Failed to finish diff JSON writer")?;
+
+    info!("Successfully wrote database diff to {:?}", args.output_file);
+    Ok(())
+}
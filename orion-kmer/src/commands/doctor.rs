@@ -0,0 +1,132 @@
+use anyhow::{Context, Result};
+use log::{info, warn};
+use std::{
+    fs,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    cli::DoctorArgs,
+    commands::bench::synthetic_genome,
+    db_types::KmerDbV2,
+    errors::OrionKmerError,
+    kmer::{canonical_u64, seq_to_u64},
+    utils::{
+        finalize_writer, get_decompressed_input_reader, get_output_writer, load_kmer_db_v2, read_kmer_db_format,
+        write_kmer_db, KMER_DB_FORMAT_VERSION,
+    },
+};
+
+// Extensions of the output codecs checked, `none` being uncompressed output.
+const CODECS: [&str; 7] = ["none", "gz", "bgz", "bz2", "xz", "zst", "lz4"];
+
+// K-mer size of the database written for the round-trip check.
+const DOCTOR_KMER_SIZE: u8 = 21;
+
+// Writes `data` to `path` with the codec of its extension and reads it back, as every command does.
+fn codec_round_trip(path: &Path, data: &[u8]) -> Result<()> {
+    let mut writer = get_output_writer(path)?;
+    writer.write_all(data).context("Failed to write round-trip data")?;
+    finalize_writer(writer)?;
+    let mut read_back = Vec::with_capacity(data.len());
+    get_decompressed_input_reader(path)?
+        .read_to_end(&mut read_back)
+        .context("Failed to read round-trip data back")?;
+    if read_back != data {
+        return Err(OrionKmerError::Generic(format!(
+            "read back {} bytes that differ from the {} written",
+            read_back.len(),
+            data.len()
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+// Writes a one-reference database of the genome's k-mers and loads it back, returning its
+// format and format version as read from its header.
+fn database_round_trip(path: &Path, genome: &[u8]) -> Result<(&'static str, u32)> {
+    let mut kmer_db = KmerDbV2::new(DOCTOR_KMER_SIZE);
+    let kmers = genome
+        .windows(DOCTOR_KMER_SIZE as usize)
+        .filter_map(|window| seq_to_u64(window, DOCTOR_KMER_SIZE))
+        .map(|kmer_val| canonical_u64(kmer_val, DOCTOR_KMER_SIZE))
+        .collect();
+    kmer_db.references.insert("doctor".to_string(), kmers);
+    write_kmer_db(&kmer_db, path)?;
+    let db_format = read_kmer_db_format(path)?;
+    if db_format.version != KMER_DB_FORMAT_VERSION {
+        return Err(OrionKmerError::Generic(format!(
+            "database header has format version {}, not the {} written",
+            db_format.version, KMER_DB_FORMAT_VERSION
+        ))
+        .into());
+    }
+    let loaded_db = load_kmer_db_v2(path)?;
+    if loaded_db.k != kmer_db.k || loaded_db.references != kmer_db.references {
+        return Err(OrionKmerError::Generic("loaded database differs from the one written".to_string()).into());
+    }
+    Ok((db_format.format, db_format.version))
+}
+
+// Runs every check in `work_dir`, printing one key<TAB>value line per result, and returns the
+// number of failed checks.
+fn run_checks(work_dir: &Path, num_threads: usize, writer: &mut impl Write) -> Result<usize> {
+    writeln!(writer, "orion_kmer_version\t{}", env!("CARGO_PKG_VERSION"))?;
+    writeln!(writer, "threads\t{}", num_threads)?;
+    writeln!(writer, "logical_cores\t{}", num_cpus::get())?;
+
+    let genome = synthetic_genome(1 << 16, 42);
+    let mut failures = 0;
+    match database_round_trip(&work_dir.join("doctor.db"), &genome) {
+        Ok((format, version)) => {
+            writeln!(writer, "database_format\t{}", format)?;
+            writeln!(writer, "database_format_version\t{}", version)?;
+            writeln!(writer, "database_round_trip\tok")?;
+        }
+        Err(e) => {
+            warn!("Database round trip failed: {:#}", e);
+            writeln!(writer, "database_round_trip\tfailed: {:#}", e)?;
+            failures += 1;
+        }
+    }
+    for codec in CODECS {
+        let path = match codec {
+            "none" => work_dir.join("doctor.txt"),
+            _ => work_dir.join(format!("doctor.txt.{}", codec)),
+        };
+        match codec_round_trip(&path, &genome) {
+            Ok(()) => writeln!(writer, "codec\t{}\tok", codec)?,
+            Err(e) => {
+                warn!("Codec {} round trip failed: {:#}", codec, e);
+                writeln!(writer, "codec\t{}\tfailed: {:#}", codec, e)?;
+                failures += 1;
+            }
+        }
+    }
+    Ok(failures)
+}
+
+pub fn run_doctor(args: DoctorArgs, num_threads: usize) -> Result<()> {
+    info!("Starting doctor command with args: {:?}", args);
+
+    // The round-trip files live in a scratch directory removed afterwards
+    let work_dir: PathBuf = std::env::temp_dir().join(format!("orion-kmer-doctor-{}", std::process::id()));
+    fs::create_dir_all(&work_dir)
+        .with_context(|| format!("Failed to create doctor directory: {:?}", work_dir))?;
+    let failures = run_checks(&work_dir, num_threads, &mut std::io::stdout().lock());
+    fs::remove_dir_all(&work_dir)
+        .with_context(|| format!("Failed to remove doctor directory: {:?}", work_dir))?;
+    let failures = failures?;
+    if failures > 0 {
+        return Err(OrionKmerError::Generic(format!(
+            "{} of the {} environment checks failed",
+            failures,
+            CODECS.len() + 1
+        ))
+        .into());
+    }
+    info!("All {} environment checks passed", CODECS.len() + 1);
+    Ok(())
+}
@@ -0,0 +1,343 @@
+use anyhow::{Context, Result};
+use log::{info, warn};
+use serde::Serialize;
+use std::{
+    fs,
+    io::{self, BufRead, Write},
+    path::Path,
+};
+
+use crate::{
+    cli::Commands,
+    commands::classify::{classify_output_paths, parse_coverage_track, parse_depth_track, parse_extract_reads},
+    errors::OrionKmerError,
+    utils::{get_decompressed_input_reader, is_tar_archive},
+};
+
+#[derive(Serialize, Debug)]
+struct PlannedInput {
+    role: &'static str, // What the command reads the file as, e.g. "sequences" or "database"
+    path: String,
+    size_bytes: u64,
+}
+
+// What a command would read and write, printed by --dry-run.
+#[derive(Serialize, Debug)]
+struct DryRunPlan {
+    command: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    kmer_size: Option<u8>, // k given on the command line; databases are not loaded to find theirs
+    inputs: Vec<PlannedInput>,
+    outputs: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    estimated_references: Option<usize>, // References `build` would add from its inputs, when known without reading them
+}
+
+impl DryRunPlan {
+    fn new(command: &'static str) -> Self {
+        DryRunPlan {
+            command,
+            kmer_size: None,
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            estimated_references: None,
+        }
+    }
+
+    fn input(&mut self, role: &'static str, path: &Path) {
+        self.inputs.push(PlannedInput {
+            role,
+            path: path.to_string_lossy().into_owned(),
+            size_bytes: 0,
+        });
+    }
+
+    fn output(&mut self, path: &Path) {
+        self.outputs.push(path.to_string_lossy().into_owned());
+    }
+}
+
+// Opens `path` the way the command would and reads its first bytes, so missing files,
+// permission problems and corrupt compression headers surface before any work starts.
+fn check_input(path: &Path) -> Result<u64> {
+    let mut reader = get_decompressed_input_reader(path)?;
+    reader
+        .fill_buf()
+        .with_context(|| format!("Failed to read input file: {:?}", path))?;
+    Ok(fs::metadata(path)?.len())
+}
+
+fn plan_command(command: &Commands) -> Result<DryRunPlan> {
+    let plan = match command {
+        Commands::Count(args) => {
+            let mut plan = DryRunPlan::new("count");
+            plan.kmer_size = args.kmer_size;
+            if let Some(db_path) = &args.match_db {
+                plan.input("database", db_path);
+            }
+            for path in &args.input_files {
+                plan.input("sequences", path);
+            }
+            if let Some(mask_path) = &args.mask_kmers {
+                plan.input("mask_kmers", mask_path);
+            }
+            if let Some(output_path) = &args.output_file {
+                plan.output(output_path);
+            }
+            if let Some(report_path) = &args.per_file_report {
+                plan.output(report_path);
+            }
+            plan
+        }
+        Commands::Build(args) => {
+            let mut plan = DryRunPlan::new("build");
+            plan.kmer_size = args.kmer_size;
+            for path in &args.genome_files {
+                plan.input("sequences", path);
+            }
+            if let Some(counts_path) = &args.from_counts {
+                plan.input("count_table", counts_path);
+            }
+            if let Some(append_path) = &args.append {
+                plan.input("database", append_path);
+            }
+            if let Some(checkpoint_path) = &args.checkpoint {
+                if args.resume && checkpoint_path.exists() {
+                    plan.input("checkpoint", checkpoint_path);
+                }
+                plan.output(checkpoint_path);
+            }
+            plan.output(&args.output_file);
+            for path in [&args.uniqueness_report, &args.overlap_matrix, &args.sorted_index].into_iter().flatten() {
+                plan.output(path);
+            }
+            // Per-record and tar archive references can only be counted by reading the inputs
+            plan.estimated_references = if args.from_counts.is_some() {
+                Some(1)
+            } else if args.per_record || args.genome_files.iter().any(|path| is_tar_archive(path)) {
+                None
+            } else {
+                Some(args.genome_files.len())
+            };
+            plan
+        }
+        Commands::Compare(args) => {
+            let mut plan = DryRunPlan::new("compare");
+            plan.kmer_size = args.kmer_size;
+            if let Some(db1_path) = &args.db1 {
+                plan.input("database", db1_path);
+            }
+            for path in &args.stream_fasta {
+                plan.input("sequences", path);
+            }
+            if let Some(db2_path) = &args.db2 {
+                plan.input("database", db2_path);
+            }
+            if let Some(fasta_path) = &args.against_fasta {
+                plan.input("sequences", fasta_path);
+            }
+            for path in [&args.output_file, &args.tsv, &args.sqlite].into_iter().flatten() {
+                plan.output(path);
+            }
+            plan
+        }
+        Commands::Diff(args) => {
+            let mut plan = DryRunPlan::new("diff");
+            plan.input("database", &args.old);
+            plan.input("database", &args.new);
+            plan.output(&args.output_file);
+            plan
+        }
+        Commands::Query(args) => {
+            let mut plan = DryRunPlan::new("query");
+            for path in &args.database_files {
+                plan.input("database", path);
+            }
+            for path in &args.bloom_files {
+                plan.input("bloom_filter", path);
+            }
+            if let Some(mask_path) = &args.mask {
+                plan.input("mask", mask_path);
+            }
+            plan.input("sequences", &args.reads_file);
+            plan.output(&args.output_file);
+            if let Some(bed_path) = &args.bed {
+                plan.output(bed_path);
+            }
+            if let Some(bin_dir) = &args.bin_output_dir {
+                plan.output(bin_dir);
+            }
+            plan
+        }
+        Commands::Classify(args) => {
+            let mut plan = DryRunPlan::new("classify");
+            plan.kmer_size = args.kmer_size;
+            for path in &args.input_files {
+                plan.input("sequences", path);
+            }
+            for path in &args.database_files {
+                plan.input("database", path);
+            }
+            if let Some(groups_path) = &args.groups {
+                plan.input("groups", groups_path);
+            }
+            let (json_path, tsv_path) = classify_output_paths(args)?;
+            plan.output(&json_path);
+            for path in [&tsv_path, &args.full_tsv, &args.sqlite, &args.dump_input_kmers, &args.krona].into_iter().flatten() {
+                plan.output(path);
+            }
+            for spec in &args.coverage_track {
+                plan.output(&parse_coverage_track(spec)?.1);
+            }
+            for spec in &args.depth_track {
+                plan.output(&parse_depth_track(spec)?.1);
+            }
+            for spec in &args.extract_reads {
+                plan.output(&parse_extract_reads(spec)?.1);
+            }
+            plan
+        }
+        Commands::Dump(args) => {
+            let mut plan = DryRunPlan::new("dump");
+            plan.input("database", &args.database_file);
+            plan.output(&args.output_file);
+            plan
+        }
+        Commands::Markers(args) => {
+            let mut plan = DryRunPlan::new("markers");
+            for path in &args.target_files {
+                plan.input("sequences", path);
+            }
+            for path in &args.background_files {
+                plan.input("database", path);
+            }
+            plan.output(&args.output_file);
+            plan
+        }
+        Commands::Neighbors(args) => {
+            let mut plan = DryRunPlan::new("neighbors");
+            plan.kmer_size = u8::try_from(args.kmer.len()).ok();
+            plan.input("database", &args.database_file);
+            plan
+        }
+        Commands::Search(args) => {
+            let mut plan = DryRunPlan::new("search");
+            if let Some(sketch_path) = &args.sketch_file {
+                plan.input("sketch", sketch_path);
+            }
+            if let Some(query_path) = &args.query_file {
+                plan.input("sequences", query_path);
+            }
+            plan.input("database", &args.database_file);
+            plan.output(&args.output_file);
+            plan
+        }
+        Commands::Validate(args) => {
+            let mut plan = DryRunPlan::new("validate");
+            plan.input("sequences", &args.input_file);
+            plan
+        }
+        Commands::Sum(args) => {
+            let mut plan = DryRunPlan::new("sum");
+            for path in &args.input_files {
+                plan.input("count_table", path);
+            }
+            plan.output(&args.output_file);
+            plan
+        }
+        Commands::Convert(args) => {
+            let mut plan = DryRunPlan::new("convert");
+            plan.input("database", &args.input_file);
+            plan.output(&args.output_file);
+            plan
+        }
+        Commands::Split(args) => {
+            let mut plan = DryRunPlan::new("split");
+            plan.input("database", &args.database_file);
+            plan.output(&args.output_dir);
+            plan
+        }
+        Commands::Info(args) => {
+            let mut plan = DryRunPlan::new("info");
+            plan.input("database", &args.database_file);
+            plan
+        }
+        Commands::Revcomp(args) => {
+            let mut plan = DryRunPlan::new("revcomp");
+            // stdin cannot be checked without consuming it
+            if args.input_file != Path::new("-") {
+                plan.input("sequences", &args.input_file);
+            }
+            if let Some(output_path) = &args.output_file {
+                plan.output(output_path);
+            }
+            plan
+        }
+        Commands::Ani(args) => {
+            let mut plan = DryRunPlan::new("ani");
+            plan.kmer_size = args.kmer_size;
+            for db_path in [&args.db1, &args.db2].into_iter().flatten() {
+                plan.input("database", db_path);
+            }
+            for fasta_path in [&args.fasta1, &args.fasta2].into_iter().flatten() {
+                plan.input("sequences", fasta_path);
+            }
+            if let Some(output_path) = &args.output_file {
+                plan.output(output_path);
+            }
+            plan
+        }
+        Commands::Cat(args) => {
+            let mut plan = DryRunPlan::new("cat");
+            plan.input("file", &args.input_file);
+            if let Some(output_path) = &args.output_file {
+                plan.output(output_path);
+            }
+            plan
+        }
+        Commands::Bench(args) => {
+            let mut plan = DryRunPlan::new("bench");
+            plan.kmer_size = Some(args.kmer_size);
+            if let Some(output_path) = &args.output_file {
+                plan.output(output_path);
+            }
+            plan
+        }
+        // Only writes to a scratch directory removed afterwards
+        Commands::Doctor(_) => DryRunPlan::new("doctor"),
+    };
+    Ok(plan)
+}
+
+/// Checks that every input of `command` can be opened and prints the resolved plan as JSON to
+/// stdout, without running the command. Fails if any input cannot be read, after warning
+/// about each one.
+pub fn run_dry_run(command: &Commands) -> Result<()> {
+    let mut plan = plan_command(command)?;
+    info!("Dry run of {}: checking {} inputs", plan.command, plan.inputs.len());
+
+    let mut unreadable_inputs = 0;
+    for input in &mut plan.inputs {
+        match check_input(Path::new(&input.path)) {
+            Ok(size_bytes) => input.size_bytes = size_bytes,
+            Err(e) => {
+                warn!("Cannot read {} input {:?}: {:#}", input.role, input.path, e);
+                unreadable_inputs += 1;
+            }
+        }
+    }
+    if unreadable_inputs > 0 {
+        return Err(OrionKmerError::Generic(format!(
+            "Dry run found {} of {} inputs that cannot be read",
+            unreadable_inputs,
+            plan.inputs.len()
+        ))
+        .into());
+    }
+
+    let mut stdout = io::stdout().lock();
+    serde_json::to_writer_pretty(&mut stdout, &plan).context("Failed to write dry-run plan")?;
+    writeln!(stdout)?;
+    Ok(())
+}
+
@@ -0,0 +1,288 @@
+use anyhow::{Context, Result};
+use arrow_array::{RecordBatch, StringArray, UInt64Array};
+use arrow_schema::{DataType, Field, Schema};
+use log::{info, warn};
+use md5::{Digest, Md5};
+use parquet::{arrow::ArrowWriter, basic::Compression, file::properties::WriterProperties};
+use rayon::prelude::*;
+use serde::Serialize;
+use std::{io::Write, sync::Arc};
+
+use crate::{
+    bloom::KmerBloomFilter,
+    cli::{DumpArgs, DumpFormat},
+    commands::search::SOURMASH_DNA_HASH_FUNCTION,
+    db_types::{HashFunction, KmerDbV2, KmerHashing},
+    errors::OrionKmerError,
+    kmer::{canonical_u64, hash_kmer, u64_to_seq, CanonicalPolicy},
+    utils::{finalize_writer, get_output_writer, load_kmer_db_v2, OutputWriter},
+};
+
+/// Magic bytes opening a `dump --format u64` file. The header continues with k and the number
+/// of records, each a little-endian u64, so the records start at byte 24.
+pub const U64_DUMP_MAGIC: &[u8; 8] = b"ORKU64V1";
+
+// Rows per record batch of `--format parquet`, so only one batch of rows is held at a time.
+const PARQUET_BATCH_ROWS: usize = 1 << 16;
+
+// scaled of a `--format sourmash` sketch when neither --scaled nor --num is given, as in sourmash
+const DEFAULT_SOURMASH_SCALED: u64 = 1000;
+
+// A sourmash signature file, in the layout (version 0.4) `sourmash sketch` writes.
+#[derive(Serialize, Debug)]
+struct SourmashSignature {
+    class: &'static str,
+    email: &'static str,
+    hash_function: &'static str,
+    filename: String,
+    name: String,
+    license: &'static str,
+    signatures: Vec<SourmashMinHash>,
+    version: f64,
+}
+
+#[derive(Serialize, Debug)]
+struct SourmashMinHash {
+    num: usize,
+    ksize: u32,
+    seed: u64,
+    max_hash: u64,
+    mins: Vec<u64>,
+    md5sum: String,
+    molecule: &'static str,
+}
+
+// sourmash's largest kept hash for a scaled sketch.
+fn sourmash_max_hash(scaled: u64) -> u64 {
+    match scaled {
+        1 => u64::MAX,
+        _ => (u64::MAX as f64 / scaled as f64) as u64,
+    }
+}
+
+// sourmash's md5sum of a sketch: the MD5 of the decimal k, then of each hash, in order.
+fn sourmash_md5sum(ksize: u32, mins: &[u64]) -> String {
+    let mut hasher = Md5::new();
+    hasher.update(ksize.to_string());
+    for min in mins {
+        hasher.update(min.to_string());
+    }
+    hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// The sourmash hashes of the database's unified k-mers. Hashed databases already store them,
+// provided they were hashed with the same function and seed.
+fn sourmash_hashes(kmer_db: &KmerDbV2, args: &DumpArgs) -> Result<Vec<u64>> {
+    let sourmash_hashing = KmerHashing {
+        function: HashFunction::Murmur3X64,
+        seed: args.seed,
+    };
+    let hashes = match kmer_db.hashing {
+        Some(db_hashing) => {
+            if db_hashing != sourmash_hashing {
+                return Err(OrionKmerError::HashingMismatch(
+                    Some(sourmash_hashing),
+                    Some(db_hashing),
+                    args.database_file.clone(),
+                )
+                .into());
+            }
+            // Hashes cannot be recanonicalized, so they must be of lexicographically canonical k-mers
+            if !kmer_db.canonical {
+                return Err(OrionKmerError::NonCanonicalDatabase(args.database_file.clone()).into());
+            }
+            if kmer_db.canonical_policy != CanonicalPolicy::Lexicographic {
+                return Err(OrionKmerError::CanonicalPolicyMismatch(
+                    CanonicalPolicy::Lexicographic,
+                    kmer_db.canonical_policy,
+                    args.database_file.clone(),
+                )
+                .into());
+            }
+            kmer_db.get_all_kmers_unified().into_iter().collect()
+        }
+        // Encodings are put in sourmash's canonical form (the lexicographically smaller strand)
+        // whatever form the database stores, so both strands of a k-mer hash alike
+        None => {
+            let k = kmer_db.k;
+            let canonical_kmers: Vec<u64> = kmer_db.iter_unique_kmers().map(|kmer| canonical_u64(kmer, k)).collect();
+            canonical_kmers.par_iter().map(|&kmer| hash_kmer(kmer, k, args.seed)).collect()
+        }
+    };
+    Ok(hashes)
+}
+
+// Writes the database's unified k-mers as a sourmash signature with one DNA MinHash sketch.
+fn write_sourmash_signature(kmer_db: &KmerDbV2, args: &DumpArgs, writer: &mut dyn Write) -> Result<()> {
+    let mut mins = sourmash_hashes(kmer_db, args)?;
+    mins.sort_unstable();
+    mins.dedup();
+    let (num, max_hash) = match (args.num, args.scaled.unwrap_or(DEFAULT_SOURMASH_SCALED)) {
+        (Some(0), _) => return Err(OrionKmerError::Generic("--num must be at least 1".to_string()).into()),
+        (Some(num), _) => {
+            mins.truncate(num);
+            (num, 0)
+        }
+        (None, 0) => return Err(OrionKmerError::Generic("--scaled must be at least 1".to_string()).into()),
+        (None, scaled) => {
+            let max_hash = sourmash_max_hash(scaled);
+            mins.retain(|&hash| hash <= max_hash);
+            (0, max_hash)
+        }
+    };
+    let ksize = u32::from(kmer_db.k);
+    let name = args
+        .database_file
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let signature = SourmashSignature {
+        class: "sourmash_signature",
+        email: "",
+        hash_function: SOURMASH_DNA_HASH_FUNCTION,
+        filename: args.database_file.to_string_lossy().into_owned(),
+        name,
+        license: "CC0",
+        signatures: vec![SourmashMinHash {
+            num,
+            ksize,
+            seed: args.seed,
+            max_hash,
+            md5sum: sourmash_md5sum(ksize, &mins),
+            mins,
+            molecule: "dna",
+        }],
+        version: 0.4,
+    };
+    let sketch_size = signature.signatures[0].mins.len();
+    // sourmash reads a list of signatures
+    serde_json::to_writer(&mut *writer, &[signature]).context("Failed to write sourmash signature JSON")?;
+    info!(
+        "Wrote a sourmash signature of {} hashes (k={}, {})",
+        sketch_size,
+        ksize,
+        if num > 0 { format!("num {}", num) } else { format!("max_hash {}", max_hash) }
+    );
+    Ok(())
+}
+
+// Writes the rows of `--format tsv` as Parquet, one record batch of up to PARQUET_BATCH_ROWS
+// rows at a time, returning the number of rows. kmer_seq is null for hashed databases.
+fn write_parquet(kmer_db: &KmerDbV2, writer: &mut OutputWriter) -> Result<usize> {
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("reference", DataType::Utf8, false),
+        Field::new("kmer_u64", DataType::UInt64, false),
+        Field::new("kmer_seq", DataType::Utf8, true),
+    ]));
+    let properties = WriterProperties::builder().set_compression(Compression::SNAPPY).build();
+    let mut parquet_writer = ArrowWriter::try_new(&mut *writer, schema.clone(), Some(properties))
+        .context("Failed to start Parquet output")?;
+
+    let mut rows_written = 0;
+    for (reference_name, kmer_set) in &kmer_db.references {
+        let mut kmers: Vec<u64> = kmer_set.iter().copied().collect();
+        kmers.sort_unstable();
+        for batch_kmers in kmers.chunks(PARQUET_BATCH_ROWS) {
+            let kmer_seqs: StringArray = batch_kmers
+                .iter()
+                .map(|&kmer_val| match kmer_db.hashing {
+                    Some(_) => None,
+                    None => Some(String::from_utf8_lossy(&u64_to_seq(kmer_val, kmer_db.k)).into_owned()),
+                })
+                .collect();
+            let batch = RecordBatch::try_new(
+                schema.clone(),
+                vec![
+                    Arc::new(StringArray::from(vec![reference_name.as_str(); batch_kmers.len()])),
+                    Arc::new(UInt64Array::from(batch_kmers.to_vec())),
+                    Arc::new(kmer_seqs),
+                ],
+            )
+            .context("Failed to build Parquet record batch")?;
+            parquet_writer.write(&batch).context("Failed to write k-mer rows to Parquet output")?;
+            rows_written += batch_kmers.len();
+        }
+    }
+    parquet_writer.close().context("Failed to finish Parquet output")?;
+    Ok(rows_written)
+}
+
+pub fn run_dump(args: DumpArgs) -> Result<()> {
+    info!("Starting dump command with args: {:?}", args);
+
+    let kmer_db_v2 = load_kmer_db_v2(&args.database_file)?;
+    let k = kmer_db_v2.k;
+
+    let mut writer = get_output_writer(&args.output_file).with_context(|| {
+        format!(
+            "Failed to get output writer for database dump: {:?}",
+            args.output_file
+        )
+    })?;
+
+    match args.format {
+        DumpFormat::Tsv => {
+            // Hashes cannot be decoded back into sequences, so kmer_seq is left empty
+            if kmer_db_v2.hashing.is_some() {
+                warn!("Database stores hashed k-mers; the kmer_seq column will be empty.");
+            }
+            writeln!(writer, "reference\tkmer_u64\tkmer_seq")?;
+
+            // Rows are streamed one reference at a time, sorted for reproducible output
+            let mut rows_written: usize = 0;
+            for (reference_name, kmer_set) in &kmer_db_v2.references {
+                let mut kmers: Vec<u64> = kmer_set.iter().copied().collect();
+                kmers.sort_unstable();
+                for kmer_val in kmers {
+                    let kmer_seq = if kmer_db_v2.hashing.is_some() {
+                        String::new()
+                    } else {
+                        String::from_utf8(u64_to_seq(kmer_val, k))
+                            .context("Failed to convert k-mer bytes to string (should not happen)")?
+                    };
+                    writeln!(writer, "{}\t{}\t{}", reference_name, kmer_val, kmer_seq)
+                        .context("Failed to write k-mer row to dump output")?;
+                }
+                rows_written += kmer_set.len();
+            }
+            info!("Wrote {} k-mer rows", rows_written);
+        }
+        DumpFormat::Parquet => {
+            if kmer_db_v2.hashing.is_some() {
+                warn!("Database stores hashed k-mers; the kmer_seq column will be null.");
+            }
+            let rows_written = write_parquet(&kmer_db_v2, &mut writer)?;
+            info!("Wrote {} k-mer rows as Parquet", rows_written);
+        }
+        DumpFormat::U64 => {
+            let mut occurrences: Vec<(u64, u32)> = kmer_db_v2.reference_occurrence_counts().into_iter().collect();
+            occurrences.sort_unstable();
+            writer.write_all(U64_DUMP_MAGIC)?;
+            writer.write_all(&u64::from(k).to_le_bytes())?;
+            writer.write_all(&(occurrences.len() as u64).to_le_bytes())?;
+            for (kmer_val, num_references) in &occurrences {
+                writer.write_all(&kmer_val.to_le_bytes())?;
+                writer
+                    .write_all(&num_references.to_le_bytes())
+                    .context("Failed to write k-mer record to dump output")?;
+            }
+            info!("Wrote {} binary k-mer records", occurrences.len());
+        }
+        DumpFormat::Bloom => {
+            let filter = KmerBloomFilter::from_kmer_db(&kmer_db_v2, args.fp_rate)?;
+            filter.write_to(&mut writer)?;
+            info!(
+                "Wrote a Bloom filter of {} k-mers: {} bits, {} hash functions, estimated false-positive rate {:.3e}",
+                filter.num_items,
+                filter.num_bits,
+                filter.num_hashes,
+                filter.estimated_fp_rate()
+            );
+        }
+        DumpFormat::Sourmash => write_sourmash_signature(&kmer_db_v2, &args, &mut writer)?,
+    }
+
+    finalize_writer(writer).context("Failed to finish database dump writer")?;
+    info!("Successfully dumped database to {:?}", args.output_file);
+    Ok(())
+}
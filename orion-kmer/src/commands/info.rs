@@ -0,0 +1,141 @@
+use anyhow::{Context, Result};
+use log::info;
+use std::io::Write;
+
+use crate::{
+    cli::InfoArgs,
+    db_types::{DbMetadata, KmerDbV2},
+    kmer::{reverse_complement_u64, CanonicalPolicy},
+    kmer_index::is_sorted_kmer_index,
+    utils::{load_kmer_db_v2_for_k, read_kmer_db_format, read_kmer_db_k_values, KmerDbFormat},
+};
+
+// Formats seconds since the Unix epoch as an RFC 3339 UTC timestamp, e.g. 2024-03-01T12:00:00Z.
+fn format_unix_timestamp(secs: u64) -> String {
+    let days = (secs / 86_400) as i64;
+    let secs_of_day = secs % 86_400;
+    // Civil date from days since 1970-01-01 (Howard Hinnant's days_from_civil inverse)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60
+    )
+}
+
+fn join_k_values(k_values: &[u8]) -> String {
+    k_values.iter().map(|k| k.to_string()).collect::<Vec<_>>().join(",")
+}
+
+// Share of the stored non-palindromic k-mers whose forward encoding is the canonical one (not
+// above their reverse complement). Only a non-canonical, unhashed database keeps the orientation
+// k-mers were read in, so other databases have none.
+fn canonical_balance(kmer_db: &KmerDbV2) -> Option<f64> {
+    if kmer_db.canonical || kmer_db.hashing.is_some() {
+        return None;
+    }
+    let (mut forward, mut reverse) = (0u64, 0u64);
+    for kmer in kmer_db.get_all_kmers_unified() {
+        let revcomp = reverse_complement_u64(kmer, kmer_db.k);
+        if kmer < revcomp {
+            forward += 1;
+        } else if kmer > revcomp {
+            reverse += 1;
+        }
+    }
+    let oriented = forward + reverse;
+    Some(if oriented == 0 { 0.0 } else { forward as f64 / oriented as f64 })
+}
+
+fn write_metadata(writer: &mut impl Write, metadata: &DbMetadata) -> Result<()> {
+    writeln!(writer, "orion_kmer_version\t{}", metadata.orion_kmer_version)?;
+    writeln!(writer, "built_at\t{}", format_unix_timestamp(metadata.built_at_unix_secs))?;
+    writeln!(writer, "build_command\t{}", metadata.command_line.join(" "))?;
+    writeln!(writer, "build_k\t{}", join_k_values(&metadata.k_values))?;
+    writeln!(writer, "build_canonical\t{}", metadata.canonical)?;
+    writeln!(writer, "build_hashed\t{}", metadata.hashed)?;
+    for source_file in &metadata.source_files {
+        writeln!(writer, "source_file\t{}", source_file)?;
+    }
+    Ok(())
+}
+
+fn write_format(writer: &mut impl Write, db_format: &KmerDbFormat) -> Result<()> {
+    writeln!(writer, "format\t{}", db_format.format)?;
+    writeln!(writer, "format_version\t{}", db_format.version)?;
+    writeln!(writer, "magic\t{}", db_format.magic.as_deref().unwrap_or("none"))?;
+    if let Some(k) = db_format.k {
+        writeln!(writer, "k\t{}", k)?;
+    }
+    if let Some(entries) = db_format.entries {
+        let key = if db_format.format == "multi_k" { "tables" } else { "references" };
+        writeln!(writer, "{}\t{}", key, entries)?;
+    }
+    Ok(())
+}
+
+pub fn run_info(args: InfoArgs) -> Result<()> {
+    info!("Starting info command with args: {:?}", args);
+
+    let path = &args.database_file;
+    if args.format_version {
+        let db_format = read_kmer_db_format(path)?;
+        let mut writer = std::io::stdout().lock();
+        writeln!(writer, "file\t{}", path.display())?;
+        return write_format(&mut writer, &db_format);
+    }
+    let k_values = read_kmer_db_k_values(path)
+        .with_context(|| format!("Failed to read k-mer database: {:?}", path))?;
+    let format = if is_sorted_kmer_index(path)? {
+        "sorted_index"
+    } else if k_values.len() > 1 {
+        "multi_k"
+    } else {
+        "hashset"
+    };
+
+    let stdout = std::io::stdout();
+    let mut writer = stdout.lock();
+    writeln!(writer, "file\t{}", path.display())?;
+    writeln!(writer, "format\t{}", format)?;
+    // Every table of a multi-k database carries the same metadata, so it is printed once
+    let mut metadata = None;
+    for &k in &k_values {
+        let kmer_db = load_kmer_db_v2_for_k(path, Some(k))?;
+        writeln!(writer, "k\t{}", kmer_db.k)?;
+        writeln!(writer, "canonical\t{}", kmer_db.canonical)?;
+        if let CanonicalPolicy::Minimizer { m } = kmer_db.canonical_policy {
+            writeln!(writer, "canonical_policy\tminimizer (m={})", m)?;
+        }
+        match kmer_db.hashing {
+            Some(hashing) => writeln!(writer, "hashing\t{:?} (seed {})", hashing.function, hashing.seed)?,
+            None => writeln!(writer, "hashing\tnone")?,
+        }
+        if let Some(name_normalization) = kmer_db.name_normalization {
+            writeln!(writer, "name_normalization\t{:?}", name_normalization)?;
+        }
+        writeln!(writer, "references\t{}", kmer_db.num_references())?;
+        writeln!(writer, "total_unique_kmers\t{}", kmer_db.total_unique_kmers())?;
+        if let Some(balance) = canonical_balance(&kmer_db) {
+            writeln!(writer, "canonical_balance\t{:.4}", balance)?;
+        }
+        metadata = metadata.or(kmer_db.metadata);
+    }
+    match &metadata {
+        Some(metadata) => write_metadata(&mut writer, metadata)?,
+        None => writeln!(writer, "metadata\tnone")?,
+    }
+    Ok(())
+}
@@ -0,0 +1,155 @@
+use anyhow::{Context, Result};
+use log::{debug, info};
+use needletail::Sequence;
+use std::{collections::HashSet, io::Write};
+
+use crate::{
+    cli::MarkersArgs,
+    db_types::KmerDbV2,
+    errors::OrionKmerError,
+    kmer::seq_to_u64,
+    utils::{
+        check_canonical_lookup, check_canonical_policy, finalize_writer, get_output_writer, load_kmer_db_v2, open_sequence_reader,
+        track_progress_and_resources,
+    },
+};
+
+// Returns the [start, end) intervals covered by runs of at least `min_run` consecutive
+// windows for which `is_specific` holds. A run of n windows starting at i covers [i, i + n - 1 + k).
+fn specific_regions(
+    seq: &[u8],
+    k: usize,
+    min_run: usize,
+    is_specific: impl Fn(&[u8]) -> bool,
+) -> Vec<(usize, usize)> {
+    let mut regions = Vec::new();
+    let mut run: Option<(usize, usize)> = None; // (first window, number of windows)
+    for (pos, window) in seq.windows(k).enumerate() {
+        if is_specific(window) {
+            let (_, run_len) = run.get_or_insert((pos, 0));
+            *run_len += 1;
+        } else if let Some((run_start, run_len)) = run.take()
+            && run_len >= min_run
+        {
+            regions.push((run_start, run_start + run_len - 1 + k));
+        }
+    }
+    if let Some((run_start, run_len)) = run
+        && run_len >= min_run
+    {
+        regions.push((run_start, run_start + run_len - 1 + k));
+    }
+    regions
+}
+
+pub fn run_markers(args: MarkersArgs) -> Result<()> {
+    info!("Starting markers command with args: {:?}", args);
+
+    // --- 1. Load background databases and determine k ---
+    let mut background_dbs: Vec<KmerDbV2> = Vec::new();
+    for db_path in &args.background_files {
+        let kmer_db = load_kmer_db_v2(db_path)
+            .with_context(|| format!("Failed to load background database: {:?}", db_path))?;
+        check_canonical_lookup(&kmer_db, db_path, false)?;
+        if let Some(first_db) = background_dbs.first() {
+            if kmer_db.k != first_db.k {
+                return Err(OrionKmerError::KmerSizeMismatchBetweenDatabases(
+                    first_db.k,
+                    kmer_db.k,
+                    db_path.clone(),
+                )
+                .into());
+            }
+            if kmer_db.hashing != first_db.hashing {
+                return Err(OrionKmerError::HashingMismatch(
+                    first_db.hashing,
+                    kmer_db.hashing,
+                    db_path.clone(),
+                )
+                .into());
+            }
+            check_canonical_policy(first_db, &kmer_db, db_path)?;
+        }
+        background_dbs.push(kmer_db);
+    }
+    let first_background = background_dbs.first().ok_or_else(|| {
+        OrionKmerError::Generic("No background databases provided.".to_string())
+    })?;
+    let k = first_background.k;
+    if k == 0 || k > 32 {
+        return Err(OrionKmerError::InvalidKmerSize(k).into());
+    }
+
+    let background_kmers: HashSet<u64> = background_dbs
+        .iter()
+        .flat_map(|kmer_db| kmer_db.references.values().flat_map(|kmer_set| kmer_set.iter().copied()))
+        .collect();
+    info!(
+        "Background contains {} unique k-mers (k={}) from {} database(s)",
+        background_kmers.len(),
+        k,
+        background_dbs.len()
+    );
+
+    let mut writer = get_output_writer(&args.output_file).with_context(|| {
+        format!(
+            "Failed to get output writer for markers: {:?}",
+            args.output_file
+        )
+    })?;
+    writeln!(writer, "reference\tsequence_id\tstart\tend\tsequence")?;
+
+    // --- 2. Scan target genomes for runs of target-specific k-mers ---
+    let mut regions_written: usize = 0;
+    let num_files = args.target_files.len() as u64;
+    track_progress_and_resources("Extracting target-specific markers", num_files, |pb_files| {
+        for target_path in &args.target_files {
+            let path_str = target_path.to_string_lossy();
+            let reference_name = target_path.file_name().map_or_else(
+                || path_str.clone().into_owned(),
+                |os_str| os_str.to_string_lossy().into_owned(),
+            );
+            info!("Scanning target genome: {}", path_str);
+
+            let mut reader = open_sequence_reader(target_path)?;
+
+            while let Some(record) = reader.next() {
+                let record =
+                    record.with_context(|| format!("Error reading record from {}", path_str))?;
+                let header = String::from_utf8_lossy(record.id()).into_owned();
+                let sequence_id = header.split_whitespace().next().unwrap_or("").to_string();
+                let norm_seq = record.normalize(false);
+
+                // Windows with non-ACGT bases or background k-mers break a run
+                let regions = specific_regions(&norm_seq, k as usize, args.min_run, |window| {
+                    seq_to_u64(window, k).is_some_and(|kmer_val| {
+                        let key = first_background.lookup_key(first_background.canonicalize(kmer_val));
+                        !background_kmers.contains(&key)
+                    })
+                });
+                for (start, end) in regions {
+                    writeln!(
+                        writer,
+                        "{}\t{}\t{}\t{}\t{}",
+                        reference_name,
+                        sequence_id,
+                        start,
+                        end,
+                        String::from_utf8_lossy(&norm_seq[start..end])
+                    )?;
+                    regions_written += 1;
+                }
+                debug!("Finished scanning record {} from {}", sequence_id, path_str);
+            }
+            pb_files.inc(1);
+        }
+        Ok(())
+    })?;
+
+    finalize_writer(writer).context("Failed to finish markers output writer")?;
+    info!(
+        "Wrote {} marker regions (runs of >= {} specific k-mers) to {:?}",
+        regions_written, args.min_run, args.output_file
+    );
+    Ok(())
+}
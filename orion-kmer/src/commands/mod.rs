@@ -1,13 +1,53 @@
+pub mod ani;
+pub mod bench;
 pub mod build;
+pub mod cat;
 pub mod classify; // Added classify module
 pub mod compare;
+pub mod convert;
 pub mod count;
+pub mod diff;
+pub mod doctor;
+pub mod dry_run;
+pub mod dump;
+pub mod info;
+pub mod markers;
+pub mod neighbors;
 pub mod query;
+pub mod revcomp;
+pub mod search;
+pub mod split;
+pub mod sum;
+pub mod validate;
 
-use crate::cli::Commands;
+use crate::cli::{Cli, Commands, LogFormat};
 use anyhow::Result;
+use log::warn;
+use std::io::Write;
+
+pub fn dispatch_command(cli: Cli) -> Result<()> {
+    let Cli {
+        command,
+        threads,
+        verbose,
+        progress_json,
+        profile_memory,
+        precision,
+        assume_format,
+        profile,
+        dry_run,
+        rna,
+        decompress_threads,
+        compress_threads,
+        progress_interval,
+        log_format,
+        lenient_gzip,
+        max_records,
+        reference,
+        strict_threads,
+        blocklist,
+    } = cli;
 
-pub fn dispatch_command(command: Commands, threads: usize, verbose: u8) -> Result<()> {
     // Setup logging based on verbosity
     let log_level = match verbose {
         0 => log::LevelFilter::Warn,
@@ -15,19 +55,98 @@ pub fn dispatch_command(command: Commands, threads: usize, verbose: u8) -> Resul
         2 => log::LevelFilter::Debug,
         _ => log::LevelFilter::Trace,
     };
+    let mut logger = env_logger::Builder::new();
+    logger.filter_level(log_level);
+    if log_format == LogFormat::Json {
+        logger.format(|buf, record| {
+            let line = serde_json::json!({
+                "timestamp": buf.timestamp_millis().to_string(),
+                "level": record.level().as_str(),
+                "target": record.target(),
+                "message": record.args().to_string(),
+            });
+            writeln!(buf, "{}", line)
+        });
+    }
     // Allow re-init of logger for tests, handle error if already initialized
-    let _ = env_logger::Builder::new()
-        .filter_level(log_level)
-        .try_init();
+    let _ = logger.try_init();
 
     // Initialize rayon thread pool
-    crate::utils::initialize_rayon_pool(crate::utils::get_num_threads(threads))?;
+    let num_threads = crate::utils::get_num_threads(threads, strict_threads)?;
+    crate::utils::initialize_rayon_pool(num_threads)?;
+
+    // Replace progress bars with JSON progress events if requested
+    if let Some(progress_json_path) = &progress_json {
+        crate::utils::init_progress_json(progress_json_path)?;
+    }
+
+    if let Some(memory_profile_path) = &profile_memory {
+        crate::utils::init_memory_profile(memory_profile_path)?;
+    }
+
+    if let Some(digits) = precision {
+        crate::utils::init_output_precision(digits);
+    }
+
+    if let Some(format) = assume_format {
+        crate::utils::init_assume_format(format);
+    }
+
+    if profile {
+        // The breakdown is a plain-text table, which would break JSON log lines
+        if log_format == LogFormat::Json {
+            warn!("--profile is ignored with --log-format json");
+        } else {
+            crate::utils::init_profile();
+        }
+    }
+
+    if rna {
+        crate::kmer::init_rna_input();
+    }
+
+    crate::utils::init_decompress_threads(decompress_threads);
+    crate::utils::init_compress_threads(compress_threads);
+    crate::utils::init_progress_interval(progress_interval)?;
+    if lenient_gzip {
+        crate::utils::init_lenient_gzip();
+    }
+    if let Some(records) = max_records {
+        crate::utils::init_max_records(records)?;
+    }
+    if let Some(reference_path) = &reference {
+        crate::alignment_reads::init_cram_reference(reference_path);
+    }
+
+    // Only check the inputs and report what would run
+    if dry_run {
+        return dry_run::run_dry_run(&command);
+    }
+
+    if let Some(blocklist_path) = &blocklist {
+        crate::utils::init_blocklist(blocklist_path)?;
+    }
 
     match command {
         Commands::Count(args) => count::run_count(args),
         Commands::Build(args) => build::run_build(args),
         Commands::Compare(args) => compare::run_compare(args),
+        Commands::Diff(args) => diff::run_diff(args),
         Commands::Query(args) => query::run_query(args),
         Commands::Classify(args) => classify::run_classify(args), // Added dispatch for Classify
+        Commands::Dump(args) => dump::run_dump(args),
+        Commands::Markers(args) => markers::run_markers(args),
+        Commands::Neighbors(args) => neighbors::run_neighbors(args),
+        Commands::Search(args) => search::run_search(args),
+        Commands::Validate(args) => validate::run_validate(args),
+        Commands::Sum(args) => sum::run_sum(args),
+        Commands::Convert(args) => convert::run_convert(args),
+        Commands::Split(args) => split::run_split(args),
+        Commands::Info(args) => info::run_info(args),
+        Commands::Revcomp(args) => revcomp::run_revcomp(args),
+        Commands::Ani(args) => ani::run_ani(args),
+        Commands::Cat(args) => cat::run_cat(args),
+        Commands::Bench(args) => bench::run_bench(args),
+        Commands::Doctor(args) => doctor::run_doctor(args, num_threads),
     }
 }
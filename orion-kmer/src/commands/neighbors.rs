@@ -0,0 +1,65 @@
+use anyhow::Result;
+use log::info;
+use std::io::Write;
+
+use crate::{
+    cli::NeighborsArgs,
+    errors::OrionKmerError,
+    kmer::{predecessors, seq_to_u64, successors, u64_to_seq},
+    utils::load_kmer_db_v2,
+};
+
+pub fn run_neighbors(args: NeighborsArgs) -> Result<()> {
+    info!("Starting neighbors command with args: {:?}", args);
+
+    let kmer_db_v2 = load_kmer_db_v2(&args.database_file)?;
+    let k = kmer_db_v2.k;
+
+    let kmer_bytes = args.kmer.to_ascii_uppercase().into_bytes();
+    if kmer_bytes.len() != k as usize {
+        return Err(OrionKmerError::Generic(format!(
+            "K-mer '{}' does not have the database's length k={}",
+            args.kmer, k
+        ))
+        .into());
+    }
+    let kmer_val = seq_to_u64(&kmer_bytes, k).ok_or_else(|| {
+        OrionKmerError::Generic(format!("Invalid k-mer '{}': only A/C/G/T are allowed", args.kmer))
+    })?;
+
+    // Neighbors are looked up the way the database stored its k-mers
+    let is_present = |neighbor: u64| {
+        let stored = if kmer_db_v2.canonical {
+            kmer_db_v2.canonicalize(neighbor)
+        } else {
+            neighbor
+        };
+        let key = kmer_db_v2.lookup_key(stored);
+        kmer_db_v2.references.values().any(|kmer_set| kmer_set.contains(&key))
+    };
+
+    let stdout = std::io::stdout();
+    let mut writer = stdout.lock();
+    writeln!(writer, "direction\tneighbor\tpresent")?;
+    let directions = [
+        ("successor", successors(kmer_val, k)),
+        ("predecessor", predecessors(kmer_val, k)),
+    ];
+    let mut present_count = 0;
+    for (direction, neighbors) in directions {
+        for neighbor in neighbors {
+            let present = is_present(neighbor);
+            present_count += present as usize;
+            writeln!(
+                writer,
+                "{}\t{}\t{}",
+                direction,
+                String::from_utf8_lossy(&u64_to_seq(neighbor, k)),
+                present
+            )?;
+        }
+    }
+    writer.flush()?;
+    info!("{} of 8 neighbors are present in the database", present_count);
+    Ok(())
+}
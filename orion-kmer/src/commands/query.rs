@@ -1,55 +1,549 @@
 use anyhow::{Context, Result};
-use log::info;
-use needletail::{parse_fastx_reader, Sequence}; // Corrected import order & changed to parse_fastx_reader
+use log::{info, warn};
+use needletail::{
+    parser::{write_fasta, write_fastq, Format, LineEnding},
+    Sequence,
+};
 use rayon::prelude::*;
 use std::{
-    collections::HashSet, // Required for the unified k-mer set
-    // fs::File, // No longer directly used
+    collections::{BTreeMap, HashMap, HashSet}, // Required for the unified k-mer set
+    fs,
     io::Write, // Only Write is needed for Mutex<Box<dyn Write>>
-    sync::Mutex,
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
 };
 
 use crate::{
-    cli::QueryArgs,
-    // KmerDbV2 is loaded by load_kmer_db_v2, direct import not needed here
-    // db_types::KmerDbV2,
+    bloom::KmerBloomFilter,
+    cli::{DatabaseCombine, PalindromeCounting, QueryArgs},
+    db_types::{KmerDbV2, MAX_INVERTED_INDEX_REFERENCES},
     errors::OrionKmerError,
-    kmer::{canonical_u64, seq_to_u64},
-    utils::{get_decompressed_input_reader, get_output_writer, load_kmer_db_v2, track_progress_and_resources}, // Import the wrapper & I/O helpers
+    kmer::{canonical_u64, canonical_u64_with_policy, hamming1_neighbors, is_palindrome, reverse_complement_u64, seq_to_u64, sequence_complexity, subkmers, CanonicalPolicy},
+    kmer_index::{is_sorted_kmer_index, write_sorted_union_index, SortedKmerMmap},
+    utils::{check_blocklist_applies, check_canonical_lookup, check_canonical_policy, finalize_writer, get_decompressed_input_reader, get_output_writer, load_kmer_db_v2_for_k, open_sequence_reader, polyx_base, report_short_records, sanitize_reference_name, take_input_record, track_progress_and_resources, trim_polyx, trim_read, ReadFilter, ReferenceSelection}, // Import the wrapper & I/O helpers
 };
 // use indicatif::ProgressBar; // For passing to the closure - actually not needed
 
 // Removed local load_kmer_db function
 
+/// The database k-mers reads are looked up in: loaded into a set, memory-mapped with --mmap, or
+/// a Bloom filter with --bloom-file.
+enum DbKmers {
+    InMemory(HashSet<u64>),
+    Mapped(SortedKmerMmap),
+    Bloom(KmerBloomFilter),
+}
+
+impl DbKmers {
+    fn contains(&self, key: &u64) -> bool {
+        match self {
+            DbKmers::InMemory(kmer_set) => kmer_set.contains(key),
+            DbKmers::Mapped(index) => index.unified_kmers().contains(*key),
+            DbKmers::Bloom(filter) => filter.contains(*key),
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            DbKmers::InMemory(kmer_set) => kmer_set.len(),
+            DbKmers::Mapped(index) => index.unified_kmers().len(),
+            DbKmers::Bloom(filter) => filter.num_items as usize,
+        }
+    }
+}
+
+/// A loaded database: its metadata for key lookups and its k-mers.
+struct QueryDb {
+    kmer_db_v2: KmerDbV2,
+    kmers: DbKmers,
+}
+
+impl QueryDb {
+    fn contains(&self, kmer_val: u64) -> bool {
+        self.kmers.contains(&self.kmer_db_v2.lookup_key(kmer_val))
+    }
+}
+
+/// A read that passed the `min_hits` filter, with the half-open intervals covered by matching k-mers.
+struct QueryHit {
+    // Position of the read among the queried reads
+    record_index: usize,
+    read_id: Vec<u8>,
+    covered_intervals: Vec<(usize, usize)>,
+    forward_hits: usize,
+    reverse_hits: usize,
+    // Indices of the databases against which the read met `min_hits`
+    matched_databases: Vec<usize>,
+    // With --annotate-references or --bin-output-dir: (database index, reference ID) and the
+    // read's windows found in that reference, most hits first
+    reference_hits: Vec<((usize, u16), usize)>,
+    // With --segment-length: each segment's half-open interval and the reference it has the
+    // most hits against, if any
+    segments: Vec<ReadSegment>,
+}
+
+/// One --segment-length segment of a reported read.
+struct ReadSegment {
+    start: usize,
+    end: usize,
+    best_reference: Option<(usize, u16)>,
+}
+
+// The reference with the most hits, ties going to the earlier database, then the lower
+// reference ID (the order of `QueryHit::reference_hits`).
+fn best_reference(hits: &HashMap<(usize, u16), usize>) -> Option<(usize, u16)> {
+    hits.iter()
+        .max_by(|a, b| a.1.cmp(b.1).then(b.0.cmp(a.0)))
+        .map(|(&reference, _)| reference)
+}
+
+/// A database's k-mer to references index for --annotate-references, with the reference
+/// names in ID order.
+struct ReferenceAnnotation {
+    inverted_index: HashMap<u64, Vec<u16>>,
+    reference_names: Vec<String>,
+}
+
+/// A queried read as it was read, before trimming, for --bin-output-dir.
+struct BinRecord {
+    id: Vec<u8>,
+    seq: Vec<u8>,
+    qual: Option<Vec<u8>>,
+}
+
+/// Writes each matching read to the bin of the reference it has the most hits against
+/// (--bin-output-dir), one file per reference named after its sanitized name, and with
+/// --keep-unbinned the other queried reads to `unbinned`. Reads keep their input order.
+fn write_read_bins(
+    bin_dir: &Path,
+    bin_records: &[BinRecord],
+    format: Format,
+    matching_reads: &[QueryHit],
+    reference_annotations: &[ReferenceAnnotation],
+    keep_unbinned: bool,
+) -> Result<()> {
+    let extension = match format {
+        Format::Fasta => "fasta",
+        Format::Fastq => "fastq",
+    };
+    // File name -> (reference name, indices of its reads)
+    let mut bins: BTreeMap<String, (&str, Vec<usize>)> = BTreeMap::new();
+    let mut binned = vec![false; bin_records.len()];
+    for hit in matching_reads {
+        // Ties were broken when sorting: the earlier database, then the lower reference ID
+        let Some(&((db_index, reference_id), _)) = hit.reference_hits.first() else {
+            continue;
+        };
+        let reference_name = reference_annotations[db_index].reference_names[reference_id as usize].as_str();
+        let file_name = format!("{}.{}", sanitize_reference_name(reference_name), extension);
+        let bin = bins.entry(file_name.clone()).or_insert_with(|| (reference_name, Vec::new()));
+        if bin.0 != reference_name {
+            return Err(OrionKmerError::Generic(format!(
+                "References {:?} and {:?} would both be binned into {:?}",
+                bin.0,
+                reference_name,
+                bin_dir.join(file_name)
+            ))
+            .into());
+        }
+        bin.1.push(hit.record_index);
+        binned[hit.record_index] = true;
+    }
+    if keep_unbinned {
+        let file_name = format!("unbinned.{}", extension);
+        if let Some((reference_name, _)) = bins.get(&file_name) {
+            return Err(OrionKmerError::Generic(format!(
+                "Reference {:?} would be binned into {:?}, which --keep-unbinned writes unmatched reads to",
+                reference_name,
+                bin_dir.join(&file_name)
+            ))
+            .into());
+        }
+        let unbinned: Vec<usize> = (0..bin_records.len()).filter(|&index| !binned[index]).collect();
+        bins.insert(file_name, ("unbinned", unbinned));
+    }
+
+    fs::create_dir_all(bin_dir).with_context(|| format!("Failed to create --bin-output-dir: {:?}", bin_dir))?;
+    for (file_name, (reference_name, record_indices)) in &bins {
+        let bin_path = bin_dir.join(file_name);
+        let mut writer = get_output_writer(&bin_path)
+            .with_context(|| format!("Failed to get output writer for read bin: {:?}", bin_path))?;
+        for &index in record_indices {
+            let record = &bin_records[index];
+            match format {
+                Format::Fasta => write_fasta(&record.id, &record.seq, &mut writer, LineEnding::Unix),
+                Format::Fastq => write_fastq(&record.id, &record.seq, record.qual.as_deref(), &mut writer, LineEnding::Unix),
+            }
+            .with_context(|| format!("Failed to write read to bin: {:?}", bin_path))?;
+        }
+        finalize_writer(writer).with_context(|| format!("Failed to finish read bin writer: {:?}", bin_path))?;
+        info!("Wrote {} reads of {:?} to {:?}", record_indices.len(), reference_name, bin_path);
+    }
+    Ok(())
+}
+
+/// Writes the --segment-length rows of the reported reads: read ID, segment interval, best
+/// reference and whether the read's segments disagree on their best reference.
+fn write_read_segments(
+    writer: &mut dyn Write,
+    matching_reads: &[QueryHit],
+    reference_annotations: &[ReferenceAnnotation],
+) -> Result<()> {
+    let mut potential_chimeras = 0;
+    for hit in matching_reads {
+        let mut best_references = hit.segments.iter().filter_map(|segment| segment.best_reference);
+        let first = best_references.next();
+        let potential_chimera = best_references.any(|reference| Some(reference) != first);
+        potential_chimeras += usize::from(potential_chimera);
+        for segment in &hit.segments {
+            let best_reference = segment.best_reference.map_or("-", |(db_index, reference_id)| {
+                reference_annotations[db_index].reference_names[reference_id as usize].as_str()
+            });
+            writer.write_all(&hit.read_id)?;
+            writeln!(
+                writer,
+                "\t{}-{}\t{}\t{}",
+                segment.start, segment.end, best_reference, potential_chimera
+            )?;
+        }
+    }
+    info!(
+        "Flagged {} of {} reported reads as potential chimeras.",
+        potential_chimeras,
+        matching_reads.len()
+    );
+    Ok(())
+}
+
+/// Applies --dedup-ids (keep the first matching read of each ID) or --error-on-duplicate-ids
+/// (fail on a repeated ID) to the matching reads; without either they pass through unchanged.
+fn resolve_duplicate_ids(matching_reads: Vec<QueryHit>, args: &QueryArgs) -> Result<Vec<QueryHit>> {
+    if !args.dedup_ids && !args.error_on_duplicate_ids {
+        return Ok(matching_reads);
+    }
+    let mut seen_ids: HashSet<Vec<u8>> = HashSet::with_capacity(matching_reads.len());
+    let mut unique_reads = Vec::with_capacity(matching_reads.len());
+    let mut duplicates = 0;
+    for hit in matching_reads {
+        if seen_ids.contains(&hit.read_id) {
+            if args.error_on_duplicate_ids {
+                return Err(OrionKmerError::Generic(format!(
+                    "Duplicate read ID {:?} among the matching reads of {:?}",
+                    String::from_utf8_lossy(&hit.read_id),
+                    args.reads_file
+                ))
+                .into());
+            }
+            duplicates += 1;
+            continue;
+        }
+        seen_ids.insert(hit.read_id.clone());
+        unique_reads.push(hit);
+    }
+    info!("Collapsed {} matching reads with duplicate IDs.", duplicates);
+    Ok(unique_reads)
+}
+
+/// Writes the sorted union of `kmer_db`'s k-mers to a temporary file and memory-maps it, for
+/// --disk-index. The file is removed once mapped; the mapping keeps its pages readable.
+fn map_union_index(kmer_db: &KmerDbV2, db_index: usize) -> Result<SortedKmerMmap> {
+    let index_path = std::env::temp_dir().join(format!("orion-kmer-query-{}-{}.kidx", std::process::id(), db_index));
+    let index = write_sorted_union_index(kmer_db, &index_path).and_then(|()| SortedKmerMmap::open(&index_path));
+    // Remove a partly written file too, but report the write error first
+    let removed = fs::remove_file(&index_path);
+    let index = index?;
+    removed.with_context(|| format!("Failed to remove temporary k-mer index: {:?}", index_path))?;
+    Ok(index)
+}
+
+fn build_reference_annotation(db: &QueryDb, db_path: &Path) -> Result<ReferenceAnnotation> {
+    let num_references = db.kmer_db_v2.num_references();
+    if num_references > MAX_INVERTED_INDEX_REFERENCES {
+        return Err(OrionKmerError::Generic(format!(
+            "--annotate-references, --bin-output-dir and --segment-length support at most {} references per database, {:?} has {}",
+            MAX_INVERTED_INDEX_REFERENCES, db_path, num_references
+        ))
+        .into());
+    }
+    let mut inverted_index = db.kmer_db_v2.build_inverted_index();
+    // --mask may have removed k-mers from the query set but not from the references
+    inverted_index.retain(|key, _| db.contains(*key));
+    Ok(ReferenceAnnotation {
+        inverted_index,
+        reference_names: db.kmer_db_v2.references.keys().cloned().collect(),
+    })
+}
+
+/// Removes the k-mers of the --mask database from every database's query set, so they never
+/// count as hits. The mask must store k-mers the same way (k, hashing, canonical form and policy).
+fn apply_query_mask(databases: &mut [QueryDb], mask_path: &Path) -> Result<()> {
+    let first = &databases[0].kmer_db_v2;
+    let mask_db = load_kmer_db_v2_for_k(mask_path, Some(first.k))
+        .with_context(|| format!("Failed to load --mask database: {:?}", mask_path))?;
+    if mask_db.k != first.k {
+        return Err(OrionKmerError::KmerSizeMismatchValidation(first.k, mask_db.k, mask_path.to_path_buf()).into());
+    }
+    if mask_db.hashing != first.hashing {
+        return Err(OrionKmerError::HashingMismatch(first.hashing, mask_db.hashing, mask_path.to_path_buf()).into());
+    }
+    if mask_db.canonical != first.canonical {
+        return Err(OrionKmerError::Generic(format!(
+            "--mask database {:?} and the query databases disagree on whether k-mers are stored in canonical form",
+            mask_path
+        ))
+        .into());
+    }
+    check_canonical_policy(first, &mask_db, mask_path)?;
+    let masked = mask_db.get_all_kmers_unified();
+    for db in databases {
+        if let DbKmers::InMemory(kmer_set) = &mut db.kmers {
+            let before = kmer_set.len();
+            kmer_set.retain(|kmer| !masked.contains(kmer));
+            info!(
+                "--mask removed {} of {} k-mers from a query database",
+                before - kmer_set.len(),
+                before
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Records a matching window starting at `start`, extending the last interval when the new
+/// window overlaps or directly follows it. A window at position i covers [i, i + k).
+fn add_covered_window(intervals: &mut Vec<(usize, usize)>, start: usize, k: usize) {
+    match intervals.last_mut() {
+        Some(last) if start <= last.1 => last.1 = start + k,
+        _ => intervals.push((start, start + k)),
+    }
+}
+
+/// Replaces a database's k-mers with their `derived_k`-length subwords (canonicalized if the
+/// database is canonical), for --derived-k.
+fn derive_query_db(db: QueryDb, derived_k: u8, db_path: &Path) -> Result<QueryDb> {
+    let k = db.kmer_db_v2.k;
+    if derived_k == 0 || derived_k >= k {
+        return Err(OrionKmerError::Generic(format!(
+            "--derived-k must be smaller than the database k-mer size {}, got {}",
+            k, derived_k
+        ))
+        .into());
+    }
+    if db.kmer_db_v2.hashing.is_some() {
+        return Err(OrionKmerError::Generic(format!(
+            "{:?} stores hashed k-mers, whose subwords cannot be derived; --derived-k needs a database built without --hashed",
+            db_path
+        ))
+        .into());
+    }
+    if db.kmer_db_v2.canonical_policy != CanonicalPolicy::Lexicographic {
+        return Err(OrionKmerError::Generic(format!(
+            "{:?} uses the {:?} canonical policy, whose k-mers do not keep their orientation in subwords; --derived-k needs a lexicographically canonical database",
+            db_path, db.kmer_db_v2.canonical_policy
+        ))
+        .into());
+    }
+    let canonical = db.kmer_db_v2.canonical;
+    let stored_subword = |subword: u64| {
+        if canonical {
+            canonical_u64(subword, derived_k)
+        } else {
+            subword
+        }
+    };
+    let derived: HashSet<u64> = match &db.kmers {
+        DbKmers::InMemory(kmer_set) => kmer_set
+            .iter()
+            .flat_map(|&kmer| subkmers(kmer, k, derived_k))
+            .map(stored_subword)
+            .collect(),
+        DbKmers::Mapped(index) => index
+            .unified_kmers()
+            .iter()
+            .flat_map(|kmer| subkmers(kmer, k, derived_k))
+            .map(stored_subword)
+            .collect(),
+        DbKmers::Bloom(_) => {
+            return Err(OrionKmerError::Generic(format!(
+                "{:?} is a Bloom filter, whose k-mers cannot be listed; --derived-k needs a database",
+                db_path
+            ))
+            .into());
+        }
+    };
+    info!(
+        "Derived {} unique {}-mers from the {} {}-mers of {:?}",
+        derived.len(),
+        derived_k,
+        db.kmers.len(),
+        k,
+        db_path
+    );
+    let mut kmer_db_v2 = db.kmer_db_v2;
+    kmer_db_v2.k = derived_k;
+    Ok(QueryDb {
+        kmer_db_v2,
+        kmers: DbKmers::InMemory(derived),
+    })
+}
+
 pub fn run_query(args: QueryArgs) -> Result<()> {
     info!("Starting query command with args: {:?}", args);
+    if let Some(cap) = args.max_hits_cap
+        && cap < args.min_hits.max(1)
+    {
+        return Err(OrionKmerError::Generic(format!(
+            "--max-hits-cap must be at least --min-hits ({}) and 1, got {}",
+            args.min_hits, cap
+        ))
+        .into());
+    }
 
-    // Load the KmerDbV2 database
-    let kmer_db_v2 = load_kmer_db_v2(&args.database_file)?;
-    let k = kmer_db_v2.k;
+    // Load each KmerDbV2 database, or only its metadata when the k-mers stay memory-mapped
+    let reference_selection = ReferenceSelection::new(&args.only_reference, &args.exclude_reference);
+    let mut found_references = HashSet::new();
+    let db_paths = if args.bloom_files.is_empty() { &args.database_files } else { &args.bloom_files };
+    let mut databases: Vec<QueryDb> = Vec::with_capacity(db_paths.len());
+    for db_path in db_paths {
+        let (kmer_db_v2, kmers) = if !args.bloom_files.is_empty() {
+            check_blocklist_applies("--bloom-file")?;
+            let mut reader = get_decompressed_input_reader(db_path)?;
+            let filter = KmerBloomFilter::read_from(&mut reader)
+                .with_context(|| format!("Failed to load Bloom filter: {:?}", db_path))?;
+            info!(
+                "Loaded Bloom filter {:?}: estimated false-positive rate {:.3e}",
+                db_path,
+                filter.estimated_fp_rate()
+            );
+            (filter.metadata(), DbKmers::Bloom(filter))
+        } else if args.mmap || (args.disk_index && is_sorted_kmer_index(db_path)?) {
+            check_blocklist_applies(if args.mmap { "--mmap" } else { "--disk-index over a sorted index" })?;
+            let index = SortedKmerMmap::open(db_path)?;
+            info!("Memory-mapped sorted k-mer index {:?}", db_path);
+            (index.metadata(), DbKmers::Mapped(index))
+        } else if args.disk_index {
+            let mut kmer_db_v2 = load_kmer_db_v2_for_k(db_path, args.kmer_size)?;
+            reference_selection.apply(&mut kmer_db_v2, &mut found_references);
+            let index = map_union_index(&kmer_db_v2, databases.len())?;
+            info!("Wrote the sorted k-mers of {:?} to a temporary index for --disk-index", db_path);
+            (index.metadata(), DbKmers::Mapped(index))
+        } else {
+            let mut kmer_db_v2 = load_kmer_db_v2_for_k(db_path, args.kmer_size)?;
+            reference_selection.apply(&mut kmer_db_v2, &mut found_references);
+            // Get the unified set of all k-mers from the database for querying
+            let kmers = DbKmers::InMemory(kmer_db_v2.get_all_kmers_unified());
+            (kmer_db_v2, kmers)
+        };
+        // --strand and --canonical-both look up both strands of each window in a non-canonical database
+        if !args.strand && !args.canonical_both {
+            check_canonical_lookup(&kmer_db_v2, db_path, args.strict)?;
+        }
+        if let Some(user_k) = args.kmer_size
+            && kmer_db_v2.k != user_k
+        {
+            return Err(OrionKmerError::KmerSizeMismatchValidation(user_k, kmer_db_v2.k, db_path.clone()).into());
+        }
+        if let Some(first) = databases.first()
+            && first.kmer_db_v2.k != kmer_db_v2.k
+        {
+            return Err(OrionKmerError::KmerSizeMismatchBetweenDatabases(
+                first.kmer_db_v2.k,
+                kmer_db_v2.k,
+                db_path.clone(),
+            )
+            .into());
+        }
+        if let Some(first) = databases.first() {
+            check_canonical_policy(&first.kmer_db_v2, &kmer_db_v2, db_path)?;
+        }
+        info!(
+            "Loaded database {:?} with k={} ({} unique k-mers in DB)",
+            db_path,
+            kmer_db_v2.k,
+            kmers.len()
+        );
+        databases.push(QueryDb { kmer_db_v2, kmers });
+    }
+    reference_selection.check_found(&found_references)?;
+    if let Some(mask_path) = &args.mask {
+        apply_query_mask(&mut databases, mask_path)?;
+    }
+    if let Some(derived_k) = args.derived_k {
+        databases = databases
+            .into_iter()
+            .zip(db_paths)
+            .map(|(db, db_path)| derive_query_db(db, derived_k, db_path))
+            .collect::<Result<_>>()?;
+    }
+    let k = databases[0].kmer_db_v2.k;
+    let canonical_policy = databases[0].kmer_db_v2.canonical_policy;
+    let reference_annotations: Vec<ReferenceAnnotation> = if args.annotate_references
+        || args.bin_output_dir.is_some()
+        || args.segment_length.is_some()
+    {
+        databases
+            .iter()
+            .zip(db_paths)
+            .map(|(db, db_path)| build_reference_annotation(db, db_path))
+            .collect::<Result<_>>()?
+    } else {
+        Vec::new()
+    };
 
     if k == 0 || k > 32 {
         return Err(OrionKmerError::InvalidKmerSize(k).into());
     }
+    if let Some(segment_length) = args.segment_length
+        && segment_length < k as usize
+    {
+        return Err(OrionKmerError::Generic(format!(
+            "--segment-length must be at least k ({}), got {}",
+            k, segment_length
+        ))
+        .into());
+    }
 
-    // Get the unified set of all k-mers from the database for querying
-    let db_all_kmers: HashSet<u64> = kmer_db_v2.get_all_kmers_unified();
     info!(
-        "Querying reads from {:?} against database with k={} ({} unique k-mers in DB)",
+        "Querying reads from {:?} against {} database(s) with k={} (combine: {:?})",
         args.reads_file,
+        databases.len(),
         k,
-        db_all_kmers.len()
+        args.combine
     );
+    if args.mismatches > 1 {
+        return Err(OrionKmerError::Generic(format!(
+            "--mismatches supports at most 1 substitution, got {}",
+            args.mismatches
+        ))
+        .into());
+    }
+    let fuzzy = args.mismatches == 1;
+    if fuzzy {
+        warn!(
+            "--mismatches 1 probes all {} single-substitution neighbors of each window not found in the database, multiplying lookups by up to {}x; this is only practical for small k",
+            3 * k as usize,
+            3 * k as usize + 1
+        );
+    }
 
-    // Use get_decompressed_input_reader for the reads file
-    let input_buf_reader = get_decompressed_input_reader(&args.reads_file).with_context(|| {
-        format!(
-            "Failed to get input reader for reads file: {:?}",
-            args.reads_file
-        )
-    })?;
-    let mut reader = parse_fastx_reader(input_buf_reader)
-        .with_context(|| format!("Failed to parse FASTQ content from: {:?}", args.reads_file))?;
+    // True if the canonical k-mer of the window `kmer_val`, or with --mismatches 1 that of one
+    // of its single-substitution neighbors, is in `db`
+    let window_in_database = |db: &QueryDb, canonical_kmer: u64, kmer_val: u64| {
+        db.contains(canonical_kmer)
+            || (fuzzy && hamming1_neighbors(kmer_val, k).any(|neighbor| db.contains(canonical_u64_with_policy(neighbor, k, canonical_policy))))
+    };
+    // True if the k-mer, in the orientation given, is in `db` (or one of its neighbors is,
+    // with --mismatches 1)
+    let raw_in_database = |db: &QueryDb, kmer_val: u64| {
+        db.contains(kmer_val) || (fuzzy && hamming1_neighbors(kmer_val, k).any(|neighbor| db.contains(neighbor)))
+    };
+    // Whether windows are looked up in `db` on each strand as read rather than in canonical form
+    let raw_lookup = |db: &QueryDb| (args.strand || args.canonical_both) && !db.kmer_db_v2.canonical;
+
+    let mut reader = open_sequence_reader(&args.reads_file)?;
 
     // Use get_output_writer for the output file
     let writer = Mutex::new(get_output_writer(&args.output_file).with_context(|| {
@@ -59,36 +553,223 @@ pub fn run_query(args: QueryArgs) -> Result<()> {
         )
     })?);
 
+    if let Some(min_complexity) = args.min_complexity
+        && !(0.0..=1.0).contains(&min_complexity)
+    {
+        return Err(OrionKmerError::Generic(format!(
+            "--min-complexity must be between 0 and 1, got {}",
+            min_complexity
+        ))
+        .into());
+    }
+
+    let read_filter = ReadFilter {
+        min_length: args.min_read_length,
+        min_mean_qual: args.min_mean_qual,
+    };
+    let trim_polyx_base = args.trim_polyx.map(polyx_base).transpose()?;
     let mut records = Vec::new();
+    // With --bin-output-dir, the queried reads as read, in the order of `records`
+    let mut bin_records: Vec<BinRecord> = Vec::new();
+    let mut reads_format = Format::Fastq;
+    let mut filtered_reads = 0;
+    let mut low_complexity_reads = 0;
+    let mut too_short_to_trim = 0;
+    let mut short_records = 0;
     while let Some(record) = reader.next() {
+        if !take_input_record() {
+            break;
+        }
         let record =
             record.with_context(|| format!("Error reading record from {:?}", args.reads_file))?;
-        records.push((record.id().to_vec(), record.sequence().to_owned()));
+        reads_format = record.format();
+        if !read_filter.passes(&record.seq(), record.qual()) {
+            filtered_reads += 1;
+            continue;
+        }
+        if let Some(min_complexity) = args.min_complexity
+            && sequence_complexity(&record.seq()) < min_complexity
+        {
+            low_complexity_reads += 1;
+            continue;
+        }
+        let trimmed = trim_read(record.sequence(), args.trim_front, args.trim_back)
+            .map(|trimmed| match trim_polyx_base {
+                Some(base) => trim_polyx(trimmed, base, args.polyx_min_run),
+                None => trimmed,
+            });
+        match trimmed {
+            // Reads shorter than k have no window to look up
+            Some(trimmed) if trimmed.len() < k as usize => short_records += 1,
+            Some(trimmed) => {
+                records.push((record.id().to_vec(), trimmed.to_owned()));
+                if args.bin_output_dir.is_some() {
+                    bin_records.push(BinRecord {
+                        id: record.id().to_vec(),
+                        seq: record.seq().into_owned(),
+                        qual: record.qual().map(<[u8]>::to_vec),
+                    });
+                }
+            }
+            None => too_short_to_trim += 1,
+        }
     }
 
+    if read_filter.is_active() {
+        info!(
+            "Skipped {} reads failing --min-read-length/--min-mean-qual.",
+            filtered_reads
+        );
+    }
+    if let Some(min_complexity) = args.min_complexity {
+        info!(
+            "Skipped {} low-complexity reads (complexity below {}).",
+            low_complexity_reads, min_complexity
+        );
+    }
+    if args.trim_front > 0 || args.trim_back > 0 {
+        info!(
+            "Skipped {} reads shorter than --trim-front plus --trim-back.",
+            too_short_to_trim
+        );
+    }
+    report_short_records(
+        &args.reads_file.to_string_lossy(),
+        short_records,
+        records.len() + short_records,
+        k,
+    );
     info!(
         "Collected {} reads. Starting parallel query...",
         records.len()
     );
 
+    let track_intervals = args.bed.is_some();
+    // With --combine any and no per-database column, one database reaching the cap decides the
+    // read; otherwise every database must reach it before the remaining windows are skipped.
+    let cap_on_any_database = args.combine == DatabaseCombine::Any && !args.annotate_databases;
+    let mut capped_reads = AtomicUsize::new(0);
     let num_records = records.len() as u64;
-    let matching_read_ids: Vec<Vec<u8>> =
+    let matching_reads: Vec<QueryHit> =
         track_progress_and_resources("Querying reads against database", num_records, |pb_query| {
-            let result: Vec<Vec<u8>> = records
+            let result: Vec<QueryHit> = records
                 .par_iter()
-                .filter_map(|(read_id_bytes, read_seq_vec)| {
-                    let mut kmer_hits = 0;
+                .enumerate()
+                .filter_map(|(record_index, (read_id_bytes, read_seq_vec))| {
+                    let mut kmer_hits = vec![0usize; databases.len()];
+                    // For the current window, the strands it matched on in each database
+                    let mut window_strands = vec![(false, false); databases.len()];
+                    let mut forward_hits = 0;
+                    let mut reverse_hits = 0;
+                    let mut covered_intervals: Vec<(usize, usize)> = Vec::new();
+                    let mut reference_hits: HashMap<(usize, u16), usize> = HashMap::new();
                     let norm_seq: &[u8] = read_seq_vec;
+                    // With --segment-length, the reference hits of each segment's k-mers
+                    let mut segment_hits: Vec<HashMap<(usize, u16), usize>> = match args.segment_length {
+                        Some(segment_length) => (0..norm_seq.len().div_ceil(segment_length)).map(|_| HashMap::new()).collect(),
+                        None => Vec::new(),
+                    };
 
                     if norm_seq.len() < k as usize {
                         return None;
                     }
 
-                    for window in norm_seq.windows(k as usize) {
+                    for (pos, window) in norm_seq.windows(k as usize).enumerate() {
+                        if let Some(cap) = args.max_hits_cap {
+                            let reached = |&hits: &usize| hits >= cap;
+                            if (cap_on_any_database && kmer_hits.iter().any(reached))
+                                || kmer_hits.iter().all(reached)
+                            {
+                                capped_reads.fetch_add(1, Ordering::Relaxed);
+                                break;
+                            }
+                        }
                         if let Some(kmer_val) = seq_to_u64(window, k) {
-                            let canonical_kmer = canonical_u64(kmer_val, k);
-                            if db_all_kmers.contains(&canonical_kmer) {
-                                kmer_hits += 1;
+                            let canonical_kmer = canonical_u64_with_policy(kmer_val, k, canonical_policy);
+                            let mut window_hit = false;
+                            // Whether the window matched on the read's forward strand, on its
+                            // reverse strand or both, in any database
+                            let mut forward_match = false;
+                            let mut reverse_match = false;
+                            for (db_index, db) in databases.iter().enumerate() {
+                                let (forward, reverse) = if raw_lookup(db) {
+                                    // K-mers are stored as read, so each strand's own encoding is looked up
+                                    (raw_in_database(db, kmer_val), raw_in_database(db, reverse_complement_u64(kmer_val, k)))
+                                } else if window_in_database(db, canonical_kmer, kmer_val) {
+                                    // Only the canonical encoding is stored, so the strand reported is
+                                    // the window's orientation relative to it. Palindromic k-mers are
+                                    // in canonical orientation.
+                                    (canonical_kmer == kmer_val, canonical_kmer != kmer_val)
+                                } else {
+                                    (false, false)
+                                };
+                                if forward || reverse {
+                                    kmer_hits[db_index] += 1;
+                                    window_hit = true;
+                                }
+                                forward_match |= forward;
+                                reverse_match |= reverse;
+                                window_strands[db_index] = (forward, reverse);
+                            }
+                            // Strand counts and covered intervals use windows found in any database
+                            if window_hit {
+                                if args.canonical_both {
+                                    if is_palindrome(kmer_val, k) {
+                                        // A palindrome matches both strands equally
+                                        if args.palindromes == PalindromeCounting::Both {
+                                            forward_hits += 1;
+                                            reverse_hits += 1;
+                                        }
+                                    } else {
+                                        // Count a hit on every strand that matched
+                                        if forward_match {
+                                            forward_hits += 1;
+                                        }
+                                        if reverse_match {
+                                            reverse_hits += 1;
+                                        }
+                                    }
+                                } else if forward_match {
+                                    // A window matching on both strands counts once, as forward
+                                    forward_hits += 1;
+                                } else if reverse_match {
+                                    reverse_hits += 1;
+                                }
+                                if track_intervals {
+                                    // Report intervals in untrimmed read coordinates
+                                    add_covered_window(&mut covered_intervals, pos + args.trim_front, k as usize);
+                                }
+                                // A k-mer counts for the segment that holds all of its bases, if any
+                                let segment = args.segment_length.and_then(|segment_length| {
+                                    let segment = pos / segment_length;
+                                    (pos + k as usize <= (segment + 1) * segment_length).then_some(segment)
+                                });
+                                for (db_index, annotation) in reference_annotations.iter().enumerate() {
+                                    let db = &databases[db_index];
+                                    // The encodings the window was found under: each strand's own
+                                    // in a database of k-mers stored as read, else the canonical one
+                                    let (forward, reverse) = window_strands[db_index];
+                                    let encodings = if raw_lookup(db) {
+                                        [forward.then_some(kmer_val), reverse.then(|| reverse_complement_u64(kmer_val, k))]
+                                    } else {
+                                        [Some(canonical_kmer), None]
+                                    };
+                                    let mut credited: Vec<u16> = Vec::new();
+                                    for encoding in encodings.into_iter().flatten() {
+                                        let key = db.kmer_db_v2.lookup_key(encoding);
+                                        for &reference_id in annotation.inverted_index.get(&key).into_iter().flatten() {
+                                            // A reference holding both strands is credited once
+                                            if credited.contains(&reference_id) {
+                                                continue;
+                                            }
+                                            credited.push(reference_id);
+                                            *reference_hits.entry((db_index, reference_id)).or_default() += 1;
+                                            if let Some(segment) = segment {
+                                                *segment_hits[segment].entry((db_index, reference_id)).or_default() += 1;
+                                            }
+                                        }
+                                    }
+                                }
                             }
                         }
                     }
@@ -100,8 +781,38 @@ pub fn run_query(args: QueryArgs) -> Result<()> {
                     // However, indicatif is generally efficient.
                     pb_query.inc(1);
 
-                    if kmer_hits >= args.min_hits {
-                        Some(read_id_bytes.clone())
+                    let matched_databases: Vec<usize> = (0..databases.len())
+                        .filter(|&db_index| kmer_hits[db_index] >= args.min_hits)
+                        .collect();
+                    let reported = match args.combine {
+                        DatabaseCombine::Any => !matched_databases.is_empty(),
+                        DatabaseCombine::All => matched_databases.len() == databases.len(),
+                    };
+                    if reported {
+                        let segments = args.segment_length.map_or_else(Vec::new, |segment_length| {
+                            segment_hits
+                                .iter()
+                                .enumerate()
+                                .map(|(segment, hits)| ReadSegment {
+                                    // In untrimmed read coordinates, like --bed intervals
+                                    start: segment * segment_length + args.trim_front,
+                                    end: ((segment + 1) * segment_length).min(norm_seq.len()) + args.trim_front,
+                                    best_reference: best_reference(hits),
+                                })
+                                .collect()
+                        });
+                        let mut reference_hits: Vec<((usize, u16), usize)> = reference_hits.into_iter().collect();
+                        reference_hits.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+                        Some(QueryHit {
+                            record_index,
+                            read_id: read_id_bytes.clone(),
+                            covered_intervals,
+                            forward_hits,
+                            reverse_hits,
+                            matched_databases,
+                            reference_hits,
+                            segments,
+                        })
                     } else {
                         None
                     }
@@ -110,25 +821,81 @@ pub fn run_query(args: QueryArgs) -> Result<()> {
             Ok(result)
         })?;
 
+    if let Some(cap) = args.max_hits_cap {
+        info!(
+            "Stopped counting hits early for {} reads reaching --max-hits-cap {}.",
+            capped_reads.get_mut(),
+            cap
+        );
+    }
     info!(
         "Found {} reads matching criteria (min_hits: {}). Writing to output...",
-        matching_read_ids.len(),
+        matching_reads.len(),
         args.min_hits
     );
+    // Bins hold reads, so they are written before duplicate IDs are resolved
+    if let Some(bin_dir) = &args.bin_output_dir {
+        write_read_bins(
+            bin_dir,
+            &bin_records,
+            reads_format,
+            &matching_reads,
+            &reference_annotations,
+            args.keep_unbinned,
+        )?;
+    }
+    let matching_reads = resolve_duplicate_ids(matching_reads, &args)?;
 
-    let mut locked_writer = writer.lock().unwrap();
-    for read_id_vec in &matching_read_ids {
-        locked_writer.write_all(read_id_vec)?;
-        locked_writer.write_all(b"\n")?;
+    let mut output_writer = writer.into_inner().unwrap();
+    if args.segment_length.is_some() {
+        write_read_segments(&mut output_writer, &matching_reads, &reference_annotations)?;
+    } else {
+        for hit in &matching_reads {
+            output_writer.write_all(&hit.read_id)?;
+            if args.strand || args.canonical_both {
+                write!(output_writer, "\t{}\t{}", hit.forward_hits, hit.reverse_hits)?;
+            }
+            if args.annotate_databases {
+                let matched_paths: Vec<String> = hit
+                    .matched_databases
+                    .iter()
+                    .map(|&db_index| db_paths[db_index].to_string_lossy().into_owned())
+                    .collect();
+                write!(output_writer, "\t{}", matched_paths.join(","))?;
+            }
+            if args.annotate_references {
+                let references: Vec<String> = hit
+                    .reference_hits
+                    .iter()
+                    .map(|&((db_index, reference_id), hits)| {
+                        format!("{}:{}", reference_annotations[db_index].reference_names[reference_id as usize], hits)
+                    })
+                    .collect();
+                write!(output_writer, "\t{}", references.join(","))?;
+            }
+            output_writer.write_all(b"\n")?;
+        }
     }
-    locked_writer
-        .flush()
-        .context("Failed to flush output writer for query results")?;
+    finalize_writer(output_writer).context("Failed to finish output writer for query results")?;
 
     info!(
         "Successfully wrote matching read IDs to {:?}",
         args.output_file
     );
 
+    if let Some(bed_path) = &args.bed {
+        info!("Writing covered intervals to BED file: {:?}", bed_path);
+        let mut bed_writer = get_output_writer(bed_path)
+            .with_context(|| format!("Failed to get output writer for BED file: {:?}", bed_path))?;
+        for hit in &matching_reads {
+            for (start, end) in &hit.covered_intervals {
+                bed_writer.write_all(&hit.read_id)?;
+                writeln!(bed_writer, "\t{}\t{}", start, end)?;
+            }
+        }
+        finalize_writer(bed_writer).context("Failed to finish output writer for BED file")?;
+        info!("Successfully wrote covered intervals to {:?}", bed_path);
+    }
+
     Ok(())
 }
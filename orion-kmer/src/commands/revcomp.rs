@@ -0,0 +1,63 @@
+use anyhow::{Context, Result};
+use log::info;
+use needletail::parser::{write_fasta, write_fastq, Format};
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    path::Path,
+};
+
+use crate::{
+    cli::RevcompArgs,
+    kmer::reverse_complement_seq,
+    utils::{finalize_writer, get_output_writer, get_sequence_file_reader, open_fastx_reader, take_input_record},
+};
+
+// Writes the reverse complement of every record of `reader` to `writer`, keeping each record's
+// header, format and line ending; FASTQ qualities are reversed along with the bases.
+// Returns the number of records written.
+fn write_reverse_complements(reader: Box<dyn BufRead + Send>, input_path: &Path, writer: &mut dyn Write) -> Result<usize> {
+    let mut fastx_reader = open_fastx_reader(reader, input_path)
+        .with_context(|| format!("Failed to parse FASTA/Q content from: {:?}", input_path))?;
+    let mut records = 0;
+    while let Some(record) = fastx_reader.next() {
+        if !take_input_record() {
+            break;
+        }
+        let record = record.with_context(|| format!("Error reading record from input file: {:?}", input_path))?;
+        let rc_seq = reverse_complement_seq(&record.seq());
+        match record.format() {
+            Format::Fasta => write_fasta(record.id(), &rc_seq, writer, record.line_ending()),
+            Format::Fastq => {
+                let rc_qual: Option<Vec<u8>> = record.qual().map(|qual| qual.iter().rev().copied().collect());
+                write_fastq(record.id(), &rc_seq, rc_qual.as_deref(), writer, record.line_ending())
+            }
+        }
+        .context("Failed to write reverse-complemented record")?;
+        records += 1;
+    }
+    Ok(records)
+}
+
+pub fn run_revcomp(args: RevcompArgs) -> Result<()> {
+    info!("Starting revcomp command with args: {:?}", args);
+
+    let input_path = args.input_file.as_path();
+    let reader: Box<dyn BufRead + Send> = if input_path == Path::new("-") {
+        Box::new(BufReader::new(io::stdin()))
+    } else {
+        get_sequence_file_reader(input_path)
+            .with_context(|| format!("Failed to get buffered file reader for file: {:?}", input_path))?
+    };
+
+    let records = match &args.output_file {
+        Some(output_path) => {
+            let mut writer = get_output_writer(output_path)?;
+            let records = write_reverse_complements(reader, input_path, &mut writer)?;
+            finalize_writer(writer).context("Failed to finish reverse complement writer")?;
+            records
+        }
+        None => write_reverse_complements(reader, input_path, &mut io::stdout().lock())?,
+    };
+    info!("Wrote the reverse complements of {} records", records);
+    Ok(())
+}
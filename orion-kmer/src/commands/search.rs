@@ -0,0 +1,299 @@
+use anyhow::{Context, Result};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    cli::SearchArgs,
+    commands::build::build_kmer_db_from_file,
+    db_types::{HashFunction, KmerDbV2, KmerHashing},
+    errors::OrionKmerError,
+    kmer::{canonical_u64, hash_kmer, CanonicalPolicy},
+    utils::{
+        check_canonical_lookup, finalize_writer, get_decompressed_input_reader, get_output_writer, load_kmer_db_v2,
+        round_float, track_progress_and_resources,
+    },
+};
+
+/// sourmash's name for MurmurHash3 x64 over canonical DNA k-mers, the only hashing supported.
+pub const SOURMASH_DNA_HASH_FUNCTION: &str = "0.murmur64";
+
+// The subset of a sourmash signature file read by `search`.
+#[derive(Deserialize, Debug)]
+struct SignatureFile {
+    name: Option<String>,
+    hash_function: Option<String>,
+    signatures: Vec<MinHashSketch>,
+}
+
+#[derive(Deserialize, Debug)]
+struct MinHashSketch {
+    ksize: u32,
+    #[serde(default = "default_sourmash_seed")]
+    seed: u64,
+    molecule: Option<String>,
+    mins: Vec<u64>,
+}
+
+fn default_sourmash_seed() -> u64 {
+    42
+}
+
+// sourmash writes a list of signatures, but a single signature object is accepted too
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum SignatureFiles {
+    Many(Vec<SignatureFile>),
+    One(SignatureFile),
+}
+
+#[derive(Serialize, Debug)]
+struct SearchOutput {
+    sketch_path: String,
+    sketch_name: Option<String>,
+    database_path: String,
+    kmer_size: u8,
+    hash_seed: u64,
+    sketch_size: usize,
+    matched_hashes: usize,
+    containment: f64, // (matched_hashes / sketch_size), the estimated fraction of the sample in the database
+}
+
+// Loads the sketch for k-mer size `k` from a sourmash signature JSON file, returning the
+// signature name, the seed and the distinct hashes.
+fn load_sketch(path: &Path, k: u8) -> Result<(Option<String>, u64, HashSet<u64>)> {
+    let reader = get_decompressed_input_reader(path)
+        .with_context(|| format!("Failed to get input reader for sketch file: {:?}", path))?;
+    let files: SignatureFiles = serde_json::from_reader(reader)
+        .with_context(|| format!("Failed to parse sourmash signature JSON from {:?}", path))?;
+    let files = match files {
+        SignatureFiles::Many(files) => files,
+        SignatureFiles::One(file) => vec![file],
+    };
+
+    for file in files {
+        if let Some(hash_function) = &file.hash_function
+            && hash_function != SOURMASH_DNA_HASH_FUNCTION
+        {
+            continue;
+        }
+        for sketch in file.signatures {
+            let is_dna = sketch
+                .molecule
+                .as_deref()
+                .is_none_or(|molecule| molecule.eq_ignore_ascii_case("dna"));
+            if sketch.ksize == k as u32 && is_dna {
+                return Ok((file.name, sketch.seed, sketch.mins.into_iter().collect()));
+            }
+        }
+    }
+    Err(OrionKmerError::Generic(format!(
+        "Sketch file {:?} has no DNA MinHash sketch with k={} (matching the database)",
+        path, k
+    ))
+    .into())
+}
+
+// Returns the sketch hashes that occur among the database's k-mers.
+fn matched_sketch_hashes(kmer_db: &KmerDbV2, sketch_hashes: &HashSet<u64>, seed: u64) -> HashSet<u64> {
+    match kmer_db.hashing {
+        // Already stored as the same hashes: look each sketch hash up directly
+        Some(_) => {
+            let db_all_kmers = kmer_db.get_all_kmers_unified();
+            sketch_hashes
+                .iter()
+                .filter(|hash| db_all_kmers.contains(hash))
+                .copied()
+                .collect()
+        }
+        // Stored as encodings: hash every database k-mer the way the sketch was built, in
+        // sourmash's canonical form whatever form the database stores
+        None => kmer_db
+            .iter_unique_kmers()
+            .map(|kmer| hash_kmer(canonical_u64(kmer, kmer_db.k), kmer_db.k, seed))
+            .filter(|hash| sketch_hashes.contains(hash))
+            .collect(),
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct ReferenceContainment {
+    reference_name: String,
+    reference_kmers: usize,
+    shared_kmers: usize,
+    query_in_reference: f64, // (shared_kmers / query_unique_kmers)
+    reference_in_query: f64, // (shared_kmers / reference_kmers)
+}
+
+#[derive(Serialize, Debug)]
+struct QuerySearchOutput {
+    query_path: String,
+    database_path: String,
+    kmer_size: u8,
+    query_unique_kmers: usize,
+    references: Vec<ReferenceContainment>,
+}
+
+fn containment(shared: usize, total: usize) -> f64 {
+    if total > 0 {
+        shared as f64 / total as f64
+    } else {
+        0.0
+    }
+}
+
+fn write_search_json<T: Serialize>(output_path: &Path, output_data: &T) -> Result<()> {
+    let mut writer = get_output_writer(output_path)
+        .with_context(|| format!("Failed to create output JSON file: {:?}", output_path))?;
+    serde_json::to_writer_pretty(&mut writer, output_data)
+        .with_context(|| format!("Failed to write search JSON to {:?}", output_path))?;
+    finalize_writer(writer).context("Failed to finish search JSON writer")
+}
+
+pub fn run_search(args: SearchArgs) -> Result<()> {
+    info!("Starting search command with args: {:?}", args);
+
+    let kmer_db = load_kmer_db_v2(&args.database_file)?;
+    check_canonical_lookup(&kmer_db, &args.database_file, args.strict)?;
+
+    match (&args.query_file, &args.sketch_file) {
+        (Some(query_path), _) => {
+            let output_data = search_query(&kmer_db, &args, query_path)?;
+            write_search_json(&args.output_file, &output_data)?;
+        }
+        (None, Some(sketch_path)) => {
+            let output_data = search_sketch(&kmer_db, &args, sketch_path)?;
+            write_search_json(&args.output_file, &output_data)?;
+        }
+        (None, None) => {
+            return Err(OrionKmerError::Generic(
+                "Either --sketch or --query must be given".to_string(),
+            )
+            .into());
+        }
+    }
+
+    info!("Successfully wrote search results to {:?}", args.output_file);
+    Ok(())
+}
+
+// Exact containment of the query's k-mers in each reference, and of each reference in the query.
+fn search_query(kmer_db: &KmerDbV2, args: &SearchArgs, query_path: &PathBuf) -> Result<QuerySearchOutput> {
+    let k = kmer_db.k;
+    // Extracted like a database built with the same k, hashing and canonical form, so values compare directly
+    info!("Extracting k-mers of {:?} with k={}", query_path, k);
+    let query_db =
+        build_kmer_db_from_file(query_path, k, kmer_db.hashing, kmer_db.canonical, kmer_db.canonical_policy)?;
+    let query_kmers = query_db.get_all_kmers_unified();
+    info!("Query {:?} has {} unique k-mers", query_path, query_kmers.len());
+
+    track_progress_and_resources(
+        &format!(
+            "Searching query {} against {}",
+            query_path.to_string_lossy(),
+            args.database_file.to_string_lossy()
+        ),
+        kmer_db.num_references() as u64,
+        |pb| {
+            let references = kmer_db
+                .references
+                .iter()
+                .map(|(reference_name, reference_kmers)| {
+                    let shared_kmers = if query_kmers.len() <= reference_kmers.len() {
+                        query_kmers.iter().filter(|kmer| reference_kmers.contains(kmer)).count()
+                    } else {
+                        reference_kmers.iter().filter(|kmer| query_kmers.contains(kmer)).count()
+                    };
+                    pb.inc(1);
+                    ReferenceContainment {
+                        reference_name: reference_name.clone(),
+                        reference_kmers: reference_kmers.len(),
+                        shared_kmers,
+                        query_in_reference: round_float(containment(shared_kmers, query_kmers.len())),
+                        reference_in_query: round_float(containment(shared_kmers, reference_kmers.len())),
+                    }
+                })
+                .collect();
+            Ok(QuerySearchOutput {
+                query_path: query_path.to_string_lossy().into_owned(),
+                database_path: args.database_file.to_string_lossy().into_owned(),
+                kmer_size: k,
+                query_unique_kmers: query_kmers.len(),
+                references,
+            })
+        },
+    )
+}
+
+// Estimated containment of a MinHash-sketched sample in the whole database.
+fn search_sketch(kmer_db: &KmerDbV2, args: &SearchArgs, sketch_path: &PathBuf) -> Result<SearchOutput> {
+    let k = kmer_db.k;
+    let (sketch_name, seed, sketch_hashes) = load_sketch(sketch_path, k)?;
+    info!(
+        "Loaded sketch with {} hashes (k={}, seed {}) from {:?}",
+        sketch_hashes.len(),
+        k,
+        seed,
+        sketch_path
+    );
+
+    // A hashed database is only comparable if it used the sketch's hash function and seed
+    let sketch_hashing = KmerHashing {
+        function: HashFunction::Murmur3X64,
+        seed,
+    };
+    if let Some(db_hashing) = kmer_db.hashing {
+        if db_hashing != sketch_hashing {
+            return Err(OrionKmerError::HashingMismatch(
+                Some(sketch_hashing),
+                Some(db_hashing),
+                args.database_file.clone(),
+            )
+            .into());
+        }
+        // Hashes cannot be recanonicalized, so they must be of lexicographically canonical k-mers,
+        // as sketches hash
+        if !kmer_db.canonical {
+            return Err(OrionKmerError::NonCanonicalDatabase(args.database_file.clone()).into());
+        }
+        if kmer_db.canonical_policy != CanonicalPolicy::Lexicographic {
+            return Err(OrionKmerError::CanonicalPolicyMismatch(
+                CanonicalPolicy::Lexicographic,
+                kmer_db.canonical_policy,
+                args.database_file.clone(),
+            )
+            .into());
+        }
+    }
+
+    let output_data = track_progress_and_resources(
+        &format!(
+            "Searching sketch {} against {}",
+            sketch_path.to_string_lossy(),
+            args.database_file.to_string_lossy()
+        ),
+        1,
+        |pb| {
+            let matched_hashes = matched_sketch_hashes(kmer_db, &sketch_hashes, seed).len();
+            pb.inc(1);
+
+            let sketch_size = sketch_hashes.len();
+            Ok(SearchOutput {
+                sketch_path: sketch_path.to_string_lossy().into_owned(),
+                sketch_name,
+                database_path: args.database_file.to_string_lossy().into_owned(),
+                kmer_size: k,
+                hash_seed: seed,
+                sketch_size,
+                matched_hashes,
+                containment: containment(matched_hashes, sketch_size),
+            })
+        },
+    )?;
+
+    info!("Search results: {:?}", output_data);
+    Ok(output_data)
+}
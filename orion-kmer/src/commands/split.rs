@@ -0,0 +1,81 @@
+use anyhow::{Context, Result};
+use log::info;
+use std::{
+    collections::BTreeMap,
+    fs,
+};
+
+use crate::{
+    cli::{OutputCompression, SplitArgs},
+    db_types::KmerDbV2,
+    errors::OrionKmerError,
+    utils::{load_kmer_db_v2, sanitize_reference_name, write_kmer_db},
+};
+
+// A database holding only reference `name` of `kmer_db`, with its positions, sampling rate,
+// sequence lengths and name normalization.
+fn single_reference_db(kmer_db: &KmerDbV2, name: &str) -> KmerDbV2 {
+    let mut single = KmerDbV2::new(kmer_db.k);
+    single.hashing = kmer_db.hashing;
+    single.canonical = kmer_db.canonical;
+    single.canonical_policy = kmer_db.canonical_policy;
+    single.name_normalization = kmer_db.name_normalization;
+    single.references = BTreeMap::from([(name.to_string(), kmer_db.references[name].clone())]);
+    if let Some(positions) = kmer_db.positions.get(name) {
+        single.positions.insert(name.to_string(), positions.clone());
+    }
+    if let Some(&rate) = kmer_db.sampling_rates.get(name) {
+        single.sampling_rates.insert(name.to_string(), rate);
+    }
+    if let Some(lengths) = kmer_db.reference_lengths.get(name) {
+        single.reference_lengths.insert(name.to_string(), lengths.clone());
+    }
+    single
+}
+
+pub fn run_split(args: SplitArgs) -> Result<()> {
+    info!("Starting split command with args: {:?}", args);
+
+    let kmer_db = load_kmer_db_v2(&args.database_file)
+        .with_context(|| format!("Failed to load database to split: {:?}", args.database_file))?;
+    let extension = match args.compress {
+        None => "db",
+        Some(OutputCompression::Gz) => "db.gz",
+        Some(OutputCompression::Xz) => "db.xz",
+        Some(OutputCompression::Zst) => "db.zst",
+    };
+
+    // Reference names may be paths or headers, so files are named after their sanitized form
+    let mut file_names: BTreeMap<String, &str> = BTreeMap::new();
+    for name in kmer_db.references.keys() {
+        let file_name = format!("{}.{}", sanitize_reference_name(name), extension);
+        if let Some(other) = file_names.insert(file_name.clone(), name) {
+            return Err(OrionKmerError::Generic(format!(
+                "References {:?} and {:?} would both be written to {:?}; rename one before splitting",
+                other, name, file_name
+            ))
+            .into());
+        }
+    }
+
+    fs::create_dir_all(&args.output_dir)
+        .with_context(|| format!("Failed to create output directory: {:?}", args.output_dir))?;
+    for (file_name, name) in &file_names {
+        let output_path = args.output_dir.join(file_name);
+        write_kmer_db(&single_reference_db(&kmer_db, name), &output_path)?;
+        info!(
+            "Wrote reference {:?} ({} k-mers) to {:?}",
+            name,
+            kmer_db.references[*name].len(),
+            output_path
+        );
+    }
+
+    info!(
+        "Split {:?} into {} single-reference databases in {:?}",
+        args.database_file,
+        file_names.len(),
+        args.output_dir
+    );
+    Ok(())
+}
@@ -0,0 +1,135 @@
+use anyhow::{Context, Result};
+use bincode::Options;
+use log::info;
+use std::{
+    collections::HashMap,
+    io::{BufRead, Read, Write},
+    path::Path,
+};
+
+use crate::{
+    cli::SumArgs,
+    db_types::KmerCountTable,
+    errors::OrionKmerError,
+    kmer::{seq_to_u64, u64_to_seq},
+    utils::{db_bincode_options, finalize_writer, get_decompressed_input_reader, get_output_writer},
+};
+
+// Reads a count table, returning its k (None for an empty TSV) and counts.
+// `count` TSV rows start with a base letter, while a binary table starts with its k (1-32),
+// so the first byte tells the formats apart.
+pub(crate) fn read_count_table(path: &Path) -> Result<(Option<u8>, HashMap<u64, usize>)> {
+    let mut reader = get_decompressed_input_reader(path)
+        .with_context(|| format!("Failed to get input reader for count table: {:?}", path))?;
+    let is_binary = reader
+        .fill_buf()
+        .with_context(|| format!("Failed to read count table: {:?}", path))?
+        .first()
+        .is_some_and(|&first_byte| !first_byte.is_ascii_alphabetic());
+
+    if is_binary {
+        let mut bytes = Vec::new();
+        reader
+            .read_to_end(&mut bytes)
+            .with_context(|| format!("Failed to read count table: {:?}", path))?;
+        let table: KmerCountTable = db_bincode_options().deserialize(&bytes).map_err(|e| {
+            OrionKmerError::DeserializationError(format!(
+                "Failed to deserialize binary count table from {:?}: {}",
+                path, e
+            ))
+        })?;
+        return Ok((Some(table.k), table.counts));
+    }
+
+    let mut k: Option<u8> = None;
+    let mut counts = HashMap::new();
+    for (line_index, line) in reader.lines().enumerate() {
+        let line = line.with_context(|| format!("Failed to read count table: {:?}", path))?;
+        let invalid_row = || {
+            OrionKmerError::FileParsingError(format!(
+                "{:?} line {}: expected `<kmer>\\t<integer count>`, got '{}'",
+                path,
+                line_index + 1,
+                line
+            ))
+        };
+        let (kmer_str, count_str) = line.split_once('\t').ok_or_else(invalid_row)?;
+        let row_k = u8::try_from(kmer_str.len()).map_err(|_| invalid_row())?;
+        if *k.get_or_insert(row_k) != row_k {
+            return Err(invalid_row().into());
+        }
+        let kmer_val = seq_to_u64(kmer_str.as_bytes(), row_k).ok_or_else(invalid_row)?;
+        let count: usize = count_str.parse().map_err(|_| invalid_row())?;
+        *counts.entry(kmer_val).or_insert(0) += count;
+    }
+    Ok((k, counts))
+}
+
+pub fn run_sum(args: SumArgs) -> Result<()> {
+    info!("Starting sum command with args: {:?}", args);
+
+    // The first table with a known k fixes it for the others
+    let mut k: Option<(u8, &Path)> = None;
+    let mut summed_counts: HashMap<u64, usize> = HashMap::new();
+    for input_path in &args.input_files {
+        let (table_k, counts) = read_count_table(input_path)?;
+        if let Some(table_k) = table_k {
+            match k {
+                Some((k, first_path)) if k != table_k => {
+                    return Err(OrionKmerError::Generic(format!(
+                        "Count table {:?} has k={}, but {:?} has k={}",
+                        input_path, table_k, first_path, k
+                    ))
+                    .into());
+                }
+                Some(_) => {}
+                None => k = Some((table_k, input_path)),
+            }
+        }
+        info!("Read {} k-mers from {:?}", counts.len(), input_path);
+        for (kmer_val, count) in counts {
+            *summed_counts.entry(kmer_val).or_insert(0) += count;
+        }
+    }
+
+    let Some((k, _)) = k else {
+        return Err(OrionKmerError::Generic(
+            "All count tables are empty, so their k-mer size is unknown".to_string(),
+        )
+        .into());
+    };
+    summed_counts.retain(|_, count| *count >= args.min_count);
+    info!(
+        "Writing {} k-mers (summed count >= {}) to {:?}",
+        summed_counts.len(),
+        args.min_count,
+        args.output_file
+    );
+
+    let mut writer = get_output_writer(&args.output_file).with_context(|| {
+        format!(
+            "Failed to get output writer for summed counts: {:?}",
+            args.output_file
+        )
+    })?;
+    if args.binary {
+        let table = KmerCountTable {
+            k,
+            counts: summed_counts,
+        };
+        db_bincode_options().serialize_into(&mut writer, &table).with_context(|| {
+            format!("Failed to serialize count table to {:?}", args.output_file)
+        })?;
+    } else {
+        let mut kmer_vec: Vec<(u64, usize)> = summed_counts.into_iter().collect();
+        kmer_vec.sort_by_key(|item| item.0);
+        for (kmer_val, count) in kmer_vec {
+            writer.write_all(&u64_to_seq(kmer_val, k))?;
+            writeln!(writer, "\t{}", count)?;
+        }
+    }
+    finalize_writer(writer).context("Failed to finish summed counts writer")?;
+
+    info!("Successfully wrote summed k-mer counts to {:?}", args.output_file);
+    Ok(())
+}
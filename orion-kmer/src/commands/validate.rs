@@ -0,0 +1,97 @@
+use anyhow::{Context, Result};
+use log::info;
+use needletail::{errors::ParseError, parse_fastx_reader, parser::Format};
+use std::{collections::BTreeSet, io::Write};
+
+use crate::{cli::ValidateArgs, errors::OrionKmerError, utils::get_decompressed_input_reader};
+
+// Summary of a sequence file; `first_error` is set when parsing stopped at a malformed record.
+#[derive(Default)]
+struct ValidationReport {
+    format: Option<Format>,
+    records: usize,
+    total_bases: u64,
+    alphabet: BTreeSet<u8>,
+    first_error: Option<ParseError>,
+}
+
+fn format_name(format: Option<Format>) -> &'static str {
+    match format {
+        Some(Format::Fasta) => "FASTA",
+        Some(Format::Fastq) => "FASTQ",
+        None => "unknown",
+    }
+}
+
+pub fn run_validate(args: ValidateArgs) -> Result<()> {
+    info!("Starting validate command with args: {:?}", args);
+
+    // Decompression failures are I/O problems rather than malformed records, so they stay errors
+    let input_buf_reader = get_decompressed_input_reader(&args.input_file).with_context(|| {
+        format!(
+            "Failed to get input reader for file: {:?}",
+            args.input_file
+        )
+    })?;
+
+    let mut report = ValidationReport::default();
+    match parse_fastx_reader(input_buf_reader) {
+        Ok(mut reader) => {
+            while let Some(record) = reader.next() {
+                match record {
+                    Ok(record) => {
+                        report.format.get_or_insert(record.format());
+                        report.records += 1;
+                        let seq = record.raw_seq();
+                        report.total_bases += seq.len() as u64;
+                        report.alphabet.extend(seq.iter().map(|base| base.to_ascii_uppercase()));
+                    }
+                    Err(e) => {
+                        report.format = report.format.or(e.format);
+                        report.first_error = Some(e);
+                        break;
+                    }
+                }
+            }
+        }
+        Err(e) => {
+            report.format = e.format;
+            report.first_error = Some(e);
+        }
+    }
+
+    let stdout = std::io::stdout();
+    let mut writer = stdout.lock();
+    writeln!(writer, "file\t{}", args.input_file.display())?;
+    writeln!(writer, "format\t{}", format_name(report.format))?;
+    writeln!(writer, "records\t{}", report.records)?;
+    writeln!(writer, "total_bases\t{}", report.total_bases)?;
+    writeln!(
+        writer,
+        "alphabet\t{}",
+        String::from_utf8_lossy(&report.alphabet.iter().copied().collect::<Vec<u8>>())
+    )?;
+    match &report.first_error {
+        None => writeln!(writer, "status\tok")?,
+        Some(e) => {
+            writeln!(writer, "status\tmalformed")?;
+            writeln!(writer, "error\t{}", e)?;
+        }
+    }
+    writer.flush()?;
+
+    if let Some(e) = report.first_error {
+        return Err(OrionKmerError::Generic(format!(
+            "{:?} is not a valid FASTA/FASTQ file: {} ({} records parsed before the error)",
+            args.input_file, e, report.records
+        ))
+        .into());
+    }
+    info!(
+        "{:?} is a valid {} file with {} records",
+        args.input_file,
+        format_name(report.format),
+        report.records
+    );
+    Ok(())
+}
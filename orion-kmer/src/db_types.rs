@@ -1,5 +1,34 @@
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+
+use crate::kmer::{canonical_u64_with_policy, hash_kmer, CanonicalPolicy};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// Most references KmerDbV2::build_inverted_index can number with u16 IDs.
+pub const MAX_INVERTED_INDEX_REFERENCES: usize = u16::MAX as usize + 1;
+
+/// Several k-mer databases built from the same inputs at different k-mer sizes, stored in one
+/// file (see `build --k-list`). Commands that read a database pick one table by k.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct KmerDbMultiK {
+    /// One database per k-mer size, keyed by k.
+    pub tables: BTreeMap<u8, KmerDbV2>,
+}
+
+impl KmerDbMultiK {
+    /// The k-mer sizes with a table, in increasing order.
+    pub fn k_values(&self) -> Vec<u8> {
+        self.tables.keys().copied().collect()
+    }
+}
+
+// SplitMix64 finalizer of a k-mer key xor'ed with a seed: a cheap, well-mixed ranking for
+// sampling k-mers that works for encoded and already-hashed keys alike.
+fn mix_kmer(kmer: u64, seed: u64) -> u64 {
+    let mut z = (kmer ^ seed).wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
 
 /// Represents a k-mer database.
 ///
@@ -10,7 +39,183 @@ pub struct KmerDbV2 {
     pub k: u8,
     /// A map where keys are reference identifiers (e.g., filenames from which k-mers were derived)
     /// and values are sets of unique k-mers (encoded as u64) found in that reference.
-    pub references: HashMap<String, HashSet<u64>>,
+    /// Ordered by name so per-reference output is deterministic.
+    pub references: BTreeMap<String, HashSet<u64>>,
+    /// How k-mers were hashed before being stored, or None when the raw 2-bit canonical
+    /// encodings are stored. Lookups must apply the same hashing to their k-mers.
+    pub hashing: Option<KmerHashing>,
+    /// Whether k-mers were stored in canonical form (the smaller of a k-mer and its reverse
+    /// complement). `query`, `classify` and `compare` canonicalize their k-mers before lookup.
+    /// Headerless databases, written before this field existed, were always canonical.
+    pub canonical: bool,
+    /// Where each reference's k-mers lie along its sequence, for references built `--per-record`
+    /// from a single linear sequence. Empty for references built from whole files, and for
+    /// headerless databases, which predate positions.
+    pub positions: BTreeMap<String, ReferencePositions>,
+    /// For references capped by `build --max-kmers-per-reference`, the fraction of their
+    /// unique k-mers that was kept. References stored in full have no entry, as have all
+    /// references of headerless databases, which could not be capped.
+    pub sampling_rates: BTreeMap<String, f64>,
+    /// How the strand of canonical k-mers was chosen (`build --canonical-policy`); lookups must
+    /// canonicalize their k-mers the same way (see `canonicalize`). This and the fields below
+    /// are part of the database since format version 4; older databases kept them in tagged
+    /// sections after it.
+    pub canonical_policy: CanonicalPolicy,
+    /// How the database was built, for databases written by `build`; None for databases
+    /// written without it, including headerless ones.
+    pub metadata: Option<DbMetadata>,
+    /// The lengths of the sequence records each reference was built from, for references
+    /// built from sequence files.
+    pub reference_lengths: BTreeMap<String, SequenceLengths>,
+    /// How `build --normalize-ref-names` rewrote the reference names, so that reference
+    /// selections can be matched the same way.
+    pub name_normalization: Option<ReferenceNameNormalization>,
+}
+
+/// How `build --normalize-ref-names` rewrites reference names before storing them.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReferenceNameNormalization {
+    /// Leading and trailing whitespace removed.
+    Trim,
+    /// Trimmed and lowercased.
+    Lowercase,
+}
+
+impl ReferenceNameNormalization {
+    /// Returns `name` as a database built with this normalization stores it.
+    pub fn apply(self, name: &str) -> String {
+        match self {
+            ReferenceNameNormalization::Trim => name.trim().to_string(),
+            ReferenceNameNormalization::Lowercase => name.trim().to_lowercase(),
+        }
+    }
+}
+
+/// The lengths of a reference's sequence records, as a count of records per length.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct SequenceLengths {
+    /// Number of records of each length in bases.
+    pub records_by_length: BTreeMap<u64, u64>,
+}
+
+impl SequenceLengths {
+    /// Records one sequence of `length` bases.
+    pub fn add(&mut self, length: u64) {
+        *self.records_by_length.entry(length).or_insert(0) += 1;
+    }
+
+    /// Total length of the records in bases.
+    pub fn bases(&self) -> u64 {
+        self.records_by_length.iter().map(|(length, records)| length * records).sum()
+    }
+
+    /// Number of k-mer windows along the records: `length - k + 1` for every record of at
+    /// least k bases.
+    pub fn kmer_windows(&self, k: u8) -> u64 {
+        self.records_by_length
+            .iter()
+            .map(|(length, records)| (length + 1).saturating_sub(k as u64) * records)
+            .sum()
+    }
+}
+
+/// Provenance of a database written by `build`, shown by `info`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct DbMetadata {
+    /// Version of orion-kmer that wrote the database.
+    pub orion_kmer_version: String,
+    /// When the database was written, in seconds since the Unix epoch.
+    pub built_at_unix_secs: u64,
+    /// The command line of the build (including `orion-kmer` itself).
+    pub command_line: Vec<String>,
+    /// The k-mer size(s) built: the `-k` given, or every `--k-list` value.
+    pub k_values: Vec<u8>,
+    /// Whether k-mers were stored in canonical form (false with `--no-canonical`).
+    pub canonical: bool,
+    /// Whether k-mers were stored hashed (`--hashed`).
+    pub hashed: bool,
+    /// Input paths as given to `build`, including those of databases it appended to.
+    pub source_files: Vec<String>,
+}
+
+/// The k-mer coordinates of one linear reference sequence.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ReferencePositions {
+    /// Length of the reference sequence in bases.
+    pub length: u64,
+    /// (stored k-mer key, 0-based start) pairs, sorted by key and then start, so the
+    /// positions of a k-mer can be found by binary search.
+    pub kmer_positions: Vec<(u64, u64)>,
+}
+
+impl ReferencePositions {
+    /// Returns, for every base of the reference, the summed `weight` of the k-mers covering it.
+    /// `weight` is called once per stored k-mer occurrence with the k-mer's key.
+    pub fn coverage(&self, k: u8, mut weight: impl FnMut(u64) -> u64) -> Vec<u64> {
+        // Difference array: +w where a k-mer starts, -w just past its end
+        let length = self.length as usize;
+        let mut deltas = vec![0i128; length + 1];
+        for &(key, start) in &self.kmer_positions {
+            let w = weight(key);
+            if w == 0 {
+                continue;
+            }
+            let start = start as usize;
+            let end = (start + k as usize).min(length);
+            deltas[start] += w as i128;
+            deltas[end] -= w as i128;
+        }
+        let mut depth = 0i128;
+        deltas[..length]
+            .iter()
+            .map(|delta| {
+                depth += delta;
+                depth as u64
+            })
+            .collect()
+    }
+
+    /// Returns, for every base of the reference, the `weight` of the k-mer starting at it, or 0
+    /// where no stored k-mer starts (the last k - 1 bases and windows with non-ACGT bases).
+    pub fn start_depth(&self, mut weight: impl FnMut(u64) -> u64) -> Vec<u64> {
+        let mut depth = vec![0u64; self.length as usize];
+        for &(key, start) in &self.kmer_positions {
+            depth[start as usize] += weight(key);
+        }
+        depth
+    }
+}
+
+/// Hash functions available for storing k-mers as hashes instead of encodings.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashFunction {
+    /// MurmurHash3 x64_128 (first 64 bits) over the canonical k-mer string, as used by sourmash/Mash.
+    Murmur3X64,
+}
+
+/// The hash function and seed used to hash a database's k-mers.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KmerHashing {
+    pub function: HashFunction,
+    pub seed: u64,
+}
+
+impl HashFunction {
+    /// Width of the stored hash values in bits.
+    pub fn bits(&self) -> u32 {
+        match self {
+            HashFunction::Murmur3X64 => 64,
+        }
+    }
+}
+
+impl KmerHashing {
+    /// Hashes a canonical k-mer encoding.
+    pub fn hash(&self, canonical_kmer: u64, k: u8) -> u64 {
+        match self.function {
+            HashFunction::Murmur3X64 => hash_kmer(canonical_kmer, k, self.seed),
+        }
+    }
 }
 
 // For compatibility, and for commands that might operate on a "flat" DB view
@@ -24,21 +229,118 @@ pub struct KmerDb {
     pub kmers: HashSet<u64>,
 }
 
+/// A table of canonical k-mers and their occurrence counts.
+///
+/// Written as bincode (optionally compressed) when a compact binary count table is needed,
+/// e.g. `classify --dump-input-kmers`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct KmerCountTable {
+    pub k: u8,
+    pub counts: HashMap<u64, usize>,
+}
+
+/// Overlap statistics between two k-mer sets, as computed by `compare`.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct ComparisonStats {
+    /// Number of k-mers in the first set.
+    pub a_size: usize,
+    /// Number of k-mers in the second set.
+    pub b_size: usize,
+    pub intersection_size: usize,
+    pub union_size: usize,
+    /// Intersection over union; 0.0 when both sets are empty.
+    pub jaccard_index: f64,
+    /// Fraction of the first set's k-mers found in the second; 0.0 when the first set is empty.
+    pub containment_a_in_b: f64,
+    /// Fraction of the second set's k-mers found in the first; 0.0 when the second set is empty.
+    pub containment_b_in_a: f64,
+}
+
 impl KmerDbV2 {
     /// Creates a new, empty KmerDbV2 with a specified k-mer size.
     pub fn new(k: u8) -> Self {
         KmerDbV2 {
             k,
-            references: HashMap::new(),
+            references: BTreeMap::new(),
+            hashing: None,
+            canonical: true,
+            positions: BTreeMap::new(),
+            sampling_rates: BTreeMap::new(),
+            canonical_policy: CanonicalPolicy::Lexicographic,
+            metadata: None,
+            reference_lengths: BTreeMap::new(),
+            name_normalization: None,
+        }
+    }
+
+    /// Returns the canonical form of an encoded k-mer under this database's canonical policy,
+    /// ready for `lookup_key`.
+    pub fn canonicalize(&self, kmer_val: u64) -> u64 {
+        canonical_u64_with_policy(kmer_val, self.k, self.canonical_policy)
+    }
+
+    /// Returns the key under which a canonical k-mer is stored in this database,
+    /// applying the database's hashing if it has any.
+    pub fn lookup_key(&self, canonical_kmer: u64) -> u64 {
+        match &self.hashing {
+            Some(hashing) => hashing.hash(canonical_kmer, self.k),
+            None => canonical_kmer,
         }
     }
 
     /// Adds a reference and its set of k-mers to the database.
-    /// If the reference name already exists, its k-mer set will be overwritten.
+    /// If the reference name already exists, its k-mer set will be overwritten and any
+    /// positions, sampling rate or sequence lengths stored for it are dropped.
     pub fn add_reference(&mut self, name: String, kmers: HashSet<u64>) {
+        self.positions.remove(&name);
+        self.reference_lengths.remove(&name);
+        self.sampling_rates.remove(&name);
         self.references.insert(name, kmers);
     }
 
+    /// Removes the k-mers stored under `keys` from every reference, along with their positions.
+    /// Returns how many (reference, k-mer) entries were removed.
+    pub fn remove_kmers(&mut self, keys: &HashSet<u64>) -> usize {
+        let mut removed = 0;
+        for kmer_set in self.references.values_mut() {
+            let before = kmer_set.len();
+            kmer_set.retain(|kmer| !keys.contains(kmer));
+            removed += before - kmer_set.len();
+        }
+        for positions in self.positions.values_mut() {
+            positions.kmer_positions.retain(|(key, _)| !keys.contains(key));
+        }
+        removed
+    }
+
+    /// Caps a reference at `max_kmers` k-mers by keeping a uniform sample of them, and records
+    /// the fraction kept in `sampling_rates`. The sample is the `max_kmers` k-mers with the
+    /// smallest `seed`-keyed hashes, so the same seed always keeps the same k-mers whatever the
+    /// set's iteration order. Positions of dropped k-mers are removed too. Returns whether the
+    /// reference had more than `max_kmers` k-mers.
+    pub fn sample_reference(&mut self, name: &str, max_kmers: usize, seed: u64) -> bool {
+        let Some(kmer_set) = self.references.get_mut(name) else {
+            return false;
+        };
+        if kmer_set.len() <= max_kmers {
+            return false;
+        }
+        let total_kmers = kmer_set.len();
+        let mut ranked: Vec<(u64, u64)> = kmer_set.iter().map(|&kmer| (mix_kmer(kmer, seed), kmer)).collect();
+        if max_kmers > 0 {
+            ranked.select_nth_unstable(max_kmers - 1);
+        }
+        ranked.truncate(max_kmers);
+        *kmer_set = ranked.into_iter().map(|(_, kmer)| kmer).collect();
+        if let Some(positions) = self.positions.get_mut(name) {
+            let kept = &self.references[name];
+            positions.kmer_positions.retain(|(key, _)| kept.contains(key));
+        }
+        self.sampling_rates
+            .insert(name.to_string(), max_kmers as f64 / total_kmers as f64);
+        true
+    }
+
     /// Returns a unified set of all unique k-mers from all references in the database.
     pub fn get_all_kmers_unified(&self) -> HashSet<u64> {
         self.references
@@ -47,6 +349,57 @@ impl KmerDbV2 {
             .collect()
     }
 
+    /// Iterates over every distinct k-mer in the database once, skipping those already yielded
+    /// from an earlier reference (by name).
+    pub fn iter_unique_kmers(&self) -> impl Iterator<Item = u64> + '_ {
+        let mut seen = HashSet::new();
+        self.references
+            .values()
+            .flat_map(|kmer_set| kmer_set.iter().copied())
+            .filter(move |&kmer| seen.insert(kmer))
+    }
+
+    /// Iterates over the k-mers of a single reference, or returns None if there is no
+    /// reference with that name.
+    pub fn iter_reference_kmers(&self, name: &str) -> Option<impl Iterator<Item = u64> + '_> {
+        self.references
+            .get(name)
+            .map(|kmer_set| kmer_set.iter().copied())
+    }
+
+    /// Returns, for every k-mer in the database, the number of references containing it. This
+    /// is the one count behind `build --uniqueness-report`, `classify --weight-by-uniqueness`
+    /// and `dump --format u64`; it does not depend on the order references were added in.
+    pub fn reference_occurrence_counts(&self) -> HashMap<u64, u32> {
+        let mut occurrences: HashMap<u64, u32> = HashMap::new();
+        for kmer_set in self.references.values() {
+            for kmer in kmer_set {
+                *occurrences.entry(*kmer).or_insert(0) += 1;
+            }
+        }
+        occurrences
+    }
+
+    /// Maps every k-mer in the database to the IDs of the references containing it, so a
+    /// k-mer can be attributed to all its references with one lookup. A reference's ID is
+    /// its position in `references` (ordered by name). Panics if the database has more than
+    /// MAX_INVERTED_INDEX_REFERENCES references, which u16 IDs cannot tell apart.
+    pub fn build_inverted_index(&self) -> HashMap<u64, Vec<u16>> {
+        assert!(
+            self.references.len() <= MAX_INVERTED_INDEX_REFERENCES,
+            "an inverted index holds at most {} references, the database has {}",
+            MAX_INVERTED_INDEX_REFERENCES,
+            self.references.len()
+        );
+        let mut inverted_index: HashMap<u64, Vec<u16>> = HashMap::new();
+        for (reference_id, kmer_set) in self.references.values().enumerate() {
+            for kmer in kmer_set {
+                inverted_index.entry(*kmer).or_default().push(reference_id as u16);
+            }
+        }
+        inverted_index
+    }
+
     /// Returns the total number of unique k-mers across all references.
     pub fn total_unique_kmers(&self) -> usize {
         self.get_all_kmers_unified().len()
@@ -57,3 +410,180 @@ impl KmerDbV2 {
         self.references.len()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_iter_unique_kmers_yields_each_kmer_once() {
+        let mut kmer_db = KmerDbV2::new(4);
+        kmer_db.add_reference("a".to_string(), HashSet::from([1, 2, 3]));
+        kmer_db.add_reference("b".to_string(), HashSet::from([2, 3, 4]));
+        kmer_db.add_reference("c".to_string(), HashSet::from([3, 5]));
+
+        let mut kmers: Vec<u64> = kmer_db.iter_unique_kmers().collect();
+        kmers.sort_unstable();
+        assert_eq!(kmers, vec![1, 2, 3, 4, 5]);
+        assert_eq!(
+            kmer_db.iter_unique_kmers().collect::<HashSet<u64>>(),
+            kmer_db.get_all_kmers_unified()
+        );
+    }
+
+    #[test]
+    fn test_reference_occurrence_counts() {
+        let mut kmer_db = KmerDbV2::new(4);
+        kmer_db.add_reference("b".to_string(), HashSet::from([2, 3, 4]));
+        kmer_db.add_reference("a".to_string(), HashSet::from([1, 2, 3]));
+        kmer_db.add_reference("c".to_string(), HashSet::from([3]));
+        let occurrences = kmer_db.reference_occurrence_counts();
+        assert_eq!(occurrences, HashMap::from([(1, 1), (2, 2), (3, 3), (4, 1)]));
+
+        // Re-adding a reference replaces its k-mers instead of counting them twice
+        kmer_db.add_reference("c".to_string(), HashSet::from([5]));
+        let occurrences = kmer_db.reference_occurrence_counts();
+        assert_eq!(occurrences, HashMap::from([(1, 1), (2, 2), (3, 2), (4, 1), (5, 1)]));
+        assert!(KmerDbV2::new(4).reference_occurrence_counts().is_empty());
+    }
+
+    #[test]
+    fn test_sequence_lengths_count_kmer_windows() {
+        let mut lengths = SequenceLengths::default();
+        for length in [10, 3, 10, 5] {
+            lengths.add(length);
+        }
+        assert_eq!(lengths.bases(), 28);
+        // 7 + 7 + 2 windows; the 3-base record is shorter than k
+        assert_eq!(lengths.kmer_windows(4), 16);
+        assert_eq!(lengths.kmer_windows(11), 0);
+    }
+
+    #[test]
+    fn test_sample_reference_keeps_seeded_subset() {
+        let mut kmer_db = KmerDbV2::new(4);
+        kmer_db.add_reference("big".to_string(), (0..100).collect());
+        kmer_db.add_reference("small".to_string(), HashSet::from([1, 2]));
+
+        assert!(!kmer_db.sample_reference("small", 10, 7));
+        assert!(kmer_db.sample_reference("big", 10, 7));
+        let sample = kmer_db.references["big"].clone();
+        assert_eq!(sample.len(), 10);
+        assert!(sample.iter().all(|kmer| *kmer < 100));
+        assert_eq!(kmer_db.sampling_rates.get("big"), Some(&0.1));
+        assert!(!kmer_db.sampling_rates.contains_key("small"));
+
+        // The same seed keeps the same k-mers regardless of insertion order
+        let mut reordered = KmerDbV2::new(4);
+        reordered.add_reference("big".to_string(), (0..100).rev().collect());
+        reordered.sample_reference("big", 10, 7);
+        assert_eq!(reordered.references["big"], sample);
+
+        // Replacing the reference forgets its sampling rate
+        kmer_db.add_reference("big".to_string(), HashSet::from([5]));
+        assert!(kmer_db.sampling_rates.is_empty());
+    }
+
+    #[test]
+    fn test_remove_kmers_from_every_reference_and_positions() {
+        let mut kmer_db = KmerDbV2::new(4);
+        kmer_db.add_reference("a".to_string(), HashSet::from([1, 2, 3]));
+        kmer_db.add_reference("b".to_string(), HashSet::from([2, 4]));
+        kmer_db.positions.insert(
+            "a".to_string(),
+            ReferencePositions {
+                length: 8,
+                kmer_positions: vec![(1, 0), (2, 1), (2, 4), (3, 2)],
+            },
+        );
+
+        assert_eq!(kmer_db.remove_kmers(&HashSet::from([2, 5])), 2);
+        assert_eq!(kmer_db.references["a"], HashSet::from([1, 3]));
+        assert_eq!(kmer_db.references["b"], HashSet::from([4]));
+        assert_eq!(kmer_db.positions["a"].kmer_positions, vec![(1, 0), (3, 2)]);
+    }
+
+    #[test]
+    fn test_iter_unique_kmers_empty_database() {
+        assert_eq!(KmerDbV2::new(4).iter_unique_kmers().count(), 0);
+    }
+
+    #[test]
+    fn test_iter_reference_kmers() {
+        let mut kmer_db = KmerDbV2::new(4);
+        kmer_db.add_reference("a".to_string(), HashSet::from([7, 9]));
+
+        let mut kmers: Vec<u64> = kmer_db.iter_reference_kmers("a").unwrap().collect();
+        kmers.sort_unstable();
+        assert_eq!(kmers, vec![7, 9]);
+        assert!(kmer_db.iter_reference_kmers("missing").is_none());
+    }
+
+    #[test]
+    fn test_build_inverted_index() {
+        let mut kmer_db = KmerDbV2::new(4);
+        kmer_db.add_reference("b".to_string(), HashSet::from([2, 3]));
+        kmer_db.add_reference("a".to_string(), HashSet::from([1, 2]));
+        kmer_db.add_reference("c".to_string(), HashSet::new());
+
+        // IDs follow name order: a=0, b=1, c=2
+        let inverted_index = kmer_db.build_inverted_index();
+        assert_eq!(inverted_index.len(), 3);
+        assert_eq!(inverted_index[&1], vec![0]);
+        assert_eq!(inverted_index[&2], vec![0, 1]);
+        assert_eq!(inverted_index[&3], vec![1]);
+        assert!(KmerDbV2::new(4).build_inverted_index().is_empty());
+    }
+
+    #[test]
+    fn test_reference_positions_coverage() {
+        // k=3 over a 6 bp reference: key 1 at 0 and 3, key 2 at 1
+        let positions = ReferencePositions {
+            length: 6,
+            kmer_positions: vec![(1, 0), (1, 3), (2, 1)],
+        };
+        assert_eq!(positions.coverage(3, |_| 1), vec![1, 2, 2, 2, 1, 1]);
+        assert_eq!(positions.coverage(3, |key| if key == 2 { 5 } else { 0 }), vec![0, 5, 5, 5, 0, 0]);
+        assert!(ReferencePositions { length: 0, kmer_positions: Vec::new() }.coverage(3, |_| 1).is_empty());
+        assert_eq!(positions.start_depth(|key| key * 10), vec![10, 20, 0, 10, 0, 0]);
+    }
+
+    #[test]
+    fn test_db_bincode_options_round_trip_as_fixed_width_little_endian() {
+        use crate::utils::db_bincode_options;
+        use bincode::Options;
+
+        let mut kmer_db = KmerDbV2::new(4);
+        kmer_db.add_reference("a".to_string(), HashSet::from([0x0102_0304_0506_0708]));
+        kmer_db.sampling_rates.insert("a".to_string(), 0.5);
+        let bytes = db_bincode_options().serialize(&kmer_db).unwrap();
+
+        let mut expected = vec![4u8]; // k
+        expected.extend(1u64.to_le_bytes()); // one reference
+        expected.extend(1u64.to_le_bytes());
+        expected.push(b'a');
+        expected.extend(1u64.to_le_bytes()); // one k-mer
+        expected.extend(0x0102_0304_0506_0708u64.to_le_bytes());
+        expected.push(0); // no hashing
+        expected.push(1); // canonical
+        expected.extend(0u64.to_le_bytes()); // no positions
+        expected.extend(1u64.to_le_bytes()); // one sampling rate
+        expected.extend(1u64.to_le_bytes());
+        expected.push(b'a');
+        expected.extend(0.5f64.to_le_bytes());
+        expected.extend(0u32.to_le_bytes()); // lexicographic canonical policy
+        expected.push(0); // no metadata
+        expected.extend(0u64.to_le_bytes()); // no reference lengths
+        expected.push(0); // no name normalization
+        assert_eq!(bytes, expected);
+        // Files written with bincode 1's defaults before the options were pinned are identical
+        assert_eq!(bincode::serialize(&kmer_db).unwrap(), bytes);
+
+        let loaded: KmerDbV2 = db_bincode_options().deserialize_from(bytes.as_slice()).unwrap();
+        assert_eq!(loaded.k, 4);
+        assert_eq!(loaded.references, kmer_db.references);
+        assert_eq!(loaded.sampling_rates, kmer_db.sampling_rates);
+        assert!(loaded.canonical);
+        assert!(loaded.hashing.is_none());
+    }
+}
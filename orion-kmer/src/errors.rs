@@ -1,6 +1,9 @@
 use std::path::PathBuf; // Added for KmerSizeMismatchValidation
 use thiserror::Error;
 
+use crate::db_types::KmerHashing;
+use crate::kmer::CanonicalPolicy;
+
 #[derive(Error, Debug)]
 pub enum OrionKmerError {
     #[error("Invalid K-mer size: {0}. Must be between 1 and 32.")]
@@ -32,9 +35,75 @@ pub enum OrionKmerError {
     )]
     KmerSizeMismatchBetweenDatabases(u8, u8, PathBuf), // Specific for classify
 
+    #[error("K-mer databases use incompatible k-mer hashing: {0:?} vs {1:?} (database: {2:?})")]
+    HashingMismatch(Option<KmerHashing>, Option<KmerHashing>, PathBuf),
+
+    #[error("K-mer databases use incompatible canonical policies: {0:?} vs {1:?} (database: {2:?})")]
+    CanonicalPolicyMismatch(CanonicalPolicy, CanonicalPolicy, PathBuf),
+
+    #[error("Database {0:?} stores non-canonical k-mers, but k-mers are looked up in canonical form")]
+    NonCanonicalDatabase(PathBuf),
+
+    #[error("Databases {0:?} and {1:?} disagree on whether k-mers are stored in canonical form")]
+    CanonicalMismatch(PathBuf, PathBuf),
+
+    #[error("Reference '{0}' already exists in the database (use --on-conflict skip or replace)")]
+    DuplicateReference(String),
+
+    #[error("Reference '{0}' was not found in any of the databases")]
+    ReferenceNotFound(String),
+
     #[error("Generic error: {0}")]
     Generic(String),
 
     #[error("An unknown error occurred")]
     Unknown,
 }
+
+// Process exit codes, documented in the README. Argument errors reported by clap also exit with 2.
+pub const EXIT_GENERIC: i32 = 1;
+pub const EXIT_INVALID_KMER_SIZE: i32 = 2;
+pub const EXIT_FILE_NOT_FOUND: i32 = 3;
+pub const EXIT_KMER_SIZE_MISMATCH: i32 = 4;
+pub const EXIT_PARSE_ERROR: i32 = 5;
+pub const EXIT_IO_ERROR: i32 = 6;
+pub const EXIT_SERIALIZATION_ERROR: i32 = 7;
+pub const EXIT_INCOMPATIBLE_DATABASES: i32 = 8;
+pub const EXIT_REFERENCE_ERROR: i32 = 9;
+// 128 + SIGINT, as shells report a process killed by Ctrl-C
+pub const EXIT_INTERRUPTED: i32 = 130;
+
+/// The exit code for an I/O error: missing files get their own code.
+pub fn io_exit_code(e: &std::io::Error) -> i32 {
+    if e.kind() == std::io::ErrorKind::NotFound {
+        EXIT_FILE_NOT_FOUND
+    } else {
+        EXIT_IO_ERROR
+    }
+}
+
+impl OrionKmerError {
+    /// The process exit code for this error, so scripts can tell failures apart.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            OrionKmerError::InvalidKmerSize(_) => EXIT_INVALID_KMER_SIZE,
+            OrionKmerError::FileNotFound(_) => EXIT_FILE_NOT_FOUND,
+            OrionKmerError::KmerSizeMismatch(..)
+            | OrionKmerError::KmerSizeMismatchValidation(..)
+            | OrionKmerError::KmerSizeMismatchBetweenDatabases(..) => EXIT_KMER_SIZE_MISMATCH,
+            OrionKmerError::FileParsingError(_) => EXIT_PARSE_ERROR,
+            OrionKmerError::IoError(e) => io_exit_code(e),
+            OrionKmerError::SerializationError(_) | OrionKmerError::DeserializationError(_) => {
+                EXIT_SERIALIZATION_ERROR
+            }
+            OrionKmerError::HashingMismatch(..)
+            | OrionKmerError::CanonicalPolicyMismatch(..)
+            | OrionKmerError::NonCanonicalDatabase(_)
+            | OrionKmerError::CanonicalMismatch(..) => EXIT_INCOMPATIBLE_DATABASES,
+            OrionKmerError::DuplicateReference(_) | OrionKmerError::ReferenceNotFound(_) => {
+                EXIT_REFERENCE_ERROR
+            }
+            OrionKmerError::Generic(_) | OrionKmerError::Unknown => EXIT_GENERIC,
+        }
+    }
+}
@@ -0,0 +1,90 @@
+//! Ctrl-C (SIGINT) handling, so an interrupted run never leaves a truncated database behind.
+//!
+//! Database files register themselves with `PartialOutput` while they are written. On SIGINT,
+//! every registered file is deleted and the process exits with `EXIT_INTERRUPTED`. A `build
+//! --checkpoint` is written to a temporary file and renamed into place, so the temporary file is
+//! deleted and the last complete checkpoint is kept for `--resume`.
+//!
+//! The signal is blocked in every thread and received by a dedicated thread with `sigwait`, so
+//! the cleanup runs as ordinary code rather than inside a signal handler.
+
+use log::{debug, error, warn};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{Mutex, PoisonError},
+};
+
+use crate::errors::EXIT_INTERRUPTED;
+
+// Output files being written, deleted when the run is interrupted.
+static PARTIAL_OUTPUTS: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// Marks a file as incomplete while it is written: if the run is interrupted before the guard
+/// is dropped, the file is deleted.
+pub struct PartialOutput {
+    path: PathBuf,
+}
+
+impl PartialOutput {
+    pub fn new(path: &Path) -> Self {
+        let path = path.to_path_buf();
+        PARTIAL_OUTPUTS.lock().unwrap_or_else(PoisonError::into_inner).push(path.clone());
+        PartialOutput { path }
+    }
+}
+
+impl Drop for PartialOutput {
+    fn drop(&mut self) {
+        let mut partial_outputs = PARTIAL_OUTPUTS.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(index) = partial_outputs.iter().rposition(|path| *path == self.path) {
+            partial_outputs.remove(index);
+        }
+    }
+}
+
+// Deletes the files being written and exits. The lock is kept until exit, so no write can
+// complete (and unregister its file) in between.
+fn handle_interrupt() -> ! {
+    let partial_outputs = PARTIAL_OUTPUTS.lock().unwrap_or_else(PoisonError::into_inner);
+    for path in partial_outputs.iter() {
+        // Only regular files are removed, never e.g. /dev/null
+        if !fs::metadata(path).is_ok_and(|metadata| metadata.is_file()) {
+            continue;
+        }
+        match fs::remove_file(path) {
+            Ok(()) => warn!("Interrupted: removed incomplete output {:?}", path),
+            Err(e) => warn!("Interrupted: failed to remove incomplete output {:?}: {}", path, e),
+        }
+    }
+    error!("Interrupted");
+    std::process::exit(EXIT_INTERRUPTED);
+}
+
+/// Installs the SIGINT handling. Must be called before any other thread is started, so that
+/// every thread inherits the blocked signal.
+#[cfg(unix)]
+pub fn install_interrupt_handler() {
+    // SAFETY: the signal set is initialized by sigemptyset before use.
+    let signals = unsafe {
+        let mut signals: libc::sigset_t = std::mem::zeroed();
+        libc::sigemptyset(&mut signals);
+        libc::sigaddset(&mut signals, libc::SIGINT);
+        if libc::pthread_sigmask(libc::SIG_BLOCK, &signals, std::ptr::null_mut()) != 0 {
+            debug!("Failed to block SIGINT; interrupted runs may leave incomplete output");
+            return;
+        }
+        signals
+    };
+    std::thread::spawn(move || loop {
+        let mut signal = 0;
+        // SAFETY: `signals` is a valid signal set and `signal` a valid output location.
+        if unsafe { libc::sigwait(&signals, &mut signal) } == 0 && signal == libc::SIGINT {
+            handle_interrupt();
+        }
+    });
+}
+
+/// Installs the SIGINT handling; a no-op on platforms without POSIX signals.
+#[cfg(not(unix))]
+pub fn install_interrupt_handler() {}
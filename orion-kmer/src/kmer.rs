@@ -1,12 +1,23 @@
 // K-mer processing logic
 
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+
 const BITS_PER_BASE: u8 = 2;
 
+// Set by the global `--rna`: U/u then encode like T/t.
+static RNA_INPUT: AtomicBool = AtomicBool::new(false);
+
+/// Makes the k-mer encoders read U/u as T, for RNA sequences.
+pub fn init_rna_input() {
+    RNA_INPUT.store(true, Ordering::Relaxed);
+}
+
 /// Encodes a single DNA base into its 2-bit representation.
 /// A -> 00 (0)
 /// C -> 01 (1)
 /// G -> 10 (2)
-/// T -> 11 (3)
+/// T -> 11 (3), and U with `--rna`
 /// Returns None if the base is not A, C, G, or T.
 #[inline]
 fn dna_base_to_u64(base: u8) -> Option<u64> {
@@ -15,6 +26,32 @@ fn dna_base_to_u64(base: u8) -> Option<u64> {
         b'C' | b'c' => Some(0b01),
         b'G' | b'g' => Some(0b10),
         b'T' | b't' => Some(0b11),
+        // Only reached for non-ACGT bases, so plain DNA pays nothing for the check
+        b'U' | b'u' if RNA_INPUT.load(Ordering::Relaxed) => Some(0b11),
+        _ => None,
+    }
+}
+
+/// Returns the concrete 2-bit encoded bases an IUPAC nucleotide code stands for.
+/// Plain A/C/G/T map to a single base. Returns None for characters that are not IUPAC codes.
+#[inline]
+fn iupac_base_options(base: u8) -> Option<&'static [u64]> {
+    match base.to_ascii_uppercase() {
+        b'A' => Some(&[0b00]),
+        b'C' => Some(&[0b01]),
+        b'G' => Some(&[0b10]),
+        b'T' | b'U' => Some(&[0b11]),
+        b'R' => Some(&[0b00, 0b10]),
+        b'Y' => Some(&[0b01, 0b11]),
+        b'S' => Some(&[0b01, 0b10]),
+        b'W' => Some(&[0b00, 0b11]),
+        b'K' => Some(&[0b10, 0b11]),
+        b'M' => Some(&[0b00, 0b01]),
+        b'B' => Some(&[0b01, 0b10, 0b11]),
+        b'D' => Some(&[0b00, 0b10, 0b11]),
+        b'H' => Some(&[0b00, 0b01, 0b11]),
+        b'V' => Some(&[0b00, 0b01, 0b10]),
+        b'N' => Some(&[0b00, 0b01, 0b10, 0b11]),
         _ => None,
     }
 }
@@ -56,6 +93,64 @@ pub fn seq_to_u64(seq: &[u8], k: u8) -> Option<u64> {
     Some(kmer_val)
 }
 
+/// Iterates over every k-length window of `seq`, yielding the window's start and its encoding,
+/// or `None` when the window contains a non-ACGT character. Equivalent to calling `seq_to_u64`
+/// on each window, but each step shifts one base into the previous encoding in O(1) instead of
+/// re-encoding all k bases.
+pub fn rolling_kmers(seq: &[u8], k: u8) -> RollingKmers<'_> {
+    if k == 0 || k > 32 {
+        panic!("Invalid k-mer length for rolling encoding: {}", k);
+    }
+    RollingKmers {
+        seq,
+        k: k as usize,
+        mask: kmer_mask(k),
+        next_base: 0,
+        kmer_val: 0,
+        valid_bases: 0,
+    }
+}
+
+/// Iterator returned by `rolling_kmers`.
+pub struct RollingKmers<'a> {
+    seq: &'a [u8],
+    k: usize,
+    mask: u64,
+    next_base: usize,
+    kmer_val: u64,
+    // Length of the run of valid bases ending at the last base read
+    valid_bases: usize,
+}
+
+impl Iterator for RollingKmers<'_> {
+    type Item = (usize, Option<u64>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.next_base < self.seq.len() {
+            match dna_base_to_u64(self.seq[self.next_base]) {
+                Some(base_val) => {
+                    self.kmer_val = ((self.kmer_val << BITS_PER_BASE) | base_val) & self.mask;
+                    self.valid_bases += 1;
+                }
+                // An invalid base resets the run; the window is rebuilt from the bases after it
+                None => self.valid_bases = 0,
+            }
+            self.next_base += 1;
+            if self.next_base >= self.k {
+                let start = self.next_base - self.k;
+                return Some((start, (self.valid_bases >= self.k).then_some(self.kmer_val)));
+            }
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining_windows =
+            (self.seq.len() + 1).saturating_sub(self.k.max(self.next_base + 1));
+        (remaining_windows, Some(remaining_windows))
+    }
+}
+
 /// Decodes a u64 k-mer representation back to a DNA sequence (Vec<u8>).
 /// `k` specifies the length of the k-mer.
 pub fn u64_to_seq(kmer_val: u64, k: u8) -> Vec<u8> {
@@ -76,32 +171,358 @@ pub fn u64_to_seq(kmer_val: u64, k: u8) -> Vec<u8> {
 
 /// Computes the reverse complement of an encoded k-mer.
 /// `k` is the length of the k-mer.
+///
+/// Works on the whole word at once: complement every base with a single NOT, reverse the
+/// order of the 2-bit bases (swap adjacent bases, then base pairs, then bytes), and shift
+/// the k reversed bases back down to the low bits.
 pub fn reverse_complement_u64(kmer_val: u64, k: u8) -> u64 {
     if k == 0 || k > 32 {
         panic!("Invalid k-mer length for reverse complement: {}", k);
     }
-    let mut rc_val: u64 = 0;
-    let mask = 0b11; // To get 2 bits
-    for i in 0..k {
-        // Get the i-th base from the original k-mer (from LSB side for easier processing here)
-        let base_val = (kmer_val >> (BITS_PER_BASE * i)) & mask;
-        // Complement: A(00) <-> T(11), C(01) <-> G(10). This is equivalent to XOR with 0b11 (3).
-        let complemented_base = base_val ^ 0b11;
-        // Place the complemented base in the reverse position
-        rc_val |= complemented_base << (BITS_PER_BASE * (k - 1 - i));
-    }
-    rc_val
+    // Complement: A(00) <-> T(11), C(01) <-> G(10). This is equivalent to XOR with 0b11 (3).
+    let mut rc_val = !kmer_val;
+    rc_val = ((rc_val >> 2) & 0x3333_3333_3333_3333) | ((rc_val & 0x3333_3333_3333_3333) << 2);
+    rc_val = ((rc_val >> 4) & 0x0F0F_0F0F_0F0F_0F0F) | ((rc_val & 0x0F0F_0F0F_0F0F_0F0F) << 4);
+    rc_val = rc_val.swap_bytes();
+    // Bits above the k-mer end up below the shift and are discarded
+    rc_val >> (64 - BITS_PER_BASE as u32 * k as u32)
+}
+
+/// The strand of a k-mer that its canonical form was taken from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strand {
+    /// The k-mer as read
+    Forward,
+    /// Its reverse complement
+    Reverse,
 }
 
 /// Returns the canonical representation of a k-mer.
 /// The canonical k-mer is the lexicographically smaller of the k-mer and its reverse complement.
+/// For a palindrome (see `is_palindrome`) both are the same encoding, so it is its own canonical form.
 /// `k` is the length of the k-mer.
 pub fn canonical_u64(kmer_val: u64, k: u8) -> u64 {
+    canonical_with_orientation(kmer_val, k).0
+}
+
+/// Returns the canonical representation of a k-mer, like `canonical_u64`, and the strand it
+/// was taken from. A palindrome is its own canonical form and is reported as `Strand::Forward`.
+#[inline]
+pub fn canonical_with_orientation(kmer_val: u64, k: u8) -> (u64, Strand) {
     let rc_kmer_val = reverse_complement_u64(kmer_val, k);
-    if kmer_val < rc_kmer_val {
-        kmer_val
+    if kmer_val <= rc_kmer_val {
+        (kmer_val, Strand::Forward)
+    } else {
+        (rc_kmer_val, Strand::Reverse)
+    }
+}
+
+/// Returns true if a k-mer equals its own reverse complement (e.g. `GTAC`), so it reads the
+/// same on both strands. Only even k can have palindromes: at odd k the middle base would have
+/// to be its own complement.
+pub fn is_palindrome(kmer_val: u64, k: u8) -> bool {
+    reverse_complement_u64(kmer_val, k) == kmer_val
+}
+
+/// How the strand of a k-mer that is stored in canonical form is chosen.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CanonicalPolicy {
+    /// The lexicographically smaller of the k-mer and its reverse complement (`canonical_u64`).
+    #[default]
+    Lexicographic,
+    /// The strand whose minimizer (its smallest `m`-mer encoding, see `minimizer_u64`) is
+    /// smaller, so k-mers sharing a minimizer are stored on the same strand. Ties, e.g. when
+    /// both strands hold the same smallest m-mer, fall back to the lexicographic choice.
+    Minimizer { m: u8 },
+}
+
+/// Returns the smallest encoding among the `m`-length subwords of a k-mer, i.e. its
+/// lexicographic minimizer. Panics unless `1 <= m <= k <= 32`.
+pub fn minimizer_u64(kmer_val: u64, k: u8, m: u8) -> u64 {
+    subkmers(kmer_val, k, m)
+        .min()
+        .expect("a k-mer has at least one subword")
+}
+
+/// Returns the canonical representation of a k-mer under `policy`. Like `canonical_u64`, a
+/// k-mer and its reverse complement always get the same representation.
+pub fn canonical_u64_with_policy(kmer_val: u64, k: u8, policy: CanonicalPolicy) -> u64 {
+    match policy {
+        CanonicalPolicy::Lexicographic => canonical_u64(kmer_val, k),
+        CanonicalPolicy::Minimizer { m } => {
+            let rc_kmer_val = reverse_complement_u64(kmer_val, k);
+            let forward_minimizer = minimizer_u64(kmer_val, k, m);
+            let reverse_minimizer = minimizer_u64(rc_kmer_val, k, m);
+            match forward_minimizer.cmp(&reverse_minimizer) {
+                std::cmp::Ordering::Less => kmer_val,
+                std::cmp::Ordering::Greater => rc_kmer_val,
+                std::cmp::Ordering::Equal => kmer_val.min(rc_kmer_val),
+            }
+        }
+    }
+}
+
+// Complement of a nucleotide or IUPAC ambiguity code, keeping its case. U complements to A;
+// characters that are neither (gaps, `*`, ...) are returned unchanged.
+#[inline]
+fn complement_base(base: u8) -> u8 {
+    let complement = match base.to_ascii_uppercase() {
+        b'A' => b'T',
+        b'T' => b'A',
+        b'U' => b'A',
+        b'C' => b'G',
+        b'G' => b'C',
+        b'R' => b'Y',
+        b'Y' => b'R',
+        b'K' => b'M',
+        b'M' => b'K',
+        b'B' => b'V',
+        b'V' => b'B',
+        b'D' => b'H',
+        b'H' => b'D',
+        // S, W and N are their own complements
+        _ => return base,
+    };
+    if base.is_ascii_lowercase() {
+        complement.to_ascii_lowercase()
+    } else {
+        complement
+    }
+}
+
+/// Returns the reverse complement of a whole sequence, as written (case kept). IUPAC ambiguity
+/// codes map to their complementary codes (e.g. R to Y, N to N) and U to A; other characters
+/// are kept as they are, only reversed.
+pub fn reverse_complement_seq(seq: &[u8]) -> Vec<u8> {
+    seq.iter().rev().map(|&base| complement_base(base)).collect()
+}
+
+// Mask selecting the low 2k bits used by a k-mer.
+#[inline]
+fn kmer_mask(k: u8) -> u64 {
+    if k == 32 {
+        u64::MAX
+    } else {
+        (1u64 << (BITS_PER_BASE as u32 * k as u32)) - 1
+    }
+}
+
+/// Returns the four k-mers that follow `kmer_val` in a de Bruijn graph: its (k-1)-suffix
+/// extended by A, C, G and T, in that order. `k` is the length of the k-mer.
+pub fn successors(kmer_val: u64, k: u8) -> [u64; 4] {
+    if k == 0 || k > 32 {
+        panic!("Invalid k-mer length for successors: {}", k);
+    }
+    let suffix = (kmer_val << BITS_PER_BASE) & kmer_mask(k);
+    [suffix, suffix | 0b01, suffix | 0b10, suffix | 0b11]
+}
+
+/// Returns the four k-mers that precede `kmer_val` in a de Bruijn graph: its (k-1)-prefix
+/// preceded by A, C, G and T, in that order. `k` is the length of the k-mer.
+pub fn predecessors(kmer_val: u64, k: u8) -> [u64; 4] {
+    if k == 0 || k > 32 {
+        panic!("Invalid k-mer length for predecessors: {}", k);
+    }
+    let prefix = (kmer_val & kmer_mask(k)) >> BITS_PER_BASE;
+    let first_base_shift = BITS_PER_BASE as u32 * (k as u32 - 1);
+    [0b00, 0b01, 0b10, 0b11].map(|base: u64| (base << first_base_shift) | prefix)
+}
+
+/// Iterates over the 3k k-mers that differ from `kmer_val` by a single base substitution,
+/// position by position from the last base. `k` is the length of the k-mer.
+///
+/// XOR-ing a base's two bits with 1, 2 or 3 yields each of the other three bases.
+pub fn hamming1_neighbors(kmer_val: u64, k: u8) -> impl Iterator<Item = u64> {
+    if k == 0 || k > 32 {
+        panic!("Invalid k-mer length for Hamming neighbors: {}", k);
+    }
+    (0..k as u32).flat_map(move |pos| {
+        let shift = BITS_PER_BASE as u32 * pos;
+        (1..=3u64).map(move |flip| kmer_val ^ (flip << shift))
+    })
+}
+
+/// Iterates over the `k - sub_k + 1` subwords of length `sub_k` of a k-mer, from its first
+/// base onwards. Panics unless `1 <= sub_k <= k <= 32`.
+pub fn subkmers(kmer_val: u64, k: u8, sub_k: u8) -> impl Iterator<Item = u64> {
+    if k == 0 || k > 32 || sub_k == 0 || sub_k > k {
+        panic!("Invalid k-mer lengths for subwords: k={}, sub_k={}", k, sub_k);
+    }
+    let mask = kmer_mask(sub_k);
+    (0..=(k - sub_k) as u32)
+        .rev()
+        .map(move |offset| (kmer_val >> (BITS_PER_BASE as u32 * offset)) & mask)
+}
+
+// Final avalanche mix of MurmurHash3.
+#[inline]
+fn fmix64(mut h: u64) -> u64 {
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xff51afd7ed558ccd);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xc4ceb9fe1a85ec53);
+    h ^= h >> 33;
+    h
+}
+
+/// Computes MurmurHash3 x64_128 of `data`, returning the two 64-bit halves (h1, h2).
+/// Matches the reference implementation for seeds that fit in 32 bits.
+pub fn murmur3_x64_128(data: &[u8], seed: u64) -> (u64, u64) {
+    const C1: u64 = 0x87c37b91114253d5;
+    const C2: u64 = 0x4cf5ad432745937f;
+
+    let mut h1 = seed;
+    let mut h2 = seed;
+
+    let blocks = data.chunks_exact(16);
+    let tail = blocks.remainder();
+    for block in blocks {
+        let k1 = u64::from_le_bytes(block[0..8].try_into().unwrap());
+        let k2 = u64::from_le_bytes(block[8..16].try_into().unwrap());
+
+        h1 ^= k1.wrapping_mul(C1).rotate_left(31).wrapping_mul(C2);
+        h1 = h1
+            .rotate_left(27)
+            .wrapping_add(h2)
+            .wrapping_mul(5)
+            .wrapping_add(0x52dce729);
+
+        h2 ^= k2.wrapping_mul(C2).rotate_left(33).wrapping_mul(C1);
+        h2 = h2
+            .rotate_left(31)
+            .wrapping_add(h1)
+            .wrapping_mul(5)
+            .wrapping_add(0x38495ab5);
+    }
+
+    let mut k1: u64 = 0;
+    let mut k2: u64 = 0;
+    for (i, &byte) in tail.iter().enumerate() {
+        if i < 8 {
+            k1 |= (byte as u64) << (8 * i);
+        } else {
+            k2 |= (byte as u64) << (8 * (i - 8));
+        }
+    }
+    if tail.len() > 8 {
+        h2 ^= k2.wrapping_mul(C2).rotate_left(33).wrapping_mul(C1);
+    }
+    if !tail.is_empty() {
+        h1 ^= k1.wrapping_mul(C1).rotate_left(31).wrapping_mul(C2);
+    }
+
+    h1 ^= data.len() as u64;
+    h2 ^= data.len() as u64;
+    h1 = h1.wrapping_add(h2);
+    h2 = h2.wrapping_add(h1);
+    h1 = fmix64(h1);
+    h2 = fmix64(h2);
+    h1 = h1.wrapping_add(h2);
+    h2 = h2.wrapping_add(h1);
+    (h1, h2)
+}
+
+/// Hashes an encoded k-mer to 64 bits with MurmurHash3 x64_128 (first half),
+/// computed over the k-mer's uppercase ACGT string as sourmash and Mash do.
+/// Pass the canonical encoding to obtain a strand-independent hash.
+pub fn hash_kmer(kmer: u64, k: u8, seed: u64) -> u64 {
+    murmur3_x64_128(&u64_to_seq(kmer, k), seed).0
+}
+
+/// Counts the positions in `seq` holding an IUPAC ambiguity code (anything other than A/C/G/T).
+/// Characters that are not IUPAC codes at all are counted as well.
+pub fn ambiguous_base_count(seq: &[u8]) -> usize {
+    seq.iter()
+        .filter(|&&base| dna_base_to_u64(base).is_none())
+        .count()
+}
+
+const COMPLEXITY_WORD_SIZE: u8 = 3;
+
+/// Normalized Shannon entropy of the trinucleotides in `seq`, between 0.0 and 1.0.
+/// The entropy is divided by its maximum for the number of trinucleotides, log2(min(64, n)),
+/// so homopolymers score 0.0 and short tandem repeats score low regardless of read length.
+/// Windows with non-ACGT characters are ignored; fewer than two valid windows score 0.0.
+pub fn sequence_complexity(seq: &[u8]) -> f64 {
+    let mut word_counts = [0usize; 64];
+    let mut total = 0usize;
+    for (_, word) in rolling_kmers(seq, COMPLEXITY_WORD_SIZE) {
+        if let Some(word) = word {
+            word_counts[word as usize] += 1;
+            total += 1;
+        }
+    }
+    if total < 2 {
+        return 0.0;
+    }
+
+    let entropy: f64 = word_counts
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let p = count as f64 / total as f64;
+            -p * p.log2()
+        })
+        .sum();
+    (entropy / (total.min(64) as f64).log2()).clamp(0.0, 1.0)
+}
+
+/// Iterator over every concrete ACGT realization of a window that may contain IUPAC codes.
+/// Created by `expand_iupac_window`.
+pub struct IupacExpansion {
+    options: Vec<&'static [u64]>,
+    indices: Vec<usize>,
+    k: u8,
+    done: bool,
+}
+
+impl Iterator for IupacExpansion {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        if self.done {
+            return None;
+        }
+
+        let mut kmer_val: u64 = 0;
+        for (i, (opts, &idx)) in self.options.iter().zip(&self.indices).enumerate() {
+            kmer_val |= opts[idx] << (BITS_PER_BASE * (self.k - 1 - i as u8));
+        }
+
+        // Advance the odometer, rightmost position first
+        self.done = true;
+        for pos in (0..self.indices.len()).rev() {
+            self.indices[pos] += 1;
+            if self.indices[pos] < self.options[pos].len() {
+                self.done = false;
+                break;
+            }
+            self.indices[pos] = 0;
+        }
+
+        Some(kmer_val)
+    }
+}
+
+/// Expands a window containing IUPAC ambiguity codes into all concrete ACGT k-mers it may represent.
+/// Yields the forward (non-canonical) encodings; callers canonicalize as needed.
+/// Yields nothing if k is invalid, the window length does not match k, or a character is not an IUPAC code.
+/// The number of realizations grows exponentially with ambiguous positions, so callers should cap
+/// them first (see `ambiguous_base_count`).
+pub fn expand_iupac_window(seq: &[u8], k: u8) -> impl Iterator<Item = u64> {
+    let options: Option<Vec<&'static [u64]>> = if k == 0 || k > 32 || seq.len() != k as usize {
+        None
     } else {
-        rc_kmer_val
+        seq.iter().map(|&base| iupac_base_options(base)).collect()
+    };
+    let done = options.is_none();
+    let options = options.unwrap_or_default();
+
+    IupacExpansion {
+        indices: vec![0; options.len()],
+        options,
+        k,
+        done,
     }
 }
 
@@ -299,6 +720,90 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_reverse_complement_u64_matches_string_reverse_complement() {
+        let patterns = [
+            0u64,
+            u64::MAX,
+            0x0123_4567_89AB_CDEF,
+            0xDEAD_BEEF_F00D_CAFE,
+            0x5555_5555_5555_5555,
+        ];
+        for k in 1..=32u8 {
+            let k_mask = if k == 32 {
+                u64::MAX
+            } else {
+                (1u64 << (2 * k)) - 1
+            };
+            for pattern in patterns {
+                let kmer_val = pattern & k_mask;
+                let expected_rc: Vec<u8> = u64_to_seq(kmer_val, k)
+                    .iter()
+                    .rev()
+                    .map(|base| match base {
+                        b'A' => b'T',
+                        b'C' => b'G',
+                        b'G' => b'C',
+                        _ => b'A',
+                    })
+                    .collect();
+                assert_eq!(
+                    reverse_complement_u64(kmer_val, k),
+                    seq_to_u64(&expected_rc, k).unwrap(),
+                    "k={} kmer={:#x}",
+                    k,
+                    kmer_val
+                );
+                // Bits above the k-mer are ignored
+                assert_eq!(
+                    reverse_complement_u64(pattern, k),
+                    reverse_complement_u64(kmer_val, k)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_canonical_u64_with_policy_picks_strand_by_minimizer() {
+        let k = 5;
+        // TAGGG has the smaller minimizer (AG) than its reverse complement CCCTA (CC), which is
+        // the lexicographically smaller strand
+        let kmer = seq_to_u64(b"TAGGG", k).unwrap();
+        let rc = reverse_complement_u64(kmer, k);
+        assert_eq!(minimizer_u64(kmer, k, 2), seq_to_u64(b"AG", 2).unwrap());
+        assert_eq!(minimizer_u64(rc, k, 2), seq_to_u64(b"CC", 2).unwrap());
+        assert_eq!(canonical_u64(kmer, k), rc);
+        assert_eq!(canonical_u64_with_policy(kmer, k, CanonicalPolicy::Minimizer { m: 2 }), kmer);
+        // GATCC: GA, AT, TC, CC vs its reverse complement GGATC: GG, GA, AT, TC. Both have AT,
+        // so the tie falls back to the lexicographic choice
+        let kmer = seq_to_u64(b"GATCC", k).unwrap();
+        assert_eq!(
+            canonical_u64_with_policy(kmer, k, CanonicalPolicy::Minimizer { m: 2 }),
+            canonical_u64(kmer, k)
+        );
+        assert_eq!(canonical_u64_with_policy(kmer, k, CanonicalPolicy::Lexicographic), canonical_u64(kmer, k));
+
+        // Both strands of every 6-mer get the same representation, one of the two strands
+        let policy = CanonicalPolicy::Minimizer { m: 3 };
+        for kmer in 0..(1u64 << 12) {
+            let rc = reverse_complement_u64(kmer, 6);
+            let canonical = canonical_u64_with_policy(kmer, 6, policy);
+            assert_eq!(canonical_u64_with_policy(rc, 6, policy), canonical);
+            assert!(canonical == kmer || canonical == rc);
+        }
+    }
+
+    #[test]
+    fn test_reverse_complement_seq_handles_case_and_ambiguity_codes() {
+        assert_eq!(reverse_complement_seq(b"ACGTN"), b"NACGT");
+        assert_eq!(reverse_complement_seq(b"aacgRYkmBDHVswU-"), b"-AwsBDHVkmRYcgtt");
+        assert_eq!(reverse_complement_seq(b""), b"");
+        // Agrees with the encoded reverse complement on plain ACGT
+        let seq = b"GATTACAGGC";
+        let encoded = reverse_complement_u64(seq_to_u64(seq, 10).unwrap(), 10);
+        assert_eq!(reverse_complement_seq(seq), u64_to_seq(encoded, 10));
+    }
+
     #[test]
     #[should_panic]
     fn test_reverse_complement_invalid_k_zero() {
@@ -338,4 +843,259 @@ mod tests {
         let kmer_gaatc = seq_to_u64(b"GAATC", k_5).unwrap();
         assert_eq!(canonical_u64(kmer_gattc, k_5), kmer_gaatc);
     }
+
+    #[test]
+    fn test_canonical_with_orientation() {
+        let k = 3;
+        let kmer_acg = seq_to_u64(b"ACG", k).unwrap();
+        assert_eq!(canonical_with_orientation(kmer_acg, k), (kmer_acg, Strand::Forward));
+        let kmer_tgt = seq_to_u64(b"TGT", k).unwrap();
+        let kmer_aca = seq_to_u64(b"ACA", k).unwrap();
+        assert_eq!(canonical_with_orientation(kmer_tgt, k), (kmer_aca, Strand::Reverse));
+        // A palindrome comes from the forward strand
+        let kmer_gtac = seq_to_u64(b"GTAC", 4).unwrap();
+        assert_eq!(canonical_with_orientation(kmer_gtac, 4), (kmer_gtac, Strand::Forward));
+    }
+
+    #[test]
+    fn test_canonical_and_reverse_complement_properties_all_k() {
+        // Seeded splitmix64 so failures are reproducible
+        let mut state: u64 = 0x9E37_79B9_7F4A_7C15;
+        let mut next = || {
+            state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+            z ^ (z >> 31)
+        };
+
+        for k in 1..=32u8 {
+            let mask = kmer_mask(k);
+            // Edge encodings (all A, all T, alternating bits) plus random ones
+            let mut values = vec![0, mask, 0x5555_5555_5555_5555 & mask, 0xAAAA_AAAA_AAAA_AAAA & mask];
+            values.extend((0..500).map(|_| next() & mask));
+
+            for x in values {
+                let rc = reverse_complement_u64(x, k);
+                assert!(rc <= mask, "rc out of range: k={}, x={:#x}", k, x);
+                assert_eq!(reverse_complement_u64(rc, k), x, "rc not an involution: k={}, x={:#x}", k, x);
+
+                let canonical = canonical_u64(x, k);
+                assert_eq!(canonical, x.min(rc), "k={}, x={:#x}", k, x);
+                assert_eq!(canonical_u64(canonical, k), canonical, "canonical not idempotent: k={}, x={:#x}", k, x);
+                assert_eq!(canonical_u64(rc, k), canonical, "k={}, x={:#x}", k, x);
+
+                let seq = u64_to_seq(x, k);
+                assert_eq!(seq_to_u64(&seq, k), Some(x), "k={}, x={:#x}", k, x);
+            }
+        }
+    }
+
+    #[test]
+    fn test_rolling_kmers_matches_seq_to_u64() {
+        // Deterministic pseudo-random sequence with scattered invalid bases
+        let mut state: u64 = 0x9E37_79B9_7F4A_7C15;
+        let seq: Vec<u8> = (0..300)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                b"ACGTACGTACGTACGTacgtN"[(state % 21) as usize]
+            })
+            .collect();
+        for k in 1..=32u8 {
+            let expected: Vec<(usize, Option<u64>)> = seq
+                .windows(k as usize)
+                .enumerate()
+                .map(|(pos, window)| (pos, seq_to_u64(window, k)))
+                .collect();
+            let rolling: Vec<(usize, Option<u64>)> = rolling_kmers(&seq, k).collect();
+            assert_eq!(rolling, expected, "k = {}", k);
+            assert_eq!(rolling_kmers(&seq, k).size_hint().0, expected.len());
+        }
+        assert_eq!(rolling_kmers(b"ACG", 4).count(), 0);
+        assert_eq!(rolling_kmers(b"", 1).count(), 0);
+    }
+
+    #[test]
+    fn test_hamming1_neighbors() {
+        let k = 3;
+        let kmer = seq_to_u64(b"ACG", k).unwrap();
+        let mut neighbors: Vec<Vec<u8>> = hamming1_neighbors(kmer, k).map(|n| u64_to_seq(n, k)).collect();
+        neighbors.sort();
+        let mut expected: Vec<Vec<u8>> = [
+            "CCG", "GCG", "TCG", "AAG", "AGG", "ATG", "ACA", "ACC", "ACT",
+        ]
+        .iter()
+        .map(|s| s.as_bytes().to_vec())
+        .collect();
+        expected.sort();
+        assert_eq!(neighbors, expected);
+
+        // Every neighbor differs in exactly one base, including at k = 32
+        for k in [1u8, 31, 32] {
+            let kmer = 0x0123_4567_89AB_CDEF & kmer_mask(k);
+            let neighbors: std::collections::HashSet<u64> = hamming1_neighbors(kmer, k).collect();
+            assert_eq!(neighbors.len(), 3 * k as usize);
+            for neighbor in neighbors {
+                let differing_bases = u64_to_seq(kmer, k)
+                    .iter()
+                    .zip(u64_to_seq(neighbor, k))
+                    .filter(|(a, b)| **a != *b)
+                    .count();
+                assert_eq!(differing_bases, 1);
+            }
+        }
+    }
+
+    #[test]
+    fn test_successors_and_predecessors() {
+        let k = 4;
+        let kmer = seq_to_u64(b"ACGT", k).unwrap();
+        let expected_succ: Vec<u64> = ["CGTA", "CGTC", "CGTG", "CGTT"]
+            .iter()
+            .map(|s| seq_to_u64(s.as_bytes(), k).unwrap())
+            .collect();
+        assert_eq!(successors(kmer, k).to_vec(), expected_succ);
+        let expected_pred: Vec<u64> = ["AACG", "CACG", "GACG", "TACG"]
+            .iter()
+            .map(|s| seq_to_u64(s.as_bytes(), k).unwrap())
+            .collect();
+        assert_eq!(predecessors(kmer, k).to_vec(), expected_pred);
+
+        // k = 32 uses every bit of the word
+        let kmer_32 = seq_to_u64(&[b'T'; 32], 32).unwrap();
+        assert_eq!(successors(kmer_32, 32)[0], u64::MAX << 2);
+        assert_eq!(predecessors(kmer_32, 32)[0], u64::MAX >> 2);
+
+        // k = 1: every base neighbors every base
+        assert_eq!(successors(0b10, 1), [0, 1, 2, 3]);
+        assert_eq!(predecessors(0b10, 1), [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_expand_iupac_window() {
+        let k = 3;
+        // Plain ACGT windows expand to themselves
+        let plain: Vec<u64> = expand_iupac_window(b"ACG", k).collect();
+        assert_eq!(plain, vec![seq_to_u64(b"ACG", k).unwrap()]);
+
+        // R = A/G, Y = C/T
+        let mut expanded: Vec<u64> = expand_iupac_window(b"ARY", k).collect();
+        expanded.sort();
+        let mut expected: Vec<u64> = [b"AAC", b"AAT", b"AGC", b"AGT"]
+            .iter()
+            .map(|s| seq_to_u64(*s, k).unwrap())
+            .collect();
+        expected.sort();
+        assert_eq!(expanded, expected);
+
+        // N expands to all four bases, lowercase is accepted
+        assert_eq!(expand_iupac_window(b"ann", k).count(), 16);
+
+        // Invalid characters, wrong length or invalid k yield nothing
+        assert_eq!(expand_iupac_window(b"AX-", k).count(), 0);
+        assert_eq!(expand_iupac_window(b"AC", k).count(), 0);
+        assert_eq!(expand_iupac_window(b"", 0).count(), 0);
+    }
+
+    #[test]
+    fn test_ambiguous_base_count() {
+        assert_eq!(ambiguous_base_count(b"ACGT"), 0);
+        assert_eq!(ambiguous_base_count(b"ARYN"), 3);
+        assert_eq!(ambiguous_base_count(b"acgt"), 0);
+    }
+
+    #[test]
+    fn test_sequence_complexity() {
+        assert_eq!(sequence_complexity(b"AAAAAAAAAAAAAAAAAAAA"), 0.0);
+        assert_eq!(sequence_complexity(b"AC"), 0.0);
+        // Every trinucleotide distinct: maximal entropy for the word count
+        assert!((sequence_complexity(b"ACGTTGCA") - 1.0).abs() < 1e-12);
+        let dinucleotide_repeat = sequence_complexity(b"ACACACACACACACACACACACACACACAC");
+        let mixed = sequence_complexity(b"ACGTAGCTTAGGCATCGATCCGATGCAATGCC");
+        assert!(dinucleotide_repeat < 0.3, "{}", dinucleotide_repeat);
+        assert!(mixed > 0.8, "{}", mixed);
+        // Windows with N are skipped
+        assert_eq!(sequence_complexity(b"AAANAAA"), 0.0);
+    }
+
+    #[test]
+    fn test_murmur3_x64_128_reference_vectors() {
+        assert_eq!(murmur3_x64_128(b"", 0), (0, 0));
+        assert_eq!(
+            murmur3_x64_128(b"hello", 0),
+            (0xcbd8a7b341bd9b02, 0x5b1e906a48ae1d19)
+        );
+        // Longer than one 16-byte block, exercising both the block loop and the tail
+        assert_eq!(
+            murmur3_x64_128(b"The quick brown fox jumps over the lazy dog", 0),
+            (0xe34bbc7bbc071b6c, 0x7a433ca9c49a9347)
+        );
+    }
+
+    #[test]
+    fn test_hash_kmer_uses_sequence_string() {
+        let k = 21;
+        let seq = b"ACGTACGTACGTACGTACGTA";
+        let kmer_val = seq_to_u64(seq, k).unwrap();
+        assert_eq!(hash_kmer(kmer_val, k, 42), murmur3_x64_128(seq, 42).0);
+        assert_ne!(hash_kmer(kmer_val, k, 42), hash_kmer(kmer_val, k, 0));
+    }
+
+    #[test]
+    fn test_is_palindrome() {
+        for palindrome in ["AT", "GC", "ACGT", "GTAC", "AATT", "GAATTC", "ACGTACGT"] {
+            let kmer_val = seq_to_u64(palindrome.as_bytes(), palindrome.len() as u8).unwrap();
+            assert!(is_palindrome(kmer_val, palindrome.len() as u8), "{}", palindrome);
+        }
+        for non_palindrome in ["AA", "ATTA", "ACGA", "GATTC"] {
+            let kmer_val = seq_to_u64(non_palindrome.as_bytes(), non_palindrome.len() as u8).unwrap();
+            assert!(!is_palindrome(kmer_val, non_palindrome.len() as u8), "{}", non_palindrome);
+        }
+    }
+
+    #[test]
+    fn test_palindromes_exist_only_at_even_k() {
+        for k in 1..=8u8 {
+            let palindromes: Vec<u64> = (0..1u64 << (2 * k)).filter(|&kmer| is_palindrome(kmer, k)).collect();
+            if k % 2 == 1 {
+                assert!(palindromes.is_empty(), "k={}", k);
+            } else {
+                // The first k/2 bases determine the rest
+                assert_eq!(palindromes.len(), 1 << k, "k={}", k);
+            }
+            // A palindrome is its own canonical form, whichever strand it was read from
+            for &kmer in &palindromes {
+                assert_eq!(canonical_u64(kmer, k), kmer);
+                assert_eq!(canonical_u64(reverse_complement_u64(kmer, k), k), kmer);
+            }
+        }
+    }
+
+    #[test]
+    fn test_is_palindrome_at_k32() {
+        // A half followed by its reverse complement reads the same on both strands
+        let half = b"ACGGTCAATGCTTAGC";
+        let mut palindrome = half.to_vec();
+        palindrome.extend(u64_to_seq(reverse_complement_u64(seq_to_u64(half, 16).unwrap(), 16), 16));
+        let kmer_val = seq_to_u64(&palindrome, 32).unwrap();
+        assert!(is_palindrome(kmer_val, 32));
+        assert_eq!(canonical_u64(kmer_val, 32), kmer_val);
+        assert!(!is_palindrome(kmer_val ^ 1, 32));
+    }
+
+    #[test]
+    fn test_subkmers() {
+        let kmer_val = seq_to_u64(b"ACGTTG", 6).unwrap();
+        let subwords: Vec<Vec<u8>> = subkmers(kmer_val, 6, 4).map(|sub| u64_to_seq(sub, 4)).collect();
+        assert_eq!(subwords, vec![b"ACGT".to_vec(), b"CGTT".to_vec(), b"GTTG".to_vec()]);
+        assert_eq!(subkmers(kmer_val, 6, 6).collect::<Vec<u64>>(), vec![kmer_val]);
+
+        // Agrees with rolling over the sequence at the smaller k, including at k=32
+        let seq = b"ACGGTCAATGCTTAGCCGATTACAGGATCCAT";
+        let kmer_val = seq_to_u64(seq, 32).unwrap();
+        let expected: Vec<u64> = seq.windows(21).map(|window| seq_to_u64(window, 21).unwrap()).collect();
+        assert_eq!(subkmers(kmer_val, 32, 21).collect::<Vec<u64>>(), expected);
+    }
 }
@@ -0,0 +1,463 @@
+// On-disk sorted k-mer index that can be memory-mapped and searched in place.
+//
+// Layout (all integers little-endian):
+//   [0..8)    magic `ORKIDX01`
+//   [8..16)   length of the bincode-encoded `SortedIndexHeader`
+//   [16..)    the header, zero-padded to a multiple of 8 bytes
+//   data      sorted u64 k-mer keys: one run per reference, followed by the sorted union
+//
+// Run offsets in the header count u64 values from the start of the data section.
+
+use anyhow::{Context, Result};
+use bincode::Options;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use crate::{
+    db_types::{KmerDbV2, KmerHashing},
+    errors::OrionKmerError,
+    interrupt::PartialOutput,
+    kmer::CanonicalPolicy,
+    utils::db_bincode_options,
+};
+
+pub(crate) const SORTED_INDEX_MAGIC: &[u8; 8] = b"ORKIDX01";
+const VALUE_SIZE: u64 = std::mem::size_of::<u64>() as u64;
+
+// Location of one sorted run of k-mer keys within the data section.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+struct KmerRun {
+    offset: u64,
+    len: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct SortedIndexHeader {
+    k: u8,
+    canonical: bool,
+    hashing: Option<KmerHashing>,
+    references: Vec<(String, KmerRun)>,
+    unified: KmerRun,
+}
+
+// None when a (corrupt) header length puts the data section past u64::MAX.
+fn data_section_start(header_len: u64) -> Option<u64> {
+    16u64.checked_add(header_len)?.div_ceil(VALUE_SIZE).checked_mul(VALUE_SIZE)
+}
+
+/// Returns whether `path` starts with the sorted k-mer index magic bytes.
+pub fn is_sorted_kmer_index(path: &Path) -> Result<bool> {
+    let mut file =
+        File::open(path).with_context(|| format!("Failed to open k-mer database: {:?}", path))?;
+    let mut magic = [0u8; 8];
+    match file.read_exact(&mut magic) {
+        Ok(()) => Ok(&magic == SORTED_INDEX_MAGIC),
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e).with_context(|| format!("Failed to read k-mer database: {:?}", path)),
+    }
+}
+
+/// Writes `kmer_db` as an uncompressed sorted k-mer index at `path`, for memory-mapped lookups
+/// with `SortedKmerMmap`. Reference runs keep the database's reference order.
+pub fn write_sorted_kmer_index(kmer_db: &KmerDbV2, path: &Path) -> Result<()> {
+    write_index(kmer_db, true, path)
+}
+
+/// Writes only the sorted union of `kmer_db`'s k-mers as a sorted k-mer index with no
+/// references, for lookups that need to know whether a k-mer is in the database but not
+/// which references hold it (`query --disk-index`). About half the size of a full index.
+pub fn write_sorted_union_index(kmer_db: &KmerDbV2, path: &Path) -> Result<()> {
+    write_index(kmer_db, false, path)
+}
+
+fn write_index(kmer_db: &KmerDbV2, with_references: bool, path: &Path) -> Result<()> {
+    // The header has no room for the policy, and lookups in an index canonicalize lexicographically
+    if kmer_db.canonical_policy != CanonicalPolicy::Lexicographic {
+        return Err(OrionKmerError::Generic(format!(
+            "Sorted k-mer indexes only hold lexicographically canonical k-mers, but the database for {:?} uses the {:?} canonical policy",
+            path, kmer_db.canonical_policy
+        ))
+        .into());
+    }
+    if with_references && !kmer_db.positions.is_empty() {
+        warn!(
+            "The sorted index {:?} does not keep the k-mer positions of --per-record references",
+            path
+        );
+    }
+    if with_references && !kmer_db.sampling_rates.is_empty() {
+        warn!(
+            "The sorted index {:?} does not keep the sampling rates of --max-kmers-per-reference references",
+            path
+        );
+    }
+    if with_references && !kmer_db.reference_lengths.is_empty() {
+        warn!("The sorted index {:?} does not keep the sequence lengths of references", path);
+    }
+    if with_references && kmer_db.name_normalization.is_some() {
+        warn!("The sorted index {:?} does not keep the reference name normalization", path);
+    }
+    let mut references = Vec::with_capacity(kmer_db.references.len());
+    let mut offset = 0;
+    for (name, kmer_set) in kmer_db.references.iter().filter(|_| with_references) {
+        let len = kmer_set.len() as u64;
+        references.push((name.clone(), KmerRun { offset, len }));
+        offset += len;
+    }
+    let unified_kmers: Vec<u64> = {
+        let mut all: Vec<u64> = kmer_db.get_all_kmers_unified().into_iter().collect();
+        all.sort_unstable();
+        all
+    };
+    let header = SortedIndexHeader {
+        k: kmer_db.k,
+        canonical: kmer_db.canonical,
+        hashing: kmer_db.hashing,
+        references,
+        unified: KmerRun {
+            offset,
+            len: unified_kmers.len() as u64,
+        },
+    };
+    let header_bytes =
+        db_bincode_options().serialize(&header).context("Failed to serialize sorted k-mer index header")?;
+
+    let _partial_output = PartialOutput::new(path);
+    let file = File::create(path)
+        .with_context(|| format!("Failed to create sorted k-mer index: {:?}", path))?;
+    let mut writer = BufWriter::new(file);
+    writer.write_all(SORTED_INDEX_MAGIC)?;
+    writer.write_all(&(header_bytes.len() as u64).to_le_bytes())?;
+    writer.write_all(&header_bytes)?;
+    let data_start = data_section_start(header_bytes.len() as u64)
+        .context("Sorted k-mer index header is too large")?;
+    let padding = data_start - 16 - header_bytes.len() as u64;
+    writer.write_all(&vec![0u8; padding as usize])?;
+
+    let write_run = |writer: &mut BufWriter<File>, values: &[u64]| -> Result<()> {
+        for value in values {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        Ok(())
+    };
+    for kmer_set in kmer_db.references.values().filter(|_| with_references) {
+        let mut values: Vec<u64> = kmer_set.iter().copied().collect();
+        values.sort_unstable();
+        write_run(&mut writer, &values)
+            .with_context(|| format!("Failed to write sorted k-mer index: {:?}", path))?;
+    }
+    write_run(&mut writer, &unified_kmers)
+        .with_context(|| format!("Failed to write sorted k-mer index: {:?}", path))?;
+    writer
+        .flush()
+        .with_context(|| format!("Failed to flush sorted k-mer index: {:?}", path))?;
+    Ok(())
+}
+
+/// A borrowed, sorted run of little-endian k-mer keys inside a mapped index.
+#[derive(Clone, Copy)]
+pub struct SortedKmers<'a> {
+    values: &'a [u64],
+}
+
+impl<'a> SortedKmers<'a> {
+    /// Binary-searches the run for `key`.
+    pub fn contains(&self, key: u64) -> bool {
+        self.values
+            .binary_search_by(|value| u64::from_le(*value).cmp(&key))
+            .is_ok()
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Iterates over the keys in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = u64> + 'a {
+        self.values.iter().map(|value| u64::from_le(*value))
+    }
+}
+
+// Read-only private mapping of a whole file, unmapped on drop.
+#[cfg(unix)]
+struct Mapping {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+// The mapping is read-only and never handed out mutably, so it can be shared across threads.
+#[cfg(unix)]
+unsafe impl Send for Mapping {}
+#[cfg(unix)]
+unsafe impl Sync for Mapping {}
+
+#[cfg(unix)]
+impl Mapping {
+    fn new(file: &File, len: usize) -> Result<Self> {
+        use std::os::unix::io::AsRawFd;
+        // SAFETY: a fresh read-only mapping of an open file descriptor; the result is checked
+        // against MAP_FAILED before use.
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ,
+                libc::MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(std::io::Error::last_os_error()).context("mmap failed");
+        }
+        Ok(Mapping { ptr, len })
+    }
+
+    fn bytes(&self) -> &[u8] {
+        // SAFETY: ptr points to `len` readable bytes for as long as the mapping lives.
+        unsafe { std::slice::from_raw_parts(self.ptr as *const u8, self.len) }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        // SAFETY: ptr and len come from a successful mmap call and are unmapped exactly once.
+        unsafe {
+            libc::munmap(self.ptr, self.len);
+        }
+    }
+}
+
+/// A sorted k-mer index (see `write_sorted_kmer_index`) memory-mapped from disk. Lookups
+/// binary-search the mapped runs, so only the pages touched are read into memory.
+pub struct SortedKmerMmap {
+    pub k: u8,
+    pub canonical: bool,
+    pub hashing: Option<KmerHashing>,
+    references: Vec<(String, KmerRun)>,
+    unified: KmerRun,
+    data_start: usize,
+    #[cfg(unix)]
+    mapping: Mapping,
+}
+
+impl SortedKmerMmap {
+    /// Maps the sorted k-mer index at `path`, validating its header against the file size.
+    #[cfg(unix)]
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = File::open(path)
+            .with_context(|| format!("Failed to open sorted k-mer index: {:?}", path))?;
+        let file_len = file
+            .metadata()
+            .with_context(|| format!("Failed to stat sorted k-mer index: {:?}", path))?
+            .len();
+
+        let mut reader = BufReader::new(&file);
+        let mut prefix = [0u8; 16];
+        reader
+            .read_exact(&mut prefix)
+            .with_context(|| format!("Failed to read sorted k-mer index header: {:?}", path))?;
+        if &prefix[..8] != SORTED_INDEX_MAGIC {
+            return Err(OrionKmerError::Generic(format!(
+                "{:?} is not a sorted k-mer index (write one with `build --sorted-index`)",
+                path
+            ))
+            .into());
+        }
+        let header_len = u64::from_le_bytes(prefix[8..16].try_into().unwrap());
+        let header: SortedIndexHeader = db_bincode_options().deserialize_from(reader.take(header_len))
+            .with_context(|| format!("Failed to deserialize sorted k-mer index header: {:?}", path))?;
+
+        let truncated = |expected: &dyn std::fmt::Display| {
+            OrionKmerError::Generic(format!(
+                "Sorted k-mer index {:?} is truncated ({} bytes, expected at least {})",
+                path, file_len, expected
+            ))
+        };
+        // A run ending past u64::MAX cannot fit in any file, so it is reported as truncation
+        let run_end = |data_start: u64, run: &KmerRun| {
+            run.offset
+                .checked_add(run.len)?
+                .checked_mul(VALUE_SIZE)?
+                .checked_add(data_start)
+        };
+        let data_start = data_section_start(header_len).ok_or_else(|| truncated(&"2^64"))?;
+        let mut data_end = data_start;
+        for run in header.references.iter().map(|(_, run)| run).chain(std::iter::once(&header.unified)) {
+            data_end = data_end.max(run_end(data_start, run).ok_or_else(|| truncated(&"2^64"))?);
+        }
+        if data_end > file_len {
+            return Err(truncated(&data_end).into());
+        }
+        let mapping = Mapping::new(&file, file_len as usize)
+            .with_context(|| format!("Failed to memory-map sorted k-mer index: {:?}", path))?;
+
+        Ok(SortedKmerMmap {
+            k: header.k,
+            canonical: header.canonical,
+            hashing: header.hashing,
+            references: header.references,
+            unified: header.unified,
+            data_start: data_start as usize,
+            mapping,
+        })
+    }
+
+    #[cfg(not(unix))]
+    pub fn open(path: &Path) -> Result<Self> {
+        Err(OrionKmerError::Generic(format!(
+            "Memory-mapped k-mer indexes are only supported on Unix platforms: {:?}",
+            path
+        ))
+        .into())
+    }
+
+    fn run(&self, run: &KmerRun) -> SortedKmers<'_> {
+        #[cfg(unix)]
+        {
+            let start = self.data_start + (run.offset * VALUE_SIZE) as usize;
+            let bytes = &self.mapping.bytes()[start..start + (run.len * VALUE_SIZE) as usize];
+            // SAFETY: the mapping is page-aligned and data_start is a multiple of 8, so the
+            // bytes are aligned for u64; any bit pattern is a valid u64.
+            let values =
+                unsafe { std::slice::from_raw_parts(bytes.as_ptr() as *const u64, run.len as usize) };
+            SortedKmers { values }
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = run;
+            SortedKmers { values: &[] }
+        }
+    }
+
+    /// Iterates over the references and their sorted k-mer keys, in database order.
+    pub fn references(&self) -> impl Iterator<Item = (&str, SortedKmers<'_>)> {
+        self.references
+            .iter()
+            .map(|(name, run)| (name.as_str(), self.run(run)))
+    }
+
+    pub fn num_references(&self) -> usize {
+        self.references.len()
+    }
+
+    /// The sorted union of all references' k-mer keys.
+    pub fn unified_kmers(&self) -> SortedKmers<'_> {
+        self.run(&self.unified)
+    }
+
+    /// Returns a `KmerDbV2` with this index's k-mer size, hashing and canonical setting but
+    /// no references, for code paths that only need database metadata.
+    pub fn metadata(&self) -> KmerDbV2 {
+        let mut kmer_db = KmerDbV2::new(self.k);
+        kmer_db.hashing = self.hashing;
+        kmer_db.canonical = self.canonical;
+        kmer_db
+    }
+
+    /// Loads the whole index back into an in-memory `KmerDbV2`.
+    pub fn to_kmer_db(&self) -> KmerDbV2 {
+        let mut kmer_db = self.metadata();
+        kmer_db.references = self
+            .references()
+            .map(|(name, kmers)| (name.to_string(), kmers.iter().collect::<HashSet<u64>>()))
+            .collect::<BTreeMap<_, _>>();
+        kmer_db
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_sorted_index_round_trip() {
+        let mut kmer_db = KmerDbV2::new(5);
+        kmer_db.add_reference("b".to_string(), [9, 3, 700].into_iter().collect());
+        kmer_db.add_reference("a".to_string(), [3, u64::MAX, 1].into_iter().collect());
+        kmer_db.add_reference("empty".to_string(), HashSet::new());
+
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("index.kidx");
+        write_sorted_kmer_index(&kmer_db, &path).unwrap();
+        assert!(is_sorted_kmer_index(&path).unwrap());
+
+        let index = SortedKmerMmap::open(&path).unwrap();
+        assert_eq!(index.k, 5);
+        assert_eq!(index.num_references(), 3);
+        let names: Vec<&str> = index.references().map(|(name, _)| name).collect();
+        assert_eq!(names, vec!["a", "b", "empty"]);
+
+        let (_, a_kmers) = index.references().next().unwrap();
+        assert_eq!(a_kmers.iter().collect::<Vec<_>>(), vec![1, 3, u64::MAX]);
+        assert!(a_kmers.contains(u64::MAX));
+        assert!(!a_kmers.contains(9));
+
+        let unified = index.unified_kmers();
+        assert_eq!(unified.iter().collect::<Vec<_>>(), vec![1, 3, 9, 700, u64::MAX]);
+        assert!(unified.contains(700));
+        assert!(!unified.contains(2));
+
+        let reloaded = index.to_kmer_db();
+        assert_eq!(reloaded.references, kmer_db.references);
+        assert_eq!(reloaded.canonical, kmer_db.canonical);
+    }
+
+    #[test]
+    fn test_sorted_union_index_has_no_references() {
+        let mut kmer_db = KmerDbV2::new(5);
+        kmer_db.add_reference("b".to_string(), [9, 3].into_iter().collect());
+        kmer_db.add_reference("a".to_string(), [3, 1].into_iter().collect());
+
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("union.kidx");
+        write_sorted_union_index(&kmer_db, &path).unwrap();
+
+        let index = SortedKmerMmap::open(&path).unwrap();
+        assert_eq!(index.num_references(), 0);
+        assert_eq!(index.unified_kmers().iter().collect::<Vec<_>>(), vec![1, 3, 9]);
+        assert_eq!(std::fs::metadata(&path).unwrap().len() % 8, 0);
+    }
+
+    #[test]
+    fn test_open_rejects_overflowing_header_lengths_and_runs() {
+        let dir = TempDir::new().unwrap();
+        let write_raw = |name: &str, header_len: u64, header: &SortedIndexHeader| {
+            let path = dir.path().join(name);
+            let mut bytes = SORTED_INDEX_MAGIC.to_vec();
+            bytes.extend_from_slice(&header_len.to_le_bytes());
+            bytes.extend_from_slice(&db_bincode_options().serialize(header).unwrap());
+            std::fs::write(&path, bytes).unwrap();
+            path
+        };
+        let header = |unified: KmerRun| SortedIndexHeader {
+            k: 5,
+            canonical: true,
+            hashing: None,
+            references: Vec::new(),
+            unified,
+        };
+
+        let huge_header = write_raw("huge_header.kidx", u64::MAX, &header(KmerRun { offset: 0, len: 0 }));
+        let err = SortedKmerMmap::open(&huge_header).err().unwrap();
+        assert!(err.to_string().contains("is truncated"), "{}", err);
+
+        let valid_header = header(KmerRun { offset: u64::MAX / 4, len: u64::MAX / 4 });
+        let header_len = db_bincode_options().serialize(&valid_header).unwrap().len() as u64;
+        let huge_run = write_raw("huge_run.kidx", header_len, &valid_header);
+        let err = SortedKmerMmap::open(&huge_run).err().unwrap();
+        assert!(err.to_string().contains("is truncated"), "{}", err);
+    }
+}
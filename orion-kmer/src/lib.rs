@@ -1,8 +1,15 @@
 // This file makes items available to main.rs and integration tests.
 
+pub mod alignment_reads;
+pub mod bgzf;
+pub mod bloom;
 pub mod cli;
 pub mod commands;
 pub mod db_types; // Added module declaration for db_types
 pub mod errors;
+pub mod interrupt;
 pub mod kmer;
+pub mod kmer_index;
+pub mod parallel_gzip;
+pub mod sqlite_output;
 pub mod utils;
@@ -2,14 +2,39 @@
 
 use anyhow::Result;
 use log::error;
-use orion_kmer::{cli, commands}; // Use items from the library part of the crate
+use orion_kmer::{
+    cli, commands, interrupt,
+    errors::{io_exit_code, OrionKmerError, EXIT_GENERIC, EXIT_PARSE_ERROR, EXIT_SERIALIZATION_ERROR},
+}; // Use items from the library part of the crate
+
+// Picks the exit code for a failed command from the most specific error in its chain:
+// an OrionKmerError, else a missing file, a malformed sequence file, an unreadable database
+// or another I/O error.
+fn exit_code(e: &anyhow::Error) -> i32 {
+    if let Some(orion_error) = e.chain().find_map(|cause| cause.downcast_ref::<OrionKmerError>()) {
+        return orion_error.exit_code();
+    }
+    for cause in e.chain() {
+        if let Some(io_error) = cause.downcast_ref::<std::io::Error>() {
+            return io_exit_code(io_error);
+        }
+        if cause.downcast_ref::<needletail::errors::ParseError>().is_some() {
+            return EXIT_PARSE_ERROR;
+        }
+        if cause.downcast_ref::<bincode::Error>().is_some() {
+            return EXIT_SERIALIZATION_ERROR;
+        }
+    }
+    EXIT_GENERIC
+}
 
 fn main() -> Result<()> {
     let matches = cli::parse_cli();
+    interrupt::install_interrupt_handler();
 
-    if let Err(e) = commands::dispatch_command(matches.command, matches.threads, matches.verbose) {
+    if let Err(e) = commands::dispatch_command(matches) {
         error!("Error: {}", e);
-        std::process::exit(1);
+        std::process::exit(exit_code(&e));
     }
 
     Ok(())
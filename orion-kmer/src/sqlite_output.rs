@@ -0,0 +1,207 @@
+// SQLite output of `compare --sqlite` and `classify --sqlite`. Every run appends rows, so many
+// jobs can gather their results in one database; the tables are created when absent.
+//
+// Schema:
+//   comparisons      one row per `compare` run: the two inputs, k, set sizes, Jaccard index
+//                    and the containment of each input in the other
+//   classifications  one row per `classify` run: the input files and input k-mer totals
+//   "references"     one row per reported reference of a classification, keyed by
+//                    classification_id; passed_filter is 0 for --full-tsv references that
+//                    failed --min-coverage/--min-reference-hits
+//
+// Each table has an `id INTEGER PRIMARY KEY` and a `created_at_unix_secs` column. A run's rows
+// are written in a single transaction, committed by `ResultsDb::commit`.
+
+use anyhow::{Context, Result};
+use rusqlite::{params, Connection};
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+const SCHEMA: &str = r#"
+CREATE TABLE IF NOT EXISTS comparisons (
+    id INTEGER PRIMARY KEY,
+    created_at_unix_secs INTEGER NOT NULL,
+    db1_path TEXT NOT NULL,
+    db2_path TEXT NOT NULL,
+    kmer_size INTEGER NOT NULL,
+    db1_unique_kmers INTEGER NOT NULL,
+    db2_unique_kmers INTEGER NOT NULL,
+    intersection_size INTEGER NOT NULL,
+    union_size INTEGER NOT NULL,
+    jaccard_index REAL NOT NULL,
+    containment_1_in_2 REAL NOT NULL,
+    containment_2_in_1 REAL NOT NULL
+);
+CREATE TABLE IF NOT EXISTS classifications (
+    id INTEGER PRIMARY KEY,
+    created_at_unix_secs INTEGER NOT NULL,
+    input_file_path TEXT NOT NULL,
+    total_unique_kmers_in_input INTEGER NOT NULL,
+    min_kmer_frequency_filter INTEGER NOT NULL,
+    input_total_bases INTEGER NOT NULL,
+    input_ambiguous_bases INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS "references" (
+    id INTEGER PRIMARY KEY,
+    classification_id INTEGER NOT NULL REFERENCES classifications(id),
+    created_at_unix_secs INTEGER NOT NULL,
+    database_path TEXT NOT NULL,
+    reference_name TEXT NOT NULL,
+    total_kmers_in_reference INTEGER NOT NULL,
+    input_kmers_hitting_reference INTEGER NOT NULL,
+    sum_depth_of_matched_kmers_in_input INTEGER NOT NULL,
+    avg_depth_of_matched_kmers_in_input REAL NOT NULL,
+    proportion_input_kmers_hitting_reference REAL NOT NULL,
+    reference_breadth_of_coverage REAL NOT NULL,
+    passed_filter INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS references_classification_id ON "references" (classification_id);
+"#;
+
+// How long a run waits for another job's transaction on the same database to finish.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// A `compare` result, as written to the `comparisons` table.
+pub struct ComparisonRow<'a> {
+    pub db1_path: &'a str,
+    pub db2_path: &'a str,
+    pub kmer_size: u8,
+    pub db1_unique_kmers: usize,
+    pub db2_unique_kmers: usize,
+    pub intersection_size: usize,
+    pub union_size: usize,
+    pub jaccard_index: f64,
+    pub containment_1_in_2: f64,
+    pub containment_2_in_1: f64,
+}
+
+/// A `classify` run, as written to the `classifications` table.
+pub struct ClassificationRow<'a> {
+    pub input_file_path: &'a str,
+    pub total_unique_kmers_in_input: usize,
+    pub min_kmer_frequency_filter: usize,
+    pub input_total_bases: u64,
+    pub input_ambiguous_bases: u64,
+}
+
+/// One reference of a classification, as written to the `references` table.
+pub struct ReferenceRow<'a> {
+    pub database_path: &'a str,
+    pub reference_name: &'a str,
+    pub total_kmers_in_reference: usize,
+    pub input_kmers_hitting_reference: usize,
+    pub sum_depth_of_matched_kmers_in_input: usize,
+    pub avg_depth_of_matched_kmers_in_input: f64,
+    pub proportion_input_kmers_hitting_reference: f64,
+    pub reference_breadth_of_coverage: f64,
+    pub passed_filter: bool,
+}
+
+/// An open --sqlite results database with the rows of the current run pending.
+pub struct ResultsDb {
+    conn: Connection,
+    path: PathBuf,
+    created_at_unix_secs: i64,
+}
+
+impl ResultsDb {
+    /// Opens (or creates) the database at `path`, creates any missing table and starts the
+    /// run's transaction.
+    pub fn open(path: &Path) -> Result<Self> {
+        let conn = Connection::open(path).with_context(|| format!("Failed to open SQLite database: {:?}", path))?;
+        conn.busy_timeout(BUSY_TIMEOUT)
+            .with_context(|| format!("Failed to configure SQLite database: {:?}", path))?;
+        conn.execute_batch(SCHEMA)
+            .with_context(|| format!("Failed to create result tables in SQLite database: {:?}", path))?;
+        conn.execute_batch("BEGIN IMMEDIATE")
+            .with_context(|| format!("Failed to start a transaction on SQLite database: {:?}", path))?;
+        let created_at_unix_secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs() as i64);
+        Ok(ResultsDb {
+            conn,
+            path: path.to_path_buf(),
+            created_at_unix_secs,
+        })
+    }
+
+    pub fn insert_comparison(&self, row: &ComparisonRow) -> Result<()> {
+        self.conn
+            .execute(
+                "INSERT INTO comparisons (created_at_unix_secs, db1_path, db2_path, kmer_size, db1_unique_kmers, \
+                 db2_unique_kmers, intersection_size, union_size, jaccard_index, containment_1_in_2, containment_2_in_1) \
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+                params![
+                    self.created_at_unix_secs,
+                    row.db1_path,
+                    row.db2_path,
+                    row.kmer_size,
+                    row.db1_unique_kmers as i64,
+                    row.db2_unique_kmers as i64,
+                    row.intersection_size as i64,
+                    row.union_size as i64,
+                    row.jaccard_index,
+                    row.containment_1_in_2,
+                    row.containment_2_in_1,
+                ],
+            )
+            .with_context(|| format!("Failed to insert comparison into SQLite database: {:?}", self.path))?;
+        Ok(())
+    }
+
+    /// Inserts a classification, returning the id its references are recorded under.
+    pub fn insert_classification(&self, row: &ClassificationRow) -> Result<i64> {
+        self.conn
+            .execute(
+                "INSERT INTO classifications (created_at_unix_secs, input_file_path, total_unique_kmers_in_input, \
+                 min_kmer_frequency_filter, input_total_bases, input_ambiguous_bases) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    self.created_at_unix_secs,
+                    row.input_file_path,
+                    row.total_unique_kmers_in_input as i64,
+                    row.min_kmer_frequency_filter as i64,
+                    row.input_total_bases as i64,
+                    row.input_ambiguous_bases as i64,
+                ],
+            )
+            .with_context(|| format!("Failed to insert classification into SQLite database: {:?}", self.path))?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    pub fn insert_reference(&self, classification_id: i64, row: &ReferenceRow) -> Result<()> {
+        let mut statement = self
+            .conn
+            .prepare_cached(
+                "INSERT INTO \"references\" (classification_id, created_at_unix_secs, database_path, reference_name, \
+                 total_kmers_in_reference, input_kmers_hitting_reference, sum_depth_of_matched_kmers_in_input, \
+                 avg_depth_of_matched_kmers_in_input, proportion_input_kmers_hitting_reference, \
+                 reference_breadth_of_coverage, passed_filter) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            )
+            .with_context(|| format!("Failed to prepare reference insert for SQLite database: {:?}", self.path))?;
+        statement
+            .execute(params![
+                classification_id,
+                self.created_at_unix_secs,
+                row.database_path,
+                row.reference_name,
+                row.total_kmers_in_reference as i64,
+                row.input_kmers_hitting_reference as i64,
+                row.sum_depth_of_matched_kmers_in_input as i64,
+                row.avg_depth_of_matched_kmers_in_input,
+                row.proportion_input_kmers_hitting_reference,
+                row.reference_breadth_of_coverage,
+                row.passed_filter,
+            ])
+            .with_context(|| format!("Failed to insert reference into SQLite database: {:?}", self.path))?;
+        Ok(())
+    }
+
+    /// Commits the run's rows. Dropping the database without committing discards them.
+    pub fn commit(self) -> Result<()> {
+        self.conn
+            .execute_batch("COMMIT")
+            .with_context(|| format!("Failed to commit results to SQLite database: {:?}", self.path))
+    }
+}
@@ -1,48 +1,319 @@
 use anyhow::{Context, Result};
-use flate2::{read::MultiGzDecoder, write::GzEncoder, Compression as GzCompression};
-use log::{debug, info}; // Added info
+use bincode::Options;
+use bzip2::{read::MultiBzDecoder, write::BzEncoder, Compression as BzCompression};
+use flate2::{bufread::GzDecoder, read::MultiGzDecoder, write::GzEncoder, Compression as GzCompression};
+use log::{debug, info, warn}; // Added info
+use regex::Regex;
 use std::{
-    fs::File,
-    io::{BufRead, BufReader, BufWriter, Write}, // Added BufRead, Write, BufWriter
-    path::Path,
+    collections::{BTreeMap, HashSet},
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Read, Write}, // Added BufRead, Write, BufWriter
+    path::{Path, PathBuf},
 };
 use xz2::{read::XzDecoder, write::XzEncoder};
+use needletail::{
+    errors::ParseError,
+    parse_fastx_reader,
+    parser::{FastaReader, FastqReader},
+    FastxReader,
+};
 use zstd::{stream::read::Decoder as ZstdDecoder, stream::write::Encoder as ZstdEncoder};
+use lz4_flex::frame::{FrameDecoder as Lz4FrameDecoder, FrameEncoder as Lz4FrameEncoder};
+
+use crate::alignment_reads::{alignment_reads_reader, AlignmentFormat};
+use crate::bgzf::BgzfWriter;
+use crate::parallel_gzip::ParallelGzWriter;
+use crate::cli::SequenceFormat;
+use crate::db_types::{KmerDbMultiK, KmerDbV2, KmerHashing, ReferencePositions}; // Import KmerDbV2
+use crate::kmer::{murmur3_x64_128, reverse_complement_u64, seq_to_u64};
+use crate::kmer_index::{is_sorted_kmer_index, SortedKmerMmap, SORTED_INDEX_MAGIC};
+use crate::errors::OrionKmerError;
+use crate::interrupt::PartialOutput;
 
-use crate::db_types::KmerDbV2; // Import KmerDbV2
+pub mod cardinality;
+pub mod cms;
 
 /// Determines the number of threads to use.
 /// If `cli_threads` is 0, it uses all available logical cores.
-/// Otherwise, it uses the number specified in `cli_threads`.
-pub fn get_num_threads(cli_threads: usize) -> usize {
+/// Otherwise, it uses the number specified in `cli_threads`. More threads than logical cores
+/// only add contention, so that is warned about, or with `strict` (`--strict-threads`) refused.
+pub fn get_num_threads(cli_threads: usize, strict: bool) -> Result<usize> {
+    let cores = num_cpus::get();
     let num_threads = if cli_threads == 0 {
-        num_cpus::get()
+        cores
     } else {
         cli_threads
     };
+    if num_threads > cores {
+        if strict {
+            return Err(OrionKmerError::Generic(format!(
+                "--threads {} exceeds the {} logical cores available (--strict-threads)",
+                num_threads, cores
+            ))
+            .into());
+        }
+        warn!(
+            "--threads {} exceeds the {} logical cores available; the extra threads only contend for them",
+            num_threads, cores
+        );
+    }
     debug!("Using {} threads for processing.", num_threads);
-    num_threads
+    Ok(num_threads)
 }
 
+// Threads of the Rayon global pool, once `initialize_rayon_pool` has built it.
+static RAYON_POOL_THREADS: OnceLock<usize> = OnceLock::new();
+
 /// Initializes the Rayon global thread pool with the specified number of threads.
+/// The global pool can only be built once per process: asking again for the same size is a
+/// no-op, and asking for another size, or after something else built the pool, is an error.
 pub fn initialize_rayon_pool(num_threads: usize) -> Result<()> {
+    if let Some(&pool_threads) = RAYON_POOL_THREADS.get() {
+        if pool_threads == num_threads {
+            debug!("Thread pool already initialized with {} threads", pool_threads);
+            return Ok(());
+        }
+        return Err(OrionKmerError::Generic(format!(
+            "The thread pool was already initialized with {} threads and cannot be resized to {}",
+            pool_threads, num_threads
+        ))
+        .into());
+    }
     rayon::ThreadPoolBuilder::new()
         .num_threads(num_threads)
-        .build_global()?;
+        .build_global()
+        .map_err(|e| {
+            OrionKmerError::Generic(format!(
+                "Failed to initialize the thread pool with {} threads: {}",
+                num_threads, e
+            ))
+        })?;
+    let _ = RAYON_POOL_THREADS.set(num_threads);
+    Ok(())
+}
+
+// Written before the bincode-encoded KmerDbMultiK of a multi-k database. A single KmerDbV2
+// starts with KMER_DB_MAGIC, or with its k (at most 32) if written before databases had a
+// header, so the first byte tells the formats apart.
+const MULTI_K_DB_MAGIC: &[u8; 8] = b"ORKMULT2";
+
+// Magic of the first multi-k layout, whose tables had the KmerDbV2 fields of format version 3
+// and shared one set of trailing sections.
+const LEGACY_MULTI_K_DB_MAGIC: &[u8; 8] = b"ORKMULTK";
+
+/// Magic opening a single KmerDbV2 database, ending in its format version (see
+/// `KMER_DB_FORMAT_VERSION`). The bincode-encoded KmerDbV2 follows.
+pub const KMER_DB_MAGIC: &[u8; 8] = b"ORKKDB04";
+
+/// Format version of the databases `build` writes, the number ending `KMER_DB_MAGIC`.
+pub const KMER_DB_FORMAT_VERSION: u32 = 4;
+
+/// Format version of databases that kept their canonical policy, metadata, reference lengths
+/// and name normalization in tagged sections after the KmerDbV2 rather than in it.
+pub const TRAILER_KMER_DB_FORMAT_VERSION: u32 = 3;
+
+// Every KmerDbV2 magic starts with these bytes, followed by two digits of its format version.
+const KMER_DB_MAGIC_PREFIX: &[u8; 6] = b"ORKKDB";
+
+// The format version of a KmerDbV2 magic, or None for bytes that are not one.
+fn kmer_db_magic_version(prefix: &[u8]) -> Option<u32> {
+    let digits = prefix.strip_prefix(KMER_DB_MAGIC_PREFIX.as_slice())?.get(..2)?;
+    if !digits.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    std::str::from_utf8(digits).ok()?.parse().ok()
+}
+
+/// Format version of headerless databases: the first KmerDbV2 layout, holding only k and the
+/// references, written before databases had a header.
+pub const LEGACY_KMER_DB_FORMAT_VERSION: u32 = 2;
+
+// The body of a headerless database. Every field KmerDbV2 gained since then is read with its
+// default: no hashing, canonical k-mers, no positions and no sampling rates.
+#[derive(serde::Deserialize)]
+struct LegacyKmerDbV2 {
+    k: u8,
+    references: BTreeMap<String, HashSet<u64>>,
+}
+
+impl From<LegacyKmerDbV2> for KmerDbV2 {
+    fn from(legacy: LegacyKmerDbV2) -> Self {
+        KmerDbV2 {
+            references: legacy.references,
+            ..KmerDbV2::new(legacy.k)
+        }
+    }
+}
+
+// The body of a format version 3 database, or of a table of the first multi-k layout: the
+// fields read from its trailing sections are left at their defaults here.
+#[derive(serde::Deserialize)]
+struct TrailerKmerDbV2 {
+    k: u8,
+    references: BTreeMap<String, HashSet<u64>>,
+    hashing: Option<KmerHashing>,
+    canonical: bool,
+    positions: BTreeMap<String, ReferencePositions>,
+    sampling_rates: BTreeMap<String, f64>,
+}
+
+impl From<TrailerKmerDbV2> for KmerDbV2 {
+    fn from(trailer_db: TrailerKmerDbV2) -> Self {
+        KmerDbV2 {
+            references: trailer_db.references,
+            hashing: trailer_db.hashing,
+            canonical: trailer_db.canonical,
+            positions: trailer_db.positions,
+            sampling_rates: trailer_db.sampling_rates,
+            ..KmerDbV2::new(trailer_db.k)
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct LegacyKmerDbMultiK {
+    tables: BTreeMap<u8, TrailerKmerDbV2>,
+}
+
+/// The bincode configuration of every binary file orion-kmer writes (databases, their trailing
+/// sections, sorted index headers and count tables): fixed-width little-endian integers, so
+/// files load the same on any architecture. This is what bincode 1's `serialize` and
+/// `deserialize` default to, pinned so existing files keep loading if those defaults change.
+pub fn db_bincode_options() -> impl bincode::Options + Copy {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .with_little_endian()
+        .allow_trailing_bytes()
+}
+
+// Tagged sections that databases before format version 4 wrote after the database, each an
+// 8-byte tag followed by a bincode value: its DbMetadata, its CanonicalPolicy when not
+// lexicographic, its reference sequence lengths when it had any and its reference name
+// normalization when names were normalized. Missing sections just mean no metadata, the
+// lexicographic policy, no lengths and names stored as given.
+const DB_METADATA_MAGIC: &[u8; 8] = b"ORKMETA1";
+const DB_CANONICAL_POLICY_MAGIC: &[u8; 8] = b"ORKCPOL1";
+const DB_REFERENCE_LENGTHS_MAGIC: &[u8; 8] = b"ORKRLEN1";
+const DB_NAME_NORMALIZATION_MAGIC: &[u8; 8] = b"ORKNAME1";
+
+// Reads the trailing sections left in `reader` after `kmer_db` has been deserialized.
+fn read_db_trailer<R: Read>(reader: &mut R, path: &Path, kmer_db: &mut KmerDbV2) -> Result<()> {
+    loop {
+        let mut tag = Vec::with_capacity(DB_METADATA_MAGIC.len());
+        (&mut *reader)
+            .take(DB_METADATA_MAGIC.len() as u64)
+            .read_to_end(&mut tag)
+            .with_context(|| format!("Failed to read k-mer database: {:?}", path))?;
+        if tag == DB_METADATA_MAGIC {
+            kmer_db.metadata = Some(
+                db_bincode_options().deserialize_from(&mut *reader)
+                    .with_context(|| format!("Failed to deserialize database metadata from {:?}", path))?,
+            );
+        } else if tag == DB_CANONICAL_POLICY_MAGIC {
+            kmer_db.canonical_policy = db_bincode_options().deserialize_from(&mut *reader)
+                .with_context(|| format!("Failed to deserialize the canonical policy of {:?}", path))?;
+        } else if tag == DB_REFERENCE_LENGTHS_MAGIC {
+            kmer_db.reference_lengths = db_bincode_options().deserialize_from(&mut *reader)
+                .with_context(|| format!("Failed to deserialize the reference lengths of {:?}", path))?;
+        } else if tag == DB_NAME_NORMALIZATION_MAGIC {
+            kmer_db.name_normalization = Some(
+                db_bincode_options().deserialize_from(&mut *reader)
+                    .with_context(|| format!("Failed to deserialize the reference name normalization of {:?}", path))?,
+            );
+        } else if tag.is_empty() {
+            return Ok(());
+        } else {
+            // A section this version does not know may change how the k-mers must be looked up
+            return Err(OrionKmerError::Generic(format!(
+                "{:?} has an unknown database section {:?} after the k-mers",
+                path,
+                String::from_utf8_lossy(&tag)
+            ))
+            .into());
+        }
+    }
+}
+
+// Reads the end of `reader`, failing unless it is empty.
+fn check_db_end<R: Read>(reader: &mut R, path: &Path) -> Result<()> {
+    let mut byte = [0u8; 1];
+    let read = reader.read(&mut byte).with_context(|| format!("Failed to read k-mer database: {:?}", path))?;
+    if read > 0 {
+        return Err(OrionKmerError::Generic(format!(
+            "{:?} has unexpected bytes after the database",
+            path
+        ))
+        .into());
+    }
     Ok(())
 }
 
 /// Loads a KmerDbV2 from the specified file path.
 /// Handles decompression automatically based on file extension.
 pub fn load_kmer_db_v2(path: &Path) -> Result<KmerDbV2> {
+    load_kmer_db_v2_for_k(path, None)
+}
+
+/// Loads a KmerDbV2 like `load_kmer_db_v2`; from a multi-k database (see `build --k-list`),
+/// loads the table for `k`, failing if none is given or the database has no table for it.
+/// For other databases `k` is not checked here.
+pub fn load_kmer_db_v2_for_k(path: &Path, k: Option<u8>) -> Result<KmerDbV2> {
     info!("Loading k-mer database (KmerDbV2) from: {:?}", path);
+    if is_sorted_kmer_index(path)? {
+        info!("{:?} is a sorted k-mer index; loading it into memory", path);
+        let mut kmer_db = SortedKmerMmap::open(path)?.to_kmer_db();
+        apply_blocklist(&mut kmer_db, path)?;
+        return Ok(kmer_db);
+    }
     // Use get_decompressed_input_reader to handle potential compression
     let mut reader = get_decompressed_input_reader(path)
         .with_context(|| format!("Failed to get input reader for k-mer database: {:?}", path))?;
+    let mut prefix = Vec::with_capacity(MULTI_K_DB_MAGIC.len());
+    (&mut reader)
+        .take(MULTI_K_DB_MAGIC.len() as u64)
+        .read_to_end(&mut prefix)
+        .with_context(|| format!("Failed to read k-mer database: {:?}", path))?;
 
-    // bincode::deserialize_from directly takes a Read, which Box<dyn BufRead + Send> implements.
-    let kmer_db: KmerDbV2 = bincode::deserialize_from(&mut reader)
-        .with_context(|| format!("Failed to deserialize KmerDbV2 from {:?}", path))?;
+    let mut kmer_db: KmerDbV2 = if prefix == MULTI_K_DB_MAGIC {
+        let multi_k_db: KmerDbMultiK = db_bincode_options().deserialize_from(&mut reader)
+            .with_context(|| format!("Failed to deserialize multi-k database from {:?}", path))?;
+        check_db_end(&mut reader, path)?;
+        take_multi_k_table(multi_k_db.tables, path, k)?
+    } else if prefix == LEGACY_MULTI_K_DB_MAGIC {
+        let multi_k_db: LegacyKmerDbMultiK = db_bincode_options().deserialize_from(&mut reader)
+            .with_context(|| format!("Failed to deserialize multi-k database from {:?}", path))?;
+        let mut kmer_db: KmerDbV2 = take_multi_k_table(multi_k_db.tables, path, k)?.into();
+        // The tables of one build shared a single trailer
+        read_db_trailer(&mut reader, path, &mut kmer_db)?;
+        kmer_db
+    } else if let Some(version) = kmer_db_magic_version(&prefix) {
+        if version == TRAILER_KMER_DB_FORMAT_VERSION {
+            let trailer_db: TrailerKmerDbV2 = db_bincode_options().deserialize_from(&mut reader)
+                .with_context(|| format!("Failed to deserialize KmerDbV2 from {:?}", path))?;
+            let mut kmer_db = trailer_db.into();
+            read_db_trailer(&mut reader, path, &mut kmer_db)?;
+            kmer_db
+        } else if version == KMER_DB_FORMAT_VERSION {
+            let kmer_db = db_bincode_options().deserialize_from(&mut reader)
+                .with_context(|| format!("Failed to deserialize KmerDbV2 from {:?}", path))?;
+            check_db_end(&mut reader, path)?;
+            kmer_db
+        } else {
+            return Err(OrionKmerError::Generic(format!(
+                "{:?} has database format version {}, but this orion-kmer reads versions {} and {} (and headerless databases)",
+                path, version, TRAILER_KMER_DB_FORMAT_VERSION, KMER_DB_FORMAT_VERSION
+            ))
+            .into());
+        }
+    } else {
+        debug!("{:?} has no database header; reading it as format version {}", path, LEGACY_KMER_DB_FORMAT_VERSION);
+        let legacy_db: LegacyKmerDbV2 = db_bincode_options().deserialize_from(prefix.as_slice().chain(&mut reader))
+            .with_context(|| format!("Failed to deserialize KmerDbV2 from {:?}", path))?;
+        let mut kmer_db = legacy_db.into();
+        read_db_trailer(&mut reader, path, &mut kmer_db)?;
+        kmer_db
+    };
+    apply_blocklist(&mut kmer_db, path)?;
 
     info!(
         "Successfully loaded KmerDbV2 from {:?} (k={}, {} references, {} total unique k-mers)",
@@ -54,9 +325,605 @@ pub fn load_kmer_db_v2(path: &Path) -> Result<KmerDbV2> {
     Ok(kmer_db)
 }
 
-use indicatif::{ProgressBar, ProgressStyle};
+// Removes the table for `k` from the tables of the multi-k database at `path`, failing if no k
+// is given or there is no table for it.
+fn take_multi_k_table<T>(mut tables: BTreeMap<u8, T>, path: &Path, k: Option<u8>) -> Result<T> {
+    let k_values: Vec<u8> = tables.keys().copied().collect();
+    let Some(k) = k else {
+        return Err(OrionKmerError::Generic(format!(
+            "{:?} is a multi-k database (k = {:?}); choose a table with --kmer-size (query and classify)",
+            path, k_values
+        ))
+        .into());
+    };
+    tables.remove(&k).ok_or_else(|| {
+        OrionKmerError::Generic(format!(
+            "Multi-k database {:?} has no table for k={} (it has k = {:?})",
+            path, k, k_values
+        ))
+        .into()
+    })
+}
+
+// The k-mers removed from every loaded database, set by the global `--blocklist`.
+struct Blocklist {
+    path: PathBuf,
+    k: u8,
+    // Plain 2-bit encodings, matched to each database's canonical form and hashing when applied
+    kmers: HashSet<u64>,
+}
+
+static BLOCKLIST: OnceLock<Blocklist> = OnceLock::new();
+
+/// Loads the `--blocklist` k-mers, from a k-mer database built without `--hashed` or a text
+/// list with one k-mer per line (further columns are ignored), so every database loaded
+/// afterwards has them removed. Only the first call has an effect.
+pub fn init_blocklist(path: &Path) -> Result<()> {
+    if BLOCKLIST.get().is_some() {
+        debug!("Blocklist already initialized; ignoring {:?}", path);
+        return Ok(());
+    }
+    let (k, kmers) = if read_kmer_db_format(path).is_ok() {
+        let kmer_db = load_kmer_db_v2(path)?;
+        if kmer_db.hashing.is_some() {
+            return Err(OrionKmerError::Generic(format!(
+                "Blocklist database {:?} stores hashed k-mers, which cannot be matched to other databases; build it without --hashed",
+                path
+            ))
+            .into());
+        }
+        (kmer_db.k, kmer_db.get_all_kmers_unified())
+    } else {
+        read_blocklist_kmers(path)?
+    };
+    info!("Loaded {} blocklisted k-mers (k={}) from {:?}", kmers.len(), k, path);
+    let _ = BLOCKLIST.set(Blocklist {
+        path: path.to_path_buf(),
+        k,
+        kmers,
+    });
+    Ok(())
+}
+
+// Reads a text blocklist: the first column of each non-blank line is a k-mer, all of one length.
+fn read_blocklist_kmers(path: &Path) -> Result<(u8, HashSet<u64>)> {
+    let reader = get_decompressed_input_reader(path)?;
+    let mut k: Option<usize> = None;
+    let mut kmers = HashSet::new();
+    for (line_idx, line) in reader.lines().enumerate() {
+        let line = line.with_context(|| format!("Failed to read blocklist: {:?}", path))?;
+        let Some(kmer_str) = line.split_whitespace().next() else {
+            continue; // Skip blank lines
+        };
+        let kmer_bytes = kmer_str.to_ascii_uppercase().into_bytes();
+        let kmer_len = *k.get_or_insert(kmer_bytes.len());
+        let kmer_val = (kmer_bytes.len() == kmer_len && kmer_len <= 32)
+            .then(|| seq_to_u64(&kmer_bytes, kmer_len as u8))
+            .flatten()
+            .ok_or_else(|| {
+                OrionKmerError::Generic(format!(
+                    "Invalid k-mer '{}' on line {} of blocklist {:?}: expected {} A/C/G/T bases (at most 32)",
+                    kmer_str,
+                    line_idx + 1,
+                    path,
+                    kmer_len
+                ))
+            })?;
+        kmers.insert(kmer_val);
+    }
+    let k = k.ok_or_else(|| OrionKmerError::Generic(format!("Blocklist {:?} has no k-mers", path)))?;
+    Ok((k as u8, kmers))
+}
+
+// Removes the --blocklist k-mers, if any, from the database just loaded from `path`.
+fn apply_blocklist(kmer_db: &mut KmerDbV2, path: &Path) -> Result<()> {
+    let Some(blocklist) = BLOCKLIST.get() else {
+        return Ok(());
+    };
+    if blocklist.k != kmer_db.k {
+        return Err(OrionKmerError::Generic(format!(
+            "--blocklist {:?} has k={}, but database {:?} has k={}",
+            blocklist.path, blocklist.k, path, kmer_db.k
+        ))
+        .into());
+    }
+    let mut keys = HashSet::with_capacity(blocklist.kmers.len());
+    for &kmer in &blocklist.kmers {
+        if kmer_db.canonical {
+            keys.insert(kmer_db.lookup_key(kmer_db.canonicalize(kmer)));
+        } else {
+            // Stored as read, so either orientation may be present
+            keys.insert(kmer_db.lookup_key(kmer));
+            keys.insert(kmer_db.lookup_key(reverse_complement_u64(kmer, kmer_db.k)));
+        }
+    }
+    let removed = kmer_db.remove_kmers(&keys);
+    info!("Removed {} blocklisted k-mer entries from {:?}", removed, path);
+    Ok(())
+}
+
+/// Fails when `--blocklist` is given with `option`, which reads databases without loading them
+/// (memory-mapped indexes, Bloom filters), so the blocklist could not be applied.
+pub fn check_blocklist_applies(option: &str) -> Result<()> {
+    match BLOCKLIST.get() {
+        Some(blocklist) => Err(OrionKmerError::Generic(format!(
+            "--blocklist {:?} cannot be applied with {}, which does not load the databases",
+            blocklist.path, option
+        ))
+        .into()),
+        None => Ok(()),
+    }
+}
+
+/// Reads the k-mer size(s) of a database without loading its k-mers: the header of a sorted
+/// index, or the `k` field leading the KmerDbV2 (after its magic, if it has one). A multi-k
+/// database is loaded to list its tables.
+pub fn read_kmer_db_k_values(path: &Path) -> Result<Vec<u8>> {
+    if is_sorted_kmer_index(path)? {
+        return Ok(vec![SortedKmerMmap::open(path)?.k]);
+    }
+    let mut reader = get_decompressed_input_reader(path)
+        .with_context(|| format!("Failed to get input reader for k-mer database: {:?}", path))?;
+    let mut prefix = Vec::with_capacity(MULTI_K_DB_MAGIC.len());
+    (&mut reader)
+        .take(MULTI_K_DB_MAGIC.len() as u64)
+        .read_to_end(&mut prefix)
+        .with_context(|| format!("Failed to read k-mer database: {:?}", path))?;
+    if prefix == MULTI_K_DB_MAGIC {
+        let multi_k_db: KmerDbMultiK = db_bincode_options().deserialize_from(&mut reader)
+            .with_context(|| format!("Failed to deserialize multi-k database from {:?}", path))?;
+        return Ok(multi_k_db.k_values());
+    }
+    if prefix == LEGACY_MULTI_K_DB_MAGIC {
+        let multi_k_db: LegacyKmerDbMultiK = db_bincode_options().deserialize_from(&mut reader)
+            .with_context(|| format!("Failed to deserialize multi-k database from {:?}", path))?;
+        return Ok(multi_k_db.tables.keys().copied().collect());
+    }
+    if kmer_db_magic_version(&prefix).is_some() {
+        let mut k = [0u8; 1];
+        reader
+            .read_exact(&mut k)
+            .with_context(|| format!("Failed to read k-mer database: {:?}", path))?;
+        return Ok(vec![k[0]]);
+    }
+    match prefix.first() {
+        Some(&k) => Ok(vec![k]),
+        None => Err(OrionKmerError::Generic(format!("K-mer database {:?} is empty", path)).into()),
+    }
+}
+
+/// The on-disk format of a k-mer database, as read from its first bytes by `read_kmer_db_format`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KmerDbFormat {
+    /// `hashset`, `multi_k` or `sorted_index`, as `info` names them.
+    pub format: &'static str,
+    /// Version of that format: the number ending the magic of a KmerDbV2 or sorted index,
+    /// `LEGACY_KMER_DB_FORMAT_VERSION` for a headerless KmerDbV2, and 1 or 2 for the two
+    /// multi-k layouts.
+    pub version: u32,
+    /// The magic bytes the file starts with; headerless databases have none.
+    pub magic: Option<String>,
+    /// K-mer size, except for multi-k databases, whose tables are listed only by a full load.
+    pub k: Option<u8>,
+    /// Number of references of a plain database, or of tables of a multi-k database.
+    pub entries: Option<u64>,
+}
+
+/// Identifies the format of a k-mer database from its first bytes only, without loading or
+/// decompressing the rest of it, so databases of any size are checked at once.
+pub fn read_kmer_db_format(path: &Path) -> Result<KmerDbFormat> {
+    let reader = get_decompressed_input_reader(path)
+        .with_context(|| format!("Failed to get input reader for k-mer database: {:?}", path))?;
+    // Magic or k, then an 8-byte length: the sorted index header length, the number of
+    // multi-k tables, or the number of references of a KmerDbV2 (right after its 1-byte k,
+    // which follows the magic unless the database is headerless)
+    let mut prefix = Vec::with_capacity(17);
+    reader
+        .take(17)
+        .read_to_end(&mut prefix)
+        .with_context(|| format!("Failed to read k-mer database: {:?}", path))?;
+    let le_u64 = |bytes: &[u8]| bytes.try_into().ok().map(u64::from_le_bytes);
+    if prefix.starts_with(SORTED_INDEX_MAGIC) {
+        let version = std::str::from_utf8(&SORTED_INDEX_MAGIC[6..]).ok().and_then(|v| v.parse().ok()).unwrap_or(0);
+        return Ok(KmerDbFormat {
+            format: "sorted_index",
+            version,
+            magic: Some(String::from_utf8_lossy(SORTED_INDEX_MAGIC).into_owned()),
+            // The bincode-encoded header starts with k
+            k: prefix.get(16).copied(),
+            entries: None,
+        });
+    }
+    if prefix.starts_with(MULTI_K_DB_MAGIC) || prefix.starts_with(LEGACY_MULTI_K_DB_MAGIC) {
+        return Ok(KmerDbFormat {
+            format: "multi_k",
+            version: if prefix.starts_with(MULTI_K_DB_MAGIC) { 2 } else { 1 },
+            magic: Some(String::from_utf8_lossy(&prefix[..MULTI_K_DB_MAGIC.len()]).into_owned()),
+            k: None,
+            entries: prefix.get(8..16).and_then(le_u64),
+        });
+    }
+    if let Some(version) = kmer_db_magic_version(&prefix) {
+        return Ok(KmerDbFormat {
+            format: "hashset",
+            version,
+            magic: Some(String::from_utf8_lossy(&prefix[..KMER_DB_MAGIC.len()]).into_owned()),
+            k: prefix.get(8).copied(),
+            entries: prefix.get(9..17).and_then(le_u64),
+        });
+    }
+    match prefix.first() {
+        Some(&k) if (1..=32).contains(&k) => Ok(KmerDbFormat {
+            format: "hashset",
+            version: LEGACY_KMER_DB_FORMAT_VERSION,
+            magic: None,
+            k: Some(k),
+            entries: prefix.get(1..9).and_then(le_u64),
+        }),
+        Some(&byte) => Err(OrionKmerError::Generic(format!(
+            "{:?} is not an orion-kmer database: it starts neither with a known magic nor with a k of 1 to 32 (first byte {:#04x})",
+            path, byte
+        ))
+        .into()),
+        None => Err(OrionKmerError::Generic(format!("K-mer database {:?} is empty", path)).into()),
+    }
+}
+
+/// Serializes the database to `output_path`, compressing according to its extension.
+pub fn write_kmer_db(kmer_db_v2: &KmerDbV2, output_path: &Path) -> Result<()> {
+    debug!("Opening output database file for writing: {:?}", output_path);
+    let _partial_output = PartialOutput::new(output_path);
+    // Use get_output_writer to handle potential compression
+    let mut writer = get_output_writer(output_path).with_context(|| {
+        format!(
+            "Failed to get output writer for database file: {:?}",
+            output_path
+        )
+    })?;
+
+    writer.write_all(KMER_DB_MAGIC)?;
+    db_bincode_options().serialize_into(&mut writer, kmer_db_v2).with_context(|| {
+        format!(
+            "Failed to serialize k-mer database (KmerDbV2) to {:?}",
+            output_path
+        )
+    })?;
+
+    // Finish explicitly: a compression error at the end of the stream would be lost on drop
+    finalize_writer(writer).context("Failed to finish output database writer")?;
+    Ok(())
+}
+
+/// Serializes a multi-k database to `output_path`, compressing according to its extension.
+pub fn write_multi_k_db(multi_k_db: &KmerDbMultiK, output_path: &Path) -> Result<()> {
+    let _partial_output = PartialOutput::new(output_path);
+    let mut writer = get_output_writer(output_path).with_context(|| {
+        format!(
+            "Failed to get output writer for database file: {:?}",
+            output_path
+        )
+    })?;
+    writer.write_all(MULTI_K_DB_MAGIC)?;
+    db_bincode_options().serialize_into(&mut writer, multi_k_db).with_context(|| {
+        format!("Failed to serialize multi-k database to {:?}", output_path)
+    })?;
+    finalize_writer(writer).context("Failed to finish output database writer")?;
+    Ok(())
+}
+
+/// Checks that canonical k-mer lookups can be trusted against `kmer_db`. A database of
+/// non-canonical k-mers triggers a warning, or an error when `strict` is set.
+pub fn check_canonical_lookup(kmer_db: &KmerDbV2, path: &Path, strict: bool) -> Result<()> {
+    if kmer_db.canonical {
+        return Ok(());
+    }
+    let err = OrionKmerError::NonCanonicalDatabase(path.to_path_buf());
+    if strict {
+        return Err(err.into());
+    }
+    warn!("{}; results may be wrong (use --strict to make this an error)", err);
+    Ok(())
+}
+
+/// Checks that `other` (read from `path`) stores canonical k-mers with the same policy as
+/// `first`, so k-mers canonicalized for one can be looked up in the other.
+pub fn check_canonical_policy(first: &KmerDbV2, other: &KmerDbV2, path: &Path) -> Result<()> {
+    if first.canonical_policy == other.canonical_policy {
+        return Ok(());
+    }
+    Err(OrionKmerError::CanonicalPolicyMismatch(first.canonical_policy, other.canonical_policy, path.to_path_buf()).into())
+}
+
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
 use psutil::process::Process;
-use std::time::Instant;
+use serde::Serialize;
+use std::{
+    cell::RefCell,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc, Mutex, OnceLock,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+// Destination for JSON progress events; when set, progress bars are not drawn.
+static PROGRESS_JSON_SINK: OnceLock<Mutex<Box<dyn Write + Send>>> = OnceLock::new();
+const PROGRESS_JSON_INTERVAL: Duration = Duration::from_millis(500);
+
+/// One line of `--progress-json` output.
+#[derive(Serialize)]
+struct ProgressEvent<'a> {
+    task: &'a str,
+    pos: u64,
+    len: u64, // 0 for tasks of unknown length
+    elapsed_ms: u64,
+    done: bool,
+}
+
+/// Makes `track_progress_and_resources` emit newline-delimited JSON progress events to
+/// `path` (`-` for stderr) instead of drawing progress bars. Only the first call has an effect.
+pub fn init_progress_json(path: &Path) -> Result<()> {
+    let sink: Box<dyn Write + Send> = if path == Path::new("-") {
+        Box::new(io::stderr())
+    } else {
+        Box::new(File::create(path).with_context(|| {
+            format!("Failed to create progress JSON file: {:?}", path)
+        })?)
+    };
+    if PROGRESS_JSON_SINK.set(Mutex::new(sink)).is_err() {
+        debug!("JSON progress output already initialized; ignoring {:?}", path);
+    }
+    Ok(())
+}
+
+// The --profile-memory TSV that tasks append their RSS samples to, and how often they sample.
+static MEMORY_PROFILE_SINK: OnceLock<Mutex<Box<dyn Write + Send>>> = OnceLock::new();
+const MEMORY_PROFILE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Makes `track_progress_and_resources` sample the resident set size every second while each
+/// task runs and write the samples to `path` as TSV rows of `unix_time_ms`, `task`,
+/// `elapsed_ms` and `rss_bytes`. Only the first call has an effect.
+pub fn init_memory_profile(path: &Path) -> Result<()> {
+    let mut file = File::create(path).with_context(|| format!("Failed to create memory profile file: {:?}", path))?;
+    writeln!(file, "unix_time_ms\ttask\telapsed_ms\trss_bytes")
+        .with_context(|| format!("Failed to write memory profile file: {:?}", path))?;
+    if MEMORY_PROFILE_SINK.set(Mutex::new(Box::new(file))).is_err() {
+        debug!("Memory profile already initialized; ignoring {:?}", path);
+    }
+    Ok(())
+}
+
+// Writes one --profile-memory sample of the current process.
+fn write_memory_sample(sink: &Mutex<Box<dyn Write + Send>>, process: &Process, task_description: &str, start_time: Instant) {
+    let rss_bytes = match process.memory_info() {
+        Ok(mem_info) => mem_info.rss(),
+        Err(e) => {
+            debug!("Failed to get memory info: {}", e);
+            return;
+        }
+    };
+    let unix_time_ms = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |now| now.as_millis());
+    let mut writer = sink.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    // Like progress reporting, memory profiling must never fail the task itself
+    let written = writeln!(
+        writer,
+        "{}\t{}\t{}\t{}",
+        unix_time_ms,
+        task_description,
+        start_time.elapsed().as_millis(),
+        rss_bytes
+    )
+    .and_then(|_| writer.flush());
+    if let Err(e) = written {
+        debug!("Failed to write memory sample: {}", e);
+    }
+}
+
+// Samples memory every MEMORY_PROFILE_INTERVAL until `stop` is dropped, then once more.
+fn sample_memory(sink: &Mutex<Box<dyn Write + Send>>, task_description: &str, start_time: Instant, stop: mpsc::Receiver<()>) {
+    let process = match Process::current() {
+        Ok(process) => process,
+        Err(e) => {
+            debug!("Failed to get current process for memory profiling: {}", e);
+            return;
+        }
+    };
+    loop {
+        write_memory_sample(sink, &process, task_description, start_time);
+        if !matches!(stop.recv_timeout(MEMORY_PROFILE_INTERVAL), Err(mpsc::RecvTimeoutError::Timeout)) {
+            break;
+        }
+    }
+    write_memory_sample(sink, &process, task_description, start_time);
+}
+
+// Number of decimal digits for floating-point output fields, set by the global `--precision`.
+static OUTPUT_PRECISION: OnceLock<usize> = OnceLock::new();
+
+/// Sets the number of decimal digits used by `format_float`/`round_float`. Only the first call has an effect.
+pub fn init_output_precision(digits: usize) {
+    if OUTPUT_PRECISION.set(digits).is_err() {
+        debug!("Output precision already initialized; ignoring {}", digits);
+    }
+}
+
+/// Formats `value` with the configured precision, or `default_digits` when `--precision` was not given.
+pub fn format_float(value: f64, default_digits: usize) -> String {
+    format!("{:.*}", *OUTPUT_PRECISION.get().unwrap_or(&default_digits), value)
+}
+
+/// Rounds `value` to the configured precision; unchanged when `--precision` was not given.
+pub fn round_float(value: f64) -> f64 {
+    match OUTPUT_PRECISION.get() {
+        Some(&digits) => format!("{:.*}", digits, value).parse().unwrap_or(value),
+        None => value,
+    }
+}
+
+// Set by the global `--profile`: commands then print the wall time spent in each phase.
+static PROFILE_PHASES: OnceLock<bool> = OnceLock::new();
+
+/// Enables the `PhaseTimer` breakdowns printed by commands.
+pub fn init_profile() {
+    let _ = PROFILE_PHASES.set(true);
+}
+
+/// Wall time spent in each phase of a command (parsing, k-mer extraction, set operations,
+/// serialization). With `--profile`, `report` prints the breakdown to stderr once the command
+/// has succeeded; otherwise timing is skipped.
+pub struct PhaseTimer {
+    command: &'static str,
+    enabled: bool,
+    started: Instant,
+    // Phases in the order they first ran, with their accumulated time
+    phases: RefCell<Vec<(&'static str, Duration)>>,
+}
+
+impl PhaseTimer {
+    pub fn new(command: &'static str) -> Self {
+        PhaseTimer {
+            command,
+            enabled: PROFILE_PHASES.get().copied().unwrap_or(false),
+            started: Instant::now(),
+            phases: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Adds `elapsed` to `phase`'s total.
+    pub fn add(&self, phase: &'static str, elapsed: Duration) {
+        if !self.enabled {
+            return;
+        }
+        let mut phases = self.phases.borrow_mut();
+        match phases.iter_mut().find(|(name, _)| *name == phase) {
+            Some((_, total)) => *total += elapsed,
+            None => phases.push((phase, elapsed)),
+        }
+    }
+
+    /// Runs `f`, counting its time towards `phase`. For phases interleaved in a loop.
+    pub fn measure<T>(&self, phase: &'static str, f: impl FnOnce() -> T) -> T {
+        if !self.enabled {
+            return f();
+        }
+        let phase_start = Instant::now();
+        let result = f();
+        self.add(phase, phase_start.elapsed());
+        result
+    }
+
+    /// Counts the time until the returned guard is dropped towards `phase`.
+    pub fn phase(&self, phase: &'static str) -> PhaseGuard<'_> {
+        PhaseGuard {
+            timer: self,
+            phase,
+            started: Instant::now(),
+        }
+    }
+
+    /// Prints the breakdown of the phases timed so far, with the unattributed rest as `other`.
+    pub fn report(&self) {
+        if !self.enabled {
+            return;
+        }
+        let total = self.started.elapsed();
+        let phases = self.phases.borrow();
+        let measured: Duration = phases.iter().map(|(_, elapsed)| *elapsed).sum();
+        eprintln!("Phase breakdown for {} ({:.3}s total):", self.command, total.as_secs_f64());
+        let other = ("other", total.saturating_sub(measured));
+        for (name, elapsed) in phases.iter().chain(std::iter::once(&other)) {
+            eprintln!(
+                "  {:<20} {:>10.3}s {:>6.1}%",
+                name,
+                elapsed.as_secs_f64(),
+                100.0 * elapsed.as_secs_f64() / total.as_secs_f64().max(f64::EPSILON)
+            );
+        }
+    }
+}
+
+/// Times a phase of a `PhaseTimer` until dropped.
+pub struct PhaseGuard<'a> {
+    timer: &'a PhaseTimer,
+    phase: &'static str,
+    started: Instant,
+}
+
+impl Drop for PhaseGuard<'_> {
+    fn drop(&mut self) {
+        self.timer.add(self.phase, self.started.elapsed());
+    }
+}
+
+// Parser forced by the global `--assume-format`, bypassing FASTA/FASTQ detection.
+static ASSUME_FORMAT: OnceLock<SequenceFormat> = OnceLock::new();
+
+/// Sets the parser used by `open_fastx_reader`. Only the first call has an effect.
+pub fn init_assume_format(format: SequenceFormat) {
+    if ASSUME_FORMAT.set(format).is_err() {
+        debug!("Assumed input format already initialized; ignoring {:?}", format);
+    }
+}
+
+// Leading bytes of the compressed streams needletail decompresses itself (gzip, bzip2, xz)
+const NEEDLETAIL_COMPRESSION_MAGICS: [&[u8]; 3] = [&[0x1f, 0x8b], b"BZ", &[0xfd, b'7']];
+
+/// Opens a FASTA/FASTQ parser over `reader`, read from `source_path`, like
+/// `needletail::parse_fastx_reader`. With `--assume-format`, uncompressed input is parsed in
+/// that format without detection. Compressed input (by extension of `source_path`, or by the
+/// stream's leading bytes) is detected as usual, as are the reads decoded from BAM/CRAM files.
+pub fn open_fastx_reader<'a, R: Read + Send + 'a>(
+    reader: R,
+    source_path: &Path,
+) -> Result<Box<dyn FastxReader + 'a>, ParseError> {
+    let Some(format) = ASSUME_FORMAT.get() else {
+        return parse_fastx_reader(reader);
+    };
+    let compressed_extension = matches!(
+        get_extension(source_path).as_deref(),
+        Some("gz" | "tgz" | "bgz" | "bz2" | "xz" | "zst" | "zstd" | "bam" | "cram")
+    );
+    let mut buf_reader = BufReader::new(reader);
+    let head = buf_reader.fill_buf()?;
+    if compressed_extension
+        || NEEDLETAIL_COMPRESSION_MAGICS
+            .iter()
+            .any(|magic| head.starts_with(magic))
+    {
+        debug!("Detecting the format of compressed input {:?} despite --assume-format", source_path);
+        return parse_fastx_reader(buf_reader);
+    }
+    Ok(match format {
+        SequenceFormat::Fasta => Box::new(FastaReader::new(buf_reader)),
+        SequenceFormat::Fastq => Box::new(FastqReader::new(buf_reader)),
+    })
+}
+
+fn emit_progress_event(
+    sink: &Mutex<Box<dyn Write + Send>>,
+    task_description: &str,
+    pb: &ProgressBar,
+    start_time: Instant,
+    done: bool,
+) {
+    let event = ProgressEvent {
+        task: task_description,
+        pos: pb.position(),
+        len: pb.length().unwrap_or(0),
+        elapsed_ms: start_time.elapsed().as_millis() as u64,
+        done,
+    };
+    let mut writer = sink.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    // Progress reporting must never fail the task itself
+    let written = serde_json::to_writer(&mut *writer, &event)
+        .map_err(io::Error::from)
+        .and_then(|_| writer.write_all(b"\n"))
+        .and_then(|_| writer.flush());
+    if let Err(e) = written {
+        debug!("Failed to write progress event: {}", e);
+    }
+}
 
 /// Wraps a function to provide progress tracking, execution time, and max RAM usage.
 pub fn track_progress_and_resources<F, R>(
@@ -70,7 +937,11 @@ where
     info!("Starting task: {}", task_description);
     let start_time = Instant::now();
 
-    let pb = ProgressBar::new(total_items);
+    let progress_json_sink = PROGRESS_JSON_SINK.get();
+    let pb = match progress_json_sink {
+        Some(_) => ProgressBar::with_draw_target(Some(total_items), ProgressDrawTarget::hidden()),
+        None => ProgressBar::new(total_items),
+    };
     pb.set_style(
         ProgressStyle::default_bar()
             .template(
@@ -83,7 +954,42 @@ where
             .progress_chars("#>-"),
     );
 
-    let result = func(&pb);
+    let memory_profile_sink = MEMORY_PROFILE_SINK.get();
+    let result = if progress_json_sink.is_none() && memory_profile_sink.is_none() {
+        func(&pb)
+    } else {
+        // Background threads report progress and sample memory periodically until the task
+        // returns; each stops once its sender is dropped, and is joined with the scope
+        let result = thread::scope(|scope| {
+            let mut stop_senders = Vec::new();
+            if let Some(sink) = progress_json_sink {
+                let (stop_tx, stop_rx) = mpsc::channel::<()>();
+                stop_senders.push(stop_tx);
+                let ticker_pb = pb.clone();
+                scope.spawn(move || loop {
+                    emit_progress_event(sink, task_description, &ticker_pb, start_time, false);
+                    if !matches!(
+                        stop_rx.recv_timeout(PROGRESS_JSON_INTERVAL),
+                        Err(mpsc::RecvTimeoutError::Timeout)
+                    ) {
+                        break;
+                    }
+                });
+            }
+            if let Some(sink) = memory_profile_sink {
+                let (stop_tx, stop_rx) = mpsc::channel::<()>();
+                stop_senders.push(stop_tx);
+                scope.spawn(move || sample_memory(sink, task_description, start_time, stop_rx));
+            }
+            let result = func(&pb);
+            drop(stop_senders);
+            result
+        });
+        if let Some(sink) = progress_json_sink {
+            emit_progress_event(sink, task_description, &pb, start_time, true);
+        }
+        result
+    };
 
     pb.finish_with_message(format!("{} completed.", task_description));
 
@@ -111,6 +1017,15 @@ where
     result
 }
 
+// No 7-Zip codec is built in; reject `.7z` paths rather than treating them as uncompressed
+fn sevenz_unsupported(path: &Path) -> anyhow::Error {
+    OrionKmerError::Generic(format!(
+        "7z archives are not supported for {:?}; extract them first, or use .xz, which uses the same LZMA compression",
+        path
+    ))
+    .into()
+}
+
 // Helper function to get file extension as lowercase string
 fn get_extension(path: &Path) -> Option<String> {
     path.extension()
@@ -118,8 +1033,254 @@ fn get_extension(path: &Path) -> Option<String> {
         .map(|s| s.to_lowercase())
 }
 
+// Records between debug progress logs while reading sequences, set by the global `--progress-interval`.
+static PROGRESS_INTERVAL: OnceLock<usize> = OnceLock::new();
+const DEFAULT_PROGRESS_INTERVAL: usize = 100_000;
+
+/// Sets how many records pass between progress logs. Only the first call has an effect.
+pub fn init_progress_interval(records: usize) -> Result<()> {
+    if records == 0 {
+        return Err(OrionKmerError::Generic("--progress-interval must be at least 1".to_string()).into());
+    }
+    if PROGRESS_INTERVAL.set(records).is_err() {
+        debug!("Progress interval already initialized; ignoring {}", records);
+    }
+    Ok(())
+}
+
+/// Whether the `record_count`-th record read should be logged as progress.
+pub fn is_progress_record(record_count: usize) -> bool {
+    record_count.is_multiple_of(*PROGRESS_INTERVAL.get().unwrap_or(&DEFAULT_PROGRESS_INTERVAL))
+}
+
+// Set by the global `--max-records`: input records read so far count against this limit.
+static MAX_RECORDS: OnceLock<u64> = OnceLock::new();
+static RECORDS_READ: AtomicU64 = AtomicU64::new(0);
+
+/// Limits how many input records commands read in total. Only the first call has an effect.
+pub fn init_max_records(records: u64) -> Result<()> {
+    if records == 0 {
+        return Err(OrionKmerError::Generic("--max-records must be at least 1".to_string()).into());
+    }
+    if MAX_RECORDS.set(records).is_err() {
+        debug!("Record limit already initialized; ignoring {}", records);
+    }
+    Ok(())
+}
+
+/// Counts one record read from an input against `--max-records`. Returns false once the limit
+/// has been reached, and the caller should then stop reading this and any later inputs.
+pub fn take_input_record() -> bool {
+    let Some(&limit) = MAX_RECORDS.get() else {
+        return true;
+    };
+    let records_read = RECORDS_READ.fetch_add(1, Ordering::Relaxed);
+    if records_read == limit {
+        info!("Reached --max-records {}; ignoring the remaining input records", limit);
+    }
+    records_read < limit
+}
+
+/// Starts counting `--max-records` from zero again, for commands that read their inputs once
+/// per pass (e.g. `build --k-list`).
+pub fn restart_record_limit() {
+    RECORDS_READ.store(0, Ordering::Relaxed);
+}
+
+// Threads for xz/zstd decompression, set by the global `--decompress-threads`.
+static DECOMPRESS_THREADS: OnceLock<usize> = OnceLock::new();
+
+// Size of the decompressed chunks a background decoder hands to its reader.
+const READ_AHEAD_CHUNK_SIZE: usize = 1 << 20;
+
+/// Sets the number of threads for xz/zstd decompression. Only the first call has an effect.
+pub fn init_decompress_threads(threads: usize) {
+    if DECOMPRESS_THREADS.set(threads).is_err() {
+        debug!("Decompression threads already initialized; ignoring {}", threads);
+    }
+}
+
+// The xz2 and zstd decoders are single-threaded, so with `--decompress-threads` above 1 a
+// decoder runs on its own thread instead, up to that many chunks ahead of the parser.
+// BAM/CRAM decoding threads hand their reads over the same way.
+pub(crate) struct ReadAheadReader {
+    chunks: mpsc::Receiver<io::Result<Vec<u8>>>,
+    chunk: Vec<u8>,
+    pos: usize,
+}
+
+impl ReadAheadReader {
+    fn spawn<R: Read + Send + 'static>(mut decoder: R, chunks_ahead: usize) -> Self {
+        let (sender, receiver) = mpsc::sync_channel(chunks_ahead);
+        thread::spawn(move || {
+            loop {
+                let mut chunk = vec![0; READ_AHEAD_CHUNK_SIZE];
+                let result = match decoder.read(&mut chunk) {
+                    Ok(0) => break,
+                    Ok(len) => {
+                        chunk.truncate(len);
+                        Ok(chunk)
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => Err(e),
+                };
+                let failed = result.is_err();
+                // Stop once the reader is dropped or after reporting an error
+                if sender.send(result).is_err() || failed {
+                    break;
+                }
+            }
+        });
+        ReadAheadReader::from_chunks(receiver)
+    }
+
+    /// Reads the chunks sent to `chunks` in order, ending when the sender is dropped.
+    pub(crate) fn from_chunks(chunks: mpsc::Receiver<io::Result<Vec<u8>>>) -> Self {
+        ReadAheadReader {
+            chunks,
+            chunk: Vec::new(),
+            pos: 0,
+        }
+    }
+}
+
+impl Read for ReadAheadReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.chunk.len() {
+            match self.chunks.recv() {
+                Ok(chunk) => {
+                    self.chunk = chunk?;
+                    self.pos = 0;
+                }
+                // The decoder thread finished the stream
+                Err(mpsc::RecvError) => return Ok(0),
+            }
+        }
+        let len = buf.len().min(self.chunk.len() - self.pos);
+        buf[..len].copy_from_slice(&self.chunk[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+// Wraps an xz/zstd decoder for reading, on a background thread with `--decompress-threads` > 1.
+fn buffered_decoder<R: Read + Send + 'static>(decoder: R) -> Box<dyn BufRead + Send> {
+    match DECOMPRESS_THREADS.get().copied().unwrap_or(1) {
+        0 | 1 => Box::new(BufReader::new(decoder)),
+        threads => Box::new(BufReader::new(ReadAheadReader::spawn(decoder, threads))),
+    }
+}
+
+// Set by the global `--lenient-gzip`: trailing bytes after the last gzip member end the input.
+static LENIENT_GZIP: OnceLock<bool> = OnceLock::new();
+
+/// Makes .gz inputs stop at trailing non-gzip bytes instead of failing (`--lenient-gzip`).
+pub fn init_lenient_gzip() {
+    let _ = LENIENT_GZIP.set(true);
+}
+
+fn lenient_gzip() -> bool {
+    LENIENT_GZIP.get().copied().unwrap_or(false)
+}
+
+// Counts the bytes read from a file, so offsets into compressed input can be reported.
+struct CountingReader<R> {
+    inner: R,
+    bytes_read: u64,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        self.bytes_read += len as u64;
+        Ok(len)
+    }
+}
+
+// Reads concatenated gzip members like `MultiGzDecoder`, except that bytes after a member that
+// do not start another one end the stream with a warning instead of a header error.
+struct LenientGzDecoder {
+    path: PathBuf,
+    member: Option<GzDecoder<BufReader<CountingReader<File>>>>,
+}
+
+impl LenientGzDecoder {
+    fn new(file: File, path: &Path) -> Self {
+        let reader = BufReader::new(CountingReader {
+            inner: file,
+            bytes_read: 0,
+        });
+        LenientGzDecoder {
+            path: path.to_path_buf(),
+            member: Some(GzDecoder::new(reader)),
+        }
+    }
+}
+
+impl Read for LenientGzDecoder {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let Some(member) = &mut self.member else {
+                return Ok(0);
+            };
+            let len = member.read(buf)?;
+            if len > 0 || buf.is_empty() {
+                return Ok(len);
+            }
+            // The member ended: the bufread decoder has consumed exactly its bytes
+            let mut reader = self.member.take().unwrap().into_inner();
+            let next = reader.fill_buf()?;
+            let buffered = next.len() as u64;
+            if buffered == 0 {
+                return Ok(0);
+            }
+            if next[0] == 0x1f && next.get(1).is_none_or(|&byte| byte == 0x8b) {
+                self.member = Some(GzDecoder::new(reader));
+                continue;
+            }
+            let offset = reader.get_ref().bytes_read - buffered;
+            warn!(
+                "--lenient-gzip: {:?} has bytes that are not gzip data at byte offset {}; stopping at the end of the last valid gzip member",
+                self.path, offset
+            );
+            return Ok(0);
+        }
+    }
+}
+
+/// Opens a sequence file for `open_fastx_reader`. Gzip and xz files are normally left to
+/// needletail to decompress; zstd and LZ4 files (which needletail cannot read) and bzip2 files
+/// (which may hold several streams) are always decompressed here, with `--decompress-threads` above 1
+/// xz and zstd files are decompressed here on a background thread instead, and with `--lenient-gzip` gzip files are
+/// decompressed here so trailing junk ends the input. BAM and CRAM files are always decoded
+/// here, into FASTQ (or FASTA) text. 7z files are rejected, as by
+/// `get_decompressed_input_reader`.
+pub fn get_sequence_file_reader(path: &Path) -> Result<Box<dyn BufRead + Send>> {
+    let threaded = DECOMPRESS_THREADS.get().is_some_and(|&threads| threads > 1);
+    match get_extension(path).as_deref() {
+        Some("7z") => return Err(sevenz_unsupported(path)),
+        Some("bam") => return alignment_reads_reader(path, AlignmentFormat::Bam),
+        Some("cram") => return alignment_reads_reader(path, AlignmentFormat::Cram),
+        Some("zst" | "zstd" | "bz2" | "lz4") => return get_decompressed_input_reader(path),
+        Some("xz") if threaded => return get_decompressed_input_reader(path),
+        Some("gz" | "bgz") if lenient_gzip() => return get_decompressed_input_reader(path),
+        _ => {}
+    }
+    Ok(Box::new(get_buffered_file_reader(path)?))
+}
+
+/// Opens the FASTA/FASTQ records of a sequence file: `get_sequence_file_reader` followed by
+/// `open_fastx_reader`, so every command reads the same compressions, BAM/CRAM input and
+/// `--assume-format`, `--decompress-threads` and `--lenient-gzip` settings.
+pub fn open_sequence_reader(path: &Path) -> Result<Box<dyn FastxReader>> {
+    // Not wrapped in further context: the reader's errors already name the file
+    let reader = get_sequence_file_reader(path)?;
+    open_fastx_reader(reader, path).with_context(|| format!("Failed to parse FASTA/Q content from: {:?}", path))
+}
+
 /// Opens a file for reading, handling decompression based on file extension.
-/// Supported extensions: .gz, .xz, .zst.
+/// Supported extensions: .gz (and BGZF .bgz), .bz2, .xz, .zst, .lz4; the reads of .bam and .cram files are
+/// decoded into FASTQ (or FASTA) text.
 /// Returns a `Box<dyn BufRead + Send>` for generic reading.
 /// This reader provides a decompressed stream.
 pub fn get_decompressed_input_reader(path: &Path) -> Result<Box<dyn BufRead + Send>> {
@@ -128,22 +1289,36 @@ pub fn get_decompressed_input_reader(path: &Path) -> Result<Box<dyn BufRead + Se
     let extension = get_extension(path);
 
     match extension.as_deref() {
-        Some("gz") => {
+        // BGZF is a series of gzip members, so the multi-member decoder reads it too
+        Some("gz") | Some("tgz") | Some("bgz") => {
             info!("Reading GZipped file: {:?}", path);
+            if lenient_gzip() {
+                return Ok(Box::new(BufReader::new(LenientGzDecoder::new(file, path))));
+            }
             let decoder = MultiGzDecoder::new(file);
             Ok(Box::new(BufReader::new(decoder)))
         }
+        Some("bz2") => {
+            info!("Reading BZip2 compressed file: {:?}", path);
+            Ok(buffered_decoder(MultiBzDecoder::new(file)))
+        }
         Some("xz") => {
             info!("Reading XZ compressed file: {:?}", path);
-            let decoder = XzDecoder::new(file);
-            Ok(Box::new(BufReader::new(decoder)))
+            Ok(buffered_decoder(XzDecoder::new(file)))
         }
         Some("zst") | Some("zstd") => {
             info!("Reading Zstandard compressed file: {:?}", path);
             let decoder = ZstdDecoder::new(file)
                 .with_context(|| format!("Failed to create ZstdDecoder for {:?}", path))?;
-            Ok(Box::new(BufReader::new(decoder)))
+            Ok(buffered_decoder(decoder))
+        }
+        Some("lz4") => {
+            info!("Reading LZ4 compressed file: {:?}", path);
+            Ok(buffered_decoder(Lz4FrameDecoder::new(file)))
         }
+        Some("7z") => Err(sevenz_unsupported(path)),
+        Some("bam") => alignment_reads_reader(path, AlignmentFormat::Bam),
+        Some("cram") => alignment_reads_reader(path, AlignmentFormat::Cram),
         _ => {
             info!("Reading uncompressed file: {:?}", path);
             Ok(Box::new(BufReader::new(file)))
@@ -151,6 +1326,135 @@ pub fn get_decompressed_input_reader(path: &Path) -> Result<Box<dyn BufRead + Se
     }
 }
 
+/// Returns true if the path names a tar archive, optionally compressed
+/// (`.tar`, `.tar.gz`, `.tgz`, `.tar.xz`, `.tar.zst`).
+pub fn is_tar_archive(path: &Path) -> bool {
+    let name = path
+        .file_name()
+        .map(|os_str| os_str.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    name.ends_with(".tgz")
+        || [".tar", ".tar.gz", ".tar.xz", ".tar.zst", ".tar.zstd"]
+            .iter()
+            .any(|ext| name.ends_with(ext))
+}
+
+// Chunks of an entry's data the tar reading thread decodes ahead of `for_each_tar_entry`'s caller.
+const TAR_ENTRY_CHUNKS_AHEAD: usize = 4;
+
+/// Streams every regular-file entry of a (possibly compressed) tar archive to `func`,
+/// together with the entry's path inside the archive.
+/// The reader passed to `func` is limited to the entry's data; anything `func` leaves unread is skipped.
+/// GNU long names and pax headers are supported; directories, links and other special entries
+/// are skipped, and a truncated archive is an error.
+///
+/// The entries of a `tar::Archive` borrow it and are not `Send`, while the FASTA/FASTQ parser
+/// needs a `Send` reader, so the archive is read on its own thread and each entry's data is
+/// handed over in chunks, like a background decompressor's (see `ReadAheadReader`).
+pub fn for_each_tar_entry<F>(path: &Path, mut func: F) -> Result<()>
+where
+    F: FnMut(&str, &mut (dyn Read + Send)) -> Result<()>,
+{
+    let reader = get_decompressed_input_reader(path)
+        .with_context(|| format!("Failed to get input reader for tar archive: {:?}", path))?;
+    let (entry_sender, entry_receiver) = mpsc::sync_channel::<io::Result<(String, mpsc::Receiver<io::Result<Vec<u8>>>)>>(0);
+    let archive_path = path.to_path_buf();
+    thread::spawn(move || {
+        let mut archive = tar::Archive::new(reader);
+        let entries = match archive.entries() {
+            Ok(entries) => entries,
+            Err(e) => {
+                let _ = entry_sender.send(Err(e));
+                return;
+            }
+        };
+        for entry in entries {
+            let mut entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    let _ = entry_sender.send(Err(e));
+                    return;
+                }
+            };
+            let name = String::from_utf8_lossy(&entry.path_bytes()).into_owned();
+            match entry.header().entry_type() {
+                tar::EntryType::Regular | tar::EntryType::Continuous => {}
+                other => {
+                    debug!("Skipping tar entry of type {:?} in {:?}", other, archive_path);
+                    continue;
+                }
+            }
+            let name_for_errors = name.clone();
+            let (chunk_sender, chunk_receiver) = mpsc::sync_channel(TAR_ENTRY_CHUNKS_AHEAD);
+            // Stop once the caller has returned
+            if entry_sender.send(Ok((name, chunk_receiver))).is_err() {
+                return;
+            }
+            let mut remaining = entry.size();
+            loop {
+                let mut chunk = vec![0; READ_AHEAD_CHUNK_SIZE];
+                let result = match entry.read(&mut chunk) {
+                    Ok(0) if remaining == 0 => break,
+                    // The archive ends inside the entry's data
+                    Ok(0) => Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        format!("tar archive ends {} bytes before the end of entry '{}'", remaining, name_for_errors),
+                    )),
+                    Ok(len) => {
+                        remaining = remaining.saturating_sub(len as u64);
+                        chunk.truncate(len);
+                        Ok(chunk)
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => Err(e),
+                };
+                let failed = result.is_err();
+                if failed {
+                    let _ = chunk_sender.send(result);
+                    return;
+                }
+                // The caller left the rest of the entry unread; the archive skips it
+                if chunk_sender.send(result).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+
+    for entry in entry_receiver {
+        let (name, chunks) = entry.with_context(|| format!("Failed to read tar archive {:?}", path))?;
+        debug!("Reading tar entry '{}' from {:?}", name, path);
+        let mut entry_reader = ReadAheadReader::from_chunks(chunks);
+        func(&name, &mut entry_reader)?;
+    }
+    Ok(())
+}
+
+/// Replaces every character of `name` that is not an ASCII letter or digit with `_`, so
+/// reference names from file paths or headers are safe identifiers in TSV output
+/// (`build --sanitize-names`).
+pub fn sanitize_reference_name(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+/// Counts the FASTA/FASTQ records of `path` in a first pass, so a progress bar over the
+/// records can show a percentage and ETA. Returns None without reading anything when `path` is
+/// not a regular file (e.g. a pipe or /dev/stdin), since it could not be read a second time.
+pub fn prescan_record_count(path: &Path) -> Result<Option<u64>> {
+    if !fs::metadata(path).is_ok_and(|metadata| metadata.is_file()) {
+        return Ok(None);
+    }
+    let mut reader = open_sequence_reader(path)?;
+    let mut record_count = 0;
+    while let Some(record) = reader.next() {
+        record.with_context(|| format!("Error reading record while prescanning: {:?}", path))?;
+        record_count += 1;
+    }
+    Ok(Some(record_count))
+}
+
 /// Opens a file and returns a BufReader<File>.
 /// This reader is Send + Sync + BufRead + Read.
 /// It does NOT perform any decompression; it provides the raw (potentially compressed) file stream.
@@ -160,40 +1464,464 @@ pub fn get_buffered_file_reader(path: &Path) -> Result<BufReader<File>> {
     Ok(BufReader::new(file))
 }
 
+// Threads compressing .gz output, set by the global `--compress-threads`.
+static COMPRESS_THREADS: OnceLock<usize> = OnceLock::new();
+
+/// Sets the number of threads for gzip output compression. Only the first call has an effect.
+pub fn init_compress_threads(threads: usize) {
+    if COMPRESS_THREADS.set(threads).is_err() {
+        debug!("Compression threads already initialized; ignoring {}", threads);
+    }
+}
+
+fn compress_threads() -> usize {
+    COMPRESS_THREADS.get().copied().unwrap_or(1)
+}
+
+// The buffered stream behind an `OutputWriter`, one variant per supported compression.
+enum OutputStream {
+    Plain(BufWriter<File>),
+    Gz(BufWriter<GzEncoder<File>>),
+    ParallelGz(BufWriter<ParallelGzWriter<File>>),
+    Bgzf(BufWriter<BgzfWriter<File>>),
+    Bz2(BufWriter<BzEncoder<File>>),
+    Xz(BufWriter<XzEncoder<File>>),
+    Zstd(BufWriter<ZstdEncoder<'static, File>>),
+    Lz4(BufWriter<Lz4FrameEncoder<File>>),
+}
+
+impl OutputStream {
+    // Flushes the buffer and writes the compressed stream's trailer, reporting any error.
+    fn finish(self) -> io::Result<()> {
+        match self {
+            OutputStream::Plain(writer) => {
+                writer.into_inner().map_err(io::IntoInnerError::into_error)?;
+            }
+            OutputStream::Gz(writer) => {
+                writer.into_inner().map_err(io::IntoInnerError::into_error)?.finish()?;
+            }
+            OutputStream::ParallelGz(writer) => {
+                writer.into_inner().map_err(io::IntoInnerError::into_error)?.finish()?;
+            }
+            OutputStream::Bgzf(writer) => {
+                writer.into_inner().map_err(io::IntoInnerError::into_error)?.finish()?;
+            }
+            OutputStream::Bz2(writer) => {
+                writer.into_inner().map_err(io::IntoInnerError::into_error)?.finish()?;
+            }
+            OutputStream::Xz(writer) => {
+                writer.into_inner().map_err(io::IntoInnerError::into_error)?.finish()?;
+            }
+            OutputStream::Zstd(writer) => {
+                writer.into_inner().map_err(io::IntoInnerError::into_error)?.finish()?;
+            }
+            OutputStream::Lz4(writer) => {
+                writer.into_inner().map_err(io::IntoInnerError::into_error)?.finish()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A buffered output file, possibly compressed, created by `get_output_writer`.
+/// Dropping it finishes the stream but ignores errors, so commands should end with
+/// `finalize_writer` to learn whether the output was written completely.
+pub struct OutputWriter {
+    stream: Option<OutputStream>,
+}
+
+impl OutputWriter {
+    fn stream_mut(&mut self) -> &mut dyn Write {
+        match self.stream.as_mut().expect("output stream used after finalize") {
+            OutputStream::Plain(writer) => writer,
+            OutputStream::Gz(writer) => writer,
+            OutputStream::ParallelGz(writer) => writer,
+            OutputStream::Bgzf(writer) => writer,
+            OutputStream::Bz2(writer) => writer,
+            OutputStream::Xz(writer) => writer,
+            OutputStream::Zstd(writer) => writer,
+            OutputStream::Lz4(writer) => writer,
+        }
+    }
+}
+
+impl Write for OutputWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream_mut().write(buf)
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.stream_mut().write_all(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream_mut().flush()
+    }
+}
+
+impl Drop for OutputWriter {
+    fn drop(&mut self) {
+        if let Some(stream) = self.stream.take()
+            && let Err(e) = stream.finish()
+        {
+            warn!("Failed to finish output stream on drop: {}", e);
+        }
+    }
+}
+
+/// Flushes `writer` and finishes its compressed stream (gzip/BGZF/xz/zstd trailer), returning
+/// any error instead of losing it on drop.
+pub fn finalize_writer(mut writer: OutputWriter) -> Result<()> {
+    if let Some(stream) = writer.stream.take() {
+        stream.finish()?;
+    }
+    Ok(())
+}
+
 /// Opens a file for writing, handling compression based on file extension.
-/// Supported extensions: .gz, .xz, .zst.
-/// Returns a `Box<dyn Write>` for generic writing.
-/// Note: The writers are typically `BufWriter`s wrapping compressing encoders.
-pub fn get_output_writer(path: &Path) -> Result<Box<dyn Write>> {
+/// Supported extensions: .gz, .bgz (BGZF, indexable with tabix), .bz2, .xz, .zst, .lz4.
+/// The returned writer is buffered; finish it with `finalize_writer`.
+pub fn get_output_writer(path: &Path) -> Result<OutputWriter> {
+    // Checked before creating the file so no empty `.7z` file is left behind
+    if get_extension(path).as_deref() == Some("7z") {
+        return Err(sevenz_unsupported(path));
+    }
     let file = File::create(path)
         .with_context(|| format!("Failed to create output file: {:?}", path))?;
     let extension = get_extension(path);
 
-    match extension.as_deref() {
+    let stream = match extension.as_deref() {
+        Some("gz") if compress_threads() > 1 => {
+            info!("Writing GZipped file with {} compression threads: {:?}", compress_threads(), path);
+            OutputStream::ParallelGz(BufWriter::new(ParallelGzWriter::new(
+                file,
+                GzCompression::default(),
+                compress_threads(),
+            )))
+        }
         Some("gz") => {
             info!("Writing GZipped file: {:?}", path);
             // BufWriter is recommended by flate2 for performance.
             // The GzEncoder itself is not necessarily buffered internally in the way BufWriter is.
             let encoder = GzEncoder::new(file, GzCompression::default());
-            Ok(Box::new(BufWriter::new(encoder)))
+            OutputStream::Gz(BufWriter::new(encoder))
+        }
+        Some("bgz") => {
+            info!("Writing BGZF compressed file: {:?}", path);
+            OutputStream::Bgzf(BufWriter::new(BgzfWriter::new(file)))
+        }
+        Some("bz2") => {
+            info!("Writing BZip2 compressed file: {:?}", path);
+            OutputStream::Bz2(BufWriter::new(BzEncoder::new(file, BzCompression::default())))
         }
         Some("xz") => {
             info!("Writing XZ compressed file: {:?}", path);
             // XzEncoder is buffered, but wrapping in BufWriter is harmless and consistent.
             let encoder = XzEncoder::new(file, 6); // Compression level 6 is a good default
-            Ok(Box::new(BufWriter::new(encoder)))
+            OutputStream::Xz(BufWriter::new(encoder))
         }
         Some("zst") | Some("zstd") => {
             info!("Writing Zstandard compressed file: {:?}", path);
             // ZstdEncoder benefits from a BufWriter.
             let encoder = ZstdEncoder::new(file, 0) // 0 is default compression level for zstd crate
-                .with_context(|| format!("Failed to create ZstdEncoder for {:?}", path))?
-                .auto_finish(); // Ensures finish is called on drop
-            Ok(Box::new(BufWriter::new(encoder)))
+                .with_context(|| format!("Failed to create ZstdEncoder for {:?}", path))?;
+            OutputStream::Zstd(BufWriter::new(encoder))
+        }
+        Some("lz4") => {
+            info!("Writing LZ4 compressed file: {:?}", path);
+            OutputStream::Lz4(BufWriter::new(Lz4FrameEncoder::new(file)))
         }
         _ => {
             info!("Writing uncompressed file: {:?}", path);
-            Ok(Box::new(BufWriter::new(file)))
+            OutputStream::Plain(BufWriter::new(file))
+        }
+    };
+    Ok(OutputWriter {
+        stream: Some(stream),
+    })
+}
+
+/// Selects FASTA/FASTQ records by their ID (the header up to the first whitespace).
+///
+/// With no include names and no regex every record is selected; otherwise a record must
+/// match an include name or the regex. Excluded names are always rejected. With a subsample,
+/// only records whose ID hashes below the kept fraction are selected.
+#[derive(Debug, Default)]
+pub struct SequenceFilter {
+    include: HashSet<String>,
+    exclude: HashSet<String>,
+    regex: Option<Regex>,
+    // (hash threshold, seed): a record is kept when the hash of its ID is below the threshold
+    subsample: Option<(u64, u64)>,
+}
+
+impl SequenceFilter {
+    pub fn new(include: &[String], exclude: &[String], seq_regex: Option<&str>) -> Result<Self> {
+        let regex = seq_regex
+            .map(|pattern| {
+                Regex::new(pattern)
+                    .with_context(|| format!("Invalid --seq-regex pattern: {}", pattern))
+            })
+            .transpose()?;
+        Ok(SequenceFilter {
+            include: include.iter().cloned().collect(),
+            exclude: exclude.iter().cloned().collect(),
+            regex,
+            subsample: None,
+        })
+    }
+
+    /// Additionally keeps only about `fraction` of the records, chosen by hashing each ID with
+    /// `seed`, so that the same records are selected on every run.
+    pub fn with_subsample(mut self, fraction: Option<f64>, seed: u64) -> Result<Self> {
+        let Some(fraction) = fraction else {
+            return Ok(self);
+        };
+        if !(0.0..=1.0).contains(&fraction) {
+            return Err(OrionKmerError::Generic(format!(
+                "--subsample must be between 0 and 1, got {}",
+                fraction
+            ))
+            .into());
+        }
+        // Scale the fraction to the hash range; 1.0 saturates to u64::MAX
+        let threshold = (fraction * u64::MAX as f64) as u64;
+        self.subsample = Some((threshold, seed));
+        Ok(self)
+    }
+
+    /// Returns true if the record with header `record_id` should be processed.
+    pub fn matches(&self, record_id: &[u8]) -> bool {
+        let header = String::from_utf8_lossy(record_id);
+        let name = header.split_whitespace().next().unwrap_or("");
+        if self.exclude.contains(name) {
+            return false;
+        }
+        if let Some((threshold, seed)) = self.subsample
+            && threshold < u64::MAX
+            && murmur3_x64_128(name.as_bytes(), seed).0 >= threshold
+        {
+            return false;
+        }
+        if self.include.is_empty() && self.regex.is_none() {
+            return true;
+        }
+        self.include.contains(name) || self.regex.as_ref().is_some_and(|re| re.is_match(name))
+    }
+}
+
+/// Rejects reads that are too short or, for FASTQ records, whose mean Phred quality is
+/// too low. FASTA records carry no qualities and always pass the quality check.
+#[derive(Debug, Default)]
+pub struct ReadFilter {
+    pub min_length: usize,
+    pub min_mean_qual: Option<f64>,
+}
+
+impl ReadFilter {
+    /// Returns true if either threshold is set.
+    pub fn is_active(&self) -> bool {
+        self.min_length > 0 || self.min_mean_qual.is_some()
+    }
+
+    /// Returns true if the read passes both thresholds. `qual` holds Phred+33 scores.
+    pub fn passes(&self, seq: &[u8], qual: Option<&[u8]>) -> bool {
+        if seq.len() < self.min_length {
+            return false;
+        }
+        match (self.min_mean_qual, qual) {
+            (Some(min_mean_qual), Some(qual)) if !qual.is_empty() => {
+                let total: u64 = qual.iter().map(|&q| q.saturating_sub(33) as u64).sum();
+                total as f64 / qual.len() as f64 >= min_mean_qual
+            }
+            _ => true,
+        }
+    }
+}
+
+/// Slices `front` bases off the start and `back` bases off the end of a read. Returns `None`
+/// if the read is shorter than the bases to trim.
+pub fn trim_read(seq: &[u8], front: usize, back: usize) -> Option<&[u8]> {
+    let end = seq.len().checked_sub(back)?;
+    (front <= end).then(|| &seq[front..end])
+}
+
+/// Checks a `--trim-polyx` base, returning it as an uppercase byte.
+pub fn polyx_base(base: char) -> Result<u8> {
+    match base.to_ascii_uppercase() {
+        base @ ('A' | 'C' | 'G' | 'T') => Ok(base as u8),
+        _ => Err(OrionKmerError::Generic(format!("--trim-polyx must be one of A, C, G or T, got '{}'", base)).into()),
+    }
+}
+
+/// Cuts a trailing run of `base` (in either case) off `seq` when the run is at least `min_run`
+/// bases long, e.g. the poly-G tails of two-color Illumina chemistry (`--trim-polyx`).
+pub fn trim_polyx(seq: &[u8], base: u8, min_run: usize) -> &[u8] {
+    let run = seq
+        .iter()
+        .rev()
+        .take_while(|b| b.eq_ignore_ascii_case(&base))
+        .count();
+    if run >= min_run.max(1) {
+        &seq[..seq.len() - run]
+    } else {
+        seq
+    }
+}
+
+/// Bases read from an input and how many of them were N or another non-ACGT character,
+/// for input QC.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BaseCounts {
+    pub total_bases: u64,
+    pub ambiguous_bases: u64,
+}
+
+impl BaseCounts {
+    /// Counts the bases of a normalized sequence (upper case, with non-ACGT bases as N or
+    /// kept IUPAC codes).
+    pub fn add_sequence(&mut self, norm_seq: &[u8]) {
+        self.total_bases += norm_seq.len() as u64;
+        self.ambiguous_bases += norm_seq
+            .iter()
+            .filter(|base| !matches!(base, b'A' | b'C' | b'G' | b'T'))
+            .count() as u64;
+    }
+
+    pub fn add(&mut self, other: &BaseCounts) {
+        self.total_bases += other.total_bases;
+        self.ambiguous_bases += other.ambiguous_bases;
+    }
+}
+
+/// Share of a record's characters outside A/C/G/T/U/N above which it is taken for protein or
+/// other non-DNA input.
+pub const MAX_NON_DNA_FRACTION: f64 = 0.5;
+
+/// Records shorter than this are too little evidence to be rejected as non-DNA.
+pub const MIN_NON_DNA_CHECK_LENGTH: usize = 20;
+
+/// Fails when most of a record's characters are not DNA bases, which happens when a protein FASTA
+/// is given by mistake: every window would be skipped, silently leaving no k-mers.
+/// Upper and lower case A/C/G/T/U/N count as DNA; line endings of multi-line FASTA are ignored.
+/// Records of fewer than `MIN_NON_DNA_CHECK_LENGTH` characters always pass.
+pub fn check_dna_record(raw_seq: &[u8], record_id: &[u8], source: &str) -> Result<()> {
+    let mut residues = 0usize;
+    let mut non_dna = 0usize;
+    for &byte in raw_seq {
+        if byte == b'\n' || byte == b'\r' {
+            continue;
+        }
+        residues += 1;
+        if !matches!(byte.to_ascii_uppercase(), b'A' | b'C' | b'G' | b'T' | b'U' | b'N') {
+            non_dna += 1;
+        }
+    }
+    if residues >= MIN_NON_DNA_CHECK_LENGTH && non_dna as f64 > MAX_NON_DNA_FRACTION * residues as f64 {
+        return Err(OrionKmerError::Generic(format!(
+            "Input appears to be protein or non-DNA: {} of {} characters of record '{}' in {} are not A/C/G/T/U/N. Pass --allow-non-dna to process it anyway",
+            non_dna,
+            residues,
+            String::from_utf8_lossy(record_id).trim(),
+            source
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+/// Logs the bases read from `source` and how many of them were N/ambiguous.
+pub fn report_base_counts(source: &str, counts: &BaseCounts) {
+    let ambiguous_percent = if counts.total_bases == 0 {
+        0.0
+    } else {
+        100.0 * counts.ambiguous_bases as f64 / counts.total_bases as f64
+    };
+    info!(
+        "Read {} bases from {}, {} ({:.2}%) of them N/ambiguous",
+        counts.total_bases, source, counts.ambiguous_bases, ambiguous_percent
+    );
+}
+
+/// Logs how many of the `total_records` records read from `source` were skipped because they
+/// are shorter than `k` and so contain no k-mer, warning when that left no record to use.
+pub fn report_short_records(source: &str, short_records: usize, total_records: usize, k: u8) {
+    if short_records > 0 && short_records == total_records {
+        warn!(
+            "All {} records in {} are shorter than k={}; no k-mers were extracted from them",
+            total_records, source, k
+        );
+    } else {
+        info!(
+            "Skipped {} of {} records shorter than k={} in {}",
+            short_records, total_records, k, source
+        );
+    }
+}
+
+/// Restricts loaded databases to a subset of their references: only the `--only-reference`
+/// names when any are given, minus the `--exclude-reference` names. In a database built with
+/// `--normalize-ref-names`, the names are matched after the same normalization.
+#[derive(Debug, Default)]
+pub struct ReferenceSelection {
+    only: HashSet<String>,
+    exclude: HashSet<String>,
+}
+
+impl ReferenceSelection {
+    pub fn new(only: &[String], exclude: &[String]) -> Self {
+        ReferenceSelection {
+            only: only.iter().cloned().collect(),
+            exclude: exclude.iter().cloned().collect(),
+        }
+    }
+
+    /// Returns true if any reference names were given.
+    pub fn is_active(&self) -> bool {
+        !self.only.is_empty() || !self.exclude.is_empty()
+    }
+
+    /// Drops the unselected references from `kmer_db`, adding the requested names it contains
+    /// to `found` so that `check_found` can report names missing from every database.
+    pub fn apply(&self, kmer_db: &mut KmerDbV2, found: &mut HashSet<String>) {
+        if !self.is_active() {
+            return;
+        }
+        let stored_name = |name: &String| match kmer_db.name_normalization {
+            Some(name_normalization) => name_normalization.apply(name),
+            None => name.clone(),
+        };
+        for name in self.only.iter().chain(&self.exclude) {
+            if kmer_db.references.contains_key(&stored_name(name)) {
+                found.insert(name.clone());
+            }
+        }
+        let only: HashSet<String> = self.only.iter().map(stored_name).collect();
+        let exclude: HashSet<String> = self.exclude.iter().map(stored_name).collect();
+        let before = kmer_db.num_references();
+        let keep = |name: &String| (only.is_empty() || only.contains(name)) && !exclude.contains(name);
+        kmer_db.references.retain(|name, _| keep(name));
+        kmer_db.positions.retain(|name, _| keep(name));
+        kmer_db.reference_lengths.retain(|name, _| keep(name));
+        info!(
+            "Using {} of {} database references after reference selection",
+            kmer_db.num_references(),
+            before
+        );
+    }
+
+    /// Fails if a requested reference name was not found by any `apply` call.
+    pub fn check_found(&self, found: &HashSet<String>) -> Result<()> {
+        let mut missing: Vec<&String> = self
+            .only
+            .iter()
+            .chain(&self.exclude)
+            .filter(|name| !found.contains(*name))
+            .collect();
+        missing.sort();
+        match missing.first() {
+            Some(name) => Err(OrionKmerError::ReferenceNotFound(name.to_string()).into()),
+            None => Ok(()),
         }
     }
 }
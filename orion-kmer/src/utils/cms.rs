@@ -0,0 +1,174 @@
+//! Approximate most frequent k-mers in bounded memory, for `count --approx-top`.
+//!
+//! A Count-Min Sketch keeps `depth` rows of `width` counters; each k-mer increments one counter
+//! per row, chosen by its hash, and its estimated count is the smallest of those counters. The
+//! estimate never undercounts, and overcounts by at most e/width of the total number of k-mers
+//! added with probability 1 - e^-depth. Counters are updated conservatively (only those at the
+//! current minimum grow), which tightens the estimates further. Alongside the sketch, the k-mers
+//! with the highest estimates seen so far are kept as heavy-hitter candidates, so memory depends
+//! only on the sketch size and the number of k-mers reported, not on the input.
+
+use std::{
+    cmp::Reverse,
+    collections::{BTreeSet, HashMap},
+};
+
+use super::cardinality::mix64;
+
+/// Counters per row of `CountMinSketch::default`.
+pub const DEFAULT_WIDTH: usize = 1 << 20;
+/// Rows of `CountMinSketch::default`: 4 rows of 2^20 four-byte counters, 16 MiB.
+pub const DEFAULT_DEPTH: usize = 4;
+
+/// A Count-Min Sketch of 64-bit k-mer encodings.
+pub struct CountMinSketch {
+    width: usize,
+    depth: usize,
+    counters: Vec<u32>,
+}
+
+impl Default for CountMinSketch {
+    fn default() -> Self {
+        CountMinSketch::new(DEFAULT_WIDTH, DEFAULT_DEPTH)
+    }
+}
+
+impl CountMinSketch {
+    /// Creates an empty sketch of `depth` rows of `width` counters. Panics if either is 0.
+    pub fn new(width: usize, depth: usize) -> Self {
+        assert!(width > 0 && depth > 0, "Count-Min Sketch width and depth must be positive");
+        CountMinSketch {
+            width,
+            depth,
+            counters: vec![0; width * depth],
+        }
+    }
+
+    // The counter of `kmer` in each row, by double hashing of its 64-bit hash. Borrows nothing
+    // from the sketch, so counters can be updated while iterating.
+    fn cells(&self, kmer: u64) -> impl Iterator<Item = usize> + use<> {
+        let hash = mix64(kmer);
+        let (h1, h2) = (hash & 0xffff_ffff, (hash >> 32) | 1);
+        let width = self.width;
+        (0..self.depth).map(move |row| row * width + (h1.wrapping_add(row as u64 * h2) % width as u64) as usize)
+    }
+
+    /// Adds one occurrence of `kmer` and returns its new estimated count.
+    pub fn add(&mut self, kmer: u64) -> u64 {
+        let estimate = self.estimate(kmer) + 1;
+        for cell in self.cells(kmer) {
+            let counter = &mut self.counters[cell];
+            if (*counter as u64) < estimate {
+                *counter = estimate.min(u32::MAX as u64) as u32;
+            }
+        }
+        estimate
+    }
+
+    /// Estimated count of `kmer`, never below its true count (short of counter saturation).
+    pub fn estimate(&self, kmer: u64) -> u64 {
+        self.cells(kmer).map(|cell| self.counters[cell] as u64).min().unwrap_or(0)
+    }
+
+    /// Memory taken by the counters in bytes.
+    pub fn memory_bytes(&self) -> usize {
+        self.counters.len() * std::mem::size_of::<u32>()
+    }
+}
+
+/// The approximately `capacity` most frequent k-mers of a stream, with estimated counts.
+pub struct HeavyHitters {
+    capacity: usize,
+    sketch: CountMinSketch,
+    // Candidates and their estimates, also ordered to find the weakest: the lowest estimate,
+    // and among equal estimates the largest k-mer, matching the order of `into_top`
+    candidates: HashMap<u64, u64>,
+    by_estimate: BTreeSet<(u64, Reverse<u64>)>,
+}
+
+impl HeavyHitters {
+    /// Tracks the `capacity` k-mers with the highest estimates in `sketch`.
+    pub fn new(capacity: usize, sketch: CountMinSketch) -> Self {
+        HeavyHitters {
+            capacity,
+            sketch,
+            candidates: HashMap::with_capacity(capacity + 1),
+            by_estimate: BTreeSet::new(),
+        }
+    }
+
+    pub fn add(&mut self, kmer: u64) {
+        let estimate = self.sketch.add(kmer);
+        if let Some(previous) = self.candidates.insert(kmer, estimate) {
+            self.by_estimate.remove(&(previous, Reverse(kmer)));
+        } else if self.candidates.len() > self.capacity {
+            // Replace the weakest candidate if the new k-mer now outranks it
+            let &weakest = self.by_estimate.first().expect("candidates are tracked");
+            if weakest < (estimate, Reverse(kmer)) {
+                self.by_estimate.remove(&weakest);
+                self.candidates.remove(&weakest.1.0);
+            } else {
+                self.candidates.remove(&kmer);
+                return;
+            }
+        }
+        self.by_estimate.insert((estimate, Reverse(kmer)));
+    }
+
+    pub fn len(&self) -> usize {
+        self.candidates.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.candidates.is_empty()
+    }
+
+    pub fn sketch(&self) -> &CountMinSketch {
+        &self.sketch
+    }
+
+    /// The candidates and their estimated counts, by descending estimate and then k-mer.
+    pub fn into_top(self) -> Vec<(u64, u64)> {
+        let mut top: Vec<(u64, u64)> = self.candidates.into_iter().collect();
+        top.sort_unstable_by_key(|&(kmer, estimate)| (Reverse(estimate), kmer));
+        top
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count_min_sketch_never_undercounts() {
+        let mut sketch = CountMinSketch::new(64, 4);
+        let mut exact: HashMap<u64, u64> = HashMap::new();
+        for i in 0..5_000u64 {
+            let kmer = (i * i) % 997;
+            sketch.add(kmer);
+            *exact.entry(kmer).or_insert(0) += 1;
+        }
+        for (&kmer, &count) in &exact {
+            assert!(sketch.estimate(kmer) >= count, "k-mer {} undercounted", kmer);
+        }
+        assert_eq!(CountMinSketch::default().estimate(42), 0);
+    }
+
+    #[test]
+    fn test_heavy_hitters_report_most_frequent_kmers() {
+        let mut heavy_hitters = HeavyHitters::new(3, CountMinSketch::default());
+        // Three frequent k-mers spread among many singletons
+        for i in 0..10_000u64 {
+            heavy_hitters.add(1_000_000 + i);
+            if i % 10 == 0 {
+                heavy_hitters.add(7);
+                heavy_hitters.add(8);
+            }
+            if i % 20 == 0 {
+                heavy_hitters.add(9);
+            }
+        }
+        assert_eq!(heavy_hitters.len(), 3);
+        assert_eq!(heavy_hitters.into_top(), vec![(7, 1000), (8, 1000), (9, 500)]);
+    }
+}
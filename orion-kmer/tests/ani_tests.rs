@@ -0,0 +1,116 @@
+use assert_cmd::prelude::*;
+use orion_kmer::commands::ani::ani_from_containment;
+use predicates::prelude::*;
+use serde_json::Value as JsonValue;
+use std::{fs, process::Command};
+use tempfile::TempDir;
+
+#[test]
+fn test_ani_of_fasta_genomes_follows_containment() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    let genome_a = dir.path().join("a.fa");
+    let genome_b = dir.path().join("b.fa");
+    fs::write(&genome_a, ">a\nACGTTGCAAGGCTTACGGATCCA\n")?;
+    // Same genome with one substitution in the middle
+    fs::write(&genome_b, ">b\nACGTTGCAAGGGTTACGGATCCA\n")?;
+
+    let output = Command::cargo_bin("orion-kmer")?
+        .arg("ani")
+        .arg("--fasta1")
+        .arg(&genome_a)
+        .arg("--fasta2")
+        .arg(&genome_a)
+        .args(["-k", "5"])
+        .output()?;
+    assert!(output.status.success());
+    let identical: JsonValue = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(identical["kmer_size"], 5);
+    assert_eq!(identical["ani_average"], 1.0);
+
+    // --max-records limits only count, build, query and classify, not the genomes read here
+    let limited = Command::cargo_bin("orion-kmer")?
+        .args(["--max-records", "1", "ani", "--fasta1"])
+        .arg(&genome_a)
+        .arg("--fasta2")
+        .arg(&genome_a)
+        .args(["-k", "5"])
+        .output()?;
+    assert!(limited.status.success());
+    assert_eq!(serde_json::from_slice::<JsonValue>(&limited.stdout)?, identical);
+
+    let json_path = dir.path().join("ani.json");
+    Command::cargo_bin("orion-kmer")?
+        .arg("ani")
+        .arg("--fasta1")
+        .arg(&genome_a)
+        .arg("--fasta2")
+        .arg(&genome_b)
+        .args(["-k", "5"])
+        .arg("-o")
+        .arg(&json_path)
+        .assert()
+        .success();
+    let report: JsonValue = serde_json::from_str(&fs::read_to_string(&json_path)?)?;
+    let containment = report["containment_1_in_2"].as_f64().unwrap();
+    assert!(containment > 0.0 && containment < 1.0);
+    let ani = report["ani_1_in_2"].as_f64().unwrap();
+    assert!((ani - ani_from_containment(containment, 5)).abs() < 1e-3);
+    assert!(ani > 0.8 && ani < 1.0);
+    let average = report["ani_average"].as_f64().unwrap();
+    let mean = (ani + report["ani_2_in_1"].as_f64().unwrap()) / 2.0;
+    assert!((average - mean).abs() < 1e-3);
+
+    assert_eq!(ani_from_containment(0.0, 21), 0.0);
+    assert_eq!(ani_from_containment(1e-12, 5), 0.0);
+    Ok(())
+}
+
+#[test]
+fn test_ani_database_against_fasta_checks_kmer_size() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    let genome = dir.path().join("genome.fa");
+    fs::write(&genome, ">g\nACGTTGCAAGGCTTACGGATCCA\n")?;
+    let db_path = dir.path().join("genome.db");
+    Command::cargo_bin("orion-kmer")?
+        .arg("build")
+        .args(["-k", "6", "-g"])
+        .arg(&genome)
+        .arg("-o")
+        .arg(&db_path)
+        .assert()
+        .success();
+
+    // The FASTA genome is extracted with the database's k
+    let output = Command::cargo_bin("orion-kmer")?
+        .arg("ani")
+        .arg("--db1")
+        .arg(&db_path)
+        .arg("--fasta2")
+        .arg(&genome)
+        .output()?;
+    assert!(output.status.success());
+    let report: JsonValue = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(report["kmer_size"], 6);
+    assert_eq!(report["shared_kmers"], report["genome1_unique_kmers"]);
+    assert_eq!(report["ani_average"], 1.0);
+
+    Command::cargo_bin("orion-kmer")?
+        .arg("ani")
+        .arg("--db1")
+        .arg(&db_path)
+        .arg("--fasta2")
+        .arg(&genome)
+        .args(["-k", "5"])
+        .assert()
+        .failure();
+    Command::cargo_bin("orion-kmer")?
+        .arg("ani")
+        .arg("--fasta1")
+        .arg(&genome)
+        .arg("--fasta2")
+        .arg(&genome)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("-k is required"));
+    Ok(())
+}
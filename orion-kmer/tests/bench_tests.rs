@@ -0,0 +1,34 @@
+use assert_cmd::prelude::*;
+use predicates::prelude::*;
+use serde_json::Value as JsonValue;
+use std::{fs, process::Command};
+use tempfile::TempDir;
+
+#[test]
+fn test_bench_writes_timings_json() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let output_path = temp_dir.path().join("bench.json");
+    Command::cargo_bin("orion-kmer")?
+        .args(["bench", "--genome-length", "5000", "-k", "11", "-o"])
+        .arg(&output_path)
+        .assert()
+        .success();
+
+    let report: JsonValue = serde_json::from_str(&fs::read_to_string(&output_path)?)?;
+    assert_eq!(report["genome_length"], 5000);
+    assert_eq!(report["kmer_size"], 11);
+    for field in ["build_seconds", "build_bases_per_second", "count_seconds", "count_bases_per_second"] {
+        assert!(report[field].as_f64().unwrap() > 0.0, "{} should be positive", field);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_bench_is_hidden_from_help() -> Result<(), Box<dyn std::error::Error>> {
+    Command::cargo_bin("orion-kmer")?
+        .arg("--help")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("convert").and(predicate::str::contains("bench").not()));
+    Ok(())
+}
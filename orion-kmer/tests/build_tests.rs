@@ -1,6 +1,6 @@
 use assert_cmd::prelude::*;
-use orion_kmer::db_types::KmerDbV2; // Updated import
-use orion_kmer::kmer::{canonical_u64, seq_to_u64}; // For test data generation
+use orion_kmer::db_types::{HashFunction, KmerDbV2}; // Updated import
+use orion_kmer::kmer::{canonical_u64, hash_kmer, seq_to_u64, CanonicalPolicy}; // For test data generation
 use predicates::prelude::*;
 use std::{
     collections::HashSet,
@@ -13,6 +13,14 @@ use tempfile::{NamedTempFile, TempDir};
 
 use std::io::Read; // For MultiGzDecoder
 
+// Decodes the bytes of a database file: the header magic, then the bincode-encoded KmerDbV2
+fn decode_kmer_db(db_bytes: &[u8]) -> Result<KmerDbV2, Box<dyn std::error::Error>> {
+    let body = db_bytes
+        .strip_prefix(orion_kmer::utils::KMER_DB_MAGIC.as_slice())
+        .ok_or("database does not start with its header magic")?;
+    Ok(bincode::deserialize(body)?)
+}
+
 // Helper to run build with actual files and load the resulting KmerDbV2 database
 fn run_build_with_files_and_load_db(
     k: u8,
@@ -52,7 +60,7 @@ fn run_build_with_files_and_load_db(
         fs::read(&output_db_path)?
     };
 
-    let kmer_db_v2: KmerDbV2 = bincode::deserialize(&db_bytes)?;
+    let kmer_db_v2: KmerDbV2 = decode_kmer_db(&db_bytes)?;
 
     // Verify reference names if needed (important if original filenames had extensions)
     // For simplicity in this helper, we assume the caller will verify the KmerDbV2 content including ref names.
@@ -107,7 +115,7 @@ fn run_build_and_load_db_v2(
     cmd.assert().success();
 
     let db_bytes = fs::read(output_db_path_str)?;
-    let kmer_db_v2: KmerDbV2 = bincode::deserialize(&db_bytes)?;
+    let kmer_db_v2: KmerDbV2 = decode_kmer_db(&db_bytes)?;
 
     Ok(kmer_db_v2)
 }
@@ -250,6 +258,38 @@ fn test_build_fasta_with_no_sequences() -> Result<(), Box<dyn std::error::Error>
     Ok(())
 }
 
+#[test]
+fn test_build_fail_on_empty_rejects_database_without_kmers() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let no_kmers_path = temp_dir.path().join("no_kmers.fa");
+    let genome_path = temp_dir.path().join("genome.fa");
+    fs::write(&no_kmers_path, ">header1\n>header2\nACG\n")?;
+    fs::write(&genome_path, ">g\nACGTACGT\n")?;
+    let db_path = temp_dir.path().join("out.db");
+    Command::cargo_bin("orion-kmer")?
+        .args(["build", "-k", "5", "-g"])
+        .arg(&no_kmers_path)
+        .arg("-o")
+        .arg(&db_path)
+        .arg("--fail-on-empty")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--fail-on-empty: the k=5 database holds no k-mers"));
+    assert!(!db_path.exists());
+
+    // The check is on the whole database, so one empty reference among others passes
+    Command::cargo_bin("orion-kmer")?
+        .args(["build", "-k", "5", "-g"])
+        .arg(&no_kmers_path)
+        .arg(&genome_path)
+        .arg("-o")
+        .arg(&db_path)
+        .arg("--fail-on-empty")
+        .assert()
+        .success();
+    Ok(())
+}
+
 #[test]
 fn test_build_malformed_fasta_file() -> Result<(), Box<dyn std::error::Error>> {
     // A file that is not valid FASTA (e.g. binary, or bad header) should cause an error during parsing.
@@ -328,6 +368,25 @@ fn get_test_data_path(file_name: &str) -> PathBuf {
     path
 }
 
+// baseline_k5.db was written before databases had a header, by building refA.fa
+// (">a\nACGTACGTTGCATT") and refB.fa (">b\nGGGGCCCCAAAATC") at k=5
+#[test]
+fn test_load_headerless_baseline_database() -> Result<(), Box<dyn std::error::Error>> {
+    let baseline_db = orion_kmer::utils::load_kmer_db_v2(&get_test_data_path("baseline_k5.db"))?;
+    let rebuilt_db = run_build_with_extra_args(
+        5,
+        vec![("refA.fa", ">a\nACGTACGTTGCATT\n"), ("refB.fa", ">b\nGGGGCCCCAAAATC\n")],
+        &[],
+    )?;
+    assert_eq!(baseline_db.k, 5);
+    assert_eq!(baseline_db.references, rebuilt_db.references);
+    assert_eq!(baseline_db.hashing, None);
+    assert!(baseline_db.canonical);
+    assert!(baseline_db.positions.is_empty());
+    assert!(baseline_db.sampling_rates.is_empty());
+    Ok(())
+}
+
 // Expected k-mers for test_input1.fasta, k=7
 // ACGTACG, CGTACGT, GTACGTA, TACGTAC, GATTACA (twice)
 // Canonical: ACGTACG (from ACGTACG & CGTACGT), GTACGTA, TACGTAC, GATTACA
@@ -457,3 +516,1166 @@ fn test_build_multiple_compressed_inputs_k5() -> Result<(), Box<dyn std::error::
 
     Ok(())
 }
+
+// Helper to run build on content-based inputs with additional command-line arguments
+fn run_build_with_extra_args(
+    k: u8,
+    input_files_content: Vec<(&str, &str)>, // Vec of (filename, content)
+    extra_args: &[&str],
+) -> Result<KmerDbV2, Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let output_db_path = temp_dir.path().join("test_db.db");
+
+    let mut cmd = Command::cargo_bin("orion-kmer")?;
+    cmd.arg("build")
+        .arg("-k")
+        .arg(k.to_string())
+        .arg("-o")
+        .arg(&output_db_path);
+    for (name, content) in &input_files_content {
+        let file_path = temp_dir.path().join(name);
+        fs::create_dir_all(file_path.parent().unwrap())?;
+        fs::write(&file_path, content)?;
+        cmd.arg("-g").arg(&file_path);
+    }
+    cmd.args(extra_args);
+    cmd.assert().success();
+
+    decode_kmer_db(&fs::read(&output_db_path)?)
+}
+
+#[test]
+fn test_build_sanitize_names_makes_unique_identifiers() -> Result<(), Box<dyn std::error::Error>> {
+    // Distinct file names that sanitize alike get numeric suffixes in input order
+    let inputs = vec![
+        ("sample.v1.fa", ">a\nACGTACGT\n"),
+        ("sample_v1.fa", ">b\nTTTTGGGG\n"),
+        ("sample-v1.fa", ">c\nGATTACA\n"),
+    ];
+    let kmer_db = run_build_with_extra_args(4, inputs.clone(), &["--sanitize-names"])?;
+    let names: Vec<&str> = kmer_db.references.keys().map(String::as_str).collect();
+    assert_eq!(names, vec!["sample_v1_fa", "sample_v1_fa_2", "sample_v1_fa_3"]);
+    assert_eq!(kmer_db.references["sample_v1_fa"], kmers_from_strings(&["ACGT", "CGTA", "GTAC"], 4));
+    // Names are kept as derived without the option
+    let unsanitized = run_build_with_extra_args(4, inputs, &[])?;
+    assert!(unsanitized.references.contains_key("sample.v1.fa"));
+
+    // A repeated record ID maps to the same identifier, so --on-conflict still applies to it
+    let kmer_db = run_build_with_extra_args(
+        4,
+        vec![("genome.fa", ">NC_1.1 first\nACGTACGT\n>NC_1_1\nTTTTGGGG\n>NC_1.1\nGATTACA\n")],
+        &["--per-record", "--sanitize-names", "--on-conflict", "skip"],
+    )?;
+    let names: Vec<&str> = kmer_db.references.keys().map(String::as_str).collect();
+    assert_eq!(names, vec!["NC_1_1", "NC_1_1_2"]);
+    assert_eq!(kmer_db.references["NC_1_1"], kmers_from_strings(&["ACGT", "CGTA", "GTAC"], 4));
+    assert!(kmer_db.positions.contains_key("NC_1_1_2"));
+    Ok(())
+}
+
+#[test]
+fn test_build_normalize_ref_names_trims_and_lowercases() -> Result<(), Box<dyn std::error::Error>> {
+    // File stems that differ only in trailing whitespace and case
+    let inputs = vec![
+        ("E.coli K12 .fa", ">a\nACGTACGT\n"),
+        ("e.coli k12.fa", ">b\nTTTTGGGG\n"),
+    ];
+    let base_args = ["--ref-name-style", "stem", "--on-conflict", "skip"];
+    let names = |extra_args: &[&str]| -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let kmer_db = run_build_with_extra_args(4, inputs.clone(), &[&base_args[..], extra_args].concat())?;
+        Ok(kmer_db.references.keys().cloned().collect())
+    };
+    assert_eq!(names(&[])?, vec!["E.coli K12 ", "e.coli k12"]);
+    assert_eq!(names(&["--normalize-ref-names", "trim"])?, vec!["E.coli K12", "e.coli k12"]);
+    // Lowercasing merges the two, keeping the first under --on-conflict skip
+    assert_eq!(names(&["--normalize-ref-names", "lowercase"])?, vec!["e.coli k12"]);
+
+    // Reference selections are normalized like the database's names
+    let temp_dir = TempDir::new()?;
+    let db_path = temp_dir.path().join("normalized.db");
+    let mut cmd = Command::cargo_bin("orion-kmer")?;
+    cmd.args(["build", "-k", "4", "--normalize-ref-names", "lowercase"])
+        .args(base_args)
+        .arg("-o")
+        .arg(&db_path);
+    for (name, content) in &inputs {
+        let file_path = temp_dir.path().join(name);
+        fs::write(&file_path, content)?;
+        cmd.arg("-g").arg(&file_path);
+    }
+    cmd.assert().success();
+    let reads_path = temp_dir.path().join("reads.fa");
+    fs::write(&reads_path, ">r\nACGTACGT\n")?;
+    let output_path = temp_dir.path().join("classify.json");
+    Command::cargo_bin("orion-kmer")?
+        .args(["classify", "-i"])
+        .arg(&reads_path)
+        .arg("-d")
+        .arg(&db_path)
+        .arg("-o")
+        .arg(&output_path)
+        .args(["--only-reference", " E.Coli K12"])
+        .assert()
+        .success();
+    let json_output: serde_json::Value = serde_json::from_str(&fs::read_to_string(&output_path)?)?;
+    let references = json_output["databases_analyzed"][0]["references"].as_array().unwrap();
+    assert_eq!(references.len(), 1);
+    assert_eq!(references[0]["reference_name"], "e.coli k12");
+
+    // Appending needs the same normalization
+    Command::cargo_bin("orion-kmer")?
+        .args(["build", "-k", "4", "--append"])
+        .arg(&db_path)
+        .arg("-g")
+        .arg(&reads_path)
+        .arg("-o")
+        .arg(temp_dir.path().join("appended.db"))
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--normalize-ref-names must match"));
+    Ok(())
+}
+
+#[test]
+fn test_build_reports_records_shorter_than_k() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let genome_path = temp_dir.path().join("short.fasta");
+    fs::write(&genome_path, ">s1\nACG\n>s2\nTTA\n")?;
+    let db_path = temp_dir.path().join("short.db");
+    let mut cmd = Command::cargo_bin("orion-kmer")?;
+    cmd.args(["build", "-k", "4", "-g"])
+        .arg(&genome_path)
+        .arg("-o")
+        .arg(&db_path);
+    cmd.assert()
+        .success()
+        .stderr(predicate::str::contains("All 2 records in").and(predicate::str::contains("shorter than k=4")));
+
+    let kmer_db: KmerDbV2 = decode_kmer_db(&fs::read(&db_path)?)?;
+    assert!(kmer_db.references["short.fasta"].is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_build_expand_iupac() -> Result<(), Box<dyn std::error::Error>> {
+    let kmer_db =
+        run_build_with_extra_args(4, vec![("iupac.fasta", ">s1\nACYT\n")], &["--expand-iupac"])?;
+    // Y = C/T: ACYT expands to ACCT and ACTT
+    assert_eq!(
+        kmer_db.references["iupac.fasta"],
+        kmers_from_strings(&["ACCT", "ACTT"], 4)
+    );
+    Ok(())
+}
+
+// Appends a minimal ustar entry (header + padded data) for a regular file to `archive`.
+fn append_tar_entry(archive: &mut Vec<u8>, name: &str, data: &[u8]) {
+    let mut header = [0u8; 512];
+    header[..name.len()].copy_from_slice(name.as_bytes());
+    header[100..107].copy_from_slice(b"0000644");
+    header[124..135].copy_from_slice(format!("{:011o}", data.len()).as_bytes());
+    header[156] = b'0';
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+    // Checksum is computed with the checksum field itself set to spaces
+    header[148..156].copy_from_slice(b"        ");
+    let checksum: u32 = header.iter().map(|&b| b as u32).sum();
+    header[148..156].copy_from_slice(format!("{:06o}\0 ", checksum).as_bytes());
+
+    archive.extend_from_slice(&header);
+    archive.extend_from_slice(data);
+    archive.resize(archive.len().div_ceil(512) * 512, 0);
+}
+
+#[test]
+fn test_build_tar_gz_archive_references() -> Result<(), Box<dyn std::error::Error>> {
+    let mut archive = Vec::new();
+    append_tar_entry(&mut archive, "genomes/genomeA.fasta", b">a\nACGTACGT\n");
+    append_tar_entry(&mut archive, "genomes/README", b"not a sequence file\n");
+    append_tar_entry(&mut archive, "genomes/genomeB.fa", b">b\nTTTTCCCC\n");
+    archive.extend_from_slice(&[0u8; 1024]); // End-of-archive marker
+
+    let temp_dir = TempDir::new()?;
+    let archive_path = temp_dir.path().join("collection.tar.gz");
+    let mut encoder =
+        flate2::write::GzEncoder::new(File::create(&archive_path)?, flate2::Compression::default());
+    encoder.write_all(&archive)?;
+    encoder.finish()?;
+
+    let kmer_db = run_build_with_files_and_load_db(4, vec![(archive_path, String::new())], false)?;
+
+    assert_eq!(kmer_db.references.len(), 2);
+    assert_eq!(
+        kmer_db.references["genomes/genomeA.fasta"],
+        kmers_from_strings(&["ACGT", "CGTA", "GTAC", "TACG"], 4)
+    );
+    assert_eq!(
+        kmer_db.references["genomes/genomeB.fa"],
+        kmers_from_strings(&["TTTT", "TTTC", "TTCC", "TCCC", "CCCC"], 4)
+    );
+    Ok(())
+}
+
+// Runs build on the uncompressed tar archive `archive`, returning the command's assertion
+fn run_build_on_tar(archive: &[u8]) -> Result<(TempDir, assert_cmd::assert::Assert), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let archive_path = temp_dir.path().join("collection.tar");
+    fs::write(&archive_path, archive)?;
+    let assert = Command::cargo_bin("orion-kmer")?
+        .args(["build", "-k", "4", "-g"])
+        .arg(&archive_path)
+        .arg("-o")
+        .arg(temp_dir.path().join("archive.db"))
+        .assert();
+    Ok((temp_dir, assert))
+}
+
+#[test]
+fn test_build_tar_archive_gnu_long_names_and_pax_headers() -> Result<(), Box<dyn std::error::Error>> {
+    let long_name = format!("genomes/{}/genomeA.fasta", "nested".repeat(20));
+    let mut builder = tar::Builder::new(Vec::new());
+    // A GNU header stores a name over 100 bytes in a preceding long-name entry
+    let data: &[u8] = b">a\nACGTACGT\n";
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    builder.append_data(&mut header, &long_name, data)?;
+    // A pax extended header overrides the name in the entry's own header
+    builder.append_pax_extensions([("path", b"pax/genomeB.fa".as_slice())])?;
+    let data: &[u8] = b">b\nTTTTCCCC\n";
+    let mut header = tar::Header::new_ustar();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    builder.append_data(&mut header, "short.fa", data)?;
+    let archive = builder.into_inner()?;
+
+    let (temp_dir, assert) = run_build_on_tar(&archive)?;
+    assert.success();
+    let kmer_db = orion_kmer::utils::load_kmer_db_v2(&temp_dir.path().join("archive.db"))?;
+    assert_eq!(kmer_db.references.keys().collect::<Vec<_>>(), [&long_name, "pax/genomeB.fa"]);
+    assert_eq!(kmer_db.references[&long_name], kmers_from_strings(&["ACGT", "CGTA", "GTAC", "TACG"], 4));
+    Ok(())
+}
+
+#[test]
+fn test_build_truncated_tar_archive_fails() -> Result<(), Box<dyn std::error::Error>> {
+    let mut archive = Vec::new();
+    append_tar_entry(&mut archive, "genomeA.fasta", b">a\nACGTACGTACGTACGT\n");
+    archive.extend_from_slice(&[0u8; 1024]);
+
+    // Cut inside the entry's data, and inside its header
+    for cut in [512 + 8, 100] {
+        let (_temp_dir, assert) = run_build_on_tar(&archive[..cut])?;
+        assert.failure().stderr(predicate::str::contains("collection.tar"));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_build_lz4_genome_and_database_round_trip() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let genome_path = temp_dir.path().join("genome.fa.lz4");
+    let mut encoder = lz4_flex::frame::FrameEncoder::new(File::create(&genome_path)?);
+    encoder.write_all(b">a\nACGTACGTTTGC\n")?;
+    encoder.finish()?;
+    let db_path = temp_dir.path().join("genome.db.lz4");
+    Command::cargo_bin("orion-kmer")?
+        .args(["build", "-k", "4", "--ref-name-style", "stem", "-g"])
+        .arg(&genome_path)
+        .arg("-o")
+        .arg(&db_path)
+        .assert()
+        .success();
+
+    // The database decompresses to the same bytes as an uncompressed build
+    let mut db_bytes = Vec::new();
+    lz4_flex::frame::FrameDecoder::new(File::open(&db_path)?).read_to_end(&mut db_bytes)?;
+    let kmer_db = decode_kmer_db(&db_bytes)?;
+    let plain_db = run_build_with_extra_args(4, vec![("genome.fa", ">a\nACGTACGTTTGC\n")], &["--ref-name-style", "stem"])?;
+    assert_eq!(kmer_db.references, plain_db.references);
+    assert_eq!(orion_kmer::utils::load_kmer_db_v2(&db_path)?.references, plain_db.references);
+    Ok(())
+}
+
+#[test]
+fn test_build_hashed_stores_murmur_hashes() -> Result<(), Box<dyn std::error::Error>> {
+    let k = 4;
+    let kmer_db = run_build_with_extra_args(
+        k,
+        vec![("hashed.fasta", ">s1\nACGTACGT\n")],
+        &["--hashed", "--hash-seed", "7"],
+    )?;
+
+    let hashing = kmer_db.hashing.expect("hashed database should record its hashing");
+    assert_eq!(hashing.function, HashFunction::Murmur3X64);
+    assert_eq!(hashing.seed, 7);
+    let expected: HashSet<u64> = kmers_from_strings(&["ACGT", "CGTA", "GTAC"], k)
+        .into_iter()
+        .map(|kmer| hash_kmer(kmer, k, 7))
+        .collect();
+    assert_eq!(kmer_db.references["hashed.fasta"], expected);
+    Ok(())
+}
+
+#[test]
+fn test_build_no_canonical_stores_kmers_as_read() -> Result<(), Box<dyn std::error::Error>> {
+    let k = 4;
+    // TTTTG reads as TTTT, TTTG; their canonical forms are AAAA, CAAA
+    let kmer_db = run_build_with_extra_args(k, vec![("fwd.fasta", ">s1\nTTTTG\n")], &["--no-canonical"])?;
+    assert!(!kmer_db.canonical);
+    let expected: HashSet<u64> = ["TTTT", "TTTG"]
+        .iter()
+        .map(|s| seq_to_u64(s.as_bytes(), k).unwrap())
+        .collect();
+    assert_eq!(kmer_db.references["fwd.fasta"], expected);
+
+    let canonical_db = run_build_with_extra_args(k, vec![("fwd.fasta", ">s1\nTTTTG\n")], &[])?;
+    assert!(canonical_db.canonical);
+    assert_eq!(canonical_db.references["fwd.fasta"], kmers_from_strings(&["TTTT", "TTTG"], k));
+    Ok(())
+}
+
+#[test]
+fn test_build_per_record_stores_each_record_with_positions() -> Result<(), Box<dyn std::error::Error>> {
+    let k = 4;
+    let kmer_db = run_build_with_extra_args(
+        k,
+        vec![("genome.fasta", ">chr1 first chromosome\nAAAACG\n>chr2\nTTTTT\n")],
+        &["--per-record"],
+    )?;
+    assert_eq!(kmer_db.references.keys().collect::<Vec<_>>(), vec!["chr1", "chr2"]);
+    assert_eq!(kmer_db.references["chr1"], kmers_from_strings(&["AAAA", "AAAC", "AACG"], k));
+
+    let key = |s: &str| canonical_u64(seq_to_u64(s.as_bytes(), k).unwrap(), k);
+    let chr1 = &kmer_db.positions["chr1"];
+    assert_eq!(chr1.length, 6);
+    let mut expected = vec![(key("AAAA"), 0), (key("AAAC"), 1), (key("AACG"), 2)];
+    expected.sort_unstable();
+    assert_eq!(chr1.kmer_positions, expected);
+    // TTTT at 0 and 1, both stored as canonical AAAA
+    assert_eq!(kmer_db.positions["chr2"].kmer_positions, vec![(key("AAAA"), 0), (key("AAAA"), 1)]);
+
+    // Whole-file references carry no positions
+    let file_db = run_build_with_extra_args(k, vec![("genome.fasta", ">chr1\nAAAACG\n")], &[])?;
+    assert!(file_db.positions.is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_build_from_counts_makes_single_reference() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let reads_path = temp_dir.path().join("reads.fasta");
+    // ACGT and CGTT twice, GTTT once
+    fs::write(&reads_path, ">r1\nACGTT\n>r2\nACGTTT\n")?;
+    let counts_path = temp_dir.path().join("sample.tsv.gz");
+    Command::cargo_bin("orion-kmer")?
+        .args(["count", "-k", "4", "-i"])
+        .arg(&reads_path)
+        .arg("-o")
+        .arg(&counts_path)
+        .assert()
+        .success();
+
+    let build_from_counts = |extra_args: &[&str]| -> Result<KmerDbV2, Box<dyn std::error::Error>> {
+        let db_path = temp_dir.path().join("from_counts.db");
+        Command::cargo_bin("orion-kmer")?
+            .args(["build", "-k", "4", "--from-counts"])
+            .arg(&counts_path)
+            .arg("-o")
+            .arg(&db_path)
+            .args(extra_args)
+            .assert()
+            .success();
+        decode_kmer_db(&fs::read(&db_path)?)
+    };
+
+    // Named after the count table's stem, with the same k-mers as building from the reads
+    let kmer_db = build_from_counts(&[])?;
+    let from_reads = run_build_with_extra_args(4, vec![("reads.fasta", ">r1\nACGTT\n>r2\nACGTTT\n")], &[])?;
+    assert_eq!(kmer_db.references.keys().collect::<Vec<_>>(), vec!["sample"]);
+    assert_eq!(kmer_db.references["sample"], from_reads.references["reads.fasta"]);
+
+    let filtered = build_from_counts(&["--min-count", "2", "--ref-name", "solid"])?;
+    assert_eq!(filtered.references["solid"], kmers_from_strings(&["ACGT", "CGTT"], 4));
+
+    // The table's k must match -k
+    Command::cargo_bin("orion-kmer")?
+        .args(["build", "-k", "5", "--from-counts"])
+        .arg(&counts_path)
+        .arg("-o")
+        .arg(temp_dir.path().join("k5.db"))
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("has k=4, but the database is being built with k=5"));
+    Ok(())
+}
+
+#[test]
+fn test_build_dry_run_prints_plan_without_building() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let genome_a = temp_dir.path().join("genomeA.fasta");
+    let genome_b = temp_dir.path().join("genomeB.fasta.gz");
+    fs::write(&genome_a, ">a\nACGTACGT\n")?;
+    let mut encoder = flate2::write::GzEncoder::new(File::create(&genome_b)?, flate2::Compression::default());
+    encoder.write_all(b">b\nTTTTGGGG\n")?;
+    encoder.finish()?;
+    let db_path = temp_dir.path().join("planned.db");
+
+    let output = Command::cargo_bin("orion-kmer")?
+        .args(["--dry-run", "build", "-k", "4", "-g"])
+        .arg(&genome_a)
+        .arg(&genome_b)
+        .arg("-o")
+        .arg(&db_path)
+        .output()?;
+    assert!(output.status.success());
+    assert!(!db_path.exists());
+    let plan: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(plan["command"], "build");
+    assert_eq!(plan["kmer_size"], 4);
+    assert_eq!(plan["estimated_references"], 2);
+    assert_eq!(plan["inputs"].as_array().unwrap().len(), 2);
+    assert_eq!(plan["inputs"][0]["path"], genome_a.to_str().unwrap());
+    assert_eq!(plan["inputs"][0]["size_bytes"], 12);
+    assert_eq!(plan["outputs"][0], db_path.to_str().unwrap());
+
+    // Every unreadable input is reported before failing
+    Command::cargo_bin("orion-kmer")?
+        .args(["build", "-k", "4", "--dry-run", "-g"])
+        .arg(&genome_a)
+        .arg(temp_dir.path().join("typo1.fasta"))
+        .arg(temp_dir.path().join("typo2.fasta"))
+        .arg("-o")
+        .arg(&db_path)
+        .assert()
+        .failure()
+        .stdout(predicate::str::is_empty())
+        .stderr(predicate::str::contains("typo1.fasta").and(predicate::str::contains("typo2.fasta")))
+        .stderr(predicate::str::contains("2 of 3 inputs that cannot be read"));
+    assert!(!db_path.exists());
+    Ok(())
+}
+
+// Builds a single-reference k=4 database at `db_path` to append to.
+fn build_base_db_for_append(dir: &TempDir, db_path: &PathBuf) -> Result<(), Box<dyn std::error::Error>> {
+    let genome_path = dir.path().join("a.fasta");
+    fs::write(&genome_path, ">a\nACGTT\n")?;
+    Command::cargo_bin("orion-kmer")?
+        .arg("build")
+        .arg("-k")
+        .arg("4")
+        .arg("-g")
+        .arg(&genome_path)
+        .arg("-o")
+        .arg(db_path)
+        .assert()
+        .success();
+    Ok(())
+}
+
+#[test]
+fn test_build_append_adds_new_references() -> Result<(), Box<dyn std::error::Error>> {
+    let base_dir = TempDir::new()?;
+    let base_db_path = base_dir.path().join("base.db");
+    build_base_db_for_append(&base_dir, &base_db_path)?;
+
+    let kmer_db = run_build_with_extra_args(
+        4,
+        vec![("b.fasta", ">b\nGGGGA\n")],
+        &["--append", base_db_path.to_str().unwrap()],
+    )?;
+    assert_eq!(kmer_db.num_references(), 2);
+    assert_eq!(
+        kmer_db.references["a.fasta"],
+        kmers_from_strings(&["ACGT", "CGTT"], 4)
+    );
+    assert_eq!(
+        kmer_db.references["b.fasta"],
+        kmers_from_strings(&["GGGG", "GGGA"], 4)
+    );
+    Ok(())
+}
+
+#[test]
+fn test_build_append_on_conflict() -> Result<(), Box<dyn std::error::Error>> {
+    let base_dir = TempDir::new()?;
+    let base_db_path = base_dir.path().join("base.db");
+    build_base_db_for_append(&base_dir, &base_db_path)?;
+    let base_db_arg = base_db_path.to_str().unwrap();
+
+    // Default policy: a reference with an existing name is an error
+    let new_dir = TempDir::new()?;
+    let new_genome_path = new_dir.path().join("a.fasta");
+    fs::write(&new_genome_path, ">a\nGGGGA\n")?;
+    Command::cargo_bin("orion-kmer")?
+        .arg("build")
+        .arg("-k")
+        .arg("4")
+        .arg("-g")
+        .arg(&new_genome_path)
+        .arg("-o")
+        .arg(new_dir.path().join("out.db"))
+        .arg("--append")
+        .arg(&base_db_path)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Reference 'a.fasta' already exists"));
+
+    let skipped = run_build_with_extra_args(
+        4,
+        vec![("a.fasta", ">a\nGGGGA\n")],
+        &["--append", base_db_arg, "--on-conflict", "skip"],
+    )?;
+    assert_eq!(
+        skipped.references["a.fasta"],
+        kmers_from_strings(&["ACGT", "CGTT"], 4)
+    );
+
+    let replaced = run_build_with_extra_args(
+        4,
+        vec![("a.fasta", ">a\nGGGGA\n")],
+        &["--append", base_db_arg, "--on-conflict", "replace"],
+    )?;
+    assert_eq!(
+        replaced.references["a.fasta"],
+        kmers_from_strings(&["GGGG", "GGGA"], 4)
+    );
+    Ok(())
+}
+
+#[test]
+fn test_build_same_name_inputs_replace_by_default_without_append() -> Result<(), Box<dyn std::error::Error>> {
+    // Two inputs named a.fasta in different directories: the later one replaces the earlier
+    let kmer_db = run_build_with_extra_args(
+        4,
+        vec![("first/a.fasta", ">a\nACGTT\n"), ("second/a.fasta", ">a\nGGGGA\n")],
+        &[],
+    )?;
+    assert_eq!(kmer_db.references.len(), 1);
+    assert_eq!(kmer_db.references["a.fasta"], kmers_from_strings(&["GGGG", "GGGA"], 4));
+
+    // An explicit --on-conflict error still rejects the clash
+    let temp_dir = TempDir::new()?;
+    let mut cmd = Command::cargo_bin("orion-kmer")?;
+    cmd.args(["build", "-k", "4", "--on-conflict", "error", "-o"]).arg(temp_dir.path().join("out.db"));
+    for (dir, content) in [("first", ">a\nACGTT\n"), ("second", ">a\nGGGGA\n")] {
+        fs::create_dir_all(temp_dir.path().join(dir))?;
+        let genome_path = temp_dir.path().join(dir).join("a.fasta");
+        fs::write(&genome_path, content)?;
+        cmd.arg("-g").arg(genome_path);
+    }
+    cmd.assert().failure().stderr(predicate::str::contains("Reference 'a.fasta' already exists"));
+    Ok(())
+}
+
+#[test]
+fn test_build_append_k_mismatch() -> Result<(), Box<dyn std::error::Error>> {
+    let base_dir = TempDir::new()?;
+    let base_db_path = base_dir.path().join("base.db");
+    build_base_db_for_append(&base_dir, &base_db_path)?;
+
+    let genome_path = base_dir.path().join("b.fasta");
+    fs::write(&genome_path, ">b\nGGGGA\n")?;
+    Command::cargo_bin("orion-kmer")?
+        .arg("build")
+        .arg("-k")
+        .arg("5")
+        .arg("-g")
+        .arg(&genome_path)
+        .arg("-o")
+        .arg(base_dir.path().join("out.db"))
+        .arg("--append")
+        .arg(&base_db_path)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "User-provided k-mer size 5 does not match k-mer size 4",
+        ));
+    Ok(())
+}
+
+#[test]
+fn test_build_uniqueness_report() -> Result<(), Box<dyn std::error::Error>> {
+    let report_dir = TempDir::new()?;
+    let report_path = report_dir.path().join("uniqueness.tsv");
+    // k=4: a.fasta -> ACGT, CGTT; b.fasta -> ACGT, CGTA, GTAC; only ACGT is shared
+    run_build_with_extra_args(
+        4,
+        vec![("a.fasta", ">a\nACGTT\n"), ("b.fasta", ">b\nACGTAC\n")],
+        &["--uniqueness-report", report_path.to_str().unwrap()],
+    )?;
+
+    let report = fs::read_to_string(&report_path)?;
+    let lines: Vec<&str> = report.lines().collect();
+    assert_eq!(
+        lines,
+        vec![
+            "reference\ttotal_kmers\tunique_kmers\tunique_fraction",
+            "a.fasta\t2\t1\t0.5000",
+            "b.fasta\t3\t2\t0.6667",
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_build_include_seq_filter() -> Result<(), Box<dyn std::error::Error>> {
+    let kmer_db = run_build_with_extra_args(
+        3,
+        vec![("multi.fasta", ">chr1\nAAA\n>chr2 second\nCCC\n")],
+        &["--include-seq", "chr2"],
+    )?;
+    assert_eq!(
+        kmer_db.references["multi.fasta"],
+        kmers_from_strings(&["CCC"], 3)
+    );
+    Ok(())
+}
+
+#[test]
+fn test_build_checkpoint_and_resume() -> Result<(), Box<dyn std::error::Error>> {
+    let checkpoint_dir = TempDir::new()?;
+    let checkpoint_path = checkpoint_dir.path().join("partial.db");
+    let checkpoint_arg = checkpoint_path.to_str().unwrap();
+
+    // A fresh build writes the checkpoint after each reference
+    let kmer_db = run_build_with_extra_args(
+        4,
+        vec![("a.fasta", ">a\nACGTT\n")],
+        &["--checkpoint", checkpoint_arg],
+    )?;
+    let checkpoint_db: KmerDbV2 = decode_kmer_db(&fs::read(&checkpoint_path)?)?;
+    assert_eq!(checkpoint_db.references, kmer_db.references);
+
+    // Resuming skips references already in the checkpoint, even if their input changed
+    let resumed = run_build_with_extra_args(
+        4,
+        vec![("a.fasta", ">a\nGGGGA\n"), ("b.fasta", ">b\nGGGGA\n")],
+        &["--checkpoint", checkpoint_arg, "--resume"],
+    )?;
+    assert_eq!(resumed.num_references(), 2);
+    assert_eq!(
+        resumed.references["a.fasta"],
+        kmers_from_strings(&["ACGT", "CGTT"], 4)
+    );
+    assert_eq!(
+        resumed.references["b.fasta"],
+        kmers_from_strings(&["GGGG", "GGGA"], 4)
+    );
+    Ok(())
+}
+
+#[test]
+fn test_build_zst_and_xz_output_are_finished() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let input_path = temp_dir.path().join("ref.fa");
+    fs::write(&input_path, ">r\nACGTTGCA\n")?;
+
+    for extension in ["zst", "xz"] {
+        let output_path = temp_dir.path().join(format!("ref.db.{}", extension));
+        Command::cargo_bin("orion-kmer")?
+            .arg("build")
+            .arg("-k")
+            .arg("4")
+            .arg("-g")
+            .arg(&input_path)
+            .arg("-o")
+            .arg(&output_path)
+            .assert()
+            .success();
+
+        // Decoding fails on a truncated stream, so this checks the trailer was written
+        let file = File::open(&output_path)?;
+        let mut db_bytes = Vec::new();
+        match extension {
+            "zst" => zstd::stream::read::Decoder::new(file)?.read_to_end(&mut db_bytes)?,
+            _ => xz2::read::XzDecoder::new(file).read_to_end(&mut db_bytes)?,
+        };
+        let kmer_db: KmerDbV2 = decode_kmer_db(&db_bytes)?;
+        assert_eq!(kmer_db.references["ref.fa"].len(), 5);
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_build_reports_output_write_errors() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let input_path = temp_dir.path().join("ref.fa");
+    fs::write(&input_path, ">r\nACGTTGCA\n")?;
+    // Every write to /dev/full fails with "No space left on device"
+    let output_path = temp_dir.path().join("full.db.zst");
+    std::os::unix::fs::symlink("/dev/full", &output_path)?;
+
+    Command::cargo_bin("orion-kmer")?
+        .arg("build")
+        .arg("-k")
+        .arg("4")
+        .arg("-g")
+        .arg(&input_path)
+        .arg("-o")
+        .arg(&output_path)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Failed to finish output database writer"));
+    Ok(())
+}
+
+#[test]
+fn test_build_ref_name_style() -> Result<(), Box<dyn std::error::Error>> {
+    let inputs = vec![
+        ("one/ecoli.k12.fa", ">chr1 first assembly\nACGTT\n"),
+        ("two/genome.fasta", ">plasmid_2 second assembly\nGGGGA\n"),
+    ];
+
+    let names = |extra_args: &[&str]| -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let kmer_db = run_build_with_extra_args(4, inputs.clone(), extra_args)?;
+        Ok(kmer_db.references.keys().cloned().collect())
+    };
+
+    assert_eq!(names(&[])?, vec!["ecoli.k12.fa", "genome.fasta"]);
+    assert_eq!(names(&["--ref-name-style", "stem"])?, vec!["ecoli.k12", "genome"]);
+    assert_eq!(
+        names(&["--ref-name-style", "header"])?,
+        vec!["chr1 first assembly", "plasmid_2 second assembly"]
+    );
+    // Full paths keep same-named files from different directories apart
+    let path_names = names(&["--ref-name-style", "path"])?;
+    assert_eq!(path_names.len(), 2);
+    assert!(path_names[0].ends_with("one/ecoli.k12.fa"), "{:?}", path_names);
+    assert!(path_names[1].ends_with("two/genome.fasta"), "{:?}", path_names);
+    Ok(())
+}
+
+#[test]
+fn test_build_same_file_names_collide_unless_named_by_path() -> Result<(), Box<dyn std::error::Error>> {
+    let inputs = vec![("one/genome.fa", ">a\nACGTT\n"), ("two/genome.fa", ">b\nGGGGA\n")];
+    let temp_dir = TempDir::new()?;
+    let mut cmd = Command::cargo_bin("orion-kmer")?;
+    cmd.arg("build")
+        .arg("-k")
+        .arg("4")
+        .args(["--on-conflict", "error"])
+        .arg("-o")
+        .arg(temp_dir.path().join("collide.db"));
+    for (name, content) in &inputs {
+        let file_path = temp_dir.path().join(name);
+        fs::create_dir_all(file_path.parent().unwrap())?;
+        fs::write(&file_path, content)?;
+        cmd.arg("-g").arg(&file_path);
+    }
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("already exists in the database"));
+
+    let kmer_db = run_build_with_extra_args(4, inputs, &["--ref-name-style", "path"])?;
+    assert_eq!(kmer_db.num_references(), 2);
+    Ok(())
+}
+
+#[test]
+fn test_build_batch_size_does_not_change_results() -> Result<(), Box<dyn std::error::Error>> {
+    // Enough records for several batches, with a remainder in the last one
+    let genome: String = (0..250)
+        .map(|i| format!(">contig{}\n{}\n", i, ["ACGTTGCA", "GGGGATTC", "TTAGCNNCAT"][i % 3]))
+        .collect();
+    let inputs = vec![("genome.fa", genome.as_str())];
+
+    let default_db = run_build_with_extra_args(4, inputs.clone(), &[])?;
+    for batch_size in ["1", "7", "100000"] {
+        let batched_db = run_build_with_extra_args(4, inputs.clone(), &["--batch-size", batch_size])?;
+        assert_eq!(batched_db.references, default_db.references, "--batch-size {}", batch_size);
+    }
+    assert_eq!(default_db.references["genome.fa"].len(), 12);
+    Ok(())
+}
+
+#[test]
+fn test_build_report_invalid_windows() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let genome_path = temp_dir.path().join("genome.fa");
+    fs::write(&genome_path, ">chr1\nACGTNACGT\n>chr2\nNNNNAAAA\n")?;
+
+    // chr1: four windows overlap the N; chr2: four windows contain an N
+    Command::cargo_bin("orion-kmer")?
+        .arg("build")
+        .arg("-k")
+        .arg("4")
+        .arg("-o")
+        .arg(temp_dir.path().join("test_db.db"))
+        .arg("-g")
+        .arg(&genome_path)
+        .arg("--report-invalid")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "Skipped 8 k-mer windows containing N or other non-ACGT characters",
+        ));
+    Ok(())
+}
+
+#[test]
+fn test_build_min_multiplicity_drops_rare_kmers() -> Result<(), Box<dyn std::error::Error>> {
+    // k=3: AAA occurs 5 times (four in s1, TTT in s2), AAC twice (AAC in s1, GTT in s2)
+    let inputs = vec![("genome.fa", ">s1\nAAAAAAC\n>s2\nGTTT\n")];
+
+    let default_db = run_build_with_extra_args(3, inputs.clone(), &[])?;
+    assert_eq!(default_db.references["genome.fa"], kmers_from_strings(&["AAA", "AAC"], 3));
+
+    // Occurrences are counted across all records of the file
+    let db = run_build_with_extra_args(3, inputs.clone(), &["--min-multiplicity", "2"])?;
+    assert_eq!(db.references["genome.fa"], kmers_from_strings(&["AAA", "AAC"], 3));
+    let db = run_build_with_extra_args(3, inputs.clone(), &["--min-multiplicity", "3"])?;
+    assert_eq!(db.references["genome.fa"], kmers_from_strings(&["AAA"], 3));
+    let db = run_build_with_extra_args(3, inputs, &["--min-multiplicity", "6"])?;
+    assert!(db.references["genome.fa"].is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_build_profile_prints_phase_breakdown() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let genome_path = temp_dir.path().join("genome.fa");
+    fs::write(&genome_path, ">chr1\nACGTACGTTTGA\n")?;
+
+    Command::cargo_bin("orion-kmer")?
+        .args(["--profile", "build", "-k", "4", "-o"])
+        .arg(temp_dir.path().join("test_db.db"))
+        .arg("-g")
+        .arg(&genome_path)
+        .assert()
+        .success()
+        .stderr(
+            predicate::str::contains("Phase breakdown for build")
+                .and(predicate::str::contains("parse"))
+                .and(predicate::str::contains("k-mer extraction"))
+                .and(predicate::str::contains("set operations"))
+                .and(predicate::str::contains("serialize")),
+        );
+    Ok(())
+}
+
+#[test]
+fn test_build_k_list_writes_one_table_per_k() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let genome_path = temp_dir.path().join("genome.fa");
+    fs::write(&genome_path, ">g\nACGTACGTTTGCATC\n")?;
+    let reads_path = temp_dir.path().join("reads.fa");
+    fs::write(&reads_path, ">read\nACGTACGTTT\n")?;
+    let build = |extra_args: &[&str], output_path: &std::path::Path| -> Result<(), Box<dyn std::error::Error>> {
+        Command::cargo_bin("orion-kmer")?
+            .arg("build")
+            .args(extra_args)
+            .arg("-g")
+            .arg(&genome_path)
+            .arg("-o")
+            .arg(output_path)
+            .assert()
+            .success();
+        Ok(())
+    };
+    let multi_k_path = temp_dir.path().join("multi.db.gz");
+    build(&["--k-list", "5,4"], &multi_k_path)?;
+
+    // Each table matches a single-k build of the same genome
+    for k in [4u8, 5] {
+        let single_path = temp_dir.path().join(format!("k{}.db", k));
+        build(&["-k", &k.to_string()], &single_path)?;
+        let table = orion_kmer::utils::load_kmer_db_v2_for_k(&multi_k_path, Some(k))?;
+        assert_eq!(table.k, k);
+        assert_eq!(table.references, orion_kmer::utils::load_kmer_db_v2(&single_path)?.references);
+
+        let output_path = temp_dir.path().join(format!("query_k{}.txt", k));
+        Command::cargo_bin("orion-kmer")?
+            .args(["query", "-k", &k.to_string(), "-d"])
+            .arg(&multi_k_path)
+            .arg("-r")
+            .arg(&reads_path)
+            .arg("-o")
+            .arg(&output_path)
+            .assert()
+            .success();
+        assert_eq!(fs::read_to_string(&output_path)?, "read\n");
+    }
+
+    // Without a k, or with one that has no table, loading fails
+    let query_output = temp_dir.path().join("query.txt");
+    let mut query = Command::cargo_bin("orion-kmer")?;
+    query.args(["query", "-d"]).arg(&multi_k_path).arg("-r").arg(&reads_path).arg("-o").arg(&query_output);
+    query.assert().failure().stderr(predicate::str::contains("is a multi-k database"));
+    Command::cargo_bin("orion-kmer")?
+        .args(["classify", "-k", "6", "-i"])
+        .arg(&reads_path)
+        .arg("-d")
+        .arg(&multi_k_path)
+        .arg("-o")
+        .arg(temp_dir.path().join("classify.json"))
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Failed to load database"));
+    let error = orion_kmer::utils::load_kmer_db_v2_for_k(&multi_k_path, Some(6)).unwrap_err();
+    assert!(format!("{:#}", error).contains("has no table for k=6"));
+    Ok(())
+}
+
+#[test]
+fn test_build_overlap_matrix_counts_shared_kmers() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    // refA: ACGT, AACG (CGTT); refB: AACG (CGTT), CAAC (GTTG); refC: GGGG
+    let mut genome_paths = Vec::new();
+    for (name, sequence) in [("refA.fa", "ACGTT"), ("refB.fa", "CGTTG"), ("refC.fa", "GGGG")] {
+        let path = temp_dir.path().join(name);
+        fs::write(&path, format!(">{}\n{}\n", name, sequence))?;
+        genome_paths.push(path);
+    }
+    let matrix_path = temp_dir.path().join("overlap.tsv");
+    Command::cargo_bin("orion-kmer")?
+        .args(["build", "-k", "4", "-g"])
+        .args(&genome_paths)
+        .arg("-o")
+        .arg(temp_dir.path().join("refs.db"))
+        .arg("--overlap-matrix")
+        .arg(&matrix_path)
+        .assert()
+        .success();
+
+    assert_eq!(
+        fs::read_to_string(&matrix_path)?,
+        "reference\trefA.fa\trefB.fa\trefC.fa\n\
+         refA.fa\t2\t1\t0\n\
+         refB.fa\t1\t2\t0\n\
+         refC.fa\t0\t0\t1\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_build_max_kmers_per_reference_samples_reproducibly() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let big_path = temp_dir.path().join("big.fa");
+    let small_path = temp_dir.path().join("small.fa");
+    fs::write(&big_path, ">big\nACGTTGCAAGGCTTACCGATTAGCCATGGACTTAGC\n")?;
+    fs::write(&small_path, ">small\nGGGGA\n")?;
+    let build = |output_name: &str, seed: &str| -> Result<KmerDbV2, Box<dyn std::error::Error>> {
+        let db_path = temp_dir.path().join(output_name);
+        Command::cargo_bin("orion-kmer")?
+            .args(["build", "-k", "4", "-g"])
+            .arg(&big_path)
+            .arg(&small_path)
+            .arg("-o")
+            .arg(&db_path)
+            .args(["--max-kmers-per-reference", "8", "--sampling-seed", seed])
+            .assert()
+            .success();
+        Ok(orion_kmer::utils::load_kmer_db_v2(&db_path)?)
+    };
+
+    let big_sequence = b"ACGTTGCAAGGCTTACCGATTAGCCATGGACTTAGC";
+    let full_big_kmers: HashSet<u64> = big_sequence
+        .windows(4)
+        .map(|window| canonical_u64(seq_to_u64(window, 4).unwrap(), 4))
+        .collect();
+
+    let sampled = build("sampled.db", "3")?;
+    assert_eq!(sampled.references["big.fa"].len(), 8);
+    assert!(sampled.references["big.fa"].is_subset(&full_big_kmers));
+    assert_eq!(sampled.references["small.fa"].len(), 2);
+    let rate = sampled.sampling_rates["big.fa"];
+    assert!((rate - 8.0 / full_big_kmers.len() as f64).abs() < 1e-12);
+    assert!(!sampled.sampling_rates.contains_key("small.fa"));
+    assert_eq!(build("again.db", "3")?.references, sampled.references);
+
+    // classify reports the rate of sampled references only
+    let reads_path = temp_dir.path().join("reads.fa");
+    fs::write(&reads_path, ">r1\nACGTTGCAAGGCTTACCGATTAGCCATGGACTTAGC\n>r2\nGGGGA\n")?;
+    let json_path = temp_dir.path().join("classify.json");
+    Command::cargo_bin("orion-kmer")?
+        .arg("classify")
+        .arg("-i")
+        .arg(&reads_path)
+        .arg("-d")
+        .arg(temp_dir.path().join("sampled.db"))
+        .arg("-o")
+        .arg(&json_path)
+        .assert()
+        .success();
+    let report: serde_json::Value = serde_json::from_str(&fs::read_to_string(&json_path)?)?;
+    let references = report["databases_analyzed"][0]["references"].as_array().unwrap();
+    for reference in references {
+        match reference["reference_name"].as_str().unwrap() {
+            "big.fa" => assert_eq!(reference["kmer_sampling_rate"].as_f64(), Some(rate)),
+            _ => assert!(reference.get("kmer_sampling_rate").is_none()),
+        }
+    }
+    assert_eq!(references.len(), 2);
+    Ok(())
+}
+
+#[test]
+fn test_build_decompress_threads_reads_zstd_genome() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let genome = b">chr1\nACGTTGCAAGGCTTACCGATTAGCCATGGACTTAGC\n>chr2\nGGGGAAAACCCCTTTT\n";
+    let plain_path = temp_dir.path().join("genome.fa");
+    fs::write(&plain_path, genome)?;
+    let zst_path = temp_dir.path().join("genome.fa.zst");
+    fs::write(&zst_path, zstd::encode_all(&genome[..], 3)?)?;
+
+    let build = |genome_path: &PathBuf, extra_args: &[&str]| -> Result<KmerDbV2, Box<dyn std::error::Error>> {
+        let db_path = temp_dir.path().join("genome.db");
+        Command::cargo_bin("orion-kmer")?
+            .args(["build", "-k", "5", "-g"])
+            .arg(genome_path)
+            .arg("-o")
+            .arg(&db_path)
+            .args(extra_args)
+            .assert()
+            .success();
+        Ok(orion_kmer::utils::load_kmer_db_v2(&db_path)?)
+    };
+    let expected = build(&plain_path, &[])?.references["genome.fa"].clone();
+    for extra_args in [&["--decompress-threads", "3"][..], &["--decompress-threads", "3", "--per-record"][..]] {
+        let kmer_db = build(&zst_path, extra_args)?;
+        let all_kmers: HashSet<u64> = kmer_db.references.values().flatten().copied().collect();
+        assert_eq!(all_kmers, expected);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_build_minimizer_canonical_policy_stores_strand_with_smaller_minimizer() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    // TAGGG's 2-mer minimizer AG is smaller than CC of its reverse complement CCCTA,
+    // although CCCTA is the lexicographically smaller k-mer
+    let genome_path = temp_dir.path().join("genome.fa");
+    fs::write(&genome_path, ">chr1\nTAGGG\n")?;
+    let build = |db_name: &str, extra_args: &[&str]| {
+        let mut cmd = Command::cargo_bin("orion-kmer").unwrap();
+        cmd.args(["build", "-k", "5", "-g"])
+            .arg(&genome_path)
+            .arg("-o")
+            .arg(temp_dir.path().join(db_name))
+            .args(extra_args);
+        cmd
+    };
+    build("lexicographic.db", &[]).assert().success();
+    build("minimizer.db", &["--canonical-policy", "minimizer", "--minimizer-length", "2"])
+        .assert()
+        .success();
+
+    let lexicographic = orion_kmer::utils::load_kmer_db_v2(&temp_dir.path().join("lexicographic.db"))?;
+    assert_eq!(lexicographic.canonical_policy, CanonicalPolicy::Lexicographic);
+    assert_eq!(lexicographic.references["genome.fa"], HashSet::from([seq_to_u64(b"CCCTA", 5).unwrap()]));
+    let minimizer = orion_kmer::utils::load_kmer_db_v2(&temp_dir.path().join("minimizer.db"))?;
+    assert_eq!(minimizer.canonical_policy, CanonicalPolicy::Minimizer { m: 2 });
+    assert_eq!(minimizer.references["genome.fa"], HashSet::from([seq_to_u64(b"TAGGG", 5).unwrap()]));
+
+    Command::cargo_bin("orion-kmer")?
+        .arg("info")
+        .arg("-d")
+        .arg(temp_dir.path().join("minimizer.db"))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("canonical\ttrue\ncanonical_policy\tminimizer (m=2)\n"));
+
+    // Appending with another policy, an out-of-range m and sorted indexes are rejected
+    build("appended.db", &["--append"])
+        .arg(temp_dir.path().join("minimizer.db"))
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("incompatible canonical policies"));
+    build("long_m.db", &["--canonical-policy", "minimizer", "--minimizer-length", "6"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--minimizer-length must be between 1 and the k-mer size 5, got 6"));
+    build("indexed.db", &["--canonical-policy", "minimizer", "--minimizer-length", "2", "--sorted-index"])
+        .arg(temp_dir.path().join("indexed.kidx"))
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Sorted k-mer indexes only hold lexicographically canonical k-mers"));
+    Ok(())
+}
+
+#[test]
+fn test_build_rejects_protein_genome_unless_allowed() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let genome = temp_dir.path().join("proteome.fa");
+    fs::write(&genome, ">sp|P69905|HBA_HUMAN\nMVLSPADKTNVKAAWGKVGAHAGEYGAEALERMFLSFPTTK\n")?;
+    for per_record in [false, true] {
+        let mut command = Command::cargo_bin("orion-kmer")?;
+        command
+            .arg("build")
+            .args(["-k", "5", "-g"])
+            .arg(&genome)
+            .arg("-o")
+            .arg(temp_dir.path().join("proteome.db"));
+        if per_record {
+            command.arg("--per-record");
+        }
+        command
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("Input appears to be protein or non-DNA"))
+            .stderr(predicate::str::contains("--allow-non-dna"));
+    }
+
+    Command::cargo_bin("orion-kmer")?
+        .arg("build")
+        .args(["-k", "5", "-g"])
+        .arg(&genome)
+        .arg("-o")
+        .arg(temp_dir.path().join("proteome.db"))
+        .arg("--allow-non-dna")
+        .assert()
+        .success();
+    let kmer_db = orion_kmer::utils::load_kmer_db_v2(&temp_dir.path().join("proteome.db"))?;
+    assert!(kmer_db.references["proteome.fa"].is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_build_reads_primary_reads_of_bam_in_sequenced_orientation() -> Result<(), Box<dyn std::error::Error>> {
+    use noodles::sam::{
+        self,
+        alignment::{io::Write as _, record::Flags, record_buf::QualityScores, RecordBuf},
+    };
+    let temp_dir = TempDir::new()?;
+    let bam_path = temp_dir.path().join("reads.bam");
+    let header = sam::Header::default();
+    let mut writer = noodles::bam::io::Writer::new(File::create(&bam_path)?);
+    writer.write_header(&header)?;
+    // read2 was aligned to the reverse strand, so its sequenced bases are the reverse complement
+    // of the stored ones; the secondary copy of read1 is skipped
+    for (name, seq, flags) in [
+        ("read1", "ACGTTGCA", Flags::UNMAPPED),
+        ("read2", "CCGGTTAA", Flags::REVERSE_COMPLEMENTED),
+        ("read1", "GGGGGGGG", Flags::SECONDARY),
+    ] {
+        let record = RecordBuf::builder()
+            .set_name(name)
+            .set_flags(flags)
+            .set_sequence(seq.as_bytes().into())
+            .set_quality_scores(QualityScores::from(vec![30; seq.len()]))
+            .build();
+        writer.write_alignment_record(&header, &record)?;
+    }
+    writer.finish(&header)?;
+    drop(writer);
+
+    let db_path = temp_dir.path().join("reads.db");
+    Command::cargo_bin("orion-kmer")?
+        .arg("build")
+        .args(["-k", "6", "--per-record", "--no-canonical", "-g"])
+        .arg(&bam_path)
+        .arg("-o")
+        .arg(&db_path)
+        .assert()
+        .success();
+    let kmer_db = orion_kmer::utils::load_kmer_db_v2(&db_path)?;
+    let kmers_of = |seq: &str| -> HashSet<u64> {
+        (0..=seq.len() - 6).map(|start| seq_to_u64(&seq.as_bytes()[start..start + 6], 6).unwrap()).collect()
+    };
+    assert_eq!(kmer_db.references.len(), 2);
+    assert_eq!(kmer_db.references["read1"], kmers_of("ACGTTGCA"));
+    assert_eq!(kmer_db.references["read2"], kmers_of("TTAACCGG"));
+    Ok(())
+}
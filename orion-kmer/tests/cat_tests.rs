@@ -0,0 +1,71 @@
+use assert_cmd::prelude::*;
+use predicates::prelude::*;
+use std::{fs, process::Command};
+use tempfile::TempDir;
+
+#[test]
+fn test_cat_transcodes_between_compressions() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    let plain_path = dir.path().join("reads.fq");
+    let reads = "@r1\nACGTACGT\n+\nIIIIIIII\n@r2\nTTGCA\n+\n#####\n";
+    fs::write(&plain_path, reads)?;
+
+    // Each step reads the previous file and writes the next compression
+    let mut previous = plain_path.clone();
+    for name in ["reads.fq.gz", "reads.fq.bz2", "reads.fq.xz", "reads.fq.zst", "reads.fq.bgz"] {
+        let next = dir.path().join(name);
+        Command::cargo_bin("orion-kmer")?
+            .arg("cat")
+            .arg(&previous)
+            .arg("-o")
+            .arg(&next)
+            .assert()
+            .success();
+        assert_ne!(fs::read(&next)?, reads.as_bytes(), "{} should be compressed", name);
+        previous = next;
+    }
+    Command::cargo_bin("orion-kmer")?
+        .arg("cat")
+        .arg(&previous)
+        .assert()
+        .success()
+        .stdout(reads);
+
+    // The .bz2 output is also read as sequence input
+    let counts_path = dir.path().join("counts.tsv");
+    Command::cargo_bin("orion-kmer")?
+        .args(["count", "-k", "4", "-i"])
+        .arg(dir.path().join("reads.fq.bz2"))
+        .arg("-o")
+        .arg(&counts_path)
+        .assert()
+        .success();
+    assert!(fs::read_to_string(&counts_path)?.contains("ACGT\t2\n"));
+    Ok(())
+}
+
+#[test]
+fn test_cat_rejects_7z_without_creating_output() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    let plain_path = dir.path().join("genome.fa");
+    fs::write(&plain_path, ">g\nACGT\n")?;
+    let sevenz_path = dir.path().join("genome.fa.7z");
+    Command::cargo_bin("orion-kmer")?
+        .arg("cat")
+        .arg(&plain_path)
+        .arg("-o")
+        .arg(&sevenz_path)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("7z archives are not supported"));
+    assert!(!sevenz_path.exists());
+
+    fs::write(&sevenz_path, b"7z\xbc\xaf\x27\x1c")?;
+    Command::cargo_bin("orion-kmer")?
+        .arg("cat")
+        .arg(&sevenz_path)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("7z archives are not supported"));
+    Ok(())
+}
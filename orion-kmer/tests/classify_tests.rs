@@ -1,10 +1,13 @@
 use assert_cmd::prelude::*;
 use csv;
+use orion_kmer::db_types::{KmerCountTable, KmerDbV2};
+use orion_kmer::utils::write_kmer_db;
+use orion_kmer::kmer::{canonical_u64, seq_to_u64};
 use predicates::prelude::*;
 use serde_json::Value as JsonValue;
 use std::{
     fs::{self, File},
-    io::Write,
+    io::{Read, Write},
     path::PathBuf, // Path is not directly used
     process::Command,
 };
@@ -109,6 +112,38 @@ fn run_classify_get_json(
     Ok(json_data)
 }
 
+// Helper to run classify with additional command-line arguments and return the parsed JSON output
+fn run_classify_with_extra_args(
+    input_content: &str,
+    input_filename: &str,
+    db_paths: &[PathBuf],
+    extra_args: &[&str],
+) -> Result<JsonValue, Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let main_input_file_path = temp_dir.path().join(input_filename);
+    let mut main_input_file = File::create(&main_input_file_path)?;
+    writeln!(main_input_file, "{}", input_content)?;
+    let output_json_path = temp_dir.path().join("classify_output.json");
+
+    let mut cmd = Command::cargo_bin("orion-kmer")?;
+    let project_root = std::env::var("CARGO_MANIFEST_DIR").unwrap_or_else(|_| ".".to_string());
+    cmd.current_dir(project_root);
+    cmd.arg("classify")
+        .arg("-i")
+        .arg(&main_input_file_path)
+        .arg("-o")
+        .arg(&output_json_path);
+    for db_path in db_paths {
+        cmd.arg("-d").arg(db_path);
+    }
+    cmd.args(extra_args);
+
+    cmd.assert().success();
+
+    let json_output_str = fs::read_to_string(&output_json_path)?;
+    Ok(serde_json::from_str(&json_output_str)?)
+}
+
 // --- Test Cases ---
 
 const INPUT_FASTA_BASIC: &str =
@@ -507,6 +542,28 @@ fn test_classify_k_validation_error() -> Result<(), Box<dyn std::error::Error>>
     Ok(())
 }
 
+#[test]
+fn test_classify_log_format_json_writes_only_json_lines() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let db_path = build_db_for_classify(4, vec![("ref.fa", DB1_REF1_FASTA)], &temp_dir, "json_log")?;
+    let input_path = temp_dir.path().join("reads.fa");
+    fs::write(&input_path, DB1_REF1_FASTA)?;
+    let output = Command::cargo_bin("orion-kmer")?
+        .args(["--log-format", "json", "-vv", "classify", "-i"])
+        .arg(&input_path)
+        .arg("-d")
+        .arg(&db_path)
+        .arg("-o")
+        .arg(temp_dir.path().join("classify.json"))
+        .output()?;
+    assert!(output.status.success());
+    for line in String::from_utf8(output.stderr)?.lines() {
+        let record: JsonValue = serde_json::from_str(line).map_err(|e| format!("{}: {:?}", e, line))?;
+        assert!(record["message"].is_string());
+    }
+    Ok(())
+}
+
 #[test]
 fn test_classify_k_mismatch_between_databases() -> Result<(), Box<dyn std::error::Error>> {
     let temp_db_storage = TempDir::new()?;
@@ -545,11 +602,161 @@ fn test_classify_k_mismatch_between_databases() -> Result<(), Box<dyn std::error
     Ok(())
 }
 
-// TODO: Add tests for FASTQ input
 // TODO: Add test for empty input file (results in 0s for most things)
-// TODO: Add test for no matches
-// TODO: Add test for input sequences shorter than k
-// TODO: Add test for empty database or reference within a database (how build handles this - it should create an empty set)
+
+#[test]
+fn test_classify_fastq_input_matches_fasta() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_db_storage = TempDir::new()?;
+    let db_path = build_db_for_classify(
+        4,
+        vec![("db1_refA.fa", DB1_REF1_FASTA), ("db1_refB.fa", DB1_REF2_FASTA)],
+        &temp_db_storage,
+        "db_fastq",
+    )?;
+
+    // Quality lines are parsed past, not read as sequence; '@' opens a quality line on purpose
+    let fastq = "@read1\nACGTACGT\n+\n@IIIIIII\n@read2\nTTTTGGGG\n+read2\nIIII####";
+    let fasta = ">read1\nACGTACGT\n>read2\nTTTTGGGG";
+    let from_fastq = run_classify_with_extra_args(fastq, "reads.fastq", std::slice::from_ref(&db_path), &["--min-coverage", "0"])?;
+    let from_fasta = run_classify_with_extra_args(fasta, "reads.fa", &[db_path], &["--min-coverage", "0"])?;
+
+    assert_eq!(from_fastq["total_unique_kmers_in_input"], 8);
+    assert_eq!(from_fastq["databases_analyzed"], from_fasta["databases_analyzed"]);
+    let references = from_fastq["databases_analyzed"][0]["references"].as_array().unwrap();
+    assert_eq!(references[0]["reference_name"], "db1_refA.fa");
+    assert_eq!(references[0]["reference_breadth_of_coverage"].as_f64(), Some(1.0));
+    Ok(())
+}
+
+#[test]
+fn test_classify_no_matching_kmers_reports_zeros() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_db_storage = TempDir::new()?;
+    let db_path = build_db_for_classify(
+        4,
+        vec![("db1_refA.fa", DB1_REF1_FASTA), ("db1_refB.fa", DB1_REF2_FASTA)],
+        &temp_db_storage,
+        "db_no_match",
+    )?;
+
+    // CCCC (and its reverse complement GGGG) is in neither reference
+    let results = run_classify_with_extra_args(
+        ">read1\nCCCCCCCC",
+        "reads.fa",
+        &[db_path],
+        &["--min-coverage", "0", "--weight-by-uniqueness"],
+    )?;
+    assert_eq!(results["total_unique_kmers_in_input"], 1);
+    let db_res = &results["databases_analyzed"][0];
+    for field in [
+        "overall_input_kmers_matched_in_db",
+        "overall_sum_depth_of_matched_kmers_in_input",
+        "overall_avg_depth_of_matched_kmers_in_input",
+        "proportion_input_kmers_in_db_overall",
+        "proportion_db_kmers_covered_overall",
+    ] {
+        assert_eq!(db_res[field].as_f64(), Some(0.0), "{}", field);
+    }
+    let references = db_res["references"].as_array().unwrap();
+    assert_eq!(references.len(), 2);
+    for ref_res in references {
+        for field in [
+            "input_kmers_hitting_reference",
+            "sum_depth_of_matched_kmers_in_input",
+            "avg_depth_of_matched_kmers_in_input",
+            "proportion_input_kmers_hitting_reference",
+            "reference_breadth_of_coverage",
+            "weighted_avg_depth_of_matched_kmers_in_input",
+            "weighted_reference_breadth_of_coverage",
+        ] {
+            assert_eq!(ref_res[field].as_f64(), Some(0.0), "{}", field);
+        }
+    }
+    Ok(())
+}
+
+// Asserts every numeric field of a reference result is zero (and a number, not NaN or null).
+fn assert_zero_reference_result(ref_res: &JsonValue) {
+    for field in [
+        "total_kmers_in_reference",
+        "input_kmers_hitting_reference",
+        "sum_depth_of_matched_kmers_in_input",
+        "avg_depth_of_matched_kmers_in_input",
+        "proportion_input_kmers_hitting_reference",
+        "reference_breadth_of_coverage",
+        "weighted_avg_depth_of_matched_kmers_in_input",
+        "weighted_reference_breadth_of_coverage",
+    ] {
+        assert_eq!(ref_res[field].as_f64(), Some(0.0), "{}", field);
+    }
+}
+
+#[test]
+fn test_classify_empty_reference_reports_zeros() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_db_storage = TempDir::new()?;
+    // A record shorter than k builds a reference with an empty k-mer set
+    let db_path = build_db_for_classify(
+        4,
+        vec![("empty.fa", ">short\nACG"), ("refA.fa", DB1_REF1_FASTA)],
+        &temp_db_storage,
+        "db_empty_ref",
+    )?;
+
+    let results = run_classify_with_extra_args(
+        INPUT_FASTA_BASIC,
+        "input.fa",
+        std::slice::from_ref(&db_path),
+        &["--min-coverage", "0", "--weight-by-uniqueness"],
+    )?;
+    let references = results["databases_analyzed"][0]["references"].as_array().unwrap();
+    assert_eq!(references.len(), 2);
+    assert_eq!(references[0]["reference_name"], "empty.fa");
+    assert_zero_reference_result(&references[0]);
+    assert_eq!(references[1]["reference_breadth_of_coverage"].as_f64(), Some(1.0));
+
+    // Any positive --min-coverage filters the empty reference out
+    let results = run_classify_get_json(INPUT_FASTA_BASIC, "input.fa", &[db_path], None, None, Some(0.01), None)?;
+    let references = results["databases_analyzed"][0]["references"].as_array().unwrap();
+    let names: Vec<&str> = references.iter().map(|r| r["reference_name"].as_str().unwrap()).collect();
+    assert_eq!(names, vec!["refA.fa"]);
+    Ok(())
+}
+
+#[test]
+fn test_classify_empty_database_produces_valid_json() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_db_storage = TempDir::new()?;
+    let db_path = temp_db_storage.path().join("no_references.db");
+    write_kmer_db(&KmerDbV2::new(4), &db_path)?;
+    let groups_path = temp_db_storage.path().join("groups.tsv");
+    fs::write(&groups_path, "refA.fa\tgenus1\n")?;
+
+    for extra_args in [vec!["--min-coverage", "0"], vec!["--weight-by-uniqueness", "--groups", groups_path.to_str().unwrap()]] {
+        let results = run_classify_with_extra_args(INPUT_FASTA_BASIC, "input.fa", std::slice::from_ref(&db_path), &extra_args)?;
+        assert_eq!(results["total_unique_kmers_in_input"], 8);
+        let db_res = &results["databases_analyzed"][0];
+        assert_eq!(db_res["database_kmer_size"], 4);
+        assert!(db_res["references"].as_array().unwrap().is_empty());
+        for field in [
+            "total_unique_kmers_in_db_across_references",
+            "overall_input_kmers_matched_in_db",
+            "overall_sum_depth_of_matched_kmers_in_input",
+            "overall_avg_depth_of_matched_kmers_in_input",
+            "proportion_input_kmers_in_db_overall",
+            "proportion_db_kmers_covered_overall",
+        ] {
+            assert_eq!(db_res[field].as_f64(), Some(0.0), "{}", field);
+        }
+    }
+
+    // Groups whose members are all missing are left out
+    let results = run_classify_with_extra_args(
+        INPUT_FASTA_BASIC,
+        "input.fa",
+        &[db_path],
+        &["--groups", groups_path.to_str().unwrap()],
+    )?;
+    assert!(results["groups"].as_array().unwrap().is_empty());
+    Ok(())
+}
 
 #[test]
 fn test_classify_min_coverage_filter() -> Result<(), Box<dyn std::error::Error>> {
@@ -737,3 +944,1298 @@ fn test_classify_output_tsv() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+#[test]
+fn test_classify_min_reference_hits_filter() -> Result<(), Box<dyn std::error::Error>> {
+    let k = 4;
+    let temp_db_storage = TempDir::new()?;
+    let db_path = build_db_for_classify(
+        k,
+        vec![
+            ("db1_refA.fa", DB1_REF1_FASTA),
+            ("db1_refB.fa", DB1_REF2_FASTA),
+        ],
+        &temp_db_storage,
+        "db_minrefhits",
+    )?;
+
+    // refA is hit by 3 distinct input k-mers, refB only by AAAA
+    let results = run_classify_with_extra_args(
+        INPUT_FASTA_BASIC,
+        "input.fa",
+        &[db_path],
+        &["--min-reference-hits", "2"],
+    )?;
+
+    let references = results["databases_analyzed"][0]["references"]
+        .as_array()
+        .unwrap();
+    assert_eq!(references.len(), 1);
+    assert_eq!(references[0]["reference_name"], "db1_refA.fa");
+    assert_eq!(references[0]["input_kmers_hitting_reference"], 3);
+    Ok(())
+}
+
+#[test]
+fn test_classify_dump_input_kmers() -> Result<(), Box<dyn std::error::Error>> {
+    let k = 4;
+    let temp_db_storage = TempDir::new()?;
+    let db_path = build_db_for_classify(
+        k,
+        vec![("db_ref.fa", DB1_REF1_FASTA)],
+        &temp_db_storage,
+        "db_dump",
+    )?;
+
+    // ACGTACGT -> ACGT:2, CGTA:2 (CGTA + TACG), GTAC:1; GTAC is dropped by min_kmer_frequency 2
+    let dump_path = temp_db_storage.path().join("input_kmers.bin");
+    run_classify_with_extra_args(
+        ">S1\nACGTACGT",
+        "input_dump.fa",
+        &[db_path],
+        &[
+            "--min-kmer-frequency",
+            "2",
+            "--dump-input-kmers",
+            dump_path.to_str().unwrap(),
+        ],
+    )?;
+
+    let count_table: KmerCountTable = bincode::deserialize(&fs::read(&dump_path)?)?;
+    assert_eq!(count_table.k, k);
+    assert_eq!(count_table.counts.len(), 2);
+    for kmer in [b"ACGT", b"CGTA"] {
+        let canonical_kmer = canonical_u64(seq_to_u64(kmer, k).unwrap(), k);
+        assert_eq!(count_table.counts[&canonical_kmer], 2);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_classify_full_tsv_includes_filtered_references() -> Result<(), Box<dyn std::error::Error>> {
+    let k = 4;
+    let temp_db_storage = TempDir::new()?;
+    let db_path = build_db_for_classify(
+        k,
+        vec![
+            ("db1_refA.fa", DB1_REF1_FASTA),
+            ("db1_refB.fa", DB1_REF2_FASTA),
+        ],
+        &temp_db_storage,
+        "db_fulltsv",
+    )?;
+
+    let tsv_path = temp_db_storage.path().join("filtered.tsv");
+    let full_tsv_path = temp_db_storage.path().join("full.tsv");
+    let results = run_classify_with_extra_args(
+        INPUT_FASTA_BASIC,
+        "input_full.fa",
+        &[db_path],
+        &[
+            "--min-reference-hits",
+            "2",
+            "--output-tsv",
+            tsv_path.to_str().unwrap(),
+            "--full-tsv",
+            full_tsv_path.to_str().unwrap(),
+        ],
+    )?;
+
+    // JSON and --output-tsv keep only the references passing the filter
+    let references = results["databases_analyzed"][0]["references"]
+        .as_array()
+        .unwrap();
+    assert_eq!(references.len(), 1);
+    let filtered_tsv = fs::read_to_string(&tsv_path)?;
+    assert_eq!(filtered_tsv.lines().count(), 2); // header + db1_refA
+
+    let full_tsv = fs::read_to_string(&full_tsv_path)?;
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(b'\t')
+        .from_reader(full_tsv.as_bytes());
+    let headers = reader.headers()?.clone();
+    assert_eq!(headers.len(), 10);
+    assert_eq!(&headers[9], "PassedFilter");
+
+    let records: Vec<csv::StringRecord> = reader.records().collect::<Result<_, _>>()?;
+    assert_eq!(records.len(), 2);
+    let record_a = records.iter().find(|r| &r[2] == "db1_refA.fa").unwrap();
+    assert_eq!(&record_a[4], "3");
+    assert_eq!(&record_a[9], "true");
+    let record_b = records.iter().find(|r| &r[2] == "db1_refB.fa").unwrap();
+    assert_eq!(&record_b[4], "1");
+    assert_eq!(&record_b[9], "false");
+    Ok(())
+}
+
+#[test]
+fn test_classify_comma_delimiter_writes_csv() -> Result<(), Box<dyn std::error::Error>> {
+    let k = 4;
+    let temp_db_storage = TempDir::new()?;
+    let db_path = build_db_for_classify(
+        k,
+        vec![
+            ("db1_refA.fa", DB1_REF1_FASTA),
+            ("db1_refB.fa", DB1_REF2_FASTA),
+        ],
+        &temp_db_storage,
+        "db_csv",
+    )?;
+
+    let csv_path = temp_db_storage.path().join("summary.csv");
+    let full_csv_path = temp_db_storage.path().join("full.csv");
+    run_classify_with_extra_args(
+        INPUT_FASTA_BASIC,
+        "input_csv.fa",
+        &[db_path],
+        &[
+            "--delimiter",
+            "comma",
+            "--output-tsv",
+            csv_path.to_str().unwrap(),
+            "--full-tsv",
+            full_csv_path.to_str().unwrap(),
+        ],
+    )?;
+
+    for path in [&csv_path, &full_csv_path] {
+        let table = fs::read_to_string(path)?;
+        assert!(!table.contains('\t'));
+        assert!(table.starts_with("InputFile,Database,Reference,"));
+        let mut reader = csv::Reader::from_reader(table.as_bytes());
+        let records: Vec<csv::StringRecord> = reader.records().collect::<Result<_, _>>()?;
+        assert_eq!(records.len(), 2);
+        let record_a = records.iter().find(|r| &r[2] == "db1_refA.fa").unwrap();
+        assert_eq!(&record_a[4], "3");
+    }
+    Ok(())
+}
+
+#[test]
+fn test_classify_weight_by_uniqueness() -> Result<(), Box<dyn std::error::Error>> {
+    let k = 4;
+    let temp_db_storage = TempDir::new()?;
+    // ref1: ACGT, CGTT; ref2: ACGT, CGTA, GTAC. ACGT is shared and weighs 1/2.
+    let db_path = build_db_for_classify(
+        k,
+        vec![("ref1.fa", ">r1\nACGTT"), ("ref2.fa", ">r2\nACGTAC")],
+        &temp_db_storage,
+        "db_weighted",
+    )?;
+
+    // Input k-mers: ACGT x1, CGTT x1
+    let unweighted =
+        run_classify_with_extra_args(">s1\nACGTT", "input.fa", std::slice::from_ref(&db_path), &[])?;
+    for reference in unweighted["databases_analyzed"][0]["references"]
+        .as_array()
+        .unwrap()
+    {
+        assert!(reference.get("weighted_reference_breadth_of_coverage").is_none());
+    }
+
+    let results = run_classify_with_extra_args(
+        ">s1\nACGTT",
+        "input.fa",
+        &[db_path],
+        &["--weight-by-uniqueness"],
+    )?;
+    let references = results["databases_analyzed"][0]["references"]
+        .as_array()
+        .unwrap();
+    let find_ref = |name: &str| {
+        references
+            .iter()
+            .find(|r| r["reference_name"] == name)
+            .unwrap()
+    };
+
+    let ref1 = find_ref("ref1.fa");
+    assert_eq!(ref1["reference_breadth_of_coverage"].as_f64().unwrap(), 1.0);
+    assert_eq!(ref1["weighted_reference_breadth_of_coverage"].as_f64().unwrap(), 1.0);
+    assert_eq!(ref1["weighted_avg_depth_of_matched_kmers_in_input"].as_f64().unwrap(), 1.0);
+
+    // Only the shared ACGT hits ref2: raw 1/3, weighted 0.5 / (0.5 + 1 + 1)
+    let ref2 = find_ref("ref2.fa");
+    assert!((ref2["reference_breadth_of_coverage"].as_f64().unwrap() - 1.0 / 3.0).abs() < 1e-9);
+    assert!((ref2["weighted_reference_breadth_of_coverage"].as_f64().unwrap() - 0.2).abs() < 1e-9);
+    Ok(())
+}
+
+#[test]
+fn test_classify_read_filters_fasta_and_fastq() -> Result<(), Box<dyn std::error::Error>> {
+    let k = 4;
+    let temp_db_storage = TempDir::new()?;
+    let db_path = build_db_for_classify(
+        k,
+        vec![("db1_refA.fa", DB1_REF1_FASTA)],
+        &temp_db_storage,
+        "db_readfilter",
+    )?;
+
+    // The low-quality read is skipped; the good one hits every refA k-mer
+    let fastq = "@good\nACGTACGT\n+\nIIIIIIII\n@bad\nACGTAC\n+\n!!!!!!";
+    let results = run_classify_with_extra_args(
+        fastq,
+        "reads.fq",
+        std::slice::from_ref(&db_path),
+        &["--min-mean-qual", "20", "--min-read-length", "5"],
+    )?;
+    assert_eq!(results["input_reads_filtered"], 1);
+    assert_eq!(results["total_unique_kmers_in_input"], 3);
+
+    // FASTA input has no qualities, so only the length filter applies
+    let results = run_classify_with_extra_args(
+        ">short\nACG\n>long\nACGTACGT",
+        "reads.fa",
+        &[db_path],
+        &["--min-mean-qual", "20", "--min-read-length", "5"],
+    )?;
+    assert_eq!(results["input_reads_filtered"], 1);
+    assert_eq!(results["total_unique_kmers_in_input"], 3);
+    Ok(())
+}
+
+#[test]
+fn test_classify_references_sorted_by_name() -> Result<(), Box<dyn std::error::Error>> {
+    let k = 4;
+    let temp_db_storage = TempDir::new()?;
+    let db_path = build_db_for_classify(
+        k,
+        vec![
+            ("zeta.fa", DB1_REF1_FASTA),
+            ("alpha.fa", DB1_REF2_FASTA),
+            ("mid.fa", DB2_REF1_FASTA),
+        ],
+        &temp_db_storage,
+        "db_sorted",
+    )?;
+
+    let results =
+        run_classify_with_extra_args(INPUT_FASTA_BASIC, "input.fa", &[db_path], &[])?;
+    let names: Vec<&str> = results["databases_analyzed"][0]["references"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|r| r["reference_name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, vec!["alpha.fa", "mid.fa", "zeta.fa"]);
+    Ok(())
+}
+
+#[test]
+fn test_classify_mmap_sorted_index_matches_in_memory() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let ref_a_path = temp_dir.path().join("refA.fa");
+    let ref_b_path = temp_dir.path().join("refB.fa");
+    fs::write(&ref_a_path, DB1_REF1_FASTA)?;
+    fs::write(&ref_b_path, DB1_REF2_FASTA)?;
+    let db_path = temp_dir.path().join("db1.db");
+    let index_path = temp_dir.path().join("db1.kidx");
+    Command::cargo_bin("orion-kmer")?
+        .arg("build")
+        .arg("-k")
+        .arg("4")
+        .arg("-g")
+        .arg(&ref_a_path)
+        .arg("-g")
+        .arg(&ref_b_path)
+        .arg("-o")
+        .arg(&db_path)
+        .arg("--sorted-index")
+        .arg(&index_path)
+        .assert()
+        .success();
+
+    let in_memory =
+        run_classify_with_extra_args(INPUT_FASTA_BASIC, "input.fa", &[db_path], &["--min-coverage", "0"])?;
+    let mapped = run_classify_with_extra_args(
+        INPUT_FASTA_BASIC,
+        "input.fa",
+        &[index_path],
+        &["--min-coverage", "0", "--mmap"],
+    )?;
+    let in_memory_db = &in_memory["databases_analyzed"][0];
+    let mapped_db = &mapped["databases_analyzed"][0];
+    assert_eq!(mapped_db["references"], in_memory_db["references"]);
+    assert_eq!(mapped_db["references"].as_array().unwrap().len(), 2);
+    for field in [
+        "total_unique_kmers_in_db_across_references",
+        "overall_input_kmers_matched_in_db",
+        "overall_sum_depth_of_matched_kmers_in_input",
+    ] {
+        assert_eq!(mapped_db[field], in_memory_db[field], "{}", field);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_classify_many_references_matches_per_reference_scan() -> Result<(), Box<dyn std::error::Error>> {
+    // Enough references for in-memory classify to use its inverted index; --mmap scans each one
+    let temp_dir = TempDir::new()?;
+    let sequences = [
+        "ACGTACGTACGT", "GGGAAAAATTTT", "ACGTTACGTT", "TTTTGGGGCCCC", "ACGTGGGAAAAA",
+        "CCCCAAAATTTT", "GATTACAGATTACA", "ACGTACGGGAAA", "TTGCATTGCA", "AAAAAAAAAAAA",
+    ];
+    let db_path = temp_dir.path().join("many.db");
+    let index_path = temp_dir.path().join("many.kidx");
+    let mut build_cmd = Command::cargo_bin("orion-kmer")?;
+    build_cmd.args(["build", "-k", "4", "-o"]).arg(&db_path).arg("--sorted-index").arg(&index_path);
+    for (i, sequence) in sequences.iter().enumerate() {
+        let ref_path = temp_dir.path().join(format!("ref{:02}.fa", i));
+        fs::write(&ref_path, format!(">ref{}\n{}\n", i, sequence))?;
+        build_cmd.arg("-g").arg(&ref_path);
+    }
+    build_cmd.assert().success();
+
+    let input = ">r1\nACGTACGTTTTGGGGAAAAATTTTCCCC\n>r2\nGATTACAACGTAAAAAA\n>r3\nACGTACGT";
+    let in_memory = run_classify_with_extra_args(input, "input.fa", &[db_path], &["--min-coverage", "0"])?;
+    let mapped = run_classify_with_extra_args(input, "input.fa", &[index_path], &["--min-coverage", "0", "--mmap"])?;
+    let in_memory_db = &in_memory["databases_analyzed"][0];
+    let mapped_db = &mapped["databases_analyzed"][0];
+    assert_eq!(in_memory_db["references"].as_array().unwrap().len(), sequences.len());
+    assert_eq!(in_memory_db["references"], mapped_db["references"]);
+    for field in ["overall_input_kmers_matched_in_db", "overall_sum_depth_of_matched_kmers_in_input"] {
+        assert_eq!(in_memory_db[field], mapped_db[field], "{}", field);
+    }
+    assert!(in_memory_db["overall_input_kmers_matched_in_db"].as_u64().unwrap() > 0);
+    Ok(())
+}
+
+#[test]
+fn test_classify_exclude_and_only_reference() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_db_storage = TempDir::new()?;
+    let db1_path = build_db_for_classify(
+        4,
+        vec![("db1_refA.fa", DB1_REF1_FASTA), ("db1_refB.fa", DB1_REF2_FASTA)],
+        &temp_db_storage,
+        "db1",
+    )?;
+    let db2_path = build_db_for_classify(4, vec![("db2_refC.fa", DB2_REF1_FASTA)], &temp_db_storage, "db2")?;
+    let db_paths = [db1_path, db2_path];
+    let reference_names = |results: &JsonValue, db_idx: usize| -> Vec<String> {
+        results["databases_analyzed"][db_idx]["references"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|r| r["reference_name"].as_str().unwrap().to_string())
+            .collect()
+    };
+
+    let excluded = run_classify_with_extra_args(
+        INPUT_FASTA_BASIC,
+        "input.fa",
+        &db_paths,
+        &["--min-coverage", "0", "--exclude-reference", "db1_refB.fa"],
+    )?;
+    assert_eq!(reference_names(&excluded, 0), vec!["db1_refA.fa"]);
+    assert_eq!(reference_names(&excluded, 1), vec!["db2_refC.fa"]);
+    // db1_refA has the three k-mers ACGT, CGTA, GTAC
+    assert_eq!(
+        excluded["databases_analyzed"][0]["total_unique_kmers_in_db_across_references"],
+        3
+    );
+
+    // A name only needs to exist in one of the databases
+    let only = run_classify_with_extra_args(
+        INPUT_FASTA_BASIC,
+        "input.fa",
+        &db_paths,
+        &["--min-coverage", "0", "--only-reference", "db2_refC.fa"],
+    )?;
+    assert!(reference_names(&only, 0).is_empty());
+    assert_eq!(reference_names(&only, 1), vec!["db2_refC.fa"]);
+    Ok(())
+}
+
+#[test]
+fn test_classify_tsv_precision() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_db_storage = TempDir::new()?;
+    let db_path = build_db_for_classify(
+        4,
+        vec![("db_refA.fa", DB1_REF1_FASTA)],
+        &temp_db_storage,
+        "db_tsv_precision",
+    )?;
+    let temp_output_dir = TempDir::new()?;
+    let tsv_output_path = temp_output_dir.path().join("output.tsv");
+
+    run_classify_with_extra_args(
+        INPUT_FASTA_BASIC,
+        "input.fa",
+        &[db_path],
+        &["--output-tsv", tsv_output_path.to_str().unwrap(), "--precision", "6"],
+    )?;
+
+    let tsv_content = fs::read_to_string(&tsv_output_path)?;
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(b'\t')
+        .from_reader(tsv_content.as_bytes());
+    let records: Vec<csv::StringRecord> = reader.records().collect::<Result<_, _>>()?;
+    assert_eq!(records.len(), 1);
+    // AvgDepth 10/3, proportion 3/8 and breadth 3/3 with six decimal digits
+    assert_eq!(&records[0][6], "3.333333");
+    assert_eq!(&records[0][7], "0.375000");
+    assert_eq!(&records[0][8], "1.000000");
+    Ok(())
+}
+
+#[test]
+fn test_classify_report_invalid_windows() -> Result<(), Box<dyn std::error::Error>> {
+    let k = 4;
+    let temp_db_storage = TempDir::new()?;
+    let db_path = build_db_for_classify(
+        k,
+        vec![("db1_refA.fa", DB1_REF1_FASTA)],
+        &temp_db_storage,
+        "db_invalid",
+    )?;
+
+    // Four of the six windows overlap the N
+    let results = run_classify_with_extra_args(
+        ">read\nACGTNACGT",
+        "reads.fa",
+        std::slice::from_ref(&db_path),
+        &["--report-invalid"],
+    )?;
+    assert_eq!(results["invalid_windows_skipped"], 4);
+
+    let results = run_classify_with_extra_args(">read\nACGTNACGT", "reads.fa", &[db_path], &[])?;
+    assert!(results.get("invalid_windows_skipped").is_none());
+    Ok(())
+}
+
+#[test]
+fn test_classify_trim_front_and_back_before_kmer_extraction() -> Result<(), Box<dyn std::error::Error>> {
+    let k = 4;
+    let temp_db_storage = TempDir::new()?;
+    let db_path = build_db_for_classify(
+        k,
+        vec![("db1_refA.fa", DB1_REF1_FASTA)],
+        &temp_db_storage,
+        "db_trim",
+    )?;
+
+    // Trimming leaves ACGTAC (ACGT, CGTA, GTAC); the second read is shorter than the trim
+    let results = run_classify_with_extra_args(
+        ">read\nTTTTACGTACGG\n>short\nACGTA\n",
+        "reads.fa",
+        std::slice::from_ref(&db_path),
+        &["--trim-front", "4", "--trim-back", "2"],
+    )?;
+    assert_eq!(results["total_unique_kmers_in_input"], 3);
+    assert_eq!(results["input_reads_too_short_to_trim"], 1);
+    assert_eq!(results["databases_analyzed"][0]["references"][0]["reference_breadth_of_coverage"], 1.0);
+
+    let results = run_classify_with_extra_args(">read\nTTTTACGTACGG\n", "reads.fa", &[db_path], &[])?;
+    assert!(results.get("input_reads_too_short_to_trim").is_none());
+    Ok(())
+}
+
+#[test]
+fn test_classify_output_dir_names_files_after_input() -> Result<(), Box<dyn std::error::Error>> {
+    let k = 4;
+    let temp_db_storage = TempDir::new()?;
+    let db_path = build_db_for_classify(
+        k,
+        vec![("db1_refA.fa", DB1_REF1_FASTA)],
+        &temp_db_storage,
+        "db_output_dir",
+    )?;
+    let work_dir = TempDir::new()?;
+    let output_dir = work_dir.path().join("results").join("batch1");
+    let project_root = std::env::var("CARGO_MANIFEST_DIR").unwrap_or_else(|_| ".".to_string());
+
+    // One invocation per sample writes into the shared, initially missing directory
+    for sample in ["sampleA", "sampleB"] {
+        let input_path = work_dir.path().join(format!("{}.fasta", sample));
+        fs::write(&input_path, INPUT_FASTA_BASIC)?;
+        let mut cmd = Command::cargo_bin("orion-kmer")?;
+        cmd.current_dir(&project_root)
+            .arg("classify")
+            .arg("-i")
+            .arg(&input_path)
+            .arg("-d")
+            .arg(&db_path)
+            .arg("--output-dir")
+            .arg(&output_dir);
+        cmd.assert().success();
+    }
+
+    for sample in ["sampleA", "sampleB"] {
+        let json: JsonValue = serde_json::from_str(&fs::read_to_string(
+            output_dir.join(format!("{}.classify.json", sample)),
+        )?)?;
+        assert!(json["input_file_path"].as_str().unwrap().ends_with(&format!("{}.fasta", sample)));
+        let tsv = fs::read_to_string(output_dir.join(format!("{}.classify.tsv", sample)))?;
+        assert_eq!(tsv.lines().count(), 2); // Header and db1_refA.fa
+    }
+
+    // --output-dir replaces -o and --output-tsv
+    let mut cmd = Command::cargo_bin("orion-kmer")?;
+    cmd.current_dir(&project_root)
+        .arg("classify")
+        .arg("-i")
+        .arg(work_dir.path().join("sampleA.fasta"))
+        .arg("-d")
+        .arg(&db_path)
+        .arg("--output-dir")
+        .arg(&output_dir)
+        .arg("-o")
+        .arg(work_dir.path().join("out.json"));
+    cmd.assert().failure().stderr(predicate::str::contains("cannot be used with"));
+    Ok(())
+}
+
+#[test]
+fn test_classify_groups_roll_up_member_references() -> Result<(), Box<dyn std::error::Error>> {
+    let k = 4;
+    let temp_db_storage = TempDir::new()?;
+    let db_path = build_db_for_classify(
+        k,
+        vec![
+            ("refA.fa", DB1_REF1_FASTA),
+            ("refB.fa", DB1_REF2_FASTA),
+            ("refC.fa", DB2_REF1_FASTA),
+        ],
+        &temp_db_storage,
+        "db_groups",
+    )?;
+    let groups_path = temp_db_storage.path().join("groups.tsv");
+    fs::write(&groups_path, "# reference\tgroup\nrefA.fa\tgenus1\nrefC.fa\tgenus1\n\nrefB.fa\tgenus2\n")?;
+
+    // Input k-mers: ACGT x2, CGTA x2, GTAC x1, all in refA
+    let results = run_classify_with_extra_args(
+        ">read\nACGTACGT",
+        "reads.fa",
+        &[db_path],
+        &["--groups", groups_path.to_str().unwrap(), "--min-coverage", "0.9"],
+    )?;
+    let groups = results["groups"].as_array().unwrap();
+    assert_eq!(groups.len(), 2);
+
+    // refA {ACGT, CGTA, GTAC} and refC {ACGT, AACG, GTTA, GTAA, CGTA} share two k-mers
+    let genus1 = &groups[0];
+    assert_eq!(genus1["group_name"], "genus1");
+    assert_eq!(genus1["num_references"], 2);
+    assert_eq!(genus1["total_kmers_in_group"], 6);
+    assert_eq!(genus1["input_kmers_hitting_group"], 3);
+    assert_eq!(genus1["sum_depth_of_matched_kmers_in_input"], 5);
+    assert_eq!(genus1["group_breadth_of_coverage"].as_f64().unwrap(), 0.5);
+
+    // Groups include members filtered out of the per-reference report
+    let genus2 = &groups[1];
+    assert_eq!(genus2["group_name"], "genus2");
+    assert_eq!(genus2["input_kmers_hitting_group"], 0);
+    assert_eq!(results["databases_analyzed"][0]["references"].as_array().unwrap().len(), 1);
+    Ok(())
+}
+
+#[test]
+fn test_classify_krona_output() -> Result<(), Box<dyn std::error::Error>> {
+    let k = 4;
+    let temp_db_storage = TempDir::new()?;
+    let db_path = build_db_for_classify(
+        k,
+        vec![
+            ("refA.fa", DB1_REF1_FASTA),
+            ("refB.fa", DB1_REF2_FASTA),
+            ("refC.fa", DB2_REF1_FASTA),
+        ],
+        &temp_db_storage,
+        "db_krona",
+    )?;
+    let groups_path = temp_db_storage.path().join("groups.tsv");
+    fs::write(&groups_path, "refA.fa\tBacteria; genus1\nrefC.fa\tBacteria;genus2\n")?;
+    let krona_path = temp_db_storage.path().join("krona.txt");
+    let krona_arg = krona_path.to_str().unwrap();
+
+    // Input k-mers: ACGT, CGTA, GTAC, AACG, AAAC, AAAA. refA matches 3, refB 1 (AAAA),
+    // refC 3 (ACGT, AACG, CGTA); only AAAC matches nothing
+    let input = ">read\nACGTACGTTTTT";
+    run_classify_with_extra_args(
+        input,
+        "reads.fa",
+        std::slice::from_ref(&db_path),
+        &["--krona", krona_arg, "--groups", groups_path.to_str().unwrap()],
+    )?;
+    assert_eq!(
+        fs::read_to_string(&krona_path)?,
+        "3\tBacteria\tgenus1\trefA.fa\n1\tUngrouped\trefB.fa\n3\tBacteria\tgenus2\trefC.fa\n1\tUnclassified\n"
+    );
+
+    // Without groups the lineage is the reference; filtered-out references are left out
+    run_classify_with_extra_args(input, "reads.fa", &[db_path], &["--krona", krona_arg, "--min-coverage", "0.5"])?;
+    assert_eq!(fs::read_to_string(&krona_path)?, "3\trefA.fa\n3\trefC.fa\n1\tUnclassified\n");
+    Ok(())
+}
+
+#[test]
+fn test_classify_streams_json_for_every_database() -> Result<(), Box<dyn std::error::Error>> {
+    let k = 4;
+    let temp_db_storage = TempDir::new()?;
+    let db1_path = build_db_for_classify(k, vec![("refA.fa", DB1_REF1_FASTA)], &temp_db_storage, "db_stream1")?;
+    let db2_path = build_db_for_classify(k, vec![("refB.fa", DB1_REF2_FASTA)], &temp_db_storage, "db_stream2")?;
+    let groups_path = temp_db_storage.path().join("groups.tsv");
+    fs::write(&groups_path, "refA.fa\tgenus1\nrefB.fa\tgenus2\n")?;
+    let input_path = temp_db_storage.path().join("reads.fa");
+    fs::write(&input_path, ">read\nACGTACGT\n")?;
+    let output_path = temp_db_storage.path().join("streamed.json");
+
+    let mut cmd = Command::cargo_bin("orion-kmer")?;
+    let project_root = std::env::var("CARGO_MANIFEST_DIR").unwrap_or_else(|_| ".".to_string());
+    cmd.current_dir(project_root)
+        .arg("classify")
+        .arg("-i")
+        .arg(&input_path)
+        .arg("-o")
+        .arg(&output_path)
+        .arg("-d")
+        .arg(&db1_path)
+        .arg("-d")
+        .arg(&db2_path)
+        .arg("-d")
+        .arg(&db1_path)
+        .args(["--min-coverage", "0.5", "--groups", groups_path.to_str().unwrap()]);
+    cmd.assert().success();
+
+    // Laid out the way serde_json pretty-prints the whole document
+    let json_text = fs::read_to_string(&output_path)?;
+    assert!(json_text.starts_with("{\n  \"input_file_path\": "));
+    assert!(json_text.contains("\n  \"databases_analyzed\": [\n    {\n      \"database_path\": "));
+    assert!(json_text.contains("\n      \"references\": []\n    },\n    {\n"));
+    assert!(json_text.ends_with("\n  ]\n}"));
+
+    let results: JsonValue = serde_json::from_str(&json_text)?;
+    let databases = results["databases_analyzed"].as_array().unwrap();
+    let database_paths: Vec<&str> = databases.iter().map(|db| db["database_path"].as_str().unwrap()).collect();
+    let (db1_str, db2_str) = (db1_path.to_str().unwrap(), db2_path.to_str().unwrap());
+    assert_eq!(database_paths, vec![db1_str, db2_str, db1_str]);
+    // refB shares no k-mer with the read, so the second database reports no references
+    assert_eq!(databases[0]["references"].as_array().unwrap().len(), 1);
+    assert!(databases[1]["references"].as_array().unwrap().is_empty());
+    assert_eq!(results["groups"].as_array().unwrap().len(), 2);
+    Ok(())
+}
+
+#[test]
+fn test_classify_dry_run_does_not_create_output_dir() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_db_storage = TempDir::new()?;
+    let db_path = build_db_for_classify(4, vec![("refA.fa", DB1_REF1_FASTA)], &temp_db_storage, "db_dry_run")?;
+    let input_path = temp_db_storage.path().join("sampleA.fastq.gz");
+    let mut encoder = flate2::write::GzEncoder::new(File::create(&input_path)?, flate2::Compression::default());
+    encoder.write_all(b"@r1\nACGTACGT\n+\nIIIIIIII\n")?;
+    encoder.finish()?;
+    let output_dir = temp_db_storage.path().join("reports");
+
+    let output = Command::cargo_bin("orion-kmer")?
+        .args(["classify", "--dry-run", "-i"])
+        .arg(&input_path)
+        .arg("-d")
+        .arg(&db_path)
+        .arg("--output-dir")
+        .arg(&output_dir)
+        .output()?;
+    assert!(output.status.success());
+    assert!(!output_dir.exists());
+    let plan: JsonValue = serde_json::from_slice(&output.stdout)?;
+    let roles: Vec<&str> = plan["inputs"].as_array().unwrap().iter().map(|input| input["role"].as_str().unwrap()).collect();
+    assert_eq!(roles, vec!["sequences", "database"]);
+    assert_eq!(plan["outputs"][0], output_dir.join("sampleA.classify.json").to_str().unwrap());
+    assert_eq!(plan["outputs"][1], output_dir.join("sampleA.classify.tsv").to_str().unwrap());
+    Ok(())
+}
+
+#[test]
+fn test_classify_coverage_track_writes_bedgraph() -> Result<(), Box<dyn std::error::Error>> {
+    let work_dir = TempDir::new()?;
+    let project_root = std::env::var("CARGO_MANIFEST_DIR").unwrap_or_else(|_| ".".to_string());
+    let genome_path = work_dir.path().join("genome.fasta");
+    fs::write(&genome_path, ">chr1\nAAAACCTTGGAT\n>chr2\nGATTACA\n")?;
+    let db_path = work_dir.path().join("per_record.db");
+    let mut cmd = Command::cargo_bin("orion-kmer")?;
+    cmd.current_dir(&project_root)
+        .args(["build", "-k", "4", "--per-record", "-g"])
+        .arg(&genome_path)
+        .arg("-o")
+        .arg(&db_path);
+    cmd.assert().success();
+
+    // The read matches chr1's k-mers starting at 0, 1 and 2
+    let input_path = work_dir.path().join("reads.fasta");
+    fs::write(&input_path, ">r1\nAAAACC\n")?;
+    let track_path = work_dir.path().join("chr1.bedgraph");
+    let mut cmd = Command::cargo_bin("orion-kmer")?;
+    cmd.current_dir(&project_root)
+        .arg("classify")
+        .arg("-i")
+        .arg(&input_path)
+        .arg("-d")
+        .arg(&db_path)
+        .arg("-o")
+        .arg(work_dir.path().join("out.json"))
+        .arg("--coverage-track")
+        .arg(format!("chr1:{}", track_path.display()));
+    cmd.assert().success();
+    assert_eq!(
+        fs::read_to_string(&track_path)?,
+        "chr1\t0\t1\t1\nchr1\t1\t2\t2\nchr1\t2\t4\t3\nchr1\t4\t5\t2\nchr1\t5\t6\t1\nchr1\t6\t12\t0\n"
+    );
+
+    // References built from whole files have no positions
+    let file_db_path = build_db_for_classify(4, vec![("db1_refA.fa", DB1_REF1_FASTA)], &work_dir, "no_positions")?;
+    let mut cmd = Command::cargo_bin("orion-kmer")?;
+    cmd.current_dir(&project_root)
+        .arg("classify")
+        .arg("-i")
+        .arg(&input_path)
+        .arg("-d")
+        .arg(&file_db_path)
+        .arg("-o")
+        .arg(work_dir.path().join("out2.json"))
+        .arg("--coverage-track")
+        .arg(format!("db1_refA.fa:{}", track_path.display()));
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("rebuild it with `build --per-record`"));
+    Ok(())
+}
+
+#[test]
+fn test_classify_depth_track_writes_input_counts_along_reference() -> Result<(), Box<dyn std::error::Error>> {
+    let work_dir = TempDir::new()?;
+    let genome_path = work_dir.path().join("genome.fasta");
+    fs::write(&genome_path, ">chr1\nAAAACCTTGGAT\n")?;
+    let db_path = work_dir.path().join("per_record.db");
+    Command::cargo_bin("orion-kmer")?
+        .args(["build", "-k", "4", "--per-record", "-g"])
+        .arg(&genome_path)
+        .arg("-o")
+        .arg(&db_path)
+        .assert()
+        .success();
+
+    // AAAA occurs 3 times in the input, AAAC and AACC twice; TTGG is read as its reverse complement
+    let input_path = work_dir.path().join("reads.fasta");
+    fs::write(&input_path, ">r1\nAAAACC\n>r2\nAAAACC\n>r3\nAAAA\n>r4\nCCAA\n")?;
+    let track_path = work_dir.path().join("chr1.depth.bedgraph");
+    Command::cargo_bin("orion-kmer")?
+        .arg("classify")
+        .arg("-i")
+        .arg(&input_path)
+        .arg("-d")
+        .arg(&db_path)
+        .arg("-o")
+        .arg(work_dir.path().join("out.json"))
+        .arg("--depth-track")
+        .arg(format!("chr1:{}", track_path.display()))
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(&track_path)?,
+        "chr1\t0\t1\t3\nchr1\t1\t3\t2\nchr1\t3\t6\t0\nchr1\t6\t7\t1\nchr1\t7\t12\t0\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_classify_extract_reads_writes_matching_records() -> Result<(), Box<dyn std::error::Error>> {
+    let work_dir = TempDir::new()?;
+    let db_path = build_db_for_classify(
+        4,
+        vec![("ref1.fa", ">r1\nACGTT"), ("ref2.fa", ">r2\nGGGGA")],
+        &work_dir,
+        "db_extract",
+    )?;
+    // r4 is the reverse complement of r1 and matches ref1 through canonical k-mers
+    let input_path = work_dir.path().join("reads.fastq");
+    fs::write(
+        &input_path,
+        "@r1\nACGTT\n+\nIIIII\n@r2\nGGGGA\n+\nIIIII\n@r3\nTTTTC\n+\nIIIII\n@r4 reverse\nAACGT\n+\nABCDE\n",
+    )?;
+    let ref1_reads_path = work_dir.path().join("ref1_reads.fastq.gz");
+    let ref2_reads_path = work_dir.path().join("ref2_reads.fastq");
+    let project_root = std::env::var("CARGO_MANIFEST_DIR").unwrap_or_else(|_| ".".to_string());
+    let mut cmd = Command::cargo_bin("orion-kmer")?;
+    cmd.current_dir(&project_root)
+        .arg("classify")
+        .arg("-i")
+        .arg(&input_path)
+        .arg("-d")
+        .arg(&db_path)
+        .arg("-o")
+        .arg(work_dir.path().join("out.json"))
+        .arg("--extract-reads")
+        .arg(format!("ref1.fa:{}", ref1_reads_path.display()))
+        .arg("--extract-reads")
+        .arg(format!("ref2.fa:{}", ref2_reads_path.display()));
+    cmd.assert().success();
+
+    let mut ref1_reads = String::new();
+    flate2::read::MultiGzDecoder::new(File::open(&ref1_reads_path)?).read_to_string(&mut ref1_reads)?;
+    assert_eq!(ref1_reads, "@r1\nACGTT\n+\nIIIII\n@r4 reverse\nAACGT\n+\nABCDE\n");
+    assert_eq!(fs::read_to_string(&ref2_reads_path)?, "@r2\nGGGGA\n+\nIIIII\n");
+
+    let mut cmd = Command::cargo_bin("orion-kmer")?;
+    cmd.current_dir(&project_root)
+        .arg("classify")
+        .arg("-i")
+        .arg(&input_path)
+        .arg("-d")
+        .arg(&db_path)
+        .arg("-o")
+        .arg(work_dir.path().join("out2.json"))
+        .arg("--extract-reads")
+        .arg(format!("missing.fa:{}", ref2_reads_path.display()));
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("is not in any database"));
+    Ok(())
+}
+
+#[test]
+fn test_classify_per_read_normalize_averages_read_fractions() -> Result<(), Box<dyn std::error::Error>> {
+    let work_dir = TempDir::new()?;
+    let db_path = build_db_for_classify(
+        4,
+        vec![("ref1.fa", ">r1\nACGTT"), ("ref2.fa", ">r2\nGGGGA")],
+        &work_dir,
+        "db_per_read",
+    )?;
+    // The long read has 6 of its 12 k-mers in ref1; the short one is all ref2
+    let input_path = work_dir.path().join("reads.fa");
+    fs::write(&input_path, ">long\nACGTTACGTTACGTT\n>short\nGGGGA\n")?;
+    let output_path = work_dir.path().join("out.json");
+    let project_root = std::env::var("CARGO_MANIFEST_DIR").unwrap_or_else(|_| ".".to_string());
+    let mut cmd = Command::cargo_bin("orion-kmer")?;
+    cmd.current_dir(&project_root)
+        .arg("classify")
+        .arg("-i")
+        .arg(&input_path)
+        .arg("-d")
+        .arg(&db_path)
+        .arg("-o")
+        .arg(&output_path)
+        .arg("--per-read-normalize");
+    cmd.assert().success();
+
+    let report: JsonValue = serde_json::from_str(&fs::read_to_string(&output_path)?)?;
+    let references = report["databases_analyzed"][0]["references"].as_array().unwrap();
+    let coverage = |name: &str| {
+        references
+            .iter()
+            .find(|reference| reference["reference_name"] == name)
+            .map(|reference| reference["mean_per_read_coverage"].as_f64().unwrap())
+    };
+    assert_eq!(coverage("ref1.fa"), Some(0.25));
+    assert_eq!(coverage("ref2.fa"), Some(0.5));
+    Ok(())
+}
+
+#[test]
+fn test_classify_pools_multiple_input_files() -> Result<(), Box<dyn std::error::Error>> {
+    let work_dir = TempDir::new()?;
+    let db_path = build_db_for_classify(
+        4,
+        vec![("ref1.fa", ">r1\nACGTTAC"), ("ref2.fa", ">r2\nGGGGA")],
+        &work_dir,
+        "db_fan_in",
+    )?;
+    let lane1 = work_dir.path().join("lane1.fa");
+    let lane2 = work_dir.path().join("lane2.fa");
+    let pooled = work_dir.path().join("pooled.fa");
+    fs::write(&lane1, ">a\nACGTTAC\n")?;
+    fs::write(&lane2, ">b\nACGTT\n>c\nGGGGA\n")?;
+    fs::write(&pooled, ">a\nACGTTAC\n>b\nACGTT\n>c\nGGGGA\n")?;
+    let project_root = std::env::var("CARGO_MANIFEST_DIR").unwrap_or_else(|_| ".".to_string());
+    let classify = |inputs: &[&PathBuf], output: &PathBuf| -> Result<JsonValue, Box<dyn std::error::Error>> {
+        let mut cmd = Command::cargo_bin("orion-kmer")?;
+        cmd.current_dir(&project_root).arg("classify").arg("-i");
+        for input in inputs {
+            cmd.arg(input);
+        }
+        cmd.arg("-d").arg(&db_path).arg("-o").arg(output);
+        cmd.assert().success();
+        Ok(serde_json::from_str(&fs::read_to_string(output)?)?)
+    };
+
+    let mut split = classify(&[&lane1, &lane2], &work_dir.path().join("split.json"))?;
+    let mut single = classify(&[&pooled], &work_dir.path().join("single.json"))?;
+    assert_eq!(
+        split["input_file_path"],
+        format!("{},{}", lane1.display(), lane2.display())
+    );
+    assert_eq!(split["total_unique_kmers_in_input"], 6);
+    // Apart from the input paths, the pooled profile is that of the concatenated files
+    split["input_file_path"] = JsonValue::Null;
+    single["input_file_path"] = JsonValue::Null;
+    assert_eq!(split, single);
+    Ok(())
+}
+
+// Returns the final --progress-json event of classify's input-reading task.
+fn last_input_progress_event(progress_path: &PathBuf) -> Result<JsonValue, Box<dyn std::error::Error>> {
+    let events: Vec<JsonValue> = fs::read_to_string(progress_path)?
+        .lines()
+        .map(serde_json::from_str)
+        .collect::<Result<_, _>>()?;
+    Ok(events
+        .into_iter()
+        .rfind(|event| event["task"].as_str().unwrap().starts_with("Processing input file"))
+        .ok_or("no input progress events")?)
+}
+
+#[test]
+fn test_classify_prescan_sets_progress_total() -> Result<(), Box<dyn std::error::Error>> {
+    let work_dir = TempDir::new()?;
+    let db_path = build_db_for_classify(4, vec![("db1_refA.fa", DB1_REF1_FASTA)], &work_dir, "db_prescan")?;
+    let input_path = work_dir.path().join("reads.fasta");
+    let reads = ">r1\nACGTACGT\n>r2\nTTTTGGGG\n>r3\nA\n";
+    fs::write(&input_path, reads)?;
+    let project_root = std::env::var("CARGO_MANIFEST_DIR").unwrap_or_else(|_| ".".to_string());
+
+    for (input, prescan, expected_len) in [
+        (input_path.clone(), true, 3),
+        (input_path.clone(), false, 0),
+        // A pipe cannot be read twice, so the prescan is skipped
+        (PathBuf::from("/dev/stdin"), true, 0),
+    ] {
+        let progress_path = work_dir.path().join("progress.ndjson");
+        let mut cmd = assert_cmd::Command::cargo_bin("orion-kmer")?;
+        cmd.current_dir(&project_root)
+            .arg("classify")
+            .arg("-i")
+            .arg(&input)
+            .arg("-d")
+            .arg(&db_path)
+            .arg("-o")
+            .arg(work_dir.path().join("out.json"))
+            .arg("--progress-json")
+            .arg(&progress_path)
+            .write_stdin(reads);
+        if prescan {
+            cmd.arg("--prescan");
+        }
+        cmd.assert().success();
+        let last = last_input_progress_event(&progress_path)?;
+        assert_eq!(last["done"], true);
+        assert_eq!(last["len"], expected_len, "{:?} prescan={}", input, prescan);
+        assert_eq!(last["pos"], expected_len);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_classify_input_sequences_shorter_than_k() -> Result<(), Box<dyn std::error::Error>> {
+    let work_dir = TempDir::new()?;
+    let db_path = build_db_for_classify(4, vec![("db1_refA.fa", DB1_REF1_FASTA)], &work_dir, "db_short_input")?;
+    let input_path = work_dir.path().join("short.fasta");
+    fs::write(&input_path, ">r1\nACG\n>r2\nGT\n")?;
+    let output_path = work_dir.path().join("out.json");
+    let project_root = std::env::var("CARGO_MANIFEST_DIR").unwrap_or_else(|_| ".".to_string());
+    let mut cmd = Command::cargo_bin("orion-kmer")?;
+    cmd.current_dir(&project_root)
+        .arg("classify")
+        .arg("-i")
+        .arg(&input_path)
+        .arg("-d")
+        .arg(&db_path)
+        .arg("-o")
+        .arg(&output_path);
+    cmd.assert()
+        .success()
+        .stderr(predicate::str::contains("All 2 records in").and(predicate::str::contains("shorter than k=4")));
+
+    let json: JsonValue = serde_json::from_str(&fs::read_to_string(&output_path)?)?;
+    assert_eq!(json["total_unique_kmers_in_input"], 0);
+    assert_eq!(json["input_reads_shorter_than_k"], 2);
+    let reference = &json["databases_analyzed"][0]["references"][0];
+    assert_eq!(reference["input_kmers_hitting_reference"], 0);
+    Ok(())
+}
+
+
+#[test]
+fn test_classify_reports_total_and_ambiguous_input_bases() -> Result<(), Box<dyn std::error::Error>> {
+    let work_dir = TempDir::new()?;
+    let db_path = build_db_for_classify(4, vec![("db1_refA.fa", DB1_REF1_FASTA)], &work_dir, "db_input_bases")?;
+    let first_path = work_dir.path().join("first.fasta");
+    let second_path = work_dir.path().join("second.fasta");
+    // Lower case bases count as ACGT, IUPAC codes and N as ambiguous; short reads still count
+    fs::write(&first_path, ">r1\nACGTNNacgt\n>r2\nAC\n")?;
+    fs::write(&second_path, ">r3\nACGRYT\n")?;
+    let output_path = work_dir.path().join("out.json");
+    Command::cargo_bin("orion-kmer")?
+        .arg("-v")
+        .arg("classify")
+        .arg("-i")
+        .arg(&first_path)
+        .arg(&second_path)
+        .arg("-d")
+        .arg(&db_path)
+        .arg("-o")
+        .arg(&output_path)
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(format!(
+            "Read 12 bases from {}, 2 (16.67%) of them N/ambiguous",
+            first_path.display()
+        )))
+        .stderr(predicate::str::contains("Read 18 bases from all input files, 4 (22.22%) of them N/ambiguous"));
+
+    let json: JsonValue = serde_json::from_str(&fs::read_to_string(&output_path)?)?;
+    assert_eq!(json["input_total_bases"], 18);
+    assert_eq!(json["input_ambiguous_bases"], 4);
+    Ok(())
+}
+
+#[test]
+fn test_classify_sqlite_appends_classifications_and_references() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_db_storage = TempDir::new()?;
+    let db_path = build_db_for_classify(
+        4,
+        vec![
+            ("db1_refA.fa", DB1_REF1_FASTA),
+            ("db1_refB.fa", DB1_REF2_FASTA),
+        ],
+        &temp_db_storage,
+        "db_sqlite",
+    )?;
+    let sqlite_path = temp_db_storage.path().join("results.sqlite");
+    let sqlite_arg = sqlite_path.to_str().unwrap();
+    let full_tsv_path = temp_db_storage.path().join("full.tsv");
+    run_classify_with_extra_args(
+        INPUT_FASTA_BASIC,
+        "input_sqlite.fa",
+        std::slice::from_ref(&db_path),
+        &["--min-reference-hits", "2", "--sqlite", sqlite_arg],
+    )?;
+    // With --full-tsv, the references failing the filters are recorded too
+    run_classify_with_extra_args(
+        INPUT_FASTA_BASIC,
+        "input_sqlite.fa",
+        &[db_path],
+        &[
+            "--min-reference-hits",
+            "2",
+            "--sqlite",
+            sqlite_arg,
+            "--full-tsv",
+            full_tsv_path.to_str().unwrap(),
+        ],
+    )?;
+
+    let conn = rusqlite::Connection::open(&sqlite_path)?;
+    let classifications: Vec<(i64, i64)> = conn
+        .prepare("SELECT id, total_unique_kmers_in_input FROM classifications ORDER BY id")?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<_, _>>()?;
+    assert_eq!(classifications.len(), 2);
+    assert!(classifications[0].1 > 0);
+    let references_of = |classification_id: i64| -> rusqlite::Result<Vec<(String, i64, bool)>> {
+        conn.prepare(
+            "SELECT reference_name, input_kmers_hitting_reference, passed_filter FROM \"references\" \
+             WHERE classification_id = ?1 ORDER BY reference_name",
+        )?
+        .query_map([classification_id], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+        .collect()
+    };
+    assert_eq!(references_of(classifications[0].0)?, vec![("db1_refA.fa".to_string(), 3, true)]);
+    let full_references = references_of(classifications[1].0)?;
+    assert_eq!(full_references.len(), 2);
+    assert_eq!(full_references[0], ("db1_refA.fa".to_string(), 3, true));
+    assert_eq!((full_references[1].0.as_str(), full_references[1].2), ("db1_refB.fa", false));
+    Ok(())
+}
+
+#[test]
+fn test_classifier_reuses_input_profile_as_database_grows() -> Result<(), Box<dyn std::error::Error>> {
+    use orion_kmer::commands::classify::Classifier;
+
+    let kmer = |seq: &str| canonical_u64(seq_to_u64(seq.as_bytes(), 4).unwrap(), 4);
+    let input_kmer_counts: std::collections::HashMap<u64, usize> = [("AAAA", 3), ("AAAC", 1), ("AACC", 2)]
+        .into_iter()
+        .map(|(seq, count)| (kmer(seq), count))
+        .collect();
+    let classifier = Classifier::new(input_kmer_counts).with_min_reference_hits(2);
+
+    let mut kmer_db = KmerDbV2::new(4);
+    kmer_db.add_reference("refA".to_string(), ["AAAA", "AAAC", "AGGG"].into_iter().map(kmer).collect());
+    let first_round = classifier.classify_against(&kmer_db);
+    assert_eq!(first_round.database_path, "");
+    assert_eq!(first_round.overall_input_kmers_matched_in_db, 2);
+    assert_eq!(first_round.references.len(), 1);
+    assert_eq!(first_round.references[0].reference_name, "refA");
+    assert_eq!(first_round.references[0].sum_depth_of_matched_kmers_in_input, 4);
+
+    // References added later are classified with the same profile; from 8 references the
+    // inverted index is used, with the same results
+    kmer_db.add_reference("refB".to_string(), ["AACC"].into_iter().map(kmer).collect());
+    for idx in 0..6 {
+        kmer_db.add_reference(format!("unrelated{}", idx), ["CCCC"].into_iter().map(kmer).collect());
+    }
+    let second_round = classifier.classify_against(&kmer_db);
+    assert_eq!(second_round.overall_input_kmers_matched_in_db, 3);
+    assert_eq!(second_round.overall_sum_depth_of_matched_kmers_in_input, 6);
+    let reported: Vec<_> = second_round.references.iter().map(|r| r.reference_name.as_str()).collect();
+    assert_eq!(reported, vec!["refA"]);
+    assert_eq!(second_round.references[0].sum_depth_of_matched_kmers_in_input, 4);
+    // refB has a single hit, below --min-reference-hits
+    let ref_b = second_round
+        .filtered_out_references
+        .iter()
+        .find(|r| r.reference_name == "refB")
+        .expect("refB is classified");
+    assert_eq!(ref_b.input_kmers_hitting_reference, 1);
+    assert_eq!(ref_b.sum_depth_of_matched_kmers_in_input, 2);
+    Ok(())
+}
+
+#[test]
+fn test_classify_per_input_record_classifies_each_record() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_db_dir = TempDir::new()?;
+    let db_path = build_db_for_classify(
+        4,
+        vec![("refA.fa", DB1_REF1_FASTA), ("refB.fa", DB1_REF2_FASTA)],
+        &temp_db_dir,
+        "per_record_db",
+    )?;
+    let json_output = run_classify_with_extra_args(
+        ">contig1 first\nACGTACGT\n>contig2\nTTTTGGGG",
+        "assembly.fa",
+        std::slice::from_ref(&db_path),
+        &["--per-input-record"],
+    )?;
+    // The pooled results are unchanged
+    assert_eq!(json_output["total_unique_kmers_in_input"], 8);
+    assert_eq!(json_output["databases_analyzed"].as_array().unwrap().len(), 1);
+
+    let records = json_output["records"].as_array().expect("records array");
+    assert_eq!(records.len(), 2);
+    let hits = |record: &JsonValue, reference: &str| {
+        let databases = record["databases_analyzed"].as_array().unwrap();
+        assert_eq!(databases[0]["database_path"], db_path.to_string_lossy().as_ref());
+        databases[0]["references"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|ref_res| ref_res["reference_name"] == reference)
+            .map_or(0, |ref_res| ref_res["input_kmers_hitting_reference"].as_u64().unwrap())
+    };
+    // contig1: ACGT, CGTA, GTAC, all in refA
+    assert_eq!(records[0]["record_id"], "contig1 first");
+    assert_eq!(records[0]["total_unique_kmers_in_record"], 3);
+    assert_eq!((hits(&records[0], "refA.fa"), hits(&records[0], "refB.fa")), (3, 0));
+    // contig2: AAAA, CAAA, CCAA, CCCA, CCCC, of which AAAA is in refB
+    assert_eq!(records[1]["record_id"], "contig2");
+    assert_eq!(records[1]["total_unique_kmers_in_record"], 5);
+    assert_eq!((hits(&records[1], "refA.fa"), hits(&records[1], "refB.fa")), (0, 1));
+
+    // Without the flag there is no records array
+    let pooled = run_classify_with_extra_args(INPUT_FASTA_BASIC, "input.fa", std::slice::from_ref(&db_path), &[])?;
+    assert!(pooled.get("records").is_none());
+    Ok(())
+}
+
+#[test]
+fn test_classify_breadth_denominator_reference_length() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_db_dir = TempDir::new()?;
+    // A repetitive reference: 5 windows of k=4 over its 8 bases, all the one k-mer AAAA; the
+    // 3-base record is shorter than k and adds no window
+    let db_path = build_db_for_classify(
+        4,
+        vec![("repeat.fa", ">r1\nAAAAAAAA\n>r2\nCCC\n")],
+        &temp_db_dir,
+        "lengths_db",
+    )?;
+    let breadth = |extra_args: &[&str]| -> Result<f64, Box<dyn std::error::Error>> {
+        let json_output =
+            run_classify_with_extra_args(">read\nAAAAAA", "reads.fa", std::slice::from_ref(&db_path), extra_args)?;
+        let ref_res = &json_output["databases_analyzed"][0]["references"][0];
+        assert_eq!(ref_res["input_kmers_hitting_reference"], 1);
+        Ok(ref_res["reference_breadth_of_coverage"].as_f64().unwrap())
+    };
+    assert_eq!(breadth(&[])?, 1.0);
+    assert_eq!(breadth(&["--breadth-denominator", "reference-kmers"])?, 1.0);
+    assert_eq!(breadth(&["--breadth-denominator", "reference-length"])?, 0.2);
+    Ok(())
+}
+
+#[test]
+fn test_classify_coverage_uniformity() -> Result<(), Box<dyn std::error::Error>> {
+    let work_dir = TempDir::new()?;
+    // k=4 reference: AAAA, AAAC, AACC, ACCC, CCCC
+    let file_db_path = build_db_for_classify(4, vec![("ref.fa", ">r\nAAAACCCC")], &work_dir, "file_db")?;
+    let uniformity = |input: &str, db_path: &PathBuf| -> Result<f64, Box<dyn std::error::Error>> {
+        let json_output =
+            run_classify_with_extra_args(input, "reads.fa", std::slice::from_ref(db_path), &["--coverage-uniformity"])?;
+        Ok(json_output["databases_analyzed"][0]["references"][0]["coverage_uniformity"].as_f64().unwrap())
+    };
+    // Every matched k-mer at the same depth
+    assert_eq!(uniformity(">r1\nAAAACCCC", &file_db_path)?, 1.0);
+    // Depths 9 (AAAA) and 1 (AAAC): Gini 0.4
+    assert!((uniformity(">r1\nAAAAAAAAAAAA\n>r2\nAAAC", &file_db_path)? - 0.6).abs() < 1e-9);
+
+    // Without the flag the field is absent
+    let json_output =
+        run_classify_with_extra_args(">r1\nAAAACCCC", "reads.fa", std::slice::from_ref(&file_db_path), &[])?;
+    assert!(json_output["databases_analyzed"][0]["references"][0].get("coverage_uniformity").is_none());
+
+    // With stored positions, the per-base depth along the reference is used: AAAA and AAAC
+    // cover bases 0-4 with depths 1, 2, 2, 2, 1 and leave bases 5-7 uncovered
+    let genome_path = work_dir.path().join("genome.fasta");
+    fs::write(&genome_path, ">chr1\nAAAACCCC\n")?;
+    let per_record_db_path = work_dir.path().join("per_record.db");
+    Command::cargo_bin("orion-kmer")?
+        .args(["build", "-k", "4", "--per-record", "-g"])
+        .arg(&genome_path)
+        .arg("-o")
+        .arg(&per_record_db_path)
+        .assert()
+        .success();
+    assert_eq!(uniformity(">r1\nAAAAC", &file_db_path)?, 1.0);
+    assert!((uniformity(">r1\nAAAAC", &per_record_db_path)? - 0.53125).abs() < 1e-9);
+    Ok(())
+}
+
+#[test]
+fn test_classify_allow_mixed_k_uses_each_database_k() -> Result<(), Box<dyn std::error::Error>> {
+    let work_dir = TempDir::new()?;
+    let db_k4_path = build_db_for_classify(4, vec![("ref4.fa", ">r\nAAAACCCC")], &work_dir, "db_k4")?;
+    let db_k5_path = build_db_for_classify(5, vec![("ref5.fa", ">r\nAAAACCCC")], &work_dir, "db_k5")?;
+    let db_paths = [db_k4_path, db_k5_path];
+
+    let json_output = run_classify_with_extra_args(
+        ">r1\nAAAACCCC",
+        "reads.fa",
+        &db_paths,
+        &["--allow-mixed-k", "--min-coverage", "0"],
+    )?;
+    // The header keeps the first database's k; the per-k counts cover both
+    assert_eq!(json_output["total_unique_kmers_in_input"], 5);
+    assert_eq!(json_output["total_unique_kmers_in_input_by_k"]["4"], 5);
+    assert_eq!(json_output["total_unique_kmers_in_input_by_k"]["5"], 4);
+    for (db_result, total_kmers) in json_output["databases_analyzed"].as_array().unwrap().iter().zip([5, 4]) {
+        let reference = &db_result["references"][0];
+        assert_eq!(reference["total_kmers_in_reference"], total_kmers);
+        assert_eq!(reference["input_kmers_hitting_reference"], total_kmers);
+        assert_eq!(reference["reference_breadth_of_coverage"], 1.0);
+    }
+
+    // Without the flag the mismatch is still an error
+    let input_path = work_dir.path().join("reads.fa");
+    fs::write(&input_path, ">r1\nAAAACCCC\n")?;
+    Command::cargo_bin("orion-kmer")?
+        .arg("classify")
+        .arg("-i")
+        .arg(&input_path)
+        .arg("-d")
+        .arg(&db_paths[0])
+        .arg("-d")
+        .arg(&db_paths[1])
+        .arg("-o")
+        .arg(work_dir.path().join("mismatch.json"))
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("does not match k-mer size 5"));
+    Ok(())
+}
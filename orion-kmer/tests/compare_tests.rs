@@ -2,7 +2,7 @@ use assert_cmd::prelude::*;
 use predicates::prelude::*;
 use serde_json::Value as JsonValue;
 use std::{
-    // collections::HashSet, // No longer needed after debug code removal
+    collections::HashSet,
     fs::{self, File},
     io::Write,
     path::PathBuf,
@@ -18,6 +18,17 @@ fn run_build_for_test(
     input_files_content: Vec<(&str, &str)>, // Vec of (filename, content)
     db_output_dir: &TempDir,                // Directory to store the .db file
     db_name_prefix: &str,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    run_build_with_args_for_test(k, input_files_content, db_output_dir, db_name_prefix, &[])
+}
+
+// Same as run_build_for_test, passing additional command-line arguments to 'build'
+fn run_build_with_args_for_test(
+    k: u8,
+    input_files_content: Vec<(&str, &str)>,
+    db_output_dir: &TempDir,
+    db_name_prefix: &str,
+    extra_args: &[&str],
 ) -> Result<PathBuf, Box<dyn std::error::Error>> {
     let temp_input_dir = TempDir::new()?; // Temporary directory for input FASTA files
     let mut input_file_paths: Vec<PathBuf> = Vec::new();
@@ -53,6 +64,7 @@ fn run_build_for_test(
     for input_path_str in &string_input_paths {
         cmd.arg("-g").arg(input_path_str);
     }
+    cmd.args(extra_args);
 
     cmd.assert().success();
 
@@ -103,6 +115,8 @@ fn test_compare_basic() -> Result<(), Box<dyn std::error::Error>> {
     let union_val = 8 + 9 - intersection;
     assert_eq!(json_data["intersection_size"], intersection);
     assert_eq!(json_data["union_size"], union_val); // Should be 12
+    assert_eq!(json_data["db1_only_kmers"], 8 - intersection);
+    assert_eq!(json_data["db2_only_kmers"], 9 - intersection);
 
     let expected_jaccard = intersection as f64 / union_val as f64; // 5.0 / 12.0
     assert!((json_data["jaccard_index"].as_f64().unwrap() - expected_jaccard).abs() < 1e-6);
@@ -143,6 +157,8 @@ fn test_compare_identical_databases() -> Result<(), Box<dyn std::error::Error>>
     assert_eq!(json_data["db2_total_unique_kmers_across_references"], 2);
     assert_eq!(json_data["intersection_size"], 2);
     assert_eq!(json_data["union_size"], 2);
+    assert_eq!(json_data["db1_only_kmers"], 0);
+    assert_eq!(json_data["db2_only_kmers"], 0);
     assert!((json_data["jaccard_index"].as_f64().unwrap() - 1.0).abs() < 1e-6);
     Ok(())
 }
@@ -218,3 +234,576 @@ fn test_compare_kmer_size_mismatch() -> Result<(), Box<dyn std::error::Error>> {
     ));
     Ok(())
 }
+
+#[test]
+fn test_compare_hashed_vs_unhashed_mismatch() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_db_dir = TempDir::new()?;
+    let plain_path = run_build_for_test(4, vec![("plain.fa", FASTA_DB1)], &temp_db_dir, "db_plain")?;
+    let hashed_path = run_build_with_args_for_test(
+        4,
+        vec![("hashed.fa", FASTA_DB1)],
+        &temp_db_dir,
+        "db_hashed",
+        &["--hashed"],
+    )?;
+
+    let mut cmd = Command::cargo_bin("orion-kmer")?;
+    let output_json_file = NamedTempFile::new()?; // Will not be written
+    cmd.arg("compare")
+        .arg("--db1")
+        .arg(&plain_path)
+        .arg("--db2")
+        .arg(&hashed_path)
+        .arg("-o")
+        .arg(output_json_file.path());
+
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "K-mer databases use incompatible k-mer hashing",
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_compare_canonical_mismatch_strict() -> Result<(), Box<dyn std::error::Error>> {
+    use orion_kmer::db_types::KmerDbV2;
+
+    let temp_db_dir = TempDir::new()?;
+    let canonical_path =
+        run_build_for_test(4, vec![("canonical.fa", FASTA_DB1)], &temp_db_dir, "db_canonical")?;
+    let mut raw_db = KmerDbV2::new(4);
+    raw_db.canonical = false;
+    let raw_path = temp_db_dir.path().join("raw.db");
+    orion_kmer::utils::write_kmer_db(&raw_db, &raw_path)?;
+
+    let output_json_file = NamedTempFile::new()?;
+    let mut cmd = Command::cargo_bin("orion-kmer")?;
+    cmd.arg("compare")
+        .arg("--db1")
+        .arg(&canonical_path)
+        .arg("--db2")
+        .arg(&raw_path)
+        .arg("-o")
+        .arg(output_json_file.path())
+        .arg("--strict");
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "disagree on whether k-mers are stored in canonical form",
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_compare_rejects_canonical_against_non_canonical_database() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_db_dir = TempDir::new()?;
+    let canonical_path =
+        run_build_for_test(4, vec![("genome.fa", FASTA_DB1)], &temp_db_dir, "db_canonical")?;
+    let forward_path = run_build_with_args_for_test(
+        4,
+        vec![("genome.fa", FASTA_DB1)],
+        &temp_db_dir,
+        "db_forward",
+        &["--no-canonical"],
+    )?;
+
+    // Without --strict the mismatch is still an error, not a warning with a wrong Jaccard index
+    let output_json_file = NamedTempFile::new()?;
+    let mut cmd = Command::cargo_bin("orion-kmer")?;
+    cmd.arg("compare")
+        .arg("--db1")
+        .arg(&canonical_path)
+        .arg("--db2")
+        .arg(&forward_path)
+        .arg("-o")
+        .arg(output_json_file.path());
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "disagree on whether k-mers are stored in canonical form",
+    ));
+
+    // Two non-canonical databases compare normally
+    let mut cmd = Command::cargo_bin("orion-kmer")?;
+    cmd.arg("compare")
+        .arg("--db1")
+        .arg(&forward_path)
+        .arg("--db2")
+        .arg(&forward_path)
+        .arg("-o")
+        .arg(output_json_file.path());
+    cmd.assert().success();
+    let report: JsonValue = serde_json::from_str(&fs::read_to_string(output_json_file.path())?)?;
+    assert_eq!(report["jaccard_index"], 1.0);
+    Ok(())
+}
+
+#[test]
+fn test_compare_coerce_canonical_canonicalizes_non_canonical_side() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_db_dir = TempDir::new()?;
+    let canonical_path =
+        run_build_for_test(4, vec![("genome.fa", FASTA_DB1)], &temp_db_dir, "db_canonical")?;
+    let forward_path = run_build_with_args_for_test(
+        4,
+        vec![("genome.fa", FASTA_DB1)],
+        &temp_db_dir,
+        "db_forward",
+        &["--no-canonical"],
+    )?;
+
+    // Canonicalized, the forward k-mers of the same genome are exactly the canonical database
+    for (db1, db2) in [(&canonical_path, &forward_path), (&forward_path, &canonical_path)] {
+        let output_json_file = NamedTempFile::new()?;
+        let mut cmd = Command::cargo_bin("orion-kmer")?;
+        cmd.arg("compare")
+            .arg("--db1")
+            .arg(db1)
+            .arg("--db2")
+            .arg(db2)
+            .arg("-o")
+            .arg(output_json_file.path())
+            .arg("--coerce-canonical");
+        cmd.assert()
+            .success()
+            .stderr(predicate::str::contains("--coerce-canonical: canonicalizing the k-mers"));
+        let report: JsonValue = serde_json::from_str(&fs::read_to_string(output_json_file.path())?)?;
+        assert_eq!(report["jaccard_index"], 1.0);
+        assert_eq!(report["db1_total_unique_kmers_across_references"], report["intersection_size"]);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_compare_collision_check_estimates_hash_collisions() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_db_dir = TempDir::new()?;
+    let hashed = |name: &str| {
+        run_build_with_args_for_test(4, vec![("genome.fa", FASTA_DB1)], &temp_db_dir, name, &["--hashed"])
+    };
+    let (db1_path, db2_path) = (hashed("hashed1")?, hashed("hashed2")?);
+    let compare = |extra_args: &[&str]| -> Result<(JsonValue, String), Box<dyn std::error::Error>> {
+        let output_json_file = NamedTempFile::new()?;
+        let output = Command::cargo_bin("orion-kmer")?
+            .arg("compare")
+            .arg("--db1")
+            .arg(&db1_path)
+            .arg("--db2")
+            .arg(&db2_path)
+            .arg("-o")
+            .arg(output_json_file.path())
+            .arg("--collision-check")
+            .args(extra_args)
+            .output()?;
+        assert!(output.status.success());
+        let report = serde_json::from_str(&fs::read_to_string(output_json_file.path())?)?;
+        Ok((report, String::from_utf8(output.stderr)?))
+    };
+
+    let (report, stderr) = compare(&[])?;
+    let check = &report["collision_check"];
+    assert_eq!(check["hash_bits"], 64);
+    let size = report["db1_total_unique_kmers_across_references"].as_f64().unwrap();
+    let expected = check["expected_false_shared_kmers"].as_f64().unwrap();
+    assert!((expected - size * size / 2f64.powi(64)).abs() < 1e-30);
+    assert!(check["collision_probability"].as_f64().unwrap() > 0.0);
+    assert!(!stderr.contains("--collision-check"));
+
+    let (_, stderr) = compare(&["--collision-threshold", "0"])?;
+    assert!(stderr.contains("--collision-check: with 64-bit hashes"));
+    Ok(())
+}
+
+#[test]
+fn test_compare_reference_selection() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_db_dir = TempDir::new()?;
+    let db1_path = run_build_for_test(
+        4,
+        vec![("shared.fa", ">a\nACGTT"), ("spike_in.fa", ">b\nGGGGA")],
+        &temp_db_dir,
+        "db1",
+    )?;
+    let db2_path = run_build_for_test(
+        4,
+        vec![("other.fa", ">c\nACGTTGGGGA")],
+        &temp_db_dir,
+        "db2",
+    )?;
+
+    let run_compare = |extra_args: &[&str]| -> Result<JsonValue, Box<dyn std::error::Error>> {
+        let output_json_file = NamedTempFile::new()?;
+        Command::cargo_bin("orion-kmer")?
+            .arg("compare")
+            .arg("--db1")
+            .arg(&db1_path)
+            .arg("--db2")
+            .arg(&db2_path)
+            .arg("-o")
+            .arg(output_json_file.path())
+            .args(extra_args)
+            .assert()
+            .success();
+        Ok(serde_json::from_str(&fs::read_to_string(output_json_file.path())?)?)
+    };
+
+    // shared.fa: ACGT, CGTT; spike_in.fa: GGGG, GGGA
+    let all = run_compare(&[])?;
+    assert_eq!(all["db1_total_unique_kmers_across_references"], 4);
+    assert_eq!(all["intersection_size"], 4);
+
+    let excluded = run_compare(&["--exclude-reference", "spike_in.fa"])?;
+    assert_eq!(excluded["db1_total_unique_kmers_across_references"], 2);
+    assert_eq!(excluded["intersection_size"], 2);
+
+    // Selecting only db1's spike-in empties db2, which has no reference of that name
+    let only = run_compare(&["--only-reference", "spike_in.fa"])?;
+    assert_eq!(only["db1_total_unique_kmers_across_references"], 2);
+    assert_eq!(only["db2_total_unique_kmers_across_references"], 0);
+
+    let output_json_file = NamedTempFile::new()?;
+    Command::cargo_bin("orion-kmer")?
+        .arg("compare")
+        .arg("--db1")
+        .arg(&db1_path)
+        .arg("--db2")
+        .arg(&db2_path)
+        .arg("-o")
+        .arg(output_json_file.path())
+        .arg("--exclude-reference")
+        .arg("missing.fa")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Reference 'missing.fa' was not found"));
+    Ok(())
+}
+
+#[test]
+fn test_compare_against_fasta_matches_prebuilt_database() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_db_dir = TempDir::new()?;
+    let fasta_path = temp_db_dir.path().join("db2.fa");
+    fs::write(&fasta_path, FASTA_DB2)?;
+
+    for extra_build_args in [&[][..], &["--hashed"][..]] {
+        let db1_path =
+            run_build_with_args_for_test(4, vec![("db1.fa", FASTA_DB1)], &temp_db_dir, "db1", extra_build_args)?;
+        let output_json_file = NamedTempFile::new()?;
+        Command::cargo_bin("orion-kmer")?
+            .arg("compare")
+            .arg("--db1")
+            .arg(&db1_path)
+            .arg("--against-fasta")
+            .arg(&fasta_path)
+            .arg("-o")
+            .arg(output_json_file.path())
+            .assert()
+            .success();
+        let json_data: JsonValue = serde_json::from_str(&fs::read_to_string(output_json_file.path())?)?;
+
+        // Same statistics as test_compare_basic against the database built from FASTA_DB2
+        assert_eq!(json_data["db2_path"], fasta_path.to_str().unwrap());
+        assert_eq!(json_data["db1_total_unique_kmers_across_references"], 8);
+        assert_eq!(json_data["db2_total_unique_kmers_across_references"], 9);
+        assert_eq!(json_data["intersection_size"], 5);
+        assert_eq!(json_data["union_size"], 12);
+    }
+
+    // --db2 and --against-fasta are alternatives
+    let db1_path = run_build_for_test(4, vec![("db1.fa", FASTA_DB1)], &temp_db_dir, "db1")?;
+    Command::cargo_bin("orion-kmer")?
+        .arg("compare")
+        .arg("--db1")
+        .arg(&db1_path)
+        .arg("--db2")
+        .arg(&db1_path)
+        .arg("--against-fasta")
+        .arg(&fasta_path)
+        .arg("-o")
+        .arg(temp_db_dir.path().join("out.json"))
+        .assert()
+        .failure();
+    Ok(())
+}
+
+#[test]
+fn test_compare_precision_rounds_jaccard() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_db_dir = TempDir::new()?;
+    // db1: ACGT, AACG (CGTT); db2: AACG (CGTT), CAAC (GTTG) -> Jaccard 1/3
+    let db1_path = run_build_for_test(4, vec![("a.fa", ">a\nACGTT")], &temp_db_dir, "db1")?;
+    let db2_path = run_build_for_test(4, vec![("b.fa", ">b\nCGTTG")], &temp_db_dir, "db2")?;
+
+    let run_compare = |extra_args: &[&str]| -> Result<JsonValue, Box<dyn std::error::Error>> {
+        let output_json_file = NamedTempFile::new()?;
+        Command::cargo_bin("orion-kmer")?
+            .args(extra_args)
+            .arg("compare")
+            .arg("--db1")
+            .arg(&db1_path)
+            .arg("--db2")
+            .arg(&db2_path)
+            .arg("-o")
+            .arg(output_json_file.path())
+            .assert()
+            .success();
+        Ok(serde_json::from_str(&fs::read_to_string(output_json_file.path())?)?)
+    };
+
+    let full = run_compare(&[])?;
+    assert_eq!(full["jaccard_index"].as_f64().unwrap(), 1.0 / 3.0);
+
+    let rounded = run_compare(&["--precision", "3"])?;
+    assert_eq!(rounded["jaccard_index"].as_f64().unwrap(), 0.333);
+    Ok(())
+}
+
+#[test]
+fn test_jaccard_stats_on_in_memory_sets() {
+    use orion_kmer::commands::compare::jaccard_stats;
+    use std::collections::HashSet;
+
+    let a: HashSet<u64> = [1, 2, 3, 4].into_iter().collect();
+    let b: HashSet<u64> = [3, 4, 5].into_iter().collect();
+    let stats = jaccard_stats(&a, &b);
+    assert_eq!((stats.a_size, stats.b_size), (4, 3));
+    assert_eq!(stats.intersection_size, 2);
+    assert_eq!(stats.union_size, 5);
+    assert_eq!(stats.jaccard_index, 0.4);
+    assert_eq!(stats.containment_a_in_b, 0.5);
+    assert!((stats.containment_b_in_a - 2.0 / 3.0).abs() < 1e-12);
+    // The statistics are symmetric apart from the containment direction
+    let swapped = jaccard_stats(&b, &a);
+    assert_eq!(swapped.jaccard_index, stats.jaccard_index);
+    assert_eq!(swapped.containment_a_in_b, stats.containment_b_in_a);
+
+    let empty = HashSet::new();
+    let stats = jaccard_stats(&empty, &empty);
+    assert_eq!(stats.union_size, 0);
+    assert_eq!(stats.jaccard_index, 0.0);
+    assert_eq!(stats.containment_a_in_b, 0.0);
+}
+
+#[test]
+fn test_compare_tsv_rows_append_to_shared_table() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_db_dir = TempDir::new()?;
+    // db1: ACGT, AACG (CGTT); db2: AACG (CGTT), CAAC (GTTG)
+    let db1_path = run_build_for_test(4, vec![("a.fa", ">a\nACGTT")], &temp_db_dir, "db1")?;
+    let db2_path = run_build_for_test(4, vec![("b.fa", ">b\nCGTTG")], &temp_db_dir, "db2")?;
+    let tsv_path = temp_db_dir.path().join("pairs.tsv");
+
+    // No JSON output is needed when --tsv is given
+    let run_compare = |first: &PathBuf, second: &PathBuf, extra_args: &[&str]| -> Result<(), Box<dyn std::error::Error>> {
+        Command::cargo_bin("orion-kmer")?
+            .args(["--precision", "3", "compare", "--db1"])
+            .arg(first)
+            .arg("--db2")
+            .arg(second)
+            .arg("--tsv")
+            .arg(&tsv_path)
+            .args(extra_args)
+            .assert()
+            .success();
+        Ok(())
+    };
+    let header = "db1\tdb2\tk\tintersection\tunion\tjaccard\tcontainment1\tcontainment2\n";
+    let row = |first: &PathBuf, second: &PathBuf, containments: &str| {
+        format!("{}\t{}\t4\t1\t3\t0.333\t{}\n", first.display(), second.display(), containments)
+    };
+    let db3_path = run_build_for_test(4, vec![("c.fa", ">c\nCGTTGAA")], &temp_db_dir, "db3")?;
+
+    run_compare(&db1_path, &db2_path, &["--append"])?;
+    run_compare(&db1_path, &db3_path, &["--append"])?;
+    // db3: AACG, CAAC, TCAA (TTGA), TTCA (TGAA) -> intersection AACG
+    let db1_db3 = format!("{}\t{}\t4\t1\t5\t0.2\t0.5\t0.25\n", db1_path.display(), db3_path.display());
+    assert_eq!(
+        fs::read_to_string(&tsv_path)?,
+        format!("{}{}{}", header, row(&db1_path, &db2_path, "0.5\t0.5"), db1_db3)
+    );
+
+    // Without --append the file is replaced
+    run_compare(&db1_path, &db2_path, &[])?;
+    assert_eq!(fs::read_to_string(&tsv_path)?, format!("{}{}", header, row(&db1_path, &db2_path, "0.5\t0.5")));
+    Ok(())
+}
+
+#[test]
+fn test_compare_stream_fasta_estimates_jaccard_from_sketches() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let fasta_a = temp_dir.path().join("a.fa");
+    let fasta_b = temp_dir.path().join("b.fa");
+    let fasta_c = temp_dir.path().join("c.fa");
+    fs::write(&fasta_a, ">a\nACGTT\n")?; // ACGT, AACG (CGTT)
+    fs::write(&fasta_b, ">b\nCGTTG\n")?; // AACG (CGTT), CAAC (GTTG)
+    fs::write(&fasta_c, ">c\nGGGGCCCC\n")?; // No canonical k-mer shared with a.fa
+    let output_path = temp_dir.path().join("stream.json");
+
+    let run_stream_compare = |first: &PathBuf, second: &PathBuf, sketch_size: &str| -> Result<JsonValue, Box<dyn std::error::Error>> {
+        Command::cargo_bin("orion-kmer")?
+            .args(["compare", "-k", "4", "--sketch-size", sketch_size, "--stream-fasta"])
+            .arg(first)
+            .arg(second)
+            .arg("-o")
+            .arg(&output_path)
+            .assert()
+            .success();
+        Ok(serde_json::from_str(&fs::read_to_string(&output_path)?)?)
+    };
+
+    // A sketch holding every k-mer gives the exact Jaccard index
+    let partial = run_stream_compare(&fasta_a, &fasta_b, "100")?;
+    assert_eq!(partial["fasta1_sketch_hashes"], 2);
+    assert_eq!(partial["fasta2_sketch_hashes"], 2);
+    assert_eq!(partial["shared_sketch_hashes"], 1);
+    assert!((partial["estimated_jaccard_index"].as_f64().unwrap() - 1.0 / 3.0).abs() < 1e-9);
+
+    let identical = run_stream_compare(&fasta_a, &fasta_a, "1")?;
+    assert_eq!(identical["fasta1_sketch_hashes"], 1);
+    assert_eq!(identical["estimated_jaccard_index"], 1.0);
+
+    let disjoint = run_stream_compare(&fasta_a, &fasta_c, "100")?;
+    assert_eq!(disjoint["shared_sketch_hashes"], 0);
+    assert_eq!(disjoint["estimated_jaccard_index"], 0.0);
+
+    // k is required since there is no database to take it from
+    Command::cargo_bin("orion-kmer")?
+        .args(["compare", "--stream-fasta"])
+        .arg(&fasta_a)
+        .arg(&fasta_b)
+        .arg("-o")
+        .arg(&output_path)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--kmer-size"));
+    Ok(())
+}
+
+#[test]
+fn test_compare_output_format_tsv_writes_header_and_row() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_db_dir = TempDir::new()?;
+    let db1_path = run_build_for_test(4, vec![("a.fa", ">a\nACGTT")], &temp_db_dir, "db1")?;
+    let db2_path = run_build_for_test(4, vec![("b.fa", ">b\nCGTTG")], &temp_db_dir, "db2")?;
+    let output_path = temp_db_dir.path().join("comparison.tsv");
+
+    Command::cargo_bin("orion-kmer")?
+        .args(["--precision", "3", "compare", "--db1"])
+        .arg(&db1_path)
+        .arg("--db2")
+        .arg(&db2_path)
+        .arg("-o")
+        .arg(&output_path)
+        .args(["--output-format", "tsv"])
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(&output_path)?,
+        format!(
+            "db1\tdb2\tk\tintersection\tunion\tjaccard\tcontainment1\tcontainment2\n{}\t{}\t4\t1\t3\t0.333\t0.5\t0.5\n",
+            db1_path.display(),
+            db2_path.display()
+        )
+    );
+    Ok(())
+}
+
+#[test]
+fn test_compare_json_output_is_compressed_by_extension() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_db_dir = TempDir::new()?;
+    let db1_path = run_build_for_test(4, vec![("a.fa", ">a\nACGTT")], &temp_db_dir, "db1")?;
+    let db2_path = run_build_for_test(4, vec![("b.fa", ">b\nCGTTG")], &temp_db_dir, "db2")?;
+    let output_path = temp_db_dir.path().join("comparison.json.gz");
+
+    Command::cargo_bin("orion-kmer")?
+        .args(["compare", "--db1"])
+        .arg(&db1_path)
+        .arg("--db2")
+        .arg(&db2_path)
+        .arg("-o")
+        .arg(&output_path)
+        .assert()
+        .success();
+
+    let bytes = fs::read(&output_path)?;
+    assert!(bytes.starts_with(&[0x1f, 0x8b]), "comparison.json.gz is not gzip compressed");
+    let stats: JsonValue = serde_json::from_reader(flate2::read::MultiGzDecoder::new(&bytes[..]))?;
+    assert_eq!(stats["intersection_size"], 1);
+    assert_eq!(stats["union_size"], 3);
+    Ok(())
+}
+
+#[test]
+fn test_compare_sqlite_appends_comparison_rows() -> Result<(), Box<dyn std::error::Error>> {
+    let k = 4;
+    let temp_db_dir = TempDir::new()?;
+    let db1_path = run_build_for_test(k, vec![("db1.fa", FASTA_DB1)], &temp_db_dir, "db1")?;
+    let db2_path = run_build_for_test(k, vec![("db2.fa", FASTA_DB2)], &temp_db_dir, "db2")?;
+    let sqlite_path = temp_db_dir.path().join("results.sqlite");
+
+    // No JSON output is needed when --sqlite is given; a second run appends to the same table
+    for (first, second) in [(&db1_path, &db2_path), (&db2_path, &db1_path)] {
+        Command::cargo_bin("orion-kmer")?
+            .arg("compare")
+            .arg("--db1")
+            .arg(first)
+            .arg("--db2")
+            .arg(second)
+            .arg("--sqlite")
+            .arg(&sqlite_path)
+            .assert()
+            .success();
+    }
+
+    // db1_path, then k, set sizes and intersection, union, Jaccard index and containment
+    type ComparisonRow = (String, i64, i64, i64, i64, i64, f64, f64);
+    let conn = rusqlite::Connection::open(&sqlite_path)?;
+    let mut statement = conn.prepare(
+        "SELECT db1_path, kmer_size, db1_unique_kmers, db2_unique_kmers, intersection_size, union_size, \
+         jaccard_index, containment_1_in_2 FROM comparisons ORDER BY id",
+    )?;
+    let rows: Vec<ComparisonRow> = statement
+        .query_map([], |row| {
+            Ok((
+                row.get(0)?,
+                row.get(1)?,
+                row.get(2)?,
+                row.get(3)?,
+                row.get(4)?,
+                row.get(5)?,
+                row.get(6)?,
+                row.get(7)?,
+            ))
+        })?
+        .collect::<Result<_, _>>()?;
+    assert_eq!(rows.len(), 2);
+    let (db1, kmer_size, db1_kmers, db2_kmers, intersection, union_size, jaccard, containment) = &rows[0];
+    assert_eq!(db1, db1_path.to_str().unwrap());
+    assert_eq!((*kmer_size, *db1_kmers, *db2_kmers, *intersection, *union_size), (4, 8, 9, 5, 12));
+    assert!((jaccard - 5.0 / 12.0).abs() < 1e-6);
+    assert!((containment - 5.0 / 8.0).abs() < 1e-6);
+    assert_eq!((rows[1].2, rows[1].3), (9, 8));
+    Ok(())
+}
+
+#[test]
+fn test_compare_intersection_db_writes_shared_kmers() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_db_dir = TempDir::new()?;
+    let db1_path = run_build_for_test(4, vec![("db1.fa", FASTA_DB1)], &temp_db_dir, "db1")?;
+    let db2_path = run_build_for_test(4, vec![("db2.fa", FASTA_DB2)], &temp_db_dir, "db2")?;
+    // Without -o only the intersection is written, compressed by its extension
+    let intersection_path = temp_db_dir.path().join("shared.db.gz");
+    Command::cargo_bin("orion-kmer")?
+        .arg("compare")
+        .arg("--db1")
+        .arg(&db1_path)
+        .arg("--db2")
+        .arg(&db2_path)
+        .arg("--intersection-db")
+        .arg(&intersection_path)
+        .assert()
+        .success();
+
+    let intersection_db = orion_kmer::utils::load_kmer_db_v2(&intersection_path)?;
+    let db1 = orion_kmer::utils::load_kmer_db_v2(&db1_path)?;
+    let db2 = orion_kmer::utils::load_kmer_db_v2(&db2_path)?;
+    assert_eq!(intersection_db.k, 4);
+    assert!(intersection_db.canonical);
+    assert_eq!(intersection_db.references.keys().collect::<Vec<_>>(), vec!["intersection"]);
+    let expected: HashSet<u64> = db1
+        .get_all_kmers_unified()
+        .intersection(&db2.get_all_kmers_unified())
+        .copied()
+        .collect();
+    assert_eq!(expected.len(), 5);
+    assert_eq!(intersection_db.references["intersection"], expected);
+    Ok(())
+}
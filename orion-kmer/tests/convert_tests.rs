@@ -0,0 +1,119 @@
+use assert_cmd::prelude::*;
+use orion_kmer::db_types::KmerDbV2;
+use orion_kmer::kmer::hash_kmer;
+use orion_kmer::kmer_index::{is_sorted_kmer_index, SortedKmerMmap};
+use orion_kmer::utils::load_kmer_db_v2;
+use predicates::prelude::*;
+use std::{collections::HashSet, fs, path::PathBuf, process::Command};
+use tempfile::TempDir;
+
+fn orion_kmer_cmd() -> Result<Command, Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("orion-kmer")?;
+    let project_root = std::env::var("CARGO_MANIFEST_DIR").unwrap_or_else(|_| ".".to_string());
+    cmd.current_dir(project_root);
+    Ok(cmd)
+}
+
+// Builds a k=4 database of two references in `dir` and returns its path.
+fn build_db(dir: &TempDir, extra_args: &[&str]) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let ref_a = dir.path().join("refA.fa");
+    let ref_b = dir.path().join("refB.fa");
+    fs::write(&ref_a, ">refA\nACGTACGTTTGC\n")?;
+    fs::write(&ref_b, ">refB\nGGGAAAAATTTT\n")?;
+    let db_path = dir.path().join("source.db");
+    orion_kmer_cmd()?
+        .arg("build")
+        .args(["-k", "4", "-g"])
+        .arg(&ref_a)
+        .arg(&ref_b)
+        .arg("-o")
+        .arg(&db_path)
+        .args(extra_args)
+        .assert()
+        .success();
+    Ok(db_path)
+}
+
+fn run_convert(input: &PathBuf, output: &PathBuf, to: &str) -> Result<Command, Box<dyn std::error::Error>> {
+    let mut cmd = orion_kmer_cmd()?;
+    cmd.arg("convert")
+        .arg("-i")
+        .arg(input)
+        .arg("-o")
+        .arg(output)
+        .args(["--to", to]);
+    Ok(cmd)
+}
+
+#[test]
+fn test_convert_round_trips_through_sorted_index() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    let db_path = build_db(&dir, &[])?;
+    let original = load_kmer_db_v2(&db_path)?;
+
+    let index_path = dir.path().join("converted.idx");
+    run_convert(&db_path, &index_path, "sorted")?.assert().success();
+    assert!(is_sorted_kmer_index(&index_path)?);
+    let index = SortedKmerMmap::open(&index_path)?;
+    assert_eq!(index.num_references(), 2);
+    assert_eq!(index.unified_kmers().len(), original.total_unique_kmers());
+
+    // Back to a hashset database, compressed
+    let restored_path = dir.path().join("restored.db.gz");
+    run_convert(&index_path, &restored_path, "hashset")?.assert().success();
+    assert!(!is_sorted_kmer_index(&restored_path)?);
+    let restored = load_kmer_db_v2(&restored_path)?;
+    assert_eq!(restored.k, original.k);
+    assert_eq!(restored.canonical, original.canonical);
+    assert_eq!(restored.hashing, None);
+    assert_eq!(restored.references, original.references);
+    Ok(())
+}
+
+#[test]
+fn test_convert_to_hashed_warns_and_hashes_every_reference() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    let db_path = build_db(&dir, &[])?;
+    let original = load_kmer_db_v2(&db_path)?;
+
+    let hashed_path = dir.path().join("hashed.db");
+    run_convert(&db_path, &hashed_path, "hashed")?
+        .args(["--hash-seed", "7"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("one-way"));
+
+    let hashed: KmerDbV2 = load_kmer_db_v2(&hashed_path)?;
+    assert_eq!(hashed.hashing.map(|hashing| hashing.seed), Some(7));
+    for (name, kmer_set) in &original.references {
+        let expected: HashSet<u64> = kmer_set.iter().map(|&kmer| hash_kmer(kmer, 4, 7)).collect();
+        assert_eq!(hashed.references[name], expected);
+    }
+
+    // The same hashing matches what `build --hashed` stores
+    let built_hashed = load_kmer_db_v2(&build_db(&dir, &["--hashed", "--hash-seed", "7"])?)?;
+    assert_eq!(built_hashed.references, hashed.references);
+    Ok(())
+}
+
+#[test]
+fn test_convert_rejects_unhashing_and_rehashing() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    let db_path = build_db(&dir, &["--hashed"])?;
+
+    run_convert(&db_path, &dir.path().join("unhashed.db"), "hashset")?
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be converted back"));
+    run_convert(&db_path, &dir.path().join("rehashed.db"), "hashed")?
+        .args(["--hash-seed", "1"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("incompatible k-mer hashing"));
+
+    // A hashed database keeps its hashing in a sorted index
+    let index_path = dir.path().join("hashed.idx");
+    run_convert(&db_path, &index_path, "sorted")?.assert().success();
+    assert!(SortedKmerMmap::open(&index_path)?.hashing.is_some());
+    Ok(())
+}
@@ -128,6 +128,35 @@ fn run_count_test_with_setup(
     Ok(result_content)
 }
 
+// Helper to run count on content-based inputs with additional command-line arguments
+fn run_count_with_extra_args(
+    k: u8,
+    input_files_content: Vec<(&str, &str)>, // Vec of (filename, content)
+    extra_args: &[&str],
+) -> Result<String, Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let output_path = temp_dir.path().join("counts.tsv");
+
+    let mut cmd = Command::cargo_bin("orion-kmer")?;
+    let project_root = std::env::var("CARGO_MANIFEST_DIR").unwrap_or_else(|_| ".".to_string());
+    cmd.current_dir(project_root);
+    cmd.arg("count")
+        .arg("-k")
+        .arg(k.to_string())
+        .arg("-o")
+        .arg(&output_path);
+
+    for (name, content) in &input_files_content {
+        let file_path = temp_dir.path().join(name);
+        let mut file = File::create(&file_path)?;
+        writeln!(file, "{}", content)?;
+        cmd.arg("-i").arg(&file_path);
+    }
+    cmd.args(extra_args);
+
+    cmd.assert().success();
+    Ok(fs::read_to_string(&output_path)?)
+}
 
 fn sort_lines(content: &str) -> String {
     let mut lines: Vec<&str> = content.trim().lines().collect();
@@ -466,3 +495,1229 @@ fn test_count_multiple_compressed_inputs_k5() -> Result<(), Box<dyn std::error::
     assert_eq!(sort_lines(&content), sort_lines(expected_combined_k5));
     Ok(())
 }
+
+#[test]
+fn test_count_expand_iupac() -> Result<(), Box<dyn std::error::Error>> {
+    // R = A/G: ARG expands to AAG and AGG (both already canonical)
+    let skipped = run_count_with_extra_args(3, vec![("iupac.fasta", ">s1\nARG")], &[])?;
+    assert_eq!(skipped.trim(), "");
+
+    let expanded =
+        run_count_with_extra_args(3, vec![("iupac.fasta", ">s1\nARG")], &["--expand-iupac"])?;
+    assert_eq!(sort_lines(&expanded), "AAG\t1\nAGG\t1");
+
+    // Two ambiguous positions exceed a cap of 1, so the window is skipped
+    let capped = run_count_with_extra_args(
+        3,
+        vec![("iupac.fasta", ">s1\nRRG")],
+        &["--expand-iupac", "--max-ambiguous-bases", "1"],
+    )?;
+    assert_eq!(capped.trim(), "");
+    Ok(())
+}
+
+#[test]
+fn test_count_sequence_id_filters() -> Result<(), Box<dyn std::error::Error>> {
+    // k=3: chr1 -> AAA, chr2 -> CCC, plasmid1 -> ACA
+    let input = vec![(
+        "multi.fasta",
+        ">chr1 first chromosome\nAAA\n>chr2\nCCC\n>plasmid1\nACA",
+    )];
+
+    let included = run_count_with_extra_args(3, input.clone(), &["--include-seq", "chr1"])?;
+    assert_eq!(sort_lines(&included), "AAA\t1");
+
+    let excluded = run_count_with_extra_args(3, input.clone(), &["--exclude-seq", "chr2"])?;
+    assert_eq!(sort_lines(&excluded), "AAA\t1\nACA\t1");
+
+    let by_regex = run_count_with_extra_args(3, input.clone(), &["--seq-regex", "^chr"])?;
+    assert_eq!(sort_lines(&by_regex), "AAA\t1\nCCC\t1");
+
+    // Exclusion wins over a matching regex; include names and the regex are combined
+    let combined = run_count_with_extra_args(
+        3,
+        input,
+        &[
+            "--seq-regex",
+            "^chr",
+            "--exclude-seq",
+            "chr1",
+            "--include-seq",
+            "plasmid1",
+        ],
+    )?;
+    assert_eq!(sort_lines(&combined), "ACA\t1\nCCC\t1");
+    Ok(())
+}
+
+#[test]
+fn test_count_progress_json_events() -> Result<(), Box<dyn std::error::Error>> {
+    let progress_dir = TempDir::new()?;
+    let progress_path = progress_dir.path().join("progress.ndjson");
+    run_count_with_extra_args(
+        3,
+        vec![("a.fasta", ">a\nACGT"), ("b.fasta", ">b\nGGGA")],
+        &["--progress-json", progress_path.to_str().unwrap()],
+    )?;
+
+    let events: Vec<serde_json::Value> = fs::read_to_string(&progress_path)?
+        .lines()
+        .map(serde_json::from_str)
+        .collect::<Result<_, _>>()?;
+    assert!(events.len() >= 2, "expected a start and a final event");
+    for event in &events {
+        assert_eq!(event["task"], "Counting k-mers from input files");
+        assert_eq!(event["len"], 2);
+        assert!(event["elapsed_ms"].is_u64());
+    }
+    let last = events.last().unwrap();
+    assert_eq!(last["done"], true);
+    assert_eq!(last["pos"], 2);
+    Ok(())
+}
+
+#[test]
+fn test_count_mask_kmers_list_and_db() -> Result<(), Box<dyn std::error::Error>> {
+    let mask_dir = TempDir::new()?;
+    // TTT masks its canonical form AAA; the count-style second column is ignored
+    let mask_list_path = mask_dir.path().join("adapters.kmers");
+    fs::write(&mask_list_path, "TTT\t3\n\ncca\n")?;
+
+    let input = vec![("reads.fasta", ">r1\nAAAACCCA")];
+    let unmasked = run_count_with_extra_args(3, input.clone(), &[])?;
+    assert_eq!(sort_lines(&unmasked), "AAA\t2\nAAC\t1\nACC\t1\nCCA\t1\nCCC\t1");
+
+    let masked = run_count_with_extra_args(
+        3,
+        input.clone(),
+        &["--mask-kmers", mask_list_path.to_str().unwrap()],
+    )?;
+    assert_eq!(sort_lines(&masked), "AAC\t1\nACC\t1\nCCC\t1");
+
+    // A database mask with a different k is rejected
+    let mask_genome_path = mask_dir.path().join("adapter.fa");
+    fs::write(&mask_genome_path, ">adapter\nAAAAC\n")?;
+    let mask_db_path = mask_dir.path().join("adapter.db");
+    Command::cargo_bin("orion-kmer")?
+        .arg("build")
+        .arg("-k")
+        .arg("4")
+        .arg("-g")
+        .arg(&mask_genome_path)
+        .arg("-o")
+        .arg(&mask_db_path)
+        .assert()
+        .success();
+    let input_path = mask_dir.path().join("reads.fasta");
+    fs::write(&input_path, ">r1\nAAAACCCA\n")?;
+    Command::cargo_bin("orion-kmer")?
+        .arg("count")
+        .arg("-k")
+        .arg("3")
+        .arg("-i")
+        .arg(&input_path)
+        .arg("-o")
+        .arg(mask_dir.path().join("counts.tsv"))
+        .arg("--mask-kmers")
+        .arg(&mask_db_path)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("does not match k-mer size 4"));
+
+    // With a matching k, the database's k-mers (AAAA, AAAC) are masked
+    let masked_by_db = run_count_with_extra_args(
+        4,
+        input,
+        &["--mask-kmers", mask_db_path.to_str().unwrap()],
+    )?;
+    assert_eq!(sort_lines(&masked_by_db), "AACC\t1\nACCC\t1\nCCCA\t1");
+    Ok(())
+}
+
+#[test]
+fn test_count_qual_weight() -> Result<(), Box<dyn std::error::Error>> {
+    // Q40 ('I') windows weigh 1.0; a Q10 ('+') base halves its windows' weight at the default Q20;
+    // GGT ('5' = Q20) is the reverse complement of ACC and adds a full 1.0 to it.
+    let fastq = "@r1\nAAACC\n+\nIII+I\n@r2\nGGT\n+\n555";
+    let output = run_count_with_extra_args(3, vec![("reads.fastq", fastq)], &["--qual-weight", "-m", "0"])?;
+    assert_eq!(output, "AAA\t1.0000\nAAC\t0.5000\nACC\t1.5000\n");
+
+    // The minimum count applies to the weighted count
+    let output = run_count_with_extra_args(3, vec![("reads.fastq", fastq)], &["--qual-weight"])?;
+    assert_eq!(output, "AAA\t1.0000\nACC\t1.5000\n");
+
+    // Raising the full-weight threshold scales every window down
+    let output = run_count_with_extra_args(
+        3,
+        vec![("reads.fastq", fastq)],
+        &["--qual-weight", "--full-weight-qual", "40", "-m", "0"],
+    )?;
+    assert_eq!(output, "AAA\t1.0000\nAAC\t0.2500\nACC\t0.7500\n");
+
+    // FASTA records have no qualities and weigh 1 per occurrence
+    let output = run_count_with_extra_args(3, vec![("seqs.fa", ">s1\nAAAA")], &["--qual-weight"])?;
+    assert_eq!(output, "AAA\t2.0000\n");
+    Ok(())
+}
+
+#[test]
+fn test_count_per_record_unique_counts_records_containing_kmer() -> Result<(), Box<dyn std::error::Error>> {
+    // k=3, r1: AAA x3; r2: AAA, AAG, AGT (ACT), GTT (AAC), TTT (AAA)
+    let fasta = ">r1\nAAAAA\n>r2\nAAAGTTT";
+    let output = run_count_with_extra_args(3, vec![("reads.fa", fasta)], &["-m", "1"])?;
+    assert_eq!(output, "AAA\t5\nAAC\t1\nAAG\t1\nACT\t1\n");
+
+    let output = run_count_with_extra_args(3, vec![("reads.fa", fasta)], &["-m", "1", "--per-record-unique"])?;
+    assert_eq!(output, "AAA\t2\nAAC\t1\nAAG\t1\nACT\t1\n");
+
+    // The minimum count then selects k-mers shared by that many records
+    let output = run_count_with_extra_args(3, vec![("reads.fa", fasta)], &["-m", "2", "--per-record-unique"])?;
+    assert_eq!(output, "AAA\t2\n");
+    Ok(())
+}
+
+#[test]
+fn test_count_matrix_columns_per_input() -> Result<(), Box<dyn std::error::Error>> {
+    // k=3, a.fa: AAA x2, AAC; b.fa: AAC (as GTT), CCC
+    let inputs = vec![("a.fa", ">s1\nAAAAC"), ("b.fa", ">s2\nGTT\n>s3\nCCC")];
+    let output = run_count_with_extra_args(3, inputs.clone(), &["--matrix"])?;
+    assert_eq!(output, "kmer\ta.fa\tb.fa\nAAA\t2\t0\nAAC\t1\t1\nCCC\t0\t1\n");
+
+    // The minimum count applies to the sum across files
+    let output = run_count_with_extra_args(3, inputs, &["--matrix", "-m", "2"])?;
+    assert_eq!(output, "kmer\ta.fa\tb.fa\nAAA\t2\t0\nAAC\t1\t1\n");
+    Ok(())
+}
+
+#[test]
+fn test_count_length_bins_split_counts_by_read_length() -> Result<(), Box<dyn std::error::Error>> {
+    // k=3; bins 0-4, 5-7 and 8+: r1 (4 bp) AAA, AAC; r2 (6 bp) AAA x3, AAC; r3 (8 bp) CCC x6
+    let inputs = vec![("reads.fa", ">r1\nAAAC\n>r2\nAAAAAC\n>r3\nCCCCCCCC\n")];
+    let output = run_count_with_extra_args(3, inputs.clone(), &["--length-bins", "5,8"])?;
+    assert_eq!(
+        output,
+        "AAA\t0-4\t1\nAAA\t5-7\t3\nAAC\t0-4\t1\nAAC\t5-7\t1\nCCC\t8+\t6\n"
+    );
+
+    // The minimum count applies within each bin
+    let output = run_count_with_extra_args(3, inputs, &["--length-bins", "5,8", "-m", "2"])?;
+    assert_eq!(output, "AAA\t5-7\t3\nCCC\t8+\t6\n");
+
+    let temp_dir = TempDir::new()?;
+    let input_path = temp_dir.path().join("reads.fa");
+    fs::write(&input_path, ">r1\nAAAC\n")?;
+    let mut cmd = Command::cargo_bin("orion-kmer")?;
+    cmd.arg("count")
+        .args(["-k", "3", "-i"])
+        .arg(&input_path)
+        .arg("-o")
+        .arg(temp_dir.path().join("counts.tsv"))
+        .args(["--length-bins", "8,5"]);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("strictly increasing"));
+    Ok(())
+}
+
+#[test]
+fn test_count_subsample_is_deterministic() -> Result<(), Box<dyn std::error::Error>> {
+    // 200 records contributing one AAA each, so the AAA count is the number of records kept
+    let fasta: String = (0..200).map(|i| format!(">read{} extra\nAAA\n", i)).collect();
+    let aaa_count = |extra_args: &[&str]| -> Result<usize, Box<dyn std::error::Error>> {
+        let output = run_count_with_extra_args(3, vec![("reads.fa", &fasta)], extra_args)?;
+        Ok(output
+            .lines()
+            .next()
+            .map_or(0, |line| line.split('\t').nth(1).unwrap().parse().unwrap()))
+    };
+
+    let half = aaa_count(&["--subsample", "0.5"])?;
+    assert!((60..=140).contains(&half), "kept {} of 200 records", half);
+    assert_eq!(aaa_count(&["--subsample", "0.5"])?, half);
+    assert_eq!(aaa_count(&["--subsample", "1"])?, 200);
+    assert_eq!(aaa_count(&["--subsample", "0"])?, 0);
+
+    // Another seed selects a different subset of the same size on average
+    let reseeded = aaa_count(&["--subsample", "0.5", "--subsample-seed", "7"])?;
+    assert!((60..=140).contains(&reseeded), "kept {} of 200 records", reseeded);
+    Ok(())
+}
+
+#[test]
+fn test_count_lz4_input_and_output_round_trip() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let reads = ">s1\nACGTACGTTT\n>s2\nGGGAAAC\n";
+    let input_path = temp_dir.path().join("input.fa.lz4");
+    let mut encoder = lz4_flex::frame::FrameEncoder::new(File::create(&input_path)?);
+    encoder.write_all(reads.as_bytes())?;
+    encoder.finish()?;
+    let output_path = temp_dir.path().join("counts.tsv.lz4");
+
+    Command::cargo_bin("orion-kmer")?
+        .arg("count")
+        .arg("-k")
+        .arg("3")
+        .arg("-i")
+        .arg(&input_path)
+        .arg("-o")
+        .arg(&output_path)
+        .assert()
+        .success();
+    let mut counts = String::new();
+    lz4_flex::frame::FrameDecoder::new(File::open(&output_path)?).read_to_string(&mut counts)?;
+    let plain_counts = run_count_with_extra_args(3, vec![("input.fa", reads)], &[])?;
+    assert_eq!(sort_lines(&counts), sort_lines(&plain_counts));
+    Ok(())
+}
+
+#[test]
+fn test_count_exactly_at_least_at_most_bounds() -> Result<(), Box<dyn std::error::Error>> {
+    // k=3: AAA x3, AAC x2 (AAC + GTT), ACC x1 (GGT)
+    let inputs = vec![("reads.fa", ">s1\nAAAAAC\n>s2\nGGTT\n")];
+    let run = |extra_args: &[&str]| run_count_with_extra_args(3, inputs.clone(), extra_args);
+
+    assert_eq!(run(&["--exactly", "1"])?, "ACC\t1\n");
+    assert_eq!(run(&["--at-least", "2"])?, "AAA\t3\nAAC\t2\n");
+    assert_eq!(run(&["--at-most", "2"])?, "AAC\t2\nACC\t1\n");
+    assert_eq!(run(&["--at-least", "2", "--at-most", "2"])?, "AAC\t2\n");
+    // --min-count still applies alongside the bounds
+    assert_eq!(run(&["-m", "3", "--at-most", "2"])?, "");
+    Ok(())
+}
+
+#[test]
+fn test_count_report_invalid_windows() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let input_path = temp_dir.path().join("reads.fa");
+    fs::write(&input_path, ">s1\nACGTNACGT\n")?;
+    let output_path = temp_dir.path().join("counts.tsv");
+    let count = |extra_args: &[&str]| -> Result<_, Box<dyn std::error::Error>> {
+        let mut cmd = Command::cargo_bin("orion-kmer")?;
+        cmd.arg("count")
+            .arg("-k")
+            .arg("4")
+            .arg("-i")
+            .arg(&input_path)
+            .arg("-o")
+            .arg(&output_path)
+            .args(extra_args);
+        Ok(cmd.assert().success())
+    };
+
+    // Four of the six windows overlap the N
+    count(&["--report-invalid"])?.stderr(predicate::str::contains(
+        "Skipped 4 k-mer windows containing N or other non-ACGT characters",
+    ));
+    // Expanded windows are not skipped
+    count(&["--report-invalid", "--expand-iupac"])?.stderr(predicate::str::contains("Skipped 0 k-mer windows"));
+    count(&[])?.stderr(predicate::str::contains("Skipped").not());
+    Ok(())
+}
+
+#[test]
+fn test_count_assume_format_overrides_detection() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let fasta_path = temp_dir.path().join("reads.fa");
+    fs::write(&fasta_path, ">s1\nACGT\n")?;
+    let gz_path = temp_dir.path().join("reads.fa.gz");
+    let mut encoder = flate2::write::GzEncoder::new(File::create(&gz_path)?, flate2::Compression::default());
+    encoder.write_all(b">s1\nACGT\n")?;
+    encoder.finish()?;
+    let output_path = temp_dir.path().join("counts.tsv");
+    let count = |input_path: &PathBuf, format: &str| -> Result<_, Box<dyn std::error::Error>> {
+        let mut cmd = Command::cargo_bin("orion-kmer")?;
+        cmd.arg("count")
+            .arg("-k")
+            .arg("4")
+            .arg("-i")
+            .arg(input_path)
+            .arg("-o")
+            .arg(&output_path)
+            .arg("--assume-format")
+            .arg(format);
+        Ok(cmd.assert())
+    };
+
+    count(&fasta_path, "fasta")?.success();
+    assert_eq!(fs::read_to_string(&output_path)?, "ACGT\t1\n");
+    // The FASTQ parser is forced, so a FASTA header is a parse error
+    count(&fasta_path, "fastq")?.failure();
+    // Compressed input is still detected normally
+    count(&gz_path, "fastq")?.success();
+    assert_eq!(fs::read_to_string(&output_path)?, "ACGT\t1\n");
+    Ok(())
+}
+
+#[test]
+fn test_count_single_thread_matches_concurrent_counting() -> Result<(), Box<dyn std::error::Error>> {
+    let inputs = vec![
+        ("a.fa", ">s1\nACGTACGTTTGACCANNACGTAC\n>s2\nGGGGCCCCAAAATTTT\n"),
+        ("b.fq", "@r1\nACGTTTGACCA\n+\nIIIIIIIIIII\n"),
+    ];
+    for extra_args in [&[][..], &["-m", "2"][..], &["--matrix"][..]] {
+        let concurrent = run_count_with_extra_args(4, inputs.clone(), extra_args)?;
+        let single_args: Vec<&str> = extra_args.iter().copied().chain(["--single-thread"]).collect();
+        let single = run_count_with_extra_args(4, inputs.clone(), &single_args)?;
+        assert!(!concurrent.is_empty());
+        assert_eq!(single, concurrent, "args {:?}", extra_args);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_count_bgzf_output_is_blocked_gzip() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let input_path = temp_dir.path().join("reads.fa");
+    fs::write(&input_path, ">s1\nAAAAAC\n>s2\nGGTT\n")?;
+    let bgz_path = temp_dir.path().join("counts.tsv.bgz");
+    Command::cargo_bin("orion-kmer")?
+        .args(["count", "-k", "3", "-i"])
+        .arg(&input_path)
+        .arg("-o")
+        .arg(&bgz_path)
+        .assert()
+        .success();
+
+    // A gzip member with the BGZF `BC` extra field, ending in the BGZF EOF block
+    let bgzf = fs::read(&bgz_path)?;
+    assert_eq!(&bgzf[..4], &[0x1f, 0x8b, 0x08, 0x04]);
+    assert_eq!(&bgzf[12..14], b"BC");
+    assert!(bgzf.ends_with(&orion_kmer::bgzf::BGZF_EOF_BLOCK));
+    let mut decoded = String::new();
+    flate2::read::MultiGzDecoder::new(&bgzf[..]).read_to_string(&mut decoded)?;
+    assert_eq!(decoded, "AAA\t3\nAAC\t2\nACC\t1\n");
+
+    // .bgz input is read back like .gz
+    let sum_path = temp_dir.path().join("sum.tsv");
+    Command::cargo_bin("orion-kmer")?
+        .args(["sum", "-i"])
+        .arg(&bgz_path)
+        .arg("-o")
+        .arg(&sum_path)
+        .assert()
+        .success();
+    assert_eq!(fs::read_to_string(&sum_path)?, decoded);
+    Ok(())
+}
+
+#[test]
+fn test_count_profile_prints_phase_breakdown() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let input_path = temp_dir.path().join("reads.fa");
+    fs::write(&input_path, ">s1\nACGTACGT\n")?;
+    let output_path = temp_dir.path().join("counts.tsv");
+    let count = |extra_args: &[&str]| -> Result<_, Box<dyn std::error::Error>> {
+        let mut cmd = Command::cargo_bin("orion-kmer")?;
+        cmd.args(extra_args)
+            .args(["count", "-k", "4", "-i"])
+            .arg(&input_path)
+            .arg("-o")
+            .arg(&output_path);
+        Ok(cmd.assert().success())
+    };
+
+    let profiled = count(&["--profile"])?;
+    let stderr = String::from_utf8(profiled.get_output().stderr.clone())?;
+    assert!(stderr.contains("Phase breakdown for count"), "{}", stderr);
+    for phase in ["parse", "k-mer extraction", "sort and write", "other"] {
+        assert!(stderr.lines().any(|line| line.trim_start().starts_with(phase)), "{}", stderr);
+    }
+    count(&[])?.stderr(predicate::str::contains("Phase breakdown").not());
+
+    // JSON log lines are not interleaved with the table, and a failed run prints none
+    count(&["--profile", "--log-format", "json"])?.stderr(
+        predicate::str::contains("Phase breakdown")
+            .not()
+            .and(predicate::str::contains("--profile is ignored with --log-format json")),
+    );
+    Command::cargo_bin("orion-kmer")?
+        .args(["--profile", "count", "-k", "4", "-i"])
+        .arg(temp_dir.path().join("missing.fa"))
+        .arg("-o")
+        .arg(&output_path)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Phase breakdown").not());
+    Ok(())
+}
+
+#[test]
+fn test_count_reports_records_shorter_than_k() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let project_root = std::env::var("CARGO_MANIFEST_DIR").unwrap_or_else(|_| ".".to_string());
+    let run = |content: &str, extra_args: &[&str]| -> Result<(String, String), Box<dyn std::error::Error>> {
+        let input_path = temp_dir.path().join("reads.fasta");
+        let output_path = temp_dir.path().join("counts.tsv");
+        fs::write(&input_path, content)?;
+        let mut cmd = Command::cargo_bin("orion-kmer")?;
+        cmd.current_dir(&project_root)
+            .args(["count", "-k", "4", "-i"])
+            .arg(&input_path)
+            .arg("-o")
+            .arg(&output_path)
+            .args(extra_args);
+        let output = cmd.assert().success().get_output().clone();
+        Ok((fs::read_to_string(&output_path)?, String::from_utf8(output.stderr)?))
+    };
+
+    let (counts, stderr) = run(">r1\nACG\n>r2\nTT\n", &[])?;
+    assert!(counts.trim().is_empty());
+    assert!(stderr.contains("All 2 records in"), "{}", stderr);
+    assert!(stderr.contains("are shorter than k=4"), "{}", stderr);
+
+    // Some long enough records: the skip count is only logged
+    let (counts, stderr) = run(">r1\nACG\n>r2\nACGTA\n", &["-v"])?;
+    assert_eq!(sort_lines(&counts), "ACGT\t1\nCGTA\t1");
+    assert!(stderr.contains("Skipped 1 of 2 records shorter than k=4"), "{}", stderr);
+    Ok(())
+}
+
+
+#[test]
+fn test_count_output_is_compressed_by_extension() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let input_path = temp_dir.path().join("input.fa");
+    fs::write(&input_path, ">seq1\nACGTACGT\n")?;
+    let run_count = |output_path: &PathBuf| -> Result<(), Box<dyn std::error::Error>> {
+        Command::cargo_bin("orion-kmer")?
+            .args(["count", "-k", "4", "-i"])
+            .arg(&input_path)
+            .arg("-o")
+            .arg(output_path)
+            .assert()
+            .success();
+        Ok(())
+    };
+    let plain_path = temp_dir.path().join("counts.tsv");
+    run_count(&plain_path)?;
+    let expected = sort_lines(&fs::read_to_string(&plain_path)?);
+    assert!(!expected.is_empty());
+
+    for (name, magic) in [
+        ("counts.tsv.gz", &[0x1f, 0x8b][..]),
+        ("counts.tsv.xz", &[0xfd, b'7', b'z', b'X', b'Z', 0x00][..]),
+        ("counts.tsv.zst", &[0x28, 0xb5, 0x2f, 0xfd][..]),
+    ] {
+        let output_path = temp_dir.path().join(name);
+        run_count(&output_path)?;
+        let bytes = fs::read(&output_path)?;
+        assert!(bytes.starts_with(magic), "{} is not compressed", name);
+        let mut decoded = String::new();
+        match name.rsplit('.').next() {
+            Some("gz") => flate2::read::MultiGzDecoder::new(&bytes[..]).read_to_string(&mut decoded)?,
+            Some("xz") => xz2::read::XzDecoder::new(&bytes[..]).read_to_string(&mut decoded)?,
+            _ => zstd::stream::read::Decoder::new(&bytes[..])?.read_to_string(&mut decoded)?,
+        };
+        assert_eq!(sort_lines(&decoded), expected);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_count_rna_input_counts_u_as_t() -> Result<(), Box<dyn std::error::Error>> {
+    let rna_counts = run_count_with_extra_args(4, vec![("rna.fa", ">rna\nACGUACGU")], &["--rna"])?;
+    let dna_counts = run_count_with_extra_args(4, vec![("dna.fa", ">dna\nACGTACGT")], &[])?;
+    assert!(!rna_counts.is_empty());
+    assert_eq!(sort_lines(&rna_counts), sort_lines(&dna_counts));
+    Ok(())
+}
+
+#[test]
+fn test_count_match_db_uses_database_k() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let genome_path = temp_dir.path().join("ref.fa");
+    fs::write(&genome_path, ">ref\nACGTACGTAC\n")?;
+    let db_path = temp_dir.path().join("ref.db.gz");
+    Command::cargo_bin("orion-kmer")?
+        .args(["build", "-k", "5", "-g"])
+        .arg(&genome_path)
+        .arg("-o")
+        .arg(&db_path)
+        .assert()
+        .success();
+
+    let reads_path = temp_dir.path().join("reads.fa");
+    fs::write(&reads_path, ">r1\nACGTAC\n")?;
+    let counts_path = temp_dir.path().join("counts.tsv");
+    let count = |extra_args: &[&str]| -> Result<assert_cmd::assert::Assert, Box<dyn std::error::Error>> {
+        Ok(Command::cargo_bin("orion-kmer")?
+            .arg("count")
+            .arg("-i")
+            .arg(&reads_path)
+            .arg("-o")
+            .arg(&counts_path)
+            .arg("--match-db")
+            .arg(&db_path)
+            .args(extra_args)
+            .assert())
+    };
+
+    // The read has two 5-mers, ACGTA and CGTAC
+    count(&[])?.success();
+    let counts = fs::read_to_string(&counts_path)?;
+    assert_eq!(counts.lines().count(), 2);
+    assert!(counts.lines().all(|line| line.split('\t').next().unwrap().len() == 5));
+
+    count(&["-k", "5"])?.success();
+    count(&["-k", "4"])?
+        .failure()
+        .stderr(predicate::str::contains("does not match k-mer size 5"));
+    Ok(())
+}
+
+#[test]
+fn test_count_with_revcomp_adds_reverse_complement_column() -> Result<(), Box<dyn std::error::Error>> {
+    // ACGTT: ACGT (its own reverse complement) and CGTT, counted as canonical AACG
+    let output = run_count_with_extra_args(4, vec![("input.fa", ">r1\nACGTT")], &["--with-revcomp"])?;
+    assert_eq!(output, "AACG\tCGTT\t1\nACGT\tACGT\t1\n");
+
+    let matrix = run_count_with_extra_args(
+        4,
+        vec![("a.fa", ">r1\nACGTT"), ("b.fa", ">r2\nACGT")],
+        &["--with-revcomp", "--matrix"],
+    )?;
+    assert_eq!(matrix, "kmer\trevcomp\ta.fa\tb.fa\nAACG\tCGTT\t1\t0\nACGT\tACGT\t1\t1\n");
+
+    // The default output keeps two columns
+    let output = run_count_with_extra_args(4, vec![("input.fa", ">r1\nACGTT")], &[])?;
+    assert_eq!(output, "AACG\t1\nACGT\t1\n");
+    Ok(())
+}
+
+#[test]
+fn test_count_decompress_threads_matches_inline_decompression() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    // Large enough to span several 1 MiB read-ahead chunks
+    let mut fasta = String::new();
+    for i in 0..40_000 {
+        fasta.push_str(&format!(">r{}\nACGTTGCAAGGCTTACCGATTAGCCATGGACTTAGC{}\n", i, ["A", "C", "G", "T"][i % 4]));
+    }
+    let xz_path = temp_dir.path().join("reads.fa.xz");
+    let mut xz_encoder = xz2::write::XzEncoder::new(File::create(&xz_path)?, 6);
+    xz_encoder.write_all(fasta.as_bytes())?;
+    xz_encoder.finish()?;
+    let zst_path = temp_dir.path().join("reads.fa.zst");
+    fs::write(&zst_path, zstd::encode_all(fasta.as_bytes(), 3)?)?;
+
+    let count = |input: &PathBuf, threads: &str| -> Result<String, Box<dyn std::error::Error>> {
+        let output_path = temp_dir.path().join(format!("counts_{}.tsv", threads));
+        Command::cargo_bin("orion-kmer")?
+            .args(["count", "-k", "7", "--decompress-threads", threads, "-i"])
+            .arg(input)
+            .arg("-o")
+            .arg(&output_path)
+            .assert()
+            .success();
+        Ok(fs::read_to_string(&output_path)?)
+    };
+    for input in [&xz_path, &zst_path] {
+        let inline = count(input, "1")?;
+        assert!(!inline.is_empty());
+        assert_eq!(count(input, "4")?, inline);
+    }
+
+    // Decoding errors from the background thread still fail the command
+    let corrupt_path = temp_dir.path().join("corrupt.fa.xz");
+    let mut corrupt = fs::read(&xz_path)?;
+    let middle = corrupt.len() / 2;
+    corrupt.truncate(middle);
+    fs::write(&corrupt_path, corrupt)?;
+    Command::cargo_bin("orion-kmer")?
+        .args(["count", "-k", "7", "--decompress-threads", "4", "-i"])
+        .arg(&corrupt_path)
+        .arg("-o")
+        .arg(temp_dir.path().join("corrupt.tsv"))
+        .assert()
+        .failure();
+    Ok(())
+}
+
+#[test]
+fn test_count_progress_interval_sets_log_cadence() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let input_path = temp_dir.path().join("reads.fa");
+    fs::write(&input_path, ">r1\nACGTA\n>r2\nACGTA\n>r3\nACGTA\n>r4\nACGTA\n>r5\nACGTA\n")?;
+    let output = Command::cargo_bin("orion-kmer")?
+        .args(["count", "-vv", "-k", "3", "--progress-interval", "2", "-i"])
+        .arg(&input_path)
+        .arg("-o")
+        .arg(temp_dir.path().join("counts.tsv"))
+        .output()?;
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.contains("Processed 2 records from"));
+    assert!(stderr.contains("Processed 4 records from"));
+    assert!(!stderr.contains("Processed 3 records from"));
+
+    Command::cargo_bin("orion-kmer")?
+        .args(["count", "-k", "3", "--progress-interval", "0", "-i"])
+        .arg(&input_path)
+        .arg("-o")
+        .arg(temp_dir.path().join("counts0.tsv"))
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--progress-interval must be at least 1"));
+    Ok(())
+}
+
+#[test]
+fn test_count_log_format_json_writes_one_object_per_line() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let input_path = temp_dir.path().join("reads.fa");
+    fs::write(&input_path, ">r1\nACGTA\n")?;
+    let output = Command::cargo_bin("orion-kmer")?
+        .args(["--log-format", "json", "-v", "count", "-k", "40", "-i"])
+        .arg(&input_path)
+        .arg("-o")
+        .arg(temp_dir.path().join("counts.tsv"))
+        .output()?;
+    assert!(!output.status.success());
+    let records: Vec<serde_json::Value> = String::from_utf8(output.stderr)?
+        .lines()
+        .map(serde_json::from_str)
+        .collect::<Result<_, _>>()?;
+    assert!(records.iter().any(|record| record["level"] == "INFO"));
+    let error = records.last().unwrap();
+    assert_eq!(error["level"], "ERROR");
+    assert!(error["message"].as_str().unwrap().contains("Invalid K-mer size: 40"));
+    assert!(error["target"].is_string());
+    assert!(error["timestamp"].as_str().unwrap().ends_with('Z'));
+    Ok(())
+}
+
+#[test]
+fn test_count_per_file_report_lists_each_file_contribution() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let a_path = temp_dir.path().join("a.fa");
+    let b_path = temp_dir.path().join("b.fa");
+    // a.fa: ACGT, CGTT (as AACG) and ACGT again; b.fa: ACGT; c.fa is shorter than k
+    fs::write(&a_path, ">r1\nACGTT\n>r2\nACGT\n")?;
+    fs::write(&b_path, ">r3\nACGT\n")?;
+    let c_path = temp_dir.path().join("c.fa");
+    fs::write(&c_path, ">r4\nAC\n")?;
+    let output_path = temp_dir.path().join("counts.tsv");
+    let report_path = temp_dir.path().join("per_file.tsv");
+    Command::cargo_bin("orion-kmer")?
+        .args(["count", "-k", "4", "-m", "2"])
+        .arg("-i")
+        .arg(&a_path)
+        .arg(&b_path)
+        .arg(&c_path)
+        .arg("-o")
+        .arg(&output_path)
+        .arg("--per-file-report")
+        .arg(&report_path)
+        .assert()
+        .success();
+
+    assert_eq!(fs::read_to_string(&output_path)?, "ACGT\t3\n");
+    assert_eq!(
+        fs::read_to_string(&report_path)?,
+        format!(
+            "filename\tunique_kmers\ttotal_kmers\ttotal_bases\tambiguous_bases\n{}\t2\t3\t9\t0\n{}\t1\t1\t4\t0\n{}\t0\t0\t2\t0\n",
+            a_path.display(),
+            b_path.display(),
+            c_path.display()
+        )
+    );
+    Ok(())
+}
+
+#[test]
+fn test_count_lenient_gzip_stops_at_trailing_junk() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let gzip_member = |content: &str| -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(content.as_bytes())?;
+        Ok(encoder.finish()?)
+    };
+    // Two concatenated members followed by padding, as left by some broken downloads
+    let mut bytes = gzip_member(">r1\nACGTT\n")?;
+    bytes.extend(gzip_member(">r2\nACGT\n")?);
+    let valid_len = bytes.len();
+    bytes.extend([0u8; 64]);
+    let gz_path = temp_dir.path().join("padded.fa.gz");
+    fs::write(&gz_path, &bytes)?;
+    let output_path = temp_dir.path().join("counts.tsv");
+    let count = |extra_args: &[&str]| -> Result<_, Box<dyn std::error::Error>> {
+        Ok(Command::cargo_bin("orion-kmer")?
+            .args(["count", "-k", "4", "-i"])
+            .arg(&gz_path)
+            .arg("-o")
+            .arg(&output_path)
+            .args(extra_args)
+            .assert())
+    };
+
+    count(&[])?.failure();
+    count(&["--lenient-gzip"])?
+        .success()
+        .stderr(predicate::str::contains(format!("not gzip data at byte offset {}", valid_len)));
+    assert_eq!(fs::read_to_string(&output_path)?, "AACG\t1\nACGT\t2\n");
+    Ok(())
+}
+
+#[test]
+fn test_count_emit_orientation_adds_strand_column() -> Result<(), Box<dyn std::error::Error>> {
+    // CGTT is stored as its reverse complement AACG; ACGT is its own reverse complement
+    let output = run_count_with_extra_args(4, vec![("input.fa", ">r1\nACGTT")], &["--emit-orientation"])?;
+    assert_eq!(output, "AACG\t1\t-\nACGT\t1\t+\n");
+
+    // AACG is read forward twice and reverse-complemented once
+    let output = run_count_with_extra_args(
+        4,
+        vec![("input.fa", ">r1\nACGTT\n>r2\nAACG\n>r3\nAACG\n")],
+        &["--emit-orientation"],
+    )?;
+    assert_eq!(output, "AACG\t3\t+\nACGT\t1\t+\n");
+    Ok(())
+}
+
+#[test]
+fn test_count_canonical_balance_reports_orientation_tally() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let input_path = temp_dir.path().join("reads.fa");
+    // AACG is read forward twice and as CGTT once; ACGT is palindromic
+    fs::write(&input_path, ">r1\nACGTT\n>r2\nAACG\n>r3\nAACG\n")?;
+    let output_path = temp_dir.path().join("counts.tsv");
+    Command::cargo_bin("orion-kmer")?
+        .args(["count", "-k", "4", "--canonical-balance", "-i"])
+        .arg(&input_path)
+        .arg("-o")
+        .arg(&output_path)
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "Canonical balance: 2 k-mer occurrences read in canonical orientation, 1 in reverse-complement orientation \
+             (forward fraction 0.6667); 1 palindromic occurrences not counted",
+        ));
+    // Counts are unchanged
+    assert_eq!(fs::read_to_string(&output_path)?, "AACG\t3\nACGT\t1\n");
+    Ok(())
+}
+
+#[test]
+fn test_count_sort_by_count_breaks_ties_by_kmer() -> Result<(), Box<dyn std::error::Error>> {
+    // AAAA occurs 3 times; ACGT, CCCC and GGGG (stored as CCCC) tie at 2; AAAC once
+    let input = vec![("input.fa", ">r1\nAAAAAAC\n>r2\nGGGG\n>r3\nCCCC\n>r4\nACGT\n>r5\nACGT\n")];
+    let by_value = "AAAA\t3\nAAAC\t1\nACGT\t2\nCCCC\t2\n";
+    assert_eq!(run_count_with_extra_args(4, input.clone(), &[])?, by_value);
+    assert_eq!(run_count_with_extra_args(4, input.clone(), &["--sort", "value"])?, by_value);
+    let by_count = "AAAA\t3\nACGT\t2\nCCCC\t2\nAAAC\t1\n";
+    assert_eq!(run_count_with_extra_args(4, input.clone(), &["--sort", "count"])?, by_count);
+    assert_eq!(run_count_with_extra_args(4, input.clone(), &["--sort", "count-then-value"])?, by_count);
+    // The order is the same whatever the thread count
+    assert_eq!(
+        run_count_with_extra_args(4, input, &["--sort", "count", "--threads", "1"])?,
+        by_count
+    );
+    Ok(())
+}
+
+#[test]
+fn test_count_estimate_only_prints_distinct_kmer_estimate() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let input_path = temp_dir.path().join("reads.fa");
+    // A pseudo-random sequence: nearly all of its 3000 windows are distinct 11-mers
+    let mut state = 12345u64;
+    let genome: String = (0..3010)
+        .map(|_| {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            b"ACGT"[(state >> 62) as usize] as char
+        })
+        .collect();
+    fs::write(&input_path, format!(">g\n{}\n", genome))?;
+
+    let output_path = temp_dir.path().join("counts.tsv");
+    Command::cargo_bin("orion-kmer")?
+        .args(["count", "-k", "11", "-i"])
+        .arg(&input_path)
+        .arg("-o")
+        .arg(&output_path)
+        .assert()
+        .success();
+    let distinct = fs::read_to_string(&output_path)?.lines().count() as f64;
+
+    let output = Command::cargo_bin("orion-kmer")?
+        .args(["count", "-k", "11", "--estimate-only", "-i"])
+        .arg(&input_path)
+        .output()?;
+    assert!(output.status.success());
+    let report = String::from_utf8(output.stdout)?;
+    let field = |name: &str| -> f64 {
+        report
+            .lines()
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix('\t'))
+            .unwrap_or_else(|| panic!("{} missing from {:?}", name, report))
+            .parse()
+            .unwrap()
+    };
+    let estimate = field("estimated_distinct_kmers");
+    assert!((estimate - distinct).abs() / distinct < 0.05, "estimated {} of {} distinct k-mers", estimate, distinct);
+    assert!(field("estimated_count_memory_bytes") >= estimate * 16.0);
+
+    // Without --estimate-only, -o is still required
+    Command::cargo_bin("orion-kmer")?
+        .args(["count", "-k", "11", "-i"])
+        .arg(&input_path)
+        .assert()
+        .failure();
+    Ok(())
+}
+
+#[test]
+fn test_count_max_records_limits_records_across_files() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let a_path = temp_dir.path().join("a.fa");
+    let b_path = temp_dir.path().join("b.fa");
+    fs::write(&a_path, ">r1\nACGT\n>r2\nACGT\n")?;
+    fs::write(&b_path, ">r3\nAACG\n>r4\nAACG\n")?;
+    let output_path = temp_dir.path().join("counts.tsv");
+    let count = |extra_args: &[&str]| -> Result<String, Box<dyn std::error::Error>> {
+        Command::cargo_bin("orion-kmer")?
+            .args(["count", "-k", "4", "-i"])
+            .arg(&a_path)
+            .arg(&b_path)
+            .arg("-o")
+            .arg(&output_path)
+            .args(extra_args)
+            .assert()
+            .success();
+        Ok(fs::read_to_string(&output_path)?)
+    };
+
+    // The limit is a total: both records of a.fa, then only r3 of b.fa
+    assert_eq!(count(&["--max-records", "3"])?, "AACG\t1\nACGT\t2\n");
+    assert_eq!(count(&["--stop-after", "1"])?, "ACGT\t1\n");
+    assert_eq!(count(&[])?, "AACG\t2\nACGT\t2\n");
+    Command::cargo_bin("orion-kmer")?
+        .args(["count", "-k", "4", "--max-records", "0", "-i"])
+        .arg(&a_path)
+        .arg("-o")
+        .arg(&output_path)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--max-records must be at least 1"));
+    Ok(())
+}
+
+#[test]
+fn test_count_trim_polyx_removes_homopolymer_tail() -> Result<(), Box<dyn std::error::Error>> {
+    let input = vec![("input.fa", ">r1\nACGTTGGGGGG\n>r2\nACGTGG\n")];
+    // r1's run of 6 G is trimmed; r2's run of 2 is kept
+    let output = run_count_with_extra_args(4, input.clone(), &["--trim-polyx", "g", "--polyx-min-run", "3"])?;
+    assert_eq!(output, "AACG\t1\nACGT\t2\nCACG\t1\nCCAC\t1\n");
+    let output = run_count_with_extra_args(4, input, &[])?;
+    assert!(output.contains("CCCC\t3\n"));
+    Ok(())
+}
+
+#[test]
+fn test_count_fail_on_empty_rejects_header_only_input() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let input_path = temp_dir.path().join("headers.fa");
+    fs::write(&input_path, ">r1\n>r2\nAC\n")?;
+    let output_path = temp_dir.path().join("counts.tsv");
+    Command::cargo_bin("orion-kmer")?
+        .args(["count", "-k", "4", "-i"])
+        .arg(&input_path)
+        .arg("-o")
+        .arg(&output_path)
+        .arg("--fail-on-empty")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--fail-on-empty: no k-mers (k=4) were counted"));
+    assert!(!output_path.exists());
+
+    // Without the flag the empty output is written as before
+    Command::cargo_bin("orion-kmer")?
+        .args(["count", "-k", "4", "-i"])
+        .arg(&input_path)
+        .arg("-o")
+        .arg(&output_path)
+        .assert()
+        .success();
+    assert_eq!(fs::read_to_string(&output_path)?, "");
+    Ok(())
+}
+
+#[test]
+fn test_count_rejects_protein_input_unless_allowed() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let input_path = temp_dir.path().join("proteins.fa");
+    fs::write(&input_path, ">dna\nACGTNACGTT\n>prot1 kinase\nMKVLITGAGS\nGIGLEIARRL\n")?;
+    let output_path = temp_dir.path().join("counts.tsv");
+    Command::cargo_bin("orion-kmer")?
+        .args(["count", "-k", "4", "-i"])
+        .arg(&input_path)
+        .arg("-o")
+        .arg(&output_path)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Input appears to be protein or non-DNA"))
+        .stderr(predicate::str::contains("record 'prot1 kinase'"));
+
+    // --allow-non-dna restores the old behaviour of skipping the non-ACGT windows
+    Command::cargo_bin("orion-kmer")?
+        .args(["count", "-k", "4", "-i"])
+        .arg(&input_path)
+        .arg("-o")
+        .arg(&output_path)
+        .arg("--allow-non-dna")
+        .assert()
+        .success();
+    assert!(fs::read_to_string(&output_path)?.contains("ACGT\t2\n"));
+    Ok(())
+}
+
+#[test]
+fn test_count_reads_unaligned_bam_and_cram_like_fastq() -> Result<(), Box<dyn std::error::Error>> {
+    use noodles::sam::{
+        self,
+        alignment::{io::Write as AlignmentWrite, record::Flags, record_buf::QualityScores, RecordBuf},
+    };
+    let reads = [("r1", "ACGTTGCAAC"), ("r2", "GGCATTACGT")];
+    let write_reads = |writer: &mut dyn AlignmentWrite| -> std::io::Result<()> {
+        let header = sam::Header::default();
+        writer.write_alignment_header(&header)?;
+        for (name, seq) in reads {
+            let record = RecordBuf::builder()
+                .set_name(name)
+                .set_flags(Flags::UNMAPPED)
+                .set_sequence(seq.as_bytes().into())
+                .set_quality_scores(QualityScores::from(vec![35; seq.len()]))
+                .build();
+            writer.write_alignment_record(&header, &record)?;
+        }
+        writer.finish(&header)
+    };
+    let temp_dir = TempDir::new()?;
+    let bam_path = temp_dir.path().join("reads.bam");
+    write_reads(&mut noodles::bam::io::Writer::new(File::create(&bam_path)?))?;
+    let cram_path = temp_dir.path().join("reads.cram");
+    write_reads(&mut noodles::cram::io::Writer::new(File::create(&cram_path)?))?;
+    let fastq_path = temp_dir.path().join("reads.fq");
+    fs::write(&fastq_path, "@r1\nACGTTGCAAC\n+\nDDDDDDDDDD\n@r2\nGGCATTACGT\n+\nDDDDDDDDDD\n")?;
+
+    let count = |input_path: &PathBuf| -> Result<String, Box<dyn std::error::Error>> {
+        let output_path = temp_dir.path().join("counts.tsv");
+        Command::cargo_bin("orion-kmer")?
+            .args(["count", "-k", "5", "-i"])
+            .arg(input_path)
+            .arg("-o")
+            .arg(&output_path)
+            .assert()
+            .success();
+        Ok(fs::read_to_string(&output_path)?)
+    };
+    let expected = count(&fastq_path)?;
+    assert!(expected.contains("AACGT\t1\n"));
+    assert_eq!(count(&bam_path)?, expected);
+    assert_eq!(count(&cram_path)?, expected);
+    Ok(())
+}
+
+#[test]
+fn test_count_compress_threads_writes_multi_member_gzip() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    // Enough distinct 21-mers for several 1 MiB gzip members
+    let mut state: u64 = 42;
+    let sequence: String = (0..300_000)
+        .map(|_| {
+            state = state.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1_442_695_040_888_963_407);
+            ['A', 'C', 'G', 'T'][(state >> 62) as usize]
+        })
+        .collect();
+    let input_path = temp_dir.path().join("genome.fa");
+    fs::write(&input_path, format!(">chr1\n{}\n", sequence))?;
+
+    let count = |threads: &str| -> Result<PathBuf, Box<dyn std::error::Error>> {
+        let output_path = temp_dir.path().join(format!("counts_{}.tsv.gz", threads));
+        Command::cargo_bin("orion-kmer")?
+            .args(["count", "-k", "21", "--compress-threads", threads, "-i"])
+            .arg(&input_path)
+            .arg("-o")
+            .arg(&output_path)
+            .assert()
+            .success();
+        Ok(output_path)
+    };
+    let read_gz = |path: &PathBuf| -> Result<String, Box<dyn std::error::Error>> {
+        let mut text = String::new();
+        flate2::read::MultiGzDecoder::new(File::open(path)?).read_to_string(&mut text)?;
+        Ok(text)
+    };
+    let single = read_gz(&count("1")?)?;
+    assert!(single.len() > 3 << 20);
+    let parallel_path = count("4")?;
+    assert_eq!(read_gz(&parallel_path)?, single);
+
+    // Readable by the standard gzip tool, when it is installed
+    if let Ok(output) = Command::new("gunzip").arg("-c").arg(&parallel_path).output() {
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8(output.stdout)?, single);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_count_and_build_open_sequence_files_alike() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    // A gzipped FASTA without a .gz extension is detected by its leading bytes in both commands
+    let fasta_path = temp_dir.path().join("genome.fa");
+    let mut encoder = flate2::write::GzEncoder::new(File::create(&fasta_path)?, flate2::Compression::default());
+    encoder.write_all(b">chr1\nACGTTGCA\n")?;
+    encoder.finish()?;
+    let sevenz_path = temp_dir.path().join("genome.fa.7z");
+    fs::write(&sevenz_path, b"7z\xbc\xaf\x27\x1c")?;
+
+    let counts_path = temp_dir.path().join("counts.tsv");
+    Command::cargo_bin("orion-kmer")?
+        .args(["count", "-k", "7", "-i"])
+        .arg(&fasta_path)
+        .arg("-o")
+        .arg(&counts_path)
+        .assert()
+        .success();
+    assert_eq!(fs::read_to_string(&counts_path)?, "ACGTTGC\t1\nCGTTGCA\t1\n");
+    Command::cargo_bin("orion-kmer")?
+        .args(["build", "-k", "7", "-g"])
+        .arg(&fasta_path)
+        .arg("-o")
+        .arg(temp_dir.path().join("genome.db"))
+        .assert()
+        .success();
+
+    // Both reject 7z archives with the same message
+    for command in ["count", "build"] {
+        let input_flag = if command == "count" { "-i" } else { "-g" };
+        Command::cargo_bin("orion-kmer")?
+            .args([command, "-k", "7", input_flag])
+            .arg(&sevenz_path)
+            .arg("-o")
+            .arg(temp_dir.path().join("out"))
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("7z archives are not supported"));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_count_strict_threads_refuses_more_threads_than_cores() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let input_path = temp_dir.path().join("input.fa");
+    fs::write(&input_path, ">r1\nACGTT\n")?;
+    Command::cargo_bin("orion-kmer")?
+        .args(["count", "-k", "4", "--threads", "100000", "--strict-threads", "-i"])
+        .arg(&input_path)
+        .arg("-o")
+        .arg(temp_dir.path().join("counts.tsv"))
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--threads 100000 exceeds the"));
+    Ok(())
+}
+
+#[test]
+fn test_count_approx_top_reports_most_frequent_kmers() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let input_path = temp_dir.path().join("reads.fa");
+    // Canonical counts: AAAA 7; CGTA 3 (with its reverse complement TACG); ACGT and GTAC 2; AAAC 1
+    fs::write(&input_path, ">r1\nAAAAAAAA\n>r2\nACGTACGTAC\n>r3\nAAAAAC\n")?;
+    let output_path = temp_dir.path().join("top.tsv");
+    Command::cargo_bin("orion-kmer")?
+        .args(["count", "-k", "4", "--approx-top", "2", "-i"])
+        .arg(&input_path)
+        .arg("-o")
+        .arg(&output_path)
+        .assert()
+        .success();
+    // With this few k-mers the sketch is exact; ties go to the smaller k-mer
+    assert_eq!(fs::read_to_string(&output_path)?, "AAAA\t7\nCGTA\t3\n");
+    Command::cargo_bin("orion-kmer")?
+        .args(["count", "-k", "4", "--approx-top", "3", "-i"])
+        .arg(&input_path)
+        .arg("-o")
+        .arg(&output_path)
+        .assert()
+        .success();
+    assert_eq!(fs::read_to_string(&output_path)?, "AAAA\t7\nCGTA\t3\nACGT\t2\n");
+
+    Command::cargo_bin("orion-kmer")?
+        .args(["count", "-k", "4", "--approx-top", "0", "-i"])
+        .arg(&input_path)
+        .arg("-o")
+        .arg(&output_path)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--approx-top must be at least 1"));
+    Ok(())
+}
+
+#[test]
+fn test_count_profile_memory_writes_rss_samples() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let input_path = temp_dir.path().join("reads.fa");
+    fs::write(&input_path, ">r1\nACGTACGTAC\n")?;
+    let profile_path = temp_dir.path().join("memory.tsv");
+    Command::cargo_bin("orion-kmer")?
+        .args(["count", "-k", "4", "-i"])
+        .arg(&input_path)
+        .arg("-o")
+        .arg(temp_dir.path().join("counts.tsv"))
+        .arg("--profile-memory")
+        .arg(&profile_path)
+        .assert()
+        .success();
+
+    let profile = fs::read_to_string(&profile_path)?;
+    let mut lines = profile.lines();
+    assert_eq!(lines.next(), Some("unix_time_ms\ttask\telapsed_ms\trss_bytes"));
+    // A sample when the task starts and one when it ends, however short it is
+    let samples: Vec<Vec<&str>> = lines.map(|line| line.split('\t').collect()).collect();
+    assert!(samples.len() >= 2, "{}", profile);
+    for sample in &samples {
+        assert_eq!(sample.len(), 4);
+        assert!(sample[0].parse::<u64>()? > 0);
+        assert_eq!(sample[1], "Counting k-mers from input files");
+        sample[2].parse::<u64>()?;
+        assert!(sample[3].parse::<u64>()? > 0);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_count_type_saturates_narrow_counts() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let input_path = temp_dir.path().join("poly_a.fa");
+    // 70000 occurrences of A plus one of C, above the u16 maximum
+    fs::write(&input_path, format!(">r\n{}C\n", "A".repeat(70_000)))?;
+
+    let count_with = |extra_args: &[&str]| -> Result<(String, String), Box<dyn std::error::Error>> {
+        let output_path = temp_dir.path().join("counts.tsv");
+        let output = Command::cargo_bin("orion-kmer")?
+            .args(["count", "-k", "1", "-i"])
+            .arg(&input_path)
+            .arg("-o")
+            .arg(&output_path)
+            .args(extra_args)
+            .output()?;
+        assert!(output.status.success());
+        Ok((fs::read_to_string(&output_path)?, String::from_utf8(output.stderr)?))
+    };
+    let (counts, stderr) = count_with(&["--count-type", "u16"])?;
+    assert_eq!(counts, "A\t65535\nC\t1\n");
+    assert!(stderr.contains("4465 k-mer occurrences were not counted"), "stderr: {}", stderr);
+
+    for count_type in ["u32", "u64"] {
+        let (counts, stderr) = count_with(&["--count-type", count_type, "--single-thread"])?;
+        assert_eq!(counts, "A\t70000\nC\t1\n");
+        assert!(!stderr.contains("saturated"));
+    }
+    // u32 is the default
+    assert_eq!(count_with(&[])?.0, "A\t70000\nC\t1\n");
+    // The single-threaded map saturates the same way
+    assert_eq!(count_with(&["--count-type", "u16", "--single-thread"])?.0, "A\t65535\nC\t1\n");
+    Ok(())
+}
@@ -0,0 +1,94 @@
+use assert_cmd::prelude::*;
+use predicates::prelude::*;
+use serde_json::Value as JsonValue;
+use std::{fs, path::PathBuf, process::Command};
+use tempfile::TempDir;
+
+// Builds a database from (file name, content) references and returns its path
+fn build_db_for_diff(
+    k: u8,
+    references: &[(&str, &str)],
+    dir: &TempDir,
+    db_name: &str,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let genome_dir = dir.path().join(format!("{}_genomes", db_name));
+    fs::create_dir_all(&genome_dir)?;
+    let db_path = dir.path().join(format!("{}.db", db_name));
+    let mut cmd = Command::cargo_bin("orion-kmer")?;
+    cmd.arg("build").arg("-k").arg(k.to_string()).arg("-o").arg(&db_path);
+    for (name, content) in references {
+        let genome_path = genome_dir.join(name);
+        fs::write(&genome_path, content)?;
+        cmd.arg("-g").arg(&genome_path);
+    }
+    cmd.assert().success();
+    Ok(db_path)
+}
+
+#[test]
+fn test_diff_reports_reference_and_kmer_changes() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    // k=4: ACGTT -> ACGT, AACG; ACGTTG adds CAAC
+    let old_db = build_db_for_diff(
+        4,
+        &[("kept.fa", ">a\nACGTT"), ("updated.fa", ">b\nGGGGA"), ("dropped.fa", ">c\nTATAT")],
+        &temp_dir,
+        "old",
+    )?;
+    let new_db = build_db_for_diff(
+        4,
+        &[("kept.fa", ">a\nACGTT"), ("updated.fa", ">b\nGGGGT"), ("new.fa", ">d\nACGTTG")],
+        &temp_dir,
+        "new",
+    )?;
+    let output_path = temp_dir.path().join("diff.json");
+
+    Command::cargo_bin("orion-kmer")?
+        .arg("diff")
+        .arg("--old")
+        .arg(&old_db)
+        .arg("--new")
+        .arg(&new_db)
+        .arg("-o")
+        .arg(&output_path)
+        .assert()
+        .success();
+
+    let report: JsonValue = serde_json::from_str(&fs::read_to_string(&output_path)?)?;
+    assert_eq!(report["kmer_size"], 4);
+    assert_eq!(report["added_references"], serde_json::json!(["new.fa"]));
+    assert_eq!(report["removed_references"], serde_json::json!(["dropped.fa"]));
+
+    let common = report["common_references"].as_array().unwrap();
+    assert_eq!(common.len(), 2);
+    assert_eq!(common[0]["reference_name"], "kept.fa");
+    assert_eq!(common[0]["gained_kmers"], 0);
+    assert_eq!(common[0]["lost_kmers"], 0);
+    // GGGGA: GGGG, GGGA; GGGGT: GGGG, GGGT
+    assert_eq!(common[1]["reference_name"], "updated.fa");
+    assert_eq!(common[1]["old_kmers"], 2);
+    assert_eq!(common[1]["new_kmers"], 2);
+    assert_eq!(common[1]["gained_kmers"], 1);
+    assert_eq!(common[1]["lost_kmers"], 1);
+    Ok(())
+}
+
+#[test]
+fn test_diff_rejects_different_kmer_sizes() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let old_db = build_db_for_diff(4, &[("a.fa", ">a\nACGTTGCA")], &temp_dir, "old")?;
+    let new_db = build_db_for_diff(5, &[("a.fa", ">a\nACGTTGCA")], &temp_dir, "new")?;
+
+    Command::cargo_bin("orion-kmer")?
+        .arg("diff")
+        .arg("--old")
+        .arg(&old_db)
+        .arg("--new")
+        .arg(&new_db)
+        .arg("-o")
+        .arg(temp_dir.path().join("diff.json"))
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("incompatible k-mer sizes"));
+    Ok(())
+}
@@ -0,0 +1,18 @@
+use assert_cmd::prelude::*;
+use std::process::Command;
+
+#[test]
+fn test_doctor_reports_codecs_threads_and_format_version() -> Result<(), Box<dyn std::error::Error>> {
+    let output = Command::cargo_bin("orion-kmer")?.args(["--threads", "1", "doctor"]).output()?;
+    assert!(output.status.success());
+    let report = String::from_utf8(output.stdout)?;
+    let lines: Vec<&str> = report.lines().collect();
+    assert!(lines.contains(&"threads\t1"), "report: {}", report);
+    assert!(lines.contains(&"database_format\thashset"));
+    assert!(lines.contains(&"database_format_version\t4"));
+    assert!(lines.contains(&"database_round_trip\tok"));
+    for codec in ["none", "gz", "bgz", "bz2", "xz", "zst", "lz4"] {
+        assert!(lines.contains(&format!("codec\t{}\tok", codec).as_str()), "codec {} in {}", codec, report);
+    }
+    Ok(())
+}
@@ -0,0 +1,295 @@
+use assert_cmd::prelude::*;
+use orion_kmer::bloom::KmerBloomFilter;
+use md5::{Digest, Md5};
+use orion_kmer::kmer::{canonical_u64, hash_kmer, seq_to_u64};
+use std::{collections::BTreeSet, fs, path::PathBuf, process::Command};
+use tempfile::TempDir;
+
+// Helper to build a database from (filename, content) inputs and return its path
+fn build_db_for_dump(
+    k: u8,
+    input_files_content: Vec<(&str, &str)>,
+    dir: &TempDir,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let output_db_path = dir.path().join("dump_input.db");
+    let mut cmd = Command::cargo_bin("orion-kmer")?;
+    cmd.arg("build")
+        .arg("-k")
+        .arg(k.to_string())
+        .arg("-o")
+        .arg(&output_db_path);
+    for (name, content) in &input_files_content {
+        let file_path = dir.path().join(name);
+        fs::write(&file_path, content)?;
+        cmd.arg("-g").arg(&file_path);
+    }
+    cmd.assert().success();
+    Ok(output_db_path)
+}
+
+// Helper to run dump with additional command-line arguments and return the output contents
+fn run_dump_with_extra_args(
+    db_path: &PathBuf,
+    dir: &TempDir,
+    extra_args: &[&str],
+) -> Result<String, Box<dyn std::error::Error>> {
+    let output_path = dir.path().join("dump_output.tsv");
+    Command::cargo_bin("orion-kmer")?
+        .arg("dump")
+        .arg("-d")
+        .arg(db_path)
+        .arg("-o")
+        .arg(&output_path)
+        .args(extra_args)
+        .assert()
+        .success();
+    Ok(fs::read_to_string(&output_path)?)
+}
+
+#[test]
+fn test_dump_tsv_rows() -> Result<(), Box<dyn std::error::Error>> {
+    let k = 4;
+    let temp_dir = TempDir::new()?;
+    // b.fasta: ACGTT -> ACGT, CGTT (canonical AACG); a.fasta: GGGG -> CCCC (canonical)
+    let db_path = build_db_for_dump(
+        k,
+        vec![("b.fasta", ">b\nACGTT\n"), ("a.fasta", ">a\nGGGG\n")],
+        &temp_dir,
+    )?;
+
+    let output = run_dump_with_extra_args(&db_path, &temp_dir, &["--format", "tsv"])?;
+    let encode = |s: &str| canonical_u64(seq_to_u64(s.as_bytes(), k).unwrap(), k);
+    let expected = [
+        "reference\tkmer_u64\tkmer_seq".to_string(),
+        format!("a.fasta\t{}\tCCCC", encode("CCCC")),
+        format!("b.fasta\t{}\tAACG", encode("CGTT")),
+        format!("b.fasta\t{}\tACGT", encode("ACGT")),
+    ];
+    assert_eq!(output.lines().collect::<Vec<_>>(), expected);
+    Ok(())
+}
+
+#[test]
+fn test_dump_parquet_rows_match_tsv() -> Result<(), Box<dyn std::error::Error>> {
+    use arrow_array::{Array, StringArray, UInt64Array};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    let temp_dir = TempDir::new()?;
+    let db_path = build_db_for_dump(
+        4,
+        vec![("b.fasta", ">b\nACGTT\n"), ("a.fasta", ">a\nGGGG\n")],
+        &temp_dir,
+    )?;
+    let parquet_path = temp_dir.path().join("kmers.parquet");
+    Command::cargo_bin("orion-kmer")?
+        .args(["dump", "--format", "parquet", "-d"])
+        .arg(&db_path)
+        .arg("-o")
+        .arg(&parquet_path)
+        .assert()
+        .success();
+
+    let reader = ParquetRecordBatchReaderBuilder::try_new(fs::File::open(&parquet_path)?)?.build()?;
+    let mut rows = vec!["reference\tkmer_u64\tkmer_seq".to_string()];
+    for batch in reader {
+        let batch = batch?;
+        let column = |name: &str| batch.column_by_name(name).expect("missing Parquet column").clone();
+        let references = column("reference");
+        let references = references.as_any().downcast_ref::<StringArray>().unwrap();
+        let kmer_u64s = column("kmer_u64");
+        let kmer_u64s = kmer_u64s.as_any().downcast_ref::<UInt64Array>().unwrap();
+        let kmer_seqs = column("kmer_seq");
+        let kmer_seqs = kmer_seqs.as_any().downcast_ref::<StringArray>().unwrap();
+        for row in 0..batch.num_rows() {
+            rows.push(format!("{}\t{}\t{}", references.value(row), kmer_u64s.value(row), kmer_seqs.value(row)));
+        }
+    }
+    let tsv = run_dump_with_extra_args(&db_path, &temp_dir, &["--format", "tsv"])?;
+    assert_eq!(rows, tsv.lines().collect::<Vec<_>>());
+    Ok(())
+}
+
+#[test]
+fn test_dump_u64_binary_records() -> Result<(), Box<dyn std::error::Error>> {
+    let k = 4;
+    let temp_dir = TempDir::new()?;
+    // ACGT is in both references
+    let db_path = build_db_for_dump(
+        k,
+        vec![("b.fasta", ">b\nACGTT\n"), ("a.fasta", ">a\nACGTA\n")],
+        &temp_dir,
+    )?;
+    let output_path = temp_dir.path().join("kmers.u64");
+    Command::cargo_bin("orion-kmer")?
+        .arg("dump")
+        .arg("-d")
+        .arg(&db_path)
+        .arg("-o")
+        .arg(&output_path)
+        .args(["--format", "u64"])
+        .assert()
+        .success();
+
+    let bytes = fs::read(&output_path)?;
+    assert_eq!(&bytes[..8], b"ORKU64V1");
+    let read_u64 = |offset: usize| u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap());
+    assert_eq!(read_u64(8), u64::from(k));
+    assert_eq!(read_u64(16), 3);
+    assert_eq!(bytes.len(), 24 + 3 * 12);
+    let records: Vec<(u64, u32)> = bytes[24..]
+        .chunks_exact(12)
+        .map(|record| {
+            (
+                u64::from_le_bytes(record[..8].try_into().unwrap()),
+                u32::from_le_bytes(record[8..].try_into().unwrap()),
+            )
+        })
+        .collect();
+
+    let encode = |s: &str| canonical_u64(seq_to_u64(s.as_bytes(), k).unwrap(), k);
+    let mut expected = vec![(encode("ACGT"), 2), (encode("CGTT"), 1), (encode("CGTA"), 1)];
+    expected.sort_unstable();
+    assert_eq!(records, expected);
+    Ok(())
+}
+
+#[test]
+fn test_dump_bloom_filter_contains_database_kmers() -> Result<(), Box<dyn std::error::Error>> {
+    let k = 4;
+    let temp_dir = TempDir::new()?;
+    let db_path = build_db_for_dump(k, vec![("a.fasta", ">a\nACGTACGTTTGC\n")], &temp_dir)?;
+    let output_path = temp_dir.path().join("kmers.bloom");
+    Command::cargo_bin("orion-kmer")?
+        .arg("dump")
+        .arg("-d")
+        .arg(&db_path)
+        .arg("-o")
+        .arg(&output_path)
+        .args(["--format", "bloom", "--fp-rate", "0.001"])
+        .assert()
+        .success();
+
+    let bytes = fs::read(&output_path)?;
+    assert_eq!(&bytes[..8], b"ORKBLM01");
+    let filter = KmerBloomFilter::read_from(&mut bytes.as_slice())?;
+    assert_eq!((filter.k, filter.canonical, filter.hashing), (k, true, None));
+    let encode = |s: &str| canonical_u64(seq_to_u64(s.as_bytes(), k).unwrap(), k);
+    let kmers = ["ACGT", "CGTA", "GTAC", "CGTT", "GTTT", "TTTG", "TTGC"];
+    assert_eq!(filter.num_items, kmers.len() as u64);
+    assert!(kmers.iter().all(|kmer| filter.contains(encode(kmer))));
+    assert!(filter.estimated_fp_rate() < 0.01);
+
+    Command::cargo_bin("orion-kmer")?
+        .arg("dump")
+        .arg("-d")
+        .arg(&db_path)
+        .arg("-o")
+        .arg(&output_path)
+        .args(["--format", "bloom", "--fp-rate", "1.5"])
+        .assert()
+        .failure();
+    Ok(())
+}
+
+#[test]
+fn test_dump_sourmash_signature() -> Result<(), Box<dyn std::error::Error>> {
+    let k = 21;
+    let temp_dir = TempDir::new()?;
+    let mut state: u64 = 7;
+    let genome: String = (0..3000)
+        .map(|_| {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            b"ACGT"[(state >> 62) as usize] as char
+        })
+        .collect();
+    let db_path = build_db_for_dump(k, vec![("genome.fasta", &format!(">g\n{}\n", genome))], &temp_dir)?;
+    // sourmash hashes every k-mer of the sequence in its lexicographically smaller orientation
+    let all_hashes: BTreeSet<u64> = genome
+        .as_bytes()
+        .windows(k as usize)
+        .map(|window| hash_kmer(canonical_u64(seq_to_u64(window, k).unwrap(), k), k, 42))
+        .collect();
+
+    let signature = |db_path: &PathBuf, extra_args: &[&str]| -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+        let output = run_dump_with_extra_args(db_path, &temp_dir, &[&["--format", "sourmash"], extra_args].concat())?;
+        Ok(serde_json::from_str(&output)?)
+    };
+    let minhash = |json: &serde_json::Value| -> Vec<u64> {
+        json[0]["signatures"][0]["mins"].as_array().unwrap().iter().map(|min| min.as_u64().unwrap()).collect()
+    };
+
+    let json = signature(&db_path, &["--scaled", "1"])?;
+    assert_eq!(json[0]["class"], "sourmash_signature");
+    assert_eq!(json[0]["hash_function"], "0.murmur64");
+    assert_eq!(json[0]["version"], 0.4);
+    let sketch = &json[0]["signatures"][0];
+    assert_eq!(sketch["ksize"], 21);
+    assert_eq!(sketch["seed"], 42);
+    assert_eq!(sketch["num"], 0);
+    assert_eq!(sketch["max_hash"], u64::MAX);
+    assert_eq!(sketch["molecule"], "dna");
+    let mins = minhash(&json);
+    assert_eq!(mins, all_hashes.iter().copied().collect::<Vec<_>>());
+    // The md5sum is taken over the decimal k and hashes, as sourmash does
+    let mut hasher = Md5::new();
+    hasher.update(b"21");
+    for min in &mins {
+        hasher.update(min.to_string());
+    }
+    let md5sum: String = hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect();
+    assert_eq!(sketch["md5sum"], md5sum.as_str());
+
+    // A scaled sketch keeps the hashes up to 2^64 / scaled
+    let json = signature(&db_path, &["--scaled", "10"])?;
+    let max_hash = json[0]["signatures"][0]["max_hash"].as_u64().unwrap();
+    assert_eq!(max_hash, 1844674407370955264);
+    assert_eq!(minhash(&json), all_hashes.iter().copied().filter(|&hash| hash <= max_hash).collect::<Vec<_>>());
+
+    // A bottom-N sketch keeps the N smallest
+    let json = signature(&db_path, &["--num", "50"])?;
+    assert_eq!(json[0]["signatures"][0]["num"], 50);
+    assert_eq!(json[0]["signatures"][0]["max_hash"], 0);
+    assert_eq!(minhash(&json), all_hashes.iter().copied().take(50).collect::<Vec<_>>());
+
+    // A database hashed with the same seed gives the same sketch; another seed is rejected
+    let hashed_db_path = temp_dir.path().join("hashed.db");
+    Command::cargo_bin("orion-kmer")?
+        .args(["build", "-k", "21", "--hashed", "-g"])
+        .arg(temp_dir.path().join("genome.fasta"))
+        .arg("-o")
+        .arg(&hashed_db_path)
+        .assert()
+        .success();
+    assert_eq!(minhash(&signature(&hashed_db_path, &["--scaled", "1"])?), mins);
+    Command::cargo_bin("orion-kmer")?
+        .args(["dump", "--format", "sourmash", "--seed", "7", "-d"])
+        .arg(&hashed_db_path)
+        .arg("-o")
+        .arg(temp_dir.path().join("seed7.sig"))
+        .assert()
+        .failure();
+
+    // The signature is read back by `search --sketch`
+    let sig_path = temp_dir.path().join("genome.sig.gz");
+    Command::cargo_bin("orion-kmer")?
+        .args(["dump", "--format", "sourmash", "--scaled", "10", "-d"])
+        .arg(&db_path)
+        .arg("-o")
+        .arg(&sig_path)
+        .assert()
+        .success();
+    let search_path = temp_dir.path().join("search.json");
+    Command::cargo_bin("orion-kmer")?
+        .arg("search")
+        .arg("--sketch")
+        .arg(&sig_path)
+        .arg("-d")
+        .arg(&db_path)
+        .arg("-o")
+        .arg(&search_path)
+        .assert()
+        .success();
+    let search: serde_json::Value = serde_json::from_str(&fs::read_to_string(&search_path)?)?;
+    assert_eq!(search["containment"], 1.0);
+    Ok(())
+}
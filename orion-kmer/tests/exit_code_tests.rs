@@ -0,0 +1,139 @@
+use assert_cmd::prelude::*;
+use orion_kmer::errors::{
+    EXIT_FILE_NOT_FOUND, EXIT_INVALID_KMER_SIZE, EXIT_KMER_SIZE_MISMATCH, EXIT_PARSE_ERROR,
+    EXIT_SERIALIZATION_ERROR,
+};
+use predicates::prelude::*;
+use std::{fs, path::PathBuf, process::Command};
+use tempfile::TempDir;
+
+fn orion_kmer_cmd() -> Result<Command, Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("orion-kmer")?;
+    let project_root = std::env::var("CARGO_MANIFEST_DIR").unwrap_or_else(|_| ".".to_string());
+    cmd.current_dir(project_root);
+    Ok(cmd)
+}
+
+fn write_file(dir: &TempDir, name: &str, content: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let path = dir.path().join(name);
+    fs::write(&path, content)?;
+    Ok(path)
+}
+
+#[test]
+fn test_exit_code_invalid_kmer_size() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    let input = write_file(&dir, "in.fa", ">s\nACGT\n")?;
+    orion_kmer_cmd()?
+        .args(["count", "-k", "33", "-i"])
+        .arg(&input)
+        .arg("-o")
+        .arg(dir.path().join("counts.tsv"))
+        .assert()
+        .code(EXIT_INVALID_KMER_SIZE)
+        .stderr(predicate::str::contains("Invalid K-mer size: 33"));
+    Ok(())
+}
+
+#[test]
+fn test_exit_code_file_not_found() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    orion_kmer_cmd()?
+        .args(["count", "-k", "4", "-i"])
+        .arg(dir.path().join("missing.fa"))
+        .arg("-o")
+        .arg(dir.path().join("counts.tsv"))
+        .assert()
+        .code(EXIT_FILE_NOT_FOUND);
+    Ok(())
+}
+
+#[test]
+fn test_exit_code_kmer_size_mismatch() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    let genome = write_file(&dir, "ref.fa", ">ref\nACGTACGTTTGC\n")?;
+    let db_path = dir.path().join("ref.db");
+    orion_kmer_cmd()?
+        .args(["build", "-k", "4", "-g"])
+        .arg(&genome)
+        .arg("-o")
+        .arg(&db_path)
+        .assert()
+        .success();
+    orion_kmer_cmd()?
+        .args(["classify", "-k", "5", "-i"])
+        .arg(&genome)
+        .arg("-d")
+        .arg(&db_path)
+        .arg("-o")
+        .arg(dir.path().join("out.json"))
+        .assert()
+        .code(EXIT_KMER_SIZE_MISMATCH);
+    Ok(())
+}
+
+#[test]
+fn test_exit_code_malformed_inputs() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    let not_fasta = write_file(&dir, "bad.fa", "this is not a sequence file\n")?;
+    orion_kmer_cmd()?
+        .args(["count", "-k", "4", "-i"])
+        .arg(&not_fasta)
+        .arg("-o")
+        .arg(dir.path().join("counts.tsv"))
+        .assert()
+        .code(EXIT_PARSE_ERROR);
+
+    let corrupt_db = write_file(&dir, "corrupt.db", "not a database")?;
+    orion_kmer_cmd()?
+        .args(["query", "-d"])
+        .arg(&corrupt_db)
+        .arg("-r")
+        .arg(&not_fasta)
+        .arg("-o")
+        .arg(dir.path().join("ids.txt"))
+        .assert()
+        .code(EXIT_SERIALIZATION_ERROR);
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_interrupted_build_removes_incomplete_database() -> Result<(), Box<dyn std::error::Error>> {
+    use orion_kmer::errors::EXIT_INTERRUPTED;
+    use std::time::{Duration, Instant};
+
+    let dir = TempDir::new()?;
+    // A pseudo-random genome large enough that writing its xz-compressed database takes a while
+    let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+    let genome: String = (0..300_000)
+        .map(|_| {
+            state = state.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1_442_695_040_888_963_407);
+            b"ACGT"[(state >> 62) as usize] as char
+        })
+        .collect();
+    let genome_path = write_file(&dir, "genome.fa", &format!(">g\n{}\n", genome))?;
+    let db_path = dir.path().join("genome.db.xz");
+    let child = orion_kmer_cmd()?
+        .args(["build", "-k", "21", "-g"])
+        .arg(&genome_path)
+        .arg("-o")
+        .arg(&db_path)
+        .stderr(std::process::Stdio::piped())
+        .spawn()?;
+
+    // Interrupt once the database file has been created
+    let deadline = Instant::now() + Duration::from_secs(120);
+    while !db_path.exists() {
+        assert!(Instant::now() < deadline, "build never started writing {:?}", db_path);
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    // SAFETY: kill only sends a signal to the child process
+    assert_eq!(unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGINT) }, 0);
+    let output = child.wait_with_output()?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(EXIT_INTERRUPTED), "stderr: {}", stderr);
+    assert!(stderr.contains("removed incomplete output"), "stderr: {}", stderr);
+    assert!(!db_path.exists(), "interrupted build left {:?} behind", db_path);
+    Ok(())
+}
@@ -0,0 +1,255 @@
+use assert_cmd::prelude::*;
+use orion_kmer::utils::load_kmer_db_v2;
+use predicates::prelude::*;
+use std::{fs, process::Command};
+use tempfile::TempDir;
+
+#[test]
+fn test_info_reports_build_metadata_including_appended_sources() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    let ref_a = dir.path().join("refA.fa");
+    let ref_b = dir.path().join("refB.fa");
+    fs::write(&ref_a, ">a\nACGTACGTTTGC\n")?;
+    fs::write(&ref_b, ">b\nGGGAAAAATTTT\n")?;
+    let first_db = dir.path().join("first.db");
+    let db_path = dir.path().join("appended.db.gz");
+    Command::cargo_bin("orion-kmer")?
+        .arg("build")
+        .args(["-k", "4", "-g"])
+        .arg(&ref_a)
+        .arg("-o")
+        .arg(&first_db)
+        .assert()
+        .success();
+    Command::cargo_bin("orion-kmer")?
+        .arg("build")
+        .args(["-k", "4", "-g"])
+        .arg(&ref_b)
+        .arg("--append")
+        .arg(&first_db)
+        .arg("-o")
+        .arg(&db_path)
+        .assert()
+        .success();
+
+    let metadata = load_kmer_db_v2(&db_path)?.metadata.expect("build records metadata");
+    assert_eq!(metadata.orion_kmer_version, env!("CARGO_PKG_VERSION"));
+    assert_eq!(metadata.k_values, vec![4]);
+    assert!(metadata.canonical);
+    assert!(!metadata.hashed);
+    assert_eq!(
+        metadata.source_files,
+        vec![ref_a.display().to_string(), ref_b.display().to_string()]
+    );
+    assert!(metadata.command_line.iter().any(|arg| arg == "--append"));
+    assert!(metadata.built_at_unix_secs > 0);
+
+    Command::cargo_bin("orion-kmer")?
+        .arg("info")
+        .arg("-d")
+        .arg(&db_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("format\thashset\nk\t4\ncanonical\ttrue\nhashing\tnone\nreferences\t2\n"))
+        .stdout(predicate::str::contains("build_k\t4\n"))
+        .stdout(predicate::str::contains(format!("source_file\t{}\n", ref_b.display())))
+        .stdout(predicate::str::is_match(r"built_at\t\d{4}-\d{2}-\d{2}T\d{2}:\d{2}:\d{2}Z\n")?);
+    Ok(())
+}
+
+#[test]
+fn test_info_loads_database_without_metadata() -> Result<(), Box<dyn std::error::Error>> {
+    // Written by an earlier release, before databases had a header or metadata
+    let legacy_path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/baseline_k5.db");
+    let legacy = load_kmer_db_v2(&legacy_path)?;
+    assert_eq!(legacy.metadata, None);
+    assert_eq!(
+        legacy.references.keys().collect::<Vec<_>>(),
+        ["refA.fa", "refB.fa"]
+    );
+
+    Command::cargo_bin("orion-kmer")?
+        .arg("info")
+        .arg("-d")
+        .arg(&legacy_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("total_unique_kmers\t16\nmetadata\tnone\n"));
+    Ok(())
+}
+
+#[test]
+fn test_info_reports_canonical_balance_of_non_canonical_database() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    let ref_path = dir.path().join("ref.fa");
+    // AAAC and AACG are stored as read, CGTT and GTTT would become AACG and AAAC canonically
+    fs::write(&ref_path, ">a\nAAACG\n>b\nCGTTT\n>c\nACGT\n")?;
+    let info = |db_path: &std::path::Path| -> Result<_, Box<dyn std::error::Error>> {
+        Ok(Command::cargo_bin("orion-kmer")?.arg("info").arg("-d").arg(db_path).assert().success())
+    };
+
+    let db_path = dir.path().join("stranded.db");
+    Command::cargo_bin("orion-kmer")?
+        .args(["build", "-k", "4", "--no-canonical", "-g"])
+        .arg(&ref_path)
+        .arg("-o")
+        .arg(&db_path)
+        .assert()
+        .success();
+    // Two of the four non-palindromic k-mers are canonical; ACGT is not counted
+    info(&db_path)?.stdout(predicate::str::contains("total_unique_kmers\t5\ncanonical_balance\t0.5000\n"));
+
+    // Canonical k-mers no longer carry the orientation they were read in
+    let canonical_path = dir.path().join("canonical.db");
+    Command::cargo_bin("orion-kmer")?
+        .args(["build", "-k", "4", "-g"])
+        .arg(&ref_path)
+        .arg("-o")
+        .arg(&canonical_path)
+        .assert()
+        .success();
+    info(&canonical_path)?.stdout(predicate::str::contains("canonical_balance").not());
+    Ok(())
+}
+
+#[test]
+fn test_info_format_version_reads_only_the_header() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    let ref_path = dir.path().join("ref.fa");
+    fs::write(&ref_path, ">a\nACGTACGTTTGC\n>b\nGGGAAAAATTTT\n")?;
+    let build = |extra_args: &[&str], db_path: &std::path::Path| -> Result<(), Box<dyn std::error::Error>> {
+        Command::cargo_bin("orion-kmer")?
+            .arg("build")
+            .args(extra_args)
+            .arg("-g")
+            .arg(&ref_path)
+            .arg("-o")
+            .arg(db_path)
+            .assert()
+            .success();
+        Ok(())
+    };
+    let format_version = |db_path: &std::path::Path| -> Result<_, Box<dyn std::error::Error>> {
+        Ok(Command::cargo_bin("orion-kmer")?
+            .args(["info", "--format-version", "-d"])
+            .arg(db_path)
+            .assert())
+    };
+
+    let db_path = dir.path().join("per_record.db.zst");
+    build(&["-k", "5", "--per-record"], &db_path)?;
+    format_version(&db_path)?.success().stdout(format!(
+        "file\t{}\nformat\thashset\nformat_version\t4\nmagic\tORKKDB04\nk\t5\nreferences\t2\n",
+        db_path.display()
+    ));
+
+    let multi_k_path = dir.path().join("multi.db");
+    build(&["--k-list", "4,6"], &multi_k_path)?;
+    format_version(&multi_k_path)?
+        .success()
+        .stdout(predicate::str::contains("format\tmulti_k\nformat_version\t2\nmagic\tORKMULT2\ntables\t2\n"));
+
+    let index_path = dir.path().join("sorted.idx");
+    build(&["-k", "7", "--sorted-index", index_path.to_str().unwrap()], &dir.path().join("indexed.db"))?;
+    format_version(&index_path)?
+        .success()
+        .stdout(predicate::str::contains("format\tsorted_index\nformat_version\t1\nmagic\tORKIDX01\nk\t7\n"));
+
+    // The version is read from the header: headerless databases are version 2, and a database
+    // of a newer version is identified but not loaded
+    let data_dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data");
+    format_version(&data_dir.join("baseline_k5.db"))?
+        .success()
+        .stdout(predicate::str::contains("format\thashset\nformat_version\t2\nmagic\tnone\nk\t5\nreferences\t2\n"));
+    format_version(&data_dir.join("format_v3_k5.db"))?
+        .success()
+        .stdout(predicate::str::contains("format\thashset\nformat_version\t3\nmagic\tORKKDB03\nk\t5\nreferences\t2\n"));
+    format_version(&data_dir.join("multi_k_v1.db"))?
+        .success()
+        .stdout(predicate::str::contains("format\tmulti_k\nformat_version\t1\nmagic\tORKMULTK\ntables\t2\n"));
+    let newer_path = dir.path().join("newer.db");
+    build(&["-k", "6"], &newer_path)?;
+    let mut newer_bytes = fs::read(&newer_path)?;
+    newer_bytes[..8].copy_from_slice(b"ORKKDB07");
+    fs::write(&newer_path, newer_bytes)?;
+    format_version(&newer_path)?
+        .success()
+        .stdout(predicate::str::contains("format\thashset\nformat_version\t7\nmagic\tORKKDB07\nk\t6\nreferences\t1\n"));
+    Command::cargo_bin("orion-kmer")?
+        .args(["info", "-d"])
+        .arg(&newer_path)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("has database format version 7, but this orion-kmer reads versions 3 and 4"));
+
+    // A file whose first byte cannot be a k is no database, however it was named
+    let bogus_path = dir.path().join("bogus.db");
+    fs::write(&bogus_path, ">not a db\n")?;
+    format_version(&bogus_path)?
+        .failure()
+        .stderr(predicate::str::contains("is not an orion-kmer database"));
+    Ok(())
+}
+
+#[test]
+fn test_info_loads_format_v3_databases_from_their_trailing_sections() -> Result<(), Box<dyn std::error::Error>> {
+    // Both fixtures were built from refA.fa and refB.fa with `--canonical-policy minimizer
+    // --minimizer-length 3 --normalize-ref-names lowercase`, before these fields moved into the
+    // database in format version 4
+    let data_dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data");
+    let v3_path = data_dir.join("format_v3_k5.db");
+    Command::cargo_bin("orion-kmer")?
+        .args(["info", "-d"])
+        .arg(&v3_path)
+        .assert()
+        .success()
+        .stdout(
+            predicate::str::contains("canonical_policy\tminimizer (m=3)\n")
+                .and(predicate::str::contains("name_normalization\tLowercase\n"))
+                .and(predicate::str::contains("total_unique_kmers\t16\n"))
+                .and(predicate::str::contains("build_k\t5\n")),
+        );
+    let v3_db = load_kmer_db_v2(&v3_path)?;
+    assert_eq!(v3_db.references.keys().collect::<Vec<_>>(), ["refa.fa", "refb.fa"]);
+    assert!(!v3_db.reference_lengths.is_empty());
+
+    // The tables of the first multi-k layout share one set of sections
+    for k in [4, 5] {
+        let table = orion_kmer::utils::load_kmer_db_v2_for_k(&data_dir.join("multi_k_v1.db"), Some(k))?;
+        assert_eq!(table.k, k);
+        assert_eq!(table.canonical_policy, orion_kmer::kmer::CanonicalPolicy::Minimizer { m: 3 });
+        assert!(table.name_normalization.is_some());
+        assert_eq!(table.metadata.as_ref().map(|metadata| metadata.k_values.clone()), Some(vec![4, 5]));
+    }
+
+    // A section this version does not know could change how lookups work, so it is an error
+    let dir = TempDir::new()?;
+    let unknown_path = dir.path().join("unknown_section.db");
+    let mut bytes = fs::read(&v3_path)?;
+    bytes.extend_from_slice(b"ORKNEW01");
+    bytes.extend_from_slice(&[1, 2, 3]);
+    fs::write(&unknown_path, bytes)?;
+    Command::cargo_bin("orion-kmer")?
+        .args(["info", "-d"])
+        .arg(&unknown_path)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("unknown database section \"ORKNEW01\""));
+
+    // Databases of format version 4 end with the k-mers
+    let v4_path = dir.path().join("v4.db");
+    let mut v4_db = v3_db.clone();
+    v4_db.metadata = None;
+    orion_kmer::utils::write_kmer_db(&v4_db, &v4_path)?;
+    assert_eq!(load_kmer_db_v2(&v4_path)?.canonical_policy, v3_db.canonical_policy);
+    let mut bytes = fs::read(&v4_path)?;
+    bytes.extend_from_slice(b"ORKMETA1");
+    fs::write(&v4_path, bytes)?;
+    Command::cargo_bin("orion-kmer")?
+        .args(["info", "-d"])
+        .arg(&v4_path)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("has unexpected bytes after the database"));
+    Ok(())
+}
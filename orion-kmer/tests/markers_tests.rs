@@ -0,0 +1,85 @@
+use assert_cmd::prelude::*;
+use std::{fs, path::PathBuf, process::Command};
+use tempfile::TempDir;
+
+// Helper to build a background database from one FASTA content string
+fn build_background_db(
+    k: u8,
+    content: &str,
+    dir: &TempDir,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let genome_path = dir.path().join("background.fa");
+    fs::write(&genome_path, content)?;
+    let db_path = dir.path().join("background.db");
+    Command::cargo_bin("orion-kmer")?
+        .arg("build")
+        .arg("-k")
+        .arg(k.to_string())
+        .arg("-g")
+        .arg(&genome_path)
+        .arg("-o")
+        .arg(&db_path)
+        .assert()
+        .success();
+    Ok(db_path)
+}
+
+// Helper to run markers on one target FASTA and return the output lines
+fn run_markers(
+    target_content: &str,
+    background_db: &PathBuf,
+    dir: &TempDir,
+    min_run: usize,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let target_path = dir.path().join("target.fa");
+    fs::write(&target_path, target_content)?;
+    let output_path = dir.path().join("markers.tsv");
+    Command::cargo_bin("orion-kmer")?
+        .arg("markers")
+        .arg("--target")
+        .arg(&target_path)
+        .arg("--background")
+        .arg(background_db)
+        .arg("-o")
+        .arg(&output_path)
+        .arg("--min-run")
+        .arg(min_run.to_string())
+        .assert()
+        .success();
+    Ok(fs::read_to_string(&output_path)?
+        .lines()
+        .map(String::from)
+        .collect())
+}
+
+// k=4 target windows: AAAA AAAA AAAC AACG | ACGT CGTA | GTAA TAAA AAAA AAAA
+// ACGT and CGTA are in the background, splitting the target into two runs of 4 windows.
+const TARGET_FASTA: &str = ">t1 target chromosome\nAAAAACGTAAAAA\n";
+const BACKGROUND_FASTA: &str = ">bg\nACGTAC\n";
+
+#[test]
+fn test_markers_reports_specific_runs() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let background_db = build_background_db(4, BACKGROUND_FASTA, &temp_dir)?;
+
+    let lines = run_markers(TARGET_FASTA, &background_db, &temp_dir, 4)?;
+    assert_eq!(
+        lines,
+        vec![
+            "reference\tsequence_id\tstart\tend\tsequence",
+            "target.fa\tt1\t0\t7\tAAAAACG",
+            "target.fa\tt1\t6\t13\tGTAAAAA",
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_markers_min_run_filters_short_regions() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let background_db = build_background_db(4, BACKGROUND_FASTA, &temp_dir)?;
+
+    let lines = run_markers(TARGET_FASTA, &background_db, &temp_dir, 5)?;
+    assert_eq!(lines, vec!["reference\tsequence_id\tstart\tend\tsequence"]);
+    Ok(())
+}
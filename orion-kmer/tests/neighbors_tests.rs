@@ -0,0 +1,84 @@
+use assert_cmd::prelude::*;
+use predicates::prelude::*;
+use std::{fs, path::PathBuf, process::Command};
+use tempfile::TempDir;
+
+// Builds a k=4 database from `content` and returns its path
+fn build_db_for_neighbors(content: &str, dir: &TempDir) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let genome_path = dir.path().join("genome.fasta");
+    fs::write(&genome_path, content)?;
+    let db_path = dir.path().join("neighbors_input.db");
+    Command::cargo_bin("orion-kmer")?
+        .arg("build")
+        .arg("-k")
+        .arg("4")
+        .arg("-g")
+        .arg(&genome_path)
+        .arg("-o")
+        .arg(&db_path)
+        .assert()
+        .success();
+    Ok(db_path)
+}
+
+// Runs neighbors for `kmer` and returns the (direction, neighbor) pairs reported present
+fn present_neighbors(db_path: &PathBuf, kmer: &str) -> Result<Vec<(String, String)>, Box<dyn std::error::Error>> {
+    let output = Command::cargo_bin("orion-kmer")?
+        .arg("neighbors")
+        .arg("-d")
+        .arg(db_path)
+        .arg("--kmer")
+        .arg(kmer)
+        .output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    let mut lines = stdout.lines();
+    assert_eq!(lines.next(), Some("direction\tneighbor\tpresent"));
+    let rows: Vec<Vec<&str>> = lines.map(|line| line.split('\t').collect()).collect();
+    assert_eq!(rows.len(), 8);
+    Ok(rows
+        .into_iter()
+        .filter(|row| row[2] == "true")
+        .map(|row| (row[0].to_string(), row[1].to_string()))
+        .collect())
+}
+
+#[test]
+fn test_neighbors_reports_present_extensions() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    // K-mers: ACGT, CGTA, GTAC
+    let db_path = build_db_for_neighbors(">s1\nACGTAC\n", &temp_dir)?;
+
+    assert_eq!(
+        present_neighbors(&db_path, "CGTA")?,
+        vec![
+            ("successor".to_string(), "GTAC".to_string()),
+            ("predecessor".to_string(), "ACGT".to_string()),
+        ]
+    );
+    // The reverse strand walks the same path backwards
+    assert_eq!(
+        present_neighbors(&db_path, "tacg")?,
+        vec![
+            ("successor".to_string(), "ACGT".to_string()),
+            ("predecessor".to_string(), "GTAC".to_string()),
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_neighbors_rejects_wrong_length_kmer() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let db_path = build_db_for_neighbors(">s1\nACGTAC\n", &temp_dir)?;
+    Command::cargo_bin("orion-kmer")?
+        .arg("neighbors")
+        .arg("-d")
+        .arg(&db_path)
+        .arg("--kmer")
+        .arg("ACGTA")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("does not have the database's length k=4"));
+    Ok(())
+}
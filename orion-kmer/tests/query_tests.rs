@@ -91,6 +91,33 @@ fn run_query_and_get_ids(
     Ok(ids_set)
 }
 
+// Helper to run query with additional command-line arguments, returning the matching read IDs output
+fn run_query_with_extra_args(
+    db_path: &PathBuf,
+    reads_content: &str,
+    extra_args: &[&str],
+) -> Result<String, Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let reads_file_path = temp_dir.path().join("query_reads.fastq");
+    fs::write(&reads_file_path, reads_content)?;
+    let output_ids_path = temp_dir.path().join("matching_ids.txt");
+
+    let mut cmd = Command::cargo_bin("orion-kmer")?;
+    let project_root = std::env::var("CARGO_MANIFEST_DIR").unwrap_or_else(|_| ".".to_string());
+    cmd.current_dir(project_root);
+    cmd.arg("query")
+        .arg("-d")
+        .arg(db_path)
+        .arg("-r")
+        .arg(&reads_file_path)
+        .arg("-o")
+        .arg(&output_ids_path)
+        .args(extra_args);
+
+    cmd.assert().success();
+    Ok(fs::read_to_string(&output_ids_path)?)
+}
+
 const DB_FASTA_CONTENT: &str = ">ref_genome_segment\nACGTACGTTTGCATC";
 // k=4, Canonicals from ACGTACGTTTGCATC:
 // ACGT, CGTA, GTAC, TACG(->CGTA), ACGT(dup), CGTT, GTTT, TTGC, TGCA, GCAT
@@ -263,3 +290,933 @@ fn test_query_reads_file_not_found() -> Result<(), Box<dyn std::error::Error>> {
     ));
     Ok(())
 }
+
+#[test]
+fn test_query_bed_output_merges_adjacent_hits() -> Result<(), Box<dyn std::error::Error>> {
+    let k = 4;
+    let temp_db_storage_dir = TempDir::new()?;
+    let db_path = run_build_for_query_test(
+        k,
+        vec![("db.fa", DB_FASTA_CONTENT)],
+        &temp_db_storage_dir,
+        "querydb_bed",
+    )?;
+
+    // Windows 0-2 (ACGT, CGTA, GTAC) and 10-11 (TTGC, TGCA) hit the database
+    let reads = "@read_bed\nACGTACCCCCTTGCA\n+\n!!!!!!!!!!!!!!!\n";
+    let bed_path = temp_db_storage_dir.path().join("covered.bed");
+    run_query_with_extra_args(&db_path, reads, &["--bed", bed_path.to_str().unwrap()])?;
+
+    let bed_content = fs::read_to_string(&bed_path)?;
+    assert_eq!(bed_content, "read_bed\t0\t6\nread_bed\t10\t15\n");
+    Ok(())
+}
+
+#[test]
+fn test_query_strand_reports_forward_and_reverse_hits() -> Result<(), Box<dyn std::error::Error>> {
+    let k = 4;
+    let temp_db_storage_dir = TempDir::new()?;
+    let db_path = run_build_for_query_test(
+        k,
+        vec![("db.fa", DB_FASTA_CONTENT)],
+        &temp_db_storage_dir,
+        "querydb_strand",
+    )?;
+
+    // The database stores ATGC (canonical of GCAT) and AAAC (canonical of GTTT).
+    // ATGC matches in canonical orientation (forward); GTTT only via its reverse complement.
+    let reads = "\
+@read_fwd
+ATGC
++
+!!!!
+@read_rev
+GTTT
++
+!!!!
+";
+    let output = run_query_with_extra_args(&db_path, reads, &["--strand"])?;
+    let lines: HashSet<&str> = output.lines().collect();
+    let expected: HashSet<&str> = ["read_fwd\t1\t0", "read_rev\t0\t1"].into_iter().collect();
+    assert_eq!(lines, expected);
+    Ok(())
+}
+
+#[test]
+fn test_query_strand_against_non_canonical_database() -> Result<(), Box<dyn std::error::Error>> {
+    use orion_kmer::{db_types::KmerDbV2, kmer::seq_to_u64};
+
+    // TTTT is stored as read, so AAAA matches only through its reverse complement, although
+    // AAAA is the canonical form
+    let mut kmer_db = KmerDbV2::new(4);
+    kmer_db.canonical = false;
+    kmer_db.add_reference("raw.fa".to_string(), HashSet::from([seq_to_u64(b"TTTT", 4).unwrap()]));
+    let temp_dir = TempDir::new()?;
+    let db_path = temp_dir.path().join("non_canonical.db");
+    orion_kmer::utils::write_kmer_db(&kmer_db, &db_path)?;
+
+    let reads = ">read_fwd\nTTTT\n>read_rev\nAAAA\n>read_none\nCCCC\n";
+    let output = run_query_with_extra_args(&db_path, reads, &["--strand", "--strict"])?;
+    assert_eq!(output, "read_fwd\t1\t0\nread_rev\t0\t1\n");
+    Ok(())
+}
+
+#[test]
+fn test_query_canonical_both_differs_from_strand_against_non_canonical_database() -> Result<(), Box<dyn std::error::Error>> {
+    use orion_kmer::{db_types::KmerDbV2, kmer::seq_to_u64};
+
+    // AAAC is stored on both strands (as AAAC and GTTT), GGGA only as read
+    let mut kmer_db = KmerDbV2::new(4);
+    kmer_db.canonical = false;
+    kmer_db.add_reference(
+        "raw.fa".to_string(),
+        ["AAAC", "GTTT", "GGGA"].iter().map(|kmer| seq_to_u64(kmer.as_bytes(), 4).unwrap()).collect(),
+    );
+    let temp_dir = TempDir::new()?;
+    let db_path = temp_dir.path().join("non_canonical.db");
+    orion_kmer::utils::write_kmer_db(&kmer_db, &db_path)?;
+
+    let reads = ">both\nAAAC\n>rev\nTCCC\n";
+    let strand = run_query_with_extra_args(&db_path, reads, &["--strand"])?;
+    assert_eq!(strand, "both\t1\t0\nrev\t0\t1\n");
+    let canonical_both = run_query_with_extra_args(&db_path, reads, &["--canonical-both"])?;
+    assert_eq!(canonical_both, "both\t1\t1\nrev\t0\t1\n");
+    Ok(())
+}
+
+#[test]
+fn test_query_annotate_references_against_non_canonical_database() -> Result<(), Box<dyn std::error::Error>> {
+    use orion_kmer::{db_types::KmerDbV2, kmer::seq_to_u64};
+
+    // raw.fa stores TTTT as read; both.fa stores AAAC on both strands (as AAAC and GTTT)
+    let encode = |kmers: &[&str]| kmers.iter().map(|kmer| seq_to_u64(kmer.as_bytes(), 4).unwrap()).collect();
+    let mut kmer_db = KmerDbV2::new(4);
+    kmer_db.canonical = false;
+    kmer_db.add_reference("raw.fa".to_string(), encode(&["TTTT"]));
+    kmer_db.add_reference("both.fa".to_string(), encode(&["AAAC", "GTTT"]));
+    let temp_dir = TempDir::new()?;
+    let db_path = temp_dir.path().join("non_canonical.db");
+    orion_kmer::utils::write_kmer_db(&kmer_db, &db_path)?;
+
+    // Hits found through the reverse complement are credited to the reference holding it, and a
+    // reference holding both strands of a window is credited once
+    let reads = ">read_rev\nAAAA\n>read_both\nAAAC\n";
+    for strand_arg in ["--strand", "--canonical-both"] {
+        let output = run_query_with_extra_args(&db_path, reads, &[strand_arg, "--annotate-references"])?;
+        let references: Vec<&str> = output.lines().map(|line| line.rsplit('\t').next().unwrap()).collect();
+        assert_eq!(references, vec!["raw.fa:1", "both.fa:1"], "{}", strand_arg);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_query_against_hashed_database() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_db_storage_dir = TempDir::new()?;
+    let db_path = temp_db_storage_dir.path().join("hashed.db");
+    let db_input_path = temp_db_storage_dir.path().join("db.fa");
+    fs::write(&db_input_path, DB_FASTA_CONTENT)?;
+
+    let mut cmd_build = Command::cargo_bin("orion-kmer")?;
+    cmd_build
+        .arg("build")
+        .arg("-k")
+        .arg("4")
+        .arg("-g")
+        .arg(&db_input_path)
+        .arg("-o")
+        .arg(&db_path)
+        .arg("--hashed");
+    cmd_build.assert().success();
+
+    // Read k-mers are hashed the same way before lookup, so results match the unhashed database
+    let matched_ids = run_query_and_get_ids(&db_path, QUERY_FASTQ_CONTENT, None)?;
+    let expected_ids: HashSet<String> = [
+        "read1_match_many",
+        "read2_match_one",
+        "read5_match_multiple_hits_but_one_kmer",
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect();
+    assert_eq!(matched_ids, expected_ids);
+    Ok(())
+}
+
+#[test]
+fn test_query_non_canonical_database_warns_or_fails_with_strict() -> Result<(), Box<dyn std::error::Error>> {
+    use orion_kmer::{db_types::KmerDbV2, kmer::seq_to_u64};
+
+    // A database holding TTTT as-is; lookups use its canonical form AAAA
+    let mut kmer_db = KmerDbV2::new(4);
+    kmer_db.canonical = false;
+    kmer_db.add_reference(
+        "raw.fa".to_string(),
+        HashSet::from([seq_to_u64(b"TTTT", 4).unwrap()]),
+    );
+    let temp_dir = TempDir::new()?;
+    let db_path = temp_dir.path().join("non_canonical.db");
+    orion_kmer::utils::write_kmer_db(&kmer_db, &db_path)?;
+    let reads_path = temp_dir.path().join("reads.fastq");
+    fs::write(&reads_path, "@r1\nTTTT\n+\n!!!!\n")?;
+
+    for (strict, should_fail) in [(false, false), (true, true)] {
+        let mut cmd = Command::cargo_bin("orion-kmer")?;
+        cmd.arg("query")
+            .arg("-d")
+            .arg(&db_path)
+            .arg("-r")
+            .arg(&reads_path)
+            .arg("-o")
+            .arg(temp_dir.path().join("ids.txt"));
+        if strict {
+            cmd.arg("--strict");
+        }
+        let assert = if should_fail {
+            cmd.assert().failure()
+        } else {
+            cmd.assert().success()
+        };
+        assert.stderr(predicate::str::contains("stores non-canonical k-mers"));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_query_min_read_length_and_mean_qual() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_db_dir = TempDir::new()?;
+    let db_path =
+        run_build_for_query_test(4, vec![("ref.fa", DB_FASTA_CONTENT)], &temp_db_dir, "qual_db")?;
+
+    // All three reads contain database k-mers. '5' is Phred 20, 'I' is Phred 40.
+    let reads = "\
+@short_read
+ACGTA
++
+IIIII
+@low_qual
+ACGTACGTTT
++
+5555555555
+@good_read
+ACGTACGTTT
++
+IIIIIIIIII
+";
+    let ids = run_query_with_extra_args(
+        &db_path,
+        reads,
+        &["--min-read-length", "6", "--min-mean-qual", "30"],
+    )?;
+    assert_eq!(ids.lines().collect::<Vec<_>>(), vec!["good_read"]);
+
+    // Without the filters every read is reported
+    let all_ids = run_query_with_extra_args(&db_path, reads, &[])?;
+    assert_eq!(all_ids.lines().count(), 3);
+    Ok(())
+}
+
+#[test]
+fn test_query_trim_front_and_back_before_kmer_extraction() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_db_dir = TempDir::new()?;
+    let db_path =
+        run_build_for_query_test(4, vec![("ref.fa", DB_FASTA_CONTENT)], &temp_db_dir, "trim_db")?;
+
+    // adapter_hit only hits the database in its first 6 bases (ACGT, CGTA, GTAC);
+    // insert_hit hits in TTGCA after the adapter; too_short is shorter than the trim
+    let reads = "\
+>adapter_hit
+ACGTACCCCCCC
+>insert_hit
+CCCCCCTTGCAC
+>too_short
+ACG
+";
+    let ids = run_query_with_extra_args(&db_path, reads, &["--trim-front", "6", "--trim-back", "1"])?;
+    assert_eq!(ids.lines().collect::<Vec<_>>(), vec!["insert_hit"]);
+
+    // Trimming the hits off the 3' end removes insert_hit instead
+    let ids = run_query_with_extra_args(&db_path, reads, &["--trim-back", "7"])?;
+    assert_eq!(ids.lines().collect::<Vec<_>>(), vec!["adapter_hit"]);
+
+    // BED intervals stay in untrimmed read coordinates
+    let bed_path = temp_db_dir.path().join("trimmed.bed");
+    run_query_with_extra_args(
+        &db_path,
+        ">read_bed\nACGTACCCCCTTGCA\n",
+        &["--trim-front", "6", "--bed", bed_path.to_str().unwrap()],
+    )?;
+    assert_eq!(fs::read_to_string(&bed_path)?, "read_bed\t10\t15\n");
+    Ok(())
+}
+
+#[test]
+fn test_query_mismatches_counts_single_substitution_hits() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_db_dir = TempDir::new()?;
+    let db_path =
+        run_build_for_query_test(8, vec![("ref.fa", DB_FASTA_CONTENT)], &temp_db_dir, "fuzzy_db")?;
+
+    // one_error is ACGTACGT with G->C at its seventh base; unrelated matches nothing
+    let reads = ">one_error\nACGTACCT\n>unrelated\nGGGGGGGG\n";
+    let exact_ids = run_query_with_extra_args(&db_path, reads, &[])?;
+    assert!(exact_ids.is_empty());
+    let fuzzy_ids = run_query_with_extra_args(&db_path, reads, &["--mismatches", "1"])?;
+    assert_eq!(fuzzy_ids.lines().collect::<Vec<_>>(), vec!["one_error"]);
+
+    let reads_path = temp_db_dir.path().join("reads.fa");
+    fs::write(&reads_path, reads)?;
+    let mut cmd = Command::cargo_bin("orion-kmer")?;
+    cmd.arg("query")
+        .arg("-d")
+        .arg(&db_path)
+        .arg("-r")
+        .arg(&reads_path)
+        .arg("-o")
+        .arg(temp_db_dir.path().join("ids.txt"))
+        .args(["--mismatches", "2"]);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("at most 1 substitution"));
+    Ok(())
+}
+
+#[test]
+fn test_query_derived_k_matches_shorter_subwords() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_db_dir = TempDir::new()?;
+    let db_path =
+        run_build_for_query_test(8, vec![("ref.fa", DB_FASTA_CONTENT)], &temp_db_dir, "derived_k_db")?;
+
+    // No 8-mer of these reads is in the database, but they share 5-mers with it:
+    // TTGCA directly, and CAAAC as the reverse complement of GTTTG
+    let reads = ">shares_5mer\nGTTTGCAA\n>reverse_strand\nCAAACG\n>unrelated\nGGGGGGGG\n";
+    assert!(run_query_with_extra_args(&db_path, reads, &[])?.is_empty());
+    let derived_ids = run_query_with_extra_args(&db_path, reads, &["--derived-k", "5"])?;
+    assert_eq!(derived_ids.lines().collect::<Vec<_>>(), vec!["shares_5mer", "reverse_strand"]);
+
+    let reads_path = temp_db_dir.path().join("reads.fa");
+    fs::write(&reads_path, reads)?;
+    let mut cmd = Command::cargo_bin("orion-kmer")?;
+    cmd.arg("query")
+        .arg("-d")
+        .arg(&db_path)
+        .arg("-r")
+        .arg(&reads_path)
+        .arg("-o")
+        .arg(temp_db_dir.path().join("ids.txt"))
+        .args(["--derived-k", "8"]);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("must be smaller than the database k-mer size 8"));
+    Ok(())
+}
+
+#[test]
+fn test_query_reports_reads_shorter_than_k() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_db_dir = TempDir::new()?;
+    let db_path = run_build_for_query_test(4, vec![("ref.fa", DB_FASTA_CONTENT)], &temp_db_dir, "short_db")?;
+    let reads_path = temp_db_dir.path().join("reads.fa");
+    fs::write(&reads_path, ">r1\nACG\n>r2\nCGT\n")?;
+    let output_path = temp_db_dir.path().join("ids.txt");
+    let mut cmd = Command::cargo_bin("orion-kmer")?;
+    cmd.arg("query")
+        .arg("-d")
+        .arg(&db_path)
+        .arg("-r")
+        .arg(&reads_path)
+        .arg("-o")
+        .arg(&output_path);
+    cmd.assert()
+        .success()
+        .stderr(predicate::str::contains("All 2 records in").and(predicate::str::contains("shorter than k=4")));
+    assert!(fs::read_to_string(&output_path)?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_query_min_complexity_skips_repetitive_reads() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_db_dir = TempDir::new()?;
+    let db_path = run_build_for_query_test(
+        4,
+        vec![("ref.fa", DB_FASTA_CONTENT)],
+        &temp_db_dir,
+        "complexity_db",
+    )?;
+
+    // Both reads hit the database; the tandem repeat only has 4 distinct trinucleotides
+    let reads = ">tandem_repeat\nACGTACGTACGTACGT\n>complex_read\nACGTACGTTTGCATC\n";
+    let ids = run_query_with_extra_args(&db_path, reads, &["--min-complexity", "0.7"])?;
+    assert_eq!(ids.lines().collect::<Vec<_>>(), vec!["complex_read"]);
+
+    let all_ids = run_query_with_extra_args(&db_path, reads, &["--min-complexity", "0"])?;
+    assert_eq!(all_ids.lines().count(), 2);
+    Ok(())
+}
+
+#[test]
+fn test_query_canonical_both_palindrome_policy() -> Result<(), Box<dyn std::error::Error>> {
+    let k = 4;
+    let temp_db_storage_dir = TempDir::new()?;
+    let db_path = run_build_for_query_test(
+        k,
+        vec![("db.fa", DB_FASTA_CONTENT)],
+        &temp_db_storage_dir,
+        "querydb_canonical_both",
+    )?;
+
+    // ATGC is stored as-is, GTTT only matches via its reverse complement AAAC,
+    // and ACGT is its own reverse complement.
+    let reads = "\
+@read_fwd
+ATGC
++
+!!!!
+@read_rev
+GTTT
++
+!!!!
+@read_palindrome
+ACGT
++
+!!!!
+";
+    let output = run_query_with_extra_args(&db_path, reads, &["--canonical-both"])?;
+    let lines: HashSet<&str> = output.lines().collect();
+    let expected: HashSet<&str> = ["read_fwd\t1\t0", "read_rev\t0\t1", "read_palindrome\t1\t1"]
+        .into_iter()
+        .collect();
+    assert_eq!(lines, expected);
+
+    let output = run_query_with_extra_args(
+        &db_path,
+        reads,
+        &["--canonical-both", "--palindromes", "neither"],
+    )?;
+    let lines: HashSet<&str> = output.lines().collect();
+    let expected: HashSet<&str> = ["read_fwd\t1\t0", "read_rev\t0\t1", "read_palindrome\t0\t0"]
+        .into_iter()
+        .collect();
+    assert_eq!(lines, expected);
+    Ok(())
+}
+
+#[test]
+fn test_query_mmap_sorted_index_matches_in_memory() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let db_path = temp_dir.path().join("db.db");
+    let index_path = temp_dir.path().join("db.kidx");
+    let db_input_path = temp_dir.path().join("db.fa");
+    fs::write(&db_input_path, DB_FASTA_CONTENT)?;
+    Command::cargo_bin("orion-kmer")?
+        .arg("build")
+        .arg("-k")
+        .arg("4")
+        .arg("-g")
+        .arg(&db_input_path)
+        .arg("-o")
+        .arg(&db_path)
+        .arg("--sorted-index")
+        .arg(&index_path)
+        .assert()
+        .success();
+
+    let in_memory = run_query_with_extra_args(&db_path, QUERY_FASTQ_CONTENT, &["--strand"])?;
+    let mapped = run_query_with_extra_args(&index_path, QUERY_FASTQ_CONTENT, &["--strand", "--mmap"])?;
+    assert_eq!(mapped, in_memory);
+    // Without --mmap the index is loaded like any other database
+    let loaded = run_query_with_extra_args(&index_path, QUERY_FASTQ_CONTENT, &["--strand"])?;
+    assert_eq!(loaded, in_memory);
+
+    // A regular database is not a sorted index
+    let reads_path = temp_dir.path().join("reads.fastq");
+    fs::write(&reads_path, QUERY_FASTQ_CONTENT)?;
+    Command::cargo_bin("orion-kmer")?
+        .arg("query")
+        .arg("-d")
+        .arg(&db_path)
+        .arg("-r")
+        .arg(&reads_path)
+        .arg("-o")
+        .arg(temp_dir.path().join("out.txt"))
+        .arg("--mmap")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("is not a sorted k-mer index"));
+    Ok(())
+}
+
+#[test]
+fn test_query_disk_index_matches_in_memory() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let db_path = temp_dir.path().join("db.db.gz");
+    let index_path = temp_dir.path().join("db.kidx");
+    let db_input_path = temp_dir.path().join("db.fa");
+    fs::write(&db_input_path, DB_FASTA_CONTENT)?;
+    Command::cargo_bin("orion-kmer")?
+        .args(["build", "-k", "4", "-g"])
+        .arg(&db_input_path)
+        .arg("-o")
+        .arg(&db_path)
+        .arg("--sorted-index")
+        .arg(&index_path)
+        .assert()
+        .success();
+
+    let in_memory = run_query_with_extra_args(&db_path, QUERY_FASTQ_CONTENT, &["--strand", "--min-hits", "2"])?;
+    assert!(!in_memory.is_empty());
+    // A regular (here compressed) database goes through a temporary sorted file
+    let from_database = run_query_with_extra_args(&db_path, QUERY_FASTQ_CONTENT, &["--strand", "--min-hits", "2", "--disk-index"])?;
+    assert_eq!(from_database, in_memory);
+    // A sorted index is mapped as it is
+    let from_index = run_query_with_extra_args(&index_path, QUERY_FASTQ_CONTENT, &["--strand", "--min-hits", "2", "--disk-index"])?;
+    assert_eq!(from_index, in_memory);
+    Ok(())
+}
+
+#[test]
+fn test_query_duplicate_read_ids() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_db_dir = TempDir::new()?;
+    let db_path = run_build_for_query_test(4, vec![("db.fa", DB_FASTA_CONTENT)], &temp_db_dir, "db_dup")?;
+    // Both reads named dup match; the second one with more hits
+    let reads = ">dup\nTTGC\n>other\nACGTA\n>dup\nACGTACGTTT\n>dup\nCCCC\n";
+
+    // By default every matching read gets a line
+    let passthrough = run_query_with_extra_args(&db_path, reads, &["--strand"])?;
+    assert_eq!(passthrough, "dup\t0\t1\nother\t2\t0\ndup\t4\t3\n");
+
+    // --dedup-ids keeps the first matching read of each ID
+    let deduplicated = run_query_with_extra_args(&db_path, reads, &["--strand", "--dedup-ids"])?;
+    assert_eq!(deduplicated, "dup\t0\t1\nother\t2\t0\n");
+
+    // A duplicate that does not match is not reported, so it does not count
+    let unique_matches = run_query_with_extra_args(&db_path, ">dup\nTTGC\n>dup\nCCCC\n", &["--error-on-duplicate-ids"])?;
+    assert_eq!(unique_matches, "dup\n");
+
+    let reads_path = temp_db_dir.path().join("dup_reads.fa");
+    fs::write(&reads_path, reads)?;
+    Command::cargo_bin("orion-kmer")?
+        .arg("query")
+        .arg("-d")
+        .arg(&db_path)
+        .arg("-r")
+        .arg(&reads_path)
+        .arg("-o")
+        .arg(temp_db_dir.path().join("dup_out.txt"))
+        .arg("--error-on-duplicate-ids")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Duplicate read ID \"dup\""));
+    Ok(())
+}
+
+#[test]
+fn test_query_rna_reads_treats_u_as_t() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_db_dir = TempDir::new()?;
+    let db_path = run_build_for_query_test(4, vec![("db.fa", DB_FASTA_CONTENT)], &temp_db_dir, "db_rna")?;
+    // The RNA form of read1_match_many
+    let reads = ">rna_read\nACGUACGUUU\n";
+
+    assert_eq!(run_query_with_extra_args(&db_path, reads, &["--strand"])?, "");
+    assert_eq!(
+        run_query_with_extra_args(&db_path, reads, &["--strand", "--rna"])?,
+        run_query_with_extra_args(&db_path, ">rna_read\nACGTACGTTT\n", &["--strand"])?
+    );
+    Ok(())
+}
+
+#[test]
+fn test_query_multiple_databases_any_all() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_db_dir = TempDir::new()?;
+    let db_a = run_build_for_query_test(4, vec![("a.fa", ">a\nACGTT")], &temp_db_dir, "db_a")?;
+    let db_b = run_build_for_query_test(4, vec![("b.fa", ">b\nGGGGA")], &temp_db_dir, "db_b")?;
+    let db_a_str = db_a.to_str().unwrap();
+    let db_b_str = db_b.to_str().unwrap();
+
+    let reads = ">read_a\nACGTT\n>read_b\nGGGGA\n>read_ab\nACGTTGGGGA\n>read_none\nTATAT\n";
+    let run = |extra_args: &[&str]| -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let mut args = vec!["-d", db_b_str];
+        args.extend_from_slice(extra_args);
+        let output = run_query_with_extra_args(&db_a, reads, &args)?;
+        Ok(output.lines().map(str::to_string).collect())
+    };
+
+    assert_eq!(run(&[])?, vec!["read_a", "read_b", "read_ab"]);
+    assert_eq!(run(&["--combine", "all"])?, vec!["read_ab"]);
+    assert_eq!(
+        run(&["--annotate-databases"])?,
+        vec![
+            format!("read_a\t{}", db_a_str),
+            format!("read_b\t{}", db_b_str),
+            format!("read_ab\t{},{}", db_a_str, db_b_str),
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_query_annotate_references_sorted_by_hits() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_db_dir = TempDir::new()?;
+    // refA: ACGT, AACG (CGTT); refB: AACG (CGTT), CAAC (GTTG), CCAA (TTGG)
+    let db_path = run_build_for_query_test(
+        4,
+        vec![("refA.fa", ">a\nACGTT"), ("refB.fa", ">b\nCGTTGG")],
+        &temp_db_dir,
+        "refs",
+    )?;
+    let reads = ">read_both\nACGTTGG\n>read_a\nACGT\n>read_tie\nACGTT\n>read_none\nTATAT\n";
+
+    let output = run_query_with_extra_args(&db_path, reads, &["--annotate-references"])?;
+    // Most hits first; ties keep reference name order
+    assert_eq!(
+        output.lines().collect::<Vec<_>>(),
+        vec!["read_both\trefB.fa:3,refA.fa:2", "read_a\trefA.fa:1", "read_tie\trefA.fa:2,refB.fa:1"]
+    );
+
+    // The column comes after --annotate-databases' one
+    let output = run_query_with_extra_args(&db_path, ">read_b\nGTTGG\n", &["--annotate-databases", "--annotate-references"])?;
+    assert_eq!(output, format!("read_b\t{}\trefB.fa:2\n", db_path.display()));
+    Ok(())
+}
+
+#[test]
+fn test_query_mask_removes_kmers_from_hits() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_db_dir = TempDir::new()?;
+    let db_path = run_build_for_query_test(4, vec![("db.fa", DB_FASTA_CONTENT)], &temp_db_dir, "db_masked")?;
+    // The mask holds ACGT and CGTA, 4 of r1's 7 hits
+    let mask_path = run_build_for_query_test(4, vec![("mask.fa", ">mask\nACGTA\n")], &temp_db_dir, "mask")?;
+    let mask_arg = mask_path.to_str().unwrap();
+    let reads = ">r1\nACGTACGTTT\n>r2\nTTGC\n";
+
+    assert_eq!(run_query_with_extra_args(&db_path, reads, &["--min-hits", "4"])?, "r1\n");
+    assert_eq!(run_query_with_extra_args(&db_path, reads, &["--min-hits", "4", "--mask", mask_arg])?, "");
+    assert_eq!(run_query_with_extra_args(&db_path, reads, &["--min-hits", "3", "--mask", mask_arg])?, "r1\n");
+
+    // The mask must use the query databases' k
+    let mask_k5_path = run_build_for_query_test(5, vec![("mask5.fa", ">mask\nACGTA\n")], &temp_db_dir, "mask5")?;
+    let reads_path = temp_db_dir.path().join("mask_reads.fa");
+    fs::write(&reads_path, reads)?;
+    Command::cargo_bin("orion-kmer")?
+        .arg("query")
+        .arg("-d")
+        .arg(&db_path)
+        .arg("-r")
+        .arg(&reads_path)
+        .arg("-o")
+        .arg(temp_db_dir.path().join("mask_out.txt"))
+        .arg("--mask")
+        .arg(&mask_k5_path)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("does not match k-mer size 5"));
+    Ok(())
+}
+
+#[test]
+fn test_query_bloom_file_matches_database_query() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let db_path = run_build_for_query_test(4, vec![("db.fa", DB_FASTA_CONTENT)], &temp_dir, "db_bloom")?;
+    let bloom_path = temp_dir.path().join("db.bloom.gz");
+    Command::cargo_bin("orion-kmer")?
+        .arg("dump")
+        .arg("-d")
+        .arg(&db_path)
+        .arg("-o")
+        .arg(&bloom_path)
+        .args(["--format", "bloom", "--fp-rate", "0.0001"])
+        .assert()
+        .success();
+
+    let reads = ">r1\nACGTACGTTT\n>r2\nTTGC\n>r3\nAAAAAAAA\n";
+    let reads_path = temp_dir.path().join("bloom_reads.fa");
+    fs::write(&reads_path, reads)?;
+    let output_path = temp_dir.path().join("bloom_out.txt");
+    Command::cargo_bin("orion-kmer")?
+        .arg("query")
+        .arg("--bloom-file")
+        .arg(&bloom_path)
+        .arg("-r")
+        .arg(&reads_path)
+        .arg("-o")
+        .arg(&output_path)
+        .assert()
+        .success();
+    assert_eq!(fs::read_to_string(&output_path)?, run_query_with_extra_args(&db_path, reads, &[])?);
+    assert_eq!(fs::read_to_string(&output_path)?, "r1\nr2\n");
+
+    // A database is not a Bloom filter
+    Command::cargo_bin("orion-kmer")?
+        .arg("query")
+        .arg("--bloom-file")
+        .arg(&db_path)
+        .arg("-r")
+        .arg(&reads_path)
+        .arg("-o")
+        .arg(&output_path)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Failed to load Bloom filter"));
+    Ok(())
+}
+
+#[test]
+fn test_query_trim_polyx_drops_tail_hits() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let db_path = run_build_for_query_test(4, vec![("polyg.fa", ">polyg\nGGGGGG\n")], &temp_dir, "db_polyg")?;
+    let reads = ">r1\nACGTACGGGGGGGGGG\n>r2\nGGGGACGT\n";
+    assert_eq!(run_query_with_extra_args(&db_path, reads, &[])?, "r1\nr2\n");
+    // Only r1's trailing poly-G hits the database
+    assert_eq!(run_query_with_extra_args(&db_path, reads, &["--trim-polyx", "G"])?, "r2\n");
+    Ok(())
+}
+
+#[test]
+fn test_query_uses_database_canonical_policy_and_rejects_mixed_policies() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let genome_path = temp_dir.path().join("genome.fa");
+    fs::write(&genome_path, ">chr1\nTAGGG\n")?;
+    let lexicographic_db = temp_dir.path().join("lexicographic.db");
+    let minimizer_db = temp_dir.path().join("minimizer.db");
+    for (db_path, extra_args) in [
+        (&lexicographic_db, &[][..]),
+        (&minimizer_db, &["--canonical-policy", "minimizer", "--minimizer-length", "2"][..]),
+    ] {
+        Command::cargo_bin("orion-kmer")?
+            .args(["build", "-k", "5", "-g"])
+            .arg(&genome_path)
+            .arg("-o")
+            .arg(db_path)
+            .args(extra_args)
+            .assert()
+            .success();
+    }
+
+    // Reads on either strand hit the minimizer-policy database
+    let reads = ">forward\nTAGGG\n>reverse\nCCCTA\n>other\nACGTA\n";
+    let matches = run_query_with_extra_args(&minimizer_db, reads, &[])?;
+    assert_eq!(matches, "forward\nreverse\n");
+
+    let reads_path = temp_dir.path().join("reads.fa");
+    fs::write(&reads_path, reads)?;
+    Command::cargo_bin("orion-kmer")?
+        .arg("query")
+        .arg("-d")
+        .arg(&lexicographic_db)
+        .arg(&minimizer_db)
+        .arg("-r")
+        .arg(&reads_path)
+        .arg("-o")
+        .arg(temp_dir.path().join("ids.txt"))
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("incompatible canonical policies: Lexicographic vs Minimizer { m: 2 }"));
+    Command::cargo_bin("orion-kmer")?
+        .arg("classify")
+        .arg("-i")
+        .arg(&reads_path)
+        .arg("-d")
+        .arg(&minimizer_db)
+        .arg(&lexicographic_db)
+        .arg("-o")
+        .arg(temp_dir.path().join("classify.json"))
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("incompatible canonical policies"));
+    Ok(())
+}
+
+#[test]
+fn test_query_bin_output_dir_writes_reads_to_best_reference() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_db_dir = TempDir::new()?;
+    // refA: ACGT, AACG (CGTT); refB: AACG (CGTT), CAAC (GTTG), CCAA (TTGG)
+    let db_path = run_build_for_query_test(
+        4,
+        vec![("refA.fa", ">a\nACGTT"), ("refB.fa", ">b\nCGTTGG")],
+        &temp_db_dir,
+        "refs",
+    )?;
+    // read_tie's only k-mer CGTT is in both references, so it goes to refA.fa, whose name sorts first
+    let reads = "@read_both sample=1\nACGTTGG\n+\nABCDEFG\n@read_a\nACGT\n+\nIIII\n@read_tie\nCGTT\n+\n!!!!\n@read_none\nTATAT\n+\n#####\n";
+    let bin_dir = temp_db_dir.path().join("bins");
+    let bin_dir_arg = bin_dir.to_str().unwrap();
+
+    let output = run_query_with_extra_args(&db_path, reads, &["--bin-output-dir", bin_dir_arg])?;
+    assert_eq!(output, "read_both sample=1\nread_a\nread_tie\n");
+    let mut bin_files: Vec<String> = fs::read_dir(&bin_dir)?
+        .map(|entry| entry.map(|entry| entry.file_name().to_string_lossy().into_owned()))
+        .collect::<Result<_, _>>()?;
+    bin_files.sort();
+    assert_eq!(bin_files, vec!["refA_fa.fastq", "refB_fa.fastq"]);
+    assert_eq!(
+        fs::read_to_string(bin_dir.join("refA_fa.fastq"))?,
+        "@read_a\nACGT\n+\nIIII\n@read_tie\nCGTT\n+\n!!!!\n"
+    );
+    assert_eq!(fs::read_to_string(bin_dir.join("refB_fa.fastq"))?, "@read_both sample=1\nACGTTGG\n+\nABCDEFG\n");
+
+    // --keep-unbinned also writes the reads below --min-hits
+    run_query_with_extra_args(&db_path, reads, &["--bin-output-dir", bin_dir_arg, "--keep-unbinned", "--min-hits", "2"])?;
+    assert_eq!(
+        fs::read_to_string(bin_dir.join("unbinned.fastq"))?,
+        "@read_a\nACGT\n+\nIIII\n@read_tie\nCGTT\n+\n!!!!\n@read_none\nTATAT\n+\n#####\n"
+    );
+    assert_eq!(fs::read_to_string(bin_dir.join("refB_fa.fastq"))?, "@read_both sample=1\nACGTTGG\n+\nABCDEFG\n");
+    Ok(())
+}
+
+#[test]
+fn test_query_max_hits_cap_keeps_decisions_and_caps_counts() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_db_storage_dir = TempDir::new()?;
+    let db_path = run_build_for_query_test(
+        4,
+        vec![("db.fa", DB_FASTA_CONTENT)],
+        &temp_db_storage_dir,
+        "querydb_cap",
+    )?;
+
+    // read1 (7 hits) and read5 (9 hits) stop counting at the cap; read2 (1 hit) stays below --min-hits
+    let output = run_query_with_extra_args(&db_path, QUERY_FASTQ_CONTENT, &["-c", "2", "--max-hits-cap", "3", "--strand"])?;
+    let lines: HashSet<&str> = output.lines().collect();
+    let expected: HashSet<&str> = ["read1_match_many\t3\t0", "read5_match_multiple_hits_but_one_kmer\t3\t0"]
+        .into_iter()
+        .collect();
+    assert_eq!(lines, expected);
+
+    let uncapped = run_query_with_extra_args(&db_path, QUERY_FASTQ_CONTENT, &["-c", "8"])?;
+    let capped = run_query_with_extra_args(&db_path, QUERY_FASTQ_CONTENT, &["-c", "8", "--max-hits-cap", "8"])?;
+    assert_eq!(capped, uncapped);
+    assert_eq!(capped, "read5_match_multiple_hits_but_one_kmer\n");
+
+    // A cap below --min-hits could change which reads pass
+    let reads_path = temp_db_storage_dir.path().join("reads.fq");
+    fs::write(&reads_path, QUERY_FASTQ_CONTENT)?;
+    Command::cargo_bin("orion-kmer")?
+        .arg("query")
+        .arg("-d")
+        .arg(&db_path)
+        .arg("-r")
+        .arg(&reads_path)
+        .arg("-o")
+        .arg(temp_db_storage_dir.path().join("ids.txt"))
+        .args(["-c", "4", "--max-hits-cap", "3"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--max-hits-cap must be at least --min-hits"));
+    Ok(())
+}
+
+#[test]
+fn test_query_rejects_7z_reads_like_other_commands() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_db_storage_dir = TempDir::new()?;
+    let db_path = run_build_for_query_test(
+        4,
+        vec![("db.fa", DB_FASTA_CONTENT)],
+        &temp_db_storage_dir,
+        "querydb_7z",
+    )?;
+    let reads_path = temp_db_storage_dir.path().join("reads.fq.7z");
+    fs::write(&reads_path, b"7z\xbc\xaf\x27\x1c")?;
+    Command::cargo_bin("orion-kmer")?
+        .arg("query")
+        .arg("-d")
+        .arg(&db_path)
+        .arg("-r")
+        .arg(&reads_path)
+        .arg("-o")
+        .arg(temp_db_storage_dir.path().join("ids.txt"))
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("7z archives are not supported"));
+    Ok(())
+}
+
+#[test]
+fn test_query_blocklist_removes_kmers_from_loaded_database() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_db_storage_dir = TempDir::new()?;
+    let db_path = run_build_for_query_test(
+        4,
+        vec![("db.fa", DB_FASTA_CONTENT)],
+        &temp_db_storage_dir,
+        "querydb_blocklist",
+    )?;
+    // read2's only hit is TTGC, blocklisted by its reverse complement
+    let blocklist_path = temp_db_storage_dir.path().join("blocklist.txt");
+    fs::write(&blocklist_path, "GCAA\t12\n\n")?;
+    let blocklist_arg = blocklist_path.to_string_lossy().into_owned();
+    let output = run_query_with_extra_args(&db_path, QUERY_FASTQ_CONTENT, &["--blocklist", &blocklist_arg])?;
+    let ids: HashSet<&str> = output.lines().collect();
+    let expected: HashSet<&str> = ["read1_match_many", "read5_match_multiple_hits_but_one_kmer"]
+        .into_iter()
+        .collect();
+    assert_eq!(ids, expected);
+
+    // The blocklist's k must match the database
+    let reads_path = temp_db_storage_dir.path().join("reads.fq");
+    fs::write(&reads_path, QUERY_FASTQ_CONTENT)?;
+    let wrong_k_path = temp_db_storage_dir.path().join("blocklist_k5.txt");
+    fs::write(&wrong_k_path, "ACGTA\n")?;
+    Command::cargo_bin("orion-kmer")?
+        .arg("query")
+        .arg("-d")
+        .arg(&db_path)
+        .arg("-r")
+        .arg(&reads_path)
+        .arg("-o")
+        .arg(temp_db_storage_dir.path().join("ids.txt"))
+        .arg("--blocklist")
+        .arg(&wrong_k_path)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("has k=5, but database"));
+    Ok(())
+}
+
+#[test]
+fn test_query_segment_length_flags_chimeric_reads() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_db_dir = TempDir::new()?;
+    // refA: ACGT, CGTA, GTAC; refB: AAAA, AAAC, AACC, ACCC, CCCC
+    let db_path = run_build_for_query_test(
+        4,
+        vec![("refA.fa", ">a\nACGTACGT"), ("refB.fa", ">b\nAAAACCCC")],
+        &temp_db_dir,
+        "segments",
+    )?;
+    // read_chimera joins refA and refB halves; read_a has a last segment of 4 bases;
+    // read_partial's second segment holds no database k-mer; read_none is not reported
+    let reads = ">read_chimera\nACGTACGTAAAACCCC\n>read_a\nACGTACGTACGT\n>read_partial\nAAAACCCCGGGAGGGA\n>read_none\nGGGAGGGA\n";
+    let output = run_query_with_extra_args(&db_path, reads, &["--segment-length", "8"])?;
+    assert_eq!(
+        output.lines().collect::<Vec<_>>(),
+        vec![
+            "read_chimera\t0-8\trefA.fa\ttrue",
+            "read_chimera\t8-16\trefB.fa\ttrue",
+            "read_a\t0-8\trefA.fa\tfalse",
+            "read_a\t8-12\trefA.fa\tfalse",
+            "read_partial\t0-8\trefB.fa\tfalse",
+            "read_partial\t8-16\t-\tfalse",
+        ]
+    );
+
+    // Segments shorter than k would hold no k-mer
+    let temp_dir = TempDir::new()?;
+    let reads_path = temp_dir.path().join("reads.fa");
+    fs::write(&reads_path, reads)?;
+    Command::cargo_bin("orion-kmer")?
+        .arg("query")
+        .arg("-d")
+        .arg(&db_path)
+        .arg("-r")
+        .arg(&reads_path)
+        .arg("-o")
+        .arg(temp_dir.path().join("segments.tsv"))
+        .args(["--segment-length", "3"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--segment-length must be at least k (4), got 3"));
+    Ok(())
+}
+
+#[test]
+fn test_query_headerless_baseline_database_is_canonical() -> Result<(), Box<dyn std::error::Error>> {
+    // GATTT is the reverse complement of AAATC, which refB.fa of the baseline database holds
+    let db_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/data/baseline_k5.db");
+    let matched_ids = run_query_with_extra_args(&db_path, "@rc_read\nGATTT\n+\n!!!!!\n", &[])?;
+    assert_eq!(matched_ids, "rc_read\n");
+    Ok(())
+}
@@ -0,0 +1,46 @@
+use assert_cmd::prelude::*;
+use orion_kmer::utils::get_decompressed_input_reader;
+use std::{fs, io::Read, process::Command};
+use tempfile::TempDir;
+
+#[test]
+fn test_revcomp_reads_stdin_and_keeps_headers() -> Result<(), Box<dyn std::error::Error>> {
+    // Multi-line FASTA, lower case and ambiguity codes
+    assert_cmd::Command::cargo_bin("orion-kmer")?
+        .arg("revcomp")
+        .write_stdin(">r1 first read\nACGTN\nAAC\n>r2\nggRy\n")
+        .assert()
+        .success()
+        .stdout(">r1 first read\nGTTNACGT\n>r2\nrYcc\n");
+    Ok(())
+}
+
+#[test]
+fn test_revcomp_reverses_fastq_qualities_and_round_trips() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    let input_path = dir.path().join("reads.fq");
+    let reads = "@q1\nACGGT\n+\nABCDE\n@q2\nTTNA\n+\n!!#I\n";
+    fs::write(&input_path, reads)?;
+    let rc_path = dir.path().join("reads.rc.fq.gz");
+    Command::cargo_bin("orion-kmer")?
+        .arg("revcomp")
+        .arg("-i")
+        .arg(&input_path)
+        .arg("-o")
+        .arg(&rc_path)
+        .assert()
+        .success();
+    let mut rc_reads = String::new();
+    get_decompressed_input_reader(&rc_path)?.read_to_string(&mut rc_reads)?;
+    assert_eq!(rc_reads, "@q1\nACCGT\n+\nEDCBA\n@q2\nTNAA\n+\nI#!!\n");
+
+    // Reverse-complementing twice gives back the input
+    Command::cargo_bin("orion-kmer")?
+        .arg("revcomp")
+        .arg("-i")
+        .arg(&rc_path)
+        .assert()
+        .success()
+        .stdout(reads);
+    Ok(())
+}
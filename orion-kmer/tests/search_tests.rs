@@ -0,0 +1,252 @@
+use assert_cmd::prelude::*;
+use orion_kmer::kmer::{canonical_u64, hash_kmer, seq_to_u64};
+use predicates::prelude::*;
+use serde_json::Value as JsonValue;
+use std::{fs, path::PathBuf, process::Command};
+use tempfile::TempDir;
+
+// Builds a k=4 database from ">r1\nACGTT" (k-mers ACGT, CGTT) with extra build arguments
+fn build_db_for_search(dir: &TempDir, extra_args: &[&str]) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let genome_path = dir.path().join("ref.fa");
+    fs::write(&genome_path, ">r1\nACGTT\n")?;
+    let db_path = dir.path().join("search_input.db");
+    Command::cargo_bin("orion-kmer")?
+        .arg("build")
+        .arg("-k")
+        .arg("4")
+        .arg("-g")
+        .arg(&genome_path)
+        .arg("-o")
+        .arg(&db_path)
+        .args(extra_args)
+        .assert()
+        .success();
+    Ok(db_path)
+}
+
+fn sourmash_hash(kmer: &str, k: u8, seed: u64) -> u64 {
+    hash_kmer(canonical_u64(seq_to_u64(kmer.as_bytes(), k).unwrap(), k), k, seed)
+}
+
+// Writes a sourmash-style signature file with one k=4 sketch of `kmers`
+fn write_sketch(dir: &TempDir, kmers: &[&str], seed: u64) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let mut mins: Vec<u64> = kmers.iter().map(|kmer| sourmash_hash(kmer, 4, seed)).collect();
+    mins.sort_unstable();
+    let signature = serde_json::json!([{
+        "class": "sourmash_signature",
+        "name": "sample",
+        "hash_function": "0.murmur64",
+        "signatures": [
+            { "ksize": 31, "seed": seed, "molecule": "dna", "mins": [1, 2, 3] },
+            { "ksize": 4, "seed": seed, "molecule": "dna", "mins": mins }
+        ],
+        "version": 0.4
+    }]);
+    let sketch_path = dir.path().join("sample.sig");
+    fs::write(&sketch_path, serde_json::to_string(&signature)?)?;
+    Ok(sketch_path)
+}
+
+fn run_search(sketch_path: &PathBuf, db_path: &PathBuf, dir: &TempDir) -> Result<JsonValue, Box<dyn std::error::Error>> {
+    let output_path = dir.path().join("search_output.json");
+    Command::cargo_bin("orion-kmer")?
+        .arg("search")
+        .arg("--sketch")
+        .arg(sketch_path)
+        .arg("-d")
+        .arg(db_path)
+        .arg("-o")
+        .arg(&output_path)
+        .assert()
+        .success();
+    Ok(serde_json::from_str(&fs::read_to_string(&output_path)?)?)
+}
+
+#[test]
+fn test_search_containment_against_unhashed_and_hashed_databases() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    // AACG is the reverse complement of CGTT, so it hashes to the same canonical k-mer
+    let sketch_path = write_sketch(&temp_dir, &["ACGT", "AACG", "GGGG", "CCCA"], 42)?;
+
+    for extra_args in [&[][..], &["--hashed"][..]] {
+        let db_dir = TempDir::new()?;
+        let db_path = build_db_for_search(&db_dir, extra_args)?;
+        let results = run_search(&sketch_path, &db_path, &db_dir)?;
+        assert_eq!(results["kmer_size"], 4);
+        assert_eq!(results["sketch_name"], "sample");
+        assert_eq!(results["sketch_size"], 4);
+        assert_eq!(results["matched_hashes"], 2);
+        assert_eq!(results["containment"].as_f64().unwrap(), 0.5);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_search_sketch_against_non_canonical_databases() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let sketch_path = write_sketch(&temp_dir, &["ACGT", "AACG", "GGGG", "CCCA"], 42)?;
+
+    // CGTT is stored as is, and still matches the sketch's canonical AACG
+    let db_path = build_db_for_search(&temp_dir, &["--no-canonical"])?;
+    let results = run_search(&sketch_path, &db_path, &temp_dir)?;
+    assert_eq!(results["matched_hashes"], 2);
+
+    // Stored hashes cannot be recanonicalized
+    let hashed_dir = TempDir::new()?;
+    let hashed_db_path = build_db_for_search(&hashed_dir, &["--no-canonical", "--hashed"])?;
+    Command::cargo_bin("orion-kmer")?
+        .arg("search")
+        .arg("--sketch")
+        .arg(&sketch_path)
+        .arg("-d")
+        .arg(&hashed_db_path)
+        .arg("-o")
+        .arg(hashed_dir.path().join("out.json"))
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("stores non-canonical k-mers"));
+    Ok(())
+}
+
+#[test]
+fn test_search_writes_compressed_json() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let db_path = build_db_for_search(&temp_dir, &[])?;
+    let sketch_path = write_sketch(&temp_dir, &["ACGT"], 42)?;
+    let output_path = temp_dir.path().join("search.json.gz");
+    Command::cargo_bin("orion-kmer")?
+        .arg("search")
+        .arg("--sketch")
+        .arg(&sketch_path)
+        .arg("-d")
+        .arg(&db_path)
+        .arg("-o")
+        .arg(&output_path)
+        .assert()
+        .success();
+    let mut json = String::new();
+    std::io::Read::read_to_string(&mut flate2::read::MultiGzDecoder::new(fs::File::open(&output_path)?), &mut json)?;
+    let results: JsonValue = serde_json::from_str(&json)?;
+    assert_eq!(results["matched_hashes"], 1);
+    Ok(())
+}
+
+#[test]
+fn test_search_rejects_seed_mismatch_and_missing_ksize() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let db_path = build_db_for_search(&temp_dir, &["--hashed", "--hash-seed", "7"])?;
+    let sketch_path = write_sketch(&temp_dir, &["ACGT"], 42)?;
+    Command::cargo_bin("orion-kmer")?
+        .arg("search")
+        .arg("--sketch")
+        .arg(&sketch_path)
+        .arg("-d")
+        .arg(&db_path)
+        .arg("-o")
+        .arg(temp_dir.path().join("out.json"))
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("incompatible k-mer hashing"));
+
+    let k21_sketch_path = temp_dir.path().join("k21.sig");
+    fs::write(&k21_sketch_path, r#"[{"signatures": [{"ksize": 21, "mins": [5]}]}]"#)?;
+    Command::cargo_bin("orion-kmer")?
+        .arg("search")
+        .arg("--sketch")
+        .arg(&k21_sketch_path)
+        .arg("-d")
+        .arg(&db_path)
+        .arg("-o")
+        .arg(temp_dir.path().join("out.json"))
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("no DNA MinHash sketch with k=4"));
+    Ok(())
+}
+
+#[test]
+fn test_search_query_reports_containment_in_both_directions() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let ref_a = temp_dir.path().join("a.fa");
+    fs::write(&ref_a, ">a\nACGTT\n")?;
+    let ref_b = temp_dir.path().join("b.fa");
+    fs::write(&ref_b, ">b\nGGGGA\n")?;
+    let db_path = temp_dir.path().join("refs.db");
+    Command::cargo_bin("orion-kmer")?
+        .arg("build")
+        .arg("-k")
+        .arg("4")
+        .arg("-g")
+        .arg(&ref_a)
+        .arg("-g")
+        .arg(&ref_b)
+        .arg("-o")
+        .arg(&db_path)
+        .assert()
+        .success();
+
+    // Query k-mers: ACGT, CGTT (both in a.fa) and GTTG (in neither)
+    let query_path = temp_dir.path().join("query.fa");
+    fs::write(&query_path, ">q\nACGTTG\n")?;
+    let output_path = temp_dir.path().join("search.json");
+    Command::cargo_bin("orion-kmer")?
+        .arg("search")
+        .arg("-q")
+        .arg(&query_path)
+        .arg("-d")
+        .arg(&db_path)
+        .arg("-o")
+        .arg(&output_path)
+        .assert()
+        .success();
+
+    let result: JsonValue = serde_json::from_str(&fs::read_to_string(&output_path)?)?;
+    assert_eq!(result["query_unique_kmers"], 3);
+    let references = result["references"].as_array().unwrap();
+    assert_eq!(references.len(), 2);
+    assert_eq!(references[0]["reference_name"], "a.fa");
+    assert_eq!(references[0]["shared_kmers"], 2);
+    assert_eq!(references[0]["query_in_reference"].as_f64().unwrap(), 2.0 / 3.0);
+    assert_eq!(references[0]["reference_in_query"].as_f64().unwrap(), 1.0);
+    assert_eq!(references[1]["reference_name"], "b.fa");
+    assert_eq!(references[1]["shared_kmers"], 0);
+    assert_eq!(references[1]["query_in_reference"].as_f64().unwrap(), 0.0);
+    Ok(())
+}
+
+#[test]
+fn test_search_query_against_non_canonical_database() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    // GTTT is stored as is, while its canonical form would be AAAC
+    let ref_path = temp_dir.path().join("ref.fa");
+    fs::write(&ref_path, ">r\nGTTT\n")?;
+    let db_path = temp_dir.path().join("raw.db");
+    Command::cargo_bin("orion-kmer")?
+        .arg("build")
+        .arg("-k")
+        .arg("4")
+        .arg("-g")
+        .arg(&ref_path)
+        .arg("-o")
+        .arg(&db_path)
+        .arg("--no-canonical")
+        .assert()
+        .success();
+
+    let output_path = temp_dir.path().join("search.json");
+    Command::cargo_bin("orion-kmer")?
+        .arg("search")
+        .arg("-q")
+        .arg(&ref_path)
+        .arg("-d")
+        .arg(&db_path)
+        .arg("-o")
+        .arg(&output_path)
+        .assert()
+        .success();
+
+    let result: JsonValue = serde_json::from_str(&fs::read_to_string(&output_path)?)?;
+    assert_eq!(result["references"][0]["shared_kmers"], 1);
+    assert_eq!(result["references"][0]["query_in_reference"].as_f64().unwrap(), 1.0);
+    Ok(())
+}
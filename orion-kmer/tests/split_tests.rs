@@ -0,0 +1,70 @@
+use assert_cmd::prelude::*;
+use orion_kmer::utils::load_kmer_db_v2;
+use predicates::prelude::*;
+use std::{fs, path::PathBuf, process::Command};
+use tempfile::TempDir;
+
+// Builds a k=4 database of `references` (file name, FASTA content) in `dir` and returns its path.
+fn build_db(dir: &TempDir, references: &[(&str, &str)]) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let db_path = dir.path().join("source.db");
+    let mut cmd = Command::cargo_bin("orion-kmer")?;
+    cmd.arg("build").args(["-k", "4", "-o"]).arg(&db_path);
+    for (name, content) in references {
+        let path = dir.path().join(name);
+        fs::write(&path, content)?;
+        cmd.arg("-g").arg(&path);
+    }
+    cmd.assert().success();
+    Ok(db_path)
+}
+
+#[test]
+fn test_split_writes_one_database_per_reference() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    let db_path = build_db(&dir, &[("refA.fa", ">a\nACGTACGTTTGC\n"), ("refB.fa", ">b\nGGGAAAAATTTT\n")])?;
+    let original = load_kmer_db_v2(&db_path)?;
+    let output_dir = dir.path().join("split");
+
+    Command::cargo_bin("orion-kmer")?
+        .arg("split")
+        .arg("-d")
+        .arg(&db_path)
+        .arg("--output-dir")
+        .arg(&output_dir)
+        .args(["--compress", "gz"])
+        .assert()
+        .success();
+
+    let mut file_names: Vec<String> = fs::read_dir(&output_dir)?
+        .map(|entry| entry.map(|entry| entry.file_name().to_string_lossy().into_owned()))
+        .collect::<Result<_, _>>()?;
+    file_names.sort();
+    assert_eq!(file_names, ["refA_fa.db.gz", "refB_fa.db.gz"]);
+    for (name, kmer_set) in &original.references {
+        let file_name = format!("{}.db.gz", name.replace('.', "_"));
+        let single = load_kmer_db_v2(&output_dir.join(file_name))?;
+        assert_eq!((single.k, single.canonical, single.hashing), (original.k, original.canonical, original.hashing));
+        assert_eq!(single.references.len(), 1);
+        assert_eq!(&single.references[name], kmer_set);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_split_rejects_references_with_the_same_file_name() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    let db_path = build_db(&dir, &[("ref.a.fa", ">a\nACGTACGTTTGC\n"), ("ref_a.fa", ">b\nGGGAAAAATTTT\n")])?;
+    let output_dir = dir.path().join("split");
+
+    Command::cargo_bin("orion-kmer")?
+        .arg("split")
+        .arg("-d")
+        .arg(&db_path)
+        .arg("--output-dir")
+        .arg(&output_dir)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("would both be written to \"ref_a_fa.db\""));
+    assert!(!output_dir.exists());
+    Ok(())
+}
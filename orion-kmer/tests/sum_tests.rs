@@ -0,0 +1,97 @@
+use assert_cmd::prelude::*;
+use orion_kmer::db_types::KmerCountTable;
+use orion_kmer::kmer::seq_to_u64;
+use predicates::prelude::*;
+use std::{collections::HashMap, fs, path::PathBuf, process::Command};
+use tempfile::TempDir;
+
+// Runs `sum` over the given inputs, writing to `output_path`
+fn run_sum(
+    inputs: &[PathBuf],
+    output_path: &PathBuf,
+    extra_args: &[&str],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("orion-kmer")?;
+    cmd.arg("sum").arg("-o").arg(output_path);
+    for input in inputs {
+        cmd.arg("-i").arg(input);
+    }
+    cmd.args(extra_args).assert().success();
+    Ok(())
+}
+
+#[test]
+fn test_sum_tsv_and_binary_tables() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let tsv_a = temp_dir.path().join("chunk_a.tsv");
+    fs::write(&tsv_a, "AAA\t2\nAAC\t1\n")?;
+    let tsv_b = temp_dir.path().join("chunk_b.tsv");
+    fs::write(&tsv_b, "AAC\t3\nCCC\t1\n")?;
+    let binary = temp_dir.path().join("chunk_c.bin");
+    let table = KmerCountTable {
+        k: 3,
+        counts: HashMap::from([(seq_to_u64(b"AAA", 3).unwrap(), 5)]),
+    };
+    fs::write(&binary, bincode::serialize(&table)?)?;
+
+    let output_path = temp_dir.path().join("summed.tsv");
+    run_sum(&[tsv_a.clone(), tsv_b.clone(), binary.clone()], &output_path, &[])?;
+    assert_eq!(fs::read_to_string(&output_path)?, "AAA\t7\nAAC\t4\nCCC\t1\n");
+
+    run_sum(&[tsv_a, tsv_b, binary], &output_path, &["-m", "2"])?;
+    assert_eq!(fs::read_to_string(&output_path)?, "AAA\t7\nAAC\t4\n");
+    Ok(())
+}
+
+#[test]
+fn test_sum_binary_output_round_trips_through_count_output() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let reads = temp_dir.path().join("reads.fa");
+    fs::write(&reads, ">r1\nAAAAC\n")?;
+    let counts = temp_dir.path().join("counts.tsv");
+    Command::cargo_bin("orion-kmer")?
+        .arg("count")
+        .arg("-k")
+        .arg("3")
+        .arg("-i")
+        .arg(&reads)
+        .arg("-o")
+        .arg(&counts)
+        .assert()
+        .success();
+
+    let binary_path = temp_dir.path().join("summed.bin");
+    run_sum(&[counts.clone(), counts], &binary_path, &["--binary"])?;
+    let table: KmerCountTable = bincode::deserialize(&fs::read(&binary_path)?)?;
+    assert_eq!(table.k, 3);
+    assert_eq!(table.counts[&seq_to_u64(b"AAA", 3).unwrap()], 4);
+    assert_eq!(table.counts[&seq_to_u64(b"AAC", 3).unwrap()], 2);
+
+    // The binary table is itself a valid input
+    let tsv_path = temp_dir.path().join("summed.tsv");
+    run_sum(&[binary_path], &tsv_path, &[])?;
+    assert_eq!(fs::read_to_string(&tsv_path)?, "AAA\t4\nAAC\t2\n");
+    Ok(())
+}
+
+#[test]
+fn test_sum_rejects_mixed_kmer_sizes() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let k3 = temp_dir.path().join("k3.tsv");
+    fs::write(&k3, "AAA\t1\n")?;
+    let k4 = temp_dir.path().join("k4.tsv");
+    fs::write(&k4, "AAAA\t1\n")?;
+
+    Command::cargo_bin("orion-kmer")?
+        .arg("sum")
+        .arg("-i")
+        .arg(&k3)
+        .arg("-i")
+        .arg(&k4)
+        .arg("-o")
+        .arg(temp_dir.path().join("summed.tsv"))
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("has k=4"));
+    Ok(())
+}
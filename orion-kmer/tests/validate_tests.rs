@@ -0,0 +1,53 @@
+use assert_cmd::prelude::*;
+use predicates::prelude::*;
+use std::{fs, process::Command};
+use tempfile::TempDir;
+
+#[test]
+fn test_validate_reports_summary_of_valid_file() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let input_path = temp_dir.path().join("reads.fastq");
+    fs::write(&input_path, "@r1\nACGTN\n+\nIIIII\n@r2\nacg\n+\nIII\n")?;
+
+    let output = Command::cargo_bin("orion-kmer")?
+        .arg("validate")
+        .arg("-i")
+        .arg(&input_path)
+        .output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("format\tFASTQ\n"));
+    assert!(stdout.contains("records\t2\n"));
+    assert!(stdout.contains("total_bases\t8\n"));
+    assert!(stdout.contains("alphabet\tACGNT\n"));
+    assert!(stdout.contains("status\tok\n"));
+    Ok(())
+}
+
+#[test]
+fn test_validate_points_at_first_malformed_record() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = TempDir::new()?;
+    let input_path = temp_dir.path().join("truncated.fastq");
+    fs::write(&input_path, "@r1\nACGT\n+\nIIII\n@r2\nACGT\n+\nII\n")?;
+
+    Command::cargo_bin("orion-kmer")?
+        .arg("validate")
+        .arg("-i")
+        .arg(&input_path)
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("records\t1\n"))
+        .stdout(predicate::str::contains("status\tmalformed\n"))
+        .stdout(predicate::str::contains("record 'r2' at line 5"));
+
+    let empty_path = temp_dir.path().join("empty.fasta");
+    fs::write(&empty_path, "")?;
+    Command::cargo_bin("orion-kmer")?
+        .arg("validate")
+        .arg("-i")
+        .arg(&empty_path)
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("Is the file empty?"));
+    Ok(())
+}